@@ -1,4 +1,7 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::compile_protos("proto/packet.proto")?;
+    // Generate `Bytes` instead of `Vec<u8>` for bytes fields, so messages can be passed to the controller without copying.
+    tonic_build::configure()
+        .bytes(["."])
+        .compile(&["proto/packet.proto"], &["proto"])?;
     Ok(())
 }
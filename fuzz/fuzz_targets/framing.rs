@@ -0,0 +1,58 @@
+//! Fuzzes the reassembly of the messages read from a link. The first three bytes of the input choose the size of the
+//! reads, the cap on the messages held whole and the payload limit, the rest is the data read from the link.
+//!
+//! Every byte that is read has to come out exactly once, as a whole message, a part or a malformed frame, unless it is
+//! the discarded rest of a malformed frame or the end of the data that does not make up a message yet.
+#![no_main]
+
+#[allow(dead_code)]
+#[path = "../../src/buffer_pool.rs"]
+mod buffer_pool;
+#[allow(dead_code)]
+#[path = "../../src/framing.rs"]
+mod framing;
+
+use bytes::BufMut;
+use framing::{frame_size, malformation, Frame, FrameReader, HEADER_SIZE, MAX_PAYLOAD_SIZE};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: &[u8]| {
+    let [read_size, max_buffered, max_payload, data @ ..] = input else {
+        return;
+    };
+    let read_size = *read_size as usize + 1;
+    let max_buffered = match max_buffered {
+        0 => MAX_PAYLOAD_SIZE + HEADER_SIZE,
+        size => *size as usize * 64,
+    };
+    let max_payload = match max_payload {
+        0 => MAX_PAYLOAD_SIZE,
+        size => *size as usize * 64,
+    };
+
+    let mut reader = FrameReader::new(1024, max_buffered, max_payload);
+    let mut framed = 0;
+    for chunk in data.chunks(read_size) {
+        reader.buffer().put_slice(chunk);
+        while let Some(frame) = reader.next_frame() {
+            match frame {
+                Frame::Whole(message) => {
+                    assert_eq!(frame_size(&message), Some(message.len()));
+                    assert!(message.len() <= max_buffered);
+                    assert_eq!(malformation(&message, max_payload), None);
+                    framed += message.len();
+                }
+                Frame::Part(part, position) => {
+                    assert!(!part.is_empty());
+                    assert!(position.offset + part.len() <= position.length);
+                    framed += part.len();
+                }
+                Frame::Malformed(bytes, _) => {
+                    assert!(!bytes.is_empty());
+                    framed += bytes.len();
+                }
+            }
+            assert!(framed <= data.len());
+        }
+    }
+});
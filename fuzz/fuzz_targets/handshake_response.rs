@@ -0,0 +1,23 @@
+//! Fuzzes the parsing of the response of a peer to the HTTP upgrade request. The input is the response as it was read
+//! from the peer. Parsing has to return a result for every input instead of panicking.
+#![no_main]
+
+#[allow(dead_code)]
+#[path = "../../src/handshake_response.rs"]
+mod handshake_response;
+#[allow(dead_code)]
+#[path = "../../src/message_type.rs"]
+mod message_type;
+#[allow(dead_code)]
+#[path = "../../src/protocol_version.rs"]
+mod protocol_version;
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: &[u8]| {
+    if let Ok(info) = handshake_response::parse(BytesMut::from(input)) {
+        // The selected version is parsed from the Upgrade header
+        assert!(info.version.is_none() || info.protocol.is_some());
+    }
+});
@@ -0,0 +1,481 @@
+//! This module is responsible for turning the response of the controller into a validated decision
+//! on what to do with an intercepted message.
+
+use crate::field_mutation::{self, FieldMutation, MutationError};
+use crate::packet_client::proto::action::Kind;
+use crate::packet_client::proto::{Action, DestinationActions, PacketAck};
+use bytes::Bytes;
+use std::cmp::min;
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+/// The protocol version in which the controller responds with a delay and send amount.
+pub const LEGACY_PROTO_VERSION: u32 = 1;
+/// The protocol version in which the controller responds with typed actions.
+pub const TYPED_ACTIONS_PROTO_VERSION: u32 = 2;
+/// The protocol version in which large messages can be sent to the controller truncated.
+pub const TRUNCATION_PROTO_VERSION: u32 = 3;
+/// The highest protocol version the interceptor supports.
+pub const PROTO_VERSION: u32 = TRUNCATION_PROTO_VERSION;
+/// The longest delay in ms the controller can apply to a message.
+pub const MAX_DELAY_MS: u32 = 30000;
+/// The names of the actions the interceptor supports, as they are named in the protocol.
+pub const SUPPORTED_ACTIONS: [&str; 7] = [
+    "forward",
+    "drop",
+    "delay_ms",
+    "mutate",
+    "duplicate",
+    "mutate_fields",
+    "fan_out",
+];
+
+/// Struct that represents the validated decision of the controller for a single message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decision {
+    /// The data to be sent, which is possibly mutated.
+    pub data: Bytes,
+    /// How long the message is delayed, counting from the moment it was read.
+    pub delay: Duration,
+    /// The amount of times the message is sent, 0 means it is dropped.
+    pub send_amount: u32,
+    /// Whether the message is signed again by the interceptor with the key of its sender, after it was mutated.
+    pub resign: bool,
+}
+
+/// Enum that represents the reasons the actions of the controller can be rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ActionError {
+    /// An action without a kind, which happens if the controller uses an action this interceptor does not know.
+    Unknown,
+    /// A delay longer than `MAX_DELAY_MS`.
+    DelayTooLong(u32),
+    /// A mutation to an empty message.
+    EmptyMutation,
+    /// A duplication without any copies.
+    NoCopies,
+    /// A drop combined with other actions.
+    DropWithOtherActions,
+    /// Field mutations that can not be applied to the message.
+    Mutation(MutationError),
+    /// A fan-out to a port that does not fit in 16 bits, and can therefore not be the port of a node.
+    PortOutOfRange(u32),
+    /// A fan-out whose actions contain another fan-out.
+    NestedFanOut,
+}
+
+impl fmt::Display for ActionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActionError::Unknown => write!(f, "unknown action"),
+            ActionError::DelayTooLong(delay_ms) => write!(
+                f,
+                "delay of {} ms exceeds the maximum of {} ms",
+                delay_ms, MAX_DELAY_MS
+            ),
+            ActionError::EmptyMutation => write!(f, "mutation to an empty message"),
+            ActionError::NoCopies => write!(f, "duplication without any copies"),
+            ActionError::DropWithOtherActions => write!(f, "drop combined with other actions"),
+            ActionError::Mutation(e) => write!(f, "invalid field mutation: {}", e),
+            ActionError::PortOutOfRange(port) => write!(f, "fan-out to port {} out of range", port),
+            ActionError::NestedFanOut => write!(f, "fan-out nested in a fan-out"),
+        }
+    }
+}
+
+impl Error for ActionError {}
+
+impl Decision {
+    /// Initializes a Decision that forwards the message unchanged and without delay.
+    ///
+    /// # Parameters
+    /// * 'data' - the data of the message.
+    pub fn forward(data: Bytes) -> Self {
+        Self {
+            data,
+            delay: Duration::ZERO,
+            send_amount: 1,
+            resign: false,
+        }
+    }
+
+    /// Initializes a Decision that drops the message.
+    ///
+    /// # Parameters
+    /// * 'data' - the data of the message.
+    pub fn dropped(data: Bytes) -> Self {
+        Self {
+            data,
+            delay: Duration::ZERO,
+            send_amount: 0,
+            resign: false,
+        }
+    }
+
+    /// Combines this decision with a decision made later for the same message: the message is dropped if either
+    /// decision drops it, the later decision determines its data, and the delays and extra copies are added.
+    ///
+    /// # Parameters
+    /// * 'next' - the later decision, made for the data of this decision.
+    pub fn then(self, next: Decision) -> Self {
+        if self.send_amount == 0 || next.send_amount == 0 {
+            return Self::dropped(next.data);
+        }
+        Self {
+            data: next.data,
+            delay: self.delay + next.delay,
+            send_amount: self.send_amount + next.send_amount - 1,
+            resign: self.resign || next.resign,
+        }
+    }
+
+    /// Returns the delay in ms, as it is recorded for the message.
+    pub fn delay_ms(&self) -> u32 {
+        self.delay.as_millis() as u32
+    }
+
+    /// Validates the response of the controller and turns it into a Decision.
+    ///
+    /// # Parameters
+    /// * 'message' - the original data of the message.
+    /// * 'ack' - the response of the controller.
+    /// * 'proto_version' - the protocol version that was negotiated with the controller.
+    pub fn from_ack(
+        message: Bytes,
+        ack: PacketAck,
+        proto_version: u32,
+    ) -> Result<Self, ActionError> {
+        if proto_version < TYPED_ACTIONS_PROTO_VERSION {
+            return Ok(Self {
+                data: ack.data,
+                delay: Duration::from_millis(min(ack.action, MAX_DELAY_MS) as u64),
+                send_amount: ack.send_amount,
+                resign: false,
+            });
+        }
+
+        fan_out(&ack)?;
+        Self::from_actions(message, &ack.actions)
+    }
+
+    /// Validates typed actions and turns them into a Decision. Fan-outs do not decide on the message itself, and are
+    /// ignored.
+    ///
+    /// # Parameters
+    /// * 'message' - the original data of the message.
+    /// * 'actions' - the actions.
+    pub fn from_actions(message: Bytes, actions: &[Action]) -> Result<Self, ActionError> {
+        let mut decision = Self::forward(message);
+        let mut dropped = false;
+        let mut decisive = 0;
+        for action in actions.iter() {
+            match &action.kind {
+                None => return Err(ActionError::Unknown),
+                Some(Kind::FanOut(_)) => continue,
+                Some(Kind::Forward(_)) => (),
+                Some(Kind::Drop(_)) => dropped = true,
+                Some(Kind::DelayMs(delay_ms)) => {
+                    if *delay_ms > MAX_DELAY_MS {
+                        return Err(ActionError::DelayTooLong(*delay_ms));
+                    }
+                    decision.delay = Duration::from_millis(*delay_ms as u64);
+                }
+                Some(Kind::Mutate(data)) => {
+                    if data.is_empty() {
+                        return Err(ActionError::EmptyMutation);
+                    }
+                    decision.data = data.clone();
+                }
+                Some(Kind::MutateFields(field_mutations)) => {
+                    let mutations = field_mutations
+                        .mutations
+                        .iter()
+                        .map(FieldMutation::try_from)
+                        .collect::<Result<Vec<FieldMutation>, MutationError>>()
+                        .map_err(ActionError::Mutation)?;
+                    decision.data = field_mutation::apply(&decision.data, &mutations)
+                        .map_err(ActionError::Mutation)?;
+                    decision.resign |= field_mutations.resign;
+                }
+                Some(Kind::Duplicate(copies)) => {
+                    if *copies == 0 {
+                        return Err(ActionError::NoCopies);
+                    }
+                    decision.send_amount = decision.send_amount.saturating_add(*copies);
+                }
+            }
+            decisive += 1;
+        }
+
+        if dropped {
+            if decisive > 1 {
+                return Err(ActionError::DropWithOtherActions);
+            }
+            decision.send_amount = 0;
+        }
+        Ok(decision)
+    }
+}
+
+/// Returns the actions of the controller for the copies of a broadcast that are sent to other nodes later on, by the
+/// port of the node they are sent to. Fails if a port does not exist or a fan-out is nested in another one.
+///
+/// # Parameters
+/// * 'ack' - the response of the controller.
+pub fn fan_out(ack: &PacketAck) -> Result<Vec<DestinationActions>, ActionError> {
+    let mut destinations = Vec::new();
+    for action in ack.actions.iter() {
+        let Some(Kind::FanOut(fan_out)) = &action.kind else {
+            continue;
+        };
+        for destination in fan_out.destinations.iter() {
+            if u16::try_from(destination.to_port).is_err() {
+                return Err(ActionError::PortOutOfRange(destination.to_port));
+            }
+            if destination
+                .actions
+                .iter()
+                .any(|action| matches!(action.kind, Some(Kind::FanOut(_))))
+            {
+                return Err(ActionError::NestedFanOut);
+            }
+            destinations.push(destination.clone());
+        }
+    }
+    Ok(destinations)
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::action::{fan_out, ActionError, Decision, LEGACY_PROTO_VERSION, PROTO_VERSION};
+    use crate::field_mutation::MutationError;
+    use crate::packet_client::proto::action::Kind;
+    use crate::packet_client::proto::field_mutation::Operation;
+    use crate::packet_client::proto::{
+        Action, DestinationActions, DropAction, FanOut, FieldMutation, FieldMutations,
+        ForwardAction, PacketAck,
+    };
+    use crate::ping::Ping;
+    use bytes::Bytes;
+    use std::time::Duration;
+
+    fn ack(kinds: Vec<Kind>) -> PacketAck {
+        PacketAck {
+            data: Bytes::new(),
+            action: 0,
+            send_amount: 0,
+            actions: kinds
+                .into_iter()
+                .map(|kind| Action { kind: Some(kind) })
+                .collect(),
+            needs_full_payload: false,
+        }
+    }
+
+    fn decide(kinds: Vec<Kind>) -> Result<Decision, ActionError> {
+        Decision::from_ack(Bytes::from_static(&[1, 2, 3]), ack(kinds), PROTO_VERSION)
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn legacy_ack() {
+        let ack = PacketAck {
+            data: Bytes::from_static(&[4, 5]),
+            action: 100_000,
+            send_amount: 2,
+            actions: vec![],
+            needs_full_payload: false,
+        };
+        let decision =
+            Decision::from_ack(Bytes::from_static(&[1]), ack, LEGACY_PROTO_VERSION).unwrap();
+        assert_eq!(decision.data, Bytes::from_static(&[4, 5]));
+        assert_eq!(decision.delay, Duration::from_millis(30000));
+        assert_eq!(decision.send_amount, 2);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn no_actions_forwards() {
+        assert_eq!(
+            decide(vec![]).unwrap(),
+            Decision::forward(Bytes::from_static(&[1, 2, 3]))
+        );
+        assert_eq!(
+            decide(vec![Kind::Forward(ForwardAction {})]).unwrap(),
+            Decision::forward(Bytes::from_static(&[1, 2, 3]))
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn combined_actions() {
+        let decision = decide(vec![
+            Kind::DelayMs(500),
+            Kind::Mutate(Bytes::from_static(&[9])),
+            Kind::Duplicate(2),
+        ])
+        .unwrap();
+        assert_eq!(decision.data, Bytes::from_static(&[9]));
+        assert_eq!(decision.delay_ms(), 500);
+        assert_eq!(decision.send_amount, 3);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn drop_action() {
+        assert_eq!(
+            decide(vec![Kind::Drop(DropAction {})]).unwrap().send_amount,
+            0
+        );
+        assert_eq!(
+            decide(vec![Kind::Drop(DropAction {}), Kind::DelayMs(5)]),
+            Err(ActionError::DropWithOtherActions)
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn invalid_actions() {
+        assert_eq!(
+            decide(vec![Kind::DelayMs(30001)]),
+            Err(ActionError::DelayTooLong(30001))
+        );
+        assert_eq!(
+            decide(vec![Kind::Mutate(Bytes::new())]),
+            Err(ActionError::EmptyMutation)
+        );
+        assert_eq!(decide(vec![Kind::Duplicate(0)]), Err(ActionError::NoCopies));
+
+        let mut unknown = ack(vec![]);
+        unknown.actions.push(Action { kind: None });
+        assert_eq!(
+            Decision::from_ack(Bytes::from_static(&[1]), unknown, PROTO_VERSION),
+            Err(ActionError::Unknown)
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn field_mutations() {
+        let ping = Ping {
+            seq: Some(4),
+            ..Ping::default()
+        };
+        let mutate_fields = |path: &str, operation, resign| {
+            Kind::MutateFields(FieldMutations {
+                mutations: vec![FieldMutation {
+                    path: path.to_string(),
+                    operation,
+                }],
+                resign,
+            })
+        };
+
+        let decision = Decision::from_ack(
+            ping.to_message(),
+            ack(vec![mutate_fields("seq", Some(Operation::Add(3)), true)]),
+            PROTO_VERSION,
+        )
+        .unwrap();
+        let expected = Ping {
+            seq: Some(7),
+            ..Ping::default()
+        };
+        assert_eq!(decision.data, expected.to_message());
+        assert!(decision.resign);
+
+        assert_eq!(
+            decide(vec![mutate_fields(
+                "seq",
+                Some(Operation::Clear(true)),
+                false
+            )]),
+            Err(ActionError::Mutation(MutationError::Malformed))
+        );
+        assert_eq!(
+            Decision::from_ack(
+                ping.to_message(),
+                ack(vec![mutate_fields("seq", None, false)]),
+                PROTO_VERSION,
+            ),
+            Err(ActionError::Mutation(MutationError::NoOperation(
+                "seq".to_string()
+            )))
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn combine_decisions() {
+        let first = Decision {
+            delay: Duration::from_millis(100),
+            send_amount: 2,
+            ..Decision::forward(Bytes::from_static(&[1]))
+        };
+        let next = Decision {
+            delay: Duration::from_millis(50),
+            send_amount: 3,
+            resign: true,
+            ..Decision::forward(Bytes::from_static(&[2]))
+        };
+        assert_eq!(
+            first.clone().then(next.clone()),
+            Decision {
+                data: Bytes::from_static(&[2]),
+                delay: Duration::from_millis(150),
+                send_amount: 4,
+                resign: true,
+            }
+        );
+        assert_eq!(
+            first.then(Decision::dropped(Bytes::from_static(&[2]))),
+            Decision::dropped(Bytes::from_static(&[2]))
+        );
+        assert_eq!(
+            Decision::dropped(Bytes::from_static(&[1])).then(next),
+            Decision::dropped(Bytes::from_static(&[2]))
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn fan_out_actions() {
+        let destination = |to_port, kinds: Vec<Kind>| DestinationActions {
+            to_port,
+            actions: kinds
+                .into_iter()
+                .map(|kind| Action { kind: Some(kind) })
+                .collect(),
+        };
+        let delayed = destination(60002, vec![Kind::DelayMs(500)]);
+        let kinds = |destinations| {
+            vec![
+                Kind::Drop(DropAction {}),
+                Kind::FanOut(FanOut { destinations }),
+            ]
+        };
+
+        // The copy itself is dropped, while the copies relayed to node 60002 are delayed
+        assert_eq!(
+            decide(kinds(vec![delayed.clone()])).unwrap(),
+            Decision::dropped(Bytes::from_static(&[1, 2, 3]))
+        );
+        let destinations = fan_out(&ack(kinds(vec![delayed.clone()]))).unwrap();
+        assert_eq!(destinations, vec![delayed]);
+        let copy = Decision::from_actions(Bytes::from_static(&[4]), &destinations[0].actions);
+        assert_eq!(copy.unwrap().delay, Duration::from_millis(500));
+
+        assert_eq!(
+            decide(kinds(vec![destination(70000, vec![])])),
+            Err(ActionError::PortOutOfRange(70000))
+        );
+        let nested = destination(
+            60002,
+            vec![Kind::FanOut(FanOut {
+                destinations: vec![],
+            })],
+        );
+        assert_eq!(decide(kinds(vec![nested])), Err(ActionError::NestedFanOut));
+    }
+}
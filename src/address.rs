@@ -0,0 +1,121 @@
+//! This module is responsible for the addresses the interceptor connects to: the host the ports of the nodes are
+//! published on and the controllers. Both can be IPv4 addresses, IPv6 addresses or hostnames.
+//!
+//! A hostname can resolve to several addresses, e.g. `localhost` to `::1` and `127.0.0.1`. They are tried in the order
+//! the resolver returns them, such that a node that only listens on one of the address families is still reached.
+
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use tokio::net::TcpStream;
+
+/// Returns a host without the brackets around an IPv6 address, e.g. `::1` for `[::1]`.
+///
+/// # Parameters
+/// * 'host' - the IP address or hostname, IPv6 addresses with or without brackets.
+fn unbracketed(host: &str) -> &str {
+    host.strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host)
+}
+
+/// Formats a host and a port as they are written in a URL or an HTTP Host header, with brackets around IPv6 addresses,
+/// e.g. `[::1]:6005` or `127.0.0.1:6005`.
+///
+/// # Parameters
+/// * 'host' - the IP address or hostname.
+/// * 'port' - the port.
+pub fn host_port(host: &str, port: u16) -> String {
+    let host = unbracketed(host);
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V6(_)) => format!("[{}]:{}", host, port),
+        _ => format!("{}:{}", host, port),
+    }
+}
+
+/// Resolves a host to the socket addresses of a port, in the order they should be tried.
+///
+/// # Parameters
+/// * 'host' - the IP address or hostname.
+/// * 'port' - the port.
+pub async fn resolve(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+    let addresses: Vec<SocketAddr> = tokio::net::lookup_host((unbracketed(host), port))
+        .await?
+        .collect();
+    if addresses.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} does not resolve to any address", host),
+        ));
+    }
+    Ok(addresses)
+}
+
+/// Connects to a port of a host, trying every address the host resolves to until one accepts the connection. Returns
+/// the error of the last address if none does.
+///
+/// # Parameters
+/// * 'host' - the IP address or hostname.
+/// * 'port' - the port.
+pub async fn connect(host: &str, port: u16) -> io::Result<TcpStream> {
+    let mut last_error = None;
+    for address in resolve(host, port).await? {
+        match TcpStream::connect(address).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap())
+}
+
+/// Returns the URI of a controller, adding the `http://` scheme if the address has none, such that controllers can be
+/// configured as e.g. `controller:50051` or `[::1]:50051`.
+///
+/// # Parameters
+/// * 'address' - the address of the controller.
+pub fn controller_uri(address: &str) -> String {
+    if address.contains("://") {
+        address.to_string()
+    } else {
+        format!("http://{}", address)
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::address::{connect, controller_uri, host_port};
+    use tokio::net::TcpListener;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn format_host_and_port() {
+        assert_eq!(host_port("127.0.0.1", 6005), "127.0.0.1:6005");
+        assert_eq!(host_port("::1", 6005), "[::1]:6005");
+        assert_eq!(host_port("[fd00::2]", 6005), "[fd00::2]:6005");
+        assert_eq!(host_port("node-0", 6005), "node-0:6005");
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn controller_uri_has_a_scheme() {
+        assert_eq!(controller_uri("[::1]:50051"), "http://[::1]:50051");
+        assert_eq!(
+            controller_uri("controller:50051"),
+            "http://controller:50051"
+        );
+        assert_eq!(
+            controller_uri("https://controller:50051"),
+            "https://controller:50051"
+        );
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn connect_tries_every_address() {
+        // localhost may resolve to ::1 first, which nothing listens on
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(connect("localhost", port).await.is_ok());
+        assert!(connect("127.0.0.1", port).await.is_ok());
+        assert!(connect("host.invalid", port).await.is_err());
+    }
+}
@@ -0,0 +1,1224 @@
+//! This module is responsible for the admin HTTP API, through which the interceptor can be controlled while it is running,
+//! without restarting the network.
+//!
+//! Endpoints:
+//! * `GET /links` - lists the intercepted links with their rules and counters.
+//! * `PUT /links/:from_port/:to_port/rule` - replaces the delay and drop rules of a link.
+//! * `POST /pause` and `POST /resume` - pauses and resumes forwarding on all links.
+//! * `GET /time-dilation` and `PUT /time-dilation` - reads and sets the factor by which all injected delays are multiplied.
+//! * `GET /breakpoints`, `POST /breakpoints` and `DELETE /breakpoints/:id` - lists, adds and removes breakpoints.
+//! * `GET /breakpoints/hits` - lists the links that are halted and the messages they are halted at.
+//! * `POST /links/:from_port/:to_port/step` and `POST /links/:from_port/:to_port/continue` - lets a halted link
+//!   handle the message it is halted at, and halts it again at its next message when stepping.
+//! * `GET /stats` - dumps the counters of the links, the gauges of the queues, the state of the circuit breaker, the
+//!   health of the controllers and the latency added to the messages while they are only observed.
+//! * `GET /eclipse`, `PUT /eclipse` and `DELETE /eclipse` - reads, starts and ends the eclipse of a victim node.
+//! * `POST /inject` - writes a message into a link on behalf of the peer the link comes from.
+//! * `GET /blackholes`, `PUT /blackholes/:from_port/:message_type` and `DELETE /blackholes/:from_port/:message_type` -
+//!   lists, starts and stops dropping all messages of a type that a node sends.
+//! * `GET /partitions/one-way`, `PUT /partitions/one-way` and `DELETE /partitions/one-way` - reads, replaces and heals
+//!   the links that are cut in one direction only.
+//! * `GET /tx-suppression`, `PUT /tx-suppression` and `DELETE /tx-suppression` - reads, replaces and ends the
+//!   suppression of the transactions relayed on links.
+//! * `GET /mutation-rules` and `PUT /mutation-rules` - reads and replaces the rules by which messages are mutated
+//!   locally, on top of the decision of the controller.
+//! * `GET /shaping-rules` and `PUT /shaping-rules` - reads and replaces the rules that limit the rate of message types
+//!   per link.
+//! * `GET /manifests` - lists the latest manifest of every master key that was seen or announced.
+//! * `GET /manifest-rules` and `PUT /manifest-rules` - reads and replaces the rules by which the manifests of nodes are
+//!   rewritten.
+//! * `POST /manifests/announce` - announces a manifest that rotates the signing key of a node or revokes its master key.
+//! * `GET /squelches` - lists the squelches that are in effect on every link.
+//! * `GET /squelch-rules` and `PUT /squelch-rules` - reads and replaces the rules by which squelches are overridden.
+//! * `POST /squelches/forge` - sends a squelch to a peer on behalf of a node.
+//! * `GET /equivocation-rules` and `PUT /equivocation-rules` - reads and replaces the rules by which the proposals of
+//!   nodes are equivocated.
+//! * `POST /replay` - re-injects captured messages, e.g. the validations from 10 ledgers ago.
+//! * `GET /broadcasts` and `PUT /broadcasts/dedupe` - reports the copies of every validation and proposal, and sets
+//!   whether their redundant copies are dropped.
+
+use crate::breakpoint::{Breakpoint, BreakpointHit, NotHaltedError};
+use crate::broadcast::{BroadcastReport, Dedupe};
+use crate::circuit_breaker::{BreakerSummary, CircuitBreaker};
+use crate::consensus_round::RoundTimeline;
+use crate::controller_pool::{ControllerPool, EndpointSummary};
+use crate::eclipse::{Eclipse, InjectError};
+use crate::equivocation::EquivocationRule;
+use crate::field_mutation::MutationRule;
+use crate::interceptor_state::{Blackhole, InterceptorState, Link, LinkRule};
+use crate::manifest::{Manifest, ManifestAction, ManifestError, ManifestRule};
+use crate::partition::OneWayPartition;
+use crate::passive::{AddedLatency, PassiveObserver};
+use crate::replay::{self, ReplayOutcome, ReplayRequest};
+use crate::squelch::{ActiveSquelch, Squelch, SquelchRule};
+use crate::traffic_shaping::ShapingRule;
+use crate::tx_suppression::TxSuppression;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{delete, get, post, put};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tracing::{error, info};
+
+/// Struct that represents a link as it is returned by the API.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LinkSummary {
+    pub from_port: u16,
+    pub to_port: u16,
+    pub protocol: Option<String>,
+    pub rule: LinkRule,
+    pub handled: u64,
+    pub dropped: u64,
+    /// The moving average of the round trip time of the requests to the controller, if any was sent.
+    pub controller_rtt_ms: Option<f64>,
+}
+
+impl From<&Link> for LinkSummary {
+    fn from(link: &Link) -> Self {
+        Self {
+            from_port: link.from_port,
+            to_port: link.to_port,
+            protocol: link.protocol.clone(),
+            rule: link.rule(),
+            handled: link.handled(),
+            dropped: link.dropped(),
+            controller_rtt_ms: link.controller_rtt().map(|rtt| rtt.as_secs_f64() * 1000.0),
+        }
+    }
+}
+
+/// Struct that represents the gauges of a queue as they are returned by the API.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QueueSummary {
+    pub name: String,
+    pub depth: usize,
+    pub max_depth: usize,
+    pub capacity: usize,
+    pub dropped: u64,
+    pub spilled: u64,
+}
+
+/// Struct that represents the statistics returned by the API.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Stats {
+    pub paused: bool,
+    pub time_dilation: f64,
+    pub passthrough: bool,
+    pub links: Vec<LinkSummary>,
+    pub queues: Vec<QueueSummary>,
+    /// The state and counters of the circuit breaker of the controller channel, if it is enabled.
+    pub circuit_breaker: Option<BreakerSummary>,
+    /// The health and channels of every controller and the amount of links pinned to it, if the links are spread over
+    /// multiple controllers or channels.
+    pub controllers: Vec<EndpointSummary>,
+    /// The latency added to the messages so far, if messages are only observed.
+    pub added_latency: Option<AddedLatency>,
+}
+
+/// Struct that represents whether forwarding is paused, as it is returned by the API.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct PauseState {
+    pub paused: bool,
+}
+
+/// Struct that represents the time dilation factor, as it is sent to and returned by the API.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TimeDilation {
+    pub factor: f64,
+}
+
+/// Struct that represents a message to be injected, as it is sent to the API.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Injection {
+    pub from_port: u16,
+    pub to_port: u16,
+    /// The complete message in hex, including its 6 byte header.
+    pub data: String,
+}
+
+/// Struct that represents a manifest to be announced, as it is sent to the API.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Announcement {
+    /// The port of the node the manifest is announced for.
+    pub node_port: u16,
+    /// Whether the signing key of the node is rotated or its master key revoked.
+    pub action: ManifestAction,
+    /// The port of the peer it is announced to, all peers of the node if not set.
+    #[serde(default)]
+    pub to_port: Option<u16>,
+}
+
+/// Struct that represents a squelch to be forged, as it is sent to the API.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ForgedSquelch {
+    /// The port of the node the squelch is sent on behalf of.
+    pub from_port: u16,
+    /// The port of the peer the squelch is sent to.
+    pub to_port: u16,
+    #[serde(flatten)]
+    pub squelch: Squelch,
+}
+
+/// Returns the router with all endpoints of the admin API.
+///
+/// # Parameters
+/// * 'state' - the runtime state that is read and changed through the API.
+pub fn router(state: Arc<InterceptorState>) -> Router {
+    Router::new()
+        .route("/links", get(list_links))
+        .route("/links/:from_port/:to_port/rule", put(set_link_rule))
+        .route("/pause", post(pause))
+        .route("/resume", post(resume))
+        .route("/time-dilation", get(time_dilation).put(set_time_dilation))
+        .route("/breakpoints", get(list_breakpoints).post(add_breakpoint))
+        .route("/breakpoints/hits", get(breakpoint_hits))
+        .route("/breakpoints/:id", delete(remove_breakpoint))
+        .route("/links/:from_port/:to_port/step", post(step_link))
+        .route("/links/:from_port/:to_port/continue", post(continue_link))
+        .route("/stats", get(stats))
+        .route("/rounds", get(rounds))
+        .route(
+            "/eclipse",
+            get(eclipse).put(start_eclipse).delete(end_eclipse),
+        )
+        .route("/inject", post(inject))
+        .route("/blackholes", get(list_blackholes))
+        .route(
+            "/blackholes/:from_port/:message_type",
+            put(add_blackhole).delete(remove_blackhole),
+        )
+        .route(
+            "/partitions/one-way",
+            get(one_way_partition)
+                .put(set_one_way_partition)
+                .delete(heal_one_way_partition),
+        )
+        .route(
+            "/tx-suppression",
+            get(tx_suppression)
+                .put(set_tx_suppression)
+                .delete(end_tx_suppression),
+        )
+        .route(
+            "/mutation-rules",
+            get(mutation_rules).put(set_mutation_rules),
+        )
+        .route("/shaping-rules", get(shaping_rules).put(set_shaping_rules))
+        .route("/manifests", get(manifests))
+        .route(
+            "/manifest-rules",
+            get(manifest_rules).put(set_manifest_rules),
+        )
+        .route("/manifests/announce", post(announce_manifest))
+        .route("/squelches", get(squelches))
+        .route("/squelch-rules", get(squelch_rules).put(set_squelch_rules))
+        .route("/squelches/forge", post(forge_squelch))
+        .route(
+            "/equivocation-rules",
+            get(equivocation_rules).put(set_equivocation_rules),
+        )
+        .route("/replay", post(replay))
+        .route("/broadcasts", get(broadcasts))
+        .route("/broadcasts/dedupe", put(set_dedupe))
+        .with_state(state)
+}
+
+/// Serves the admin API until the listener fails.
+///
+/// # Parameters
+/// * 'listener' - the bound listener of the admin API.
+/// * 'state' - the runtime state that is read and changed through the API.
+pub async fn serve(listener: TcpListener, state: Arc<InterceptorState>) {
+    info!("Serving the admin API on {:?}", listener.local_addr());
+    if let Err(e) = axum::serve(listener, router(state)).await {
+        error!("The admin API stopped: {}", e);
+    }
+}
+
+/// Lists all intercepted links.
+async fn list_links(State(state): State<Arc<InterceptorState>>) -> Json<Vec<LinkSummary>> {
+    Json(
+        state
+            .links()
+            .iter()
+            .map(|link| LinkSummary::from(link.as_ref()))
+            .collect(),
+    )
+}
+
+/// Replaces the rules of a link. Responds with 404 if the link does not exist, and with 400 if the rule is invalid.
+async fn set_link_rule(
+    State(state): State<Arc<InterceptorState>>,
+    Path((from_port, to_port)): Path<(u16, u16)>,
+    Json(rule): Json<LinkRule>,
+) -> Result<Json<LinkSummary>, (StatusCode, String)> {
+    match state.set_link_rule(from_port, to_port, rule) {
+        Ok(Some(link)) => {
+            info!("Rule of link {}->{} set to {:?}", from_port, to_port, rule);
+            Ok(Json(LinkSummary::from(link.as_ref())))
+        }
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Link {}->{} does not exist", from_port, to_port),
+        )),
+        Err(e) => Err((StatusCode::BAD_REQUEST, e.to_string())),
+    }
+}
+
+/// Pauses forwarding on all links.
+async fn pause(State(state): State<Arc<InterceptorState>>) -> Json<PauseState> {
+    state.set_paused(true);
+    info!("Forwarding paused");
+    Json(PauseState { paused: true })
+}
+
+/// Resumes forwarding on all links.
+async fn resume(State(state): State<Arc<InterceptorState>>) -> Json<PauseState> {
+    state.set_paused(false);
+    info!("Forwarding resumed");
+    Json(PauseState { paused: false })
+}
+
+/// Returns the factor by which all injected delays are multiplied.
+async fn time_dilation(State(state): State<Arc<InterceptorState>>) -> Json<TimeDilation> {
+    Json(TimeDilation {
+        factor: state.time_dilation(),
+    })
+}
+
+/// Sets the factor by which all injected delays are multiplied. Responds with 400 if the factor is invalid.
+async fn set_time_dilation(
+    State(state): State<Arc<InterceptorState>>,
+    Json(time_dilation): Json<TimeDilation>,
+) -> Result<Json<TimeDilation>, (StatusCode, String)> {
+    state
+        .set_time_dilation(time_dilation.factor)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    info!("Time dilation set to {}", time_dilation.factor);
+    Ok(Json(time_dilation))
+}
+
+/// Lists all breakpoints.
+async fn list_breakpoints(State(state): State<Arc<InterceptorState>>) -> Json<Vec<Breakpoint>> {
+    Json(state.breakpoints())
+}
+
+/// Adds a breakpoint and responds with it, including its assigned ID.
+async fn add_breakpoint(
+    State(state): State<Arc<InterceptorState>>,
+    Json(breakpoint): Json<Breakpoint>,
+) -> (StatusCode, Json<Breakpoint>) {
+    let breakpoint = state.add_breakpoint(breakpoint);
+    info!("Breakpoint added: {:?}", breakpoint);
+    (StatusCode::CREATED, Json(breakpoint))
+}
+
+/// Removes a breakpoint. Responds with 404 if the breakpoint does not exist.
+async fn remove_breakpoint(
+    State(state): State<Arc<InterceptorState>>,
+    Path(id): Path<u32>,
+) -> StatusCode {
+    if state.remove_breakpoint(id) {
+        info!("Breakpoint {} removed", id);
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+/// Lists the messages all halted links are halted at.
+async fn breakpoint_hits(State(state): State<Arc<InterceptorState>>) -> Json<Vec<BreakpointHit>> {
+    Json(
+        state
+            .links()
+            .iter()
+            .filter_map(|link| link.debugger.halted_at())
+            .collect(),
+    )
+}
+
+/// Lets a halted link handle the message it is halted at, and halts it again at its next message.
+async fn step_link(
+    State(state): State<Arc<InterceptorState>>,
+    Path((from_port, to_port)): Path<(u16, u16)>,
+) -> Result<Json<BreakpointHit>, (StatusCode, String)> {
+    resume_link(&state, from_port, to_port, true)
+}
+
+/// Lets a halted link handle the message it is halted at, and run until a breakpoint matches.
+async fn continue_link(
+    State(state): State<Arc<InterceptorState>>,
+    Path((from_port, to_port)): Path<(u16, u16)>,
+) -> Result<Json<BreakpointHit>, (StatusCode, String)> {
+    resume_link(&state, from_port, to_port, false)
+}
+
+/// Resumes a halted link and responds with the message it was halted at.
+/// Responds with 404 if the link does not exist, and with 409 if it is not halted.
+///
+/// # Parameters
+/// * 'state' - the runtime state containing the links.
+/// * 'from_port' - the port of the peer the messages come from.
+/// * 'to_port' - the port of the peer the messages go to.
+/// * 'step' - whether the link halts again at its next message.
+fn resume_link(
+    state: &InterceptorState,
+    from_port: u16,
+    to_port: u16,
+    step: bool,
+) -> Result<Json<BreakpointHit>, (StatusCode, String)> {
+    let link = state.link(from_port, to_port).ok_or((
+        StatusCode::NOT_FOUND,
+        format!("Link {}->{} does not exist", from_port, to_port),
+    ))?;
+    let hit = link.debugger.resume(step).ok_or_else(|| {
+        (
+            StatusCode::CONFLICT,
+            NotHaltedError { from_port, to_port }.to_string(),
+        )
+    })?;
+    Ok(Json(hit))
+}
+
+/// Returns the current eclipse, which is null if no node is eclipsed.
+async fn eclipse(State(state): State<Arc<InterceptorState>>) -> Json<Option<Eclipse>> {
+    Json(state.eclipse())
+}
+
+/// Eclipses a victim node, replacing the current eclipse. Responds with 404 if no link reads from the victim.
+async fn start_eclipse(
+    State(state): State<Arc<InterceptorState>>,
+    Json(eclipse): Json<Eclipse>,
+) -> Result<Json<Eclipse>, (StatusCode, String)> {
+    if !state
+        .links()
+        .iter()
+        .any(|link| link.from_port == eclipse.victim_port)
+    {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("Node {} does not exist", eclipse.victim_port),
+        ));
+    }
+    info!("Eclipse started: {:?}", eclipse);
+    state.set_eclipse(Some(eclipse.clone()));
+    Ok(Json(eclipse))
+}
+
+/// Ends the eclipse, reconnecting the victim to all its peers.
+async fn end_eclipse(State(state): State<Arc<InterceptorState>>) -> StatusCode {
+    state.set_eclipse(None);
+    info!("Eclipse ended");
+    StatusCode::NO_CONTENT
+}
+
+/// Lists the message types that are dropped per node.
+async fn list_blackholes(State(state): State<Arc<InterceptorState>>) -> Json<Vec<Blackhole>> {
+    Json(state.blackholes())
+}
+
+/// Starts dropping all messages of a type that a node sends, e.g. `PUT /blackholes/60002/mtVALIDATION`.
+/// Responds with 400 if the message type is unknown, and with 404 if no link reads from the node.
+async fn add_blackhole(
+    State(state): State<Arc<InterceptorState>>,
+    Path((from_port, message_type)): Path<(u16, String)>,
+) -> Result<Json<Blackhole>, (StatusCode, String)> {
+    let blackhole = blackhole(&state, from_port, &message_type)?;
+    if state.add_blackhole(blackhole) {
+        info!(
+            "Dropping all {} of node {}",
+            blackhole.message_type, from_port
+        );
+    }
+    Ok(Json(blackhole))
+}
+
+/// Stops dropping all messages of a type that a node sends.
+/// Responds with 400 if the message type is unknown, and with 404 if the messages were not dropped.
+async fn remove_blackhole(
+    State(state): State<Arc<InterceptorState>>,
+    Path((from_port, message_type)): Path<(u16, String)>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let blackhole = blackhole(&state, from_port, &message_type)?;
+    if !state.remove_blackhole(blackhole) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!(
+                "{} of node {} are not dropped",
+                blackhole.message_type, from_port
+            ),
+        ));
+    }
+    info!(
+        "No longer dropping all {} of node {}",
+        blackhole.message_type, from_port
+    );
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Parses the blackhole of a request. Fails with 400 if the message type is unknown,
+/// and with 404 if no link reads from the node.
+///
+/// # Parameters
+/// * 'state' - the runtime state, containing the links.
+/// * 'from_port' - the port of the node whose messages are dropped.
+/// * 'message_type' - the name of the type of the messages, e.g. 'mtVALIDATION'.
+fn blackhole(
+    state: &InterceptorState,
+    from_port: u16,
+    message_type: &str,
+) -> Result<Blackhole, (StatusCode, String)> {
+    let message_type = message_type
+        .parse()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    if !state.links().iter().any(|link| link.from_port == from_port) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("Node {} does not exist", from_port),
+        ));
+    }
+    Ok(Blackhole {
+        from_port,
+        message_type,
+    })
+}
+
+/// Returns the links that are cut in one direction only.
+async fn one_way_partition(State(state): State<Arc<InterceptorState>>) -> Json<OneWayPartition> {
+    Json(state.one_way_partition())
+}
+
+/// Replaces the links that are cut in one direction only. Responds with 404 if one of the links does not exist.
+async fn set_one_way_partition(
+    State(state): State<Arc<InterceptorState>>,
+    Json(partition): Json<OneWayPartition>,
+) -> Result<Json<OneWayPartition>, (StatusCode, String)> {
+    if let Some((from_port, to_port)) = partition
+        .cut_links
+        .iter()
+        .find(|(from_port, to_port)| state.link(*from_port, *to_port).is_none())
+    {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("Link {}->{} does not exist", from_port, to_port),
+        ));
+    }
+    info!("One-way partition set: {:?}", partition.cut_links);
+    state.set_one_way_partition(partition.clone());
+    Ok(Json(partition))
+}
+
+/// Heals the one-way partition, such that messages flow in both directions on all links again.
+async fn heal_one_way_partition(State(state): State<Arc<InterceptorState>>) -> StatusCode {
+    state.set_one_way_partition(OneWayPartition::default());
+    info!("One-way partition healed");
+    StatusCode::NO_CONTENT
+}
+
+/// Returns the links on which the relay of transactions is suppressed.
+async fn tx_suppression(State(state): State<Arc<InterceptorState>>) -> Json<TxSuppression> {
+    Json(state.tx_suppression())
+}
+
+/// Replaces the links on which the relay of transactions is suppressed. Responds with 404 if one of the links does not
+/// exist.
+async fn set_tx_suppression(
+    State(state): State<Arc<InterceptorState>>,
+    Json(suppression): Json<TxSuppression>,
+) -> Result<Json<TxSuppression>, (StatusCode, String)> {
+    if let Some((from_port, to_port)) = suppression
+        .links
+        .iter()
+        .find(|(from_port, to_port)| state.link(*from_port, *to_port).is_none())
+    {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("Link {}->{} does not exist", from_port, to_port),
+        ));
+    }
+    info!("Transaction relay suppressed on: {:?}", suppression.links);
+    state.set_tx_suppression(suppression.clone());
+    Ok(Json(suppression))
+}
+
+/// Ends the suppression, such that transactions are relayed on all links again.
+async fn end_tx_suppression(State(state): State<Arc<InterceptorState>>) -> StatusCode {
+    state.set_tx_suppression(TxSuppression::default());
+    info!("Transaction relay suppression ended");
+    StatusCode::NO_CONTENT
+}
+
+/// Writes a message into a link. Responds with 404 if the link does not exist, and with 400 if the message is malformed.
+async fn inject(
+    State(state): State<Arc<InterceptorState>>,
+    Json(injection): Json<Injection>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let data = hex::decode(&injection.data).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            InjectError::Malformed(e.to_string()).to_string(),
+        )
+    })?;
+    match state
+        .inject(injection.from_port, injection.to_port, data.into())
+        .await
+    {
+        Ok(()) => Ok(StatusCode::NO_CONTENT),
+        Err(e @ InjectError::NoLink(..)) => Err((StatusCode::NOT_FOUND, e.to_string())),
+        Err(e) => Err((StatusCode::BAD_REQUEST, e.to_string())),
+    }
+}
+
+/// Returns the rules by which messages are mutated locally.
+async fn mutation_rules(State(state): State<Arc<InterceptorState>>) -> Json<Vec<MutationRule>> {
+    Json(state.mutation_rules())
+}
+
+/// Replaces the rules by which messages are mutated locally. Responds with 400 if a path does not address a field.
+async fn set_mutation_rules(
+    State(state): State<Arc<InterceptorState>>,
+    Json(rules): Json<Vec<MutationRule>>,
+) -> Result<Json<Vec<MutationRule>>, (StatusCode, String)> {
+    for rule in rules.iter() {
+        rule.validate()
+            .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    }
+    info!("Mutation rules set: {:?}", rules);
+    state.set_mutation_rules(rules.clone());
+    Ok(Json(rules))
+}
+
+/// Returns the rules that limit the rate of message types per link.
+async fn shaping_rules(State(state): State<Arc<InterceptorState>>) -> Json<Vec<ShapingRule>> {
+    Json(state.shaping_rules())
+}
+
+/// Replaces the rules that limit the rate of message types per link. Responds with 400 if a rate or burst is invalid.
+async fn set_shaping_rules(
+    State(state): State<Arc<InterceptorState>>,
+    Json(rules): Json<Vec<ShapingRule>>,
+) -> Result<Json<Vec<ShapingRule>>, (StatusCode, String)> {
+    state
+        .set_shaping_rules(rules.clone())
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    info!("Shaping rules set: {:?}", rules);
+    Ok(Json(rules))
+}
+
+/// Returns the latest manifest of every master key that was seen or announced.
+async fn manifests(State(state): State<Arc<InterceptorState>>) -> Json<Vec<Manifest>> {
+    Json(state.manifests())
+}
+
+/// Returns the rules by which the manifests of nodes are rewritten.
+async fn manifest_rules(State(state): State<Arc<InterceptorState>>) -> Json<Vec<ManifestRule>> {
+    Json(state.manifest_rules())
+}
+
+/// Replaces the rules by which the manifests of nodes are rewritten.
+async fn set_manifest_rules(
+    State(state): State<Arc<InterceptorState>>,
+    Json(rules): Json<Vec<ManifestRule>>,
+) -> Json<Vec<ManifestRule>> {
+    info!("Manifest rules set: {:?}", rules);
+    state.set_manifest_rules(rules.clone());
+    Json(rules)
+}
+
+/// Announces a manifest of a node and returns it. Responds with 404 if the node or its links do not exist, and with 400
+/// if the action can not be announced.
+async fn announce_manifest(
+    State(state): State<Arc<InterceptorState>>,
+    Json(announcement): Json<Announcement>,
+) -> Result<Json<Manifest>, (StatusCode, String)> {
+    match state
+        .announce_manifest(
+            announcement.node_port,
+            announcement.action,
+            announcement.to_port,
+        )
+        .await
+    {
+        Ok(manifest) => {
+            info!(
+                "Manifest {} announced for node {}",
+                manifest.sequence, announcement.node_port
+            );
+            Ok(Json(manifest))
+        }
+        Err(e @ (ManifestError::NoNodeKey(_) | ManifestError::NoLink(..))) => {
+            Err((StatusCode::NOT_FOUND, e.to_string()))
+        }
+        Err(e) => Err((StatusCode::BAD_REQUEST, e.to_string())),
+    }
+}
+
+/// Returns the squelches that are in effect on every link.
+async fn squelches(State(state): State<Arc<InterceptorState>>) -> Json<Vec<ActiveSquelch>> {
+    Json(state.squelches())
+}
+
+/// Returns the rules by which squelches are overridden.
+async fn squelch_rules(State(state): State<Arc<InterceptorState>>) -> Json<Vec<SquelchRule>> {
+    Json(state.squelch_rules())
+}
+
+/// Replaces the rules by which squelches are overridden.
+async fn set_squelch_rules(
+    State(state): State<Arc<InterceptorState>>,
+    Json(rules): Json<Vec<SquelchRule>>,
+) -> Json<Vec<SquelchRule>> {
+    info!("Squelch rules set: {:?}", rules);
+    state.set_squelch_rules(rules.clone());
+    Json(rules)
+}
+
+/// Sends a squelch to a peer on behalf of a node. Responds with 404 if the link does not exist.
+async fn forge_squelch(
+    State(state): State<Arc<InterceptorState>>,
+    Json(forged): Json<ForgedSquelch>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    match state
+        .forge_squelch(forged.from_port, forged.to_port, &forged.squelch)
+        .await
+    {
+        Ok(()) => Ok(StatusCode::NO_CONTENT),
+        Err(e @ InjectError::NoLink(..)) => Err((StatusCode::NOT_FOUND, e.to_string())),
+        Err(e) => Err((StatusCode::BAD_REQUEST, e.to_string())),
+    }
+}
+
+/// Returns the rules by which the proposals of nodes are equivocated.
+async fn equivocation_rules(
+    State(state): State<Arc<InterceptorState>>,
+) -> Json<Vec<EquivocationRule>> {
+    Json(state.equivocation_rules())
+}
+
+/// Replaces the rules by which the proposals of nodes are equivocated. Responds with 400 if a rule is not valid.
+async fn set_equivocation_rules(
+    State(state): State<Arc<InterceptorState>>,
+    Json(rules): Json<Vec<EquivocationRule>>,
+) -> Result<Json<Vec<EquivocationRule>>, (StatusCode, String)> {
+    state
+        .set_equivocation_rules(rules.clone())
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    info!("Equivocation rules set: {:?}", rules);
+    Ok(Json(rules))
+}
+
+/// Re-injects the captured messages selected by the request. Fails with 404 if messages are not captured.
+async fn replay(
+    State(state): State<Arc<InterceptorState>>,
+    Json(request): Json<ReplayRequest>,
+) -> Result<Json<ReplayOutcome>, (StatusCode, String)> {
+    replay::replay(&request, &state).await.map(Json).ok_or((
+        StatusCode::NOT_FOUND,
+        "Messages are not captured, configure [replay] to capture them".to_string(),
+    ))
+}
+
+/// Reports the copies of the broadcasts so far. Fails with 404 if the broadcasts are not tracked.
+async fn broadcasts(
+    State(state): State<Arc<InterceptorState>>,
+) -> Result<Json<BroadcastReport>, (StatusCode, String)> {
+    state
+        .broadcasts()
+        .map(|broadcasts| Json(broadcasts.report()))
+        .ok_or_else(not_tracked)
+}
+
+/// Sets whether the redundant copies of broadcasts are dropped. Fails with 404 if the broadcasts are not tracked.
+async fn set_dedupe(
+    State(state): State<Arc<InterceptorState>>,
+    Json(dedupe): Json<Dedupe>,
+) -> Result<Json<Dedupe>, (StatusCode, String)> {
+    let broadcasts = state.broadcasts().ok_or_else(not_tracked)?;
+    broadcasts.set_dedupe(dedupe.enabled);
+    info!("Deduplication of broadcasts set to {}", dedupe.enabled);
+    Ok(Json(dedupe))
+}
+
+/// Returns the response to requests about broadcasts while they are not tracked.
+fn not_tracked() -> (StatusCode, String) {
+    (
+        StatusCode::NOT_FOUND,
+        "Broadcasts are not tracked, configure [broadcasts] to track them".to_string(),
+    )
+}
+
+/// Dumps the counters of the links, the gauges of the queues, the state of the circuit breaker and the added latency.
+async fn stats(State(state): State<Arc<InterceptorState>>) -> Json<Stats> {
+    Json(Stats {
+        paused: state.is_paused(),
+        time_dilation: state.time_dilation(),
+        passthrough: state.is_passthrough(),
+        links: state
+            .links()
+            .iter()
+            .map(|link| LinkSummary::from(link.as_ref()))
+            .collect(),
+        queues: state
+            .queue_gauges()
+            .iter()
+            .map(|gauge| QueueSummary {
+                name: gauge.name.clone(),
+                depth: gauge.depth(),
+                max_depth: gauge.max_depth(),
+                capacity: gauge.capacity,
+                dropped: gauge.dropped(),
+                spilled: gauge.spilled(),
+            })
+            .collect(),
+        circuit_breaker: state.circuit_breaker().map(CircuitBreaker::summary),
+        controllers: state
+            .controller_pool()
+            .map(ControllerPool::summary)
+            .unwrap_or_default(),
+        added_latency: state.passive().map(PassiveObserver::added_latency),
+    })
+}
+
+/// Returns the counts of the handled messages per consensus round, node and message type.
+async fn rounds(State(state): State<Arc<InterceptorState>>) -> Json<RoundTimeline> {
+    Json(state.statistics.round_timeline())
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::admin_api::{
+        add_blackhole, add_breakpoint, breakpoint_hits, broadcasts, continue_link, eclipse,
+        end_eclipse, heal_one_way_partition, inject, list_blackholes, list_breakpoints, list_links,
+        mutation_rules, one_way_partition, pause, remove_blackhole, remove_breakpoint, replay,
+        resume, set_dedupe, set_link_rule, set_mutation_rules, set_one_way_partition,
+        set_shaping_rules, set_time_dilation, shaping_rules, start_eclipse, stats, step_link,
+        Injection, TimeDilation,
+    };
+    use crate::breakpoint::{Breakpoint, BreakpointHit};
+    use crate::broadcast::{BroadcastTracker, Dedupe};
+    use crate::config::{BroadcastConfig, OverflowPolicy};
+    use crate::eclipse::Eclipse;
+    use crate::field_mutation::{FieldMutation, FieldOperation, MutationRule};
+    use crate::interceptor_state::{Blackhole, InterceptorState, LinkRule};
+    use crate::message_queue::{BoundedQueue, QueueGauge};
+    use crate::message_type::MessageType;
+    use crate::packet_timeline::PacketTimeline;
+    use crate::partition::OneWayPartition;
+    use crate::ping::Ping;
+    use crate::replay::{CaptureBuffer, CapturedMessage, ReplayOutcome, ReplayRequest};
+    use crate::traffic_shaping::{ShapingAction, ShapingRule};
+    use axum::extract::{Path, State};
+    use axum::http::StatusCode;
+    use axum::Json;
+    use std::sync::Arc;
+
+    fn state() -> Arc<InterceptorState> {
+        let state = Arc::new(InterceptorState::new(Arc::new(PacketTimeline::new(10))));
+        state.register_link(60000, 60001, Some("XRPL/2.2".to_string()));
+        state.register_queue("decision:60000->60001".to_string(), 10);
+        state
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn change_link_rule() {
+        let state = state();
+        let rule = LinkRule {
+            delay_ms: 200,
+            drop_probability: 0.1,
+        };
+        let Json(link) = set_link_rule(State(state.clone()), Path((60000, 60001)), Json(rule))
+            .await
+            .unwrap();
+        assert_eq!(link.rule, rule);
+
+        let Json(links) = list_links(State(state.clone())).await;
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].rule, rule);
+        assert_eq!(links[0].protocol.as_deref(), Some("XRPL/2.2"));
+
+        let error = set_link_rule(State(state.clone()), Path((60001, 60000)), Json(rule))
+            .await
+            .unwrap_err();
+        assert_eq!(error.0, StatusCode::NOT_FOUND);
+
+        let invalid = LinkRule {
+            delay_ms: 0,
+            drop_probability: -1.0,
+        };
+        let error = set_link_rule(State(state), Path((60000, 60001)), Json(invalid))
+            .await
+            .unwrap_err();
+        assert_eq!(error.0, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn pause_resume_and_stats() {
+        let state = state();
+        assert!(pause(State(state.clone())).await.paused);
+        let Json(paused_stats) = stats(State(state.clone())).await;
+        assert!(paused_stats.paused);
+        assert_eq!(paused_stats.links.len(), 1);
+        assert_eq!(paused_stats.queues[0].name, "decision:60000->60001");
+        assert_eq!(paused_stats.queues[0].capacity, 10);
+
+        assert!(!resume(State(state.clone())).await.paused);
+        assert!(!stats(State(state)).await.paused);
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn change_time_dilation() {
+        let state = state();
+        let Json(time_dilation) =
+            set_time_dilation(State(state.clone()), Json(TimeDilation { factor: 4.0 }))
+                .await
+                .unwrap();
+        assert_eq!(time_dilation.factor, 4.0);
+        assert_eq!(stats(State(state.clone())).await.time_dilation, 4.0);
+
+        let error = set_time_dilation(State(state.clone()), Json(TimeDilation { factor: -1.0 }))
+            .await
+            .unwrap_err();
+        assert_eq!(error.0, StatusCode::BAD_REQUEST);
+        assert_eq!(state.time_dilation(), 4.0);
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn breakpoints_and_stepping() {
+        let state = state();
+        let breakpoint = Breakpoint {
+            id: 0,
+            message_type: Some(MessageType::Validation),
+            from_port: Some(60000),
+            to_port: None,
+            ledger_sequence: None,
+        };
+        let (status, Json(added)) = add_breakpoint(State(state.clone()), Json(breakpoint)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(
+            list_breakpoints(State(state.clone())).await.0,
+            vec![added.clone()]
+        );
+
+        let error = step_link(State(state.clone()), Path((60000, 60001)))
+            .await
+            .unwrap_err();
+        assert_eq!(error.0, StatusCode::CONFLICT);
+        let error = continue_link(State(state.clone()), Path((60001, 60000)))
+            .await
+            .unwrap_err();
+        assert_eq!(error.0, StatusCode::NOT_FOUND);
+
+        let link = state.link(60000, 60001).unwrap();
+        let hit = BreakpointHit {
+            breakpoint_id: Some(added.id),
+            from_port: 60000,
+            to_port: 60001,
+            sequence: 3,
+            message_type: MessageType::Validation,
+            ledger_sequence: None,
+        };
+        let halted = tokio::spawn({
+            let link = link.clone();
+            let hit = hit.clone();
+            async move { link.debugger.halt(hit).await }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        assert_eq!(
+            breakpoint_hits(State(state.clone())).await.0,
+            vec![hit.clone()]
+        );
+
+        let Json(resumed) = step_link(State(state.clone()), Path((60000, 60001)))
+            .await
+            .unwrap();
+        assert_eq!(resumed, hit);
+        halted.await.unwrap();
+        assert!(link.debugger.is_stepping());
+
+        assert_eq!(
+            remove_breakpoint(State(state.clone()), Path(added.id)).await,
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(
+            remove_breakpoint(State(state), Path(added.id)).await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn start_and_end_eclipse() {
+        let state = state();
+        let victim = Eclipse {
+            victim_port: 60000,
+            visible_peers: vec![],
+            visible_types: vec![],
+        };
+        let Json(started) = start_eclipse(State(state.clone()), Json(victim.clone()))
+            .await
+            .unwrap();
+        assert_eq!(started, victim);
+        assert_eq!(eclipse(State(state.clone())).await.0, Some(victim));
+        assert!(state.is_eclipsed(60000, 60001, MessageType::Validation));
+
+        let unknown = Eclipse {
+            victim_port: 60005,
+            visible_peers: vec![],
+            visible_types: vec![],
+        };
+        let error = start_eclipse(State(state.clone()), Json(unknown))
+            .await
+            .unwrap_err();
+        assert_eq!(error.0, StatusCode::NOT_FOUND);
+
+        assert_eq!(
+            end_eclipse(State(state.clone())).await,
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(eclipse(State(state)).await.0, None);
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn add_and_remove_blackhole() {
+        let state = state();
+        let path = |from_port: u16, message_type: &str| Path((from_port, message_type.to_string()));
+        let Json(blackhole) = add_blackhole(State(state.clone()), path(60000, "mtVALIDATION"))
+            .await
+            .unwrap();
+        assert_eq!(
+            blackhole,
+            Blackhole {
+                from_port: 60000,
+                message_type: MessageType::Validation,
+            }
+        );
+        assert_eq!(
+            list_blackholes(State(state.clone())).await.0,
+            vec![blackhole]
+        );
+
+        let error = add_blackhole(State(state.clone()), path(60000, "mtUNKNOWN"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.0, StatusCode::BAD_REQUEST);
+        let error = add_blackhole(State(state.clone()), path(60005, "mtVALIDATION"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.0, StatusCode::NOT_FOUND);
+
+        assert_eq!(
+            remove_blackhole(State(state.clone()), path(60000, "mtVALIDATION")).await,
+            Ok(StatusCode::NO_CONTENT)
+        );
+        let error = remove_blackhole(State(state.clone()), path(60000, "mtVALIDATION"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.0, StatusCode::NOT_FOUND);
+        assert!(list_blackholes(State(state)).await.0.is_empty());
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn set_and_heal_one_way_partition() {
+        let state = state();
+        let partition = OneWayPartition {
+            cut_links: vec![(60000, 60001)],
+        };
+        let Json(set) = set_one_way_partition(State(state.clone()), Json(partition.clone()))
+            .await
+            .unwrap();
+        assert_eq!(set, partition);
+        assert_eq!(one_way_partition(State(state.clone())).await.0, partition);
+        assert_eq!(
+            state.cut_reason(60000, 60001, MessageType::Validation),
+            Some("partition")
+        );
+
+        let unknown = OneWayPartition {
+            cut_links: vec![(60001, 60000)],
+        };
+        let error = set_one_way_partition(State(state.clone()), Json(unknown))
+            .await
+            .unwrap_err();
+        assert_eq!(error.0, StatusCode::NOT_FOUND);
+
+        assert_eq!(
+            heal_one_way_partition(State(state.clone())).await,
+            StatusCode::NO_CONTENT
+        );
+        assert!(one_way_partition(State(state)).await.0.cut_links.is_empty());
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn inject_message() {
+        let state = state();
+        let write_queue = Arc::new(BoundedQueue::new(
+            OverflowPolicy::Block,
+            Arc::new(QueueGauge::new("write:60000".to_string(), 10)),
+        ));
+        state.register_write_queue(60000, write_queue.clone());
+        let ping = Ping::default().to_message();
+
+        let status = inject(
+            State(state.clone()),
+            Json(Injection {
+                from_port: 60000,
+                to_port: 60001,
+                data: hex::encode(&ping),
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let message = write_queue.pop().await;
+        assert_eq!(message.data, ping);
+        assert_eq!(message.peer_to_port, 60001);
+
+        let error = inject(
+            State(state.clone()),
+            Json(Injection {
+                from_port: 60001,
+                to_port: 60000,
+                data: hex::encode(&ping),
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.0, StatusCode::NOT_FOUND);
+        let error = inject(
+            State(state),
+            Json(Injection {
+                from_port: 60000,
+                to_port: 60001,
+                data: "0000".to_string(),
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.0, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn replay_captured_messages() {
+        let state = state();
+        let error = replay(State(state.clone()), Json(ReplayRequest::default()))
+            .await
+            .unwrap_err();
+        assert_eq!(error.0, StatusCode::NOT_FOUND);
+
+        let write_queue = Arc::new(BoundedQueue::new(
+            OverflowPolicy::Block,
+            Arc::new(QueueGauge::new("write:60000".to_string(), 10)),
+        ));
+        state.register_write_queue(60000, write_queue.clone());
+        state.enable_capture(Arc::new(CaptureBuffer::new(10, vec![MessageType::Ping])));
+        let ping = Ping::default().to_message();
+        for to_port in [60001, 60002] {
+            state.capture_buffer().unwrap().capture(CapturedMessage {
+                from_port: 60000,
+                to_port,
+                message_type: MessageType::Ping,
+                ledger_sequence: None,
+                data: ping.clone(),
+            });
+        }
+
+        let Json(outcome) = replay(State(state), Json(ReplayRequest::default()))
+            .await
+            .unwrap();
+        assert_eq!(
+            outcome,
+            ReplayOutcome {
+                replayed: 1,
+                skipped: 1
+            }
+        );
+        let message = write_queue.pop().await;
+        assert_eq!(message.data, ping);
+        assert_eq!(message.peer_to_port, 60001);
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn replace_mutation_rules() {
+        let state = state();
+        let rule = |path: &str| MutationRule {
+            message_type: MessageType::ProposeLedger,
+            from_port: Some(60000),
+            to_port: None,
+            mutations: vec![FieldMutation {
+                path: path.to_string(),
+                operation: FieldOperation::Add { delta: 5 },
+            }],
+            resign: true,
+        };
+        let Json(rules) = set_mutation_rules(State(state.clone()), Json(vec![rule("closeTime")]))
+            .await
+            .unwrap();
+        assert_eq!(rules, vec![rule("closeTime")]);
+        let Json(rules) = mutation_rules(State(state.clone())).await;
+        assert_eq!(rules, vec![rule("closeTime")]);
+
+        let error = set_mutation_rules(State(state.clone()), Json(vec![rule("closingTime")]))
+            .await
+            .unwrap_err();
+        assert_eq!(error.0, StatusCode::BAD_REQUEST);
+        assert_eq!(state.mutation_rules(), vec![rule("closeTime")]);
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn replace_shaping_rules() {
+        let state = state();
+        let rule = |rate| ShapingRule {
+            message_type: MessageType::Transaction,
+            from_port: Some(60000),
+            to_port: Some(60001),
+            rate,
+            burst: Some(10),
+            action: ShapingAction::Delay,
+        };
+        let Json(rules) = set_shaping_rules(State(state.clone()), Json(vec![rule(50.0)]))
+            .await
+            .unwrap();
+        assert_eq!(rules, vec![rule(50.0)]);
+        let Json(rules) = shaping_rules(State(state.clone())).await;
+        assert_eq!(rules, vec![rule(50.0)]);
+
+        let error = set_shaping_rules(State(state.clone()), Json(vec![rule(0.0)]))
+            .await
+            .unwrap_err();
+        assert_eq!(error.0, StatusCode::BAD_REQUEST);
+        assert_eq!(state.shaping_rules(), vec![rule(50.0)]);
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn report_and_deduplicate_broadcasts() {
+        let state = state();
+        let error = broadcasts(State(state.clone())).await.unwrap_err();
+        assert_eq!(error.0, StatusCode::NOT_FOUND);
+        let enable = Json(Dedupe { enabled: true });
+        let error = set_dedupe(State(state.clone()), enable).await.unwrap_err();
+        assert_eq!(error.0, StatusCode::NOT_FOUND);
+
+        state.enable_broadcast_tracking(BroadcastTracker::new(&BroadcastConfig::default()));
+        let Json(report) = broadcasts(State(state.clone())).await.unwrap();
+        assert!(!report.dedupe);
+        assert!(report.message_types.is_empty());
+        let Json(dedupe) = set_dedupe(State(state.clone()), Json(Dedupe { enabled: true }))
+            .await
+            .unwrap();
+        assert!(dedupe.enabled);
+        assert!(broadcasts(State(state)).await.unwrap().dedupe);
+    }
+}
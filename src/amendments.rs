@@ -0,0 +1,242 @@
+//! This module is responsible for the amendments the nodes vote on: the sections of the node configurations that set
+//! their votes, and the monitoring of the voting, whose changes are published as events.
+//!
+//! The status of the amendments is polled with the `feature` method of every node. It contains whether an amendment is
+//! enabled or vetoed by the node, how many trusted validators voted for it in the last flag ledger, and since when it
+//! has a majority.
+
+use crate::event_bus::{EventBus, EventKind};
+use crate::node_rpc::NodeRpcClient;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha512};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::debug;
+
+/// Struct that represents the status of an amendment at a node, as far as it is monitored.
+#[derive(Debug, Clone, PartialEq)]
+struct AmendmentStatus {
+    /// Whether the amendment is enabled in the ledger of the node.
+    enabled: bool,
+    /// Whether the node votes against the amendment.
+    vetoed: bool,
+    /// The amount of trusted validators that voted for the amendment, if it is not enabled.
+    count: Option<u64>,
+    /// The amount of votes the amendment needs for a majority, if it is not enabled.
+    threshold: Option<u64>,
+    /// Since when the amendment has a majority, in seconds since the Ripple epoch, if it has one.
+    majority: Option<u64>,
+}
+
+/// Struct that represents the last observed status of the amendments at every node.
+#[derive(Debug, Default)]
+pub struct AmendmentTracker {
+    /// The status of every amendment by the ID of the node and the ID of the amendment.
+    statuses: HashMap<(u32, String), AmendmentStatus>,
+}
+
+impl AmendmentTracker {
+    /// Observes the amendments of a node and returns an event for every amendment whose status changed. Amendments
+    /// that are seen for the first time are only reported if they are not enabled yet.
+    ///
+    /// # Parameters
+    /// * 'node_id' - the ID of the node.
+    /// * 'features' - the `features` object in the result of the `feature` method of the node.
+    pub fn observe(&mut self, node_id: u32, features: &Value) -> Vec<EventKind> {
+        let Some(features) = features.as_object() else {
+            return Vec::new();
+        };
+        let mut events = Vec::new();
+        for (amendment, feature) in features {
+            let status = AmendmentStatus {
+                enabled: feature["enabled"].as_bool().unwrap_or(false),
+                // Obsolete amendments are vetoed with the string 'Obsolete'
+                vetoed: match &feature["vetoed"] {
+                    Value::Bool(vetoed) => *vetoed,
+                    Value::String(_) => true,
+                    _ => false,
+                },
+                count: feature["count"].as_u64(),
+                threshold: feature["threshold"].as_u64(),
+                majority: feature["majority"].as_u64(),
+            };
+            let previous = self
+                .statuses
+                .insert((node_id, amendment.clone()), status.clone());
+            let changed = match previous {
+                Some(previous) => previous != status,
+                None => !status.enabled,
+            };
+            if changed {
+                events.push(EventKind::AmendmentVoting {
+                    node_id,
+                    amendment: amendment.clone(),
+                    name: feature["name"].as_str().unwrap_or(amendment).to_string(),
+                    enabled: status.enabled,
+                    vetoed: status.vetoed,
+                    count: status.count,
+                    threshold: status.threshold,
+                    majority: status.majority,
+                });
+            }
+        }
+        events
+    }
+}
+
+/// Struct that represents the monitor which periodically polls the amendments of the nodes and publishes the changes.
+#[derive(Debug)]
+pub struct AmendmentMonitor {
+    /// The RPC clients of the nodes, together with their IDs.
+    nodes: Vec<(u32, NodeRpcClient)>,
+    /// How often the amendments of every node are polled.
+    interval: Duration,
+    /// The bus on which the changes are published.
+    events: Arc<EventBus>,
+}
+
+impl AmendmentMonitor {
+    /// Initializes a new AmendmentMonitor.
+    ///
+    /// # Parameters
+    /// * 'nodes' - the RPC clients of the nodes, together with their IDs.
+    /// * 'interval' - how often the amendments of every node are polled.
+    /// * 'events' - the bus on which the changes are published.
+    pub fn new(
+        nodes: Vec<(u32, NodeRpcClient)>,
+        interval: Duration,
+        events: Arc<EventBus>,
+    ) -> Self {
+        Self {
+            nodes,
+            interval,
+            events,
+        }
+    }
+
+    /// Polls the amendments of every node at the configured interval and publishes every change as an event.
+    pub async fn run(self) {
+        let mut tracker = AmendmentTracker::default();
+        let mut interval = tokio::time::interval(self.interval);
+        loop {
+            interval.tick().await;
+            for (node_id, client) in self.nodes.iter() {
+                let result = match client.request("feature", json!({})).await {
+                    Ok(result) => result,
+                    Err(e) => {
+                        debug!("Could not fetch amendments of node {}: {}", node_id, e);
+                        continue;
+                    }
+                };
+                for event in tracker.observe(*node_id, &result["features"]) {
+                    self.events.emit(event);
+                }
+            }
+        }
+    }
+}
+
+/// Returns the ID of an amendment: the first half of the SHA-512 hash of its name in hex. IDs are returned as they are.
+///
+/// # Parameters
+/// * 'amendment' - the name or ID of the amendment.
+pub fn amendment_id(amendment: &str) -> String {
+    if amendment.len() == 64 && amendment.chars().all(|c| c.is_ascii_hexdigit()) {
+        return amendment.to_uppercase();
+    }
+    hex::encode_upper(&Sha512::digest(amendment.as_bytes())[..32])
+}
+
+/// Returns the sections of a node configuration that make the node vote for and against amendments. Every line holds
+/// the ID and the name of an amendment, amendments configured by their ID are named by their ID.
+///
+/// # Parameters
+/// * 'enabled' - the names or IDs of the amendments the node votes for.
+/// * 'vetoed' - the names or IDs of the amendments the node votes against.
+pub fn config_sections(enabled: &[String], vetoed: &[String]) -> String {
+    let section = |name: &str, amendments: &[String]| {
+        if amendments.is_empty() {
+            return String::new();
+        }
+        let lines: Vec<String> = amendments
+            .iter()
+            .map(|amendment| format!("{} {}", amendment_id(amendment), amendment))
+            .collect();
+        format!("\n[{}]\n{}\n", name, lines.join("\n"))
+    };
+    section("amendments", enabled) + &section("veto_amendments", vetoed)
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::amendments::{amendment_id, config_sections, AmendmentTracker};
+    use crate::event_bus::EventKind;
+    use serde_json::json;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn amendment_ids() {
+        // The ID of the MultiSign amendment, see https://xrpl.org/resources/known-amendments
+        assert_eq!(
+            amendment_id("MultiSign"),
+            "4C97EBA926031A7CF7D7B36FDE3ED66DDA5421192D63DE53FFB46E43B9DC8373"
+        );
+        let id = "4c97eba926031a7cf7d7b36fde3ed66dda5421192d63de53ffb46e43b9dc8373";
+        assert_eq!(amendment_id(id), id.to_uppercase());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn amendment_sections() {
+        assert_eq!(config_sections(&[], &[]), "");
+        assert_eq!(
+            config_sections(&[], &["MultiSign".to_string()]),
+            "\n[veto_amendments]\n\
+            4C97EBA926031A7CF7D7B36FDE3ED66DDA5421192D63DE53FFB46E43B9DC8373 MultiSign\n"
+        );
+        assert!(config_sections(&["MultiSign".to_string()], &[]).starts_with("\n[amendments]\n"));
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn voting_changes_are_reported() {
+        let mut tracker = AmendmentTracker::default();
+        let features = json!({
+            "AAAA": { "name": "Enabled", "enabled": true, "vetoed": false },
+            "BBBB": { "name": "Pending", "enabled": false, "vetoed": false, "count": 1, "threshold": 4 },
+        });
+        let events = tracker.observe(0, &features);
+        assert_eq!(
+            events,
+            vec![EventKind::AmendmentVoting {
+                node_id: 0,
+                amendment: "BBBB".to_string(),
+                name: "Pending".to_string(),
+                enabled: false,
+                vetoed: false,
+                count: Some(1),
+                threshold: Some(4),
+                majority: None,
+            }]
+        );
+        assert!(tracker.observe(0, &features).is_empty());
+
+        let features = json!({
+            "AAAA": { "name": "Enabled", "enabled": true, "vetoed": false },
+            "BBBB": { "name": "Pending", "enabled": false, "vetoed": "Obsolete", "count": 4, "threshold": 4, "majority": 770391649 },
+        });
+        let events = tracker.observe(0, &features);
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            events[0],
+            EventKind::AmendmentVoting {
+                vetoed: true,
+                count: Some(4),
+                majority: Some(770391649),
+                ..
+            }
+        ));
+        assert_eq!(tracker.observe(1, &features).len(), 1);
+    }
+}
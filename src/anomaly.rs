@@ -0,0 +1,573 @@
+//! This module is responsible for detecting anomalies in the traffic while the interceptor runs, and alerting on them
+//! through the event bus and optionally the controller. The detectors are simple and online, they only look at the
+//! recent traffic:
+//!
+//! - silence: a node that sent messages before has not sent any for a while;
+//! - validation burst: a node sends more distinct validations within a short window than a validator should;
+//! - repeated message: the same message is sent on a link over and over, e.g. by a duplicating controller;
+//! - mutation rejected: a connection closes shortly after a mutated message was sent on it, which is how a peer treats a
+//!   message of which the signature or checksum no longer matches.
+//!
+//! Every anomaly is reported once, until the condition is over: a silent node is reported again only after it sent
+//! messages again, and a burst or repeated message only after its window has passed.
+
+use crate::config::AnomalyConfig;
+use crate::event_bus::{Event, EventBus, EventKind};
+use crate::message_type::MessageType;
+use crate::packet_client::proto::Alert;
+use crate::packet_client::PacketClient;
+use crate::packet_timeline::PacketRecord;
+use crate::record_sink::RecordSink;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc, Mutex as AsyncMutex};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::Code;
+use tracing::{debug, info, warn};
+
+/// The time between two checks for silent nodes.
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+/// The amount of alerts buffered for the controller, before alerts are not sent to it.
+const ALERT_CAPACITY: usize = 256;
+/// The amount of handled messages buffered for the detectors, before messages are not looked at.
+pub const RECORD_CAPACITY: usize = 65_536;
+
+/// Struct that represents what the detectors remember of the recent traffic.
+#[derive(Debug, Default)]
+struct Tracked {
+    /// The moment every node last sent a message.
+    last_seen: HashMap<u16, DateTime<Utc>>,
+    /// The nodes that were reported silent and did not send messages since.
+    silent: HashSet<u16>,
+    /// The moments and hashes of the distinct validations of every node within the burst window.
+    validations: HashMap<u16, VecDeque<(DateTime<Utc>, String)>>,
+    /// The moment a burst of every node was last reported.
+    bursts: HashMap<u16, DateTime<Utc>>,
+    /// The moment of the first send within the repeat window and the amount of sends of every message per link.
+    repeats: HashMap<(u16, u16, String), (DateTime<Utc>, u32)>,
+    /// The moment a mutated message was last sent on every link.
+    mutations: HashMap<(u16, u16), DateTime<Utc>>,
+}
+
+/// Struct that represents the detectors of anomalies in the traffic.
+#[derive(Debug)]
+pub struct AnomalyDetector {
+    config: AnomalyConfig,
+    /// The bus the anomalies are published on.
+    events: Arc<EventBus>,
+    tracked: Mutex<Tracked>,
+}
+
+impl AnomalyDetector {
+    /// Initializes a new AnomalyDetector that has not seen any traffic.
+    ///
+    /// # Parameters
+    /// * 'config' - the configuration of the detectors.
+    /// * 'events' - the bus the anomalies are published on.
+    pub fn new(config: &AnomalyConfig, events: Arc<EventBus>) -> Self {
+        Self {
+            config: config.clone(),
+            events,
+            tracked: Mutex::new(Tracked::default()),
+        }
+    }
+
+    /// Publishes an anomaly.
+    ///
+    /// # Parameters
+    /// * 'anomaly' - the kind of anomaly.
+    /// * 'from_port' - the node, or the node the messages of the link come from.
+    /// * 'to_port' - the node the messages of the link go to, None if the anomaly is about a node.
+    /// * 'detail' - a description of what was detected.
+    fn alert(&self, anomaly: &str, from_port: u16, to_port: Option<u16>, detail: String) {
+        info!("Anomaly {} at {}: {}", anomaly, from_port, detail);
+        self.events.emit(EventKind::Anomaly {
+            anomaly: anomaly.to_string(),
+            from_port,
+            to_port,
+            detail,
+        });
+    }
+
+    /// Looks for bursts, repeated messages and nodes that are no longer silent in a handled message.
+    ///
+    /// # Parameters
+    /// * 'record' - the record of the message.
+    pub fn observe_record(&self, record: &PacketRecord) {
+        let mut tracked = self.tracked.lock().unwrap();
+        let now = record.timestamp;
+        let from_port = record.from_port;
+        tracked.last_seen.insert(from_port, now);
+        if tracked.silent.remove(&from_port) {
+            debug!("Node {} is no longer silent", from_port);
+        }
+        if record.send_amount > 0 && record.sent_size != record.size {
+            tracked.mutations.insert((from_port, record.to_port), now);
+        }
+
+        if self.config.validation_burst > 0 && record.message_type == MessageType::Validation {
+            let window = ChronoDuration::milliseconds(self.config.burst_window_ms as i64);
+            let validations = tracked.validations.entry(from_port).or_default();
+            while validations
+                .front()
+                .is_some_and(|(moment, _)| now - *moment > window)
+            {
+                validations.pop_front();
+            }
+            // A validation is broadcast on every link of the node, but counts once
+            if !validations.iter().any(|(_, hash)| *hash == record.hash) {
+                validations.push_back((now, record.hash.clone()));
+            }
+            let amount = validations.len();
+            let reported = tracked
+                .bursts
+                .get(&from_port)
+                .is_some_and(|moment| now - *moment <= window);
+            if amount > self.config.validation_burst && !reported {
+                tracked.bursts.insert(from_port, now);
+                self.alert(
+                    "validation_burst",
+                    from_port,
+                    None,
+                    format!(
+                        "{} distinct validations within {} ms",
+                        amount, self.config.burst_window_ms
+                    ),
+                );
+            }
+        }
+
+        if self.config.repeat_threshold > 0 && record.send_amount > 0 {
+            let window = ChronoDuration::milliseconds(self.config.repeat_window_ms as i64);
+            let repeat = tracked
+                .repeats
+                .entry((from_port, record.to_port, record.hash.clone()))
+                .or_insert((now, 0));
+            if now - repeat.0 > window {
+                *repeat = (now, 0);
+            }
+            repeat.1 += record.send_amount;
+            let sends = repeat.1;
+            // Reported once per window, when the threshold is crossed
+            if sends > self.config.repeat_threshold
+                && sends - record.send_amount <= self.config.repeat_threshold
+            {
+                self.alert(
+                    "repeated_message",
+                    from_port,
+                    Some(record.to_port),
+                    format!(
+                        "{} sent {} times within {} ms",
+                        record.message_type, sends, self.config.repeat_window_ms
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Looks for a connection that closed shortly after a mutated message was sent on it.
+    ///
+    /// # Parameters
+    /// * 'event' - the event.
+    pub fn observe_event(&self, event: &Event) {
+        let now = DateTime::from_timestamp_nanos(event.timestamp_ns as i64);
+        let mut tracked = self.tracked.lock().unwrap();
+        match &event.kind {
+            EventKind::MutationApplied {
+                from_port, to_port, ..
+            } => {
+                tracked.mutations.insert((*from_port, *to_port), now);
+            }
+            EventKind::LinkDropped {
+                from_port,
+                to_port,
+                reason,
+            } if self.config.rejection_window_ms > 0 => {
+                let window = ChronoDuration::milliseconds(self.config.rejection_window_ms as i64);
+                // Either side of the connection could have been sent the mutated message
+                for link in [(*from_port, *to_port), (*to_port, *from_port)] {
+                    let Some(mutated_at) = tracked.mutations.get(&link).copied() else {
+                        continue;
+                    };
+                    if now - mutated_at <= window {
+                        tracked.mutations.remove(&link);
+                        self.alert(
+                            "mutation_rejected",
+                            link.0,
+                            Some(link.1),
+                            format!(
+                                "the connection closed {} ms after a mutated message: {}",
+                                (now - mutated_at).num_milliseconds(),
+                                reason
+                            ),
+                        );
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Reports the nodes that have been silent for too long, and forgets the traffic that is too old to matter.
+    ///
+    /// # Parameters
+    /// * 'now' - the current moment.
+    pub fn check(&self, now: DateTime<Utc>) {
+        let mut tracked = self.tracked.lock().unwrap();
+        if self.config.silence_ms > 0 {
+            let silence = ChronoDuration::milliseconds(self.config.silence_ms as i64);
+            let mut silent: Vec<(u16, DateTime<Utc>)> = tracked
+                .last_seen
+                .iter()
+                .filter(|(port, last_seen)| {
+                    now - **last_seen > silence && !tracked.silent.contains(port)
+                })
+                .map(|(port, last_seen)| (*port, *last_seen))
+                .collect();
+            silent.sort_unstable();
+            for (port, last_seen) in silent {
+                tracked.silent.insert(port);
+                self.alert(
+                    "silence",
+                    port,
+                    None,
+                    format!(
+                        "no messages for {} ms",
+                        (now - last_seen).num_milliseconds()
+                    ),
+                );
+            }
+        }
+        let repeat_window = ChronoDuration::milliseconds(self.config.repeat_window_ms as i64);
+        tracked
+            .repeats
+            .retain(|_, (first_sent, _)| now - *first_sent <= repeat_window);
+        let rejection_window = ChronoDuration::milliseconds(self.config.rejection_window_ms as i64);
+        tracked
+            .mutations
+            .retain(|_, mutated_at| now - *mutated_at <= rejection_window);
+    }
+
+    /// Watches the events for rejected mutations and checks for silent nodes at a fixed interval, until the bus is
+    /// closed. The anomalies published by the detector itself are skipped.
+    ///
+    /// # Parameters
+    /// * 'events' - the receiver of the events, subscribed before the links started.
+    pub async fn watch(self: Arc<Self>, mut events: broadcast::Receiver<Arc<Event>>) {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            tokio::select! {
+                received = events.recv() => match received {
+                    Ok(event) => self.observe_event(&event),
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!("The anomaly detectors missed {} events", missed);
+                    }
+                    Err(RecvError::Closed) => return,
+                },
+                _ = interval.tick() => self.check(Utc::now()),
+            }
+        }
+    }
+}
+
+/// Struct that represents the sink passing the handled messages to the anomaly detectors.
+pub struct AnomalySink(pub Arc<AnomalyDetector>);
+
+impl RecordSink for AnomalySink {
+    fn name(&self) -> String {
+        "anomaly detectors".to_string()
+    }
+
+    fn write(&mut self, records: &[PacketRecord]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for record in records {
+            self.0.observe_record(record);
+        }
+        Ok(())
+    }
+}
+
+/// Converts an anomaly event to the alert sent to the controller, None for other events.
+///
+/// # Parameters
+/// * 'event' - the event.
+pub fn to_alert(event: &Event) -> Option<Alert> {
+    let EventKind::Anomaly {
+        anomaly,
+        from_port,
+        to_port,
+        detail,
+    } = &event.kind
+    else {
+        return None;
+    };
+    Some(Alert {
+        timestamp_ns: event.timestamp_ns,
+        anomaly: anomaly.clone(),
+        from_port: u32::from(*from_port),
+        to_port: to_port.map_or(0, u32::from),
+        detail: detail.clone(),
+    })
+}
+
+/// Streams the anomalies to the controller, until the controller ends the stream or the bus is closed.
+/// Controllers that do not implement the alerts are skipped.
+///
+/// # Parameters
+/// * 'client' - the PacketClient whose connection to the controller is used.
+/// * 'events' - the receiver of the events, subscribed before the links started.
+pub async fn report(
+    client: Arc<AsyncMutex<PacketClient>>,
+    mut events: broadcast::Receiver<Arc<Event>>,
+) {
+    let (sender, receiver) = mpsc::channel(ALERT_CAPACITY);
+    let (mut service, request) = {
+        let client = client.lock().await;
+        (
+            client.client.clone(),
+            client.request(ReceiverStream::new(receiver)),
+        )
+    };
+    let forward = tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    if let Some(alert) = to_alert(&event) {
+                        if sender.try_send(alert).is_err() {
+                            warn!("The controller is not keeping up with the alerts, an alert was skipped");
+                        }
+                    }
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("The alerts to the controller missed {} events", missed);
+                }
+                Err(RecvError::Closed) => return,
+            }
+        }
+    });
+    match service.send_alerts(request).await {
+        Ok(_) => info!("The stream of alerts to the controller ended"),
+        Err(status) if status.code() == Code::Unimplemented => {
+            debug!("The controller does not receive alerts");
+        }
+        Err(status) => warn!("The alerts to the controller stopped: {}", status),
+    }
+    forward.abort();
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::anomaly::{to_alert, AnomalyDetector};
+    use crate::config::AnomalyConfig;
+    use crate::event_bus::{Event, EventBus, EventKind};
+    use crate::message_type::MessageType;
+    use crate::packet_timeline::PacketRecord;
+    use chrono::{DateTime, Duration as ChronoDuration, Utc};
+    use std::sync::Arc;
+    use tokio::sync::broadcast;
+
+    fn record(
+        timestamp: DateTime<Utc>,
+        from_port: u16,
+        to_port: u16,
+        message_type: MessageType,
+        hash: &str,
+    ) -> PacketRecord {
+        PacketRecord {
+            timestamp,
+            from_port,
+            to_port,
+            message_type,
+            hash: hash.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn detector() -> (AnomalyDetector, broadcast::Receiver<Arc<Event>>) {
+        let events = Arc::new(EventBus::default());
+        let receiver = events.subscribe();
+        let config = AnomalyConfig {
+            silence_ms: 3000,
+            validation_burst: 3,
+            burst_window_ms: 1000,
+            repeat_threshold: 2,
+            repeat_window_ms: 5000,
+            rejection_window_ms: 2000,
+            report_to_controller: false,
+        };
+        (AnomalyDetector::new(&config, events), receiver)
+    }
+
+    /// Returns the kind and ports of the anomalies published so far.
+    fn anomalies(
+        receiver: &mut broadcast::Receiver<Arc<Event>>,
+    ) -> Vec<(String, u16, Option<u16>)> {
+        let mut anomalies = Vec::new();
+        while let Ok(event) = receiver.try_recv() {
+            if let EventKind::Anomaly {
+                anomaly,
+                from_port,
+                to_port,
+                ..
+            } = &event.kind
+            {
+                anomalies.push((anomaly.clone(), *from_port, *to_port));
+            }
+        }
+        anomalies
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn silent_nodes_are_reported_once() {
+        let (detector, mut receiver) = detector();
+        let start = Utc::now();
+        let ms = |ms: i64| start + ChronoDuration::milliseconds(ms);
+        detector.observe_record(&record(ms(0), 60000, 60001, MessageType::Ping, "a"));
+        detector.observe_record(&record(ms(2000), 60001, 60000, MessageType::Ping, "b"));
+        detector.check(ms(2500));
+        assert_eq!(anomalies(&mut receiver), []);
+
+        detector.check(ms(3500));
+        detector.check(ms(4000));
+        assert_eq!(
+            anomalies(&mut receiver),
+            [("silence".to_string(), 60000, None)]
+        );
+
+        // Once the node sent messages again, it can become silent again
+        detector.observe_record(&record(ms(4500), 60000, 60001, MessageType::Ping, "c"));
+        detector.check(ms(8000));
+        assert_eq!(
+            anomalies(&mut receiver),
+            [
+                ("silence".to_string(), 60000, None),
+                ("silence".to_string(), 60001, None)
+            ]
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn validation_bursts_count_distinct_validations() {
+        let (detector, mut receiver) = detector();
+        let start = Utc::now();
+        let ms = |ms: i64| start + ChronoDuration::milliseconds(ms);
+        // Broadcast on two links, and spread over more than the window
+        for (i, hash) in ["a", "b", "c", "d"].iter().enumerate() {
+            for to_port in [60001, 60002] {
+                detector.observe_record(&record(
+                    ms(i as i64 * 400),
+                    60000,
+                    to_port,
+                    MessageType::Validation,
+                    hash,
+                ));
+            }
+        }
+        assert_eq!(anomalies(&mut receiver), []);
+
+        for hash in ["e", "f", "g", "h"] {
+            detector.observe_record(&record(
+                ms(1300),
+                60000,
+                60001,
+                MessageType::Validation,
+                hash,
+            ));
+        }
+        assert_eq!(
+            anomalies(&mut receiver),
+            [("validation_burst".to_string(), 60000, None)]
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn repeated_messages_and_rejected_mutations() {
+        let (detector, mut receiver) = detector();
+        let start = Utc::now();
+        let ms = |ms: i64| start + ChronoDuration::milliseconds(ms);
+        for i in 0..5 {
+            detector.observe_record(&record(
+                ms(i * 100),
+                60000,
+                60001,
+                MessageType::Transaction,
+                "a",
+            ));
+        }
+        for i in 0..2 {
+            detector.observe_record(&record(
+                ms(i * 100),
+                60000,
+                60002,
+                MessageType::Transaction,
+                "b",
+            ));
+        }
+        assert_eq!(
+            anomalies(&mut receiver),
+            [("repeated_message".to_string(), 60000, Some(60001))]
+        );
+        // Sent again after the window, the message is not repeated
+        detector.observe_record(&record(
+            ms(6000),
+            60000,
+            60002,
+            MessageType::Transaction,
+            "b",
+        ));
+        assert_eq!(anomalies(&mut receiver), []);
+
+        let mut mutated = record(ms(7000), 60001, 60002, MessageType::ProposeLedger, "c");
+        mutated.sent_size = 48;
+        detector.observe_record(&mutated);
+        let dropped = |ms: i64| Event {
+            timestamp_ns: (start + ChronoDuration::milliseconds(ms))
+                .timestamp_nanos_opt()
+                .unwrap() as u64,
+            kind: EventKind::LinkDropped {
+                from_port: 60002,
+                to_port: 60001,
+                reason: "Connection reset by peer".to_string(),
+            },
+        };
+        detector.observe_event(&dropped(10_000));
+        assert_eq!(anomalies(&mut receiver), []);
+        detector.observe_record(&mutated);
+        detector.observe_event(&dropped(8000));
+        assert_eq!(
+            anomalies(&mut receiver),
+            [("mutation_rejected".to_string(), 60001, Some(60002))]
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn anomalies_are_alerts_for_the_controller() {
+        let event = Event {
+            timestamp_ns: 7,
+            kind: EventKind::Anomaly {
+                anomaly: "silence".to_string(),
+                from_port: 60003,
+                to_port: None,
+                detail: "no messages for 12000 ms".to_string(),
+            },
+        };
+        let alert = to_alert(&event).unwrap();
+        assert_eq!(alert.from_port, 60003);
+        assert_eq!(alert.to_port, 0);
+        assert_eq!(alert.anomaly, "silence");
+        assert_eq!(
+            to_alert(&Event {
+                timestamp_ns: 7,
+                kind: EventKind::PartitionChanged { partitions: vec![] },
+            }),
+            None
+        );
+    }
+}
@@ -0,0 +1,386 @@
+//! This module is responsible for checking liveness and safety properties of the network during a run.
+//! Violations are reported together with the messages that were intercepted right before them.
+
+use crate::config::AssertionConfig;
+use crate::node_rpc::NodeRpcClient;
+use crate::packet_timeline::{PacketRecord, PacketTimeline};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, error};
+
+/// The amount of validated ledgers that are remembered per node.
+const HISTORY_LENGTH: usize = 256;
+
+/// Enum that represents the properties that are checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Property {
+    /// All honest nodes validate the same ledger hash for the same ledger index.
+    Agreement,
+    /// The validated ledger index of every honest node advances at least every configured interval.
+    Progress,
+    /// No node validates two different ledgers at the same ledger index.
+    NoEquivocation,
+}
+
+impl Property {
+    /// All properties that are checked.
+    pub const ALL: [Property; 3] = [
+        Property::Agreement,
+        Property::Progress,
+        Property::NoEquivocation,
+    ];
+}
+
+impl fmt::Display for Property {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Property::Agreement => "agreement",
+            Property::Progress => "progress",
+            Property::NoEquivocation => "no-equivocation",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Struct that represents a violation of one of the properties.
+#[derive(Debug, Clone)]
+pub struct Violation {
+    /// The property that was violated.
+    pub property: Property,
+    /// The ID of the node that caused the violation to be detected.
+    pub node_id: u32,
+    /// A human-readable description of the violation.
+    pub description: String,
+    /// The moment the violation was detected.
+    pub timestamp: DateTime<Utc>,
+    /// The messages that were intercepted right before the violation was detected.
+    pub timeline: Vec<PacketRecord>,
+}
+
+/// Struct that represents the state needed to check the properties, independent of how the
+/// validated ledgers are observed.
+#[derive(Debug)]
+pub struct PropertyChecker {
+    /// The maximum time between two advances of the validated ledger index of a node.
+    max_ledger_interval: Duration,
+    /// The nodes that are excluded from the agreement and progress properties.
+    byzantine_nodes: HashSet<u32>,
+    /// For every node, the validated ledger hashes by ledger index.
+    validated_by_node: HashMap<u32, BTreeMap<u32, String>>,
+    /// For every ledger index, the first honest node that validated it and the hash it validated.
+    validated_by_index: BTreeMap<u32, (u32, String)>,
+    /// For every node, the highest validated ledger index and the moment it was first observed.
+    last_advance: HashMap<u32, (u32, Instant)>,
+    /// The nodes for which a progress violation was reported, until they advance again.
+    stalled: HashSet<u32>,
+}
+
+impl PropertyChecker {
+    /// Initializes a new PropertyChecker.
+    ///
+    /// # Parameters
+    /// * 'max_ledger_interval' - the maximum time between two advances of the validated ledger index.
+    /// * 'byzantine_nodes' - the IDs of the nodes that are excluded from the agreement and progress properties.
+    pub fn new(max_ledger_interval: Duration, byzantine_nodes: &[u32]) -> Self {
+        Self {
+            max_ledger_interval,
+            byzantine_nodes: byzantine_nodes.iter().copied().collect(),
+            validated_by_node: HashMap::new(),
+            validated_by_index: BTreeMap::new(),
+            last_advance: HashMap::new(),
+            stalled: HashSet::new(),
+        }
+    }
+
+    /// Registers a node, starting the progress timer for it.
+    ///
+    /// # Parameters
+    /// * 'node_id' - the ID of the node.
+    /// * 'now' - the current moment.
+    pub fn register_node(&mut self, node_id: u32, now: Instant) {
+        self.last_advance.entry(node_id).or_insert((0, now));
+    }
+
+    /// Processes the latest validated ledger of a node and returns the violations it causes.
+    ///
+    /// # Parameters
+    /// * 'node_id' - the ID of the node.
+    /// * 'ledger_index' - the index of the latest validated ledger of the node.
+    /// * 'ledger_hash' - the hash of the latest validated ledger of the node.
+    /// * 'now' - the moment the ledger was observed.
+    pub fn observe(
+        &mut self,
+        node_id: u32,
+        ledger_index: u32,
+        ledger_hash: &str,
+        now: Instant,
+    ) -> Vec<(Property, String)> {
+        let mut violations = Vec::new();
+
+        let history = self.validated_by_node.entry(node_id).or_default();
+        match history.get(&ledger_index) {
+            Some(previous_hash) if previous_hash != ledger_hash => violations.push((
+                Property::NoEquivocation,
+                format!(
+                    "node {} validated both {} and {} at ledger index {}",
+                    node_id, previous_hash, ledger_hash, ledger_index
+                ),
+            )),
+            Some(_) => (),
+            None => {
+                history.insert(ledger_index, ledger_hash.to_string());
+                while history.len() > HISTORY_LENGTH {
+                    history.pop_first();
+                }
+            }
+        }
+
+        if !self.byzantine_nodes.contains(&node_id) {
+            match self.validated_by_index.get(&ledger_index) {
+                Some((other_node_id, other_hash))
+                    if *other_node_id != node_id && other_hash != ledger_hash =>
+                {
+                    violations.push((
+                        Property::Agreement,
+                        format!(
+                            "node {} validated {} but node {} validated {} at ledger index {}",
+                            node_id, ledger_hash, other_node_id, other_hash, ledger_index
+                        ),
+                    ))
+                }
+                Some(_) => (),
+                None => {
+                    self.validated_by_index
+                        .insert(ledger_index, (node_id, ledger_hash.to_string()));
+                    while self.validated_by_index.len() > HISTORY_LENGTH {
+                        self.validated_by_index.pop_first();
+                    }
+                }
+            }
+        }
+
+        let last_advance = self.last_advance.entry(node_id).or_insert((0, now));
+        if ledger_index > last_advance.0 {
+            *last_advance = (ledger_index, now);
+            self.stalled.remove(&node_id);
+        }
+
+        violations
+    }
+
+    /// Checks whether every honest node advanced its validated ledger recently enough.
+    /// A stalled node is only reported once, until it advances again.
+    ///
+    /// # Parameters
+    /// * 'now' - the current moment.
+    pub fn check_progress(&mut self, now: Instant) -> Vec<(u32, Property, String)> {
+        let mut violations = Vec::new();
+        for (node_id, (ledger_index, advanced_at)) in self.last_advance.iter() {
+            if self.byzantine_nodes.contains(node_id) || self.stalled.contains(node_id) {
+                continue;
+            }
+            let stalled_for = now.saturating_duration_since(*advanced_at);
+            if stalled_for > self.max_ledger_interval {
+                violations.push((
+                    *node_id,
+                    Property::Progress,
+                    format!(
+                        "node {} did not advance past validated ledger {} for {:?}",
+                        node_id, ledger_index, stalled_for
+                    ),
+                ));
+            }
+        }
+        for (node_id, _, _) in violations.iter() {
+            self.stalled.insert(*node_id);
+        }
+        violations
+    }
+}
+
+/// Struct that represents the engine which periodically polls the nodes and checks the properties.
+#[derive(Debug)]
+pub struct AssertionEngine {
+    /// The configuration of the engine.
+    config: AssertionConfig,
+    /// The RPC clients of the nodes, together with their IDs.
+    nodes: Vec<(u32, NodeRpcClient)>,
+    /// The timeline of intercepted messages, included in violation reports.
+    timeline: Arc<PacketTimeline>,
+    /// All violations detected so far.
+    violations: Arc<Mutex<Vec<Violation>>>,
+}
+
+impl AssertionEngine {
+    /// Initializes a new AssertionEngine.
+    ///
+    /// # Parameters
+    /// * 'config' - the configuration of the engine.
+    /// * 'nodes' - the RPC clients of the nodes, together with their IDs.
+    /// * 'timeline' - the timeline of intercepted messages.
+    pub fn new(
+        config: AssertionConfig,
+        nodes: Vec<(u32, NodeRpcClient)>,
+        timeline: Arc<PacketTimeline>,
+    ) -> Self {
+        Self {
+            config,
+            nodes,
+            timeline,
+            violations: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Returns a handle to the violations detected by the engine, which stays valid after the engine was started.
+    pub fn violations(&self) -> Arc<Mutex<Vec<Violation>>> {
+        self.violations.clone()
+    }
+
+    /// Polls the latest validated ledger of every node at the configured interval and reports all violations.
+    pub async fn run(self) {
+        let mut checker = PropertyChecker::new(
+            Duration::from_secs(self.config.max_ledger_interval_secs),
+            &self.config.byzantine_nodes,
+        );
+        let start = Instant::now();
+        for (node_id, _) in self.nodes.iter() {
+            checker.register_node(*node_id, start);
+        }
+
+        let mut interval =
+            tokio::time::interval(Duration::from_millis(self.config.poll_interval_ms));
+        loop {
+            interval.tick().await;
+            for (node_id, client) in self.nodes.iter() {
+                let result = match client
+                    .request("ledger", json!({ "ledger_index": "validated" }))
+                    .await
+                {
+                    Ok(result) => result,
+                    Err(e) => {
+                        debug!(
+                            "Could not fetch validated ledger of node {}: {}",
+                            node_id, e
+                        );
+                        continue;
+                    }
+                };
+                let (Some(ledger_index), Some(ledger_hash)) = (
+                    result["ledger_index"].as_u64(),
+                    result["ledger_hash"].as_str(),
+                ) else {
+                    continue;
+                };
+                for (property, description) in
+                    checker.observe(*node_id, ledger_index as u32, ledger_hash, Instant::now())
+                {
+                    self.report(property, *node_id, description);
+                }
+            }
+            for (node_id, property, description) in checker.check_progress(Instant::now()) {
+                self.report(property, node_id, description);
+            }
+        }
+    }
+
+    /// Logs a violation together with the preceding messages and stores it.
+    ///
+    /// # Parameters
+    /// * 'property' - the property that was violated.
+    /// * 'node_id' - the ID of the node that caused the violation to be detected.
+    /// * 'description' - a human-readable description of the violation.
+    fn report(&self, property: Property, node_id: u32, description: String) {
+        let timeline = self.timeline.latest(self.config.timeline_packets);
+        let timeline_str = timeline
+            .iter()
+            .map(|record| format!("    {}", record))
+            .collect::<Vec<String>>()
+            .join("\n");
+        error!(
+            "Property '{}' violated: {}\nPreceding messages:\n{}",
+            property, description, timeline_str
+        );
+        self.violations.lock().unwrap().push(Violation {
+            property,
+            node_id,
+            description,
+            timestamp: Utc::now(),
+            timeline,
+        });
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::assertion_engine::{Property, PropertyChecker};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn agreement_holds() {
+        let mut checker = PropertyChecker::new(Duration::from_secs(30), &[]);
+        let now = Instant::now();
+        assert!(checker.observe(0, 5, "AAAA", now).is_empty());
+        assert!(checker.observe(1, 5, "AAAA", now).is_empty());
+        assert!(checker.observe(1, 6, "BBBB", now).is_empty());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn agreement_violated() {
+        let mut checker = PropertyChecker::new(Duration::from_secs(30), &[]);
+        let now = Instant::now();
+        assert!(checker.observe(0, 5, "AAAA", now).is_empty());
+        let violations = checker.observe(1, 5, "BBBB", now);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].0, Property::Agreement);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn agreement_ignores_byzantine_nodes() {
+        let mut checker = PropertyChecker::new(Duration::from_secs(30), &[1]);
+        let now = Instant::now();
+        assert!(checker.observe(0, 5, "AAAA", now).is_empty());
+        assert!(checker.observe(1, 5, "BBBB", now).is_empty());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn equivocation_violated() {
+        let mut checker = PropertyChecker::new(Duration::from_secs(30), &[]);
+        let now = Instant::now();
+        assert!(checker.observe(0, 5, "AAAA", now).is_empty());
+        let violations = checker.observe(0, 5, "BBBB", now);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].0, Property::NoEquivocation);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn progress_violated_once() {
+        let mut checker = PropertyChecker::new(Duration::from_secs(30), &[]);
+        let start = Instant::now();
+        checker.register_node(0, start);
+        checker.observe(0, 5, "AAAA", start);
+        assert!(checker
+            .check_progress(start + Duration::from_secs(10))
+            .is_empty());
+
+        let violations = checker.check_progress(start + Duration::from_secs(31));
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].1, Property::Progress);
+        assert!(checker
+            .check_progress(start + Duration::from_secs(40))
+            .is_empty());
+
+        checker.observe(0, 6, "BBBB", start + Duration::from_secs(41));
+        assert!(checker
+            .check_progress(start + Duration::from_secs(50))
+            .is_empty());
+    }
+}
@@ -0,0 +1,355 @@
+//! This module is responsible for the benchmark mode, which measures the overhead the interceptor adds to the network.
+//!
+//! The benchmark runs a network of two nodes and measures the traffic between them twice: once with every message
+//! forwarded as-is (passthrough) and once with every message sent to the controller (intercepted).
+//! Afterwards it floods the controller with messages to measure the maximum amount of decisions it can make per second.
+//! For the added latency to be meaningful, the controller should not delay or drop any messages during the benchmark.
+
+use crate::config::BenchConfig;
+use crate::docker_manager::DockerNetwork;
+use crate::interceptor_state::InterceptorState;
+use crate::packet_client::proto::{Config, Partition};
+use crate::packet_client::{PacketClient, PacketMetadata};
+use crate::packet_timeline::PacketRecord;
+use bytes::Bytes;
+use chrono::Utc;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::info;
+
+/// The amount of nodes the benchmark network consists of.
+const BENCH_NUMBER_OF_NODES: u32 = 2;
+/// A complete mtPING message (header and TMPing payload) used to flood the controller.
+const PING_MESSAGE: [u8; 8] = [0, 0, 0, 2, 0, 3, 0x08, 0x00];
+
+/// Struct that represents the measurements of a single phase of the benchmark.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhaseStats {
+    /// The amount of messages that were handled.
+    pub messages: usize,
+    /// How long the phase was measured.
+    pub duration: Duration,
+    /// The latency of every handled message, sorted from low to high.
+    latencies: Vec<Duration>,
+}
+
+impl PhaseStats {
+    /// Computes the measurements of a phase from the messages that were handled during it.
+    ///
+    /// # Parameters
+    /// * 'records' - the records of all messages handled during the phase.
+    /// * 'duration' - how long the phase was measured.
+    pub fn from_records(records: &[PacketRecord], duration: Duration) -> Self {
+        let mut latencies: Vec<Duration> = records.iter().map(|record| record.latency).collect();
+        latencies.sort();
+        Self {
+            messages: records.len(),
+            duration,
+            latencies,
+        }
+    }
+
+    /// Returns the amount of messages handled per second.
+    pub fn throughput(&self) -> f64 {
+        if self.duration.is_zero() {
+            return 0.0;
+        }
+        self.messages as f64 / self.duration.as_secs_f64()
+    }
+
+    /// Returns the latency below which the given percentage of messages was handled.
+    ///
+    /// # Parameters
+    /// * 'percentile' - the percentile, between 0 and 100.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        percentile_of(&self.latencies, percentile)
+    }
+}
+
+/// Struct that represents the result of the benchmark.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchReport {
+    /// The measurements while messages were forwarded as-is.
+    pub passthrough: PhaseStats,
+    /// The measurements while messages were sent to the controller.
+    pub intercepted: PhaseStats,
+    /// The maximum amount of decisions the controller made per second.
+    pub controller_throughput: f64,
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Interception overhead benchmark")?;
+        writeln!(
+            f,
+            "{:<12} {:>10} {:>10} {:>12} {:>12} {:>12} {:>12}",
+            "phase", "messages", "msgs/s", "p50", "p95", "p99", "max"
+        )?;
+        for (name, stats) in [
+            ("passthrough", &self.passthrough),
+            ("intercepted", &self.intercepted),
+        ] {
+            writeln!(
+                f,
+                "{:<12} {:>10} {:>10.1} {:>12} {:>12} {:>12} {:>12}",
+                name,
+                stats.messages,
+                stats.throughput(),
+                format!("{:.2?}", stats.percentile(50.0)),
+                format!("{:.2?}", stats.percentile(95.0)),
+                format!("{:.2?}", stats.percentile(99.0)),
+                format!("{:.2?}", stats.percentile(100.0)),
+            )?;
+        }
+        writeln!(
+            f,
+            "Added latency per message: p50 {:.2?}, p95 {:.2?}, p99 {:.2?}",
+            self.intercepted
+                .percentile(50.0)
+                .saturating_sub(self.passthrough.percentile(50.0)),
+            self.intercepted
+                .percentile(95.0)
+                .saturating_sub(self.passthrough.percentile(95.0)),
+            self.intercepted
+                .percentile(99.0)
+                .saturating_sub(self.passthrough.percentile(99.0)),
+        )?;
+        write!(
+            f,
+            "Maximum controller throughput: {:.1} msgs/s",
+            self.controller_throughput
+        )
+    }
+}
+
+/// Returns the value below which the given percentage of the sorted values lies, using the nearest-rank method.
+/// Returns zero if there are no values.
+///
+/// # Parameters
+/// * 'sorted' - the values, sorted from low to high.
+/// * 'percentile' - the percentile, between 0 and 100.
+pub fn percentile_of(sorted: &[Duration], percentile: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (percentile.clamp(0.0, 100.0) / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Changes the network configuration received from the controller into the network used by the benchmark:
+/// two fully connected nodes that trust each other.
+///
+/// # Parameters
+/// * 'config' - the network configuration received from the controller.
+pub fn adjust_network_config(config: &mut Config) {
+    config.number_of_nodes = BENCH_NUMBER_OF_NODES;
+    config.net_partitions = vec![Partition {
+        nodes: (0..BENCH_NUMBER_OF_NODES).collect(),
+    }];
+    config.unl_partitions = vec![Partition {
+        nodes: (0..BENCH_NUMBER_OF_NODES).collect(),
+    }];
+}
+
+/// Sleeps for the given duration, or until Ctrl+C was pressed. Returns whether it was not interrupted.
+///
+/// # Parameters
+/// * 'duration' - how long to sleep.
+/// * 'running' - the flag that is cleared once Ctrl+C was pressed.
+async fn sleep_while_running(duration: Duration, running: &AtomicBool) -> bool {
+    let deadline = Instant::now() + duration;
+    while running.load(Ordering::SeqCst) {
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        tokio::time::sleep((deadline - now).min(Duration::from_millis(100))).await;
+    }
+    false
+}
+
+/// Measures the traffic that is handled during a single phase.
+///
+/// # Parameters
+/// * 'duration' - how long the phase is measured.
+/// * 'state' - the runtime state, whose timeline contains the handled messages.
+/// * 'running' - the flag that is cleared once Ctrl+C was pressed.
+async fn measure_phase(
+    duration: Duration,
+    state: &InterceptorState,
+    running: &AtomicBool,
+) -> PhaseStats {
+    let start = Utc::now();
+    let start_moment = Instant::now();
+    sleep_while_running(duration, running).await;
+    let records = state.timeline.between(start, Utc::now());
+    PhaseStats::from_records(&records, start_moment.elapsed())
+}
+
+/// Floods the controller with ping messages for the given duration and returns the amount of responses per second.
+///
+/// # Parameters
+/// * 'duration' - how long the controller is flooded.
+/// * 'client' - the PacketClient used to send the messages to the controller.
+/// * 'from_port' - the port of the node the messages pretend to come from.
+/// * 'to_port' - the port of the node the messages pretend to be sent to.
+/// * 'running' - the flag that is cleared once Ctrl+C was pressed.
+async fn measure_controller_throughput(
+    duration: Duration,
+    client: Arc<Mutex<PacketClient>>,
+    from_port: u32,
+    to_port: u32,
+    running: &AtomicBool,
+) -> f64 {
+    let message = Bytes::from_static(&PING_MESSAGE);
+    let mut client = client.lock().await;
+    let start = Instant::now();
+    let mut responses = 0;
+    while start.elapsed() < duration && running.load(Ordering::SeqCst) {
+        client
+            .send_packet(
+                message.clone(),
+                from_port,
+                to_port,
+                &PacketMetadata::default(),
+            )
+            .await
+            .expect("Error occurred while requesting message and action from the controller.");
+        responses += 1;
+    }
+    responses as f64 / start.elapsed().as_secs_f64()
+}
+
+/// Runs the benchmark on a network of which all links are already being handled, and returns the report.
+/// The network should be configured with `adjust_network_config`.
+///
+/// # Parameters
+/// * 'config' - the configuration of the benchmark.
+/// * 'network' - the running benchmark network.
+/// * 'client' - the PacketClient used to measure the throughput of the controller.
+/// * 'state' - the runtime state shared by all links, used to switch between passthrough and interception.
+/// * 'running' - the flag that is cleared once Ctrl+C was pressed.
+pub async fn run(
+    config: BenchConfig,
+    network: &DockerNetwork,
+    client: Arc<Mutex<PacketClient>>,
+    state: Arc<InterceptorState>,
+    running: Arc<AtomicBool>,
+) -> BenchReport {
+    let phase_duration = Duration::from_secs(config.phase_secs);
+
+    info!("Warming up for {} seconds", config.warmup_secs);
+    state.set_passthrough(true);
+    sleep_while_running(Duration::from_secs(config.warmup_secs), &running).await;
+
+    info!("Measuring passthrough for {} seconds", config.phase_secs);
+    let passthrough = measure_phase(phase_duration, &state, &running).await;
+
+    info!("Measuring interception for {} seconds", config.phase_secs);
+    state.set_passthrough(false);
+    let intercepted = measure_phase(phase_duration, &state, &running).await;
+
+    info!(
+        "Measuring controller throughput for {} seconds",
+        config.throughput_secs
+    );
+    let controller_throughput = measure_controller_throughput(
+        Duration::from_secs(config.throughput_secs),
+        client,
+        network.containers[0].port_peer,
+        network.containers[1].port_peer,
+        &running,
+    )
+    .await;
+
+    BenchReport {
+        passthrough,
+        intercepted,
+        controller_throughput,
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::bench::{adjust_network_config, percentile_of, BenchReport, PhaseStats};
+    use crate::message_type::MessageType;
+    use crate::packet_client::proto::Config;
+    use crate::packet_timeline::PacketRecord;
+    use std::time::Duration;
+
+    fn record(latency_ms: u64) -> PacketRecord {
+        PacketRecord {
+            message_type: MessageType::Ping,
+            size: 8,
+            sent_size: 8,
+            latency: Duration::from_millis(latency_ms),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn percentile_nearest_rank() {
+        let sorted: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(percentile_of(&sorted, 50.0), Duration::from_millis(50));
+        assert_eq!(percentile_of(&sorted, 99.0), Duration::from_millis(99));
+        assert_eq!(percentile_of(&sorted, 100.0), Duration::from_millis(100));
+        assert_eq!(percentile_of(&sorted, 0.0), Duration::from_millis(1));
+        assert_eq!(percentile_of(&[], 50.0), Duration::ZERO);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn phase_stats_from_records() {
+        let records = vec![record(3), record(1), record(2), record(4)];
+        let stats = PhaseStats::from_records(&records, Duration::from_secs(2));
+        assert_eq!(stats.messages, 4);
+        assert_eq!(stats.throughput(), 2.0);
+        assert_eq!(stats.percentile(50.0), Duration::from_millis(2));
+        assert_eq!(stats.percentile(100.0), Duration::from_millis(4));
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn phase_stats_zero_duration() {
+        let stats = PhaseStats::from_records(&[], Duration::ZERO);
+        assert_eq!(stats.throughput(), 0.0);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn report_contains_added_latency() {
+        let report = BenchReport {
+            passthrough: PhaseStats::from_records(&[record(1)], Duration::from_secs(1)),
+            intercepted: PhaseStats::from_records(&[record(3)], Duration::from_secs(1)),
+            controller_throughput: 500.0,
+        };
+        let output = report.to_string();
+        assert!(output.contains("passthrough"));
+        assert!(output.contains("intercepted"));
+        assert!(output.contains("Added latency per message: p50 2.00ms"));
+        assert!(output.contains("Maximum controller throughput: 500.0 msgs/s"));
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn adjust_network_config_two_nodes() {
+        let mut config = Config {
+            base_port_peer: 60000,
+            base_port_ws: 61000,
+            base_port_ws_admin: 62000,
+            base_port_rpc: 63000,
+            number_of_nodes: 5,
+            net_partitions: vec![],
+            unl_partitions: vec![],
+        };
+        adjust_network_config(&mut config);
+        assert_eq!(config.number_of_nodes, 2);
+        assert_eq!(config.net_partitions[0].nodes, vec![0, 1]);
+        assert_eq!(config.unl_partitions[0].nodes, vec![0, 1]);
+        assert_eq!(config.base_port_peer, 60000);
+    }
+}
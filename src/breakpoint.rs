@@ -0,0 +1,301 @@
+//! This module is responsible for packet breakpoints, which turn the interceptor into an interactive consensus debugger.
+//!
+//! A breakpoint is a predicate on the messages of the links. When a message matches, the link it was read from halts
+//! before the message is handled, until it is continued or stepped through the admin API. Stepping handles the
+//! message and halts the link again at its next message, regardless of the breakpoints.
+
+use crate::message_type::MessageType;
+use crate::wire_format::ledger_sequence;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use tokio::sync::watch;
+
+/// Struct that represents a breakpoint. A message matches if it matches all conditions that are set.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Breakpoint {
+    /// The ID of the breakpoint, assigned when it is added.
+    #[serde(default)]
+    pub id: u32,
+    /// The type of the message.
+    #[serde(default)]
+    pub message_type: Option<MessageType>,
+    /// The port of the node the message comes from.
+    #[serde(default)]
+    pub from_port: Option<u16>,
+    /// The port of the node the message goes to.
+    #[serde(default)]
+    pub to_port: Option<u16>,
+    /// The ledger sequence in the message, only messages that contain a ledger sequence can match.
+    #[serde(default)]
+    pub ledger_sequence: Option<u32>,
+}
+
+impl Breakpoint {
+    /// Returns whether a message matches all conditions of the breakpoint.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the node the message comes from.
+    /// * 'to_port' - the port of the node the message goes to.
+    /// * 'message' - the message including its header.
+    pub fn matches(&self, from_port: u16, to_port: u16, message: &[u8]) -> bool {
+        self.from_port.map_or(true, |port| port == from_port)
+            && self.to_port.map_or(true, |port| port == to_port)
+            && self.message_type.map_or(true, |message_type| {
+                MessageType::from_message(message) == Some(message_type)
+            })
+            && self
+                .ledger_sequence
+                .map_or(true, |sequence| ledger_sequence(message) == Some(sequence))
+    }
+}
+
+/// Struct that represents the breakpoints that are set.
+#[derive(Debug, Default)]
+pub struct Breakpoints {
+    /// The ID assigned to the next breakpoint.
+    next_id: u32,
+    /// The breakpoints in the order they were added.
+    breakpoints: Vec<Breakpoint>,
+}
+
+impl Breakpoints {
+    /// Adds a breakpoint and returns it with its assigned ID.
+    ///
+    /// # Parameters
+    /// * 'breakpoint' - the breakpoint, of which the ID is ignored.
+    pub fn add(&mut self, mut breakpoint: Breakpoint) -> Breakpoint {
+        self.next_id += 1;
+        breakpoint.id = self.next_id;
+        self.breakpoints.push(breakpoint.clone());
+        breakpoint
+    }
+
+    /// Removes a breakpoint. Returns whether it existed.
+    ///
+    /// # Parameters
+    /// * 'id' - the ID of the breakpoint.
+    pub fn remove(&mut self, id: u32) -> bool {
+        let length = self.breakpoints.len();
+        self.breakpoints.retain(|breakpoint| breakpoint.id != id);
+        self.breakpoints.len() != length
+    }
+
+    /// Returns all breakpoints in the order they were added.
+    pub fn list(&self) -> Vec<Breakpoint> {
+        self.breakpoints.clone()
+    }
+
+    /// Returns whether no breakpoints are set.
+    pub fn is_empty(&self) -> bool {
+        self.breakpoints.is_empty()
+    }
+
+    /// Returns the first breakpoint a message matches, if any.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the node the message comes from.
+    /// * 'to_port' - the port of the node the message goes to.
+    /// * 'message' - the message including its header.
+    pub fn find(&self, from_port: u16, to_port: u16, message: &[u8]) -> Option<&Breakpoint> {
+        self.breakpoints
+            .iter()
+            .find(|breakpoint| breakpoint.matches(from_port, to_port, message))
+    }
+}
+
+/// Struct that represents the message a link halted at.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BreakpointHit {
+    /// The ID of the breakpoint that matched, None if the link halted after a step.
+    pub breakpoint_id: Option<u32>,
+    pub from_port: u16,
+    pub to_port: u16,
+    /// The position of the message on its link.
+    pub sequence: u64,
+    pub message_type: MessageType,
+    pub ledger_sequence: Option<u32>,
+}
+
+/// Enum that represents whether a link is halted by the debugger.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DebugState {
+    /// Messages are handled until a breakpoint matches.
+    Running,
+    /// The next message halts the link, regardless of the breakpoints.
+    Stepping,
+    /// The link waits before handling the contained message.
+    Halted(BreakpointHit),
+}
+
+/// Struct that represents the reason a halted link can not be continued.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NotHaltedError {
+    pub from_port: u16,
+    pub to_port: u16,
+}
+
+impl fmt::Display for NotHaltedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Link {}->{} is not halted", self.from_port, self.to_port)
+    }
+}
+
+impl Error for NotHaltedError {}
+
+/// Struct that represents the debugger of a single link.
+#[derive(Debug)]
+pub struct LinkDebugger {
+    /// Whether the link is halted, shared with the decision stage of the link.
+    state: watch::Sender<DebugState>,
+}
+
+impl LinkDebugger {
+    /// Initializes a new LinkDebugger of a running link.
+    pub fn new() -> Self {
+        Self {
+            state: watch::Sender::new(DebugState::Running),
+        }
+    }
+
+    /// Returns whether the next message halts the link regardless of the breakpoints.
+    pub fn is_stepping(&self) -> bool {
+        *self.state.borrow() == DebugState::Stepping
+    }
+
+    /// Returns the message the link is halted at, if it is halted.
+    pub fn halted_at(&self) -> Option<BreakpointHit> {
+        match &*self.state.borrow() {
+            DebugState::Halted(hit) => Some(hit.clone()),
+            _ => None,
+        }
+    }
+
+    /// Halts the link at a message and waits until it is continued or stepped.
+    ///
+    /// # Parameters
+    /// * 'hit' - the message the link halts at.
+    pub async fn halt(&self, hit: BreakpointHit) {
+        let mut state = self.state.subscribe();
+        self.state.send_replace(DebugState::Halted(hit));
+        // The sender is owned by self, so it can not be dropped while waiting
+        let _ = state
+            .wait_for(|state| !matches!(state, DebugState::Halted(_)))
+            .await;
+    }
+
+    /// Lets a halted link handle the message it is halted at.
+    /// Returns that message, or None if the link is not halted.
+    ///
+    /// # Parameters
+    /// * 'step' - true to halt again at the next message, false to run until a breakpoint matches.
+    pub fn resume(&self, step: bool) -> Option<BreakpointHit> {
+        let hit = self.halted_at()?;
+        self.state.send_replace(if step {
+            DebugState::Stepping
+        } else {
+            DebugState::Running
+        });
+        Some(hit)
+    }
+}
+
+impl Default for LinkDebugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::breakpoint::{Breakpoint, BreakpointHit, Breakpoints, LinkDebugger};
+    use crate::message_type::MessageType;
+    use crate::wire_format::ledger_sequence;
+    use prost::encoding::encode_varint;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn message(message_type: MessageType, payload: &[u8]) -> Vec<u8> {
+        let mut message = (payload.len() as u32).to_be_bytes().to_vec();
+        message.extend_from_slice(&message_type.value().to_be_bytes());
+        message.extend_from_slice(payload);
+        message
+    }
+
+    fn status_change(ledger_seq: u64) -> Vec<u8> {
+        let mut payload = Vec::new();
+        // newEvent = neACCEPTED_LEDGER
+        encode_varint(2 << 3, &mut payload);
+        encode_varint(3, &mut payload);
+        encode_varint(3 << 3, &mut payload);
+        encode_varint(ledger_seq, &mut payload);
+        message(MessageType::StatusChange, &payload)
+    }
+
+    fn validation(ledger_seq: u32) -> Vec<u8> {
+        // sfFlags, sfLedgerSequence and sfSigningTime
+        let mut validation = vec![0x22, 0x80, 0, 0, 1, 0x26];
+        validation.extend_from_slice(&ledger_seq.to_be_bytes());
+        validation.extend_from_slice(&[0x29, 0, 0, 0, 9]);
+        let mut payload = Vec::new();
+        encode_varint(1 << 3 | 2, &mut payload);
+        encode_varint(validation.len() as u64, &mut payload);
+        payload.extend_from_slice(&validation);
+        message(MessageType::Validation, &payload)
+    }
+
+    fn hit() -> BreakpointHit {
+        BreakpointHit {
+            breakpoint_id: Some(1),
+            from_port: 60000,
+            to_port: 60001,
+            sequence: 0,
+            message_type: MessageType::Validation,
+            ledger_sequence: Some(5),
+        }
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn breakpoint_conditions() {
+        let mut breakpoints = Breakpoints::default();
+        let added = breakpoints.add(Breakpoint {
+            id: 0,
+            message_type: Some(MessageType::Validation),
+            from_port: Some(60000),
+            to_port: None,
+            ledger_sequence: Some(8),
+        });
+        assert_eq!(added.id, 1);
+
+        assert!(breakpoints.find(60000, 60001, &validation(8)).is_some());
+        assert!(breakpoints.find(60000, 60002, &validation(8)).is_some());
+        assert!(breakpoints.find(60001, 60000, &validation(8)).is_none());
+        assert!(breakpoints.find(60000, 60001, &validation(9)).is_none());
+        assert!(breakpoints.find(60000, 60001, &status_change(8)).is_none());
+
+        assert!(breakpoints.remove(1));
+        assert!(!breakpoints.remove(1));
+        assert!(breakpoints.is_empty());
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn halt_and_step() {
+        let debugger = Arc::new(LinkDebugger::new());
+        assert!(debugger.resume(false).is_none());
+
+        let halted = tokio::spawn({
+            let debugger = debugger.clone();
+            async move { debugger.halt(hit()).await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(debugger.halted_at(), Some(hit()));
+        assert!(!halted.is_finished());
+
+        assert_eq!(debugger.resume(true), Some(hit()));
+        halted.await.unwrap();
+        assert!(debugger.is_stepping());
+        assert_eq!(debugger.halted_at(), None);
+    }
+}
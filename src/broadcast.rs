@@ -0,0 +1,436 @@
+//! This module is responsible for the analysis of duplicate broadcasts: how many copies of every validation and
+//! proposal traverse the intercepted network, and how many of them are sent to a node that already has the broadcast.
+//! A node has a broadcast once it sent a copy of it, or a copy of it was sent to it.
+//!
+//! Copies are identified by their contents rather than by their bytes, such that a validation relayed by another peer
+//! is recognized as the same broadcast. With deduplication enabled, the redundant copies are dropped, which shows how
+//! the network behaves when every node receives every broadcast once.
+//!
+//! The controller can also decide on the copies of a broadcast that are sent later on, e.g. when the node a copy goes to
+//! relays it onward: the actions of its fan-out are remembered with the broadcast per node the copies are sent to.
+
+use crate::config::BroadcastConfig;
+use crate::message_type::MessageType;
+use crate::packet_client::proto::{Action, DestinationActions};
+use crate::run_id::RunId;
+use crate::wire_format;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Struct that represents what is known about a single broadcast.
+#[derive(Debug, Default)]
+struct Broadcast {
+    /// The ports of the nodes that have the broadcast.
+    holders: Vec<u16>,
+    copies: u32,
+    /// The actions of the controller for the copies sent to a node, by the port of that node.
+    fan_out: HashMap<u16, Vec<Action>>,
+}
+
+/// Struct that represents the broadcasts that are remembered and the counts of all broadcasts so far.
+#[derive(Debug, Default)]
+struct Tracked {
+    broadcasts: HashMap<[u8; 32], Broadcast>,
+    /// The remembered broadcasts, the oldest first.
+    order: VecDeque<[u8; 32]>,
+    counts: HashMap<MessageType, BroadcastCounts>,
+    /// The amount of redundant copies per port of the node they were sent to.
+    redundant_per_node: BTreeMap<u16, u64>,
+}
+
+impl Tracked {
+    /// Remembers a broadcast and counts it if it is new, after which the oldest broadcast is forgotten if too many are
+    /// remembered. Returns the broadcast.
+    ///
+    /// # Parameters
+    /// * 'id' - the ID of the broadcast.
+    /// * 'message_type' - the type of the broadcast.
+    /// * 'remembered' - the amount of broadcasts that are remembered.
+    fn remember(
+        &mut self,
+        id: [u8; 32],
+        message_type: MessageType,
+        remembered: usize,
+    ) -> &mut Broadcast {
+        if !self.broadcasts.contains_key(&id) {
+            self.counts.entry(message_type).or_default().broadcasts += 1;
+            self.order.push_back(id);
+            if self.order.len() > remembered {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.broadcasts.remove(&oldest);
+                }
+            }
+        }
+        self.broadcasts.entry(id).or_default()
+    }
+}
+
+/// Returns the type and the ID of a broadcast, which is the SHA-256 hash of its contents, or None if the message is not
+/// a broadcast.
+///
+/// # Parameters
+/// * 'message' - the message including its header.
+fn broadcast_id(message: &[u8]) -> Option<(MessageType, [u8; 32])> {
+    let message_type = MessageType::from_message(message)?;
+    let contents = wire_format::broadcast_contents(message)?;
+    Some((message_type, Sha256::digest(contents).into()))
+}
+
+/// Struct that represents the counts of the copies of the broadcasts of a single message type.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct BroadcastCounts {
+    /// The amount of distinct broadcasts.
+    pub broadcasts: u64,
+    /// The amount of copies that were read, over all links.
+    pub copies: u64,
+    /// The amount of copies that were sent to a node that already had the broadcast.
+    pub redundant: u64,
+    /// The amount of redundant copies that were dropped by the deduplication.
+    pub deduplicated: u64,
+    /// The highest amount of copies of a single broadcast.
+    pub max_copies: u32,
+}
+
+/// Struct that represents the counts of the broadcasts of a single message type, as they are reported.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MessageTypeBroadcasts {
+    pub message_type: MessageType,
+    #[serde(flatten)]
+    pub counts: BroadcastCounts,
+    /// The mean amount of copies of a broadcast.
+    pub copies_per_broadcast: f64,
+}
+
+/// Struct that represents the amount of redundant copies that were sent to a single node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct NodeRedundancy {
+    pub port: u16,
+    pub redundant: u64,
+}
+
+/// Struct that represents the report of the duplicate broadcasts of a run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BroadcastReport {
+    /// Whether the redundant copies are dropped.
+    pub dedupe: bool,
+    /// The counts per message type, ordered by message type.
+    pub message_types: Vec<MessageTypeBroadcasts>,
+    /// The redundant copies per node they were sent to, ordered by port.
+    pub nodes: Vec<NodeRedundancy>,
+}
+
+impl BroadcastReport {
+    /// Writes the report as JSON to a new file in the given directory, and returns the path of that file.
+    ///
+    /// # Parameters
+    /// * 'directory' - the directory the reports of all runs are stored in.
+    /// * 'run_id' - the identifier of the run, which names the file.
+    pub fn save(&self, directory: &str, run_id: &RunId) -> Result<PathBuf, Box<dyn Error>> {
+        fs::create_dir_all(directory)?;
+        let path = Path::new(directory).join(format!("broadcasts-{}.json", run_id));
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+}
+
+/// Struct that represents whether the redundant copies are dropped, as it is sent to and returned by the API.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Dedupe {
+    pub enabled: bool,
+}
+
+/// Struct that represents the tracking of the copies of every broadcast over all links.
+#[derive(Debug)]
+pub struct BroadcastTracker {
+    /// Whether the redundant copies are dropped.
+    dedupe: AtomicBool,
+    /// The amount of broadcasts that are remembered, the oldest are forgotten when there are more.
+    remembered: usize,
+    tracked: Mutex<Tracked>,
+}
+
+impl BroadcastTracker {
+    /// Initializes a new BroadcastTracker that has not seen any broadcasts.
+    ///
+    /// # Parameters
+    /// * 'config' - the configuration of the tracking.
+    pub fn new(config: &BroadcastConfig) -> Self {
+        Self {
+            dedupe: AtomicBool::new(config.dedupe),
+            remembered: config.remembered,
+            tracked: Mutex::new(Tracked::default()),
+        }
+    }
+
+    /// Returns whether the redundant copies are dropped.
+    pub fn is_deduplicating(&self) -> bool {
+        self.dedupe.load(Ordering::Relaxed)
+    }
+
+    /// Sets whether the redundant copies are dropped from now on.
+    ///
+    /// # Parameters
+    /// * 'dedupe' - true to drop the redundant copies.
+    pub fn set_dedupe(&self, dedupe: bool) {
+        self.dedupe.store(dedupe, Ordering::Relaxed);
+    }
+
+    /// Counts a copy of a message that was read, and returns whether it should be dropped because it is a redundant copy
+    /// of a broadcast and the redundant copies are dropped. Messages that are not broadcasts are not counted.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the node the copy came from.
+    /// * 'to_port' - the port of the node the copy is sent to.
+    /// * 'message' - the message including its header, before the controller mutated it.
+    /// * 'sent' - whether the copy is sent, or dropped by an earlier decision.
+    pub fn observe(&self, from_port: u16, to_port: u16, message: &[u8], sent: bool) -> bool {
+        let Some((message_type, id)) = broadcast_id(message) else {
+            return false;
+        };
+        let dedupe = self.is_deduplicating();
+
+        let mut tracked = self.tracked.lock().unwrap();
+        let tracked = &mut *tracked;
+        tracked.remember(id, message_type, self.remembered);
+        let counts = tracked.counts.entry(message_type).or_default();
+        let broadcast = tracked.broadcasts.entry(id).or_default();
+        broadcast.copies += 1;
+        counts.copies += 1;
+        counts.max_copies = counts.max_copies.max(broadcast.copies);
+        if !broadcast.holders.contains(&from_port) {
+            broadcast.holders.push(from_port);
+        }
+        if !sent {
+            return false;
+        }
+        if !broadcast.holders.contains(&to_port) {
+            broadcast.holders.push(to_port);
+            return false;
+        }
+        counts.redundant += 1;
+        *tracked.redundant_per_node.entry(to_port).or_default() += 1;
+        if dedupe {
+            counts.deduplicated += 1;
+        }
+        dedupe
+    }
+
+    /// Remembers the actions of the controller for the copies of a broadcast that are sent to other nodes, replacing
+    /// those for the same nodes. Returns false if the message is not a broadcast.
+    ///
+    /// # Parameters
+    /// * 'message' - the message including its header, before the controller mutated it.
+    /// * 'destinations' - the actions by the port of the node the copies are sent to.
+    pub fn set_fan_out(&self, message: &[u8], destinations: Vec<DestinationActions>) -> bool {
+        let Some((message_type, id)) = broadcast_id(message) else {
+            return false;
+        };
+        let mut tracked = self.tracked.lock().unwrap();
+        let broadcast = tracked.remember(id, message_type, self.remembered);
+        for destination in destinations {
+            broadcast
+                .fan_out
+                .insert(destination.to_port as u16, destination.actions);
+        }
+        true
+    }
+
+    /// Returns the actions of the controller for a copy of a broadcast that is sent to a node, or None if the
+    /// controller did not decide on the copies sent to that node.
+    ///
+    /// # Parameters
+    /// * 'to_port' - the port of the node the copy is sent to.
+    /// * 'message' - the message including its header.
+    pub fn fan_out(&self, to_port: u16, message: &[u8]) -> Option<Vec<Action>> {
+        let (_, id) = broadcast_id(message)?;
+        let tracked = self.tracked.lock().unwrap();
+        tracked.broadcasts.get(&id)?.fan_out.get(&to_port).cloned()
+    }
+
+    /// Returns the report of the broadcasts so far.
+    pub fn report(&self) -> BroadcastReport {
+        let tracked = self.tracked.lock().unwrap();
+        let mut message_types: Vec<MessageTypeBroadcasts> = tracked
+            .counts
+            .iter()
+            .map(|(&message_type, &counts)| MessageTypeBroadcasts {
+                message_type,
+                counts,
+                copies_per_broadcast: counts.copies as f64 / counts.broadcasts.max(1) as f64,
+            })
+            .collect();
+        message_types.sort_by_key(|broadcasts| broadcasts.message_type.value());
+        BroadcastReport {
+            dedupe: self.is_deduplicating(),
+            message_types,
+            nodes: tracked
+                .redundant_per_node
+                .iter()
+                .map(|(&port, &redundant)| NodeRedundancy { port, redundant })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::broadcast::{BroadcastCounts, BroadcastTracker, NodeRedundancy};
+    use crate::config::BroadcastConfig;
+    use crate::message_type::MessageType;
+    use crate::packet_client::proto::action::Kind;
+    use crate::packet_client::proto::{Action, DestinationActions};
+    use crate::wire_format;
+    use prost::encoding::encode_varint;
+
+    fn message(message_type: MessageType, payload: &[u8]) -> Vec<u8> {
+        let mut message = (payload.len() as u32).to_be_bytes().to_vec();
+        message.extend_from_slice(&message_type.value().to_be_bytes());
+        message.extend_from_slice(payload);
+        message
+    }
+
+    fn validation(contents: u8, hops: u64) -> Vec<u8> {
+        let mut payload = Vec::new();
+        encode_varint(1 << 3 | 2, &mut payload);
+        encode_varint(4, &mut payload);
+        payload.extend_from_slice(&[contents; 4]);
+        encode_varint(3 << 3, &mut payload);
+        encode_varint(hops, &mut payload);
+        message(MessageType::Validation, &payload)
+    }
+
+    fn proposal(signature: u8) -> Vec<u8> {
+        let mut payload = Vec::new();
+        encode_varint(1 << 3, &mut payload);
+        encode_varint(3, &mut payload);
+        encode_varint(5 << 3 | 2, &mut payload);
+        encode_varint(2, &mut payload);
+        payload.extend_from_slice(&[signature; 2]);
+        message(MessageType::ProposeLedger, &payload)
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn contents_of_broadcasts() {
+        assert_eq!(
+            wire_format::broadcast_contents(&validation(7, 1)),
+            Some(&[7u8; 4][..])
+        );
+        assert_eq!(
+            wire_format::broadcast_contents(&proposal(9)),
+            Some(&[9u8; 2][..])
+        );
+        let ping = message(MessageType::Ping, &[8, 0]);
+        assert_eq!(wire_format::broadcast_contents(&ping), None);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn redundant_copies_are_counted_and_deduplicated() {
+        let tracker = BroadcastTracker::new(&BroadcastConfig::default());
+        // Node 60000 broadcasts a validation to its peers
+        assert!(!tracker.observe(60000, 60001, &validation(1, 0), true));
+        assert!(!tracker.observe(60000, 60002, &validation(1, 0), true));
+        // The peers relay it, to each other and back, with a different hop count
+        assert!(!tracker.observe(60001, 60002, &validation(1, 1), true));
+        assert!(!tracker.observe(60002, 60000, &validation(1, 1), true));
+        // A dropped copy does not make its destination hold the broadcast
+        assert!(!tracker.observe(60000, 60003, &validation(1, 0), false));
+        assert!(!tracker.observe(60002, 60003, &validation(1, 1), true));
+
+        tracker.set_dedupe(true);
+        assert!(tracker.observe(60001, 60003, &validation(1, 1), true));
+        assert!(!tracker.observe(60001, 60000, &proposal(2), true));
+        assert!(!tracker.observe(60001, 60000, &message(MessageType::Ping, &[8, 0]), true));
+
+        let report = tracker.report();
+        assert!(report.dedupe);
+        assert_eq!(report.message_types.len(), 2);
+        assert_eq!(
+            report.message_types[0].message_type,
+            MessageType::ProposeLedger
+        );
+        let validations = &report.message_types[1];
+        assert_eq!(
+            validations.counts,
+            BroadcastCounts {
+                broadcasts: 1,
+                copies: 7,
+                redundant: 3,
+                deduplicated: 1,
+                max_copies: 7,
+            }
+        );
+        assert_eq!(validations.copies_per_broadcast, 7.0);
+        assert_eq!(
+            report.nodes,
+            [
+                NodeRedundancy {
+                    port: 60000,
+                    redundant: 1
+                },
+                NodeRedundancy {
+                    port: 60002,
+                    redundant: 1
+                },
+                NodeRedundancy {
+                    port: 60003,
+                    redundant: 1
+                }
+            ]
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn oldest_broadcasts_are_forgotten() {
+        let tracker = BroadcastTracker::new(&BroadcastConfig {
+            remembered: 1,
+            ..BroadcastConfig::default()
+        });
+        tracker.observe(60000, 60001, &validation(1, 0), true);
+        tracker.observe(60000, 60001, &validation(2, 0), true);
+        // The first validation is counted as a new broadcast again
+        tracker.observe(60000, 60001, &validation(1, 0), true);
+        let counts = tracker.report().message_types[0].counts;
+        assert_eq!(counts.broadcasts, 3);
+        assert_eq!(counts.redundant, 0);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn fan_out_is_remembered_per_destination() {
+        let tracker = BroadcastTracker::new(&BroadcastConfig::default());
+        let delay = |delay_ms| {
+            vec![Action {
+                kind: Some(Kind::DelayMs(delay_ms)),
+            }]
+        };
+        let destinations = vec![
+            DestinationActions {
+                to_port: 60002,
+                actions: delay(500),
+            },
+            DestinationActions {
+                to_port: 60003,
+                actions: delay(0),
+            },
+        ];
+        assert!(tracker.set_fan_out(&validation(1, 0), destinations));
+        assert!(!tracker.set_fan_out(&message(MessageType::Ping, &[8, 0]), Vec::new()));
+        tracker.observe(60000, 60001, &validation(1, 0), true);
+
+        // The copies node 60001 relays are recognized by their contents, regardless of the hop count
+        assert_eq!(tracker.fan_out(60002, &validation(1, 1)), Some(delay(500)));
+        assert_eq!(tracker.fan_out(60003, &validation(1, 1)), Some(delay(0)));
+        assert_eq!(tracker.fan_out(60000, &validation(1, 1)), None);
+        assert_eq!(tracker.fan_out(60002, &validation(2, 1)), None);
+        assert_eq!(tracker.report().message_types[0].counts.broadcasts, 1);
+    }
+}
@@ -0,0 +1,147 @@
+//! This module is responsible for providing read buffers without allocating a new buffer for every read.
+
+use bytes::{Bytes, BytesMut};
+
+/// Struct that represents a pool of read buffer memory for a single link.
+///
+/// Data is read into the spare capacity of one large buffer and split off as immutable `Bytes`,
+/// which can be passed along without copying. Once every `Bytes` split off from an allocation
+/// has been dropped, the allocation is reused for the next reads instead of allocating a new one.
+#[derive(Debug)]
+pub struct BufferPool {
+    /// The minimum amount of spare capacity available for every read.
+    chunk_size: usize,
+    /// The buffer that is read into, only contains data that has not been taken yet.
+    buffer: BytesMut,
+}
+
+impl BufferPool {
+    /// Initializes a new BufferPool.
+    ///
+    /// # Parameters
+    /// * 'chunk_size' - the minimum amount of spare capacity available for every read.
+    pub fn new(chunk_size: usize) -> Self {
+        Self {
+            chunk_size,
+            buffer: BytesMut::with_capacity(chunk_size),
+        }
+    }
+
+    /// Returns the buffer to read into, with at least `chunk_size` bytes of spare capacity.
+    /// Reading appends to the data that has not been taken yet.
+    pub fn buffer(&mut self) -> &mut BytesMut {
+        self.buffer.reserve(self.chunk_size);
+        &mut self.buffer
+    }
+
+    /// Returns the amount of bytes that have been read but not taken yet.
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Returns the bytes that have been read but not taken yet.
+    pub fn data(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// Returns whether all the bytes that have been read have also been taken.
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Takes the first `len` bytes that have been read out of the pool, without copying them.
+    ///
+    /// # Parameters
+    /// * 'len' - the amount of bytes to take.
+    ///
+    /// # Panics
+    /// * If `len` is larger than the amount of bytes that have been read.
+    pub fn take(&mut self, len: usize) -> Bytes {
+        self.buffer.split_to(len).freeze()
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::buffer_pool::BufferPool;
+    use bytes::{BufMut, BytesMut};
+    use std::time::Instant;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn buffer_has_spare_capacity() {
+        let mut pool = BufferPool::new(1024);
+        assert!(pool.buffer().capacity() - pool.len() >= 1024);
+        pool.buffer().put_slice(&[1; 1000]);
+        assert!(pool.buffer().capacity() - pool.len() >= 1024);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn take_splits_off_read_data() {
+        let mut pool = BufferPool::new(1024);
+        pool.buffer().put_slice(&[1, 2, 3, 4, 5]);
+        let taken = pool.take(3);
+        assert_eq!(taken.as_ref(), &[1, 2, 3]);
+        assert_eq!(pool.len(), 2);
+        assert_eq!(pool.take(2).as_ref(), &[4, 5]);
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn allocation_is_reused() {
+        let mut pool = BufferPool::new(1024);
+        pool.buffer().put_slice(&[1; 512]);
+        let first_ptr = pool.take(512).as_ptr();
+
+        // The taken bytes were dropped, so the next read reuses the same allocation
+        pool.buffer().put_slice(&[2; 512]);
+        let second = pool.take(512);
+        assert_eq!(first_ptr, second.as_ptr());
+    }
+
+    // Benchmark comparing the pooled read path with allocating a fresh 64KB buffer and copying every message.
+    // Run with: cargo test --release bench_buffer_pool -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn bench_buffer_pool() {
+        const ITERATIONS: usize = 200_000;
+        const BUFFER_SIZE: usize = 64 * 1024;
+        let message = vec![7u8; 1500];
+
+        let start = Instant::now();
+        let mut total = 0;
+        for _ in 0..ITERATIONS {
+            let mut buffer = BytesMut::with_capacity(BUFFER_SIZE);
+            buffer.resize(BUFFER_SIZE, 0);
+            buffer[..message.len()].copy_from_slice(&message);
+            buffer.resize(message.len(), 0);
+            let copied = buffer[..].to_vec();
+            total += copied.len();
+        }
+        let fresh = start.elapsed();
+
+        let start = Instant::now();
+        let mut pool = BufferPool::new(BUFFER_SIZE);
+        for _ in 0..ITERATIONS {
+            pool.buffer().put_slice(&message);
+            let taken = pool.take(message.len());
+            total += taken.len();
+        }
+        let pooled = start.elapsed();
+
+        println!(
+            "{} messages of {} bytes: fresh buffers {:?} ({:?}/msg), pooled {:?} ({:?}/msg), {} bytes total",
+            ITERATIONS,
+            message.len(),
+            fresh,
+            fresh / ITERATIONS as u32,
+            pooled,
+            pooled / ITERATIONS as u32,
+            total
+        );
+        assert!(pooled < fresh);
+    }
+}
@@ -0,0 +1,573 @@
+//! This module is responsible for the capture file of a run: every handled message is written to it as it was read, such
+//! that the run can be inspected with the `inspect` subcommand and fed to a controller again with the `replay`
+//! subcommand after the run.
+//!
+//! The capture file is a pcap file with nanosecond timestamps and the link type reserved for private use
+//! (LINKTYPE_USER0), such that general tools can open it. Every packet is a message of the peer protocol including its
+//! 6 byte header, preceded by a pseudo-header with the ports of its link and its position on the link.
+
+use crate::config::CaptureFileConfig;
+use crate::message_decoder;
+use crate::message_type::MessageType;
+use crate::packet_client::{PacketClient, PacketMetadata};
+use crate::run_id::RunId;
+use bytes::Bytes;
+use chrono::DateTime;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+/// The magic number of a pcap file with nanosecond timestamps, which is the kind of file that is written.
+const PCAP_MAGIC_NS: u32 = 0xa1b2_3c4d;
+
+/// The magic number of a pcap file with microsecond timestamps.
+const PCAP_MAGIC_US: u32 = 0xa1b2_c3d4;
+
+/// The link type reserved for private use, LINKTYPE_USER0, which tells the packets of the interceptor apart.
+const LINKTYPE_USER0: u32 = 147;
+
+/// The largest packet that is written whole, far above the largest message of the peer protocol.
+const SNAPSHOT_LENGTH: u32 = 0x0400_0000;
+
+/// The header of an SQLite database, which is what the session database of a run is.
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+/// The length of the pseudo-header that precedes every message: the ports of its link and its position on the link.
+const PSEUDO_HEADER_LENGTH: usize = 12;
+
+/// Struct that represents a message as it was read from a link, as it is stored in a capture file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedFrame {
+    /// The wall-clock time the message was read, in nanoseconds since the UNIX epoch.
+    pub timestamp_ns: u64,
+    /// The port of the peer where the message came from.
+    pub from_port: u16,
+    /// The port of the peer the message was sent to.
+    pub to_port: u16,
+    /// The position of the message on its link.
+    pub sequence: u64,
+    /// The message as it was read, including its 6 byte header.
+    pub data: Bytes,
+}
+
+impl CapturedFrame {
+    /// Returns the type of the message.
+    pub fn message_type(&self) -> MessageType {
+        MessageType::from_message(&self.data).unwrap_or(MessageType::Unknown(0))
+    }
+}
+
+/// Struct that represents the writer of a capture file.
+#[derive(Debug)]
+pub struct CaptureWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Initializes a new CaptureWriter, which writes the header of the capture file.
+    ///
+    /// # Parameters
+    /// * 'writer' - where the capture file is written to.
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(&PCAP_MAGIC_NS.to_le_bytes())?;
+        writer.write_all(&2u16.to_le_bytes())?;
+        writer.write_all(&4u16.to_le_bytes())?;
+        // The offset from UTC and the accuracy of the timestamps, which are unused
+        writer.write_all(&[0; 8])?;
+        writer.write_all(&SNAPSHOT_LENGTH.to_le_bytes())?;
+        writer.write_all(&LINKTYPE_USER0.to_le_bytes())?;
+        Ok(Self { writer })
+    }
+
+    /// Writes a message to the capture file.
+    ///
+    /// # Parameters
+    /// * 'frame' - the message.
+    pub fn write(&mut self, frame: &CapturedFrame) -> io::Result<()> {
+        let length = (PSEUDO_HEADER_LENGTH + frame.data.len()) as u32;
+        self.writer
+            .write_all(&((frame.timestamp_ns / 1_000_000_000) as u32).to_le_bytes())?;
+        self.writer
+            .write_all(&((frame.timestamp_ns % 1_000_000_000) as u32).to_le_bytes())?;
+        self.writer.write_all(&length.to_le_bytes())?;
+        self.writer.write_all(&length.to_le_bytes())?;
+        self.writer.write_all(&frame.from_port.to_be_bytes())?;
+        self.writer.write_all(&frame.to_port.to_be_bytes())?;
+        self.writer.write_all(&frame.sequence.to_be_bytes())?;
+        self.writer.write_all(&frame.data)
+    }
+
+    /// Flushes everything that was written.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Struct that represents the reader of a capture file, which reads the messages in the order they were written.
+#[derive(Debug)]
+pub struct CaptureReader<R: BufRead> {
+    reader: R,
+    /// Whether the numbers in the headers are big-endian, i.e. the file was written on a big-endian machine.
+    big_endian: bool,
+    /// Whether the timestamps have nanosecond instead of microsecond resolution.
+    nanoseconds: bool,
+}
+
+impl CaptureReader<BufReader<File>> {
+    /// Opens a capture file.
+    ///
+    /// # Parameters
+    /// * 'path' - the path of the capture file.
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let file = File::open(path)
+            .map_err(|e| format!("Could not open capture file {}: {}", path.display(), e))?;
+        Self::new(BufReader::new(file))
+    }
+}
+
+impl<R: BufRead> CaptureReader<R> {
+    /// Initializes a new CaptureReader, which reads the header of the capture file.
+    /// Returns an error if it is not a pcap file, or not a capture file of the interceptor.
+    ///
+    /// # Parameters
+    /// * 'reader' - where the capture file is read from.
+    pub fn new(mut reader: R) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut header = [0u8; 24];
+        reader.read_exact(&mut header)?;
+        let magic = u32::from_le_bytes(header[0..4].try_into().unwrap());
+        let (big_endian, nanoseconds) = match magic {
+            PCAP_MAGIC_NS => (false, true),
+            PCAP_MAGIC_US => (false, false),
+            _ if magic == PCAP_MAGIC_NS.swap_bytes() => (true, true),
+            _ if magic == PCAP_MAGIC_US.swap_bytes() => (true, false),
+            _ if header.starts_with(SQLITE_HEADER) => return Err(
+                "Not a pcap file but a session database, which holds no messages, query it instead"
+                    .into(),
+            ),
+            _ => return Err("Not a pcap file".into()),
+        };
+        let capture = Self {
+            reader,
+            big_endian,
+            nanoseconds,
+        };
+        let link_type = capture.number(&header[20..24]);
+        if link_type != LINKTYPE_USER0 {
+            return Err(format!(
+                "Not a capture file of the interceptor, its link type is {} instead of {}",
+                link_type, LINKTYPE_USER0
+            )
+            .into());
+        }
+        Ok(capture)
+    }
+
+    /// Returns a number of a header in the byte order of the file.
+    ///
+    /// # Parameters
+    /// * 'bytes' - the 4 bytes of the number.
+    fn number(&self, bytes: &[u8]) -> u32 {
+        let bytes = bytes.try_into().unwrap();
+        match self.big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        }
+    }
+
+    /// Reads the next message, or returns None at the end of the file.
+    fn read_frame(&mut self) -> Result<Option<CapturedFrame>, Box<dyn Error + Send + Sync>> {
+        if self.reader.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        let mut header = [0u8; 16];
+        self.reader.read_exact(&mut header)?;
+        let seconds = self.number(&header[0..4]) as u64;
+        let fraction = self.number(&header[4..8]) as u64;
+        let length = self.number(&header[8..12]) as usize;
+        if length < PSEUDO_HEADER_LENGTH {
+            return Err(format!("A packet of {} bytes has no pseudo-header", length).into());
+        }
+        let mut packet = vec![0u8; length];
+        self.reader.read_exact(&mut packet)?;
+        let data = Bytes::from(packet);
+        Ok(Some(CapturedFrame {
+            timestamp_ns: seconds * 1_000_000_000
+                + fraction * if self.nanoseconds { 1 } else { 1000 },
+            from_port: u16::from_be_bytes(data[0..2].try_into().unwrap()),
+            to_port: u16::from_be_bytes(data[2..4].try_into().unwrap()),
+            sequence: u64::from_be_bytes(data[4..12].try_into().unwrap()),
+            data: data.slice(PSEUDO_HEADER_LENGTH..),
+        }))
+    }
+}
+
+impl<R: BufRead> Iterator for CaptureReader<R> {
+    type Item = Result<CapturedFrame, Box<dyn Error + Send + Sync>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+/// Struct that represents the sending side of the capture file of a run, which is written by its own thread.
+/// If the thread can not keep up, messages are not captured instead of slowing down the links.
+#[derive(Debug)]
+pub struct CaptureFile {
+    /// The path of the capture file.
+    pub path: PathBuf,
+    /// The types of the captured messages, all types are captured if it is empty.
+    message_types: Vec<MessageType>,
+    /// The channel to the thread writing the capture file.
+    sender: mpsc::Sender<CapturedFrame>,
+    /// The amount of messages that were not captured because the thread could not keep up.
+    dropped: AtomicU64,
+}
+
+impl CaptureFile {
+    /// Creates the capture file of a new run in the configured directory, named after the run ID, and starts the thread
+    /// writing it. The thread flushes the file and stops once the returned handle is dropped.
+    ///
+    /// # Parameters
+    /// * 'config' - the configuration of the capture file.
+    /// * 'run_id' - the identifier of the run.
+    pub fn create(
+        config: &CaptureFileConfig,
+        run_id: &RunId,
+    ) -> Result<(Arc<Self>, JoinHandle<()>), Box<dyn Error + Send + Sync>> {
+        let message_types = config
+            .message_types
+            .iter()
+            .map(|name| name.parse())
+            .collect::<Result<Vec<MessageType>, String>>()?;
+        fs::create_dir_all(&config.directory)?;
+        let path = Path::new(&config.directory).join(format!("capture-{}.pcap", run_id));
+        let mut writer = CaptureWriter::new(BufWriter::new(File::create(&path)?))?;
+        let (sender, mut receiver) = mpsc::channel::<CapturedFrame>(config.capacity.max(1));
+        let name = path.display().to_string();
+        let thread = tokio::task::spawn_blocking(move || {
+            let mut written = 0u64;
+            while let Some(frame) = receiver.blocking_recv() {
+                match writer.write(&frame) {
+                    Ok(()) => written += 1,
+                    Err(e) => error!("Could not write a message to capture file {}: {}", name, e),
+                }
+            }
+            if let Err(e) = writer.flush() {
+                error!("Could not flush capture file {}: {}", name, e);
+            }
+            info!("Captured {} messages to {}", written, name);
+        });
+        let capture_file = Arc::new(Self {
+            path,
+            message_types,
+            sender,
+            dropped: AtomicU64::new(0),
+        });
+        Ok((capture_file, thread))
+    }
+
+    /// Captures a message if it has one of the captured types, or drops it if the thread writing the file is behind.
+    ///
+    /// # Parameters
+    /// * 'frame' - the message.
+    pub fn capture(&self, frame: CapturedFrame) {
+        if !self.message_types.is_empty() && !self.message_types.contains(&frame.message_type()) {
+            return;
+        }
+        match self.sender.try_send(frame) {
+            Ok(()) | Err(TrySendError::Closed(_)) => (),
+            Err(TrySendError::Full(_)) => {
+                if self.dropped.fetch_add(1, Ordering::Relaxed) == 0 {
+                    warn!(
+                        "Capture file {} can not keep up, messages are not captured",
+                        self.path.display()
+                    );
+                }
+            }
+        }
+    }
+
+    /// Returns the amount of messages that were not captured because the thread writing the file could not keep up.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Struct that represents which messages of a capture file are inspected. A message is inspected if it matches all
+/// conditions that are set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InspectFilter {
+    /// The links of which the messages are inspected, as the ports of the peers they are from and to, all if empty.
+    pub links: Vec<(u16, u16)>,
+    /// The ports of the peers of which the sent and received messages are inspected, all if empty.
+    pub ports: Vec<u16>,
+    /// The types of the inspected messages, all if empty.
+    pub message_types: Vec<MessageType>,
+    /// The time since the first message of the capture file from which messages are inspected.
+    pub from: Option<Duration>,
+    /// The time since the first message of the capture file until which messages are inspected.
+    pub to: Option<Duration>,
+}
+
+impl InspectFilter {
+    /// Returns whether a message is inspected.
+    ///
+    /// # Parameters
+    /// * 'frame' - the message.
+    /// * 'offset' - the time between the first message of the capture file and the message.
+    pub fn matches(&self, frame: &CapturedFrame, offset: Duration) -> bool {
+        (self.links.is_empty() || self.links.contains(&(frame.from_port, frame.to_port)))
+            && (self.ports.is_empty()
+                || self.ports.contains(&frame.from_port)
+                || self.ports.contains(&frame.to_port))
+            && (self.message_types.is_empty() || self.message_types.contains(&frame.message_type()))
+            && self.from.map_or(true, |from| offset >= from)
+            && self.to.map_or(true, |to| offset <= to)
+    }
+}
+
+/// Runs the `inspect` subcommand: prints the messages of a capture file that match a filter, one per line, decoded to
+/// their key fields, see `message_decoder`. The time of a message is the time since the first message of the file.
+///
+/// # Parameters
+/// * 'path' - the path of the capture file.
+/// * 'filter' - which messages are printed.
+/// * 'hex_dump' - whether every message is also printed whole in hex, below its line.
+pub fn inspect_command(
+    path: &Path,
+    filter: &InspectFilter,
+    hex_dump: bool,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    println!(
+        "{:>15}  {:<11}  {:>8}  {:<28}  {:>8}  {:>8}  fields",
+        "time", "link", "sequence", "type", "size", "ledger"
+    );
+    let mut first_timestamp_ns = None;
+    let (mut total, mut inspected) = (0u64, 0u64);
+    for frame in CaptureReader::open(path)? {
+        let frame = frame?;
+        total += 1;
+        let first_timestamp_ns = *first_timestamp_ns.get_or_insert(frame.timestamp_ns);
+        let offset = Duration::from_nanos(frame.timestamp_ns.saturating_sub(first_timestamp_ns));
+        if !filter.matches(&frame, offset) {
+            continue;
+        }
+        inspected += 1;
+        let decoded = message_decoder::decode(&frame.data);
+        println!(
+            "{:>15.6}  {:<11}  {:>8}  {:<28}  {:>8}  {:>8}  {}",
+            offset.as_secs_f64(),
+            format!("{}->{}", frame.from_port, frame.to_port),
+            frame.sequence,
+            frame.message_type().to_string(),
+            frame.data.len(),
+            decoded
+                .as_ref()
+                .and_then(|decoded| decoded.ledger_sequence)
+                .map_or("-".to_string(), |sequence| sequence.to_string()),
+            decoded.map_or("malformed".to_string(), |decoded| decoded.to_string()),
+        );
+        if hex_dump {
+            println!("{}", hex::encode_upper(&frame.data));
+        }
+    }
+    match first_timestamp_ns {
+        Some(first_timestamp_ns) => println!(
+            "{} of {} messages, the first read at {}",
+            inspected,
+            total,
+            DateTime::from_timestamp_nanos(first_timestamp_ns as i64)
+                .format("%Y-%m-%d %H:%M:%S%.6f UTC")
+        ),
+        None => println!("{} has no messages", path.display()),
+    }
+    Ok(())
+}
+
+/// Runs the `replay` subcommand: feeds the messages of a capture file to a controller, with the time between them as
+/// they were read scaled by a speed factor, and prints the action the controller takes on every message. No network is
+/// set up, so the actions are only reported, which makes a controller strategy testable against a fixed run.
+///
+/// # Parameters
+/// * 'path' - the path of the capture file.
+/// * 'address' - the address of the controller.
+/// * 'speed' - how many times faster than they were read the messages are fed, 0 feeds them without waiting.
+/// * 'run_id' - the identifier of the replay, sent to the controller.
+pub async fn replay_command(
+    path: &Path,
+    address: &str,
+    speed: f64,
+    run_id: RunId,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if !speed.is_finite() || speed < 0.0 {
+        return Err(format!("The speed {} has to be at least 0", speed).into());
+    }
+    let frames = CaptureReader::open(path)?;
+    let mut client = PacketClient::connect(address)
+        .await
+        .map_err(|e| format!("Could not connect to the controller at {}: {}", address, e))?;
+    client.set_run_id(run_id);
+    client
+        .negotiate_version()
+        .await
+        .map_err(|e| format!("Could not negotiate the protocol version: {}", e))?;
+
+    let start = tokio::time::Instant::now();
+    let mut first_timestamp_ns = None;
+    let mut actions: BTreeMap<&str, u64> = BTreeMap::new();
+    for frame in frames {
+        let frame = frame?;
+        let first_timestamp_ns = *first_timestamp_ns.get_or_insert(frame.timestamp_ns);
+        if speed > 0.0 {
+            let offset =
+                Duration::from_nanos(frame.timestamp_ns.saturating_sub(first_timestamp_ns));
+            tokio::time::sleep_until(start + offset.div_f64(speed)).await;
+        }
+        let metadata = PacketMetadata {
+            sequence: frame.sequence,
+            capture_timestamp_ns: frame.timestamp_ns,
+            ..PacketMetadata::default()
+        };
+        let ack = client
+            .send_packet(
+                frame.data.clone(),
+                frame.from_port as u32,
+                frame.to_port as u32,
+                &metadata,
+            )
+            .await
+            .map_err(|e| format!("The controller did not decide on a message: {}", e))?;
+        let action = match ack.send_amount {
+            0 => "drop",
+            1 if ack.action > 0 => "delay",
+            1 => "forward",
+            _ => "duplicate",
+        };
+        *actions.entry(action).or_default() += 1;
+        println!(
+            "{:>5}->{:<5}  {:>8}  {:<28}  {} (delay {} ms, sent {} times)",
+            frame.from_port,
+            frame.to_port,
+            frame.sequence,
+            frame.message_type().to_string(),
+            action,
+            ack.action,
+            ack.send_amount
+        );
+    }
+    let summary: Vec<String> = actions
+        .iter()
+        .map(|(action, count)| format!("{} {}", count, action))
+        .collect();
+    println!(
+        "Replayed {} to {}: {}",
+        path.display(),
+        address,
+        summary.join(", ")
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::capture_file::{CaptureReader, CaptureWriter, CapturedFrame, InspectFilter};
+    use crate::message_decoder;
+    use crate::message_type::MessageType;
+    use bytes::Bytes;
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    fn frame(sequence: u64, message_type: u16, payload: &[u8]) -> CapturedFrame {
+        let mut data = (payload.len() as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(&message_type.to_be_bytes());
+        data.extend_from_slice(payload);
+        CapturedFrame {
+            timestamp_ns: 1_700_000_000_123_456_789 + sequence,
+            from_port: 60000,
+            to_port: 60001,
+            sequence,
+            data: Bytes::from(data),
+        }
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn captured_messages_are_read_back() {
+        let frames = vec![frame(0, 3, &[8, 1]), frame(1, 41, &[0x22; 40])];
+        let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+        for frame in &frames {
+            writer.write(frame).unwrap();
+        }
+        let file = writer.writer;
+        assert_eq!(file.len(), 24 + 16 + 12 + 8 + 16 + 12 + 46);
+
+        let read: Vec<CapturedFrame> = CaptureReader::new(Cursor::new(file))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(read, frames);
+        assert_eq!(read[0].message_type(), MessageType::Ping);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn other_files_are_rejected() {
+        assert!(CaptureReader::new(Cursor::new(b"not a capture file at all".to_vec())).is_err());
+        let error =
+            CaptureReader::new(Cursor::new(b"SQLite format 3\0 database".to_vec())).unwrap_err();
+        assert!(error.to_string().contains("session database"));
+
+        // A pcap file of Ethernet frames
+        let mut file = CaptureWriter::new(Vec::new()).unwrap().writer;
+        file[20..24].copy_from_slice(&1u32.to_le_bytes());
+        assert!(CaptureReader::new(Cursor::new(file)).is_err());
+
+        // A packet that ends before its pseudo-header
+        let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+        writer.write(&frame(0, 3, &[])).unwrap();
+        let mut file = writer.writer;
+        file.truncate(24 + 16 + 4);
+        let mut reader = CaptureReader::new(Cursor::new(file)).unwrap();
+        assert!(reader.next().unwrap().is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn inspected_messages_are_filtered() {
+        let ping = frame(0, 3, &[8, 1]);
+        let validation = frame(1, 41, &[0x22; 40]);
+        let second = Duration::from_secs(1);
+        assert!(InspectFilter::default().matches(&ping, second));
+
+        let filter = InspectFilter {
+            links: vec![(60000, 60001)],
+            message_types: vec![MessageType::Validation],
+            ..InspectFilter::default()
+        };
+        assert!(!filter.matches(&ping, second));
+        assert!(filter.matches(&validation, second));
+        let mut other_link = validation.clone();
+        other_link.to_port = 60002;
+        assert!(!filter.matches(&other_link, second));
+
+        let filter = InspectFilter {
+            ports: vec![60002],
+            from: Some(second),
+            to: Some(2 * second),
+            ..InspectFilter::default()
+        };
+        assert!(filter.matches(&other_link, second));
+        assert!(filter.matches(&other_link, 2 * second));
+        assert!(!filter.matches(&other_link, Duration::from_millis(999)));
+        assert!(!filter.matches(&validation, second));
+    }
+}
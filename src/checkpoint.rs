@@ -0,0 +1,228 @@
+//! This module is responsible for the `checkpoint` and `restore` subcommands, which save a network at a point of its
+//! ledger history and later return it to that point, such that a long-running setup can be reused across experiments.
+//!
+//! A checkpoint holds an archive of the database volume of every node, see `[persistence]`, the database of the run if
+//! it was stored, and the state of the session: the seed the keys of the nodes were derived from and the names of their
+//! containers. The state of the session is written by every run that keeps the databases of its nodes.
+
+use crate::config::PersistenceConfig;
+use crate::docker_manager::DockerNetwork;
+use crate::packet_client::proto;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The file the state of the session of the last run is written to.
+pub const SESSION_FILE: &str = "network/session.json";
+
+/// The file in the directory of a checkpoint that describes the checkpoint.
+const CHECKPOINT_FILE: &str = "checkpoint.json";
+
+/// The usage of the `checkpoint` and `restore` subcommands.
+pub const CHECKPOINT_USAGE: &str = "Usage: rocket-interceptor checkpoint <name>
+       rocket-interceptor restore <name>
+Saves the network of the last run as a checkpoint, or returns it to a checkpoint. Requires the [persistence] section.";
+
+/// Struct that represents the state of the session of a run, which is needed to continue from its ledger history.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionState {
+    /// The seed the keys of the nodes were derived from, if the run was seeded.
+    pub seed: Option<u64>,
+    /// The names of the containers of the nodes, whose volumes hold their databases.
+    pub containers: Vec<String>,
+    /// The path of the database of the run, if the handled messages were stored.
+    pub session_database: Option<PathBuf>,
+}
+
+impl SessionState {
+    /// Writes the state of the session to a file.
+    ///
+    /// # Parameters
+    /// * 'path' - the path of the file.
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Reads the state of a session from a file.
+    ///
+    /// # Parameters
+    /// * 'path' - the path of the file.
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+}
+
+/// Struct that represents the description of a checkpoint, stored next to its archives.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Checkpoint {
+    /// When the checkpoint was taken, in RFC 3339.
+    created_at: String,
+    /// The state of the session the checkpoint was taken of.
+    session: SessionState,
+    /// The names of the archived volumes, each stored as `<volume>.tar`.
+    volumes: Vec<String>,
+    /// The file name of the copy of the database of the run, if it was stored.
+    session_database: Option<String>,
+}
+
+/// Returns the name of the checkpoint in the arguments of a subcommand.
+///
+/// # Parameters
+/// * 'args' - the arguments following the subcommand.
+fn checkpoint_name(args: &[String]) -> Result<String, String> {
+    match args {
+        [name]
+            if !name.is_empty() && !name.contains(['/', '\\']) && name != "." && name != ".." =>
+        {
+            Ok(name.clone())
+        }
+        [_] => Err("A checkpoint name cannot be a path".to_string()),
+        _ => Err("Expected the name of a checkpoint".to_string()),
+    }
+}
+
+/// Runs the `checkpoint` subcommand: stops the nodes of the last run and saves their volumes, the database of the run
+/// and the state of the session to a new checkpoint.
+///
+/// # Parameters
+/// * 'args' - the arguments following `checkpoint`.
+/// * 'persistence' - the configuration of the volumes and checkpoints.
+pub async fn checkpoint_command(
+    args: &[String],
+    persistence: &PersistenceConfig,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let name = checkpoint_name(args).map_err(|e| format!("{}\n{}", e, CHECKPOINT_USAGE))?;
+    let directory = Path::new(&persistence.checkpoint_directory).join(&name);
+    if directory.exists() {
+        return Err(format!("Checkpoint {} already exists", name).into());
+    }
+    let session = SessionState::read(Path::new(SESSION_FILE))?;
+
+    // The databases of the nodes are only consistent once the nodes are stopped
+    let network = DockerNetwork::new(proto::Config::default());
+    network.stop_network().await;
+    fs::create_dir_all(&directory)?;
+    let mut volumes = Vec::new();
+    for container in session.containers.iter() {
+        let volume = persistence.volume(container);
+        network.archive_volume(&volume, &directory).await?;
+        println!("Saved volume {}", volume);
+        volumes.push(volume);
+    }
+    let session_database = match &session.session_database {
+        Some(path) => {
+            let file_name = path
+                .file_name()
+                .ok_or("The session database has no file name")?
+                .to_string_lossy()
+                .to_string();
+            fs::copy(path, directory.join(&file_name))?;
+            Some(file_name)
+        }
+        None => None,
+    };
+    let checkpoint = Checkpoint {
+        created_at: Utc::now().to_rfc3339(),
+        session,
+        volumes,
+        session_database,
+    };
+    fs::write(
+        directory.join(CHECKPOINT_FILE),
+        serde_json::to_string_pretty(&checkpoint)?,
+    )?;
+    println!("Saved checkpoint {} to {}", name, directory.display());
+    Ok(())
+}
+
+/// Runs the `restore` subcommand: stops the running nodes and returns their volumes, the database of the run and the
+/// state of the session to a checkpoint. The next run without `--fresh` continues from the ledgers of the checkpoint.
+///
+/// # Parameters
+/// * 'args' - the arguments following `restore`.
+/// * 'persistence' - the configuration of the volumes and checkpoints.
+pub async fn restore_command(
+    args: &[String],
+    persistence: &PersistenceConfig,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let name = checkpoint_name(args).map_err(|e| format!("{}\n{}", e, CHECKPOINT_USAGE))?;
+    let directory = Path::new(&persistence.checkpoint_directory).join(&name);
+    let contents = fs::read_to_string(directory.join(CHECKPOINT_FILE))
+        .map_err(|e| format!("Could not read checkpoint {}: {}", name, e))?;
+    let checkpoint: Checkpoint = serde_json::from_str(&contents)?;
+
+    let network = DockerNetwork::new(proto::Config::default());
+    network.stop_network().await;
+    for volume in checkpoint.volumes.iter() {
+        network.restore_volume(volume, &directory).await?;
+        println!("Restored volume {}", volume);
+    }
+    if let (Some(file_name), Some(path)) = (
+        &checkpoint.session_database,
+        &checkpoint.session.session_database,
+    ) {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(directory.join(file_name), path)?;
+    }
+    checkpoint.session.write(Path::new(SESSION_FILE))?;
+
+    println!(
+        "Restored checkpoint {} taken at {}",
+        name, checkpoint.created_at
+    );
+    match checkpoint.session.seed {
+        Some(seed) => println!(
+            "Start the interceptor with --seed {} and without --fresh to continue from it",
+            seed
+        ),
+        None => println!(
+            "Start the interceptor without --fresh to continue from it, the run was not seeded so the nodes get new keys"
+        ),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::checkpoint::{checkpoint_name, SessionState};
+    use std::path::PathBuf;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn checkpoint_names() {
+        assert_eq!(
+            checkpoint_name(&["after-10k".to_string()]),
+            Ok("after-10k".to_string())
+        );
+        assert!(checkpoint_name(&[]).is_err());
+        assert!(checkpoint_name(&["a".to_string(), "b".to_string()]).is_err());
+        assert!(checkpoint_name(&["../a".to_string()]).is_err());
+        assert!(checkpoint_name(&["..".to_string()]).is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn session_state_round_trip() {
+        let path = std::env::temp_dir()
+            .join(format!("session-{}", std::process::id()))
+            .join("session.json");
+        let session = SessionState {
+            seed: Some(42),
+            containers: vec!["validator_0".to_string(), "validator_1".to_string()],
+            session_database: Some(PathBuf::from("sessions/run-20240101-000000.sqlite")),
+        };
+        session.write(&path).unwrap();
+        assert_eq!(SessionState::read(&path).unwrap(), session);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
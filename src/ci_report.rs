@@ -0,0 +1,281 @@
+//! This module is responsible for the result of a run as seen by CI pipelines:
+//! a JUnit XML report with a test case per checked property, and an exit code that tells a clean run,
+//! a violated consensus property and a failure of the infrastructure apart.
+
+use crate::assertion_engine::{Property, Violation};
+use std::error::Error;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Enum that represents the outcome of a run, which determines the exit code of the interceptor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunOutcome {
+    /// The run completed and no property was violated.
+    Clean,
+    /// At least one consensus property was violated.
+    ConsensusViolated,
+    /// The network, the controller or the interceptor itself failed, so the properties can not be trusted.
+    InfrastructureFailure,
+}
+
+impl RunOutcome {
+    /// Returns the exit code of the interceptor for this outcome.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RunOutcome::Clean => 0,
+            RunOutcome::ConsensusViolated => 1,
+            RunOutcome::InfrastructureFailure => 2,
+        }
+    }
+}
+
+/// Struct that represents the result of a run that is reported to CI.
+#[derive(Debug, Clone)]
+pub struct CiReport {
+    /// The name of the scenario, used as the name of the test suite.
+    pub scenario: String,
+    /// How long the run took.
+    pub duration: Duration,
+    /// Whether the properties were checked, if not the report contains no property test cases.
+    pub properties_checked: bool,
+    /// All violations detected by the assertion engine.
+    pub violations: Vec<Violation>,
+    /// Descriptions of the failures of the infrastructure during the run.
+    pub infrastructure_failures: Vec<String>,
+}
+
+impl CiReport {
+    /// Returns the outcome of the run. A failure of the infrastructure takes precedence over violations,
+    /// since those may have been caused by it.
+    pub fn outcome(&self) -> RunOutcome {
+        if !self.infrastructure_failures.is_empty() {
+            RunOutcome::InfrastructureFailure
+        } else if !self.violations.is_empty() {
+            RunOutcome::ConsensusViolated
+        } else {
+            RunOutcome::Clean
+        }
+    }
+
+    /// Returns the report in the JUnit XML format. Every property is a test case,
+    /// and the infrastructure is a test case with an error if it failed.
+    pub fn to_junit_xml(&self) -> String {
+        let properties: &[Property] = if self.properties_checked {
+            &Property::ALL
+        } else {
+            &[]
+        };
+        let failures = properties
+            .iter()
+            .filter(|property| self.violations_of(**property).next().is_some())
+            .count();
+        let errors = usize::from(!self.infrastructure_failures.is_empty());
+        let seconds = self.duration.as_secs_f64();
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            xml,
+            "<testsuites tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{:.3}\">",
+            properties.len() + 1,
+            failures,
+            errors,
+            seconds
+        );
+        let _ = writeln!(
+            xml,
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{:.3}\">",
+            escape(&self.scenario),
+            properties.len() + 1,
+            failures,
+            errors,
+            seconds
+        );
+        for property in properties {
+            let _ = write!(
+                xml,
+                "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+                escape(&self.scenario),
+                property,
+                seconds
+            );
+            let violations: Vec<&Violation> = self.violations_of(*property).collect();
+            if violations.is_empty() {
+                xml.push_str("/>\n");
+                continue;
+            }
+            let details = violations
+                .iter()
+                .map(|violation| {
+                    format!(
+                        "{} node {}: {}",
+                        violation.timestamp.to_rfc3339(),
+                        violation.node_id,
+                        violation.description
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join("\n");
+            let _ = writeln!(
+                xml,
+                ">\n      <failure message=\"{}\" type=\"{}\">{}</failure>\n    </testcase>",
+                escape(&format!(
+                    "{} violation(s) of {}",
+                    violations.len(),
+                    property
+                )),
+                property,
+                escape(&details)
+            );
+        }
+        let _ = write!(
+            xml,
+            "    <testcase classname=\"{}\" name=\"infrastructure\" time=\"{:.3}\"",
+            escape(&self.scenario),
+            seconds
+        );
+        if self.infrastructure_failures.is_empty() {
+            xml.push_str("/>\n");
+        } else {
+            let _ = writeln!(
+                xml,
+                ">\n      <error message=\"{}\">{}</error>\n    </testcase>",
+                escape(&format!(
+                    "{} infrastructure failure(s)",
+                    self.infrastructure_failures.len()
+                )),
+                escape(&self.infrastructure_failures.join("\n"))
+            );
+        }
+        xml.push_str("  </testsuite>\n</testsuites>\n");
+        xml
+    }
+
+    /// Writes the JUnit XML report to a file.
+    ///
+    /// # Parameters
+    /// * 'path' - the path of the file.
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_junit_xml())?;
+        Ok(())
+    }
+
+    /// Returns the violations of a single property.
+    ///
+    /// # Parameters
+    /// * 'property' - the property.
+    fn violations_of(&self, property: Property) -> impl Iterator<Item = &Violation> {
+        self.violations
+            .iter()
+            .filter(move |violation| violation.property == property)
+    }
+}
+
+/// Escapes text such that it can be used in XML attributes and elements.
+///
+/// # Parameters
+/// * 'text' - the text.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::assertion_engine::{Property, Violation};
+    use crate::ci_report::{escape, CiReport, RunOutcome};
+    use chrono::Utc;
+    use std::time::Duration;
+
+    fn report(violations: Vec<Violation>, infrastructure_failures: Vec<String>) -> CiReport {
+        CiReport {
+            scenario: "partition".to_string(),
+            duration: Duration::from_secs(60),
+            properties_checked: true,
+            violations,
+            infrastructure_failures,
+        }
+    }
+
+    fn violation(property: Property) -> Violation {
+        Violation {
+            property,
+            node_id: 1,
+            description: "node 1 validated <A> & <B>".to_string(),
+            timestamp: Utc::now(),
+            timeline: Vec::new(),
+        }
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn outcome_and_exit_code() {
+        assert_eq!(report(vec![], vec![]).outcome(), RunOutcome::Clean);
+        assert_eq!(
+            report(vec![violation(Property::Agreement)], vec![]).outcome(),
+            RunOutcome::ConsensusViolated
+        );
+        assert_eq!(
+            report(
+                vec![violation(Property::Agreement)],
+                vec!["controller unreachable".to_string()]
+            )
+            .outcome(),
+            RunOutcome::InfrastructureFailure
+        );
+        assert_eq!(RunOutcome::Clean.exit_code(), 0);
+        assert_eq!(RunOutcome::ConsensusViolated.exit_code(), 1);
+        assert_eq!(RunOutcome::InfrastructureFailure.exit_code(), 2);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn junit_xml_has_a_test_case_per_property() {
+        let xml = report(
+            vec![
+                violation(Property::Agreement),
+                violation(Property::Agreement),
+            ],
+            vec![],
+        )
+        .to_junit_xml();
+        assert!(
+            xml.contains("<testsuite name=\"partition\" tests=\"4\" failures=\"1\" errors=\"0\"")
+        );
+        assert!(xml.contains("name=\"progress\" time=\"60.000\"/>"));
+        assert!(
+            xml.contains("<failure message=\"2 violation(s) of agreement\" type=\"agreement\">")
+        );
+        assert!(xml.contains("validated &lt;A&gt; &amp; &lt;B&gt;"));
+        assert!(xml.contains("name=\"infrastructure\" time=\"60.000\"/>"));
+
+        let mut unchecked = report(vec![], vec!["task panicked".to_string()]);
+        unchecked.properties_checked = false;
+        let xml = unchecked.to_junit_xml();
+        assert!(xml.contains("tests=\"1\" failures=\"0\" errors=\"1\""));
+        assert!(
+            xml.contains("<error message=\"1 infrastructure failure(s)\">task panicked</error>")
+        );
+        assert!(!xml.contains("agreement"));
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn escape_xml() {
+        assert_eq!(escape("a\"b'c"), "a&quot;b&apos;c");
+    }
+}
@@ -0,0 +1,283 @@
+//! This module is responsible for the circuit breaker of the controller channel.
+//!
+//! The breaker tracks whether the most recent requests to the controller failed or timed out. While it is closed, all
+//! intercepted messages are sent to the controller. Once too many of them fail, it opens: messages are forwarded without
+//! asking the controller, and only the local rules apply to them. After the configured time, a single message is sent
+//! as a probe while the breaker is half-open, which closes it if the controller decides on it, or opens it again.
+
+use crate::config::CircuitBreakerConfig;
+use crate::event_bus::{EventBus, EventKind};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Enum that represents the state of the circuit breaker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// The controller is healthy, all messages are sent to it.
+    Closed,
+    /// The controller is unhealthy, messages are forwarded without asking it.
+    Open,
+    /// A single message is sent to the controller to probe whether it recovered.
+    HalfOpen,
+}
+
+impl fmt::Display for BreakerState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BreakerState::Closed => write!(f, "closed"),
+            BreakerState::Open => write!(f, "open"),
+            BreakerState::HalfOpen => write!(f, "half_open"),
+        }
+    }
+}
+
+/// Struct that represents the state and counters of the circuit breaker, as they are exposed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct BreakerSummary {
+    pub state: BreakerState,
+    /// The share of the requests in the window that failed, between 0 and 1.
+    pub failure_rate: f64,
+    /// How many times the breaker opened.
+    pub times_opened: u64,
+    /// The amount of messages forwarded without asking the controller because the breaker was open.
+    pub bypassed: u64,
+}
+
+/// Struct that represents the mutable part of the circuit breaker.
+#[derive(Debug)]
+struct Breaker {
+    state: BreakerState,
+    /// Whether each of the most recent requests succeeded, oldest first, only kept while closed.
+    outcomes: VecDeque<bool>,
+    /// The moment the breaker last opened.
+    opened_at: Instant,
+    /// Whether the probe of the half-open breaker is still waiting for its outcome.
+    probing: bool,
+    times_opened: u64,
+    bypassed: u64,
+}
+
+impl Breaker {
+    /// Returns the share of the requests in the window that failed.
+    fn failure_rate(&self) -> f64 {
+        if self.outcomes.is_empty() {
+            return 0.0;
+        }
+        let failures = self.outcomes.iter().filter(|success| !**success).count();
+        failures as f64 / self.outcomes.len() as f64
+    }
+}
+
+/// Struct that represents the circuit breaker of the controller channel.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    /// The event bus the transitions are published on.
+    events: Arc<EventBus>,
+    breaker: Mutex<Breaker>,
+}
+
+impl CircuitBreaker {
+    /// Initializes a new, closed CircuitBreaker.
+    ///
+    /// # Parameters
+    /// * 'config' - the configuration of when the breaker opens and how long it stays open.
+    /// * 'events' - the event bus the transitions are published on.
+    pub fn new(config: CircuitBreakerConfig, events: Arc<EventBus>) -> Self {
+        Self {
+            config,
+            events,
+            breaker: Mutex::new(Breaker {
+                state: BreakerState::Closed,
+                outcomes: VecDeque::new(),
+                opened_at: Instant::now(),
+                probing: false,
+                times_opened: 0,
+                bypassed: 0,
+            }),
+        }
+    }
+
+    /// Returns whether a message can be sent to the controller. Once the breaker has been open for the configured time,
+    /// it becomes half-open and lets this message through as the probe. Messages that can not be sent are counted.
+    pub fn allow(&self) -> bool {
+        let mut breaker = self.breaker.lock().unwrap();
+        let allowed = match breaker.state {
+            BreakerState::Closed => true,
+            BreakerState::Open
+                if breaker.opened_at.elapsed() >= Duration::from_millis(self.config.open_ms) =>
+            {
+                breaker.probing = true;
+                self.transition(&mut breaker, BreakerState::HalfOpen, "probing".to_string());
+                true
+            }
+            BreakerState::Open => false,
+            BreakerState::HalfOpen => {
+                !breaker.probing && {
+                    breaker.probing = true;
+                    true
+                }
+            }
+        };
+        if !allowed {
+            breaker.bypassed += 1;
+        }
+        allowed
+    }
+
+    /// Records the outcome of a request to the controller, which can open or close the breaker.
+    /// Outcomes of requests sent before the breaker opened are ignored.
+    ///
+    /// # Parameters
+    /// * 'success' - whether the controller decided in time.
+    pub fn record(&self, success: bool) {
+        let mut breaker = self.breaker.lock().unwrap();
+        match breaker.state {
+            BreakerState::Closed => {
+                breaker.outcomes.push_back(success);
+                if breaker.outcomes.len() > self.config.window.max(1) {
+                    breaker.outcomes.pop_front();
+                }
+                let failure_rate = breaker.failure_rate();
+                if breaker.outcomes.len() >= self.config.min_requests
+                    && failure_rate >= self.config.failure_threshold
+                {
+                    let reason = format!(
+                        "{:.0}% of the last {} requests failed",
+                        failure_rate * 100.0,
+                        breaker.outcomes.len()
+                    );
+                    self.open(&mut breaker, reason);
+                }
+            }
+            BreakerState::Open => {}
+            BreakerState::HalfOpen => {
+                breaker.probing = false;
+                if success {
+                    breaker.outcomes.clear();
+                    self.transition(
+                        &mut breaker,
+                        BreakerState::Closed,
+                        "probe succeeded".to_string(),
+                    );
+                } else {
+                    self.open(&mut breaker, "probe failed".to_string());
+                }
+            }
+        }
+    }
+
+    /// Returns the state and counters of the breaker.
+    pub fn summary(&self) -> BreakerSummary {
+        let breaker = self.breaker.lock().unwrap();
+        BreakerSummary {
+            state: breaker.state,
+            failure_rate: breaker.failure_rate(),
+            times_opened: breaker.times_opened,
+            bypassed: breaker.bypassed,
+        }
+    }
+
+    /// Opens the breaker.
+    ///
+    /// # Parameters
+    /// * 'breaker' - the locked breaker.
+    /// * 'reason' - why the breaker opens.
+    fn open(&self, breaker: &mut Breaker, reason: String) {
+        breaker.opened_at = Instant::now();
+        breaker.times_opened += 1;
+        breaker.outcomes.clear();
+        self.transition(breaker, BreakerState::Open, reason);
+    }
+
+    /// Changes the state of the breaker, logging the transition and publishing it as an event.
+    ///
+    /// # Parameters
+    /// * 'breaker' - the locked breaker.
+    /// * 'state' - the new state.
+    /// * 'reason' - why the state changes.
+    fn transition(&self, breaker: &mut Breaker, state: BreakerState, reason: String) {
+        match state {
+            BreakerState::Open => warn!(
+                "Opened the circuit breaker of the controller, forwarding without asking it: {}",
+                reason
+            ),
+            _ => info!(
+                "The circuit breaker of the controller is {}: {}",
+                state, reason
+            ),
+        }
+        breaker.state = state;
+        self.events.emit(EventKind::CircuitBreakerChanged {
+            state: state.to_string(),
+            reason,
+        });
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::circuit_breaker::{BreakerState, CircuitBreaker};
+    use crate::config::CircuitBreakerConfig;
+    use crate::event_bus::EventBus;
+    use std::sync::Arc;
+
+    fn breaker(open_ms: u64) -> CircuitBreaker {
+        CircuitBreaker::new(
+            CircuitBreakerConfig {
+                window: 4,
+                failure_threshold: 0.5,
+                min_requests: 4,
+                open_ms,
+            },
+            Arc::new(EventBus::default()),
+        )
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn opens_when_too_many_requests_fail() {
+        let breaker = breaker(60_000);
+        breaker.record(false);
+        breaker.record(false);
+        breaker.record(true);
+        // Not enough requests yet
+        assert_eq!(breaker.summary().state, BreakerState::Closed);
+        breaker.record(true);
+        assert_eq!(breaker.summary().state, BreakerState::Open);
+        assert!(!breaker.allow());
+        assert!(!breaker.allow());
+
+        let summary = breaker.summary();
+        assert_eq!(summary.times_opened, 1);
+        assert_eq!(summary.bypassed, 2);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn probes_when_half_open() {
+        let breaker = breaker(0);
+        for _ in 0..4 {
+            breaker.record(false);
+        }
+        assert_eq!(breaker.summary().state, BreakerState::Open);
+
+        // Only one probe at a time
+        assert!(breaker.allow());
+        assert!(!breaker.allow());
+        assert_eq!(breaker.summary().state, BreakerState::HalfOpen);
+        breaker.record(false);
+        assert_eq!(breaker.summary().state, BreakerState::Open);
+        assert_eq!(breaker.summary().times_opened, 2);
+
+        assert!(breaker.allow());
+        breaker.record(true);
+        assert_eq!(breaker.summary().state, BreakerState::Closed);
+        assert!(breaker.allow());
+    }
+}
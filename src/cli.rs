@@ -0,0 +1,285 @@
+//! This module is responsible for the command line of the interceptor: the options that apply to every run and the
+//! subcommands. Without a subcommand, the interceptor runs the network as configured, like with `run`.
+
+use crate::config::GrayFailureRuleConfig;
+use crate::gray_failure::GrayFailureRule;
+use crate::message_type::MessageType;
+use crate::run_id::RunId;
+use crate::run_seed::RunSeed;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The peer port of the first node, as the controller configures it by default.
+const DEFAULT_BASE_PORT_PEER: u32 = 60000;
+
+/// Struct that represents the parsed command line.
+#[derive(Debug, Parser)]
+#[command(
+    name = "rocket-interceptor",
+    version,
+    about = "Intercepts the messages between XRPL validator nodes in Docker and lets a controller decide on them"
+)]
+pub struct Cli {
+    /// The seed all randomness of the run is derived from, such that the run can be reproduced.
+    #[arg(long, global = true)]
+    pub seed: Option<RunSeed>,
+    /// The identifier of the run, generated if not given.
+    #[arg(long, global = true, value_parser = RunId::parse)]
+    pub run_id: Option<RunId>,
+    /// Removes the volumes of the nodes of a previous run, if their databases persist across runs.
+    #[arg(long, global = true)]
+    pub fresh: bool,
+    /// Applies a gray failure preset to a node or link, as <preset>@<node> or <preset>@<from_node>-<to_node>.
+    #[arg(long = "gray-failure", global = true, value_parser = GrayFailureRule::parse_option)]
+    pub gray_failures: Vec<GrayFailureRuleConfig>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Enum that represents the subcommands.
+#[derive(Debug, Clone, PartialEq, Subcommand)]
+pub enum Command {
+    /// Runs the network as configured, which is what happens without a subcommand.
+    Run,
+    /// Checks the configuration, the topology and the scheduled faults without starting any containers.
+    Validate {
+        /// The configuration file, `ROCKET_INTERCEPTOR_CONFIG` or interceptor.toml if not given.
+        config: Option<String>,
+        /// The amount of nodes the controller configures.
+        #[arg(long)]
+        nodes: u32,
+        /// The peer port of the first node the controller configures.
+        #[arg(long, default_value_t = DEFAULT_BASE_PORT_PEER)]
+        base_port: u32,
+    },
+    /// Feeds the messages of a capture file to the controller and prints its actions, without a network.
+    Replay {
+        /// The capture file, written by a run with the [capture_file] section.
+        capture: PathBuf,
+        /// The address of the controller, the first configured controller if not given.
+        #[arg(long)]
+        controller: Option<String>,
+        /// How many times faster than they were read the messages are fed, 0 feeds them without waiting.
+        #[arg(long, default_value_t = 1.0)]
+        speed: f64,
+    },
+    /// Prints the messages of a capture file decoded to their key fields, optionally only some of them.
+    Inspect {
+        /// The capture file, written by a run with the [capture_file] section.
+        capture: PathBuf,
+        /// Only the messages of a link, as <from_port>-<to_port>.
+        #[arg(long = "link", value_parser = parse_link)]
+        links: Vec<(u16, u16)>,
+        /// Only the messages sent or received by the node with this peer port.
+        #[arg(long = "port")]
+        ports: Vec<u16>,
+        /// Only the messages of this type, e.g. mtVALIDATION.
+        #[arg(long = "type")]
+        message_types: Vec<MessageType>,
+        /// Only the messages read at least this many seconds after the first message.
+        #[arg(long, value_parser = parse_seconds)]
+        from: Option<Duration>,
+        /// Only the messages read at most this many seconds after the first message.
+        #[arg(long, value_parser = parse_seconds)]
+        to: Option<Duration>,
+        /// Also prints every message whole in hex.
+        #[arg(long)]
+        hex: bool,
+    },
+    /// Removes the containers and networks left behind by runs that did not shut down, those of the run given with
+    /// `--run-id` or of every run.
+    Clean {
+        /// Removes the volumes of the nodes as well, which hold their databases if they persist across runs.
+        #[arg(long)]
+        volumes: bool,
+    },
+    /// Queries the database of a previous run.
+    Query {
+        /// The database, followed by the filters of the messages.
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Saves the network of the last run as a checkpoint. Requires the [persistence] section.
+    Checkpoint {
+        /// The name of the checkpoint.
+        name: String,
+    },
+    /// Returns the network to a checkpoint. Requires the [persistence] section.
+    Restore {
+        /// The name of the checkpoint.
+        name: String,
+    },
+    /// Attaches the terminal to the console of a running network. Requires the [console] section.
+    Console {
+        /// The address of the console, the configured one if not given.
+        #[arg(long)]
+        address: Option<String>,
+    },
+    /// Decides on the messages relayed by the previous interceptor in a chain, without setting up a network.
+    Relay,
+    /// Measures the overhead of the interception.
+    Bench,
+}
+
+/// Parses a link given with `--link`, as the peer ports of its nodes separated by a dash.
+///
+/// # Parameters
+/// * 'value' - the link, e.g. '60000-60001'.
+fn parse_link(value: &str) -> Result<(u16, u16), String> {
+    let invalid = || format!("'{}' is not a link, which is <from_port>-<to_port>", value);
+    let (from_port, to_port) = value.split_once('-').ok_or_else(invalid)?;
+    Ok((
+        from_port.parse().map_err(|_| invalid())?,
+        to_port.parse().map_err(|_| invalid())?,
+    ))
+}
+
+/// Parses a time given in seconds, which can be fractional.
+///
+/// # Parameters
+/// * 'value' - the amount of seconds, e.g. '12.5'.
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| format!("'{}' is not an amount of seconds", value))
+}
+
+/// Enum that represents what a run does with the network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunMode {
+    /// Sets up the network and intercepts its messages.
+    Network,
+    /// Decides on the messages relayed by the previous interceptor in a chain, see `relay`.
+    Relay,
+    /// Measures the overhead of the interception, see `bench::run`.
+    Bench,
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::cli::{Cli, Command};
+    use crate::message_type::MessageType;
+    use crate::run_id::RunId;
+    use crate::run_seed::RunSeed;
+    use clap::Parser;
+    use std::time::Duration;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn run_without_subcommand() {
+        let cli = Cli::try_parse_from([
+            "rocket-interceptor",
+            "--seed",
+            "42",
+            "--run-id=nightly-42",
+            "--fresh",
+            "--gray-failure",
+            "slow-leader@0",
+            "--gray-failure=lossy-wan@1-2",
+        ])
+        .unwrap();
+        assert_eq!(cli.command, None);
+        assert_eq!(cli.seed, Some(RunSeed(42)));
+        assert_eq!(cli.run_id, Some(RunId("nightly-42".to_string())));
+        assert!(cli.fresh);
+        assert_eq!(cli.gray_failures.len(), 2);
+        assert_eq!(cli.gray_failures[1].from_node, Some(1));
+
+        for invalid in [
+            ["rocket-interceptor", "--seed", "x"],
+            ["rocket-interceptor", "--run-id", "../x"],
+            ["rocket-interceptor", "--gray-failure", "unknown@1"],
+        ] {
+            assert!(Cli::try_parse_from(invalid).is_err());
+        }
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn subcommands() {
+        // The options of a run can also follow the subcommand
+        let cli = Cli::try_parse_from(["rocket-interceptor", "relay", "--seed=7"]).unwrap();
+        assert_eq!(cli.command, Some(Command::Relay));
+        assert_eq!(cli.seed, Some(RunSeed(7)));
+
+        let cli = Cli::try_parse_from(["rocket-interceptor", "validate", "--nodes", "5"]).unwrap();
+        assert_eq!(
+            cli.command,
+            Some(Command::Validate {
+                config: None,
+                nodes: 5,
+                base_port: 60000,
+            })
+        );
+        assert!(Cli::try_parse_from(["rocket-interceptor", "validate"]).is_err());
+
+        let cli = Cli::try_parse_from([
+            "rocket-interceptor",
+            "query",
+            "runs/session.db",
+            "--type",
+            "mtVALIDATION",
+            "--count",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.command,
+            Some(Command::Query {
+                args: vec![
+                    "runs/session.db".to_string(),
+                    "--type".to_string(),
+                    "mtVALIDATION".to_string(),
+                    "--count".to_string(),
+                ],
+            })
+        );
+
+        let cli = Cli::try_parse_from([
+            "rocket-interceptor",
+            "replay",
+            "capture.pcap",
+            "--speed",
+            "0",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Replay { speed, .. }) if speed == 0.0
+        ));
+
+        let cli = Cli::try_parse_from([
+            "rocket-interceptor",
+            "inspect",
+            "capture.pcap",
+            "--link",
+            "60000-60001",
+            "--type=mtVALIDATION",
+            "--type=33",
+            "--from",
+            "1.5",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Inspect { links, message_types, from, to: None, hex: false, .. })
+                if links == vec![(60000, 60001)]
+                    && message_types == vec![MessageType::Validation, MessageType::ProposeLedger]
+                    && from == Some(Duration::from_millis(1500))
+        ));
+        for invalid in [
+            ["--link", "60000"],
+            ["--from", "-1"],
+            ["--type", "mtNOTHING"],
+        ] {
+            assert!(Cli::try_parse_from(
+                ["rocket-interceptor", "inspect", "capture.pcap"]
+                    .into_iter()
+                    .chain(invalid)
+            )
+            .is_err());
+        }
+    }
+}
@@ -0,0 +1,69 @@
+//! This module is responsible for the time in which the delays of the messages and the schedules of the scenarios are
+//! measured. Both ask a `Clock` for the current moment and to wait, instead of asking the system, such that their timing
+//! can be tested deterministically.
+//!
+//! The clock of a run is the `TokioClock`, which follows the time of the tokio runtime. In a test whose runtime starts
+//! with paused time, e.g. `#[tokio::test(start_paused = true)]`, that time is virtual: it only advances when every task
+//! waits or when the test advances it, so a delay of minutes passes instantly, and at the same moments in every run.
+
+use futures_util::future::BoxFuture;
+use std::fmt;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Trait that represents a source of time.
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Returns the current moment.
+    fn now(&self) -> Instant;
+
+    /// Waits until a duration has passed.
+    ///
+    /// # Parameters
+    /// * 'duration' - how long to wait.
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+
+    /// Returns how long ago a moment was, or zero if it is not in the past.
+    ///
+    /// # Parameters
+    /// * 'moment' - the moment.
+    fn elapsed(&self, moment: Instant) -> Duration {
+        self.now().saturating_duration_since(moment)
+    }
+}
+
+/// Struct that represents the time of the tokio runtime, which is virtual if the runtime paused its time.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::clock::{Clock, TokioClock};
+    use std::time::Duration;
+
+    #[tokio::test(start_paused = true)]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn paused_time_is_virtual() {
+        let clock = TokioClock;
+        let start = clock.now();
+        clock.sleep(Duration::from_secs(3600)).await;
+        assert_eq!(clock.elapsed(start), Duration::from_secs(3600));
+
+        tokio::time::advance(Duration::from_millis(5)).await;
+        assert_eq!(clock.now() - start, Duration::from_millis(3600_005));
+        // A moment that is not in the past was zero ago, instead of underflowing
+        assert_eq!(
+            clock.elapsed(clock.now() + Duration::from_secs(1)),
+            Duration::ZERO
+        );
+    }
+}
@@ -0,0 +1,403 @@
+//! This module is responsible for the timeline of a run that interleaves the ledger closes of the nodes with the
+//! interception events, such that it shows which dropped and delayed messages preceded a slow round.
+//!
+//! The timeline follows the event bus, which carries the ledger closes reported by the ledger monitor, the dropped
+//! messages together with why they were dropped, and the changes to the links and the network. Delayed and duplicated
+//! messages are not published as events, so they are taken from the records of the handled messages through a sink.
+//! When the timeline is exported, every ledger close is annotated with the time since the previous close of its node,
+//! and with the amount of messages that were dropped, delayed or duplicated in between.
+
+use crate::config::CloseTimelineConfig;
+use crate::event_bus::{Event, EventKind};
+use crate::packet_timeline::PacketRecord;
+use crate::record_sink::RecordSink;
+use crate::run_id::RunId;
+use chrono::DateTime;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+
+/// Enum that represents what happened at a moment of the timeline.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TimelineItem {
+    /// A node closed a ledger.
+    LedgerClose {
+        node_id: u32,
+        ledger_index: u32,
+        /// The hash of the closed ledger in hex.
+        ledger_hash: String,
+        /// The amount of transactions in the ledger.
+        txn_count: u64,
+        /// The time in ms since the previous ledger close of the node, None for its first close in the timeline.
+        interval_ms: Option<u64>,
+        /// Whether the interval exceeded the threshold of a slow round.
+        slow: bool,
+        /// The amount of messages of all links dropped, delayed or duplicated since the previous close of the node.
+        impaired_since_previous: Option<u64>,
+    },
+    /// A message was delayed or duplicated.
+    Packet {
+        from_port: u16,
+        to_port: u16,
+        /// The position of the message on its link.
+        sequence: u64,
+        message_type: String,
+        /// The action taken: 'delay' or 'duplicate'.
+        action: &'static str,
+        delay_ms: u32,
+        send_amount: u32,
+    },
+    /// Any other event, e.g. a dropped message or a partition.
+    Event(EventKind),
+}
+
+impl TimelineItem {
+    /// Returns whether the item is a message that was dropped, delayed or duplicated.
+    fn is_impaired(&self) -> bool {
+        matches!(
+            self,
+            TimelineItem::Packet { .. } | TimelineItem::Event(EventKind::PacketDropped { .. })
+        )
+    }
+}
+
+/// Struct that represents a moment of the timeline.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TimelineEntry {
+    /// The wall-clock time of the entry, in nanoseconds since the UNIX epoch.
+    pub timestamp_ns: u64,
+    /// The wall-clock time of the entry, in RFC 3339 format.
+    pub timestamp: String,
+    #[serde(flatten)]
+    pub item: TimelineItem,
+}
+
+impl TimelineEntry {
+    /// Initializes a new TimelineEntry.
+    ///
+    /// # Parameters
+    /// * 'timestamp_ns' - the wall-clock time of the entry, in nanoseconds since the UNIX epoch.
+    /// * 'item' - what happened.
+    fn new(timestamp_ns: u64, item: TimelineItem) -> Self {
+        Self {
+            timestamp_ns,
+            timestamp: DateTime::from_timestamp_nanos(timestamp_ns as i64).to_rfc3339(),
+            item,
+        }
+    }
+}
+
+/// Struct that represents the entries that were added so far.
+#[derive(Debug, Default)]
+struct Entries {
+    /// The entries in the order they were added, which differs from the order of their moments.
+    entries: VecDeque<TimelineEntry>,
+    /// The amount of entries that were left out, because there were more than the capacity or the events were missed.
+    omitted: u64,
+}
+
+/// Struct that represents the timeline of ledger closes and interception events of a run.
+#[derive(Debug)]
+pub struct CloseTimeline {
+    config: CloseTimelineConfig,
+    entries: Mutex<Entries>,
+}
+
+impl CloseTimeline {
+    /// Initializes a new CloseTimeline without entries.
+    ///
+    /// # Parameters
+    /// * 'config' - the configuration of the timeline.
+    pub fn new(config: &CloseTimelineConfig) -> Self {
+        Self {
+            config: config.clone(),
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// Adds an entry, and leaves out the oldest entry if the timeline is full.
+    ///
+    /// # Parameters
+    /// * 'entry' - the entry.
+    fn add(&self, entry: TimelineEntry) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.entries.len() >= self.config.capacity.max(1) {
+            entries.entries.pop_front();
+            entries.omitted += 1;
+        }
+        entries.entries.push_back(entry);
+    }
+
+    /// Adds an event, a ledger close becomes an entry of its own.
+    ///
+    /// # Parameters
+    /// * 'event' - the event.
+    pub fn add_event(&self, event: &Event) {
+        let item = match &event.kind {
+            EventKind::LedgerClosed {
+                node_id,
+                ledger_index,
+                ledger_hash,
+                txn_count,
+            } => TimelineItem::LedgerClose {
+                node_id: *node_id,
+                ledger_index: *ledger_index,
+                ledger_hash: ledger_hash.clone(),
+                txn_count: *txn_count,
+                interval_ms: None,
+                slow: false,
+                impaired_since_previous: None,
+            },
+            kind => TimelineItem::Event(kind.clone()),
+        };
+        self.add(TimelineEntry::new(event.timestamp_ns, item));
+    }
+
+    /// Adds a handled message if it was delayed or duplicated. Dropped messages are added through their events.
+    ///
+    /// # Parameters
+    /// * 'record' - the record of the message.
+    pub fn add_record(&self, record: &PacketRecord) {
+        let action = record.action_name();
+        if action != "delay" && action != "duplicate" {
+            return;
+        }
+        self.add(TimelineEntry::new(
+            record.timestamp.timestamp_nanos_opt().unwrap_or(0) as u64,
+            TimelineItem::Packet {
+                from_port: record.from_port,
+                to_port: record.to_port,
+                sequence: record.sequence,
+                message_type: record.message_type.to_string(),
+                action,
+                delay_ms: record.action,
+                send_amount: record.send_amount,
+            },
+        ));
+    }
+
+    /// Adds every event published on the bus, until the bus is closed.
+    ///
+    /// # Parameters
+    /// * 'events' - the receiver of the events, subscribed before the links started.
+    pub async fn follow(self: Arc<Self>, mut events: broadcast::Receiver<Arc<Event>>) {
+        loop {
+            match events.recv().await {
+                Ok(event) => self.add_event(&event),
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("The close timeline missed {} events", missed);
+                    self.entries.lock().unwrap().omitted += missed;
+                }
+                Err(RecvError::Closed) => return,
+            }
+        }
+    }
+
+    /// Returns the entries in the order of their moments, with the ledger closes annotated, together with the amount of
+    /// entries that were left out.
+    pub fn entries(&self) -> (Vec<TimelineEntry>, u64) {
+        let (mut entries, omitted) = {
+            let entries = self.entries.lock().unwrap();
+            (
+                entries.entries.iter().cloned().collect::<Vec<_>>(),
+                entries.omitted,
+            )
+        };
+        entries.sort_by_key(|entry| entry.timestamp_ns);
+        // The moment of the previous close of every node, and the amount of impaired messages before it
+        let mut previous_closes: HashMap<u32, (u64, u64)> = HashMap::new();
+        let mut impaired = 0;
+        for entry in entries.iter_mut() {
+            if entry.item.is_impaired() {
+                impaired += 1;
+            }
+            if let TimelineItem::LedgerClose {
+                node_id,
+                interval_ms,
+                slow,
+                impaired_since_previous,
+                ..
+            } = &mut entry.item
+            {
+                if let Some((closed_at, impaired_before)) =
+                    previous_closes.insert(*node_id, (entry.timestamp_ns, impaired))
+                {
+                    let interval = entry.timestamp_ns.saturating_sub(closed_at) / 1_000_000;
+                    *interval_ms = Some(interval);
+                    *slow = interval > self.config.slow_close_ms;
+                    *impaired_since_previous = Some(impaired - impaired_before);
+                }
+            }
+        }
+        (entries, omitted)
+    }
+
+    /// Writes the timeline as JSON to a new file in the configured directory, and returns the path of that file.
+    ///
+    /// # Parameters
+    /// * 'run_id' - the identifier of the run, which names the file.
+    pub fn save(&self, run_id: &RunId) -> Result<PathBuf, Box<dyn Error>> {
+        let (entries, omitted) = self.entries();
+        if omitted > 0 {
+            warn!(
+                "{} entries were left out of the close timeline, consider a larger capacity",
+                omitted
+            );
+        }
+        fs::create_dir_all(&self.config.directory)?;
+        let path =
+            Path::new(&self.config.directory).join(format!("close-timeline-{}.json", run_id));
+        fs::write(&path, serde_json::to_string_pretty(&entries)?)?;
+        Ok(path)
+    }
+}
+
+/// Struct that represents the sink adding the delayed and duplicated messages to the close timeline.
+pub struct CloseTimelineSink(pub Arc<CloseTimeline>);
+
+impl RecordSink for CloseTimelineSink {
+    fn name(&self) -> String {
+        "close timeline".to_string()
+    }
+
+    fn write(&mut self, records: &[PacketRecord]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for record in records {
+            self.0.add_record(record);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::close_timeline::{CloseTimeline, TimelineItem};
+    use crate::config::CloseTimelineConfig;
+    use crate::event_bus::{Event, EventKind};
+    use crate::message_type::MessageType;
+    use crate::packet_timeline::PacketRecord;
+    use chrono::DateTime;
+
+    const SECOND_NS: u64 = 1_000_000_000;
+
+    fn ledger_closed(timestamp_ns: u64, node_id: u32, ledger_index: u32) -> Event {
+        Event {
+            timestamp_ns,
+            kind: EventKind::LedgerClosed {
+                node_id,
+                ledger_index,
+                ledger_hash: format!("{:064X}", ledger_index),
+                txn_count: 0,
+            },
+        }
+    }
+
+    fn dropped(timestamp_ns: u64) -> Event {
+        Event {
+            timestamp_ns,
+            kind: EventKind::packet_dropped(
+                60000,
+                60001,
+                MessageType::Validation,
+                Some(1),
+                "controller",
+            ),
+        }
+    }
+
+    fn record(timestamp_ns: u64, action: u32, send_amount: u32) -> PacketRecord {
+        PacketRecord {
+            timestamp: DateTime::from_timestamp_nanos(timestamp_ns as i64),
+            from_port: 60001,
+            to_port: 60002,
+            sequence: 3,
+            message_type: MessageType::ProposeLedger,
+            action,
+            send_amount,
+            ..Default::default()
+        }
+    }
+
+    fn timeline(capacity: usize) -> CloseTimeline {
+        CloseTimeline::new(&CloseTimelineConfig {
+            capacity,
+            slow_close_ms: 5000,
+            ..CloseTimelineConfig::default()
+        })
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn ledger_closes_are_interleaved_with_impairments() {
+        let timeline = timeline(100);
+        timeline.add_event(&ledger_closed(SECOND_NS, 0, 10));
+        timeline.add_event(&ledger_closed(4 * SECOND_NS, 0, 11));
+        timeline.add_event(&ledger_closed(12 * SECOND_NS, 0, 12));
+        // The records arrive later than the events, but are ordered by the moment they were read
+        timeline.add_event(&dropped(5 * SECOND_NS));
+        timeline.add_record(&record(6 * SECOND_NS, 2000, 1));
+        timeline.add_record(&record(7 * SECOND_NS, 0, 2));
+        timeline.add_record(&record(8 * SECOND_NS, 0, 1));
+        timeline.add_record(&record(9 * SECOND_NS, 0, 0));
+
+        let (entries, omitted) = timeline.entries();
+        assert_eq!(omitted, 0);
+        let kinds: Vec<&str> = entries
+            .iter()
+            .map(|entry| match &entry.item {
+                TimelineItem::LedgerClose { .. } => "close",
+                TimelineItem::Packet { action, .. } => *action,
+                TimelineItem::Event(_) => "event",
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            ["close", "close", "event", "delay", "duplicate", "close"]
+        );
+        let closes: Vec<(Option<u64>, bool, Option<u64>)> = entries
+            .iter()
+            .filter_map(|entry| match entry.item {
+                TimelineItem::LedgerClose {
+                    interval_ms,
+                    slow,
+                    impaired_since_previous,
+                    ..
+                } => Some((interval_ms, slow, impaired_since_previous)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            closes,
+            [
+                (None, false, None),
+                (Some(3000), false, Some(0)),
+                (Some(8000), true, Some(3)),
+            ]
+        );
+
+        let json = serde_json::to_value(&entries[2]).unwrap();
+        assert_eq!(json["kind"], "event");
+        assert_eq!(json["event"], "packet_dropped");
+        assert_eq!(json["reason"], "controller");
+        assert_eq!(json["timestamp_ns"], 5 * SECOND_NS);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn the_oldest_entries_are_left_out() {
+        let timeline = timeline(2);
+        for i in 0..5 {
+            timeline.add_event(&ledger_closed(i * SECOND_NS, 1, i as u32));
+        }
+        let (entries, omitted) = timeline.entries();
+        assert_eq!(omitted, 3);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].timestamp_ns, 3 * SECOND_NS);
+    }
+}
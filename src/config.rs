@@ -0,0 +1,2920 @@
+//! This module is responsible for loading the local configuration of the interceptor.
+//!
+//! The network configuration itself is still provided by the controller, this file only contains
+//! settings for functionality that lives entirely inside the interceptor.
+
+use crate::field_mutation::FieldMutation;
+use crate::gray_failure::Preset;
+use crate::manifest::ManifestAction;
+use crate::squelch::SquelchAction;
+use crate::traffic_shaping::ShapingAction;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+/// The environment variable that can be used to point to a different configuration file.
+const CONFIG_PATH_ENV: &str = "ROCKET_INTERCEPTOR_CONFIG";
+/// The path of the configuration file that is used if the environment variable is not set.
+const DEFAULT_CONFIG_PATH: &str = "interceptor.toml";
+/// The highest rate of the transaction generator, at which a transaction is submitted every nanosecond.
+const MAX_TX_RATE: f64 = 1e9;
+
+/// Struct that represents the local configuration of the interceptor.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct InterceptorConfig {
+    /// The configuration of the logging output.
+    pub logging: LoggingConfig,
+    /// The networks run at the same time, each with its own configuration and controller. If empty, the network of
+    /// this configuration is run.
+    pub tenants: Vec<TenantConfig>,
+    /// The Docker labels of the containers and volumes of the run, besides the run ID label.
+    pub labels: BTreeMap<String, String>,
+    /// The configuration of the addresses the nodes are reached on.
+    pub addressing: AddressingConfig,
+    /// The configuration of the OpenTelemetry export, if spans should be exported.
+    pub opentelemetry: Option<OpenTelemetryConfig>,
+    /// The configuration of the transaction generator, if it should be run.
+    pub tx_generator: Option<TxGeneratorConfig>,
+    /// The configuration of the assertion engine, if properties should be checked.
+    pub assertions: Option<AssertionConfig>,
+    /// The configuration of the benchmark, only used when running the `bench` subcommand.
+    pub bench: BenchConfig,
+    /// The configuration of the queues between the read, decision and write stages of every link.
+    pub queues: QueueConfig,
+    /// The configuration of the truncation of large messages sent to the controller, if they should be truncated.
+    pub truncation: Option<TruncationConfig>,
+    /// The configuration of the messages that are too large to be held whole for a decision.
+    pub large_messages: LargeMessageConfig,
+    /// The configuration of the frames that violate the peer protocol.
+    pub malformed_frames: MalformedFrameConfig,
+    /// The configuration of which message types are sent to the controller.
+    pub interception: InterceptionConfig,
+    /// The configuration of the requests to the controller.
+    pub controller: ControllerConfig,
+    /// The configuration of the local handling of mtPING messages, if the interceptor should answer them itself.
+    pub keepalive: Option<KeepaliveConfig>,
+    /// The configuration of the headers sent in the handshake with the nodes.
+    pub handshake: HandshakeConfig,
+    /// The configuration of the timeouts of the connections with the nodes.
+    pub timeouts: TimeoutConfig,
+    /// The configuration of the TLS sessions with the nodes.
+    pub tls: TlsConfig,
+    /// The configuration of the WebSocket endpoint streaming link-state events, if it should be served.
+    pub events: Option<EventsConfig>,
+    /// The configuration of the live terminal dashboard, if it should be shown.
+    pub dashboard: Option<DashboardConfig>,
+    /// The configuration of the admin HTTP API, if it should be served.
+    pub admin: Option<AdminConfig>,
+    /// The configuration of the nemesis API Jepsen-style harnesses inject faults through, if it should be served.
+    pub nemesis: Option<NemesisConfig>,
+    /// The configuration of the console an experiment is steered through by hand, if it should be served.
+    pub console: Option<ConsoleConfig>,
+    /// The configuration of the gRPC service the controller can query the run through, if it should be served.
+    pub grpc_server: Option<GrpcServerConfig>,
+    /// The configuration of the heartbeats sent to the controller, if they should be sent.
+    pub heartbeat: Option<HeartbeatConfig>,
+    /// The configuration of the stats of the node containers sent to the controller, if they should be sent.
+    pub container_stats: Option<ContainerStatsConfig>,
+    /// The configuration of the next interceptor in the chain, if messages should be relayed to it.
+    pub relay: Option<RelayConfig>,
+    /// The configuration of the forwarding on all links when the interceptor starts.
+    pub forwarding: ForwardingConfig,
+    /// The configuration of the startup of the containers of the network.
+    pub startup: StartupConfig,
+    /// The configuration of the host ports the containers of the nodes are published on.
+    pub ports: PortConfig,
+    /// The configuration of the SQLite database the handled messages of a run are stored in, if they should be stored.
+    pub storage: Option<StorageConfig>,
+    /// The configuration of the file the metadata and decisions of handled messages are exported to, if they should be exported.
+    pub export: Option<ExportConfig>,
+    /// The configuration of the Kafka topic or NATS subject handled messages are published to, if they should be published.
+    pub stream: Option<StreamConfig>,
+    /// The configuration of the timeline of ledger closes and interception events, if it should be written.
+    pub close_timeline: Option<CloseTimelineConfig>,
+    /// The configuration of the detectors of anomalies in the traffic, if the traffic should be watched.
+    pub anomaly: Option<AnomalyConfig>,
+    /// The configuration of the passive observation mode, if messages should only be observed.
+    pub passive: Option<PassiveConfig>,
+    /// The configuration of the warm-up and cool-down around the fault window, if messages should be passed through
+    /// untouched before and after it.
+    pub phases: Option<PhaseConfig>,
+    /// The configuration of the analysis of duplicate broadcasts, if the copies of broadcasts should be tracked.
+    pub broadcasts: Option<BroadcastConfig>,
+    /// The configuration of the capture file handled messages are written to as they were read, if they should be captured.
+    pub capture_file: Option<CaptureFileConfig>,
+    /// The conditions under which the run ends by itself, if it should not run until it is interrupted.
+    pub termination: Option<TerminationConfig>,
+    /// The configuration of the summary of the run that is produced at shutdown.
+    pub summary: SummaryConfig,
+    /// The configuration of the result of the run as reported to CI.
+    pub ci: CiConfig,
+    /// The configuration of the bundle of diagnostics collected when a run fails.
+    pub crash_bundle: CrashBundleConfig,
+    /// The configuration of the proxy in front of the public WebSocket port of every node, if clients should be intercepted.
+    pub websocket_proxy: Option<WebSocketProxyConfig>,
+    /// The configuration of the proxy in front of the JSON-RPC port of every node, if clients should be intercepted.
+    pub rpc_proxy: Option<RpcProxyConfig>,
+    /// The roles of the nodes that do not validate, if any.
+    pub roles: Option<RoleConfig>,
+    /// The configuration of the shadow node every message delivered to an observed node is mirrored to, if any.
+    pub shadow: Option<ShadowConfig>,
+    /// The amendments the nodes vote for or against, and the monitoring of their voting, if any.
+    pub amendments: Option<AmendmentConfig>,
+    /// The configuration of the validator list publisher the nodes get their UNL from, if the UNL should be published.
+    pub validator_list: Option<ValidatorListConfig>,
+    /// The configuration of the pairs of nodes that get a link, if not every pair that shares a partition should.
+    pub topology: Option<TopologyConfig>,
+    /// The configuration of the eclipse of a victim node, if a node should be eclipsed during the run.
+    pub eclipse: Option<EclipseConfig>,
+    /// The memory and CPU limits of the containers of the nodes, if they should be limited.
+    pub resources: Option<ResourceConfig>,
+    /// The configuration of the CPU throttling of nodes during the run, if nodes should be throttled.
+    pub cpu_throttle: Option<CpuThrottleConfig>,
+    /// The configuration of the skewed clocks of nodes, if the clocks of nodes should disagree.
+    pub clock_skew: Option<ClockSkewConfig>,
+    /// The configuration of the volumes the databases of the nodes are kept in, if they should persist across runs.
+    pub persistence: Option<PersistenceConfig>,
+    /// The configuration of the Sybil peers the interceptor pretends to be towards a target node, if any.
+    pub sybil: Option<SybilConfig>,
+    /// The configuration of the links that are periodically disconnected and reconnected, if any.
+    pub flapping: Option<FlappingConfig>,
+    /// The configuration of the links that are cut in one direction only during the run, if any.
+    pub one_way_partition: Option<OneWayPartitionConfig>,
+    /// The configuration of the links on which the relay of transactions is suppressed during the run, if any.
+    pub tx_suppression: Option<TxSuppressionConfig>,
+    /// The message types that are dropped per node from the start of the run, if any.
+    pub blackhole: Option<BlackholeConfig>,
+    /// The configuration of the messages that are captured and replayed later, if messages should be replayed.
+    pub replay: Option<ReplayConfig>,
+    /// The rules by which messages are mutated locally from the start of the run, if any.
+    pub mutation: Option<MutationConfig>,
+    /// The rules that limit the rate of message types per link from the start of the run, if any.
+    pub shaping: Option<ShapingConfig>,
+    /// The gray failure presets applied to nodes and links from the start of the run, if any.
+    pub gray_failure: Option<GrayFailureConfig>,
+    /// The rules by which the manifests of nodes are rewritten from the start of the run, if any.
+    pub manifests: Option<ManifestConfig>,
+    /// The rules by which the squelches of validators are overridden from the start of the run, if any.
+    pub squelch: Option<SquelchConfig>,
+    /// The rules by which the proposals of nodes are equivocated from the start of the run, if any.
+    pub equivocation: Option<EquivocationConfig>,
+    /// The base latency of every link by the nodes it connects, if the links should emulate geographic distances.
+    pub latency: Option<LatencyConfig>,
+    /// The WASM plugins that decide on every sent message, if any.
+    pub plugins: Option<PluginConfig>,
+    /// The configuration of the reloading of the local rules when their file changes, if they should be reloaded.
+    pub hot_reload: Option<HotReloadConfig>,
+}
+
+/// Enum that represents the format of the log output.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines, one per event.
+    #[default]
+    Text,
+    /// One JSON object per event, including the fields of all spans it happened in.
+    Json,
+}
+
+/// Struct that represents the configuration of the logging output.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// The format of the log output.
+    pub format: LogFormat,
+    /// The log level or filter directives, used if `RUST_LOG` is not set.
+    pub level: String,
+    /// The file the logs are appended to. Logs are written to stderr if not set.
+    pub file: Option<String>,
+    /// Whether an event is logged when a span closes, which includes how long it was active.
+    pub span_events: bool,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Text,
+            level: "error".to_string(),
+            file: None,
+            span_events: false,
+        }
+    }
+}
+
+/// Struct that represents the configuration of the OpenTelemetry (OTLP) export of spans.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct OpenTelemetryConfig {
+    /// The gRPC endpoint of the OTLP collector.
+    pub endpoint: String,
+    /// The service name the spans are exported under.
+    pub service_name: String,
+    /// The most verbose level of spans that are exported.
+    pub level: String,
+}
+
+impl Default for OpenTelemetryConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: "http://localhost:4317".to_string(),
+            service_name: "rocket-interceptor".to_string(),
+            level: "info".to_string(),
+        }
+    }
+}
+
+/// Struct that represents the configuration of the transaction generator.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct TxGeneratorConfig {
+    /// The amount of transactions submitted per second, across all nodes.
+    pub rate: f64,
+    /// The amount of accounts that are created and funded before the traffic starts.
+    pub accounts: u32,
+    /// For how long transactions are submitted, in seconds. Runs until shutdown if not set.
+    pub duration_secs: Option<u64>,
+    /// How long to wait after the network is set up before submitting transactions, in seconds.
+    pub start_delay_secs: u64,
+    /// The amount of drops each generated account is funded with.
+    pub funding_drops: u64,
+    /// The amount of drops that is sent with every generated payment.
+    pub payment_drops: u64,
+}
+
+impl Default for TxGeneratorConfig {
+    fn default() -> Self {
+        Self {
+            rate: 1.0,
+            accounts: 5,
+            duration_secs: None,
+            start_delay_secs: 5,
+            funding_drops: 1_000_000_000,
+            payment_drops: 1_000,
+        }
+    }
+}
+
+impl TxGeneratorConfig {
+    /// Checks that the rate is a positive, finite amount of transactions per second, and that the time between two
+    /// transactions is at least a nanosecond.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.rate.is_finite() || self.rate <= 0.0 {
+            return Err(format!(
+                "rate {} has to be a positive number of transactions per second",
+                self.rate
+            ));
+        }
+        if self.rate > MAX_TX_RATE {
+            return Err(format!(
+                "rate {} can not be higher than {} transactions per second",
+                self.rate, MAX_TX_RATE
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Struct that represents the configuration of the assertion engine.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct AssertionConfig {
+    /// How often the validated ledger of every node is polled, in milliseconds.
+    pub poll_interval_ms: u64,
+    /// The maximum time between two advances of the validated ledger index of a node, in seconds.
+    pub max_ledger_interval_secs: u64,
+    /// The IDs of the nodes that are not considered honest, they are excluded from the agreement
+    /// and progress properties.
+    pub byzantine_nodes: Vec<u32>,
+    /// The amount of intercepted messages preceding a violation that are included in its report.
+    pub timeline_packets: usize,
+}
+
+impl Default for AssertionConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval_ms: 1000,
+            max_ledger_interval_secs: 30,
+            byzantine_nodes: Vec::new(),
+            timeline_packets: 50,
+        }
+    }
+}
+
+/// Enum that represents what happens when a message is pushed to a full queue.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Wait until there is space, which stops reading from the link until the controller catches up.
+    #[default]
+    Block,
+    /// Drop the oldest message in the queue to make space.
+    DropOldest,
+    /// Spill messages to disk until the controller catches up. Only messages waiting for a decision are spilled,
+    /// the other queues block.
+    SpillToDisk,
+}
+
+/// Struct that represents the configuration of the queues between the read, decision and write stages.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct QueueConfig {
+    /// The maximum amount of messages in every queue.
+    pub capacity: usize,
+    /// What happens when a message is pushed to a full queue.
+    pub overflow: OverflowPolicy,
+    /// How often the depth of every queue is logged, in seconds. Not logged if 0.
+    pub gauge_interval_secs: u64,
+    /// The directory where spilled messages are stored, with a subdirectory per link.
+    pub spill_directory: String,
+    /// The size in bytes after which a new segment file is started when spilling.
+    pub segment_size_bytes: u64,
+    /// Whether the delayed messages of a link are released in the order they were read, instead of as soon as their
+    /// own delay has passed.
+    pub preserve_order: bool,
+    /// Which messages are written first when messages to a node wait in the queue of the write stage.
+    pub write_priority: WritePriority,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            overflow: OverflowPolicy::Block,
+            gauge_interval_secs: 10,
+            spill_directory: "spill".to_string(),
+            segment_size_bytes: 64 * 1024 * 1024,
+            preserve_order: false,
+            write_priority: WritePriority::Fifo,
+        }
+    }
+}
+
+/// Enum that represents which messages are written first when the queue of a write stage is congested.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WritePriority {
+    /// The messages are written in the order they were handled.
+    #[default]
+    Fifo,
+    /// Proposals and validations are written before the other messages, and relayed transactions and ledger data last.
+    ConsensusFirst,
+    /// Relayed transactions and ledger data are written before the other messages, and proposals and validations last.
+    ConsensusLast,
+}
+
+/// Enum that represents how messages of a certain type are handled.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum InterceptionMode {
+    /// The controller decides what happens to the message.
+    #[default]
+    Intercept,
+    /// The message is forwarded immediately, and a copy is sent to the controller without waiting for its decision.
+    Mirror,
+    /// The message is forwarded immediately and never sent to the controller.
+    Passthrough,
+}
+
+/// Struct that represents the configuration of which message types are sent to the controller.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct InterceptionConfig {
+    /// The mode of all message types that are not configured explicitly.
+    pub default: InterceptionMode,
+    /// The mode per message type, by the name used by rippled (e.g. 'mtPING') or by numeric value.
+    pub message_types: HashMap<String, InterceptionMode>,
+    /// The connections of which only one direction is intercepted, as pairs of node IDs, e.g. [[0, 1]] to intercept the
+    /// messages from node 0 to 1 and forward those from 1 to 0 as-is.
+    pub one_way: Vec<[u32; 2]>,
+}
+
+/// Enum that represents the action applied to a message the controller did not decide on in time.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeoutAction {
+    /// The message is forwarded unchanged.
+    #[default]
+    Forward,
+    /// The message is dropped.
+    Drop,
+}
+
+/// Struct that represents the configuration of the requests to the controller.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ControllerConfig {
+    /// The addresses of the controllers. The links are spread over all of them, and the first one also sets up the
+    /// network and receives the reports. If empty, the controller at the default address is used.
+    pub endpoints: Vec<String>,
+    /// The amount of channels to every controller. The links are spread over the channels, such that the messages of
+    /// independent links are decided on in parallel.
+    pub channels: usize,
+    /// How long a controller that became unreachable is not assigned links, in ms.
+    pub failover_retry_ms: u64,
+    /// How long the controller can take to decide on a message, in ms, 0 to wait until it decides.
+    pub decision_timeout_ms: u64,
+    /// The action applied to a message the controller did not decide on in time.
+    pub timeout_action: TimeoutAction,
+    /// The configuration of the circuit breaker, if messages should bypass the controller while it is unhealthy.
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+    /// The compression of the intercepted messages sent to the controller and of its decisions.
+    pub compression: CompressionConfig,
+}
+
+impl Default for ControllerConfig {
+    fn default() -> Self {
+        Self {
+            endpoints: Vec::new(),
+            channels: 1,
+            failover_retry_ms: 10_000,
+            decision_timeout_ms: 0,
+            timeout_action: TimeoutAction::default(),
+            circuit_breaker: None,
+            compression: CompressionConfig::default(),
+        }
+    }
+}
+
+/// Enum that represents the compression of the messages exchanged with the controller.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// The messages are not compressed.
+    #[default]
+    None,
+    /// The messages are compressed with gzip.
+    Gzip,
+    /// The messages are compressed with zstd.
+    Zstd,
+}
+
+/// Struct that represents the configuration of the compression of the intercepted messages sent to the controller, and
+/// of the decisions it sends back, per direction.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct CompressionConfig {
+    /// The compression of the intercepted messages sent to the controller.
+    pub requests: Compression,
+    /// The compression the controller is allowed to use for its decisions.
+    pub responses: Compression,
+}
+
+/// Struct that represents the configuration of the circuit breaker of the controller channel.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct CircuitBreakerConfig {
+    /// The amount of most recent requests the failure rate is computed over.
+    pub window: usize,
+    /// The share of failed or timed out requests in the window at which the breaker opens, between 0 and 1.
+    pub failure_threshold: f64,
+    /// The minimum amount of requests in the window before the breaker can open.
+    pub min_requests: usize,
+    /// How long the breaker stays open before the controller is probed, in ms.
+    pub open_ms: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            window: 20,
+            failure_threshold: 0.5,
+            min_requests: 10,
+            open_ms: 5000,
+        }
+    }
+}
+
+/// Struct that represents the configuration of the truncation of large messages sent to the controller.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct TruncationConfig {
+    /// Messages larger than this amount of bytes are truncated.
+    pub threshold_bytes: usize,
+    /// The amount of bytes at the start of a truncated message that are still sent.
+    pub preview_bytes: usize,
+}
+
+impl Default for TruncationConfig {
+    fn default() -> Self {
+        Self {
+            threshold_bytes: 64 * 1024,
+            preview_bytes: 256,
+        }
+    }
+}
+
+/// Enum that represents how messages that are too large to be held whole are handled. They are always forwarded
+/// unchanged, part by part as they are read, unless their link is cut.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OversizedPolicy {
+    /// The first bytes, the SHA-256 digest and the length of the message are sent to the controller once it was
+    /// forwarded, without waiting for a decision.
+    #[default]
+    TruncateToController,
+    /// The controller never sees the message.
+    Passthrough,
+}
+
+/// Struct that represents the configuration of the messages that are too large to be held whole for a decision.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct LargeMessageConfig {
+    /// The size in bytes, including the header, of the largest message that is held whole. Larger messages are
+    /// forwarded part by part.
+    pub max_buffered_bytes: usize,
+    /// How messages above the cap are handled.
+    pub oversized: OversizedPolicy,
+    /// The amount of bytes at the start of a message above the cap that are sent to the controller.
+    pub preview_bytes: usize,
+}
+
+impl Default for LargeMessageConfig {
+    fn default() -> Self {
+        Self {
+            // The largest payload size the 26 bits of the header allow, such that every message is held whole
+            max_buffered_bytes: 64 * 1024 * 1024 + 6,
+            oversized: OversizedPolicy::TruncateToController,
+            preview_bytes: 256,
+        }
+    }
+}
+
+/// Enum that represents what happens to a link once a frame that violates the peer protocol was read from it.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MalformedAction {
+    /// The frame is not forwarded, and the link continues with the next frame.
+    #[default]
+    Drop,
+    /// Nothing is read from the link anymore, as rippled disconnects a peer that sends such a frame.
+    KillLink,
+}
+
+/// Struct that represents the configuration of the frames that violate the peer protocol. Such frames are stored in
+/// the quarantine directory as they were read, and never forwarded.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct MalformedFrameConfig {
+    /// The size in bytes of the largest payload that is allowed, larger frames are malformed.
+    pub max_payload_bytes: usize,
+    /// What happens to the link a malformed frame was read from.
+    pub action: MalformedAction,
+    /// The directory where malformed frames are stored, with a subdirectory per link.
+    pub quarantine_directory: String,
+}
+
+impl Default for MalformedFrameConfig {
+    fn default() -> Self {
+        Self {
+            // The largest message rippled accepts
+            max_payload_bytes: 64 * 1024 * 1024,
+            action: MalformedAction::Drop,
+            quarantine_directory: "quarantine".to_string(),
+        }
+    }
+}
+
+/// Struct that represents the configuration of the local handling of mtPING messages.
+/// Pings are answered by the interceptor on the leg they were read from instead of being forwarded,
+/// such that delays induced by the controller do not cause nodes to disconnect.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct KeepaliveConfig {
+    /// How often the interceptor sends its own ping to every node on every leg, in seconds. 0 disables them.
+    pub ping_interval_secs: u64,
+}
+
+impl Default for KeepaliveConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval_secs: 10,
+        }
+    }
+}
+
+/// Struct that represents the configuration of the headers sent in the handshake with the nodes.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct HandshakeConfig {
+    /// The protocol versions offered in the Upgrade header, in order of preference, e.g. 'XRPL/2.2'.
+    pub protocols: Vec<String>,
+    /// The ID of the network sent in the Network-ID header, the header is omitted if not set.
+    pub network_id: Option<u32>,
+    /// Whether the Crawl header allows the nodes to crawl the peer the interceptor pretends to be.
+    pub crawl: bool,
+    /// The hash sent in the Closed-Ledger header. Taken from the node the interceptor pretends to be if not set.
+    pub closed_ledger: Option<String>,
+    /// The hash sent in the Previous-Ledger header. Taken from the node the interceptor pretends to be if not set.
+    pub previous_ledger: Option<String>,
+    /// The amount of times a handshake that failed temporarily, e.g. because the peer slots are full, is retried.
+    pub retries: u32,
+    /// The time to wait before the first retry in ms, which doubles after every retry.
+    pub retry_backoff_ms: u64,
+    /// The longest time to wait before a retry in ms.
+    pub max_retry_backoff_ms: u64,
+}
+
+impl Default for HandshakeConfig {
+    fn default() -> Self {
+        Self {
+            protocols: vec!["XRPL/2.2".to_string(), "XRPL/2.1".to_string()],
+            network_id: None,
+            crawl: false,
+            closed_ledger: None,
+            previous_ledger: None,
+            retries: 5,
+            retry_backoff_ms: 500,
+            max_retry_backoff_ms: 10000,
+        }
+    }
+}
+
+/// Struct that represents the configuration of the timeouts of the connections with the nodes.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct TimeoutConfig {
+    /// The timeout of establishing a TCP connection, in ms.
+    pub connect_ms: u64,
+    /// The timeout of the TLS handshake, in ms.
+    pub tls_handshake_ms: u64,
+    /// The timeout of sending the upgrade request and receiving its response, in ms.
+    pub upgrade_ms: u64,
+    /// After how many seconds without data from a node a warning is logged for the link, 0 disables it.
+    pub idle_read_secs: u64,
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        Self {
+            connect_ms: 5000,
+            tls_handshake_ms: 5000,
+            upgrade_ms: 10000,
+            idle_read_secs: 60,
+        }
+    }
+}
+
+/// Struct that represents the configuration of the startup of the containers of the network.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct StartupConfig {
+    /// The maximum amount of node containers that are created and started at the same time.
+    pub parallelism: usize,
+}
+
+impl Default for StartupConfig {
+    fn default() -> Self {
+        Self { parallelism: 8 }
+    }
+}
+
+/// Enum that represents what happens when the ports of the nodes provided by the controller can not be used.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PortAllocation {
+    /// The run fails before any container is started.
+    #[default]
+    Check,
+    /// Free ranges of ports are picked instead, and reported to the controller with the nodes.
+    Auto,
+}
+
+/// Struct that represents the configuration of the host ports the containers of the nodes are published on.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct PortConfig {
+    /// What happens when the ports provided by the controller are in use or overlap.
+    pub allocation: PortAllocation,
+    /// The first port that may be allocated.
+    pub search_from: u16,
+    /// The last port that may be allocated.
+    pub search_to: u16,
+}
+
+impl Default for PortConfig {
+    fn default() -> Self {
+        PortConfig {
+            allocation: PortAllocation::Check,
+            search_from: 20000,
+            search_to: 32767,
+        }
+    }
+}
+
+impl PortConfig {
+    /// Checks that the ports that may be allocated form a range.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.search_from == 0 || self.search_from > self.search_to {
+            return Err(format!(
+                "search_from {} has to be a port up to search_to {}",
+                self.search_from, self.search_to
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Enum that represents a version of TLS.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TlsVersion {
+    #[serde(rename = "tls1.0")]
+    Tls10,
+    #[serde(rename = "tls1.1")]
+    Tls11,
+    #[serde(rename = "tls1.2")]
+    Tls12,
+    #[serde(rename = "tls1.3")]
+    Tls13,
+}
+
+/// Struct that represents the configuration of the TLS sessions with the nodes.
+/// Settings that are not set use the defaults of the TLS backend.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct TlsConfig {
+    /// The lowest TLS version that is offered.
+    pub min_version: Option<TlsVersion>,
+    /// The highest TLS version that is offered.
+    pub max_version: Option<TlsVersion>,
+    /// The cipher suites offered for TLS 1.2 and lower, in OpenSSL's cipher list format.
+    pub cipher_list: Option<String>,
+    /// The cipher suites offered for TLS 1.3, separated by ':', e.g. 'TLS_AES_256_GCM_SHA384'.
+    pub ciphersuites: Option<String>,
+    /// The protocols offered with ALPN, in order of preference. ALPN is not used if empty.
+    pub alpn: Vec<String>,
+    /// Whether the certificate of the nodes is verified against the certificates in 'ca_file'.
+    pub verify: bool,
+    /// The PEM file with the trusted certificates, used if 'verify' is enabled.
+    pub ca_file: Option<String>,
+    /// Whether the certificate of every node is logged after the TLS handshake.
+    pub log_certificates: bool,
+}
+
+/// Struct that represents the configuration of the benchmark measuring the interception overhead.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct BenchConfig {
+    /// How long to wait after the network is set up before measuring, in seconds.
+    pub warmup_secs: u64,
+    /// How long the traffic is measured, both with and without interception, in seconds.
+    pub phase_secs: u64,
+    /// How long the controller is flooded with messages to measure its maximum throughput, in seconds.
+    pub throughput_secs: u64,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            warmup_secs: 10,
+            phase_secs: 30,
+            throughput_secs: 10,
+        }
+    }
+}
+
+/// Struct that represents the configuration of the WebSocket endpoint streaming link-state events.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct EventsConfig {
+    /// The address the WebSocket endpoint listens on.
+    pub websocket_address: String,
+}
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        Self {
+            websocket_address: "127.0.0.1:8765".to_string(),
+        }
+    }
+}
+
+/// Struct that represents the configuration of the live terminal dashboard.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct DashboardConfig {
+    /// The time between two redraws of the dashboard in ms.
+    pub refresh_ms: u64,
+    /// The length of the window over which the messages per second are calculated, in seconds.
+    pub rate_window_secs: u64,
+    /// The amount of recently handled messages shown.
+    pub recent_decisions: usize,
+}
+
+impl Default for DashboardConfig {
+    fn default() -> Self {
+        Self {
+            refresh_ms: 500,
+            rate_window_secs: 5,
+            recent_decisions: 20,
+        }
+    }
+}
+
+/// Struct that represents the configuration of the admin HTTP API.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct AdminConfig {
+    /// The address the admin API listens on.
+    pub address: String,
+}
+
+impl Default for AdminConfig {
+    fn default() -> Self {
+        Self {
+            address: "127.0.0.1:8080".to_string(),
+        }
+    }
+}
+
+/// Struct that represents the configuration of the nemesis API.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct NemesisConfig {
+    /// The address the nemesis API listens on.
+    pub address: String,
+}
+
+impl Default for NemesisConfig {
+    fn default() -> Self {
+        Self {
+            address: "127.0.0.1:8081".to_string(),
+        }
+    }
+}
+
+/// Struct that represents the configuration of the console an experiment is steered through by hand.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ConsoleConfig {
+    /// The address the console listens on, which the `console` subcommand attaches to.
+    pub address: String,
+}
+
+impl Default for ConsoleConfig {
+    fn default() -> Self {
+        Self {
+            address: "127.0.0.1:8082".to_string(),
+        }
+    }
+}
+
+/// Struct that represents the configuration of the gRPC service of the interceptor.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct GrpcServerConfig {
+    /// The address the gRPC service listens on.
+    pub address: String,
+}
+
+impl Default for GrpcServerConfig {
+    fn default() -> Self {
+        Self {
+            address: "127.0.0.1:50052".to_string(),
+        }
+    }
+}
+
+/// Struct that represents the configuration of the next interceptor in the chain, which decides on every message after
+/// this interceptor did.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct RelayConfig {
+    /// The address of the gRPC service of the next interceptor, which is started as a relay layer.
+    pub next: String,
+}
+
+impl Default for RelayConfig {
+    fn default() -> Self {
+        Self {
+            next: "http://127.0.0.1:50052".to_string(),
+        }
+    }
+}
+
+/// Enum that represents the shapes of the generated topologies.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TopologyShape {
+    /// Every node is linked to the next one, and the last one to the first one.
+    Ring,
+    /// Every node is linked to the hub.
+    Star,
+    /// A ring in which every node is linked to its nearest neighbours, of which some links are moved to random nodes.
+    SmallWorld,
+    /// Every pair of nodes is linked with the same probability.
+    Random,
+}
+
+/// Struct that represents the configuration of the pairs of nodes that get an intercepted link, which are either read
+/// from a file or generated.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct TopologyConfig {
+    /// The file describing the links, as a graph in the DOT language if its extension is .dot or .gv, and as a JSON
+    /// adjacency list otherwise, if the links are read from a file.
+    pub file: Option<String>,
+    /// The shape of the topology, if the links are generated.
+    pub shape: Option<TopologyShape>,
+    /// The seed of the random shapes, such that the same topology is generated again.
+    pub seed: u64,
+    /// The ID of the node every other node is linked to in a star.
+    pub hub: u32,
+    /// The amount of nearest neighbours every node is linked to in a small world, an even number.
+    pub degree: u32,
+    /// The probability that a link of a small world is moved to a random node.
+    pub rewire_probability: f64,
+    /// The probability that a pair of nodes is linked in a random topology.
+    pub link_probability: f64,
+}
+
+impl Default for TopologyConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            shape: None,
+            seed: 0,
+            hub: 0,
+            degree: 4,
+            rewire_probability: 0.1,
+            link_probability: 0.5,
+        }
+    }
+}
+
+/// Struct that represents a network that is run next to other networks by the same interceptor.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct TenantConfig {
+    /// The name of the network, which prefixes the names of its containers and the directory of its configurations.
+    pub name: String,
+    /// The path of the configuration file of the network, which configures its controller, ports and faults.
+    pub config: String,
+}
+
+impl TenantConfig {
+    /// Checks whether networks can run at the same time: every network needs a configuration file and a unique name
+    /// that Docker accepts in the names of containers.
+    ///
+    /// # Parameters
+    /// * 'tenants' - the networks.
+    pub fn validate(tenants: &[TenantConfig]) -> Result<(), String> {
+        for (i, tenant) in tenants.iter().enumerate() {
+            if tenant.name.is_empty()
+                || !tenant
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-')
+            {
+                return Err(format!(
+                    "'{}' is not a valid network name, use letters, digits and dashes",
+                    tenant.name
+                ));
+            }
+            if tenants[..i].iter().any(|other| other.name == tenant.name) {
+                return Err(format!("network {} is configured twice", tenant.name));
+            }
+            if tenant.config.is_empty() {
+                return Err(format!("network {} has no configuration file", tenant.name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Struct that represents the configuration of the heartbeats sent to the controller while the interceptor runs.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct HeartbeatConfig {
+    /// The time between two heartbeats, in ms.
+    pub interval_ms: u64,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self { interval_ms: 1000 }
+    }
+}
+
+/// Struct that represents the configuration of the stats of the node containers sent to the controller.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ContainerStatsConfig {
+    /// The minimum time between two samples of a container, in ms. Docker samples about once per second.
+    pub interval_ms: u64,
+}
+
+impl Default for ContainerStatsConfig {
+    fn default() -> Self {
+        Self { interval_ms: 1000 }
+    }
+}
+
+/// Struct that represents the configuration of the forwarding on all links when the interceptor starts.
+/// Both can be changed through the admin API while running.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ForwardingConfig {
+    /// Whether forwarding starts paused, such that the network can be inspected before consensus starts.
+    pub start_paused: bool,
+    /// The factor by which all injected delays are multiplied, above 1 to run the network in slow motion.
+    pub time_dilation: f64,
+}
+
+impl Default for ForwardingConfig {
+    fn default() -> Self {
+        Self {
+            start_paused: false,
+            time_dilation: 1.0,
+        }
+    }
+}
+
+/// Struct that represents the configuration of the SQLite database the handled messages of a run are stored in.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// The directory in which a database is created for every run.
+    pub directory: String,
+    /// The amount of messages buffered for the database before messages are not stored.
+    pub capacity: usize,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            directory: "runs".to_string(),
+            capacity: 65_536,
+        }
+    }
+}
+
+/// Enum that represents the format of the export file.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// One JSON object per line.
+    #[default]
+    Jsonl,
+    /// One CSV row per message, after a header row.
+    Csv,
+}
+
+/// Struct that represents the configuration of the file the metadata and decisions of handled messages are exported to.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ExportConfig {
+    /// The directory in which an export file is created for every run.
+    pub directory: String,
+    /// The format of the export file.
+    pub format: ExportFormat,
+    /// The amount of messages buffered for the export file before messages are not exported.
+    pub capacity: usize,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            directory: "runs".to_string(),
+            format: ExportFormat::Jsonl,
+            capacity: 65_536,
+        }
+    }
+}
+
+/// Enum that represents the streaming platform handled messages are published to.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamBackend {
+    /// A Kafka topic, keyed by the direction of the link.
+    Kafka,
+    /// A NATS subject.
+    #[default]
+    Nats,
+}
+
+/// Struct that represents the configuration of the Kafka topic or NATS subject handled messages are published to.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct StreamConfig {
+    /// The streaming platform.
+    pub backend: StreamBackend,
+    /// The Kafka brokers as 'host:port', or the NATS server URLs.
+    pub servers: Vec<String>,
+    /// The Kafka topic or NATS subject.
+    pub topic: String,
+    /// The amount of messages buffered for the stream before messages are not published.
+    pub capacity: usize,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            backend: StreamBackend::Nats,
+            servers: vec!["nats://127.0.0.1:4222".to_string()],
+            topic: "rocket.packets".to_string(),
+            capacity: 65_536,
+        }
+    }
+}
+
+/// Struct that represents the configuration of the timeline that interleaves the ledger closes of the nodes with the
+/// interception events, written when the interceptor shuts down.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct CloseTimelineConfig {
+    /// The directory in which a timeline file is created for every run.
+    pub directory: String,
+    /// The amount of entries that are kept, the oldest ones are left out of the timeline when there are more.
+    pub capacity: usize,
+    /// The time in ms between two ledger closes of a node above which the round is marked as slow.
+    pub slow_close_ms: u64,
+}
+
+impl Default for CloseTimelineConfig {
+    fn default() -> Self {
+        Self {
+            directory: "runs".to_string(),
+            capacity: 100_000,
+            slow_close_ms: 5000,
+        }
+    }
+}
+
+/// Struct that represents the configuration of the detectors of anomalies in the traffic.
+/// A detector is disabled by setting its threshold or window to 0.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct AnomalyConfig {
+    /// The time in ms a node that sent messages before can be silent before it is reported.
+    pub silence_ms: u64,
+    /// The amount of distinct validations a node can send within the burst window before it is reported.
+    pub validation_burst: usize,
+    /// The window in ms in which the validations of a burst are counted.
+    pub burst_window_ms: u64,
+    /// The amount of times the same message can be sent on a link within the repeat window before it is reported.
+    pub repeat_threshold: u32,
+    /// The window in ms in which the repetitions of a message are counted.
+    pub repeat_window_ms: u64,
+    /// The time in ms after a mutated message within which the connection it was sent on closing is reported as a
+    /// rejection by the peer.
+    pub rejection_window_ms: u64,
+    /// Whether the anomalies are streamed to the controller, besides being published as events.
+    pub report_to_controller: bool,
+}
+
+impl Default for AnomalyConfig {
+    fn default() -> Self {
+        Self {
+            silence_ms: 10_000,
+            validation_burst: 20,
+            burst_window_ms: 1000,
+            repeat_threshold: 5,
+            repeat_window_ms: 10_000,
+            rejection_window_ms: 2000,
+            report_to_controller: false,
+        }
+    }
+}
+
+/// Struct that represents the configuration of the passive observation mode, in which every message is forwarded as
+/// soon as it is read, without asking the controller or applying any local rule to it.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct PassiveConfig {
+    /// The latency in µs between reading a message and queueing it to be written above which it is counted as over budget.
+    pub latency_budget_us: u64,
+}
+
+impl Default for PassiveConfig {
+    fn default() -> Self {
+        Self {
+            latency_budget_us: 1000,
+        }
+    }
+}
+
+/// Struct that represents the configuration of the phases of a run: a warm-up, the fault window and a cool-down.
+/// During the warm-up and the cool-down, every message is passed through untouched like in the passive observation mode.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct PhaseConfig {
+    /// How long messages are passed through untouched after the links started, in seconds.
+    pub warm_up_secs: u64,
+    /// How long the fault window lasts, in seconds, or None to let it last until the run ends.
+    pub fault_secs: Option<u64>,
+    /// How long messages are passed through untouched after the fault window, in seconds, after which the run ends,
+    /// or None to let the cool-down last until the run ends otherwise.
+    pub cool_down_secs: Option<u64>,
+}
+
+impl Default for PhaseConfig {
+    fn default() -> Self {
+        Self {
+            warm_up_secs: 30,
+            fault_secs: None,
+            cool_down_secs: None,
+        }
+    }
+}
+
+/// Struct that represents the configuration of the analysis of the copies of every validation and proposal that
+/// traverse the network, written when the interceptor shuts down.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct BroadcastConfig {
+    /// Whether the copies of a broadcast sent to a node that already has it are dropped.
+    pub dedupe: bool,
+    /// The amount of broadcasts that are remembered, the oldest ones are forgotten when there are more.
+    pub remembered: usize,
+    /// The directory in which a report file is created for every run.
+    pub directory: String,
+}
+
+impl Default for BroadcastConfig {
+    fn default() -> Self {
+        Self {
+            dedupe: false,
+            remembered: 100_000,
+            directory: "runs".to_string(),
+        }
+    }
+}
+
+/// Struct that represents the configuration of the capture file every handled message is written to as it was read,
+/// such that the run can be inspected and replayed afterwards.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct CaptureFileConfig {
+    /// The directory in which a capture file is created for every run.
+    pub directory: String,
+    /// The names of the types of the captured messages, all types are captured if it is empty.
+    pub message_types: Vec<String>,
+    /// The amount of messages buffered for the capture file before messages are not captured.
+    pub capacity: usize,
+}
+
+impl Default for CaptureFileConfig {
+    fn default() -> Self {
+        Self {
+            directory: "runs".to_string(),
+            message_types: Vec::new(),
+            capacity: 65_536,
+        }
+    }
+}
+
+/// Struct that represents the conditions under which a run ends by itself, after which it shuts down and is reported
+/// like a run that was interrupted. The run ends at the first condition that is met.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct TerminationConfig {
+    /// The amount of ledgers validated since the start of the run after which it ends, or None to not count them.
+    pub validated_ledgers: Option<u64>,
+    /// The wall-clock time after which the run ends, in seconds, or None to not limit it.
+    pub duration_secs: Option<u64>,
+    /// Whether the run ends at the first violated property, which requires the [assertions] section.
+    pub on_violation: bool,
+    /// Whether the run ends when the controller signals that its scenario is complete.
+    pub on_controller_signal: bool,
+    /// How often the validated ledger of the nodes is polled, in milliseconds.
+    pub poll_interval_ms: u64,
+}
+
+impl Default for TerminationConfig {
+    fn default() -> Self {
+        Self {
+            validated_ledgers: None,
+            duration_secs: None,
+            on_violation: false,
+            on_controller_signal: false,
+            poll_interval_ms: 1000,
+        }
+    }
+}
+
+/// Struct that represents the configuration of the summary of the run that is produced at shutdown.
+/// The summary is always printed as a table.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct SummaryConfig {
+    /// The directory the summary is written to as JSON, or None to not write it.
+    pub directory: Option<String>,
+    /// Whether the summary is reported to the controller.
+    pub report_to_controller: bool,
+}
+
+impl Default for SummaryConfig {
+    fn default() -> Self {
+        Self {
+            directory: Some("runs".to_string()),
+            report_to_controller: true,
+        }
+    }
+}
+
+/// Struct that represents the configuration of the result of the run as reported to CI.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct CiConfig {
+    /// The name of the scenario, used as the name of the test suite.
+    pub scenario: String,
+    /// The path the JUnit XML report is written to, or None to not write it.
+    pub junit_path: Option<String>,
+}
+
+impl Default for CiConfig {
+    fn default() -> Self {
+        Self {
+            scenario: "rocket".to_string(),
+            junit_path: None,
+        }
+    }
+}
+
+/// Struct that represents the configuration of the bundle of diagnostics collected when a run fails.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct CrashBundleConfig {
+    /// Whether a bundle is collected when a run fails.
+    pub enabled: bool,
+    /// The directory the bundles are written to.
+    pub directory: String,
+    /// The amount of most recently handled messages included in the bundle.
+    pub recent_packets: usize,
+    /// The amount of last lines of the output of every container included in the bundle.
+    pub container_log_lines: usize,
+}
+
+impl Default for CrashBundleConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            directory: "crash-bundles".to_string(),
+            recent_packets: 1000,
+            container_log_lines: 10_000,
+        }
+    }
+}
+
+/// Struct that represents the configuration of the proxy in front of the public WebSocket port of every node.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct WebSocketProxyConfig {
+    /// The port of the proxy of the first node, the proxy of node i listens on this port plus i.
+    pub base_port: u16,
+    /// Whether the frames sent by clients, such as submitted transactions, are sent to the controller.
+    pub intercept_requests: bool,
+    /// Whether the frames sent by the nodes, such as subscription responses, are sent to the controller.
+    pub intercept_responses: bool,
+}
+
+impl Default for WebSocketProxyConfig {
+    fn default() -> Self {
+        Self {
+            base_port: 6100,
+            intercept_requests: true,
+            intercept_responses: true,
+        }
+    }
+}
+
+/// Struct that represents the configuration of the proxy in front of the JSON-RPC port of every node.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct RpcProxyConfig {
+    /// The port of the proxy of the first node, the proxy of node i listens on this port plus i.
+    pub base_port: u16,
+    /// Whether the bodies of the requests of clients, such as admin commands, are sent to the controller.
+    pub intercept_requests: bool,
+    /// Whether the bodies of the responses of the nodes are sent to the controller.
+    pub intercept_responses: bool,
+}
+
+impl Default for RpcProxyConfig {
+    fn default() -> Self {
+        Self {
+            base_port: 6200,
+            intercept_requests: true,
+            intercept_responses: true,
+        }
+    }
+}
+
+/// Enum that represents the role of a node in the network.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeRole {
+    /// The node validates, and is trusted by the other validators.
+    #[default]
+    Validator,
+    /// The node follows the ledger without validating, and is not trusted by any node.
+    Tracking,
+    /// The node does not validate, and is linked to every node it shares a partition with.
+    Hub,
+}
+
+impl NodeRole {
+    /// Returns the name of the role, as it is sent to the controller.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NodeRole::Validator => "validator",
+            NodeRole::Tracking => "tracking",
+            NodeRole::Hub => "hub",
+        }
+    }
+}
+
+/// Struct that represents the configuration of the roles of the nodes, by their IDs. Nodes without a role validate.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct RoleConfig {
+    /// The IDs of the tracking nodes, which follow the ledger without validating.
+    pub tracking: Vec<u32>,
+    /// The IDs of the hub nodes, which do not validate and are linked to every node they share a partition with.
+    pub hubs: Vec<u32>,
+}
+
+impl RoleConfig {
+    /// Returns the role of a node.
+    ///
+    /// # Parameters
+    /// * 'node' - the ID of the node.
+    pub fn role(&self, node: u32) -> NodeRole {
+        if self.hubs.contains(&node) {
+            NodeRole::Hub
+        } else if self.tracking.contains(&node) {
+            NodeRole::Tracking
+        } else {
+            NodeRole::Validator
+        }
+    }
+
+    /// Checks whether the roles fit a network: every node with a role has to exist and have a single role, and at least
+    /// one node has to validate.
+    ///
+    /// # Parameters
+    /// * 'number_of_nodes' - the amount of nodes in the network.
+    pub fn validate(&self, number_of_nodes: u32) -> Result<(), String> {
+        for node in self.tracking.iter().chain(self.hubs.iter()) {
+            if *node >= number_of_nodes {
+                return Err(format!("node {} does not exist", node));
+            }
+        }
+        if let Some(node) = self.tracking.iter().find(|node| self.hubs.contains(node)) {
+            return Err(format!("node {} is both a tracking node and a hub", node));
+        }
+        if (0..number_of_nodes).all(|node| self.role(node) != NodeRole::Validator) {
+            return Err("no node validates".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Struct that represents the configuration of the shadow node, which receives a copy of every message delivered to
+/// the observed node, but is not part of any UNL.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct ShadowConfig {
+    /// The ID of the node whose delivered messages are mirrored to the shadow node.
+    pub observed_node: u32,
+    /// The Docker image the shadow node runs, e.g. another version of rippled. The image of the validators if not set.
+    pub image: Option<String>,
+}
+
+/// Struct that represents the configuration of the amendments the nodes vote for or against, and of the monitoring of
+/// their voting.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct AmendmentConfig {
+    /// The votes of groups of nodes. Nodes without votes keep the default votes of rippled.
+    pub votes: Vec<AmendmentVotes>,
+    /// How often the amendment status of every node is polled, in milliseconds. The voting is not monitored if 0.
+    pub monitor_interval_ms: u64,
+}
+
+impl Default for AmendmentConfig {
+    fn default() -> Self {
+        AmendmentConfig {
+            votes: Vec::new(),
+            monitor_interval_ms: 5000,
+        }
+    }
+}
+
+impl AmendmentConfig {
+    /// Returns the amendments a node votes for and against, by their names or IDs.
+    ///
+    /// # Parameters
+    /// * 'node' - the ID of the node.
+    pub fn votes_of(&self, node: u32) -> (Vec<String>, Vec<String>) {
+        let mut enabled = Vec::new();
+        let mut vetoed = Vec::new();
+        for votes in self
+            .votes
+            .iter()
+            .filter(|votes| votes.nodes.contains(&node))
+        {
+            enabled.extend(votes.enabled.iter().cloned());
+            vetoed.extend(votes.vetoed.iter().cloned());
+        }
+        (enabled, vetoed)
+    }
+
+    /// Checks whether the votes fit a network: every node with votes has to exist, and can not vote both for and
+    /// against the same amendment.
+    ///
+    /// # Parameters
+    /// * 'number_of_nodes' - the amount of nodes in the network.
+    pub fn validate(&self, number_of_nodes: u32) -> Result<(), String> {
+        for node in self.votes.iter().flat_map(|votes| votes.nodes.iter()) {
+            if *node >= number_of_nodes {
+                return Err(format!("node {} does not exist", node));
+            }
+            let (enabled, vetoed) = self.votes_of(*node);
+            if let Some(amendment) = enabled.iter().find(|amendment| vetoed.contains(amendment)) {
+                return Err(format!(
+                    "node {} votes both for and against {}",
+                    node, amendment
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Struct that represents the votes of a group of nodes on amendments.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct AmendmentVotes {
+    /// The IDs of the nodes that vote.
+    pub nodes: Vec<u32>,
+    /// The names or IDs of the amendments the nodes vote for.
+    pub enabled: Vec<String>,
+    /// The names or IDs of the amendments the nodes vote against.
+    pub vetoed: Vec<String>,
+}
+
+/// Struct that represents the configuration of the validator list publisher, a container that serves a signed list of
+/// the validators every node trusts instead of a fixed UNL.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ValidatorListConfig {
+    /// The Docker image of the web server that serves the list.
+    pub image: String,
+    /// The IDs of the validators on the first published list. All validators are listed if not set.
+    pub validators: Option<Vec<u32>>,
+    /// The amount of days after which the published lists expire.
+    pub expiration_days: u32,
+    /// The interval in minutes in which the nodes fetch the list again, between 1 and 1440.
+    pub refresh_interval_mins: u32,
+    /// The lists that replace the published list during the run, in order.
+    pub rotations: Vec<ValidatorListRotation>,
+}
+
+impl Default for ValidatorListConfig {
+    fn default() -> Self {
+        ValidatorListConfig {
+            image: "nginx:1.27-alpine".to_string(),
+            validators: None,
+            expiration_days: 365,
+            refresh_interval_mins: 1,
+            rotations: Vec::new(),
+        }
+    }
+}
+
+impl ValidatorListConfig {
+    /// Checks whether the listed nodes fit a network: every listed node has to exist and validate, and every list has
+    /// to contain at least one validator.
+    ///
+    /// # Parameters
+    /// * 'roles' - the roles of the nodes.
+    /// * 'number_of_nodes' - the amount of nodes in the network.
+    pub fn validate(&self, roles: &RoleConfig, number_of_nodes: u32) -> Result<(), String> {
+        let lists = self
+            .validators
+            .iter()
+            .chain(self.rotations.iter().map(|rotation| &rotation.validators));
+        for list in lists {
+            if list.is_empty() {
+                return Err("a list contains no validators".to_string());
+            }
+            for node in list {
+                if *node >= number_of_nodes {
+                    return Err(format!("node {} does not exist", node));
+                }
+                if roles.role(*node) != NodeRole::Validator {
+                    return Err(format!("node {} does not validate", node));
+                }
+            }
+        }
+        if !(1..=1440).contains(&self.refresh_interval_mins) {
+            return Err("the refresh interval has to be between 1 and 1440 minutes".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Struct that represents a list of validators that is published during the run, to rotate the UNL of the nodes.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct ValidatorListRotation {
+    /// The amount of seconds after the start of the run after which the list is published.
+    pub after_secs: u64,
+    /// The IDs of the validators on the list.
+    pub validators: Vec<u32>,
+}
+
+/// Struct that represents the configuration of the eclipse of a victim node, which is cut off from all its peers
+/// except the visible ones.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct EclipseConfig {
+    /// The ID of the victim node.
+    pub victim: u32,
+    /// The IDs of the peers that stay connected to the victim.
+    pub visible_peers: Vec<u32>,
+    /// The names of the message types the visible peers can still send to the victim, e.g. 'mtVALIDATION', all if empty.
+    pub visible_types: Vec<String>,
+    /// After how many seconds the eclipse starts, counting from the moment the links are started.
+    pub start_after_secs: u64,
+    /// After how many seconds the eclipse ends, it lasts until the end of the run if 0.
+    pub duration_secs: u64,
+}
+
+/// Struct that represents the memory and CPU limits of a container. Unset limits leave the resource unlimited.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(default)]
+pub struct ResourceLimits {
+    /// The memory limit in MiB.
+    pub memory_mb: Option<u64>,
+    /// The amount of CPUs the container can use, e.g. 0.5 for half a CPU.
+    pub cpus: Option<f64>,
+}
+
+/// Struct that represents the configuration of the memory and CPU limits of the containers of the nodes.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct ResourceConfig {
+    /// The limits of every node without limits of its own.
+    #[serde(flatten)]
+    pub default: ResourceLimits,
+    /// The limits of groups of nodes, which replace the default limits.
+    pub nodes: Vec<NodeResources>,
+}
+
+/// Struct that represents the memory and CPU limits of a group of nodes.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct NodeResources {
+    /// The IDs of the nodes.
+    pub nodes: Vec<u32>,
+    /// The limits of the nodes.
+    #[serde(flatten)]
+    pub limits: ResourceLimits,
+}
+
+impl ResourceConfig {
+    /// Returns the limits of the container of a node: those of the last group it is in, or the default limits.
+    ///
+    /// # Parameters
+    /// * 'node' - the ID of the node.
+    pub fn limits(&self, node: u32) -> ResourceLimits {
+        self.nodes
+            .iter()
+            .rev()
+            .find(|group| group.nodes.contains(&node))
+            .map(|group| group.limits)
+            .unwrap_or(self.default)
+    }
+
+    /// Checks whether the limits fit a network: every node with limits has to exist, and every limit has to be
+    /// positive.
+    ///
+    /// # Parameters
+    /// * 'number_of_nodes' - the amount of nodes in the network.
+    pub fn validate(&self, number_of_nodes: u32) -> Result<(), String> {
+        if let Some(node) = self
+            .nodes
+            .iter()
+            .flat_map(|group| group.nodes.iter())
+            .find(|node| **node >= number_of_nodes)
+        {
+            return Err(format!("node {} does not exist", node));
+        }
+        let limits =
+            std::iter::once(&self.default).chain(self.nodes.iter().map(|group| &group.limits));
+        for limits in limits {
+            if limits.memory_mb == Some(0) || limits.cpus.is_some_and(|cpus| cpus <= 0.0) {
+                return Err("limits have to be positive".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Struct that represents the configuration of the CPU throttling of nodes, which simulates overloaded nodes by
+/// tightening the CPU limit of their containers for a period of the run.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct CpuThrottleConfig {
+    /// The IDs of the throttled nodes.
+    pub nodes: Vec<u32>,
+    /// The amount of CPUs the throttled nodes can use, e.g. 0.1 for a tenth of a CPU.
+    pub cpus: f64,
+    /// After how many seconds the throttling starts, counting from the moment the links are started.
+    pub start_after_secs: u64,
+    /// After how many seconds the original limits are restored, the nodes stay throttled until the end of the run if 0.
+    pub duration_secs: u64,
+}
+
+/// Struct that represents how the clock of a node deviates from the real time.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct ClockSkew {
+    /// How many seconds the clock is ahead of the real time when the node starts, behind if negative.
+    pub offset_secs: i64,
+    /// How fast the clock runs compared to the real time, e.g. 1.001 to gain a second every 1000 seconds.
+    pub drift_rate: f64,
+}
+
+impl Default for ClockSkew {
+    fn default() -> Self {
+        ClockSkew {
+            offset_secs: 0,
+            drift_rate: 1.0,
+        }
+    }
+}
+
+/// Struct that represents the skewed clock of a group of nodes.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct NodeClockSkew {
+    /// The IDs of the nodes.
+    pub nodes: Vec<u32>,
+    /// How the clocks of the nodes deviate.
+    #[serde(flatten)]
+    pub skew: ClockSkew,
+}
+
+/// Struct that represents the configuration of the skewed clocks of nodes. The clocks are skewed by preloading
+/// libfaketime into the containers of the nodes, which only changes the time the nodes see.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ClockSkewConfig {
+    /// The path of libfaketime on the host, which is mounted into the containers of the skewed nodes.
+    pub library: String,
+    /// The skewed clocks of groups of nodes.
+    pub nodes: Vec<NodeClockSkew>,
+}
+
+impl Default for ClockSkewConfig {
+    fn default() -> Self {
+        ClockSkewConfig {
+            library: "/usr/lib/x86_64-linux-gnu/faketime/libfaketime.so.1".to_string(),
+            nodes: Vec::new(),
+        }
+    }
+}
+
+impl ClockSkewConfig {
+    /// Returns how the clock of a node deviates: as configured in the last group it is in, or None if it is in none.
+    ///
+    /// # Parameters
+    /// * 'node' - the ID of the node.
+    pub fn skew(&self, node: u32) -> Option<ClockSkew> {
+        self.nodes
+            .iter()
+            .rev()
+            .find(|group| group.nodes.contains(&node))
+            .map(|group| group.skew)
+    }
+
+    /// Checks whether the skewed clocks fit a network: every skewed node has to exist, and every clock has to run
+    /// forward.
+    ///
+    /// # Parameters
+    /// * 'number_of_nodes' - the amount of nodes in the network.
+    pub fn validate(&self, number_of_nodes: u32) -> Result<(), String> {
+        if let Some(node) = self
+            .nodes
+            .iter()
+            .flat_map(|group| group.nodes.iter())
+            .find(|node| **node >= number_of_nodes)
+        {
+            return Err(format!("node {} does not exist", node));
+        }
+        if self.nodes.iter().any(|group| group.skew.drift_rate <= 0.0) {
+            return Err("drift rates have to be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Struct that represents the configuration of the Docker volumes the databases of the nodes are kept in, such that a
+/// network can be restarted against the ledger history of a previous run.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct PersistenceConfig {
+    /// The prefix of the names of the volumes, followed by the names of the containers of the nodes.
+    pub volume_prefix: String,
+    /// The directory the checkpoints of the network are saved in, one directory per checkpoint.
+    pub checkpoint_directory: String,
+}
+
+impl Default for PersistenceConfig {
+    fn default() -> Self {
+        PersistenceConfig {
+            volume_prefix: "rocket_db".to_string(),
+            checkpoint_directory: "checkpoints".to_string(),
+        }
+    }
+}
+
+impl PersistenceConfig {
+    /// Returns the name of the volume of a container.
+    ///
+    /// # Parameters
+    /// * 'container_name' - the name of the container.
+    pub fn volume(&self, container_name: &str) -> String {
+        format!("{}_{}", self.volume_prefix, container_name)
+    }
+}
+
+/// Struct that represents the configuration of the addresses the nodes are reached on, which can be IPv4 or IPv6, and
+/// of the Docker network the containers run in.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct AddressingConfig {
+    /// The host the ports of the nodes are published on: an IPv4 or IPv6 address, or a hostname.
+    pub node_host: String,
+    /// Whether the containers run in a dual-stack Docker network, such that they have an IPv6 address as well.
+    pub dual_stack: bool,
+    /// The IPv6 subnet of the dual-stack network.
+    pub ipv6_subnet: String,
+    /// The IPv4 subnet of the Docker network, chosen by Docker if not set.
+    pub subnet: Option<String>,
+    /// The IPv4 gateway of the Docker network, chosen by Docker if not set.
+    pub gateway: Option<String>,
+    /// The DNS names of nodes in the Docker network, besides the names of their containers.
+    pub aliases: Vec<NodeAliases>,
+}
+
+/// Struct that represents the DNS names of a node in the Docker network, besides the name of its container.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct NodeAliases {
+    /// The ID of the node.
+    pub node: u32,
+    /// The DNS names of the node.
+    pub names: Vec<String>,
+}
+
+impl Default for AddressingConfig {
+    fn default() -> Self {
+        AddressingConfig {
+            node_host: "127.0.0.1".to_string(),
+            dual_stack: false,
+            ipv6_subnet: "fd00:7263:6b74::/64".to_string(),
+            subnet: None,
+            gateway: None,
+            aliases: Vec::new(),
+        }
+    }
+}
+
+/// Checks whether a value is a subnet of an address family in CIDR notation, e.g. '172.30.0.0/16'.
+///
+/// # Parameters
+/// * 'subnet' - the value.
+/// * 'max_prefix' - the length of the addresses of the family in bits.
+fn is_subnet<A: std::str::FromStr>(subnet: &str, max_prefix: u8) -> bool {
+    subnet.split_once('/').is_some_and(|(address, prefix)| {
+        address.parse::<A>().is_ok()
+            && prefix
+                .parse::<u8>()
+                .is_ok_and(|prefix| prefix <= max_prefix)
+    })
+}
+
+impl AddressingConfig {
+    /// Returns whether the containers run in a Docker network of their own instead of the default bridge network,
+    /// which is needed for IPv6, a chosen subnet and DNS names.
+    pub fn user_network(&self) -> bool {
+        self.dual_stack
+            || self.subnet.is_some()
+            || self.gateway.is_some()
+            || !self.aliases.is_empty()
+    }
+
+    /// Returns the DNS names of a node besides the name of its container.
+    ///
+    /// # Parameters
+    /// * 'node' - the ID of the node.
+    pub fn aliases(&self, node: u32) -> Vec<String> {
+        self.aliases
+            .iter()
+            .filter(|aliases| aliases.node == node)
+            .flat_map(|aliases| aliases.names.iter().cloned())
+            .collect()
+    }
+
+    /// Checks that the host is set, that the subnets and the gateway are addresses of the right family, and that the
+    /// aliases are valid DNS names of existing nodes that are not used twice.
+    ///
+    /// # Parameters
+    /// * 'n' - the amount of nodes in the network.
+    pub fn validate(&self, n: u32) -> Result<(), String> {
+        if self.node_host.is_empty() {
+            return Err("node_host cannot be empty".to_string());
+        }
+        if self.dual_stack && !is_subnet::<std::net::Ipv6Addr>(&self.ipv6_subnet, 128) {
+            return Err(format!(
+                "ipv6_subnet '{}' is not an IPv6 subnet, e.g. 'fd00:7263:6b74::/64'",
+                self.ipv6_subnet
+            ));
+        }
+        if let Some(subnet) = &self.subnet {
+            if !is_subnet::<std::net::Ipv4Addr>(subnet, 32) {
+                return Err(format!(
+                    "subnet '{}' is not an IPv4 subnet, e.g. '172.30.0.0/16'",
+                    subnet
+                ));
+            }
+        }
+        if let Some(gateway) = &self.gateway {
+            if gateway.parse::<std::net::Ipv4Addr>().is_err() {
+                return Err(format!("gateway '{}' is not an IPv4 address", gateway));
+            }
+            if self.subnet.is_none() {
+                return Err("a gateway can only be set together with its subnet".to_string());
+            }
+        }
+        let mut names = std::collections::HashSet::new();
+        for aliases in self.aliases.iter() {
+            if aliases.node >= n {
+                return Err(format!("node {} does not exist", aliases.node));
+            }
+            for name in aliases.names.iter() {
+                if name.is_empty()
+                    || !name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+                {
+                    return Err(format!(
+                        "alias '{}' may only contain letters, digits, dashes and dots",
+                        name
+                    ));
+                }
+                if !names.insert(name) {
+                    return Err(format!("alias '{}' is used more than once", name));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Struct that represents the configuration of the Sybil peers: additional, distinct peers the interceptor pretends to
+/// be towards a target node, without running a node for any of them.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct SybilConfig {
+    /// The ID of the target node.
+    pub target: u32,
+    /// The amount of Sybil peers that connect to the target node, each with its own generated key.
+    pub peers: u16,
+    /// The port that identifies the first Sybil peer, Sybil peer i is identified by this port plus i.
+    /// Nothing listens on these ports, they only identify the links of the Sybil peers.
+    pub base_port: u16,
+}
+
+impl Default for SybilConfig {
+    fn default() -> Self {
+        Self {
+            target: 0,
+            peers: 8,
+            base_port: 61000,
+        }
+    }
+}
+
+/// Struct that represents the configuration of the connection-flapping fault: the connections of some links are
+/// periodically closed and re-established.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct FlappingConfig {
+    /// The pairs of node IDs whose links flap, e.g. [[0, 1]] for the links from node 0 to 1 and from node 1 to 0.
+    pub links: Vec<[u32; 2]>,
+    /// How long the connections stay up, in milliseconds.
+    pub up_ms: u64,
+    /// How long the connections stay down, in milliseconds.
+    pub down_ms: u64,
+    /// The length of a cycle of the connections going up and down, in milliseconds.
+    /// Only used together with 'duty_cycle', which then replaces 'up_ms' and 'down_ms'.
+    pub period_ms: Option<u64>,
+    /// The fraction of every period that the connections are up, between 0 and 1.
+    pub duty_cycle: Option<f64>,
+    /// After how many seconds the first connection goes down, counting from the moment the links are started.
+    pub start_after_secs: u64,
+}
+
+impl Default for FlappingConfig {
+    fn default() -> Self {
+        Self {
+            links: Vec::new(),
+            up_ms: 10_000,
+            down_ms: 2_000,
+            period_ms: None,
+            duty_cycle: None,
+            start_after_secs: 0,
+        }
+    }
+}
+
+/// Struct that represents the configuration of a one-way partition: links that are cut in one direction only.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct OneWayPartitionConfig {
+    /// The cut links as pairs of node IDs, e.g. [[0, 1]] to drop the messages from node 0 to 1 but not those from 1 to 0.
+    pub cut_links: Vec<[u32; 2]>,
+    /// After how many seconds the partition starts, counting from the moment the links are started.
+    pub start_after_secs: u64,
+    /// After how many seconds the partition is healed, it lasts until the end of the run if 0.
+    pub duration_secs: u64,
+}
+
+/// Struct that represents the configuration of the transaction-relay suppression: the transactions relayed on the
+/// selected links are dropped for a period of the run, while the consensus messages are delivered as decided.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct TxSuppressionConfig {
+    /// The suppressed links as pairs of node IDs, e.g. [[0, 1]] to drop the transactions from node 0 to 1 only.
+    pub links: Vec<[u32; 2]>,
+    /// The IDs of the nodes whose links are suppressed in both directions, such that no transactions reach or leave them.
+    pub nodes: Vec<u32>,
+    /// After how many seconds the suppression starts, counting from the moment the links are started.
+    pub start_after_secs: u64,
+    /// After how many seconds the suppression ends, it lasts until the end of the run if 0.
+    pub duration_secs: u64,
+    /// The directory in which the report of the ledgers closed before, during and after the suppression is created.
+    pub directory: String,
+}
+
+impl Default for TxSuppressionConfig {
+    fn default() -> Self {
+        Self {
+            links: Vec::new(),
+            nodes: Vec::new(),
+            start_after_secs: 0,
+            duration_secs: 0,
+            directory: "runs".to_string(),
+        }
+    }
+}
+
+/// Struct that represents the configuration of the message types that are dropped per node from the start of the run.
+/// Blackholes can be added and removed at runtime through the admin API.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct BlackholeConfig {
+    /// The node and message type of every blackhole.
+    pub rules: Vec<BlackholeRuleConfig>,
+}
+
+/// Struct that represents the configuration of a single blackhole: all messages of a type sent by a node are dropped.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct BlackholeRuleConfig {
+    /// The ID of the node whose messages are dropped.
+    pub node: u32,
+    /// The name of the type of the dropped messages, e.g. 'mtVALIDATION'.
+    pub message_type: String,
+}
+
+/// Struct that represents the configuration of the base latency of every link, as a matrix by the IDs of the nodes the
+/// messages come from and go to.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct LatencyConfig {
+    /// The latency in ms of the messages from the node of the row to the node of the column, one row per node.
+    pub matrix: Vec<Vec<f64>>,
+    /// The CSV file the matrix is read from instead, if any.
+    pub file: Option<String>,
+    /// Whether the matrix contains round trip times, of which half is applied to the messages in each direction.
+    pub round_trip: bool,
+}
+
+/// Struct that represents the configuration of the replay fault: messages are captured as they are handled,
+/// and re-injected later. Replays can also be requested at runtime through the admin API.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ReplayConfig {
+    /// The maximum amount of captured messages, older messages are discarded.
+    pub capacity: usize,
+    /// The names of the types of the captured messages.
+    pub message_types: Vec<String>,
+    /// The replays that are done at fixed moments of the run.
+    pub replays: Vec<ScheduledReplayConfig>,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 10_000,
+            message_types: vec!["mtVALIDATION".to_string(), "mtPROPOSE_LEDGER".to_string()],
+            replays: Vec::new(),
+        }
+    }
+}
+
+/// Struct that represents the configuration of a replay at a fixed moment of the run.
+/// Conditions that are not set select all captured messages.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct ScheduledReplayConfig {
+    /// After how many seconds the messages are replayed, counting from the moment the links are started.
+    pub after_secs: u64,
+    /// The name of the type of the replayed messages.
+    pub message_type: Option<String>,
+    /// The ID of the node the replayed messages came from.
+    pub from_node: Option<u32>,
+    /// The ID of the node the messages are replayed to, the node they were originally sent to if not set.
+    pub to_node: Option<u32>,
+    /// How many ledgers the replayed messages are behind the most recent captured ledger sequence.
+    pub ledgers_ago: Option<u32>,
+    /// The maximum amount of replayed messages.
+    pub limit: Option<usize>,
+}
+
+/// Struct that represents the configuration of the rules by which messages are mutated locally, on top of the decision
+/// of the controller. The rules can be replaced at runtime through the admin API.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct MutationConfig {
+    /// The rules, which are all applied in order to the messages they match.
+    pub rules: Vec<MutationRuleConfig>,
+}
+
+/// Struct that represents the configuration of a single mutation rule.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct MutationRuleConfig {
+    /// The name of the type of the mutated messages, e.g. 'mtPROPOSE_LEDGER'.
+    pub message_type: String,
+    /// The ID of the node the mutated messages come from, all nodes if not set.
+    #[serde(default)]
+    pub from_node: Option<u32>,
+    /// The ID of the node the mutated messages go to, all nodes if not set.
+    #[serde(default)]
+    pub to_node: Option<u32>,
+    /// The mutations, e.g. { path = "closeTime", op = "add", delta = 5 }.
+    pub mutations: Vec<FieldMutation>,
+    /// Whether the mutated messages are signed again with the key of the node they come from.
+    #[serde(default)]
+    pub resign: bool,
+}
+
+/// Struct that represents the configuration of the rules that limit the rate of message types per link, on top of the
+/// decision of the controller. The rules can be replaced at runtime through the admin API.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct ShapingConfig {
+    /// The rules, of which the first matching one limits a message.
+    pub rules: Vec<ShapingRuleConfig>,
+}
+
+/// Struct that represents the configuration of a single shaping rule.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct ShapingRuleConfig {
+    /// The name of the type of the limited messages, e.g. 'mtVALIDATION'.
+    pub message_type: String,
+    /// The ID of the node the limited messages come from, all nodes if not set.
+    #[serde(default)]
+    pub from_node: Option<u32>,
+    /// The ID of the node the limited messages go to, all nodes if not set.
+    #[serde(default)]
+    pub to_node: Option<u32>,
+    /// The amount of messages per second on every matching link.
+    pub rate: f64,
+    /// The amount of messages that can be sent at once after the link was quiet, 1 if not set.
+    #[serde(default)]
+    pub burst: Option<u32>,
+    /// What happens to the messages above the rate: 'delay' or 'drop'.
+    #[serde(default)]
+    pub action: ShapingAction,
+}
+
+/// Struct that represents the configuration of the gray failure presets, which impair nodes and links on top of the
+/// decision of the controller. Presets given on the command line are added to these.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct GrayFailureConfig {
+    /// The presets, which are all applied to the nodes and links they target.
+    pub rules: Vec<GrayFailureRuleConfig>,
+}
+
+/// Struct that represents the configuration of a single gray failure preset, applied to either a node or a link.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct GrayFailureRuleConfig {
+    /// The name of the preset, e.g. 'lossy-wan', with the parameters that should not have their default.
+    #[serde(flatten)]
+    pub preset: Preset,
+    /// The ID of the node whose links are impaired in both directions, if the preset applies to a node.
+    #[serde(default)]
+    pub node: Option<u32>,
+    /// The ID of the node the messages of the impaired link come from, if the preset applies to a link.
+    #[serde(default)]
+    pub from_node: Option<u32>,
+    /// The ID of the node the messages of the impaired link go to, if the preset applies to a link.
+    #[serde(default)]
+    pub to_node: Option<u32>,
+}
+
+/// Struct that represents the configuration of the rewriting of manifests, which suppresses, rotates or revokes the
+/// manifests of nodes on selected links.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct ManifestConfig {
+    /// The rules, of which the first one that matches a link and master key rewrites a manifest.
+    pub rules: Vec<ManifestRuleConfig>,
+}
+
+/// Struct that represents the configuration of a single manifest rule.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct ManifestRuleConfig {
+    /// How the manifests are rewritten: 'suppress', 'rotate' or 'revoke'.
+    pub action: ManifestAction,
+    /// The ID of the node whose manifests are rewritten, the manifests of all master keys if not set.
+    #[serde(default)]
+    pub node: Option<u32>,
+    /// The ID of the node the rewritten messages come from, all nodes if not set.
+    #[serde(default)]
+    pub from_node: Option<u32>,
+    /// The ID of the node the rewritten messages go to, all nodes if not set.
+    #[serde(default)]
+    pub to_node: Option<u32>,
+}
+
+/// Struct that represents the configuration of the overriding of squelches, which suppresses or amplifies the squelches
+/// the nodes send on selected links.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct SquelchConfig {
+    /// The rules, of which the first one that matches a link and validator overrides a squelch.
+    pub rules: Vec<SquelchRuleConfig>,
+}
+
+/// Struct that represents the configuration of a single squelch rule.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct SquelchRuleConfig {
+    /// How the squelches are overridden: 'suppress', or 'amplify' with an optional `duration_secs`.
+    #[serde(flatten)]
+    pub action: SquelchAction,
+    /// The ID of the node whose validations are squelched, all validators if not set.
+    #[serde(default)]
+    pub node: Option<u32>,
+    /// The ID of the node that sends the squelches, all nodes if not set.
+    #[serde(default)]
+    pub from_node: Option<u32>,
+    /// The ID of the node the squelches are sent to, all nodes if not set.
+    #[serde(default)]
+    pub to_node: Option<u32>,
+}
+
+/// Struct that represents the configuration of the equivocation of proposals, which delivers a different variant of the
+/// proposals of a node to every group of its peers.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct EquivocationConfig {
+    /// The rules, of which the first one of a node equivocates its proposals.
+    pub rules: Vec<EquivocationRuleConfig>,
+}
+
+/// Struct that represents the configuration of a single equivocation rule.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct EquivocationRuleConfig {
+    /// The ID of the node whose proposals are equivocated.
+    pub node: u32,
+    /// The variants of its proposals, every peer is in at most one of them.
+    pub variants: Vec<ProposalVariantConfig>,
+}
+
+/// Struct that represents the configuration of a variant of the proposals of a node.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct ProposalVariantConfig {
+    /// The IDs of the nodes the variant is delivered to.
+    pub to_nodes: Vec<u32>,
+    /// The mutations that turn a proposal into the variant, e.g. { path = "closeTime", op = "add", delta = 5 }.
+    pub mutations: Vec<FieldMutation>,
+}
+
+/// Struct that represents the configuration of the WASM plugins, which decide on every sent message on top of the
+/// decision of the controller.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct PluginConfig {
+    /// The paths of the `.wasm` or `.wat` modules, in the order the plugins are applied.
+    pub wasm: Vec<String>,
+    /// The amount of fuel a plugin can use per message, which bounds its execution time.
+    pub fuel: u64,
+}
+
+impl Default for PluginConfig {
+    fn default() -> Self {
+        Self {
+            wasm: Vec::new(),
+            fuel: 10_000_000,
+        }
+    }
+}
+
+/// Struct that represents the configuration of the reloading of the local rules: the `[interception]`, `[blackhole]`,
+/// `[mutation]`, `[shaping]` and `[latency]` sections, which are replaced at once when their file changes.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct HotReloadConfig {
+    /// The path of the file the rules are read from, the configuration file itself if not set.
+    pub file: Option<String>,
+    /// How long to wait for further changes of the file before reloading, in ms.
+    pub debounce_ms: u64,
+}
+
+impl Default for HotReloadConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            debounce_ms: 200,
+        }
+    }
+}
+
+impl InterceptorConfig {
+    /// Loads the configuration from the file specified by `ROCKET_INTERCEPTOR_CONFIG`,
+    /// or from `interceptor.toml` if that variable is not set.
+    /// If the file does not exist, the default configuration is returned.
+    ///
+    /// # Panics
+    /// * If the file exists but could not be read or parsed.
+    pub fn load() -> Self {
+        let path = Self::path();
+        if !Path::new(&path).exists() {
+            return Self::default();
+        }
+        Self::from_file(&path)
+            .unwrap_or_else(|e| panic!("Could not load configuration file {}: {}", path, e))
+    }
+
+    /// Returns the path of the configuration file, which is `ROCKET_INTERCEPTOR_CONFIG` if set,
+    /// and `interceptor.toml` otherwise.
+    pub fn path() -> String {
+        std::env::var(CONFIG_PATH_ENV).unwrap_or(DEFAULT_CONFIG_PATH.to_string())
+    }
+
+    /// Reads and parses a configuration file.
+    ///
+    /// # Parameters
+    /// * 'path' - the path of the configuration file.
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let contents = fs::read_to_string(path)?;
+        Self::parse(&contents)
+    }
+
+    /// Parses the contents of a configuration file.
+    ///
+    /// # Parameters
+    /// * 'contents' - the TOML contents of the configuration file.
+    pub fn parse(contents: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(toml::from_str(contents)?)
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::config::{
+        AmendmentConfig, AmendmentVotes, AssertionConfig, ClockSkew, ClockSkewConfig, Compression,
+        EventsConfig, InterceptorConfig, KeepaliveConfig, LogFormat, LoggingConfig,
+        MalformedAction, MalformedFrameConfig, NodeRole, OverflowPolicy, OversizedPolicy,
+        PortAllocation, QueueConfig, ResourceLimits, RoleConfig, StartupConfig, StreamBackend,
+        StreamConfig, TenantConfig, TlsVersion, TxGeneratorConfig, ValidatorListRotation,
+        WritePriority,
+    };
+    use std::time::Duration;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_empty_config() {
+        let config = InterceptorConfig::parse("").unwrap();
+        assert_eq!(config, InterceptorConfig::default());
+        assert!(config.tx_generator.is_none());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_startup_config() {
+        assert_eq!(InterceptorConfig::default().startup.parallelism, 8);
+        let config = InterceptorConfig::parse("[startup]\nparallelism = 2\n").unwrap();
+        assert_eq!(config.startup, StartupConfig { parallelism: 2 });
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_port_config() {
+        let config = InterceptorConfig::parse("").unwrap();
+        assert_eq!(config.ports.allocation, PortAllocation::Check);
+        assert_eq!(config.ports.validate(), Ok(()));
+
+        let config = InterceptorConfig::parse(
+            "[ports]\nallocation = \"auto\"\nsearch_from = 40000\nsearch_to = 41000\n",
+        )
+        .unwrap();
+        assert_eq!(config.ports.allocation, PortAllocation::Auto);
+        assert_eq!(config.ports.search_from, 40000);
+        assert_eq!(config.ports.validate(), Ok(()));
+
+        let config =
+            InterceptorConfig::parse("[ports]\nsearch_from = 41000\nsearch_to = 40000\n").unwrap();
+        assert!(config.ports.validate().is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_tx_generator_config() {
+        let config = InterceptorConfig::parse(
+            "\
+            [tx_generator]\n\
+            rate = 2.5\n\
+            accounts = 10\n\
+            duration_secs = 60\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.tx_generator,
+            Some(TxGeneratorConfig {
+                rate: 2.5,
+                accounts: 10,
+                duration_secs: Some(60),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn tx_generator_rate_is_finite() {
+        let rate = |contents: &str| {
+            InterceptorConfig::parse(&format!("[tx_generator]\nrate = {}\n", contents))
+                .unwrap()
+                .tx_generator
+                .unwrap()
+                .validate()
+        };
+        assert!(rate("2.5").is_ok());
+        assert!(rate("0.0").is_err());
+        assert!(rate("nan").is_err());
+        assert!(rate("inf").is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn tx_generator_rate_is_bounded() {
+        let config = |rate| TxGeneratorConfig {
+            rate,
+            ..Default::default()
+        };
+        assert!(config(1e9).validate().is_ok());
+        assert!(config(2e9).validate().is_err());
+        assert!(config(1e300).validate().is_err());
+        assert!(!Duration::from_secs_f64(1.0 / 1e9).is_zero());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_assertion_config() {
+        let config = InterceptorConfig::parse("[assertions]\nbyzantine_nodes = [2]\n").unwrap();
+        assert_eq!(
+            config.assertions,
+            Some(AssertionConfig {
+                byzantine_nodes: vec![2],
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_logging_config() {
+        let config = InterceptorConfig::parse("[logging]\nformat = \"json\"\n").unwrap();
+        assert_eq!(
+            config.logging,
+            LoggingConfig {
+                format: LogFormat::Json,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_compression_config() {
+        let config =
+            InterceptorConfig::parse("[controller.compression]\nrequests = \"zstd\"\n").unwrap();
+        assert_eq!(config.controller.compression.requests, Compression::Zstd);
+        assert_eq!(config.controller.compression.responses, Compression::None);
+        assert!(
+            InterceptorConfig::parse("[controller.compression]\nresponses = \"lz4\"\n").is_err()
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_role_config() {
+        let config = InterceptorConfig::parse("[roles]\ntracking = [3]\nhubs = [0]\n").unwrap();
+        let roles = config.roles.unwrap();
+        assert_eq!(roles.role(0), NodeRole::Hub);
+        assert_eq!(roles.role(3), NodeRole::Tracking);
+        assert_eq!(roles.role(1), NodeRole::Validator);
+        assert_eq!(roles.validate(4), Ok(()));
+        assert!(roles.validate(3).is_err());
+
+        let conflicting = RoleConfig {
+            tracking: vec![1],
+            hubs: vec![1],
+        };
+        assert!(conflicting.validate(3).is_err());
+        let no_validator = RoleConfig {
+            tracking: vec![0],
+            hubs: vec![1],
+        };
+        assert!(no_validator.validate(2).is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_amendment_config() {
+        let config = InterceptorConfig::parse(
+            "[[amendments.votes]]\n\
+            nodes = [0, 1]\n\
+            enabled = [\"fixNFTokenReserve\"]\n\
+            [[amendments.votes]]\n\
+            nodes = [1, 2]\n\
+            vetoed = [\"AMM\"]\n",
+        )
+        .unwrap();
+        let amendments = config.amendments.unwrap();
+        assert_eq!(amendments.monitor_interval_ms, 5000);
+        assert_eq!(
+            amendments.votes_of(1),
+            (
+                vec!["fixNFTokenReserve".to_string()],
+                vec!["AMM".to_string()]
+            )
+        );
+        assert_eq!(amendments.votes_of(3), (vec![], vec![]));
+        assert_eq!(amendments.validate(3), Ok(()));
+        assert!(amendments.validate(2).is_err());
+
+        let conflicting = AmendmentConfig {
+            votes: vec![AmendmentVotes {
+                nodes: vec![0],
+                enabled: vec!["AMM".to_string()],
+                vetoed: vec!["AMM".to_string()],
+            }],
+            ..Default::default()
+        };
+        assert!(conflicting.validate(1).is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_tenant_config() {
+        let config = InterceptorConfig::parse(
+            "[[tenants]]\n\
+            name = \"a\"\n\
+            config = \"a.toml\"\n\
+            [[tenants]]\n\
+            name = \"b\"\n\
+            config = \"b.toml\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.tenants.len(), 2);
+        assert_eq!(config.tenants[1].config, "b.toml");
+        assert_eq!(TenantConfig::validate(&config.tenants), Ok(()));
+        assert!(InterceptorConfig::default().tenants.is_empty());
+
+        let tenant = |name: &str| TenantConfig {
+            name: name.to_string(),
+            config: "a.toml".to_string(),
+        };
+        assert!(TenantConfig::validate(&[tenant("a"), tenant("a")]).is_err());
+        assert!(TenantConfig::validate(&[tenant("a_b")]).is_err());
+        assert!(TenantConfig::validate(&[tenant("")]).is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_addressing_config() {
+        let config = InterceptorConfig::parse("").unwrap();
+        assert_eq!(config.addressing.node_host, "127.0.0.1");
+        assert_eq!(config.addressing.validate(3), Ok(()));
+        assert!(!config.addressing.user_network());
+
+        let config =
+            InterceptorConfig::parse("[addressing]\nnode_host = \"::1\"\ndual_stack = true\n")
+                .unwrap();
+        assert_eq!(config.addressing.node_host, "::1");
+        assert_eq!(config.addressing.validate(3), Ok(()));
+        assert!(config.addressing.user_network());
+
+        let config = InterceptorConfig::parse(
+            "[addressing]\ndual_stack = true\nipv6_subnet = \"10.0.0.0/8\"\n",
+        )
+        .unwrap();
+        assert!(config.addressing.validate(3).is_err());
+        let config = InterceptorConfig::parse("[addressing]\nnode_host = \"\"\n").unwrap();
+        assert!(config.addressing.validate(3).is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_network_addressing_config() {
+        let config = InterceptorConfig::parse(
+            "[addressing]\n\
+            subnet = \"172.30.0.0/16\"\n\
+            gateway = \"172.30.0.1\"\n\
+            [[addressing.aliases]]\n\
+            node = 0\n\
+            names = [\"hub\", \"node-a.rocket\"]\n",
+        )
+        .unwrap();
+        let addressing = config.addressing;
+        assert_eq!(addressing.validate(3), Ok(()));
+        assert!(addressing.user_network());
+        assert_eq!(addressing.aliases(0), vec!["hub", "node-a.rocket"]);
+        assert!(addressing.aliases(1).is_empty());
+        assert!(addressing.validate(0).is_err());
+
+        let with = |toml: &str| {
+            InterceptorConfig::parse(toml)
+                .unwrap()
+                .addressing
+                .validate(3)
+        };
+        assert!(with("[addressing]\nsubnet = \"fd00::/64\"\n").is_err());
+        assert!(with("[addressing]\ngateway = \"172.30.0.1\"\n").is_err());
+        assert!(with("[addressing]\nsubnet = \"172.30.0.0/16\"\ngateway = \"x\"\n").is_err());
+        assert!(with(
+            "[[addressing.aliases]]\nnode = 0\nnames = [\"a\"]\n\
+            [[addressing.aliases]]\nnode = 1\nnames = [\"a\"]\n"
+        )
+        .is_err());
+        assert!(with("[[addressing.aliases]]\nnode = 0\nnames = [\"a_b\"]\n").is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_labels() {
+        let config =
+            InterceptorConfig::parse("[labels]\nteam = \"consensus\"\n\"ci.job\" = \"42\"\n")
+                .unwrap();
+        assert_eq!(config.labels.len(), 2);
+        assert_eq!(config.labels["ci.job"], "42");
+        assert!(InterceptorConfig::default().labels.is_empty());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_persistence_config() {
+        let config = InterceptorConfig::parse("[persistence]\n").unwrap();
+        assert_eq!(
+            config.persistence.unwrap().volume("validator_0"),
+            "rocket_db_validator_0"
+        );
+        let config = InterceptorConfig::parse("[persistence]\nvolume_prefix = \"exp\"\n").unwrap();
+        assert_eq!(
+            config.persistence.unwrap().volume("shadow_1"),
+            "exp_shadow_1"
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_clock_skew_config() {
+        let config = InterceptorConfig::parse(
+            "[[clock_skew.nodes]]\n\
+            nodes = [1, 2]\n\
+            offset_secs = -30\n\
+            [[clock_skew.nodes]]\n\
+            nodes = [2]\n\
+            drift_rate = 1.01\n",
+        )
+        .unwrap();
+        let clock_skew = config.clock_skew.unwrap();
+        assert_eq!(clock_skew.library, ClockSkewConfig::default().library);
+        assert_eq!(clock_skew.skew(0), None);
+        assert_eq!(
+            clock_skew.skew(1),
+            Some(ClockSkew {
+                offset_secs: -30,
+                drift_rate: 1.0,
+            })
+        );
+        assert_eq!(
+            clock_skew.skew(2),
+            Some(ClockSkew {
+                offset_secs: 0,
+                drift_rate: 1.01,
+            })
+        );
+        assert_eq!(clock_skew.validate(3), Ok(()));
+        assert!(clock_skew.validate(2).is_err());
+
+        let config =
+            InterceptorConfig::parse("[[clock_skew.nodes]]\nnodes = [0]\ndrift_rate = 0.0\n")
+                .unwrap();
+        assert!(config.clock_skew.unwrap().validate(1).is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_resource_config() {
+        let config = InterceptorConfig::parse(
+            "[resources]\n\
+            memory_mb = 1024\n\
+            cpus = 1.5\n\
+            [[resources.nodes]]\n\
+            nodes = [2]\n\
+            cpus = 0.5\n",
+        )
+        .unwrap();
+        let resources = config.resources.unwrap();
+        assert_eq!(
+            resources.limits(0),
+            ResourceLimits {
+                memory_mb: Some(1024),
+                cpus: Some(1.5),
+            }
+        );
+        assert_eq!(
+            resources.limits(2),
+            ResourceLimits {
+                memory_mb: None,
+                cpus: Some(0.5),
+            }
+        );
+        assert_eq!(resources.validate(3), Ok(()));
+        assert!(resources.validate(2).is_err());
+
+        let config = InterceptorConfig::parse("[resources]\ncpus = 0.0\n").unwrap();
+        assert!(config.resources.unwrap().validate(1).is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_validator_list_config() {
+        let config = InterceptorConfig::parse(
+            "[validator_list]\n\
+            validators = [0, 1, 2]\n\
+            [[validator_list.rotations]]\n\
+            after_secs = 120\n\
+            validators = [1, 2, 3]\n",
+        )
+        .unwrap();
+        let validator_list = config.validator_list.unwrap();
+        assert_eq!(validator_list.validators, Some(vec![0, 1, 2]));
+        assert_eq!(validator_list.refresh_interval_mins, 1);
+        assert_eq!(
+            validator_list.rotations,
+            vec![ValidatorListRotation {
+                after_secs: 120,
+                validators: vec![1, 2, 3],
+            }]
+        );
+        let roles = RoleConfig {
+            tracking: vec![3],
+            hubs: vec![],
+        };
+        assert_eq!(validator_list.validate(&RoleConfig::default(), 4), Ok(()));
+        assert!(validator_list.validate(&roles, 4).is_err());
+        assert!(validator_list.validate(&RoleConfig::default(), 3).is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_queue_config() {
+        let config = InterceptorConfig::parse(
+            "[queues]\ncapacity = 16\noverflow = \"drop_oldest\"\nwrite_priority = \"consensus_first\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.queues,
+            QueueConfig {
+                capacity: 16,
+                overflow: OverflowPolicy::DropOldest,
+                write_priority: WritePriority::ConsensusFirst,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_large_message_config() {
+        let config = InterceptorConfig::parse("").unwrap();
+        assert_eq!(
+            config.large_messages.oversized,
+            OversizedPolicy::TruncateToController
+        );
+        assert_eq!(config.large_messages.max_buffered_bytes, 67108870);
+
+        let config = InterceptorConfig::parse(
+            "[large_messages]\nmax_buffered_bytes = 1048576\noversized = \"passthrough\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.large_messages.max_buffered_bytes, 1048576);
+        assert_eq!(
+            config.large_messages.oversized,
+            OversizedPolicy::Passthrough
+        );
+        assert_eq!(config.large_messages.preview_bytes, 256);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_malformed_frame_config() {
+        let config = InterceptorConfig::parse("").unwrap();
+        assert_eq!(config.malformed_frames, MalformedFrameConfig::default());
+        assert_eq!(config.malformed_frames.max_payload_bytes, 67108864);
+
+        let config = InterceptorConfig::parse(
+            "[malformed_frames]\nmax_payload_bytes = 1024\naction = \"kill_link\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.malformed_frames.max_payload_bytes, 1024);
+        assert_eq!(config.malformed_frames.action, MalformedAction::KillLink);
+        assert_eq!(config.malformed_frames.quarantine_directory, "quarantine");
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_keepalive_config() {
+        let config = InterceptorConfig::parse("[keepalive]\n").unwrap();
+        assert_eq!(config.keepalive, Some(KeepaliveConfig::default()));
+        assert_eq!(InterceptorConfig::parse("").unwrap().keepalive, None);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_events_config() {
+        let config =
+            InterceptorConfig::parse("[events]\nwebsocket_address = \"0.0.0.0:9000\"\n").unwrap();
+        assert_eq!(
+            config.events,
+            Some(EventsConfig {
+                websocket_address: "0.0.0.0:9000".to_string()
+            })
+        );
+        assert_eq!(InterceptorConfig::parse("").unwrap().events, None);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_tls_config() {
+        let config = InterceptorConfig::parse(
+            "[tls]\nmin_version = \"tls1.2\"\nmax_version = \"tls1.2\"\nalpn = [\"xrpl\"]\n",
+        )
+        .unwrap();
+        assert_eq!(config.tls.min_version, Some(TlsVersion::Tls12));
+        assert_eq!(config.tls.max_version, Some(TlsVersion::Tls12));
+        assert_eq!(config.tls.alpn, vec!["xrpl".to_string()]);
+        assert!(!config.tls.verify);
+        assert!(InterceptorConfig::parse("[tls]\nmin_version = \"ssl3\"\n").is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_stream_config() {
+        let config = InterceptorConfig::parse(
+            "[stream]\nbackend = \"kafka\"\nservers = [\"localhost:9092\"]\ntopic = \"packets\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.stream,
+            Some(StreamConfig {
+                backend: StreamBackend::Kafka,
+                servers: vec!["localhost:9092".to_string()],
+                topic: "packets".to_string(),
+                capacity: 65_536,
+            })
+        );
+        assert!(InterceptorConfig::parse("[stream]\nbackend = \"mqtt\"\n").is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_invalid_config() {
+        assert!(InterceptorConfig::parse("[tx_generator]\nrate = \"fast\"").is_err());
+    }
+}
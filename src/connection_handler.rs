@@ -1,22 +1,50 @@
 //! This module is responsible for intercepting and handling all messages sent between peers.
 
-use crate::packet_client::PacketClient;
-use bytes::BytesMut;
-use log::error;
-use std::cmp::min;
-use std::collections::HashMap;
-use std::sync::{mpsc, Arc};
-use std::time::{Duration, Instant};
+use crate::action::{self, Decision};
+use crate::capture_file::CapturedFrame;
+use crate::clock::Clock;
+use crate::config::{
+    InterceptionMode, KeepaliveConfig, LargeMessageConfig, MalformedAction, MalformedFrameConfig,
+    OverflowPolicy, OversizedPolicy, QueueConfig, TimeoutAction, WritePriority,
+};
+use crate::delay_schedule::DelaySchedule;
+use crate::disk_queue::DiskQueue;
+use crate::event_bus::{EventBus, EventKind};
+use crate::framing::{malformation, Frame, FrameReader, Malformation, Part, MAX_PAYLOAD_SIZE};
+use crate::handshake_response::HandshakeInfo;
+use crate::interceptor_state::{DecisionTimeout, InterceptorState, Link};
+use crate::message_queue::BoundedQueue;
+use crate::message_type::MessageType;
+use crate::packet_client::proto::{Channel, PacketAck};
+use crate::packet_client::{PacketClient, PacketMetadata};
+use crate::packet_hook::{self, HookStage, PacketContext};
+use crate::packet_timeline::PacketRecord;
+use crate::peer_stream::PeerStream;
+use crate::ping::Ping;
+use crate::protocol_version::ProtocolVersion;
+use crate::relay;
+use crate::replay::CapturedMessage;
+use crate::tls::TlsStream;
+use crate::traffic_shaping::Shaped;
+use crate::wire_format;
+use bytes::{Bytes, BytesMut};
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
-use tokio::net::TcpStream;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 use tokio::sync::Mutex;
 use tokio::task::JoinHandle;
-use tokio_openssl::SslStream;
+use tokio::time::Instant;
+use tracing::{debug, error, info, info_span, instrument, warn, Instrument, Span};
 
 const SIZE_KB: usize = 1024;
 #[allow(unused)]
 const SIZE_MB: usize = 1024 * SIZE_KB;
-const SIZE_64KB: usize = 64 * SIZE_KB;
+pub const SIZE_64KB: usize = 64 * SIZE_KB;
 #[allow(unused)]
 const SIZE_64MB: usize = 64 * SIZE_MB;
 
@@ -24,31 +52,181 @@ const SIZE_64MB: usize = 64 * SIZE_MB;
 #[derive(Debug)]
 pub struct Message {
     /// The data of the intercepted message.
-    pub data: Vec<u8>,
+    pub data: Bytes,
     /// The port of the peer the message is supposed to be sent to.
     pub peer_to_port: u16,
+    /// Where the data is located in a message above the hard cap, if it is only a part of one.
+    pub part: Option<Part>,
+    /// The span of the intercepted message, such that writing it is traced as part of it.
+    pub span: Span,
 }
 
 impl Message {
-    /// Initializes a new Message.
+    /// Initializes a new Message, which belongs to the current span.
     ///
     /// # Parameters
     /// * 'data' - the data of the intercepted message.
     /// * 'peer_to_port' - the port of the peer the message is supposed to be sent to.
-    pub fn new(data: Vec<u8>, peer_to_port: u16) -> Self {
-        Self { data, peer_to_port }
+    pub fn new(data: Bytes, peer_to_port: u16) -> Self {
+        Self {
+            data,
+            peer_to_port,
+            part: None,
+            span: Span::current(),
+        }
+    }
+
+    /// Returns the priority of a message in a write queue that writes proposals and validations first: 2 for those, 0
+    /// for relayed transactions and ledger data, and 1 for the other messages, including the parts of messages above the
+    /// hard cap, which keep their order.
+    ///
+    /// # Parameters
+    /// * 'message' - the message.
+    fn consensus_first(message: &Message) -> u8 {
+        if message.part.is_some() {
+            return 1;
+        }
+        match MessageType::from_message(&message.data) {
+            Some(message_type) if message_type.is_consensus() => 2,
+            Some(message_type) if message_type.is_relay() => 0,
+            _ => 1,
+        }
     }
+
+    /// Returns the priority of a message in a write queue that writes proposals and validations last, the inverse of
+    /// 'consensus_first'.
+    ///
+    /// # Parameters
+    /// * 'message' - the message.
+    fn consensus_last(message: &Message) -> u8 {
+        2 - Self::consensus_first(message)
+    }
+}
+
+/// Struct that represents a message that was read from a link, but not yet handled.
+#[derive(Debug)]
+pub struct ReadMessage {
+    /// The data that was read.
+    pub data: Bytes,
+    /// The moment the data was read.
+    pub read_moment: Instant,
+    /// The position of the message on its link, counting from 0.
+    pub sequence: u64,
+    /// The wall-clock time the data was read, in nanoseconds since the UNIX epoch.
+    pub capture_timestamp_ns: u64,
+    /// Where the data is located in a message above the hard cap, if it is only a part of one.
+    pub part: Option<Part>,
+}
+
+impl ReadMessage {
+    /// Initializes a new ReadMessage for data that was read just now.
+    ///
+    /// # Parameters
+    /// * 'data' - the data that was read.
+    /// * 'sequence' - the position of the message on its link.
+    pub fn new(data: Bytes, sequence: u64) -> Self {
+        Self {
+            data,
+            read_moment: Instant::now(),
+            sequence,
+            capture_timestamp_ns: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos() as u64,
+            part: None,
+        }
+    }
+
+    /// Converts the message into a record that can be spilled to disk.
+    /// The moment the data was read is restored from the capture timestamp, since an Instant can not be stored.
+    /// A whole message is stored with a part of length 0.
+    fn encode(&self) -> Vec<u8> {
+        let part = self.part.unwrap_or(Part {
+            offset: 0,
+            length: 0,
+        });
+        let mut record = Vec::with_capacity(32 + self.data.len());
+        record.extend_from_slice(&self.sequence.to_le_bytes());
+        record.extend_from_slice(&self.capture_timestamp_ns.to_le_bytes());
+        record.extend_from_slice(&(part.offset as u64).to_le_bytes());
+        record.extend_from_slice(&(part.length as u64).to_le_bytes());
+        record.extend_from_slice(&self.data);
+        record
+    }
+
+    /// Converts a record that was spilled to disk back into a message. Returns None if the record is too short.
+    ///
+    /// # Parameters
+    /// * 'record' - the record created by 'encode'.
+    fn decode(record: &[u8]) -> Option<Self> {
+        let sequence = u64::from_le_bytes(record.get(0..8)?.try_into().ok()?);
+        let capture_timestamp_ns = u64::from_le_bytes(record.get(8..16)?.try_into().ok()?);
+        let offset = u64::from_le_bytes(record.get(16..24)?.try_into().ok()?) as usize;
+        let length = u64::from_le_bytes(record.get(24..32)?.try_into().ok()?) as usize;
+        let read_time = UNIX_EPOCH + Duration::from_nanos(capture_timestamp_ns);
+        let since_read = SystemTime::now()
+            .duration_since(read_time)
+            .unwrap_or_default();
+        let now = Instant::now();
+        Some(Self {
+            data: Bytes::copy_from_slice(&record[32..]),
+            read_moment: now.checked_sub(since_read).unwrap_or(now),
+            sequence,
+            capture_timestamp_ns,
+            part: (length > 0).then_some(Part { offset, length }),
+        })
+    }
+}
+
+/// Struct that represents a message above the hard cap of which not all parts have been handled yet.
+struct OversizedMessage {
+    /// The type of the message, read from the header in its first part.
+    message_type: MessageType,
+    /// Why the parts of the message are dropped instead of forwarded, if its link is cut.
+    cut_reason: Option<&'static str>,
+    /// Whether the message is only observed: its parts are forwarded as they are read, and nothing is sent to the
+    /// controller.
+    observed: bool,
+    /// The first bytes of the message, which are sent to the controller.
+    preview: BytesMut,
+    /// The digest of the parts handled so far.
+    hasher: Sha256,
+    /// The wall-clock time the first part was read.
+    read_timestamp: DateTime<Utc>,
+    /// The moment the first part was read.
+    read_moment: Instant,
+}
+
+/// Enum that represents a change of a connection between the interceptor and a node, as seen by a stage that uses
+/// one of the halves of the connection.
+#[derive(Debug)]
+pub enum ConnectionChange<T> {
+    /// The connection was closed on purpose, the stage drops its half.
+    Down,
+    /// The connection was re-established, the stage continues with the new half.
+    Up(T),
 }
 
+/// The changes of the connections a write stage writes to, together with the port of the peer the connection leads to.
+pub type WriteChange<S = TlsStream> = (u16, ConnectionChange<(WriteHalf<S>, ProtocolVersion)>);
+
 /// Struct that represents a peer from a node's perspective.
 #[derive(Debug)]
 pub struct Peer {
     /// The port of the peer where the connection is established to. This is also its ID.
     pub port: u16,
+    /// The validation public key of the peer, which identifies it independently of its port.
+    pub public_key: String,
+    /// The response of the node to the handshake of the connection that is read from.
+    pub handshake: HandshakeInfo,
+    /// The protocol version negotiated on the connection that is written to.
+    pub write_protocol: ProtocolVersion,
     /// The half that the interceptor uses to write to that peer.
-    pub write_half: WriteHalf<SslStream<TcpStream>>,
+    pub write_half: WriteHalf<TlsStream>,
     /// the half that the node writes to if it wants to send a message to the peer.
-    pub read_half: ReadHalf<SslStream<TcpStream>>,
+    pub read_half: ReadHalf<TlsStream>,
+    /// The changes of the connection that is read from, if it can be closed and re-established during the run.
+    pub read_changes: Option<UnboundedReceiver<ConnectionChange<ReadHalf<TlsStream>>>>,
 }
 
 impl Peer {
@@ -56,19 +234,39 @@ impl Peer {
     ///
     /// # Parameters
     /// * 'port' - the port of the peer where the connection is established to. This uniquely identifies them.
+    /// * 'public_key' - the validation public key of the peer.
+    /// * 'handshake' - the response of the node to the handshake of the connection that is read from.
+    /// * 'write_protocol' - the protocol version negotiated on the connection that is written to.
     /// * 'write_half' - the half that the interceptor uses to write to that peer.
     /// * 'read_half' - the half that the node writes to if it wants to send a message to the peer.
     pub fn new(
         port: u16,
-        write_half: WriteHalf<SslStream<TcpStream>>,
-        read_half: ReadHalf<SslStream<TcpStream>>,
+        public_key: String,
+        handshake: HandshakeInfo,
+        write_protocol: ProtocolVersion,
+        write_half: WriteHalf<TlsStream>,
+        read_half: ReadHalf<TlsStream>,
     ) -> Self {
         Self {
             port,
+            public_key,
+            handshake,
+            write_protocol,
             write_half,
             read_half,
+            read_changes: None,
         }
     }
+
+    /// Allows the connection that is read from to be closed and re-established during the run.
+    /// Returns the sender through which the read stage of the link is told about the changes.
+    pub fn subscribe_read_changes(
+        &mut self,
+    ) -> UnboundedSender<ConnectionChange<ReadHalf<TlsStream>>> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.read_changes = Some(receiver);
+        sender
+    }
 }
 
 /// Struct that represents a node in the network.
@@ -76,8 +274,14 @@ impl Peer {
 pub struct Node {
     /// The port of the peer where connections can be established to. The port uniquely identifies them.
     pub port: u16,
+    /// The validation public key of the node, which identifies it independently of its port.
+    pub public_key: String,
     /// The peers the node is connected to.
     pub peers: Vec<Peer>,
+    /// The connection to the shadow node on behalf of this node, if this node is a peer of the observed node.
+    pub shadow: Option<Peer>,
+    /// The sender and receiver of the changes of the connections this node writes to.
+    write_changes: (UnboundedSender<WriteChange>, UnboundedReceiver<WriteChange>),
 }
 
 impl Node {
@@ -85,13 +289,23 @@ impl Node {
     ///
     /// # Parameters
     /// * 'port' - the port of the peer where connections can be established to. The port uniquely identifies.
-    pub fn new(port: u16) -> Self {
+    /// * 'public_key' - the validation public key of the node.
+    pub fn new(port: u16, public_key: String) -> Self {
         Self {
             port,
+            public_key,
             peers: Vec::new(),
+            shadow: None,
+            write_changes: mpsc::unbounded_channel(),
         }
     }
 
+    /// Returns the sender through which the write stage of this node is told about connections to its peers that
+    /// are closed and re-established during the run.
+    pub fn subscribe_write_changes(&self) -> UnboundedSender<WriteChange> {
+        self.write_changes.0.clone()
+    }
+
     /// Adds a Peer to the node's peer list.
     ///
     /// # Parameters
@@ -100,161 +314,1962 @@ impl Node {
         self.peers.push(peer);
     }
 
+    /// Creates the queue of the write stage of this node, through which all messages to its peers are written.
+    /// The messages waiting in the queue are prioritized by their type as configured.
+    ///
+    /// # Parameters
+    /// * 'state' - the runtime state, in which the gauge of the queue is registered.
+    /// * 'queue_config' - the capacity, overflow policy and write priority of the queue.
+    pub fn write_queue(
+        &self,
+        state: &InterceptorState,
+        queue_config: &QueueConfig,
+    ) -> Arc<BoundedQueue<Message>> {
+        let queue = BoundedQueue::new(
+            queue_config.overflow,
+            state.register_queue(format!("write:{}", self.port), queue_config.capacity),
+        );
+        Arc::new(match queue_config.write_priority {
+            WritePriority::Fifo => queue,
+            WritePriority::ConsensusFirst => queue.with_priority(Message::consensus_first),
+            WritePriority::ConsensusLast => queue.with_priority(Message::consensus_last),
+        })
+    }
+
     /// This method handles all the messages which this node wants to write to its peers.
+    /// Every link has a read stage and a decision stage, and all links of the node share a write stage.
+    /// The stages are connected by bounded queues, such that reading slows down if the controller can not keep up.
+    ///
+    /// If keepalive is configured, pings read from a link are answered on that same link through the write stage of the peer,
+    /// which is the only stage that writes to this node. Pongs are absorbed, and the interceptor sends its own pings.
     ///
     /// # Parameters
     /// * 'client' - the PacketClient where it can make requests to the controller for the action of every message.
+    /// * 'state' - the runtime state shared by all links, in which the gauges of the queues are registered.
+    /// * 'queue_config' - the capacity and overflow policy of the queues between the stages.
+    /// * 'keepalive' - the configuration of the local handling of pings, if pings should be answered locally.
+    /// * 'write_queues' - the queues of the write stages of all nodes, created with 'write_queue', by port.
+    /// * 'idle_read_timeout' - after how long without data from the node a link event is logged, if at all.
+    /// * 'large_messages' - the cap on the messages held whole, and how larger messages are handled.
+    /// * 'malformed_frames' - the limit on the payload size, and how frames that violate the protocol are handled.
+    ///
+    /// # Panics
+    /// * If messages should be spilled to disk, but the spill directory could not be created.
+    /// * If the write queue of this node or one of its peers is missing from 'write_queues'.
+    #[allow(clippy::too_many_arguments)]
     pub fn handle_messages(
         self,
         client: Arc<Mutex<PacketClient>>,
+        state: Arc<InterceptorState>,
+        queue_config: &QueueConfig,
+        keepalive: Option<&KeepaliveConfig>,
+        write_queues: &HashMap<u16, Arc<BoundedQueue<Message>>>,
+        idle_read_timeout: Option<Duration>,
+        large_messages: &LargeMessageConfig,
+        malformed_frames: &MalformedFrameConfig,
     ) -> (Vec<JoinHandle<()>>, JoinHandle<()>) {
-        let (sender, receiver) = mpsc::channel::<Message>();
+        let write_queue = write_queues[&self.port].clone();
         let mut read_threads = Vec::new();
         let mut peer_to_write_half = HashMap::new();
 
         for peer in self.peers {
+            debug!(
+                "Handshake response of node {} on the link to {}: {:?}",
+                self.port, peer.port, peer.handshake
+            );
+            let protocol = peer.handshake.version.map(|version| version.to_string());
+            state.register_link(self.port, peer.port, protocol.clone());
+            state.events.emit(EventKind::LinkConnected {
+                from_port: self.port,
+                to_port: peer.port,
+                protocol,
+            });
+            let gauge = state.register_queue(
+                format!("decision:{}->{}", self.port, peer.port),
+                queue_config.capacity,
+            );
+            let decision_queue = Arc::new(match queue_config.overflow {
+                OverflowPolicy::SpillToDisk => BoundedQueue::with_spill(
+                    gauge,
+                    DiskQueue::new(
+                        &Path::new(&queue_config.spill_directory)
+                            .join(format!("{}-{}", self.port, peer.port)),
+                        queue_config.segment_size_bytes,
+                    )
+                    .expect("Could not create the spill directory"),
+                    ReadMessage::encode,
+                    ReadMessage::decode,
+                ),
+                overflow => BoundedQueue::new(overflow, gauge),
+            });
+            let reply_queue = write_queues[&peer.port].clone();
+            if let Some(keepalive) = keepalive.filter(|keepalive| keepalive.ping_interval_secs > 0)
+            {
+                read_threads.push(tokio::spawn(Self::ping_loop(
+                    write_queues[&peer.port].clone(),
+                    self.port,
+                    peer.port,
+                    Duration::from_secs(keepalive.ping_interval_secs),
+                )));
+            }
             let read_thread = tokio::spawn(Self::read_loop(
                 peer.read_half,
+                peer.read_changes,
+                self.port,
+                peer.port,
+                decision_queue.clone(),
+                idle_read_timeout,
+                large_messages.max_buffered_bytes,
+                malformed_frames.clone(),
+                state.events.clone(),
+            ));
+            let decision_thread = tokio::spawn(Self::decision_loop(
+                decision_queue,
                 client.clone(),
+                state.clone(),
                 self.port,
                 peer.port,
-                sender.clone(),
+                self.public_key.clone(),
+                peer.public_key,
+                write_queue.clone(),
+                reply_queue,
+                keepalive.is_some(),
+                large_messages.clone(),
+                queue_config.preserve_order,
             ));
             read_threads.push(read_thread);
-            peer_to_write_half.insert(peer.port, peer.write_half);
+            read_threads.push(decision_thread);
+            peer_to_write_half.insert(peer.port, (peer.write_half, peer.write_protocol));
+        }
+        let shadow_port = self.shadow.as_ref().map(|shadow| shadow.port);
+        if let Some(shadow) = self.shadow {
+            read_threads.push(tokio::spawn(Self::shadow_read_loop(
+                shadow.read_half,
+                self.port,
+                shadow.port,
+                write_queue.clone(),
+                state.clone(),
+            )));
+            peer_to_write_half.insert(shadow.port, (shadow.write_half, shadow.write_protocol));
         }
 
-        let write_thread = tokio::spawn(Self::write_loop(receiver, peer_to_write_half));
+        // Without other senders, the channel closes and the write stage stops waiting for changes
+        let (_, write_changes) = self.write_changes;
+        let write_thread = tokio::spawn(Self::write_loop(
+            write_queue,
+            peer_to_write_half,
+            write_changes,
+            self.port,
+            shadow_port,
+            state.events.clone(),
+        ));
         (read_threads, write_thread)
     }
 
-    /// This method reads from one ReadHalf  from the node and spawns a thread that handles the intercepted message.
+    /// This method reads from one ReadHalf from the node and enqueues the messages in the data for the decision stage.
     /// All of this happens in an infinite loop to handle all the messages.
+    /// Messages that are split over several reads are reassembled, and messages above the hard cap are enqueued part by
+    /// part as they are read, see `framing`.
+    /// Whenever nothing is read for the idle timeout, a warning is logged for the link and reading continues.
+    /// While the connection is closed on purpose, nothing is read until it is re-established.
+    /// Frames that violate the peer protocol are quarantined to disk instead of enqueued, and end the loop if the link
+    /// should be killed.
     ///
     /// # Parameters
     /// * 'read_half' - the ReadHalf where it reads for messages.
-    /// * 'client' - the PacketClient used to be passed to 'handle_message_and_action'.
+    /// * 'changes' - the changes of the connection, if it can be closed and re-established during the run.
     /// * 'peer_from_port' - the port of the peer where the message came from.
     /// * 'peer_to_port' - the port of the peer the message is sent to.
-    /// * 'message_queue_sender' - the queue where the received messages are enqueued
-    async fn read_loop(
-        mut read_half: ReadHalf<SslStream<TcpStream>>,
-        client: Arc<Mutex<PacketClient>>,
+    /// * 'decision_queue' - the queue where the read data is enqueued.
+    /// * 'idle_timeout' - after how long without data a warning is logged, if at all.
+    /// * 'max_buffered' - the size of the largest message that is held whole.
+    /// * 'malformed_frames' - the limit on the payload size, and how malformed frames are handled.
+    /// * 'events' - the bus on which idle and dropped links and malformed frames are published.
+    ///
+    /// # Panics
+    /// * If the TlsStream could not be read from or has been closed.
+    #[allow(clippy::too_many_arguments)]
+    #[instrument(name = "link", skip_all, fields(from_port = peer_from_port, to_port = peer_to_port))]
+    async fn read_loop<S: PeerStream>(
+        read_half: ReadHalf<S>,
+        mut changes: Option<UnboundedReceiver<ConnectionChange<ReadHalf<S>>>>,
         peer_from_port: u16,
         peer_to_port: u16,
-        message_queue_sender: mpsc::Sender<Message>,
+        decision_queue: Arc<BoundedQueue<ReadMessage>>,
+        idle_timeout: Option<Duration>,
+        max_buffered: usize,
+        malformed_frames: MalformedFrameConfig,
+        events: Arc<EventBus>,
     ) {
+        let mut frame_reader =
+            FrameReader::new(SIZE_64KB, max_buffered, malformed_frames.max_payload_bytes);
+        let mut sequence = 0;
+        let mut read_half = Some(read_half);
         loop {
-            let mut buffer = BytesMut::with_capacity(SIZE_64KB);
-            buffer.resize(SIZE_64KB, 0);
-            let size_read = read_half
-                .read(buffer.as_mut())
-                .await
-                .expect("Could not read from SSL stream");
-
-            let read_moment = Instant::now();
-
-            buffer.resize(size_read, 0);
+            let Some(connection) = read_half.as_mut() else {
+                match Self::next_change(&mut changes).await {
+                    ConnectionChange::Up(new_half) => read_half = Some(new_half),
+                    ConnectionChange::Down => (),
+                }
+                continue;
+            };
+            let read = async {
+                let read = connection.read_buf(frame_reader.buffer());
+                match idle_timeout {
+                    // Reading is cancel safe, so no data is lost if the timeout elapses or the connection changes
+                    Some(idle_timeout) => tokio::time::timeout(idle_timeout, read).await.ok(),
+                    None => Some(read.await),
+                }
+            };
+            let outcome = tokio::select! {
+                change = Self::next_change(&mut changes) => Err(change),
+                size_read = read => Ok(size_read),
+            };
+            let size_read = match outcome {
+                Err(ConnectionChange::Down) => {
+                    debug!(
+                        "The connection from peer {} was closed on purpose",
+                        peer_from_port
+                    );
+                    read_half = None;
+                    continue;
+                }
+                Err(ConnectionChange::Up(new_half)) => {
+                    read_half = Some(new_half);
+                    continue;
+                }
+                Ok(None) => {
+                    let idle_timeout = idle_timeout.unwrap_or_default();
+                    warn!(
+                        event = "idle_timeout",
+                        "Nothing was read from peer {} for {:?}", peer_from_port, idle_timeout
+                    );
+                    events.emit(EventKind::LinkIdle {
+                        from_port: peer_from_port,
+                        to_port: peer_to_port,
+                        idle_ms: idle_timeout.as_millis() as u64,
+                    });
+                    continue;
+                }
+                Ok(Some(size_read)) => size_read,
+            };
+            let reason = match &size_read {
+                Ok(0) => Some("closed".to_string()),
+                Ok(_) => None,
+                Err(e) => Some(e.to_string()),
+            };
+            if let Some(reason) = reason {
+                events.emit(EventKind::LinkDropped {
+                    from_port: peer_from_port,
+                    to_port: peer_to_port,
+                    reason,
+                });
+            }
+            let size_read = size_read.expect("Could not read from SSL stream");
             if size_read == 0 {
                 panic!(
-                    "SslStream from peer {} to peer {} has been closed.",
+                    "TlsStream from peer {} to peer {} has been closed.",
                     peer_from_port, peer_to_port
                 );
             }
+            while let Some(frame) = frame_reader.next_frame() {
+                let read_message = match frame {
+                    Frame::Whole(data) => ReadMessage::new(data, sequence),
+                    Frame::Part(data, part) => ReadMessage {
+                        part: Some(part),
+                        ..ReadMessage::new(data, sequence)
+                    },
+                    Frame::Malformed(data, malformation) => {
+                        let kill = malformed_frames.action == MalformedAction::KillLink;
+                        warn!(
+                            event = "malformed_frame",
+                            "Read a malformed frame from peer {} ({:?}), {}",
+                            peer_from_port,
+                            malformation,
+                            if kill {
+                                "killing the link"
+                            } else {
+                                "dropping it"
+                            }
+                        );
+                        let path = Self::quarantine(
+                            &malformed_frames.quarantine_directory,
+                            peer_from_port,
+                            peer_to_port,
+                            &data,
+                            malformation,
+                        )
+                        .await;
+                        events.emit(EventKind::MalformedFrame {
+                            from_port: peer_from_port,
+                            to_port: peer_to_port,
+                            reason: malformation.name().to_string(),
+                            size: data.len(),
+                            path,
+                            link_killed: kill,
+                        });
+                        if kill {
+                            events.emit(EventKind::LinkDropped {
+                                from_port: peer_from_port,
+                                to_port: peer_to_port,
+                                reason: format!("malformed frame: {}", malformation.name()),
+                            });
+                            return;
+                        }
+                        continue;
+                    }
+                };
+                // The parts of a message share its sequence
+                if read_message
+                    .part
+                    .is_none_or(|part| part.is_last(read_message.data.len()))
+                {
+                    sequence += 1;
+                }
+                decision_queue.push(read_message).await;
+            }
+        }
+    }
 
-            tokio::spawn(Self::handle_message_and_action(
-                buffer,
+    /// Stores the bytes of a malformed frame in a file of its own in the quarantine directory of its link, and returns
+    /// the path of the file. Returns None and logs a warning if the file could not be written.
+    ///
+    /// # Parameters
+    /// * 'directory' - the quarantine directory of all links.
+    /// * 'peer_from_port' - the port of the peer the frame came from.
+    /// * 'peer_to_port' - the port of the peer the frame was sent to.
+    /// * 'data' - the bytes of the frame that were read.
+    /// * 'malformation' - how the frame violates the peer protocol, which names the file.
+    async fn quarantine(
+        directory: &str,
+        peer_from_port: u16,
+        peer_to_port: u16,
+        data: &[u8],
+        malformation: Malformation,
+    ) -> Option<String> {
+        let directory = Path::new(directory).join(format!("{}-{}", peer_from_port, peer_to_port));
+        let timestamp_ns = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let path = directory.join(format!("{}-{}.bin", timestamp_ns, malformation.name()));
+        let written = match tokio::fs::create_dir_all(&directory).await {
+            Ok(()) => tokio::fs::write(&path, data).await,
+            Err(e) => Err(e),
+        };
+        match written {
+            Ok(()) => Some(path.display().to_string()),
+            Err(e) => {
+                warn!(
+                    "Could not quarantine a malformed frame to {:?}: {}",
+                    path, e
+                );
+                None
+            }
+        }
+    }
+
+    /// Waits for the next change of one or more connections.
+    /// Waits forever if the connections can not change, or if there will be no more changes.
+    ///
+    /// # Parameters
+    /// * 'changes' - the changes of the connections, if they can change.
+    async fn next_change<T>(changes: &mut Option<UnboundedReceiver<T>>) -> T {
+        if let Some(receiver) = changes {
+            if let Some(change) = receiver.recv().await {
+                return change;
+            }
+            *changes = None;
+        }
+        std::future::pending().await
+    }
+
+    /// This method reads what the shadow node sends to the peer the interceptor pretends to be, which is not forwarded.
+    /// The messages are reassembled from what was read, as in `read_loop`, and pings are answered to keep the connection
+    /// alive, everything else is discarded. Messages above the buffer size are discarded part by part.
+    /// The loop ends when the connection is closed, without affecting the rest of the network.
+    ///
+    /// # Parameters
+    /// * 'read_half' - the ReadHalf of the connection to the shadow node.
+    /// * 'peer_port' - the port of the peer the interceptor pretends to be.
+    /// * 'shadow_port' - the port of the shadow node.
+    /// * 'write_queue' - the queue of the write stage that writes to the shadow node on behalf of the peer.
+    /// * 'state' - the runtime state, containing the statistics where a closed connection is counted.
+    #[instrument(name = "shadow", skip_all, fields(from_port = shadow_port, to_port = peer_port))]
+    async fn shadow_read_loop<S: PeerStream>(
+        mut read_half: ReadHalf<S>,
+        peer_port: u16,
+        shadow_port: u16,
+        write_queue: Arc<BoundedQueue<Message>>,
+        state: Arc<InterceptorState>,
+    ) {
+        let mut frame_reader = FrameReader::new(SIZE_64KB, SIZE_64KB, MAX_PAYLOAD_SIZE);
+        loop {
+            let reason = match read_half.read_buf(frame_reader.buffer()).await {
+                Ok(0) => "closed".to_string(),
+                Ok(_) => {
+                    while let Some(frame) = frame_reader.next_frame() {
+                        let Frame::Whole(data) = frame else {
+                            continue;
+                        };
+                        match Ping::from_message(&data) {
+                            Some(ping) if !ping.pong => {
+                                write_queue
+                                    .push(Message::new(ping.reply().to_message(), shadow_port))
+                                    .await;
+                            }
+                            _ => debug!(
+                                "Discarded {} from the shadow node",
+                                MessageType::from_message(&data).unwrap_or(MessageType::Unknown(0))
+                            ),
+                        }
+                    }
+                    continue;
+                }
+                Err(e) => e.to_string(),
+            };
+            warn!(
+                "The connection of shadow node {} to peer {} was lost: {}",
+                shadow_port, peer_port, reason
+            );
+            state.statistics.count_error("shadow_connection_lost", 1);
+            return;
+        }
+    }
+
+    /// This method handles the messages read from one link in the order they were read.
+    /// All of this happens in an infinite loop to handle all the messages.
+    /// While messages are only observed, they are forwarded as read, see `observe`.
+    ///
+    /// # Parameters
+    /// * 'decision_queue' - the queue where the read data is dequeued from.
+    /// * 'client' - the PacketClient used to be passed to 'handle_message_and_action'.
+    /// * 'state' - the runtime state used to be passed to 'handle_message_and_action'.
+    /// * 'peer_from_port' - the port of the peer where the message came from.
+    /// * 'peer_to_port' - the port of the peer the message is sent to.
+    /// * 'from_public_key' - the validation public key of the peer where the message came from.
+    /// * 'to_public_key' - the validation public key of the peer the message is sent to.
+    /// * 'write_queue' - the queue where the handled messages are enqueued.
+    /// * 'reply_queue' - the queue of the write stage that writes to the node this link reads from.
+    /// * 'answer_pings' - whether pings are answered locally. Pings that are cut off, e.g. by an eclipse or a one-way
+    ///   partition, are always answered locally, such that the connections stay open.
+    /// * 'large_messages' - how messages above the hard cap are handled, see `forward_part`.
+    /// * 'preserve_order' - whether delayed messages are released in the order they were read.
+    #[allow(clippy::too_many_arguments)]
+    #[instrument(name = "link", skip_all, fields(from_port = peer_from_port, to_port = peer_to_port))]
+    async fn decision_loop(
+        decision_queue: Arc<BoundedQueue<ReadMessage>>,
+        client: Arc<Mutex<PacketClient>>,
+        state: Arc<InterceptorState>,
+        peer_from_port: u16,
+        peer_to_port: u16,
+        from_public_key: String,
+        to_public_key: String,
+        write_queue: Arc<BoundedQueue<Message>>,
+        reply_queue: Arc<BoundedQueue<Message>>,
+        answer_pings: bool,
+        large_messages: LargeMessageConfig,
+        preserve_order: bool,
+    ) {
+        let link = state.link(peer_from_port, peer_to_port);
+        let mut oversized = None;
+        let mut delays = DelaySchedule::new(preserve_order, state.clock());
+        loop {
+            let read_message = decision_queue.pop().await;
+            if read_message.part.is_some() {
+                let metadata = PacketMetadata {
+                    from_node: from_public_key.clone(),
+                    to_node: to_public_key.clone(),
+                    sequence: read_message.sequence,
+                    capture_timestamp_ns: read_message.capture_timestamp_ns,
+                    channel: Channel::Peer,
+                    signature_valid: None,
+                };
+                Self::forward_part(
+                    read_message,
+                    &mut oversized,
+                    metadata,
+                    &client,
+                    &state,
+                    peer_from_port,
+                    peer_to_port,
+                    &write_queue,
+                    &large_messages,
+                )
+                .await;
+                continue;
+            }
+            state.track_manifests(peer_from_port, &read_message.data);
+            state.track_squelch(peer_from_port, peer_to_port, &read_message.data);
+            if state.passive().is_some() || !state.phase().intercepts() {
+                Self::observe(
+                    read_message,
+                    &state,
+                    peer_from_port,
+                    peer_to_port,
+                    &write_queue,
+                )
+                .await;
+                continue;
+            }
+            if (answer_pings
+                || state
+                    .cut_reason(peer_from_port, peer_to_port, MessageType::Ping)
+                    .is_some())
+                && Self::answer_keepalive(&read_message.data, &reply_queue, peer_from_port).await
+            {
+                continue;
+            }
+            if let Some(link) = &link {
+                Self::break_if_hit(&state, link, &read_message).await;
+            }
+            let metadata = PacketMetadata {
+                from_node: from_public_key.clone(),
+                to_node: to_public_key.clone(),
+                sequence: read_message.sequence,
+                capture_timestamp_ns: read_message.capture_timestamp_ns,
+                channel: Channel::Peer,
+                signature_valid: state.signature_valid(&read_message.data),
+            };
+            Self::handle_message_and_action(
+                read_message.data,
+                metadata,
                 client.clone(),
+                state.clone(),
                 peer_from_port,
                 peer_to_port,
-                message_queue_sender.clone(),
-                read_moment,
-            ));
+                write_queue.clone(),
+                read_message.read_moment,
+                &mut delays,
+            )
+            .await;
+        }
+    }
+
+    /// Forwards a part of a message above the hard cap without waiting for a decision, unless the link of the message
+    /// is cut, in which case all its parts are dropped. Once the last part was handled, the message is recorded, and with
+    /// the `truncate_to_controller` policy its first bytes, digest and length are sent to the controller.
+    /// Parts of a message whose first part was not handled, e.g. because it was dropped from a full queue, are dropped.
+    /// While messages are only observed or the run is outside its fault window, every part is forwarded as it is read,
+    /// as `observe` does for whole messages: links are not cut or paused, and nothing is sent to the controller.
+    ///
+    /// # Parameters
+    /// * 'read_message' - the part that was read.
+    /// * 'oversized' - the message the previous parts belong to, if it has not ended yet.
+    /// * 'metadata' - the identities of the nodes, the sequence number and the capture time of the message.
+    /// * 'client' - the PacketClient used to send the first bytes to the controller, if there is only one.
+    /// * 'state' - the runtime state, containing the timeline where the message is recorded.
+    /// * 'peer_from_port' - the port of the peer where the message came from.
+    /// * 'peer_to_port' - the port of the peer the message is sent to.
+    /// * 'write_queue' - the queue where the parts are enqueued.
+    /// * 'large_messages' - how messages above the hard cap are handled.
+    #[allow(clippy::too_many_arguments)]
+    async fn forward_part(
+        read_message: ReadMessage,
+        oversized: &mut Option<OversizedMessage>,
+        metadata: PacketMetadata,
+        client: &Arc<Mutex<PacketClient>>,
+        state: &Arc<InterceptorState>,
+        peer_from_port: u16,
+        peer_to_port: u16,
+        write_queue: &BoundedQueue<Message>,
+        large_messages: &LargeMessageConfig,
+    ) {
+        let Some(part) = read_message.part else {
+            return;
+        };
+        if part.is_first() {
+            let message_type =
+                MessageType::from_message(&read_message.data).unwrap_or(MessageType::Unknown(0));
+            info!(
+                "Forwarding {} of {} bytes from peer {} part by part, it is too large to be held whole",
+                message_type, part.length, peer_from_port
+            );
+            let observed = state.passive().is_some() || !state.phase().intercepts();
+            *oversized = Some(OversizedMessage {
+                message_type,
+                cut_reason: if observed {
+                    None
+                } else {
+                    state.cut_reason(peer_from_port, peer_to_port, message_type)
+                },
+                observed,
+                preview: BytesMut::new(),
+                hasher: Sha256::new(),
+                read_timestamp: DateTime::from_timestamp_nanos(
+                    read_message.capture_timestamp_ns as i64,
+                ),
+                read_moment: read_message.read_moment,
+            });
+        }
+        let Some(message) = oversized.as_mut() else {
+            return;
+        };
+        message.hasher.update(&read_message.data);
+        let preview_missing = large_messages
+            .preview_bytes
+            .saturating_sub(message.preview.len())
+            .min(read_message.data.len());
+        message
+            .preview
+            .extend_from_slice(&read_message.data[..preview_missing]);
+        if message.cut_reason.is_none() {
+            if !message.observed && state.is_paused() {
+                state
+                    .wait_until_resumed()
+                    .instrument(info_span!("paused"))
+                    .await;
+            }
+            write_queue
+                .push(Message {
+                    part: Some(part),
+                    ..Message::new(read_message.data.clone(), peer_to_port)
+                })
+                .await;
+        }
+        if !part.is_last(read_message.data.len()) {
+            return;
+        }
+        let Some(message) = oversized.take() else {
+            return;
+        };
+        let digest = message.hasher.finalize();
+        let latency = state.clock().elapsed(message.read_moment);
+        if message.observed
+            && state
+                .passive()
+                .is_some_and(|observer| observer.measure(latency))
+        {
+            debug!(
+                "Forwarding a message took {:?}, above the budget of the passive observation mode",
+                latency
+            );
+        }
+        let send_amount = match message.cut_reason {
+            Some(cut_reason) => {
+                state.events.emit(EventKind::packet_dropped(
+                    peer_from_port,
+                    peer_to_port,
+                    message.message_type,
+                    Some(metadata.sequence),
+                    cut_reason,
+                ));
+                0
+            }
+            None => 1,
+        };
+        state.record(PacketRecord {
+            timestamp: message.read_timestamp,
+            from_port: peer_from_port,
+            to_port: peer_to_port,
+            sequence: metadata.sequence,
+            message_type: message.message_type,
+            ledger_sequence: None,
+            round: state.rounds.current(),
+            phase: state.phase(),
+            size: part.length,
+            hash: hex::encode(&digest),
+            sent_size: part.length,
+            action: 0,
+            send_amount,
+            controller_latency: None,
+            latency,
+        });
+        if message.observed
+            || send_amount == 0
+            || large_messages.oversized != OversizedPolicy::TruncateToController
+        {
+            return;
+        }
+        let (endpoint, client) = match state.controller_pool() {
+            Some(pool) => {
+                let (index, client) = pool.client_for(peer_from_port, peer_to_port);
+                (Some(index), client)
+            }
+            None => (None, client.clone()),
+        };
+        let state = state.clone();
+        tokio::spawn(
+            async move {
+                let result = client
+                    .lock()
+                    .await
+                    .send_preview(
+                        message.preview.freeze(),
+                        Bytes::copy_from_slice(&digest),
+                        part.length as u64,
+                        u32::from(peer_from_port),
+                        u32::from(peer_to_port),
+                        &metadata,
+                    )
+                    .await;
+                if let Err(e) = result {
+                    error!(
+                        "Could not send the preview of a large message to the controller: {}",
+                        e
+                    );
+                    state.statistics.count_error("mirror_failed", 1);
+                    if let (Some(pool), Some(index)) = (state.controller_pool(), endpoint) {
+                        pool.report_failure(index, &e.to_string());
+                    }
+                }
+            }
+            .in_current_span(),
+        );
+    }
+
+    /// Forwards a message as soon as it was read, while messages are only observed or the run is outside its fault
+    /// window. Once it is queued to be written, and mirrored to the shadow node if there is one, the latency that was
+    /// added is measured if messages are only observed, and the message is recorded.
+    /// Pings are forwarded as well, rather than answered locally.
+    ///
+    /// # Parameters
+    /// * 'read_message' - the message that was read.
+    /// * 'state' - the runtime state, containing the measurement of the added latency and the timeline.
+    /// * 'peer_from_port' - the port of the peer where the message came from.
+    /// * 'peer_to_port' - the port of the peer the message is sent to.
+    /// * 'write_queue' - the queue where the message is enqueued.
+    async fn observe(
+        read_message: ReadMessage,
+        state: &InterceptorState,
+        peer_from_port: u16,
+        peer_to_port: u16,
+        write_queue: &BoundedQueue<Message>,
+    ) {
+        let message = read_message.data;
+        write_queue
+            .push(Message::new(message.clone(), peer_to_port))
+            .await;
+        let latency = state.clock().elapsed(read_message.read_moment);
+        if let Some(shadow_port) = state.shadow_port(peer_to_port) {
+            write_queue
+                .push(Message::new(message.clone(), shadow_port))
+                .await;
+        }
+        if state
+            .passive()
+            .is_some_and(|observer| observer.measure(latency))
+        {
+            debug!(
+                "Forwarding a message took {:?}, above the budget of the passive observation mode",
+                latency
+            );
+        }
+
+        state.capture_to_file(CapturedFrame {
+            timestamp_ns: read_message.capture_timestamp_ns,
+            from_port: peer_from_port,
+            to_port: peer_to_port,
+            sequence: read_message.sequence,
+            data: message.clone(),
+        });
+        state.record(PacketRecord {
+            timestamp: DateTime::from_timestamp_nanos(read_message.capture_timestamp_ns as i64),
+            from_port: peer_from_port,
+            to_port: peer_to_port,
+            sequence: read_message.sequence,
+            message_type: MessageType::from_message(&message).unwrap_or(MessageType::Unknown(0)),
+            ledger_sequence: wire_format::ledger_sequence(&message),
+            round: state.rounds.round(&message),
+            phase: state.phase(),
+            size: message.len(),
+            hash: hex::encode(Sha256::digest(&message)),
+            sent_size: message.len(),
+            action: 0,
+            send_amount: 1,
+            controller_latency: None,
+            latency,
+        });
+    }
+
+    /// Halts the link before a message is handled if it matches a breakpoint or the link is being stepped,
+    /// and waits until the link is continued or stepped.
+    ///
+    /// # Parameters
+    /// * 'state' - the runtime state, containing the breakpoints and the event bus.
+    /// * 'link' - the link the message was read from.
+    /// * 'read_message' - the message.
+    async fn break_if_hit(state: &InterceptorState, link: &Link, read_message: &ReadMessage) {
+        let Some(hit) = state.breakpoint_hit(link, &read_message.data, read_message.sequence)
+        else {
+            return;
+        };
+        info!(
+            breakpoint = hit.breakpoint_id,
+            "Halted before {} with sequence {}", hit.message_type, hit.sequence
+        );
+        state.events.emit(EventKind::BreakpointHit(hit.clone()));
+        link.debugger
+            .halt(hit)
+            .instrument(info_span!("breakpoint"))
+            .await;
+        state.events.emit(EventKind::LinkResumed {
+            from_port: link.from_port,
+            to_port: link.to_port,
+            step: link.debugger.is_stepping(),
+        });
+    }
+
+    /// Answers a ping on the link it was read from, and absorbs a pong.
+    /// Returns whether the message was a ping or a pong, in which case it should not be forwarded.
+    ///
+    /// # Parameters
+    /// * 'data' - the data that was read.
+    /// * 'reply_queue' - the queue of the write stage that writes to the peer the message came from.
+    /// * 'peer_from_port' - the port of the peer where the message came from.
+    async fn answer_keepalive(
+        data: &[u8],
+        reply_queue: &BoundedQueue<Message>,
+        peer_from_port: u16,
+    ) -> bool {
+        match Ping::from_message(data) {
+            None => false,
+            Some(ping) if ping.pong => {
+                debug!("Absorbed pong with seq {:?}", ping.seq);
+                true
+            }
+            Some(ping) => {
+                reply_queue
+                    .push(Message::new(ping.reply().to_message(), peer_from_port))
+                    .await;
+                debug!("Answered ping with seq {:?}", ping.seq);
+                true
+            }
+        }
+    }
+
+    /// This method sends a ping of the interceptor to the node on one link at a fixed interval.
+    /// All of this happens in an infinite loop, the pongs are absorbed by 'answer_keepalive'.
+    ///
+    /// # Parameters
+    /// * 'reply_queue' - the queue of the write stage that writes to the node on this link.
+    /// * 'peer_from_port' - the port of the node the pings are sent to.
+    /// * 'peer_to_port' - the port of the peer at the other end of the link.
+    /// * 'interval' - the time between two pings.
+    #[instrument(name = "link", skip_all, fields(from_port = peer_from_port, to_port = peer_to_port))]
+    async fn ping_loop(
+        reply_queue: Arc<BoundedQueue<Message>>,
+        peer_from_port: u16,
+        peer_to_port: u16,
+        interval: Duration,
+    ) {
+        let mut interval = tokio::time::interval(interval);
+        let mut seq: u32 = 0;
+        loop {
+            interval.tick().await;
+            let ping = Ping {
+                pong: false,
+                seq: Some(seq),
+                ping_time: Some(
+                    SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs(),
+                ),
+                net_time: None,
+            };
+            reply_queue
+                .push(Message::new(ping.to_message(), peer_from_port))
+                .await;
+            seq = seq.wrapping_add(1);
         }
     }
 
     /// This method handles an intercepted message.
-    /// It asks the controller what action to take, and takes that action.
+    /// It is decided on as described by 'decide', after which the delay is scaled by the time dilation factor.
     /// Once the action has taken, it sends the message to a queue where another thread will immediately send the message to the corresponding peer.
+    /// Delayed messages are queued for the delay queue of the link, such that they do not hold up the messages read after
+    /// them, see `DelaySchedule`.
     ///
     /// # Parameters
     /// * 'buffered_message' - the received message inside a buffer.
+    /// * 'metadata' - the identities of the nodes, the sequence number and the capture time of the message.
     /// * 'client' - the PacketClient used to send a request to the controller.
+    /// * 'state' - the runtime state, containing the timeline where the handled message is recorded.
     /// * 'peer_from_port' - the port of the peer where the message came from.
     /// * 'peer_to_port' - the port of the peer the message is sent to.
-    /// * 'message_queue_sender' - the queue where the received messages are enqueued
+    /// * 'write_queue' - the queue where the handled messages are enqueued.
     /// * 'read_moment' - the moment the message was read, used if message needs to be delayed.
+    /// * 'delays' - the releases of the delayed messages of the link.
     ///
     /// # Panics
-    /// * If an error occurred while requesting an action from the controller.
-    /// * If the message sent to the queue will never be received, meaning there is no receiver.
+    /// * If an error occurred while requesting an action from the controller, and there is no circuit breaker.
+    #[allow(clippy::too_many_arguments)]
+    #[instrument(
+        name = "message",
+        skip_all,
+        fields(link, sequence = metadata.sequence, message_type, size, mode, action, send_amount, controller_latency_ms)
+    )]
     async fn handle_message_and_action(
-        buffered_message: BytesMut,
+        buffered_message: Bytes,
+        metadata: PacketMetadata,
         client: Arc<Mutex<PacketClient>>,
+        state: Arc<InterceptorState>,
         peer_from_port: u16,
         peer_to_port: u16,
-        message_queue_sender: mpsc::Sender<Message>,
+        write_queue: Arc<BoundedQueue<Message>>,
         read_moment: Instant,
+        delays: &mut DelaySchedule,
     ) {
         let message = Self::check_message(buffered_message);
-        let response = client
-            .lock()
-            .await
-            .send_packet(message, u32::from(peer_from_port), u32::from(peer_to_port))
-            .await
-            .expect("Error occurred while requesting message and action from the controller.");
-
-        match response.action {
-            0 => (),
-            delay_ms => {
-                let delay_ms = min(delay_ms, 30000) as u128;
-                let time_elapsed = read_moment.elapsed().as_millis();
-                if time_elapsed < delay_ms {
-                    let delay_compensated = delay_ms - time_elapsed;
-                    tokio::time::sleep(Duration::from_millis(delay_compensated as u64)).await
+        let read_timestamp = DateTime::from_timestamp_nanos(metadata.capture_timestamp_ns as i64);
+        let message_type = MessageType::from_message(&message).unwrap_or(MessageType::Unknown(0));
+        let message_size = message.len();
+        let span = Span::current();
+        span.record(
+            "link",
+            tracing::field::display(format!("{}->{}", peer_from_port, peer_to_port)),
+        );
+        span.record("message_type", tracing::field::display(message_type));
+        span.record("size", message_size);
+
+        let sequence = metadata.sequence;
+        let ledger_sequence = wire_format::ledger_sequence(&message);
+        let round = state.rounds.round(&message);
+        if let Some(capture_buffer) = state.capture_buffer() {
+            capture_buffer.capture(CapturedMessage {
+                from_port: peer_from_port,
+                to_port: peer_to_port,
+                message_type,
+                ledger_sequence,
+                data: message.clone(),
+            });
+        }
+        state.capture_to_file(CapturedFrame {
+            timestamp_ns: metadata.capture_timestamp_ns,
+            from_port: peer_from_port,
+            to_port: peer_to_port,
+            sequence,
+            data: message.clone(),
+        });
+        let hash = hex::encode(Sha256::digest(&message));
+        let (mut decision, controller_latency) = Self::decide(
+            message.clone(),
+            metadata,
+            client,
+            state.clone(),
+            peer_from_port,
+            peer_to_port,
+            message_type,
+        )
+        .await;
+        decision.delay = state.dilate(decision.delay);
+        span.record("action", decision.delay_ms());
+        span.record("send_amount", decision.send_amount);
+
+        let record = PacketRecord {
+            timestamp: read_timestamp,
+            from_port: peer_from_port,
+            to_port: peer_to_port,
+            sequence,
+            message_type,
+            ledger_sequence,
+            round,
+            phase: state.phase(),
+            size: message_size,
+            hash,
+            sent_size: decision.data.len(),
+            action: decision.delay_ms(),
+            send_amount: decision.send_amount,
+            controller_latency,
+            latency: Duration::ZERO,
+        };
+
+        delays
+            .schedule(
+                Self::remaining_delay(decision.delay, read_moment, state.clock().as_ref()),
+                Self::deliver(decision, record, state, write_queue, read_moment),
+            )
+            .await;
+    }
+
+    /// Decides on an intercepted message, without delivering it.
+    /// Messages cut off by an eclipse, a one-way partition or a blackhole are dropped without asking the controller.
+    /// While the circuit breaker of the controller channel is open, messages are forwarded without asking it.
+    /// Otherwise, the hooks of the before-controller stage decide on it first, and if they drop it the controller is
+    /// not asked.
+    /// Depending on the interception mode of its type and link, it asks the controller what action to take,
+    /// forwards it as-is while sending a copy to the controller (mirror), or only forwards it as-is (passthrough).
+    /// The hooks of the after-controller stage, the actions the controller set for the copies of a broadcast with a
+    /// fan-out and the local mutation rules decide on the message as it was left.
+    /// Then the shaping rule that limits its type on the link and the rule of the link, set through the admin API, are
+    /// applied on top of the action. Copies of broadcasts are counted before the rule of the link, and dropped there
+    /// if they are redundant and deduplicated.
+    /// Finally, if the interceptor is chained to a next one, that interceptor decides on the message as it was left.
+    /// Returns the decision, of which the delay is not yet dilated, together with the latency of the controller if it
+    /// was asked.
+    ///
+    /// # Parameters
+    /// * 'message' - the message.
+    /// * 'metadata' - the identities of the nodes, the sequence number and the capture time of the message.
+    /// * 'client' - the PacketClient used to send a request to the controller.
+    /// * 'state' - the runtime state, containing the rules, hooks and event bus.
+    /// * 'peer_from_port' - the port of the peer where the message came from.
+    /// * 'peer_to_port' - the port of the peer the message is sent to.
+    /// * 'message_type' - the type of the message.
+    ///
+    /// # Panics
+    /// * If an error occurred while requesting an action from the controller, and there is no circuit breaker.
+    pub async fn decide(
+        message: Bytes,
+        metadata: PacketMetadata,
+        client: Arc<Mutex<PacketClient>>,
+        state: Arc<InterceptorState>,
+        peer_from_port: u16,
+        peer_to_port: u16,
+        message_type: MessageType,
+    ) -> (Decision, Option<Duration>) {
+        let mode = state.interception_mode(peer_from_port, peer_to_port, message_type);
+        Span::current().record("mode", tracing::field::debug(mode));
+        let sequence = metadata.sequence;
+        let mut controller_latency = None;
+        // Whether this copy set the fan-out of its broadcast, whose actions it already carries
+        let mut carried_fan_out = false;
+
+        let cut_reason = state.cut_reason(peer_from_port, peer_to_port, message_type);
+        let context = PacketContext {
+            from_port: peer_from_port,
+            to_port: peer_to_port,
+            message_type,
+            sequence,
+        };
+        let before = match cut_reason {
+            Some(_) => Decision::forward(message.clone()),
+            None => {
+                packet_hook::run_hooks(
+                    HookStage::BeforeController,
+                    Decision::forward(message.clone()),
+                    &context,
+                    &state,
+                )
+                .await
+            }
+        };
+        let message = before.data.clone();
+        let original = message.clone();
+        let decision = match mode {
+            _ if cut_reason.is_some() => {
+                state.events.emit(EventKind::packet_dropped(
+                    peer_from_port,
+                    peer_to_port,
+                    message_type,
+                    Some(metadata.sequence),
+                    cut_reason.unwrap_or_default(),
+                ));
+                Decision::dropped(message)
+            }
+            _ if before.send_amount == 0 => Decision::dropped(message),
+            InterceptionMode::Passthrough => Decision::forward(message),
+            InterceptionMode::Mirror => {
+                tokio::spawn(
+                    Self::mirror(
+                        message.clone(),
+                        metadata.clone(),
+                        client,
+                        state.clone(),
+                        peer_from_port,
+                        peer_to_port,
+                    )
+                    .in_current_span(),
+                );
+                Decision::forward(message)
+            }
+            InterceptionMode::Intercept => 'intercept: {
+                let circuit_breaker = state.circuit_breaker();
+                if circuit_breaker.is_some_and(|breaker| !breaker.allow()) {
+                    break 'intercept Decision::forward(message);
+                }
+                let request_moment = Instant::now();
+                let mut request = tokio::spawn(
+                    Self::request_action(
+                        message.clone(),
+                        metadata.clone(),
+                        client,
+                        state.clone(),
+                        peer_from_port,
+                        peer_to_port,
+                    )
+                    .instrument(info_span!("controller_decision")),
+                );
+                let result = match state.decision_timeout() {
+                    None => request.await,
+                    Some(timeout) => match tokio::time::timeout(timeout.budget, &mut request).await
+                    {
+                        Ok(result) => result,
+                        Err(_) => {
+                            if let Some(breaker) = circuit_breaker {
+                                breaker.record(false);
+                            }
+                            break 'intercept Self::decide_without_controller(
+                                message,
+                                request,
+                                timeout,
+                                &state,
+                                peer_from_port,
+                                peer_to_port,
+                                message_type,
+                                sequence,
+                            );
+                        }
+                    },
+                };
+                let result = result.unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()));
+                let (response, proto_version) = match (result, circuit_breaker) {
+                    (Ok(response), _) => {
+                        if let Some(breaker) = circuit_breaker {
+                            breaker.record(true);
+                        }
+                        response
+                    }
+                    (Err(e), Some(breaker)) => {
+                        breaker.record(false);
+                        error!(
+                            "Could not request an action from the controller, forwarding unchanged: {}",
+                            e
+                        );
+                        state.statistics.count_error("controller_failed", 1);
+                        break 'intercept Decision::forward(message);
+                    }
+                    (Err(e), None) => panic!(
+                        "Error occurred while requesting message and action from the controller: {}",
+                        e
+                    ),
+                };
+                let latency = request_moment.elapsed();
+                Span::current().record("controller_latency_ms", latency.as_secs_f64() * 1000.0);
+                controller_latency = Some(latency);
+                if let Some(link) = state.link(peer_from_port, peer_to_port) {
+                    link.record_controller_rtt(latency);
+                }
+                debug!("Received action from the controller");
+                let fan_out = action::fan_out(&response).unwrap_or_default();
+                let (mut decision, fan_out) =
+                    match Decision::from_ack(message.clone(), response, proto_version) {
+                        Ok(decision) => (decision, fan_out),
+                        Err(e) => {
+                            error!(
+                                "Rejected action from the controller, forwarding unchanged: {}",
+                                e
+                            );
+                            state.statistics.count_error("rejected_action", 1);
+                            (Decision::forward(message.clone()), Vec::new())
+                        }
+                    };
+                if decision.resign {
+                    match state.resign(peer_from_port, &decision.data) {
+                        Ok(resigned) => decision.data = resigned,
+                        Err(e) => {
+                            error!(
+                                "Could not sign the mutated message again, sending it as mutated: {}",
+                                e
+                            );
+                            state.statistics.count_error("resign_failed", 1);
+                        }
+                    }
                 }
+                if !fan_out.is_empty() {
+                    carried_fan_out = state.set_fan_out(&message, fan_out);
+                    if !carried_fan_out {
+                        warn!(
+                            "Ignored the fan-out of the controller for {}, which is only applied to tracked broadcasts",
+                            message_type
+                        );
+                        state.statistics.count_error("fan_out_ignored", 1);
+                    }
+                }
+                if decision.send_amount == 0 {
+                    state.events.emit(EventKind::packet_dropped(
+                        peer_from_port,
+                        peer_to_port,
+                        message_type,
+                        Some(metadata.sequence),
+                        "controller",
+                    ));
+                } else if decision.data != message {
+                    state.events.emit(EventKind::MutationApplied {
+                        from_port: peer_from_port,
+                        to_port: peer_to_port,
+                        message_type: message_type.to_string(),
+                        sequence: metadata.sequence,
+                        original_size: message.len(),
+                        mutated_size: decision.data.len(),
+                    });
+                }
+                decision
             }
+        };
+        let decision = packet_hook::run_hooks(
+            HookStage::AfterController,
+            before.then(decision),
+            &context,
+            &state,
+        )
+        .await;
+        let decision = if carried_fan_out {
+            decision
+        } else {
+            Self::apply_fan_out(
+                decision,
+                &original,
+                &state,
+                peer_from_port,
+                peer_to_port,
+                message_type,
+                sequence,
+            )
+        };
+        let decision = Self::apply_mutation_rules(
+            decision,
+            &state,
+            peer_from_port,
+            peer_to_port,
+            message_type,
+            sequence,
+        );
+        let decision = Self::apply_equivocation(
+            decision,
+            &state,
+            peer_from_port,
+            peer_to_port,
+            message_type,
+            sequence,
+        );
+        let decision = Self::apply_manifest_rules(
+            decision,
+            &state,
+            peer_from_port,
+            peer_to_port,
+            message_type,
+            sequence,
+        );
+        let decision = Self::apply_squelch_rules(
+            decision,
+            &state,
+            peer_from_port,
+            peer_to_port,
+            message_type,
+            sequence,
+        );
+        let decision = Self::apply_shaping(
+            decision,
+            &state,
+            peer_from_port,
+            peer_to_port,
+            message_type,
+            sequence,
+        );
+        let decision = Self::apply_gray_failures(
+            decision,
+            &state,
+            peer_from_port,
+            peer_to_port,
+            message_type,
+            sequence,
+        );
+        let decision = Self::apply_dedupe(
+            decision,
+            &state,
+            peer_from_port,
+            peer_to_port,
+            message_type,
+            sequence,
+        );
+        let decision = Self::apply_link_rule(
+            decision,
+            &state,
+            peer_from_port,
+            peer_to_port,
+            message_type,
+            sequence,
+        );
+        let decision = relay::relay(decision, &metadata, &context, &state).await;
+        (decision, controller_latency)
+    }
+
+    /// Applies the actions of the controller for the copies of a broadcast sent to the destination of a message, as the
+    /// controller set them with the fan-out of another copy, to a decision that sends it. If the actions are not valid
+    /// for the message, it is sent as decided.
+    ///
+    /// # Parameters
+    /// * 'decision' - the decision made for the message.
+    /// * 'message' - the message before the controller decided on it, by which its broadcast is recognized.
+    /// * 'state' - the runtime state, containing the tracked broadcasts and the event bus.
+    /// * 'peer_from_port' - the port of the peer where the message came from.
+    /// * 'peer_to_port' - the port of the peer the message is sent to.
+    /// * 'message_type' - the type of the message.
+    /// * 'sequence' - the position of the message on its link.
+    fn apply_fan_out(
+        decision: Decision,
+        message: &[u8],
+        state: &InterceptorState,
+        peer_from_port: u16,
+        peer_to_port: u16,
+        message_type: MessageType,
+        sequence: u64,
+    ) -> Decision {
+        if decision.send_amount == 0 {
+            return decision;
         }
+        let Some(actions) = state.fan_out(peer_to_port, message) else {
+            return decision;
+        };
+        let mut next = match Decision::from_actions(decision.data.clone(), &actions) {
+            Ok(next) => next,
+            Err(e) => {
+                error!(
+                    "Could not apply the fan-out of the controller to {}, sending it as decided: {}",
+                    message_type, e
+                );
+                state.statistics.count_error("rejected_fan_out", 1);
+                return decision;
+            }
+        };
+        if next.resign {
+            match state.resign(peer_from_port, &next.data) {
+                Ok(resigned) => next.data = resigned,
+                Err(e) => {
+                    error!(
+                        "Could not sign the mutated message again, sending it as mutated: {}",
+                        e
+                    );
+                    state.statistics.count_error("resign_failed", 1);
+                }
+            }
+        }
+        if next.send_amount == 0 {
+            state.events.emit(EventKind::packet_dropped(
+                peer_from_port,
+                peer_to_port,
+                message_type,
+                Some(sequence),
+                "fan_out",
+            ));
+        } else if next.data != decision.data {
+            state.events.emit(EventKind::MutationApplied {
+                from_port: peer_from_port,
+                to_port: peer_to_port,
+                message_type: message_type.to_string(),
+                sequence,
+                original_size: decision.data.len(),
+                mutated_size: next.data.len(),
+            });
+        }
+        decision.then(next)
+    }
 
-        for _ in 0..response.send_amount {
-            message_queue_sender
-                .send(Message::new(response.data.clone(), peer_to_port))
-                .unwrap_or_else(|_| {
-                    panic!(
-                        "Could not write message from {} to {} to the queue.",
-                        peer_from_port, peer_to_port,
-                    )
+    /// Applies the local mutation rules that match a message to a decision that sends it.
+    /// If a rule can not be applied, the message is sent as decided.
+    ///
+    /// # Parameters
+    /// * 'decision' - the decision made for the message.
+    /// * 'state' - the runtime state, containing the mutation rules and the event bus.
+    /// * 'peer_from_port' - the port of the peer where the message came from.
+    /// * 'peer_to_port' - the port of the peer the message is sent to.
+    /// * 'message_type' - the type of the message.
+    /// * 'sequence' - the position of the message on its link.
+    fn apply_mutation_rules(
+        mut decision: Decision,
+        state: &InterceptorState,
+        peer_from_port: u16,
+        peer_to_port: u16,
+        message_type: MessageType,
+        sequence: u64,
+    ) -> Decision {
+        if decision.send_amount == 0 {
+            return decision;
+        }
+        match state.mutate(peer_from_port, peer_to_port, message_type, &decision.data) {
+            None => (),
+            Some(Ok(mutated)) => {
+                state.events.emit(EventKind::MutationApplied {
+                    from_port: peer_from_port,
+                    to_port: peer_to_port,
+                    message_type: message_type.to_string(),
+                    sequence,
+                    original_size: decision.data.len(),
+                    mutated_size: mutated.len(),
                 });
+                decision.data = mutated;
+            }
+            Some(Err(e)) => {
+                error!(
+                    "Could not apply the mutation rules to {}, sending it as decided: {}",
+                    message_type, e
+                );
+                state.statistics.count_error("mutation_rule_failed", 1);
+            }
         }
+        decision
     }
 
-    /// Checks a message that is contained inside buf if it is valid.
-    /// Returns the validated message as a Vec\<u8>\.
+    /// Applies the equivocation rule of the node a proposal comes from to a decision that sends it: the proposal is
+    /// replaced by the variant delivered to the peer and signed again. If the variant can not be made, the proposal is
+    /// sent as decided.
     ///
     /// # Parameters
-    /// * 'buffered_message' - the message inside a buffer to be checked.
+    /// * 'decision' - the decision made for the message.
+    /// * 'state' - the runtime state, containing the equivocation rules and the event bus.
+    /// * 'peer_from_port' - the port of the peer where the message came from.
+    /// * 'peer_to_port' - the port of the peer the message is sent to.
+    /// * 'message_type' - the type of the message.
+    /// * 'sequence' - the position of the message on its link.
+    fn apply_equivocation(
+        mut decision: Decision,
+        state: &InterceptorState,
+        peer_from_port: u16,
+        peer_to_port: u16,
+        message_type: MessageType,
+        sequence: u64,
+    ) -> Decision {
+        if decision.send_amount == 0 {
+            return decision;
+        }
+        match state.equivocate(peer_from_port, peer_to_port, message_type, &decision.data) {
+            None => (),
+            Some(Ok((variant, equivocated))) => {
+                state.events.emit(EventKind::ProposalEquivocated {
+                    from_port: peer_from_port,
+                    to_port: peer_to_port,
+                    sequence,
+                    variant,
+                });
+                decision.data = equivocated;
+            }
+            Some(Err(e)) => {
+                error!(
+                    "Could not equivocate the proposal from {} to {}, sending it as decided: {}",
+                    peer_from_port, peer_to_port, e
+                );
+                state.statistics.count_error("equivocation_failed", 1);
+            }
+        }
+        decision
+    }
+
+    /// Applies the manifest rules that match the link of an mtMANIFESTS message to a decision that sends it: its
+    /// manifests are rewritten, or it is dropped if all its manifests are suppressed. If the manifests can not be
+    /// rewritten, the message is sent as decided.
     ///
-    /// # Panics
-    /// * If a compressed message was received.
-    /// * If the message has an unknown version header.
-    /// * If the payload size could not be parsed.
-    /// * If the payload size is bigger than the buffer's size, meaning it only read a part of the message.
-    fn check_message(buffered_message: BytesMut) -> Vec<u8> {
-        // Check if the most significant bit turned on, indicating a compressed message
-        if (buffered_message[0] & 0b1000_0000) != 0 {
-            panic!(
-                "Received compressed message: bytes[0] = {:?}",
-                buffered_message[0]
-            );
+    /// # Parameters
+    /// * 'decision' - the decision made for the message.
+    /// * 'state' - the runtime state, containing the manifest rules and the event bus.
+    /// * 'peer_from_port' - the port of the peer where the message came from.
+    /// * 'peer_to_port' - the port of the peer the message is sent to.
+    /// * 'message_type' - the type of the message.
+    /// * 'sequence' - the position of the message on its link.
+    fn apply_manifest_rules(
+        mut decision: Decision,
+        state: &InterceptorState,
+        peer_from_port: u16,
+        peer_to_port: u16,
+        message_type: MessageType,
+        sequence: u64,
+    ) -> Decision {
+        if decision.send_amount == 0 || message_type != MessageType::Manifests {
+            return decision;
+        }
+        match state.rewrite_manifests(peer_from_port, peer_to_port, &decision.data) {
+            None => (),
+            Some(Ok(Some(rewritten))) => {
+                state.events.emit(EventKind::MutationApplied {
+                    from_port: peer_from_port,
+                    to_port: peer_to_port,
+                    message_type: message_type.to_string(),
+                    sequence,
+                    original_size: decision.data.len(),
+                    mutated_size: rewritten.len(),
+                });
+                decision.data = rewritten;
+            }
+            Some(Ok(None)) => {
+                decision.send_amount = 0;
+                state.events.emit(EventKind::packet_dropped(
+                    peer_from_port,
+                    peer_to_port,
+                    message_type,
+                    Some(sequence),
+                    "manifest",
+                ));
+            }
+            Some(Err(e)) => {
+                error!(
+                    "Could not apply the manifest rules to {}, sending it as decided: {}",
+                    message_type, e
+                );
+                state.statistics.count_error("manifest_rule_failed", 1);
+            }
         }
+        decision
+    }
 
-        // Check if any of the 6 most significant bits are turned on, indicating an unknown header
-        if (buffered_message[0] & 0b1111_1100) != 0 {
-            panic!(
-                "Unknown version header: bytes[0] = {:?}",
-                buffered_message[0]
-            );
+    /// Applies the squelch rules that match the link and validator of an mtSQUELCH message to a decision that sends it:
+    /// its squelch is amplified, or it is dropped if it is suppressed. If the squelch can not be overridden, the message
+    /// is sent as decided.
+    ///
+    /// # Parameters
+    /// * 'decision' - the decision made for the message.
+    /// * 'state' - the runtime state, containing the squelch rules and the event bus.
+    /// * 'peer_from_port' - the port of the peer where the message came from.
+    /// * 'peer_to_port' - the port of the peer the message is sent to.
+    /// * 'message_type' - the type of the message.
+    /// * 'sequence' - the position of the message on its link.
+    fn apply_squelch_rules(
+        mut decision: Decision,
+        state: &InterceptorState,
+        peer_from_port: u16,
+        peer_to_port: u16,
+        message_type: MessageType,
+        sequence: u64,
+    ) -> Decision {
+        if decision.send_amount == 0 || message_type != MessageType::Squelch {
+            return decision;
+        }
+        match state.override_squelch(peer_from_port, peer_to_port, &decision.data) {
+            None => (),
+            Some(Ok(Some(overridden))) => {
+                state.events.emit(EventKind::MutationApplied {
+                    from_port: peer_from_port,
+                    to_port: peer_to_port,
+                    message_type: message_type.to_string(),
+                    sequence,
+                    original_size: decision.data.len(),
+                    mutated_size: overridden.len(),
+                });
+                decision.data = overridden;
+            }
+            Some(Ok(None)) => {
+                decision.send_amount = 0;
+                state.events.emit(EventKind::packet_dropped(
+                    peer_from_port,
+                    peer_to_port,
+                    message_type,
+                    Some(sequence),
+                    "squelch",
+                ));
+            }
+            Some(Err(e)) => {
+                error!(
+                    "Could not apply the squelch rules to {}, sending it as decided: {}",
+                    message_type, e
+                );
+                state.statistics.count_error("squelch_rule_failed", 1);
+            }
+        }
+        decision
+    }
+
+    /// Applies the shaping rule that limits the type of a message on its link to a decision that sends it: the message is
+    /// delayed until the rate of the rule allows it, or dropped if the rule drops the messages above its rate.
+    ///
+    /// # Parameters
+    /// * 'decision' - the decision made for the message.
+    /// * 'state' - the runtime state, containing the shaping rules and the event bus.
+    /// * 'peer_from_port' - the port of the peer where the message came from.
+    /// * 'peer_to_port' - the port of the peer the message is sent to.
+    /// * 'message_type' - the type of the message.
+    /// * 'sequence' - the position of the message on its link.
+    fn apply_shaping(
+        mut decision: Decision,
+        state: &InterceptorState,
+        peer_from_port: u16,
+        peer_to_port: u16,
+        message_type: MessageType,
+        sequence: u64,
+    ) -> Decision {
+        if decision.send_amount == 0 {
+            return decision;
+        }
+        match state.shape(peer_from_port, peer_to_port, message_type) {
+            None => (),
+            Some(Shaped::Delayed(delay)) => decision.delay += delay,
+            Some(Shaped::Dropped) => {
+                decision.send_amount = 0;
+                state.events.emit(EventKind::packet_dropped(
+                    peer_from_port,
+                    peer_to_port,
+                    message_type,
+                    Some(sequence),
+                    "shaping",
+                ));
+            }
+        }
+        decision
+    }
+
+    /// Applies the gray failure presets that target the link of a message to a decision that sends it.
+    ///
+    /// # Parameters
+    /// * 'decision' - the decision made for the message.
+    /// * 'state' - the runtime state, containing the gray failures and the event bus.
+    /// * 'peer_from_port' - the port of the peer where the message came from.
+    /// * 'peer_to_port' - the port of the peer the message is sent to.
+    /// * 'message_type' - the type of the message.
+    /// * 'sequence' - the position of the message on its link.
+    fn apply_gray_failures(
+        mut decision: Decision,
+        state: &InterceptorState,
+        peer_from_port: u16,
+        peer_to_port: u16,
+        message_type: MessageType,
+        sequence: u64,
+    ) -> Decision {
+        if decision.send_amount == 0 {
+            return decision;
+        }
+        match state.impair(peer_from_port, peer_to_port, message_type) {
+            None => (),
+            Some(Shaped::Delayed(delay)) => decision.delay += delay,
+            Some(Shaped::Dropped) => {
+                decision.send_amount = 0;
+                state.events.emit(EventKind::packet_dropped(
+                    peer_from_port,
+                    peer_to_port,
+                    message_type,
+                    Some(sequence),
+                    "gray_failure",
+                ));
+            }
+        }
+        decision
+    }
+
+    /// Counts the copy of a broadcast, if the copies of broadcasts are tracked, and drops it if it is sent to a node that
+    /// already has the broadcast while the redundant copies are deduplicated.
+    ///
+    /// # Parameters
+    /// * 'decision' - the decision made for the message.
+    /// * 'state' - the runtime state, containing the tracking of the broadcasts and the event bus.
+    /// * 'peer_from_port' - the port of the peer where the message came from.
+    /// * 'peer_to_port' - the port of the peer the message is sent to.
+    /// * 'message_type' - the type of the message.
+    /// * 'sequence' - the position of the message on its link.
+    fn apply_dedupe(
+        mut decision: Decision,
+        state: &InterceptorState,
+        peer_from_port: u16,
+        peer_to_port: u16,
+        message_type: MessageType,
+        sequence: u64,
+    ) -> Decision {
+        let Some(broadcasts) = state.broadcasts() else {
+            return decision;
+        };
+        if broadcasts.observe(
+            peer_from_port,
+            peer_to_port,
+            &decision.data,
+            decision.send_amount > 0,
+        ) {
+            decision.send_amount = 0;
+            state.events.emit(EventKind::packet_dropped(
+                peer_from_port,
+                peer_to_port,
+                message_type,
+                Some(sequence),
+                "dedupe",
+            ));
+        }
+        decision
+    }
+
+    /// Applies the base latency and the rule of the link to a decision: the base latency and the delay of the rule are
+    /// added, and the message is dropped with the probability of the rule. The decision is counted in the counters of
+    /// the link.
+    ///
+    /// # Parameters
+    /// * 'decision' - the decision made for the message.
+    /// * 'state' - the runtime state, containing the base latencies, the links and the event bus.
+    /// * 'peer_from_port' - the port of the peer where the message came from.
+    /// * 'peer_to_port' - the port of the peer the message is sent to.
+    /// * 'message_type' - the type of the message.
+    /// * 'sequence' - the position of the message on its link.
+    fn apply_link_rule(
+        mut decision: Decision,
+        state: &InterceptorState,
+        peer_from_port: u16,
+        peer_to_port: u16,
+        message_type: MessageType,
+        sequence: u64,
+    ) -> Decision {
+        if decision.send_amount > 0 {
+            decision.delay += state.base_latency(peer_from_port, peer_to_port);
+        }
+        let Some(link) = state.link(peer_from_port, peer_to_port) else {
+            return decision;
+        };
+        if link.apply_rule(&mut decision) {
+            state.events.emit(EventKind::packet_dropped(
+                peer_from_port,
+                peer_to_port,
+                message_type,
+                Some(sequence),
+                "link_rule",
+            ));
+        }
+        decision
+    }
+
+    /// Asks the controller what action to take on a message.
+    /// Returns its response together with the protocol version it is to be interpreted with.
+    /// If the links are spread over multiple controllers, the message is sent to the controller its link is pinned to,
+    /// and if that controller fails, the link is moved and the message sent again, until no other controller is left.
+    ///
+    /// # Parameters
+    /// * 'message' - the message.
+    /// * 'metadata' - the identities of the nodes, the sequence number and the capture time of the message.
+    /// * 'client' - the PacketClient used to send the message to the controller, if there is only one.
+    /// * 'state' - the runtime state, containing the controllers and the statistics.
+    /// * 'peer_from_port' - the port of the peer where the message came from.
+    /// * 'peer_to_port' - the port of the peer the message is sent to.
+    async fn request_action(
+        message: Bytes,
+        metadata: PacketMetadata,
+        client: Arc<Mutex<PacketClient>>,
+        state: Arc<InterceptorState>,
+        peer_from_port: u16,
+        peer_to_port: u16,
+    ) -> Result<(PacketAck, u32), String> {
+        let Some(pool) = state.controller_pool() else {
+            return Self::send_to_controller(
+                message,
+                &metadata,
+                client,
+                peer_from_port,
+                peer_to_port,
+            )
+            .await;
+        };
+        let mut attempts = pool.controller_count();
+        loop {
+            let (index, client) = pool.client_for(peer_from_port, peer_to_port);
+            match Self::send_to_controller(
+                message.clone(),
+                &metadata,
+                client,
+                peer_from_port,
+                peer_to_port,
+            )
+            .await
+            {
+                Ok(response) => return Ok(response),
+                // A single controller with multiple channels has nothing to fail over to
+                Err(e) if pool.controller_count() == 1 => return Err(e),
+                Err(e) => {
+                    state.statistics.count_error("controller_unreachable", 1);
+                    attempts -= 1;
+                    if !pool.report_failure(index, &e) || attempts == 0 {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sends a message to a controller, asking for an action.
+    /// Returns its response together with the protocol version it is to be interpreted with.
+    ///
+    /// # Parameters
+    /// * 'message' - the message.
+    /// * 'metadata' - the identities of the nodes, the sequence number and the capture time of the message.
+    /// * 'client' - the PacketClient used to send the message to the controller.
+    /// * 'peer_from_port' - the port of the peer where the message came from.
+    /// * 'peer_to_port' - the port of the peer the message is sent to.
+    async fn send_to_controller(
+        message: Bytes,
+        metadata: &PacketMetadata,
+        client: Arc<Mutex<PacketClient>>,
+        peer_from_port: u16,
+        peer_to_port: u16,
+    ) -> Result<(PacketAck, u32), String> {
+        let mut client = client.lock().await;
+        let proto_version = client.proto_version();
+        let response = client
+            .send_packet(
+                message,
+                u32::from(peer_from_port),
+                u32::from(peer_to_port),
+                metadata,
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok((response, proto_version))
+    }
+
+    /// Decides on a message the controller did not decide on within the budget, by applying the action of the timeout.
+    /// The answer of the controller is still awaited, and logged when it arrives.
+    ///
+    /// # Parameters
+    /// * 'message' - the message.
+    /// * 'request' - the pending request to the controller.
+    /// * 'timeout' - the budget of the controller and the action applied when it is exceeded.
+    /// * 'state' - the runtime state, containing the statistics and the event bus.
+    /// * 'peer_from_port' - the port of the peer where the message came from.
+    /// * 'peer_to_port' - the port of the peer the message is sent to.
+    /// * 'message_type' - the type of the message.
+    /// * 'sequence' - the position of the message on its link.
+    #[allow(clippy::too_many_arguments)]
+    fn decide_without_controller(
+        message: Bytes,
+        request: JoinHandle<Result<(PacketAck, u32), String>>,
+        timeout: DecisionTimeout,
+        state: &InterceptorState,
+        peer_from_port: u16,
+        peer_to_port: u16,
+        message_type: MessageType,
+        sequence: u64,
+    ) -> Decision {
+        warn!(
+            "The controller did not decide within {:?}, applying the timeout action {:?}",
+            timeout.budget, timeout.action
+        );
+        state.statistics.count_error("decision_timeout", 1);
+        let budget = timeout.budget;
+        tokio::spawn(
+            async move {
+                let request_moment = Instant::now();
+                match request.await {
+                    Ok(Ok((response, _))) => warn!(
+                        "The controller decided {:?} too late, {:?} after the budget of {:?}",
+                        response.actions,
+                        request_moment.elapsed(),
+                        budget
+                    ),
+                    Ok(Err(e)) => warn!("The controller failed to decide too late: {}", e),
+                    Err(e) => warn!("The late request to the controller failed: {}", e),
+                }
+            }
+            .in_current_span(),
+        );
+        match timeout.action {
+            TimeoutAction::Forward => Decision::forward(message),
+            TimeoutAction::Drop => {
+                state.events.emit(EventKind::packet_dropped(
+                    peer_from_port,
+                    peer_to_port,
+                    message_type,
+                    Some(sequence),
+                    "decision_timeout",
+                ));
+                Decision::dropped(message)
+            }
+        }
+    }
+
+    /// Sends a copy of a message that has already been forwarded to the controller, ignoring its decision.
+    /// If the links are spread over multiple controllers, the copy is sent to the controller its link is pinned to.
+    ///
+    /// # Parameters
+    /// * 'message' - the message.
+    /// * 'metadata' - the identities of the nodes, the sequence number and the capture time of the message.
+    /// * 'client' - the PacketClient used to send the copy to the controller, if there is only one.
+    /// * 'state' - the runtime state, containing the controllers and the statistics where failures are counted.
+    /// * 'peer_from_port' - the port of the peer where the message came from.
+    /// * 'peer_to_port' - the port of the peer the message is sent to.
+    async fn mirror(
+        message: Bytes,
+        metadata: PacketMetadata,
+        client: Arc<Mutex<PacketClient>>,
+        state: Arc<InterceptorState>,
+        peer_from_port: u16,
+        peer_to_port: u16,
+    ) {
+        let (endpoint, client) = match state.controller_pool() {
+            Some(pool) => {
+                let (index, client) = pool.client_for(peer_from_port, peer_to_port);
+                (Some(index), client)
+            }
+            None => (None, client),
+        };
+        let result = client
+            .lock()
+            .await
+            .send_packet(
+                message,
+                u32::from(peer_from_port),
+                u32::from(peer_to_port),
+                &metadata,
+            )
+            .instrument(info_span!("controller_mirror"))
+            .await;
+        if let Err(e) = result {
+            error!("Could not mirror message to the controller: {}", e);
+            state.statistics.count_error("mirror_failed", 1);
+            if let (Some(pool), Some(index)) = (state.controller_pool(), endpoint) {
+                pool.report_failure(index, &e.to_string());
+            }
         }
+    }
+
+    /// Returns how much longer a message has to be delayed, if at all.
+    /// The time since the message was read is subtracted from the delay, and a delay that already passed while the
+    /// controller decided is not applied at all.
+    ///
+    /// # Parameters
+    /// * 'delay' - the delay the controller applied to the message.
+    /// * 'read_moment' - the moment the message was read.
+    /// * 'clock' - the time in which the delay is measured.
+    fn remaining_delay(
+        delay: Duration,
+        read_moment: Instant,
+        clock: &dyn Clock,
+    ) -> Option<Duration> {
+        delay
+            .checked_sub(clock.elapsed(read_moment))
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Enqueues a handled message as many times as the controller decided, and records it in the timeline.
+    /// If forwarding is paused, it waits until forwarding is resumed before enqueueing the message.
+    /// A message delivered to the observed node is also enqueued for the shadow node, if there is one.
+    ///
+    /// # Parameters
+    /// * 'decision' - the decision of the controller, containing the possibly mutated message.
+    /// * 'record' - the record of the message, of which the latency is filled in.
+    /// * 'state' - the runtime state, containing the timeline where the message is recorded.
+    /// * 'write_queue' - the queue where the message is enqueued.
+    /// * 'read_moment' - the moment the message was read.
+    async fn deliver(
+        decision: Decision,
+        mut record: PacketRecord,
+        state: Arc<InterceptorState>,
+        write_queue: Arc<BoundedQueue<Message>>,
+        read_moment: Instant,
+    ) {
+        if state.is_paused() {
+            state
+                .wait_until_resumed()
+                .instrument(info_span!("paused"))
+                .await;
+        }
+        let shadow_port = state.shadow_port(record.to_port);
+        for _ in 0..decision.send_amount {
+            write_queue
+                .push(Message::new(decision.data.clone(), record.to_port))
+                .await;
+            if let Some(shadow_port) = shadow_port {
+                write_queue
+                    .push(Message::new(decision.data.clone(), shadow_port))
+                    .await;
+            }
+        }
+
+        record.latency = state.clock().elapsed(read_moment);
+        state.record(record);
+    }
+
+    /// Checks a message that is contained inside buf if it is valid.
+    /// Returns the validated message as a slice of the buffer, without copying it.
+    /// Compressed frames and frames with an unknown version header never get here, the frame reader discards them
+    /// with a malformed frame event, see `framing::malformation`.
+    ///
+    /// # Parameters
+    /// * 'buffered_message' - the message inside a buffer to be checked.
+    fn check_message(buffered_message: Bytes) -> Bytes {
+        debug_assert!(
+            malformation(&buffered_message, usize::MAX).is_none(),
+            "Malformed frame was not discarded: bytes[0] = {:?}",
+            buffered_message[0]
+        );
 
         let payload_size = u32::from_be_bytes(buffered_message[0..4].try_into().unwrap()) as usize;
 
@@ -263,47 +2278,183 @@ impl Node {
         }
 
         // return the full message
-        buffered_message[0..(6 + payload_size)].to_vec()
+        buffered_message.slice(0..(6 + payload_size))
     }
 
     /// This method polls a queue with messages.
-    /// It sends every message to the corresponding node immediately.
+    /// It sends every message to the corresponding node immediately,
+    /// unless the protocol version of that connection does not support the type of the message, in which case it is dropped.
+    /// Messages to a peer whose connection is closed on purpose are dropped until the connection is re-established.
+    /// While a message above the hard cap is written to a peer part by part, the other messages to that peer are held
+    /// back until its last part was written, such that they do not end up in the middle of it.
     ///
     /// # Parameters
-    /// * 'message_queue_receiver' - the queue where it receives messages to be sent.
-    /// * 'peer_to_write_half' - a HashMap which maps a port to the corresponding WriteHalf.
+    /// * 'write_queue' - the queue where it receives messages to be sent.
+    /// * 'peer_to_write_half' - a HashMap which maps a port to the corresponding WriteHalf and its protocol version.
+    /// * 'changes' - the changes of the connections to the peers that can be closed and re-established during the run.
+    /// * 'port' - the port of the node the messages come from.
+    /// * 'shadow_port' - the port of the shadow node, whose connection may be lost without ending the loop, if any.
+    /// * 'events' - the bus on which dropped messages are published.
     ///
     /// # Panics
-    /// * If no messages will be ever sent to the queue, meaning all senders have been dropped.
     /// * If the peer's port could not be found in the map.
     /// * If an error occurred while sending the message to the other peer.
-    async fn write_loop(
-        message_queue_receiver: mpsc::Receiver<Message>,
-        mut peer_to_write_half: HashMap<u16, WriteHalf<SslStream<TcpStream>>>,
+    async fn write_loop<S: PeerStream>(
+        write_queue: Arc<BoundedQueue<Message>>,
+        mut peer_to_write_half: HashMap<u16, (WriteHalf<S>, ProtocolVersion)>,
+        changes: UnboundedReceiver<WriteChange<S>>,
+        port: u16,
+        shadow_port: Option<u16>,
+        events: Arc<EventBus>,
     ) {
+        let mut changes = Some(changes);
+        let mut down = HashSet::new();
+        // The messages held back per peer a message above the hard cap is being written to, and those released again
+        let mut streaming: HashMap<u16, VecDeque<Message>> = HashMap::new();
+        let mut released = VecDeque::new();
         loop {
-            let message = message_queue_receiver.recv().unwrap();
+            let message = if let Some(message) = released.pop_front() {
+                message
+            } else {
+                tokio::select! {
+                    (peer_port, change) = Self::next_change(&mut changes) => {
+                        match change {
+                            ConnectionChange::Down => {
+                                peer_to_write_half.remove(&peer_port);
+                                down.insert(peer_port);
+                                // The rest of a message that was being written can no longer be written
+                                released.extend(streaming.remove(&peer_port).unwrap_or_default());
+                            }
+                            ConnectionChange::Up(connection) => {
+                                peer_to_write_half.insert(peer_port, connection);
+                                down.remove(&peer_port);
+                            }
+                        }
+                        continue;
+                    }
+                    message = write_queue.pop() => message,
+                }
+            };
+            let to_shadow = shadow_port == Some(message.peer_to_port);
+            match message.part {
+                None => {
+                    if let Some(held) = streaming.get_mut(&message.peer_to_port) {
+                        held.push_back(message);
+                        continue;
+                    }
+                }
+                Some(part) if part.is_first() => {
+                    if peer_to_write_half.contains_key(&message.peer_to_port) {
+                        streaming.insert(message.peer_to_port, VecDeque::new());
+                    }
+                }
+                // A part of a message whose first part was not written
+                Some(_) if !streaming.contains_key(&message.peer_to_port) => continue,
+                Some(_) => {}
+            }
+            if let Some(part) = message.part {
+                if part.is_last(message.data.len()) {
+                    released.extend(streaming.remove(&message.peer_to_port).unwrap_or_default());
+                }
+            }
 
-            let write_half = peer_to_write_half.get_mut(&message.peer_to_port).unwrap();
+            let Some((write_half, protocol)) = peer_to_write_half.get_mut(&message.peer_to_port)
+            else {
+                if down.contains(&message.peer_to_port) {
+                    if let Some(message_type) = MessageType::from_message(&message.data)
+                        .filter(|_| message.part.is_none_or(|part| part.is_first()))
+                    {
+                        events.emit(EventKind::packet_dropped(
+                            port,
+                            message.peer_to_port,
+                            message_type,
+                            None,
+                            "link_down",
+                        ));
+                    }
+                    continue;
+                }
+                if to_shadow {
+                    // The connection to the shadow node was lost
+                    continue;
+                }
+                panic!("No connection to peer {}", message.peer_to_port);
+            };
+            if let Some(message_type) =
+                MessageType::from_message(&message.data).filter(|_| message.part.is_none())
+            {
+                if !protocol.supports(message_type) {
+                    debug!(
+                        parent: &message.span,
+                        "Dropped {} to peer {}, which speaks {}",
+                        message_type,
+                        message.peer_to_port,
+                        protocol
+                    );
+                    events.emit(EventKind::packet_dropped(
+                        port,
+                        message.peer_to_port,
+                        message_type,
+                        None,
+                        "unsupported_by_protocol",
+                    ));
+                    continue;
+                }
+            }
 
-            write_half
+            let result = write_half
                 .write_all(&message.data)
-                .await
-                .expect("Could not write to SSL stream");
+                .instrument(info_span!(parent: &message.span, "write"))
+                .await;
+            if let Err(e) = result {
+                if !to_shadow {
+                    panic!("Could not write to SSL stream: {}", e);
+                }
+                warn!(
+                    "Could not write to shadow node {}, no longer mirroring messages of {}: {}",
+                    message.peer_to_port, port, e
+                );
+                peer_to_write_half.remove(&message.peer_to_port);
+            }
         }
     }
 }
 
 #[cfg(test)]
 mod unit_tests {
-    use crate::connection_handler::{Message, Node, SIZE_64KB, SIZE_64MB};
-    use bytes::BytesMut;
+    use crate::action::Decision;
+    use crate::broadcast::BroadcastTracker;
+    use crate::clock::{Clock, TokioClock};
+    use crate::config::{
+        BroadcastConfig, MalformedFrameConfig, OverflowPolicy, PassiveConfig, TimeoutAction,
+    };
+    use crate::connection_handler::{Message, Node, ReadMessage, SIZE_64KB, SIZE_64MB};
+    use crate::event_bus::{EventBus, EventKind};
+    use crate::framing::{malformation, Malformation, Part};
+    use crate::gray_failure::{GrayFailureRule, Preset, Target};
+    use crate::interceptor_state::{DecisionTimeout, InterceptorState};
+    use crate::message_queue::{BoundedQueue, QueueGauge};
+    use crate::message_type::MessageType;
+    use crate::packet_client::proto::action::Kind;
+    use crate::packet_client::proto::{Action, DestinationActions};
+    use crate::packet_timeline::PacketTimeline;
+    use crate::partition::OneWayPartition;
+    use crate::passive::PassiveObserver;
+    use crate::ping::Ping;
+    use crate::protocol_version::ProtocolVersion;
+    use crate::traffic_shaping::{ShapingAction, ShapingRule};
+    use bytes::{Bytes, BytesMut};
+    use chrono::Utc;
     use rand::Rng;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
     #[test]
     // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
     fn test_message_new() {
-        let data = vec![1, 2, 3, 4, 5];
+        let data = Bytes::from(vec![1, 2, 3, 4, 5]);
         let peer_to_port = 8080;
         let message = Message::new(data.clone(), peer_to_port);
 
@@ -315,12 +2466,74 @@ mod unit_tests {
     // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
     fn test_node_new() {
         let port = 8080;
-        let node = Node::new(port);
+        let node = Node::new(
+            port,
+            "n9KjTKEaHJ12Kuon5PDZ7fQAo5ExZ6cKH4h3L8q6m9YhoYqeBDho".to_string(),
+        );
 
         assert_eq!(node.port, port);
         assert_eq!(node.peers.len(), 0);
     }
 
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn remaining_delay_without_action() {
+        let clock = TokioClock;
+        assert_eq!(
+            Node::remaining_delay(Duration::ZERO, clock.now(), &clock),
+            None
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn remaining_delay_is_compensated() {
+        let clock = TokioClock;
+        let read_moment = clock.now();
+        tokio::time::advance(Duration::from_millis(300)).await;
+        assert_eq!(
+            Node::remaining_delay(Duration::from_millis(1000), read_moment, &clock),
+            Some(Duration::from_millis(700))
+        );
+        assert_eq!(
+            Node::remaining_delay(Duration::from_millis(300), read_moment, &clock),
+            None
+        );
+        // The controller took longer than the delay, which is not applied instead of underflowing
+        assert_eq!(
+            Node::remaining_delay(Duration::from_millis(100), read_moment, &clock),
+            None
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn read_message_encode_decode() {
+        let mut message = ReadMessage::new(Bytes::from(vec![1, 2, 3]), 42);
+        message.capture_timestamp_ns -= 200_000_000;
+        let decoded = ReadMessage::decode(&message.encode()).unwrap();
+
+        assert_eq!(decoded.data, message.data);
+        assert_eq!(decoded.sequence, 42);
+        assert_eq!(decoded.capture_timestamp_ns, message.capture_timestamp_ns);
+        assert!(decoded.read_moment.elapsed() >= Duration::from_millis(150));
+        assert!(decoded.read_moment.elapsed() < Duration::from_secs(5));
+        assert_eq!(decoded.part, None);
+        assert!(ReadMessage::decode(&[1, 2, 3]).is_none());
+
+        let part = Part {
+            offset: 1000,
+            length: 5000,
+        };
+        let message = ReadMessage {
+            part: Some(part),
+            ..ReadMessage::new(Bytes::from(vec![4, 5]), 7)
+        };
+        let decoded = ReadMessage::decode(&message.encode()).unwrap();
+        assert_eq!(decoded.data, message.data);
+        assert_eq!(decoded.part, Some(part));
+    }
+
     fn create_dummy_payload(length: usize) -> Vec<u8> {
         let mut payload = Vec::new();
         let mut rng = rand::thread_rng();
@@ -350,28 +2563,32 @@ mod unit_tests {
 
     #[test]
     // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
-    #[should_panic(expected = "Received compressed message: bytes[0] = 128")]
-    fn panic_compressed_message() {
+    fn compressed_message_is_malformed() {
         let mut buf = BytesMut::with_capacity(SIZE_64KB);
         let payload_size: usize = 255;
         buf.extend_from_slice(&create_header(0b1000_0000, payload_size));
         buf.extend_from_slice(&create_dummy_payload(payload_size));
         buf.resize(6 + payload_size, 0);
 
-        let _message = Node::check_message(buf);
+        assert_eq!(
+            malformation(&buf, SIZE_64MB),
+            Some(Malformation::Compressed)
+        );
     }
 
     #[test]
     // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
-    #[should_panic(expected = "Unknown version header: bytes[0] = 40")]
-    fn panic_unknown_version_header() {
+    fn unknown_version_header_is_malformed() {
         let mut buffer = BytesMut::with_capacity(SIZE_64KB);
         let payload_size: usize = 44;
         buffer.extend_from_slice(&create_header(0b0010_1000, payload_size));
         buffer.extend_from_slice(&create_dummy_payload(payload_size));
         buffer.resize(6 + payload_size, 0);
 
-        let _message = Node::check_message(buffer);
+        assert_eq!(
+            malformation(&buffer, SIZE_64MB),
+            Some(Malformation::UnknownVersion(40))
+        );
     }
 
     #[test]
@@ -383,7 +2600,7 @@ mod unit_tests {
         buffer.extend_from_slice(&create_dummy_payload(payload_size));
         buffer.resize(6 + payload_size, 0);
 
-        let message = Node::check_message(buffer);
+        let message = Node::check_message(buffer.freeze());
 
         assert_eq!(message.len(), payload_size + 6)
     }
@@ -397,8 +2614,426 @@ mod unit_tests {
         buffer.extend_from_slice(&create_dummy_payload(payload_size));
         buffer.resize(6 + payload_size, 0);
 
-        let message = Node::check_message(buffer);
+        let message = Node::check_message(buffer.freeze());
 
         assert_eq!(message.len(), payload_size + 6)
     }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn answer_keepalive_replies_to_pings() {
+        let reply_queue = BoundedQueue::new(
+            OverflowPolicy::Block,
+            Arc::new(QueueGauge::new("test".to_string(), 4)),
+        );
+        let ping = Ping {
+            seq: Some(7),
+            ..Default::default()
+        };
+
+        assert!(Node::answer_keepalive(&ping.to_message(), &reply_queue, 60000).await);
+        let reply = reply_queue.pop().await;
+        assert_eq!(reply.peer_to_port, 60000);
+        assert_eq!(Ping::from_message(&reply.data), Some(ping.reply()));
+
+        assert!(Node::answer_keepalive(&ping.reply().to_message(), &reply_queue, 60000).await);
+        assert!(!Node::answer_keepalive(&[0, 0, 0, 0, 0, 41], &reply_queue, 60000).await);
+        assert!(reply_queue.is_empty());
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn decide_without_controller_applies_timeout_action() {
+        let state = InterceptorState::new(Arc::new(PacketTimeline::new(10)));
+        let message = Bytes::from_static(&[0, 0, 0, 1, 0, 3, 8]);
+        let timeout = |action| DecisionTimeout {
+            budget: Duration::from_millis(10),
+            action,
+        };
+        let pending = || tokio::spawn(async { Err("late".to_string()) });
+
+        let forwarded = Node::decide_without_controller(
+            message.clone(),
+            pending(),
+            timeout(TimeoutAction::Forward),
+            &state,
+            60000,
+            60001,
+            MessageType::Ping,
+            0,
+        );
+        assert_eq!(forwarded, Decision::forward(message.clone()));
+        let dropped = Node::decide_without_controller(
+            message.clone(),
+            pending(),
+            timeout(TimeoutAction::Drop),
+            &state,
+            60000,
+            60001,
+            MessageType::Ping,
+            1,
+        );
+        assert_eq!(dropped, Decision::dropped(message));
+        let summary = state.statistics.summarize(Utc::now(), Utc::now(), None);
+        assert_eq!(summary.errors["decision_timeout"], 2);
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn write_priority_orders_congested_messages() {
+        let message =
+            |message_type: u8| Message::new(Bytes::from(vec![0, 0, 0, 0, 0, message_type]), 60001);
+        // A transaction, a validation, a ping and a proposal wait in the write queue
+        let types = [30, 41, 3, 33];
+        let written = |priority: fn(&Message) -> u8| async move {
+            let queue = BoundedQueue::new(
+                OverflowPolicy::Block,
+                Arc::new(QueueGauge::new("test".to_string(), 4)),
+            )
+            .with_priority(priority);
+            for message_type in types {
+                queue.push(message(message_type)).await;
+            }
+            let mut written = Vec::new();
+            for _ in types {
+                written.push(queue.pop().await.data[5]);
+            }
+            written
+        };
+        assert_eq!(written(Message::consensus_first).await, [41, 33, 3, 30]);
+        assert_eq!(written(Message::consensus_last).await, [30, 3, 41, 33]);
+
+        // The parts of a message above the hard cap keep their order among the other messages
+        let part = Message {
+            part: Some(Part {
+                offset: 0,
+                length: 10,
+            }),
+            ..message(41)
+        };
+        assert_eq!(Message::consensus_first(&part), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn shaping_delays_or_drops_messages_above_the_rate() {
+        let state = InterceptorState::new(Arc::new(PacketTimeline::new(10)));
+        let rule = |action| ShapingRule {
+            message_type: MessageType::Validation,
+            from_port: Some(60000),
+            to_port: None,
+            rate: 2.0,
+            burst: None,
+            action,
+        };
+        let message = Bytes::from_static(&[0, 0, 0, 1, 0, 41, 8]);
+        let shape = |sequence| {
+            Node::apply_shaping(
+                Decision::forward(message.clone()),
+                &state,
+                60000,
+                60001,
+                MessageType::Validation,
+                sequence,
+            )
+        };
+
+        state
+            .set_shaping_rules(vec![rule(ShapingAction::Delay)])
+            .unwrap();
+        assert_eq!(shape(0).delay, Duration::ZERO);
+        assert_eq!(shape(1).delay, Duration::from_millis(500));
+        assert_eq!(shape(2).delay, Duration::from_millis(1000));
+
+        state
+            .set_shaping_rules(vec![rule(ShapingAction::Drop)])
+            .unwrap();
+        let mut events = state.events.subscribe();
+        assert_eq!(shape(3).send_amount, 1);
+        assert_eq!(shape(4).send_amount, 0);
+        assert_eq!(
+            events.recv().await.unwrap().kind,
+            EventKind::packet_dropped(60000, 60001, MessageType::Validation, Some(4), "shaping")
+        );
+        tokio::time::advance(Duration::from_millis(500)).await;
+        assert_eq!(shape(5).send_amount, 1);
+
+        let invalid = ShapingRule {
+            rate: -1.0,
+            ..rule(ShapingAction::Drop)
+        };
+        assert!(state.set_shaping_rules(vec![invalid]).is_err());
+        assert_eq!(state.shaping_rules(), vec![rule(ShapingAction::Drop)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn gray_failures_impair_the_targeted_links() {
+        let state = InterceptorState::new(Arc::new(PacketTimeline::new(10)));
+        let rule = GrayFailureRule {
+            preset: Preset::IntermittentPartition {
+                up_ms: Some(1000),
+                down_ms: Some(1000),
+            },
+            target: Target::Node(60001),
+        };
+        state.set_gray_failures(vec![rule]).unwrap();
+        let message = Bytes::from_static(&[0, 0, 0, 1, 0, 41, 8]);
+        let impair = |from, to| {
+            Node::apply_gray_failures(
+                Decision::forward(message.clone()),
+                &state,
+                from,
+                to,
+                MessageType::Validation,
+                7,
+            )
+        };
+
+        assert_eq!(impair(60000, 60001).send_amount, 1);
+        tokio::time::advance(Duration::from_millis(1500)).await;
+        let mut events = state.events.subscribe();
+        assert_eq!(impair(60001, 60002).send_amount, 0);
+        assert_eq!(
+            events.recv().await.unwrap().kind,
+            EventKind::packet_dropped(
+                60001,
+                60002,
+                MessageType::Validation,
+                Some(7),
+                "gray_failure"
+            )
+        );
+        // Links of other nodes are not impaired
+        assert_eq!(impair(60000, 60002).send_amount, 1);
+        assert_eq!(state.gray_failures(), vec![rule]);
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn observed_messages_are_forwarded_as_read() {
+        let state = InterceptorState::new(Arc::new(PacketTimeline::new(10)));
+        let write_queue = BoundedQueue::new(
+            OverflowPolicy::Block,
+            Arc::new(QueueGauge::new("test".to_string(), 4)),
+        );
+        let ping = Ping::default().to_message();
+        // Nothing is forwarded while messages are not only observed
+        Node::observe(
+            ReadMessage::new(ping.clone(), 0),
+            &state,
+            60000,
+            60001,
+            &write_queue,
+        )
+        .await;
+        assert!(write_queue.is_empty());
+
+        state.enable_passive(PassiveObserver::new(&PassiveConfig::default()));
+        // Cut links do not apply
+        state.set_one_way_partition(OneWayPartition {
+            cut_links: vec![(60000, 60001)],
+        });
+        Node::observe(
+            ReadMessage::new(ping.clone(), 3),
+            &state,
+            60000,
+            60001,
+            &write_queue,
+        )
+        .await;
+        let forwarded = write_queue.pop().await;
+        assert_eq!(forwarded.peer_to_port, 60001);
+        assert_eq!(forwarded.data, ping);
+
+        let record = &state.timeline.latest(1)[0];
+        assert_eq!(record.sequence, 3);
+        assert_eq!(record.message_type, MessageType::Ping);
+        assert_eq!(record.send_amount, 1);
+        assert_eq!(record.controller_latency, None);
+        assert_eq!(state.passive().unwrap().added_latency().messages, 1);
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn redundant_broadcasts_are_deduplicated() {
+        let state = InterceptorState::new(Arc::new(PacketTimeline::new(10)));
+        // A validation of which the serialized STValidation is a single byte
+        let message = Bytes::from_static(&[0, 0, 0, 3, 0, 41, 0x0A, 1, 5]);
+        let dedupe = |from, to| {
+            Node::apply_dedupe(
+                Decision::forward(message.clone()),
+                &state,
+                from,
+                to,
+                MessageType::Validation,
+                3,
+            )
+        };
+        // Without tracking, nothing is counted or dropped
+        assert_eq!(dedupe(60000, 60001).send_amount, 1);
+        assert_eq!(dedupe(60002, 60001).send_amount, 1);
+
+        state.enable_broadcast_tracking(BroadcastTracker::new(&BroadcastConfig {
+            dedupe: true,
+            ..BroadcastConfig::default()
+        }));
+        let mut events = state.events.subscribe();
+        assert_eq!(dedupe(60000, 60001).send_amount, 1);
+        assert_eq!(dedupe(60001, 60002).send_amount, 1);
+        assert_eq!(dedupe(60002, 60001).send_amount, 0);
+        assert_eq!(
+            events.recv().await.unwrap().kind,
+            EventKind::packet_dropped(60002, 60001, MessageType::Validation, Some(3), "dedupe")
+        );
+        let counts = state.broadcasts().unwrap().report().message_types[0].counts;
+        assert_eq!((counts.copies, counts.deduplicated), (3, 1));
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn fan_out_is_looked_up_by_the_message_as_it_was_read() {
+        let state = InterceptorState::new(Arc::new(PacketTimeline::new(10)));
+        state.enable_broadcast_tracking(BroadcastTracker::new(&BroadcastConfig::default()));
+        let message = Bytes::from_static(&[0, 0, 0, 3, 0, 41, 0x0A, 1, 5]);
+        let mutated = Bytes::from_static(&[0, 0, 0, 3, 0, 41, 0x0A, 1, 6]);
+        let delayed = DestinationActions {
+            to_port: 60002,
+            actions: vec![Action {
+                kind: Some(Kind::DelayMs(500)),
+            }],
+        };
+        assert!(state.set_fan_out(&message, vec![delayed]));
+        let fan_out = |to_port| {
+            Node::apply_fan_out(
+                Decision::forward(mutated.clone()),
+                &message,
+                &state,
+                60001,
+                to_port,
+                MessageType::Validation,
+                3,
+            )
+        };
+
+        // A copy the controller mutated is still recognized as a copy of the broadcast
+        assert_eq!(fan_out(60002).delay, Duration::from_millis(500));
+        assert_eq!(fan_out(60003), Decision::forward(mutated.clone()));
+    }
+
+    fn queue<T>(capacity: usize) -> Arc<BoundedQueue<T>> {
+        Arc::new(BoundedQueue::new(
+            OverflowPolicy::Block,
+            Arc::new(QueueGauge::new("test".to_string(), capacity)),
+        ))
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn read_loop_reassembles_messages_from_stream() {
+        let (mut node, interceptor) = tokio::io::duplex(SIZE_64KB);
+        let (read_half, _write_half) = tokio::io::split(interceptor);
+        let decision_queue = queue(4);
+        let reader = tokio::spawn(Node::read_loop(
+            read_half,
+            None,
+            60000,
+            60001,
+            decision_queue.clone(),
+            None,
+            SIZE_64KB,
+            MalformedFrameConfig::default(),
+            Arc::new(EventBus::default()),
+        ));
+
+        let ping = Ping {
+            seq: Some(1),
+            ..Default::default()
+        }
+        .to_message();
+        let pong = Ping {
+            seq: Some(1),
+            ..Default::default()
+        }
+        .reply()
+        .to_message();
+        let mut data = ping.to_vec();
+        data.extend_from_slice(&pong);
+        // The first message is split over two writes, the second one follows in the same write as its start
+        node.write_all(&data[..3]).await.unwrap();
+        node.flush().await.unwrap();
+        node.write_all(&data[3..]).await.unwrap();
+
+        let first = decision_queue.pop().await;
+        assert_eq!((first.data, first.sequence), (ping, 0));
+        let second = decision_queue.pop().await;
+        assert_eq!((second.data, second.sequence), (pong, 1));
+        reader.abort();
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn shadow_read_loop_answers_pings_between_other_messages() {
+        let (mut shadow, interceptor) = tokio::io::duplex(SIZE_64KB);
+        let (read_half, _write_half) = tokio::io::split(interceptor);
+        let write_queue = queue(4);
+        let reader = tokio::spawn(Node::shadow_read_loop(
+            read_half,
+            60000,
+            60005,
+            write_queue.clone(),
+            Arc::new(InterceptorState::new(Arc::new(PacketTimeline::new(10)))),
+        ));
+
+        let ping = |seq| Ping {
+            seq: Some(seq),
+            ..Default::default()
+        };
+        let mut data = ping(1).reply().to_message().to_vec();
+        data.extend_from_slice(&ping(2).to_message());
+        data.extend_from_slice(&ping(3).to_message());
+        // The first ping follows a pong in the same read, the second one is split over two reads
+        let split = data.len() - 3;
+        shadow.write_all(&data[..split]).await.unwrap();
+        shadow.flush().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        shadow.write_all(&data[split..]).await.unwrap();
+
+        for seq in [2, 3] {
+            let reply = write_queue.pop().await;
+            assert_eq!(
+                (reply.data, reply.peer_to_port),
+                (ping(seq).reply().to_message(), 60005)
+            );
+        }
+        reader.abort();
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn write_loop_writes_messages_to_stream() {
+        let (mut node, interceptor) = tokio::io::duplex(SIZE_64KB);
+        let (_read_half, write_half) = tokio::io::split(interceptor);
+        let write_queue = queue(4);
+        let (_changes, receiver) = tokio::sync::mpsc::unbounded_channel();
+        let writer = tokio::spawn(Node::write_loop(
+            write_queue.clone(),
+            HashMap::from([(60001, (write_half, ProtocolVersion::new(2, 2)))]),
+            receiver,
+            60000,
+            None,
+            Arc::new(EventBus::default()),
+        ));
+
+        let ping = Ping {
+            seq: Some(3),
+            ..Default::default()
+        }
+        .to_message();
+        write_queue.push(Message::new(ping.clone(), 60001)).await;
+        let mut written = vec![0; ping.len()];
+        node.read_exact(&mut written).await.unwrap();
+        assert_eq!(written, ping);
+        writer.abort();
+    }
 }
@@ -0,0 +1,301 @@
+//! This module is responsible for grouping the intercepted messages per consensus round, such that the statistics of a
+//! run can be read as a timeline: how many messages of every type every node sent in every round.
+//!
+//! Round N is the consensus round that builds ledger N. A validation belongs to the round of the ledger it validates,
+//! and a proposal to the round after the ledger it builds on, once the hash of that ledger is known from a validation.
+//! All other messages, and the proposals that build on an unknown ledger, belong to the round in progress when they are
+//! read, which is the round after the highest validated ledger so far. Messages read before the first validation do not
+//! belong to a round.
+
+use crate::message_type::MessageType;
+use crate::packet_timeline::PacketRecord;
+use crate::run_id::RunId;
+use crate::run_summary::{sort_by_frequency, ActionCounts, MessageTypeCounts};
+use crate::wire_format;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// The amount of ledgers before the highest validated one of which the hashes are remembered.
+const REMEMBERED_LEDGERS: u32 = 256;
+
+/// Struct that represents what is known about the rounds so far.
+#[derive(Debug, Default)]
+struct Rounds {
+    /// The sequences of the recently validated ledgers by their hash.
+    ledger_hashes: HashMap<[u8; 32], u32>,
+    /// The round in progress, None until the first validation was read.
+    current: Option<u32>,
+}
+
+/// Struct that represents the assignment of the intercepted messages to the consensus round they belong to.
+#[derive(Debug, Default)]
+pub struct RoundTracker {
+    rounds: Mutex<Rounds>,
+}
+
+impl RoundTracker {
+    /// Returns the round in progress, None until the first validation was read.
+    pub fn current(&self) -> Option<u32> {
+        self.rounds.lock().unwrap().current
+    }
+
+    /// Returns the round a message belongs to, None if it was read before the first validation.
+    /// A validation of a ledger that is not older than the round in progress ends that round.
+    ///
+    /// # Parameters
+    /// * 'message' - the message including its header.
+    pub fn round(&self, message: &[u8]) -> Option<u32> {
+        let mut rounds = self.rounds.lock().unwrap();
+        match MessageType::from_message(message) {
+            Some(MessageType::Validation) => {
+                let Some(sequence) = wire_format::ledger_sequence(message) else {
+                    return rounds.current;
+                };
+                if let Some(hash) = wire_format::ledger_hash(message) {
+                    rounds.ledger_hashes.insert(hash, sequence);
+                }
+                if rounds.current.map_or(true, |current| sequence >= current) {
+                    rounds.current = Some(sequence.saturating_add(1));
+                    let oldest = sequence.saturating_sub(REMEMBERED_LEDGERS);
+                    rounds
+                        .ledger_hashes
+                        .retain(|_, validated| *validated >= oldest);
+                }
+                Some(sequence)
+            }
+            Some(MessageType::ProposeLedger) => wire_format::ledger_hash(message)
+                .and_then(|hash| rounds.ledger_hashes.get(&hash))
+                .map(|previous| previous.saturating_add(1))
+                .or(rounds.current),
+            _ => rounds.current,
+        }
+    }
+}
+
+/// Struct that represents the tallies of the messages of a single round, kept while the interceptor runs.
+#[derive(Debug, Clone)]
+pub struct RoundTally {
+    first_read_at: DateTime<Utc>,
+    last_read_at: DateTime<Utc>,
+    totals: ActionCounts,
+    /// The counts per port of the node the messages came from and message type.
+    nodes: BTreeMap<u16, HashMap<MessageType, ActionCounts>>,
+}
+
+impl RoundTally {
+    /// Initializes a new RoundTally without messages.
+    ///
+    /// # Parameters
+    /// * 'read_at' - the moment the first message of the round was read.
+    pub fn new(read_at: DateTime<Utc>) -> Self {
+        Self {
+            first_read_at: read_at,
+            last_read_at: read_at,
+            totals: ActionCounts::default(),
+            nodes: BTreeMap::new(),
+        }
+    }
+
+    /// Counts a handled message of the round.
+    ///
+    /// # Parameters
+    /// * 'record' - the record of the message.
+    pub fn count(&mut self, record: &PacketRecord) {
+        self.first_read_at = self.first_read_at.min(record.timestamp);
+        self.last_read_at = self.last_read_at.max(record.timestamp);
+        self.totals.count(record);
+        self.nodes
+            .entry(record.from_port)
+            .or_default()
+            .entry(record.message_type)
+            .or_default()
+            .count(record);
+    }
+
+    /// Converts the tallies to the counts of the round as they are exported.
+    ///
+    /// # Parameters
+    /// * 'round' - the round.
+    pub fn to_counts(&self, round: u32) -> RoundCounts {
+        RoundCounts {
+            round,
+            first_read_at: self.first_read_at.to_rfc3339(),
+            last_read_at: self.last_read_at.to_rfc3339(),
+            totals: self.totals,
+            nodes: self
+                .nodes
+                .iter()
+                .map(|(&port, message_types)| {
+                    let mut message_types: Vec<MessageTypeCounts> = message_types
+                        .iter()
+                        .map(|(&message_type, &counts)| MessageTypeCounts {
+                            message_type,
+                            counts,
+                        })
+                        .collect();
+                    sort_by_frequency(&mut message_types);
+                    NodeRoundCounts {
+                        port,
+                        message_types,
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Struct that represents the counts of the messages a single node sent in a round.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NodeRoundCounts {
+    pub port: u16,
+    /// The counts per message type, the most frequent first.
+    pub message_types: Vec<MessageTypeCounts>,
+}
+
+/// Struct that represents the counts of the messages of a single round.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RoundCounts {
+    /// The sequence of the ledger that is built in the round.
+    pub round: u32,
+    /// The moment the first message of the round was read, in RFC 3339 format.
+    pub first_read_at: String,
+    /// The moment the last message of the round was read, in RFC 3339 format.
+    pub last_read_at: String,
+    /// The counts of all messages of the round.
+    pub totals: ActionCounts,
+    /// The counts per node the messages came from, ordered by port.
+    pub nodes: Vec<NodeRoundCounts>,
+}
+
+/// Struct that represents the timeline of the consensus rounds of a run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RoundTimeline {
+    /// The counts per round, ordered by round.
+    pub rounds: Vec<RoundCounts>,
+}
+
+impl RoundTimeline {
+    /// Writes the timeline as JSON to a new file in the given directory, and returns the path of that file.
+    ///
+    /// # Parameters
+    /// * 'directory' - the directory the summaries of all runs are stored in.
+    /// * 'run_id' - the identifier of the run, which names the file.
+    pub fn save(&self, directory: &str, run_id: &RunId) -> Result<PathBuf, Box<dyn Error>> {
+        fs::create_dir_all(directory)?;
+        let path = Path::new(directory).join(format!("rounds-{}.json", run_id));
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::consensus_round::RoundTracker;
+    use crate::message_type::MessageType;
+    use crate::packet_timeline::PacketRecord;
+    use crate::run_summary::RunStatistics;
+    use chrono::Duration as ChronoDuration;
+    use prost::encoding::encode_varint;
+
+    fn message(message_type: MessageType, payload: &[u8]) -> Vec<u8> {
+        let mut message = (payload.len() as u32).to_be_bytes().to_vec();
+        message.extend_from_slice(&message_type.value().to_be_bytes());
+        message.extend_from_slice(payload);
+        message
+    }
+
+    fn validation(ledger_seq: u32, ledger_hash: u8) -> Vec<u8> {
+        // sfFlags, sfLedgerSequence, sfSigningTime and sfLedgerHash
+        let mut validation = vec![0x22, 0x80, 0, 0, 1, 0x26];
+        validation.extend_from_slice(&ledger_seq.to_be_bytes());
+        validation.extend_from_slice(&[0x29, 0, 0, 0, 9, 0x51]);
+        validation.extend_from_slice(&[ledger_hash; 32]);
+        let mut payload = Vec::new();
+        encode_varint(1 << 3 | 2, &mut payload);
+        encode_varint(validation.len() as u64, &mut payload);
+        payload.extend_from_slice(&validation);
+        message(MessageType::Validation, &payload)
+    }
+
+    fn proposal(previous_ledger_hash: u8) -> Vec<u8> {
+        let mut payload = Vec::new();
+        encode_varint(1 << 3, &mut payload);
+        encode_varint(0, &mut payload);
+        encode_varint(6 << 3 | 2, &mut payload);
+        encode_varint(32, &mut payload);
+        payload.extend_from_slice(&[previous_ledger_hash; 32]);
+        message(MessageType::ProposeLedger, &payload)
+    }
+
+    fn record(from_port: u16, message_type: MessageType, round: Option<u32>) -> PacketRecord {
+        PacketRecord {
+            from_port,
+            message_type,
+            round,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn messages_are_assigned_to_rounds() {
+        let tracker = RoundTracker::default();
+        let ping = message(MessageType::Ping, &[8, 0]);
+        assert_eq!(tracker.round(&ping), None);
+        assert_eq!(tracker.round(&proposal(1)), None);
+
+        assert_eq!(tracker.round(&validation(10, 1)), Some(10));
+        assert_eq!(tracker.current(), Some(11));
+        assert_eq!(tracker.round(&ping), Some(11));
+        // A proposal builds on the ledger it names, also when it is read after the next validation
+        assert_eq!(tracker.round(&proposal(1)), Some(11));
+        assert_eq!(tracker.round(&validation(11, 2)), Some(11));
+        assert_eq!(tracker.round(&proposal(1)), Some(11));
+        assert_eq!(tracker.round(&proposal(2)), Some(12));
+        assert_eq!(tracker.round(&proposal(9)), Some(12));
+
+        // A lagging validation does not move the round in progress back
+        assert_eq!(tracker.round(&validation(9, 3)), Some(9));
+        assert_eq!(tracker.current(), Some(12));
+        assert_eq!(tracker.round(&proposal(3)), Some(10));
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn timeline_counts_per_round_node_and_type() {
+        let statistics = RunStatistics::default();
+        statistics.tally(&record(60000, MessageType::Ping, None));
+        statistics.tally(&record(60000, MessageType::ProposeLedger, Some(5)));
+        statistics.tally(&record(60000, MessageType::ProposeLedger, Some(5)));
+        statistics.tally(&record(60000, MessageType::Validation, Some(5)));
+        statistics.tally(&record(60002, MessageType::ProposeLedger, Some(5)));
+        let mut late = record(60002, MessageType::Validation, Some(6));
+        late.timestamp += ChronoDuration::seconds(4);
+        statistics.tally(&late);
+
+        let timeline = statistics.round_timeline();
+        assert_eq!(
+            timeline
+                .rounds
+                .iter()
+                .map(|round| (round.round, round.totals.handled))
+                .collect::<Vec<_>>(),
+            [(5, 4), (6, 1)]
+        );
+        let round = &timeline.rounds[0];
+        assert_eq!(
+            round.nodes.iter().map(|node| node.port).collect::<Vec<_>>(),
+            [60000, 60002]
+        );
+        let message_types = &round.nodes[0].message_types;
+        assert_eq!(message_types[0].message_type, MessageType::ProposeLedger);
+        assert_eq!(message_types[0].counts.handled, 2);
+        assert_eq!(message_types[1].message_type, MessageType::Validation);
+        assert_ne!(timeline.rounds[1].first_read_at, round.first_read_at);
+    }
+}
@@ -0,0 +1,633 @@
+//! This module is responsible for the console, a REPL through which an experiment is steered by hand while it runs, e.g.
+//! `delay 1 2 300ms`, `drop validations from 3`, `partition {0,1}|{2,3}`, `heal` and `stats`.
+//!
+//! The console is served on a TCP socket while `[console]` is configured, one command per line, and the `console`
+//! subcommand attaches a terminal to it. Nodes are given by their ID, and the commands use the same primitives as the
+//! admin API: link rules, blackholes, the one-way partition and pausing, so the two overwrite each other.
+
+use crate::interceptor_state::{Blackhole, InterceptorState, LinkRule};
+use crate::message_type::MessageType;
+use crate::partition::OneWayPartition;
+use std::error::Error;
+use std::io::{self, BufRead};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+/// The commands of the console, as they are listed by `help`.
+const HELP: &str = "Commands, nodes are given by their ID:
+  delay <from> <to> <delay>       delays the messages from node <from> to node <to>, e.g. 300ms or 2s, 0 to stop
+  loss <from> <to> <probability>  drops the messages from node <from> to node <to> with a probability between 0 and 1
+  drop <messages> from <node>     drops all messages of a type that a node sends, e.g. validations or mtPING
+  undrop <messages> from <node>   stops dropping the messages of a type that a node sends
+  partition <components>          cuts the links between components in both directions, e.g. {0,1}|{2,3}
+  heal                            heals the partition, and removes all delays, losses and dropped messages
+  pause, resume                   pauses and resumes forwarding on all links
+  stats                           prints the counters and rules of the links, and what is dropped or cut
+  quit                            ends the session, the run continues";
+
+/// Enum that represents a command of the console.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConsoleCommand {
+    /// Sets the delay of the messages on a link, keeping its drop probability.
+    Delay { from: u32, to: u32, delay: Duration },
+    /// Sets the probability that a message on a link is dropped, keeping its delay.
+    Loss {
+        from: u32,
+        to: u32,
+        probability: f64,
+    },
+    /// Drops all messages of a type that a node sends, on all its links.
+    Drop {
+        message_type: MessageType,
+        from: u32,
+    },
+    /// Stops dropping the messages of a type that a node sends.
+    Undrop {
+        message_type: MessageType,
+        from: u32,
+    },
+    /// Cuts the links between the components of the network in both directions. The nodes that are not in any
+    /// component form one component together.
+    Partition(Vec<Vec<u32>>),
+    /// Heals the partition, and removes the rules of all links and all blackholes.
+    Heal,
+    /// Pauses forwarding on all links.
+    Pause,
+    /// Resumes forwarding on all links.
+    Resume,
+    /// Prints the counters and rules of the links, the blackholes and the cut links.
+    Stats,
+    /// Prints the commands.
+    Help,
+}
+
+impl FromStr for ConsoleCommand {
+    type Err = String;
+
+    /// Parses a command as it is typed, e.g. 'delay 1 2 300ms'.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let node = |word: &str| {
+            word.trim_start_matches('n')
+                .parse::<u32>()
+                .map_err(|_| format!("'{}' is not a node ID", word))
+        };
+        let usage = |usage: &str| Err(format!("Usage: {}", usage));
+        match words.as_slice() {
+            ["delay", from, to, delay] => Ok(ConsoleCommand::Delay {
+                from: node(from)?,
+                to: node(to)?,
+                delay: parse_delay(delay)?,
+            }),
+            ["delay", ..] => usage("delay <from> <to> <delay>"),
+            ["loss", from, to, probability] => Ok(ConsoleCommand::Loss {
+                from: node(from)?,
+                to: node(to)?,
+                probability: probability
+                    .parse()
+                    .map_err(|_| format!("'{}' is not a probability", probability))?,
+            }),
+            ["loss", ..] => usage("loss <from> <to> <probability>"),
+            ["drop", messages, "from", from] => Ok(ConsoleCommand::Drop {
+                message_type: parse_messages(messages)?,
+                from: node(from)?,
+            }),
+            ["drop", ..] => usage("drop <messages> from <node>"),
+            ["undrop", messages, "from", from] => Ok(ConsoleCommand::Undrop {
+                message_type: parse_messages(messages)?,
+                from: node(from)?,
+            }),
+            ["undrop", ..] => usage("undrop <messages> from <node>"),
+            ["partition", components @ ..] if !components.is_empty() => {
+                let components = components
+                    .concat()
+                    .split('|')
+                    .map(|component| {
+                        let nodes = component
+                            .strip_prefix('{')
+                            .and_then(|nodes| nodes.strip_suffix('}'))
+                            .ok_or_else(|| {
+                                format!("'{}' is not a component like {{0,1}}", component)
+                            })?;
+                        nodes
+                            .split(',')
+                            .filter(|id| !id.is_empty())
+                            .map(node)
+                            .collect::<Result<Vec<u32>, String>>()
+                    })
+                    .collect::<Result<Vec<Vec<u32>>, String>>()?;
+                match components.len() {
+                    1 => Err("A partition has at least 2 components".to_string()),
+                    _ => Ok(ConsoleCommand::Partition(components)),
+                }
+            }
+            ["partition", ..] => usage("partition {<node>,...}|{<node>,...}"),
+            ["heal"] => Ok(ConsoleCommand::Heal),
+            ["pause"] => Ok(ConsoleCommand::Pause),
+            ["resume"] => Ok(ConsoleCommand::Resume),
+            ["stats"] => Ok(ConsoleCommand::Stats),
+            ["help"] => Ok(ConsoleCommand::Help),
+            [command, ..] => Err(format!(
+                "Unknown command '{}', type help for the commands",
+                command
+            )),
+            [] => Err("No command given".to_string()),
+        }
+    }
+}
+
+/// Parses a delay, in ms or s with the unit, or in ms without, e.g. '300ms', '1.5s' or '300'.
+///
+/// # Parameters
+/// * 'value' - the delay.
+fn parse_delay(value: &str) -> Result<Duration, String> {
+    let (number, factor) = match (value.strip_suffix("ms"), value.strip_suffix('s')) {
+        (Some(ms), _) => (ms, 0.001),
+        (None, Some(s)) => (s, 1.0),
+        (None, None) => (value, 0.001),
+    };
+    number
+        .parse::<f64>()
+        .ok()
+        .and_then(|number| Duration::try_from_secs_f64(number * factor).ok())
+        .ok_or_else(|| format!("'{}' is not a delay like 300ms or 2s", value))
+}
+
+/// Parses the type of messages, by a word like 'validations' for the common types or by its name used by rippled.
+///
+/// # Parameters
+/// * 'value' - the messages, e.g. 'validations' or 'mtVALIDATION'.
+fn parse_messages(value: &str) -> Result<MessageType, String> {
+    match value {
+        "validations" => Ok(MessageType::Validation),
+        "proposals" => Ok(MessageType::ProposeLedger),
+        "transactions" => Ok(MessageType::Transaction),
+        "statuses" => Ok(MessageType::StatusChange),
+        "manifests" => Ok(MessageType::Manifests),
+        "pings" => Ok(MessageType::Ping),
+        _ => value.parse(),
+    }
+}
+
+/// Struct that represents the console of a running network.
+#[derive(Debug)]
+pub struct Console {
+    /// The runtime state the commands are carried out on.
+    state: Arc<InterceptorState>,
+    /// The peer ports of the nodes, by their IDs.
+    ports: Vec<u16>,
+}
+
+impl Console {
+    /// Initializes a new Console.
+    ///
+    /// # Parameters
+    /// * 'state' - the runtime state the commands are carried out on.
+    /// * 'ports' - the peer ports of the nodes, by their IDs.
+    pub fn new(state: Arc<InterceptorState>, ports: Vec<u16>) -> Self {
+        Self { state, ports }
+    }
+
+    /// Parses and carries out a line typed in the console, and returns what is printed back.
+    ///
+    /// # Parameters
+    /// * 'line' - the line.
+    pub fn handle_line(&self, line: &str) -> String {
+        match line.parse().and_then(|command| self.execute(command)) {
+            Ok(output) => {
+                info!("Console: {}", line);
+                output
+            }
+            Err(e) => e,
+        }
+    }
+
+    /// Carries out a command, and returns what was done.
+    ///
+    /// # Parameters
+    /// * 'command' - the command.
+    pub fn execute(&self, command: ConsoleCommand) -> Result<String, String> {
+        match command {
+            ConsoleCommand::Delay { from, to, delay } => {
+                let delay_ms = u32::try_from(delay.as_millis())
+                    .map_err(|_| format!("A delay of {:?} is too long", delay))?;
+                self.change_rule(from, to, |rule| rule.delay_ms = delay_ms)
+            }
+            ConsoleCommand::Loss {
+                from,
+                to,
+                probability,
+            } => self.change_rule(from, to, |rule| rule.drop_probability = probability),
+            ConsoleCommand::Drop { message_type, from } => {
+                let blackhole = Blackhole {
+                    from_port: self.port(from)?,
+                    message_type,
+                };
+                match self.state.add_blackhole(blackhole) {
+                    true => Ok(format!("Dropping all {} of node {}", message_type, from)),
+                    false => Ok(format!(
+                        "{} of node {} were already dropped",
+                        message_type, from
+                    )),
+                }
+            }
+            ConsoleCommand::Undrop { message_type, from } => {
+                let blackhole = Blackhole {
+                    from_port: self.port(from)?,
+                    message_type,
+                };
+                match self.state.remove_blackhole(blackhole) {
+                    true => Ok(format!(
+                        "No longer dropping {} of node {}",
+                        message_type, from
+                    )),
+                    false => Err(format!("{} of node {} are not dropped", message_type, from)),
+                }
+            }
+            ConsoleCommand::Partition(components) => self.partition(&components),
+            ConsoleCommand::Heal => {
+                self.state.set_one_way_partition(OneWayPartition::default());
+                for link in self.state.links() {
+                    let _ =
+                        self.state
+                            .set_link_rule(link.from_port, link.to_port, LinkRule::default());
+                }
+                for blackhole in self.state.blackholes() {
+                    self.state.remove_blackhole(blackhole);
+                }
+                Ok(
+                    "Healed the partition, and removed all delays, losses and dropped messages"
+                        .to_string(),
+                )
+            }
+            ConsoleCommand::Pause => {
+                self.state.set_paused(true);
+                Ok("Forwarding paused".to_string())
+            }
+            ConsoleCommand::Resume => {
+                self.state.set_paused(false);
+                Ok("Forwarding resumed".to_string())
+            }
+            ConsoleCommand::Stats => Ok(self.stats()),
+            ConsoleCommand::Help => Ok(HELP.to_string()),
+        }
+    }
+
+    /// Changes the rule of a link, and returns the new rule.
+    ///
+    /// # Parameters
+    /// * 'from' - the ID of the node the messages come from.
+    /// * 'to' - the ID of the node the messages are sent to.
+    /// * 'change' - how the rule is changed.
+    fn change_rule(
+        &self,
+        from: u32,
+        to: u32,
+        change: impl FnOnce(&mut LinkRule),
+    ) -> Result<String, String> {
+        let (from_port, to_port) = (self.port(from)?, self.port(to)?);
+        let link = self
+            .state
+            .link(from_port, to_port)
+            .ok_or_else(|| format!("Link {}->{} does not exist", from, to))?;
+        let mut rule = link.rule();
+        change(&mut rule);
+        self.state
+            .set_link_rule(from_port, to_port, rule)
+            .map_err(|e| e.to_string())?;
+        Ok(format!(
+            "Link {}->{}: delay {} ms, drop probability {}",
+            from, to, rule.delay_ms, rule.drop_probability
+        ))
+    }
+
+    /// Cuts the links between components, and returns the amount of cut links.
+    ///
+    /// # Parameters
+    /// * 'components' - the components, by the IDs of their nodes.
+    fn partition(&self, components: &[Vec<u32>]) -> Result<String, String> {
+        let mut component_of = vec![None; self.ports.len()];
+        for (i, component) in components.iter().enumerate() {
+            for node in component {
+                self.port(*node)?;
+                if component_of[*node as usize].replace(i).is_some() {
+                    return Err(format!("Node {} is in more than one component", node));
+                }
+            }
+        }
+        let cut_links: Vec<(u16, u16)> = self
+            .state
+            .links()
+            .iter()
+            .filter_map(|link| {
+                let from = self.node(link.from_port)?;
+                let to = self.node(link.to_port)?;
+                (component_of[from] != component_of[to]).then_some((link.from_port, link.to_port))
+            })
+            .collect();
+        let amount = cut_links.len();
+        self.state
+            .set_one_way_partition(OneWayPartition { cut_links });
+        Ok(format!("Cut {} links", amount))
+    }
+
+    /// Returns the counters and rules of the links, the blackholes and the cut links, as they are printed.
+    fn stats(&self) -> String {
+        let name = |port: u16| {
+            self.node(port)
+                .map_or(port.to_string(), |node| node.to_string())
+        };
+        let mut lines = vec![
+            format!(
+                "paused: {}, time dilation: {}, passthrough: {}",
+                self.state.is_paused(),
+                self.state.time_dilation(),
+                self.state.is_passthrough()
+            ),
+            format!(
+                "{:<11}  {:>10}  {:>10}  {:>8}  {:>16}",
+                "link", "handled", "dropped", "delay_ms", "drop_probability"
+            ),
+        ];
+        for link in self.state.links() {
+            let rule = link.rule();
+            lines.push(format!(
+                "{:<11}  {:>10}  {:>10}  {:>8}  {:>16}",
+                format!("{}->{}", name(link.from_port), name(link.to_port)),
+                link.handled(),
+                link.dropped(),
+                rule.delay_ms,
+                rule.drop_probability
+            ));
+        }
+        let blackholes: Vec<String> = self
+            .state
+            .blackholes()
+            .iter()
+            .map(|blackhole| {
+                format!(
+                    "{} of {}",
+                    blackhole.message_type,
+                    name(blackhole.from_port)
+                )
+            })
+            .collect();
+        if !blackholes.is_empty() {
+            lines.push(format!("dropped: {}", blackholes.join(", ")));
+        }
+        let cut_links: Vec<String> = self
+            .state
+            .one_way_partition()
+            .cut_links
+            .iter()
+            .map(|(from_port, to_port)| format!("{}->{}", name(*from_port), name(*to_port)))
+            .collect();
+        if !cut_links.is_empty() {
+            lines.push(format!("cut: {}", cut_links.join(", ")));
+        }
+        lines.join("\n")
+    }
+
+    /// Returns the peer port of a node.
+    ///
+    /// # Parameters
+    /// * 'node' - the ID of the node.
+    fn port(&self, node: u32) -> Result<u16, String> {
+        self.ports
+            .get(node as usize)
+            .copied()
+            .ok_or_else(|| format!("Node {} does not exist", node))
+    }
+
+    /// Returns the ID of the node with a peer port, if it is one of the nodes.
+    ///
+    /// # Parameters
+    /// * 'port' - the peer port.
+    fn node(&self, port: u16) -> Option<usize> {
+        self.ports.iter().position(|node_port| *node_port == port)
+    }
+}
+
+/// Serves the console until the listener fails. Every connection is a session, of which every line is a command.
+///
+/// # Parameters
+/// * 'listener' - the bound listener of the console.
+/// * 'console' - the console the commands are carried out by.
+pub async fn serve(listener: TcpListener, console: Arc<Console>) {
+    info!("Serving the console on {:?}", listener.local_addr());
+    loop {
+        match listener.accept().await {
+            Ok((stream, address)) => {
+                let console = console.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = session(stream, &console).await {
+                        warn!("Console session with {} ended: {}", address, e);
+                    }
+                });
+            }
+            Err(e) => {
+                error!("The console stopped: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+/// Carries out the commands of a session until it quits or the connection is closed.
+///
+/// # Parameters
+/// * 'stream' - the connection of the session.
+/// * 'console' - the console the commands are carried out by.
+async fn session(stream: TcpStream, console: &Console) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    writer
+        .write_all(b"Connected to the console, type help for the commands\n")
+        .await?;
+    while let Some(line) = lines.next_line().await? {
+        let line = line.trim();
+        match line {
+            "" => continue,
+            "quit" | "exit" => break,
+            line => {
+                let output = console.handle_line(line);
+                writer.write_all(format!("{}\n", output).as_bytes()).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs the `console` subcommand: attaches the terminal to the console of a running network, until the session quits or
+/// the input ends.
+///
+/// # Parameters
+/// * 'address' - the address the console is served on.
+pub async fn attach_command(address: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let stream = TcpStream::connect(address)
+        .await
+        .map_err(|e| format!("Could not connect to the console at {}: {}", address, e))?;
+    let (mut reader, mut writer) = stream.into_split();
+    let mut output =
+        tokio::spawn(async move { tokio::io::copy(&mut reader, &mut tokio::io::stdout()).await });
+
+    // The terminal is read on a thread of its own, which does not keep the runtime from shutting down
+    let (line_sender, mut lines) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(Result::ok) {
+            if line_sender.send(line).is_err() {
+                return;
+            }
+        }
+    });
+    loop {
+        tokio::select! {
+            result = &mut output => {
+                result??;
+                return Ok(());
+            }
+            line = lines.recv() => match line {
+                Some(line) => writer.write_all(format!("{}\n", line).as_bytes()).await?,
+                None => {
+                    writer.shutdown().await?;
+                    output.await??;
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::console::{Console, ConsoleCommand};
+    use crate::interceptor_state::{Blackhole, InterceptorState, LinkRule};
+    use crate::message_type::MessageType;
+    use crate::packet_timeline::PacketTimeline;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn console() -> (Arc<InterceptorState>, Console) {
+        let state = Arc::new(InterceptorState::new(Arc::new(PacketTimeline::new(10))));
+        for from in 0..4u16 {
+            for to in (0..4u16).filter(|to| *to != from) {
+                state.register_link(60000 + from, 60000 + to, None);
+            }
+        }
+        let console = Console::new(state.clone(), (60000..60004).collect());
+        (state, console)
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn commands_are_parsed() {
+        assert_eq!(
+            "delay 1 2 300ms".parse(),
+            Ok(ConsoleCommand::Delay {
+                from: 1,
+                to: 2,
+                delay: Duration::from_millis(300),
+            })
+        );
+        assert_eq!(
+            "delay n1 n2 1.5s".parse::<ConsoleCommand>().unwrap(),
+            ConsoleCommand::Delay {
+                from: 1,
+                to: 2,
+                delay: Duration::from_millis(1500),
+            }
+        );
+        assert_eq!(
+            "drop validations from 3".parse(),
+            Ok(ConsoleCommand::Drop {
+                message_type: MessageType::Validation,
+                from: 3,
+            })
+        );
+        assert_eq!(
+            "undrop mtPING from 0".parse(),
+            Ok(ConsoleCommand::Undrop {
+                message_type: MessageType::Ping,
+                from: 0,
+            })
+        );
+        assert_eq!(
+            "partition {0, 1} | {2,3}".parse(),
+            Ok(ConsoleCommand::Partition(vec![vec![0, 1], vec![2, 3]]))
+        );
+        assert_eq!("  stats ".parse(), Ok(ConsoleCommand::Stats));
+
+        for invalid in [
+            "delay 1 2",
+            "delay 1 2 soon",
+            "drop validations 3",
+            "drop gossip from 3",
+            "partition {0,1}",
+            "partition 0,1|2",
+            "reboot 1",
+            "",
+        ] {
+            assert!(invalid.parse::<ConsoleCommand>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn commands_change_the_state() {
+        let (state, console) = console();
+        console.handle_line("delay 1 2 300ms");
+        console.handle_line("loss 1 2 0.5");
+        assert_eq!(
+            state.link(60001, 60002).unwrap().rule(),
+            LinkRule {
+                delay_ms: 300,
+                drop_probability: 0.5,
+            }
+        );
+        assert_eq!(
+            state.link(60002, 60001).unwrap().rule(),
+            LinkRule::default()
+        );
+        assert!(console
+            .handle_line("loss 1 2 2")
+            .contains("not between 0 and 1"));
+        assert!(console
+            .handle_line("delay 1 7 300ms")
+            .contains("does not exist"));
+
+        console.handle_line("drop validations from 3");
+        assert_eq!(
+            state.blackholes(),
+            vec![Blackhole {
+                from_port: 60003,
+                message_type: MessageType::Validation,
+            }]
+        );
+
+        assert_eq!(console.handle_line("partition {0,1}|{2}"), "Cut 10 links");
+        let partition = state.one_way_partition();
+        assert!(partition.cuts(60000, 60002) && partition.cuts(60002, 60001));
+        // Node 3 is in no component, so it is cut off from all of them
+        assert!(partition.cuts(60003, 60000) && !partition.cuts(60000, 60001));
+        assert!(console
+            .handle_line("partition {0,1}|{1,2}")
+            .contains("more than one component"));
+
+        let stats = console.handle_line("stats");
+        assert!(stats.contains("1->2"), "{}", stats);
+        assert!(stats.contains("dropped: mtVALIDATION of 3"), "{}", stats);
+
+        console.handle_line("heal");
+        assert!(state.one_way_partition().cut_links.is_empty());
+        assert!(state.blackholes().is_empty());
+        assert_eq!(
+            state.link(60001, 60002).unwrap().rule(),
+            LinkRule::default()
+        );
+    }
+}
@@ -0,0 +1,181 @@
+//! This module is responsible for the resource usage of the node containers that is streamed to the controller, such
+//! that the behavior of the messages can be correlated with the resource pressure on the nodes.
+//!
+//! Docker streams the stats of every container about once per second. At most one sample per container is forwarded
+//! every interval, over a single call to the controller. If the controller is not keeping up, samples are skipped rather
+//! than queued.
+
+use crate::docker_manager::DockerContainer;
+use crate::packet_client::proto::ContainerStats;
+use crate::packet_client::PacketClient;
+use bollard::container::{Stats, StatsOptions};
+use bollard::Docker;
+use chrono::DateTime;
+use futures_util::StreamExt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, Mutex};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::Code;
+use tracing::{debug, info, warn};
+
+/// Computes the CPU usage of a container the way `docker stats` does: 100 per fully used CPU.
+///
+/// # Parameters
+/// * 'cpu_delta' - the CPU time used by the container since the previous sample.
+/// * 'system_delta' - the CPU time of the host since the previous sample.
+/// * 'online_cpus' - the amount of CPUs of the host.
+fn cpu_percent(cpu_delta: u64, system_delta: u64, online_cpus: u64) -> f64 {
+    if system_delta == 0 {
+        return 0.0;
+    }
+    cpu_delta as f64 / system_delta as f64 * online_cpus as f64 * 100.0
+}
+
+/// Converts the stats of a container as reported by Docker to the stats sent to the controller.
+///
+/// # Parameters
+/// * 'container' - the container.
+/// * 'stats' - the stats of the container.
+fn container_stats(container: &DockerContainer, stats: &Stats) -> ContainerStats {
+    let cpu = &stats.cpu_stats;
+    let precpu = &stats.precpu_stats;
+    let online_cpus = cpu.online_cpus.unwrap_or_else(|| {
+        cpu.cpu_usage
+            .percpu_usage
+            .as_ref()
+            .map_or(1, |usage| usage.len() as u64)
+    });
+    let (network_rx_bytes, network_tx_bytes) = stats
+        .networks
+        .iter()
+        .flat_map(|networks| networks.values())
+        .fold((0, 0), |(rx, tx), network| {
+            (rx + network.rx_bytes, tx + network.tx_bytes)
+        });
+    ContainerStats {
+        peer_port: container.port_peer,
+        name: container.name.clone(),
+        timestamp_ns: DateTime::parse_from_rfc3339(&stats.read)
+            .ok()
+            .and_then(|read| read.timestamp_nanos_opt())
+            .unwrap_or_default() as u64,
+        cpu_percent: cpu_percent(
+            cpu.cpu_usage
+                .total_usage
+                .saturating_sub(precpu.cpu_usage.total_usage),
+            cpu.system_cpu_usage
+                .unwrap_or_default()
+                .saturating_sub(precpu.system_cpu_usage.unwrap_or_default()),
+            online_cpus,
+        ),
+        memory_usage_bytes: stats.memory_stats.usage.unwrap_or_default(),
+        memory_limit_bytes: stats.memory_stats.limit.unwrap_or_default(),
+        network_rx_bytes,
+        network_tx_bytes,
+    }
+}
+
+/// Reads the stats of a container from Docker and sends at most one sample every interval, until the container stops
+/// or the stream to the controller ends.
+///
+/// # Parameters
+/// * 'docker' - the Docker API the stats are read from.
+/// * 'container' - the container.
+/// * 'interval' - the minimum time between two samples of the container.
+/// * 'sender' - the sender of the stream to the controller.
+async fn sample(
+    docker: Docker,
+    container: DockerContainer,
+    interval: Duration,
+    sender: mpsc::Sender<ContainerStats>,
+) {
+    let Some(id) = container.id.clone() else {
+        return;
+    };
+    let mut stats = docker.stats(
+        &id,
+        Some(StatsOptions {
+            stream: true,
+            one_shot: false,
+        }),
+    );
+    let mut last_sent: Option<Instant> = None;
+    while let Some(result) = stats.next().await {
+        let stats = match result {
+            Ok(stats) => stats,
+            Err(e) => {
+                debug!("Could not read the stats of {}: {}", container.name, e);
+                break;
+            }
+        };
+        if last_sent.is_some_and(|last_sent| last_sent.elapsed() < interval) {
+            continue;
+        }
+        last_sent = Some(Instant::now());
+        match sender.try_send(container_stats(&container, &stats)) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                debug!(
+                    "Skipped the stats of {}, the controller is behind",
+                    container.name
+                )
+            }
+            Err(TrySendError::Closed(_)) => break,
+        }
+    }
+}
+
+/// Streams the stats of every node container to the controller, until all containers stopped or the controller ends
+/// the stream. Controllers that do not implement the stats are skipped.
+///
+/// # Parameters
+/// * 'client' - the PacketClient whose connection to the controller is used.
+/// * 'docker' - the Docker API the stats are read from.
+/// * 'containers' - the node containers.
+/// * 'interval' - the minimum time between two samples of a container.
+pub async fn run(
+    client: Arc<Mutex<PacketClient>>,
+    docker: Docker,
+    containers: Vec<DockerContainer>,
+    interval: Duration,
+) {
+    let (sender, receiver) = mpsc::channel(containers.len().max(1));
+    let (mut service, request) = {
+        let client = client.lock().await;
+        (
+            client.client.clone(),
+            client.request(ReceiverStream::new(receiver)),
+        )
+    };
+    let samplers: Vec<_> = containers
+        .into_iter()
+        .map(|container| tokio::spawn(sample(docker.clone(), container, interval, sender.clone())))
+        .collect();
+    drop(sender);
+    match service.send_container_stats(request).await {
+        Ok(_) => info!("The stream of container stats to the controller ended"),
+        Err(status) if status.code() == Code::Unimplemented => {
+            debug!("The controller does not receive container stats");
+        }
+        Err(status) => warn!("The container stats to the controller stopped: {}", status),
+    }
+    for sampler in samplers {
+        sampler.abort();
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::container_stats::cpu_percent;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn cpu_usage_is_relative_to_one_cpu() {
+        assert_eq!(cpu_percent(0, 0, 4), 0.0);
+        assert_eq!(cpu_percent(50, 100, 1), 50.0);
+        // A container using two of the four CPUs of the host
+        assert_eq!(cpu_percent(200, 400, 4), 200.0);
+    }
+}
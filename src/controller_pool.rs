@@ -0,0 +1,343 @@
+//! This module is responsible for spreading the intercepted messages over multiple controllers, and over multiple
+//! channels to every controller.
+//!
+//! Every link is pinned to one controller at a time, chosen by hashing the link, such that the messages of a link are
+//! decided on in order by the same controller while the links together are spread over all controllers. When a
+//! controller becomes unreachable, the links pinned to it are moved to the other controllers. It is only assigned new
+//! links again after the retry time, and links that were moved stay on the controller they were moved to.
+//!
+//! Every channel is a separate connection with its own client, and requests on a channel are sent one at a time. The
+//! links of a controller are spread over its channels by hashing them as well, such that the decisions for independent
+//! links proceed in parallel, while the messages of a link are still sent in order over the same channel.
+
+use crate::event_bus::{EventBus, EventKind};
+use crate::packet_client::PacketClient;
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Struct that represents a controller as it is added to the pool.
+#[derive(Debug)]
+pub struct ControllerEndpoint {
+    /// The address of the gRPC server of the controller.
+    pub address: String,
+    /// The clients of the channels to the controller, at least one.
+    pub channels: Vec<Arc<tokio::sync::Mutex<PacketClient>>>,
+    /// Why the controller is unreachable, if it was found to be unreachable while connecting to it.
+    pub unreachable: Option<String>,
+}
+
+/// Struct that represents one of the controllers the messages are spread over.
+#[derive(Debug)]
+struct Endpoint {
+    /// The address of the gRPC server of the controller.
+    address: String,
+    /// The clients of the channels to the controller.
+    channels: Vec<Arc<tokio::sync::Mutex<PacketClient>>>,
+    /// The moment the controller was last found to be unreachable, if it was.
+    failed_at: Mutex<Option<Instant>>,
+}
+
+/// Struct that represents the health of a controller and the links pinned to it, as they are exposed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EndpointSummary {
+    /// The address of the gRPC server of the controller.
+    pub address: String,
+    /// Whether the controller can be assigned links.
+    pub healthy: bool,
+    /// The amount of channels to the controller.
+    pub channels: usize,
+    /// The amount of links pinned to the controller.
+    pub links: usize,
+    /// How many times the controller was found to be unreachable.
+    pub failures: u64,
+}
+
+/// Struct that represents the controllers the messages are spread over.
+#[derive(Debug)]
+pub struct ControllerPool {
+    endpoints: Vec<Endpoint>,
+    /// How long an unreachable controller is not assigned new links.
+    retry_after: Duration,
+    /// The controller every link is pinned to, by the ports of the peer its messages come from and go to.
+    assignments: Mutex<HashMap<(u16, u16), usize>>,
+    /// How many times every controller was found to be unreachable.
+    failures: Mutex<Vec<u64>>,
+    /// The event bus the failovers are published on.
+    events: Arc<EventBus>,
+}
+
+impl ControllerPool {
+    /// Initializes a new ControllerPool, in which the controllers that were found to be unreachable while connecting
+    /// to them are not assigned links until the retry time passed.
+    ///
+    /// # Parameters
+    /// * 'endpoints' - the address, channels and health of every controller.
+    /// * 'retry_after' - how long an unreachable controller is not assigned new links.
+    /// * 'events' - the event bus the failovers are published on.
+    ///
+    /// # Panics
+    /// * If there are no controllers, or a controller has no channels.
+    pub fn new(
+        endpoints: Vec<ControllerEndpoint>,
+        retry_after: Duration,
+        events: Arc<EventBus>,
+    ) -> Self {
+        assert!(
+            !endpoints.is_empty(),
+            "A pool needs at least one controller"
+        );
+        assert!(
+            endpoints
+                .iter()
+                .all(|endpoint| !endpoint.channels.is_empty()),
+            "Every controller needs at least one channel"
+        );
+        let unreachable: Vec<(usize, String)> = endpoints
+            .iter()
+            .enumerate()
+            .filter_map(|(index, endpoint)| Some((index, endpoint.unreachable.clone()?)))
+            .collect();
+        let pool = Self {
+            failures: Mutex::new(vec![0; endpoints.len()]),
+            endpoints: endpoints
+                .into_iter()
+                .map(|endpoint| Endpoint {
+                    address: endpoint.address,
+                    channels: endpoint.channels,
+                    failed_at: Mutex::new(None),
+                })
+                .collect(),
+            retry_after,
+            assignments: Mutex::new(HashMap::new()),
+            events,
+        };
+        for (index, reason) in unreachable {
+            pool.report_failure(index, &reason);
+        }
+        pool
+    }
+
+    /// Returns the controller a link is pinned to as its index, together with the client of the channel of the link.
+    /// A link that is not pinned yet is pinned to the healthy controller with the highest hash for it, or to any
+    /// controller if none is healthy.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer where the messages of the link come from.
+    /// * 'to_port' - the port of the peer the messages of the link are sent to.
+    pub fn client_for(
+        &self,
+        from_port: u16,
+        to_port: u16,
+    ) -> (usize, Arc<tokio::sync::Mutex<PacketClient>>) {
+        let mut assignments = self.assignments.lock().unwrap();
+        let index = *assignments
+            .entry((from_port, to_port))
+            .or_insert_with(|| self.choose(from_port, to_port));
+        let channels = &self.endpoints[index].channels;
+        let channel = hash(&(from_port, to_port)) as usize % channels.len();
+        (index, channels[channel].clone())
+    }
+
+    /// Marks a controller as unreachable and unpins the links pinned to it, such that their next messages are sent to
+    /// another controller. Returns whether another controller is healthy, which is never the case for a single one.
+    ///
+    /// # Parameters
+    /// * 'index' - the index of the controller.
+    /// * 'reason' - why the controller is unreachable.
+    pub fn report_failure(&self, index: usize, reason: &str) -> bool {
+        // A single controller has nothing to fail over to
+        if self.endpoints.len() == 1 {
+            return false;
+        }
+        let endpoint = &self.endpoints[index];
+        *endpoint.failed_at.lock().unwrap() = Some(Instant::now());
+        self.failures.lock().unwrap()[index] += 1;
+        let links = {
+            let mut assignments = self.assignments.lock().unwrap();
+            let before = assignments.len();
+            assignments.retain(|_, assigned| *assigned != index);
+            before - assignments.len()
+        };
+        warn!(
+            "The controller at {} is unreachable, moving its {} links to the other controllers: {}",
+            endpoint.address, links, reason
+        );
+        self.events.emit(EventKind::ControllerFailover {
+            address: endpoint.address.clone(),
+            links,
+            reason: reason.to_string(),
+        });
+        (0..self.endpoints.len()).any(|other| other != index && self.is_healthy(other))
+    }
+
+    /// Returns the amount of controllers in the pool.
+    pub fn controller_count(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    /// Returns the health of every controller and the amount of links pinned to it.
+    pub fn summary(&self) -> Vec<EndpointSummary> {
+        let assignments = self.assignments.lock().unwrap();
+        let failures = self.failures.lock().unwrap();
+        self.endpoints
+            .iter()
+            .enumerate()
+            .map(|(index, endpoint)| EndpointSummary {
+                address: endpoint.address.clone(),
+                healthy: self.is_healthy(index),
+                channels: endpoint.channels.len(),
+                links: assignments
+                    .values()
+                    .filter(|assigned| **assigned == index)
+                    .count(),
+                failures: failures[index],
+            })
+            .collect()
+    }
+
+    /// Returns whether a controller can be assigned links.
+    ///
+    /// # Parameters
+    /// * 'index' - the index of the controller.
+    fn is_healthy(&self, index: usize) -> bool {
+        self.endpoints[index]
+            .failed_at
+            .lock()
+            .unwrap()
+            .map_or(true, |failed_at| failed_at.elapsed() >= self.retry_after)
+    }
+
+    /// Chooses the controller for a link by rendezvous hashing, such that a link keeps its controller when another
+    /// controller fails.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer where the messages of the link come from.
+    /// * 'to_port' - the port of the peer the messages of the link are sent to.
+    fn choose(&self, from_port: u16, to_port: u16) -> usize {
+        let score = |index: usize| hash(&(from_port, to_port, index));
+        let healthy: Vec<usize> = (0..self.endpoints.len())
+            .filter(|index| self.is_healthy(*index))
+            .collect();
+        let candidates = if healthy.is_empty() {
+            (0..self.endpoints.len()).collect()
+        } else {
+            healthy
+        };
+        candidates
+            .into_iter()
+            .max_by_key(|index| score(*index))
+            .unwrap_or_default()
+    }
+}
+
+/// Returns the hash of a value, which is the same for every run.
+///
+/// # Parameters
+/// * 'value' - the value.
+fn hash<T: Hash>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::controller_pool::{ControllerEndpoint, ControllerPool};
+    use crate::event_bus::EventBus;
+    use crate::packet_client::PacketClient;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::Mutex;
+
+    fn pool(controllers: usize, channels: usize, retry_after: Duration) -> ControllerPool {
+        ControllerPool::new(
+            (0..controllers)
+                .map(|i| ControllerEndpoint {
+                    address: format!("http://[::1]:{}", 50051 + i),
+                    channels: (0..channels)
+                        .map(|_| Arc::new(Mutex::new(PacketClient::lazy())))
+                        .collect(),
+                    unreachable: None,
+                })
+                .collect(),
+            retry_after,
+            Arc::new(EventBus::default()),
+        )
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn links_are_spread_and_pinned() {
+        let pool = pool(3, 1, Duration::from_secs(60));
+        let mut used = [false; 3];
+        for to_port in 60001..60031 {
+            let (index, _) = pool.client_for(60000, to_port);
+            assert_eq!(pool.client_for(60000, to_port).0, index);
+            used[index] = true;
+        }
+        assert_eq!(used, [true; 3]);
+        assert_eq!(
+            pool.summary()
+                .iter()
+                .map(|endpoint| endpoint.links)
+                .sum::<usize>(),
+            30
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn failover_moves_only_the_links_of_the_failed_controller() {
+        let pool = pool(2, 1, Duration::from_secs(60));
+        let on_second = (60001..60011)
+            .filter(|to_port| pool.client_for(60000, *to_port).0 == 1)
+            .count();
+
+        assert!(pool.report_failure(0, "unavailable"));
+        let summary = pool.summary();
+        assert!(!summary[0].healthy);
+        assert_eq!(summary[0].failures, 1);
+        assert_eq!(summary[0].links, 0);
+        assert_eq!(summary[1].links, on_second);
+        for to_port in 60001..60011 {
+            assert_eq!(pool.client_for(60000, to_port).0, 1);
+        }
+
+        // Without healthy controllers, links are still assigned
+        assert!(!pool.report_failure(1, "unavailable"));
+        assert!(pool.client_for(60000, 60001).0 < 2);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn failed_controller_is_retried_for_new_links() {
+        let pool = pool(2, 1, Duration::ZERO);
+        pool.report_failure(0, "unavailable");
+        assert!(pool.summary()[0].healthy);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn links_are_spread_over_channels() {
+        let pool = pool(1, 4, Duration::from_secs(60));
+        let mut channels: Vec<Arc<Mutex<PacketClient>>> = Vec::new();
+        for to_port in 60001..60041 {
+            let (_, channel) = pool.client_for(60000, to_port);
+            assert!(Arc::ptr_eq(&pool.client_for(60000, to_port).1, &channel));
+            if !channels.iter().any(|used| Arc::ptr_eq(used, &channel)) {
+                channels.push(channel);
+            }
+        }
+        assert_eq!(channels.len(), 4);
+        assert_eq!(pool.summary()[0].channels, 4);
+
+        // A single controller is never marked as unreachable
+        assert!(!pool.report_failure(0, "unavailable"));
+        assert!(pool.summary()[0].healthy);
+    }
+}
@@ -0,0 +1,107 @@
+//! This module is responsible for throttling the CPU of nodes during a run, which simulates overloaded nodes: they
+//! stay connected and keep participating in consensus, but handle everything more slowly.
+//!
+//! The CPU limit of the containers of the nodes is tightened through Docker for a period of the run, after which the
+//! limit the containers were started with is restored.
+
+use crate::docker_manager;
+use crate::event_bus::{EventBus, EventKind};
+use bollard::Docker;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info};
+
+/// Struct that represents a node whose CPU is throttled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThrottledNode {
+    /// The ID of the node.
+    pub node_id: u32,
+    /// The ID of the container of the node.
+    pub container_id: String,
+    /// The amount of CPUs the container was started with, which is restored after the throttling. Unlimited if None.
+    pub original_cpus: Option<f64>,
+}
+
+/// Struct that represents the throttling of the CPU of a set of nodes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CpuThrottle {
+    /// The throttled nodes.
+    pub nodes: Vec<ThrottledNode>,
+    /// The amount of CPUs the throttled nodes can use.
+    pub cpus: f64,
+}
+
+impl CpuThrottle {
+    /// Tightens the CPU limit of all throttled nodes.
+    ///
+    /// # Parameters
+    /// * 'docker' - the Docker API the limits are changed through.
+    /// * 'events' - the bus on which every changed limit is published.
+    pub async fn start(&self, docker: &Docker, events: &EventBus) {
+        for node in self.nodes.iter() {
+            Self::set_limit(docker, events, node, Some(self.cpus)).await;
+        }
+    }
+
+    /// Restores the CPU limit every throttled node was started with.
+    ///
+    /// # Parameters
+    /// * 'docker' - the Docker API the limits are changed through.
+    /// * 'events' - the bus on which every changed limit is published.
+    pub async fn restore(&self, docker: &Docker, events: &EventBus) {
+        for node in self.nodes.iter() {
+            Self::set_limit(docker, events, node, node.original_cpus).await;
+        }
+    }
+
+    /// Changes the CPU limit of the container of a node, and publishes the change if it succeeded.
+    ///
+    /// # Parameters
+    /// * 'docker' - the Docker API the limit is changed through.
+    /// * 'events' - the bus on which the changed limit is published.
+    /// * 'node' - the node.
+    /// * 'cpus' - the amount of CPUs the node can use, unlimited if None.
+    async fn set_limit(
+        docker: &Docker,
+        events: &EventBus,
+        node: &ThrottledNode,
+        cpus: Option<f64>,
+    ) {
+        match docker_manager::set_cpu_limit(docker, &node.container_id, cpus).await {
+            Ok(()) => events.emit(EventKind::CpuLimitChanged {
+                node_id: node.node_id,
+                cpus,
+            }),
+            Err(e) => error!(
+                "Could not change the CPU limit of node {}: {}",
+                node.node_id, e
+            ),
+        }
+    }
+}
+
+/// Throttles the CPU of nodes for a fixed period of the run, as configured locally.
+///
+/// # Parameters
+/// * 'throttle' - the throttling.
+/// * 'docker' - the Docker API the limits are changed through.
+/// * 'start_after' - how long after the links are started the throttling starts.
+/// * 'duration' - how long the throttling lasts, until the end of the run if None.
+/// * 'events' - the bus on which every changed limit is published.
+pub async fn run_scheduled(
+    throttle: CpuThrottle,
+    docker: Docker,
+    start_after: Duration,
+    duration: Option<Duration>,
+    events: Arc<EventBus>,
+) {
+    tokio::time::sleep(start_after).await;
+    let node_ids: Vec<u32> = throttle.nodes.iter().map(|node| node.node_id).collect();
+    info!("Throttling nodes {:?} to {} CPUs", node_ids, throttle.cpus);
+    throttle.start(&docker, &events).await;
+    if let Some(duration) = duration {
+        tokio::time::sleep(duration).await;
+        info!("Restoring the CPU limits of nodes {:?}", node_ids);
+        throttle.restore(&docker, &events).await;
+    }
+}
@@ -0,0 +1,137 @@
+//! This module is responsible for collecting the diagnostics of a failed run into a single tarball,
+//! such that it can be attached to a bug report.
+
+use crate::run_id::RunId;
+use chrono::Utc;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::error::Error;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// Struct that represents the diagnostics of a failed run, before they are written.
+#[derive(Debug, Clone, Default)]
+pub struct CrashBundle {
+    /// The files in the bundle, by their name in the tarball.
+    files: Vec<(String, Vec<u8>)>,
+}
+
+impl CrashBundle {
+    /// Initializes a new CrashBundle, which explains why it was collected.
+    ///
+    /// # Parameters
+    /// * 'reason' - why the run failed.
+    pub fn new(reason: &str) -> Self {
+        let mut bundle = Self::default();
+        bundle.add(
+            "reason.txt",
+            format!(
+                "{}\n\nCollected at {} by rocket-interceptor {}\n",
+                reason,
+                Utc::now().to_rfc3339(),
+                env!("CARGO_PKG_VERSION")
+            ),
+        );
+        bundle
+    }
+
+    /// Adds a file to the bundle.
+    ///
+    /// # Parameters
+    /// * 'name' - the name of the file in the tarball.
+    /// * 'contents' - the contents of the file.
+    pub fn add(&mut self, name: &str, contents: impl Into<Vec<u8>>) {
+        self.files.push((name.to_string(), contents.into()));
+    }
+
+    /// Adds a file on disk to the bundle, if it exists.
+    ///
+    /// # Parameters
+    /// * 'name' - the name of the file in the tarball.
+    /// * 'path' - the path of the file on disk.
+    pub fn add_file(&mut self, name: &str, path: &str) {
+        match fs::read(path) {
+            Ok(contents) => self.add(name, contents),
+            Err(e) => debug!("Not adding {} to the crash bundle: {}", path, e),
+        }
+    }
+
+    /// Returns the names of the files in the bundle, in the order they were added.
+    pub fn names(&self) -> Vec<&str> {
+        self.files.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    /// Writes the bundle as a gzipped tarball to a new file in the given directory,
+    /// and returns the path of that file.
+    ///
+    /// # Parameters
+    /// * 'directory' - the directory all bundles are written to.
+    /// * 'run_id' - the identifier of the run, which names the bundle.
+    pub fn write(&self, directory: &str, run_id: &RunId) -> Result<PathBuf, Box<dyn Error>> {
+        fs::create_dir_all(directory)?;
+        let name = format!("crash-{}", run_id);
+        let path = Path::new(directory).join(format!("{}.tar.gz", name));
+        let encoder = GzEncoder::new(File::create(&path)?, Compression::default());
+        let mut archive = tar::Builder::new(encoder);
+        let modified = Utc::now().timestamp() as u64;
+        for (file_name, contents) in self.files.iter() {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(modified);
+            header.set_cksum();
+            archive.append_data(
+                &mut header,
+                format!("{}/{}", name, file_name),
+                contents.as_slice(),
+            )?;
+        }
+        archive.into_inner()?.finish()?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::crash_bundle::CrashBundle;
+    use crate::run_id::RunId;
+    use flate2::read::GzDecoder;
+    use std::fs::{self, File};
+    use std::io::Read;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn write_bundle() {
+        let directory = std::env::temp_dir().join("rocket_crash_bundle_test");
+        let _ = fs::remove_dir_all(&directory);
+
+        let mut bundle = CrashBundle::new("A task panicked: controller unreachable");
+        bundle.add("packets.txt", "60000 -> 60001 mtPING");
+        bundle.add_file("interceptor.toml", "/nonexistent/interceptor.toml");
+        assert_eq!(bundle.names(), vec!["reason.txt", "packets.txt"]);
+
+        let path = bundle
+            .write(directory.to_str().unwrap(), &RunId("test".to_string()))
+            .unwrap();
+        assert!(path.to_str().unwrap().ends_with(".tar.gz"));
+
+        let mut archive = tar::Archive::new(GzDecoder::new(File::open(&path).unwrap()));
+        let mut files = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let name = entry.path().unwrap().to_str().unwrap().to_string();
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents).unwrap();
+            files.push((name, contents));
+        }
+        assert_eq!(files.len(), 2);
+        assert!(files[0].0.ends_with("/reason.txt"));
+        assert!(files[0]
+            .1
+            .starts_with("A task panicked: controller unreachable"));
+        assert_eq!(files[1].1, "60000 -> 60001 mtPING");
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,463 @@
+//! This module is responsible for the live terminal dashboard, which shows the state and traffic of every
+//! intercepted link and the most recent decisions while the interceptor is running.
+//!
+//! The dashboard is built from the link-state events and the packet timeline, such that it does not
+//! add any work to the links themselves.
+
+use crate::config::DashboardConfig;
+use crate::event_bus::{Event, EventKind};
+use crate::interceptor_state::InterceptorState;
+use crate::message_type::MessageType;
+use crate::packet_timeline::PacketRecord;
+use chrono::{TimeDelta, Utc};
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::event::{self, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{Block, List, ListItem, Row, Table};
+use ratatui::{Frame, Terminal};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::TryRecvError;
+use tracing::warn;
+
+/// The amount of message types shown per link, the busiest first.
+const TYPES_PER_LINK: usize = 4;
+
+/// Enum that represents the state of a link as far as the dashboard knows.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkState {
+    /// Messages are being read from the link.
+    Connected,
+    /// Nothing was read from the link for longer than the idle timeout.
+    Idle,
+    /// The link is halted at a breakpoint before handling the message with the contained sequence.
+    Halted(u64),
+    /// The link stopped for the contained reason.
+    Dropped(String),
+}
+
+impl fmt::Display for LinkState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkState::Connected => write!(f, "connected"),
+            LinkState::Idle => write!(f, "idle"),
+            LinkState::Halted(sequence) => write!(f, "halted at #{}", sequence),
+            LinkState::Dropped(reason) => write!(f, "dropped ({})", reason),
+        }
+    }
+}
+
+/// Struct that represents everything the dashboard shows about a single link.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkView {
+    /// The state of the link.
+    pub state: LinkState,
+    /// The XRPL protocol version negotiated with the node the link reads from, if known.
+    pub protocol: Option<String>,
+    /// The messages per second of every message type, within the rate window.
+    pub rates: HashMap<MessageType, f64>,
+    /// The delay in ms applied to the most recent message on the link.
+    pub delay_ms: u32,
+}
+
+impl LinkView {
+    /// Initializes a new LinkView of a connected link without traffic.
+    fn new() -> Self {
+        Self {
+            state: LinkState::Connected,
+            protocol: None,
+            rates: HashMap::new(),
+            delay_ms: 0,
+        }
+    }
+
+    /// Returns the message types with the highest rates, the busiest first.
+    ///
+    /// # Parameters
+    /// * 'n' - the maximum amount of message types returned.
+    pub fn busiest(&self, n: usize) -> Vec<(MessageType, f64)> {
+        let mut rates: Vec<(MessageType, f64)> =
+            self.rates.iter().map(|(t, rate)| (*t, *rate)).collect();
+        rates.sort_by(|a, b| {
+            b.1.total_cmp(&a.1)
+                .then(a.0.to_string().cmp(&b.0.to_string()))
+        });
+        rates.truncate(n);
+        rates
+    }
+}
+
+/// Struct that represents the data shown on the dashboard, independent of how it is drawn.
+#[derive(Debug, Default)]
+pub struct DashboardModel {
+    /// The views of all links, by the ports of the peer the messages come from and go to.
+    pub links: BTreeMap<(u16, u16), LinkView>,
+    /// The partitions of the network, as published by the most recent event.
+    pub partitions: Vec<Vec<u32>>,
+    /// The most recently handled messages, newest first.
+    pub recent: Vec<PacketRecord>,
+}
+
+impl DashboardModel {
+    /// Updates the state of the links with a link-state event.
+    ///
+    /// # Parameters
+    /// * 'event' - the event.
+    pub fn apply(&mut self, event: &Event) {
+        match &event.kind {
+            EventKind::LinkConnected {
+                from_port,
+                to_port,
+                protocol,
+            } => {
+                let link = self
+                    .links
+                    .entry((*from_port, *to_port))
+                    .or_insert_with(LinkView::new);
+                link.state = LinkState::Connected;
+                link.protocol.clone_from(protocol);
+            }
+            EventKind::LinkIdle {
+                from_port, to_port, ..
+            } => {
+                self.link(*from_port, *to_port).state = LinkState::Idle;
+            }
+            EventKind::LinkDropped {
+                from_port,
+                to_port,
+                reason,
+            } => {
+                self.link(*from_port, *to_port).state = LinkState::Dropped(reason.clone());
+            }
+            EventKind::BreakpointHit(hit) => {
+                self.link(hit.from_port, hit.to_port).state = LinkState::Halted(hit.sequence);
+            }
+            EventKind::LinkResumed {
+                from_port, to_port, ..
+            } => {
+                self.link(*from_port, *to_port).state = LinkState::Connected;
+            }
+            EventKind::PartitionChanged { partitions } => {
+                self.partitions.clone_from(partitions);
+            }
+            EventKind::PacketDropped { .. }
+            | EventKind::MalformedFrame { .. }
+            | EventKind::MutationApplied { .. }
+            | EventKind::EclipseChanged { .. }
+            | EventKind::OneWayPartitionChanged { .. }
+            | EventKind::TxSuppressionChanged { .. }
+            | EventKind::BlackholeChanged { .. }
+            | EventKind::MessageInjected { .. }
+            | EventKind::ManifestChanged { .. }
+            | EventKind::SquelchSent { .. }
+            | EventKind::ProposalEquivocated { .. }
+            | EventKind::RulesReloaded { .. }
+            | EventKind::AmendmentVoting { .. }
+            | EventKind::LedgerClosed { .. }
+            | EventKind::Anomaly { .. }
+            | EventKind::CpuLimitChanged { .. }
+            | EventKind::PhaseChanged { .. }
+            | EventKind::CircuitBreakerChanged { .. }
+            | EventKind::ControllerFailover { .. } => {}
+        }
+    }
+
+    /// Replaces the traffic of all links with the messages handled within the rate window.
+    /// A link that was idle becomes connected again once messages are handled on it.
+    ///
+    /// # Parameters
+    /// * 'records' - the messages handled within the rate window, oldest first.
+    /// * 'window' - the length of the rate window.
+    pub fn update_traffic(&mut self, records: &[PacketRecord], window: Duration) {
+        let seconds = window.as_secs_f64().max(f64::EPSILON);
+        for link in self.links.values_mut() {
+            link.rates.clear();
+        }
+        for record in records {
+            let link = self.link(record.from_port, record.to_port);
+            *link.rates.entry(record.message_type).or_insert(0.0) += 1.0 / seconds;
+            link.delay_ms = record.action;
+            if link.state == LinkState::Idle {
+                link.state = LinkState::Connected;
+            }
+        }
+    }
+
+    /// Returns the view of a link, which is created if the link was not seen before.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer the messages come from.
+    /// * 'to_port' - the port of the peer the messages go to.
+    fn link(&mut self, from_port: u16, to_port: u16) -> &mut LinkView {
+        self.links
+            .entry((from_port, to_port))
+            .or_insert_with(LinkView::new)
+    }
+}
+
+/// Struct that represents the live terminal dashboard.
+pub struct Dashboard {
+    /// The configuration of the dashboard.
+    config: DashboardConfig,
+    /// The runtime state, containing the timeline of handled messages.
+    state: Arc<InterceptorState>,
+    /// The receiver of the link-state events, subscribed before the links are started.
+    events: broadcast::Receiver<Arc<Event>>,
+    /// Whether the interceptor is still running, cleared when the dashboard is quit.
+    running: Arc<AtomicBool>,
+}
+
+impl Dashboard {
+    /// Initializes a new Dashboard.
+    ///
+    /// # Parameters
+    /// * 'config' - the configuration of the dashboard.
+    /// * 'state' - the runtime state, containing the event bus and the timeline.
+    /// * 'running' - whether the interceptor is still running, cleared when the dashboard is quit.
+    pub fn new(
+        config: DashboardConfig,
+        state: Arc<InterceptorState>,
+        running: Arc<AtomicBool>,
+    ) -> Self {
+        let events = state.events.subscribe();
+        Self {
+            config,
+            state,
+            events,
+            running,
+        }
+    }
+
+    /// Draws the dashboard on the terminal until the interceptor stops or the dashboard is quit with 'q' or Ctrl+C.
+    /// This blocks the calling thread, so it should be run with `tokio::task::spawn_blocking`.
+    pub fn run(mut self) {
+        let mut terminal = match Self::enter() {
+            Ok(terminal) => terminal,
+            Err(e) => {
+                warn!("Could not start the dashboard: {}", e);
+                return;
+            }
+        };
+
+        let mut model = DashboardModel::default();
+        let refresh = Duration::from_millis(self.config.refresh_ms);
+        let window = Duration::from_secs(self.config.rate_window_secs.max(1));
+        while self.running.load(Ordering::SeqCst) {
+            loop {
+                match self.events.try_recv() {
+                    Ok(event) => model.apply(&event),
+                    Err(TryRecvError::Lagged(_)) => continue,
+                    Err(TryRecvError::Empty) | Err(TryRecvError::Closed) => break,
+                }
+            }
+            let now = Utc::now();
+            let from = now - TimeDelta::from_std(window).unwrap_or(TimeDelta::zero());
+            model.update_traffic(&self.state.timeline.between(from, now), window);
+            model.recent = self.state.timeline.latest(self.config.recent_decisions);
+            model.recent.reverse();
+
+            if let Err(e) = terminal.draw(|frame| Self::draw(frame, &model)) {
+                warn!("Could not draw the dashboard: {}", e);
+                break;
+            }
+            if self.quit_requested(refresh) {
+                self.running.store(false, Ordering::SeqCst);
+            }
+        }
+
+        if let Err(e) = Self::leave(&mut terminal) {
+            warn!("Could not restore the terminal: {}", e);
+        }
+    }
+
+    /// Switches the terminal to the alternate screen in raw mode.
+    fn enter() -> io::Result<Terminal<CrosstermBackend<io::Stdout>>> {
+        enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen)?;
+        Terminal::new(CrosstermBackend::new(io::stdout()))
+    }
+
+    /// Restores the terminal to the state before the dashboard was started.
+    ///
+    /// # Parameters
+    /// * 'terminal' - the terminal the dashboard was drawn on.
+    fn leave(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> io::Result<()> {
+        disable_raw_mode()?;
+        execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+        terminal.show_cursor()
+    }
+
+    /// Waits for a key press for at most the refresh interval, and returns whether it should quit the dashboard.
+    /// In raw mode Ctrl+C is read as a key press instead of a signal, so it is handled here as well.
+    ///
+    /// # Parameters
+    /// * 'timeout' - how long to wait for a key press.
+    fn quit_requested(&self, timeout: Duration) -> bool {
+        if !event::poll(timeout).unwrap_or(false) {
+            return false;
+        }
+        match event::read() {
+            Ok(event::Event::Key(key)) if key.kind == KeyEventKind::Press => {
+                key.code == KeyCode::Char('q')
+                    || (key.code == KeyCode::Char('c')
+                        && key.modifiers.contains(KeyModifiers::CONTROL))
+            }
+            _ => false,
+        }
+    }
+
+    /// Draws the links and the recent decisions.
+    ///
+    /// # Parameters
+    /// * 'frame' - the frame to draw on.
+    /// * 'model' - the data to draw.
+    fn draw(frame: &mut Frame, model: &DashboardModel) {
+        let [links_area, recent_area] =
+            Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .areas(frame.size());
+
+        let header = Row::new(vec!["Link", "State", "Protocol", "Delay", "Messages/s"])
+            .style(Style::default().add_modifier(Modifier::BOLD));
+        let rows = model.links.iter().map(|((from_port, to_port), link)| {
+            let rates = link
+                .busiest(TYPES_PER_LINK)
+                .iter()
+                .map(|(message_type, rate)| format!("{} {:.1}", message_type, rate))
+                .collect::<Vec<String>>()
+                .join(", ");
+            let color = match link.state {
+                LinkState::Connected => Color::Green,
+                LinkState::Idle => Color::Yellow,
+                LinkState::Halted(_) => Color::Magenta,
+                LinkState::Dropped(_) => Color::Red,
+            };
+            Row::new(vec![
+                format!("{} -> {}", from_port, to_port),
+                link.state.to_string(),
+                link.protocol.clone().unwrap_or("-".to_string()),
+                format!("{} ms", link.delay_ms),
+                rates,
+            ])
+            .style(Style::default().fg(color))
+        });
+        let links = Table::new(
+            rows,
+            [
+                Constraint::Length(15),
+                Constraint::Length(20),
+                Constraint::Length(9),
+                Constraint::Length(9),
+                Constraint::Min(20),
+            ],
+        )
+        .header(header)
+        .block(Block::bordered().title(format!(
+            " Links (partitions: {:?}) - press q to quit ",
+            model.partitions
+        )));
+        frame.render_widget(links, links_area);
+
+        let recent = List::new(
+            model
+                .recent
+                .iter()
+                .map(|record| ListItem::new(record.to_string())),
+        )
+        .block(Block::bordered().title(" Recent decisions "));
+        frame.render_widget(recent, recent_area);
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::dashboard::{DashboardModel, LinkState};
+    use crate::event_bus::{Event, EventKind};
+    use crate::message_type::MessageType;
+    use crate::packet_timeline::PacketRecord;
+    use std::time::Duration;
+
+    fn event(kind: EventKind) -> Event {
+        Event {
+            timestamp_ns: 0,
+            kind,
+        }
+    }
+
+    fn record(message_type: MessageType, action: u32) -> PacketRecord {
+        PacketRecord {
+            message_type,
+            size: 10,
+            sent_size: 10,
+            action,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn link_state_follows_events() {
+        let mut model = DashboardModel::default();
+        model.apply(&event(EventKind::LinkConnected {
+            from_port: 60000,
+            to_port: 60001,
+            protocol: Some("XRPL/2.2".to_string()),
+        }));
+        let link = &model.links[&(60000, 60001)];
+        assert_eq!(link.state, LinkState::Connected);
+        assert_eq!(link.protocol.as_deref(), Some("XRPL/2.2"));
+
+        model.apply(&event(EventKind::LinkIdle {
+            from_port: 60000,
+            to_port: 60001,
+            idle_ms: 1000,
+        }));
+        assert_eq!(model.links[&(60000, 60001)].state, LinkState::Idle);
+
+        // Traffic on an idle link means it is connected again
+        model.update_traffic(&[record(MessageType::Ping, 0)], Duration::from_secs(1));
+        assert_eq!(model.links[&(60000, 60001)].state, LinkState::Connected);
+
+        model.apply(&event(EventKind::LinkDropped {
+            from_port: 60000,
+            to_port: 60001,
+            reason: "closed".to_string(),
+        }));
+        assert_eq!(
+            model.links[&(60000, 60001)].state,
+            LinkState::Dropped("closed".to_string())
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn traffic_rates_per_type() {
+        let mut model = DashboardModel::default();
+        let records = vec![
+            record(MessageType::Validation, 0),
+            record(MessageType::Validation, 0),
+            record(MessageType::Validation, 0),
+            record(MessageType::Validation, 0),
+            record(MessageType::ProposeLedger, 250),
+        ];
+        model.update_traffic(&records, Duration::from_secs(2));
+
+        let link = &model.links[&(60000, 60001)];
+        assert_eq!(link.delay_ms, 250);
+        assert_eq!(link.busiest(1), vec![(MessageType::Validation, 2.0)]);
+        assert_eq!(link.rates[&MessageType::ProposeLedger], 0.5);
+
+        // Rates are recalculated from scratch every update
+        model.update_traffic(&[], Duration::from_secs(2));
+        assert!(model.links[&(60000, 60001)].rates.is_empty());
+    }
+}
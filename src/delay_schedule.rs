@@ -0,0 +1,324 @@
+//! This module is responsible for releasing the delayed messages of a link. The delayed messages of a link wait in a
+//! queue ordered by the moment they are due, which a single task of the link releases, such that a delayed message does
+//! not hold up the messages read after it, and thousands of messages can be delayed at once without a task each.
+//!
+//! By default, every message is released as soon as its own delay has passed, so a message with a short delay overtakes
+//! the delayed messages read before it. When the order of a link has to be preserved, a message is also held until the
+//! message read before it was released, which makes it wait at most until the end of the longest delay before it.
+
+use crate::clock::Clock;
+use futures_util::future::BoxFuture;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::time::Instant;
+use tracing::{info_span, Instrument};
+
+/// Struct that represents a delayed message waiting to be released.
+struct Release {
+    /// The moment the message is due.
+    due: Instant,
+    /// The position of the message among the delayed messages of the link, which breaks ties between equal moments.
+    position: u64,
+    /// Releases the message.
+    release: BoxFuture<'static, ()>,
+}
+
+impl PartialEq for Release {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Release {}
+
+impl PartialOrd for Release {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Release {
+    // Reversed, such that the BinaryHeap pops the release that is due first
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.due, other.position).cmp(&(self.due, self.position))
+    }
+}
+
+/// Struct that represents the releases of the delayed messages of one link.
+#[derive(Debug)]
+pub struct DelaySchedule {
+    /// Whether messages are released in the order they were read.
+    ordered: bool,
+    /// The time in which the delays are measured.
+    clock: Arc<dyn Clock>,
+    /// The sending side of the queue of the task that releases the delayed messages.
+    releases: UnboundedSender<Release>,
+    /// The amount of delayed messages that have not been released yet.
+    pending: Arc<AtomicUsize>,
+    /// The moment the last delayed message is due, which a message after it waits for if the order is preserved.
+    last_due: Option<Instant>,
+    /// The amount of delayed messages so far.
+    positions: u64,
+}
+
+impl DelaySchedule {
+    /// Initializes a new DelaySchedule without delayed messages, and starts the task that releases them.
+    /// The task runs until the DelaySchedule is dropped and the messages delayed by then are released.
+    ///
+    /// # Parameters
+    /// * 'ordered' - whether messages are released in the order they were read.
+    /// * 'clock' - the time in which the delays are measured.
+    pub fn new(ordered: bool, clock: Arc<dyn Clock>) -> Self {
+        let (releases, queue) = mpsc::unbounded_channel();
+        let pending = Arc::new(AtomicUsize::new(0));
+        tokio::spawn(Self::release_loop(queue, clock.clone(), pending.clone()).in_current_span());
+        Self {
+            ordered,
+            clock,
+            releases,
+            pending,
+            last_due: None,
+            positions: 0,
+        }
+    }
+
+    /// Returns the amount of delayed messages that have not been released yet.
+    pub fn pending(&self) -> usize {
+        self.pending.load(AtomicOrdering::Acquire)
+    }
+
+    /// Releases a message once its delay has passed, and if the order is preserved, once the message scheduled before it
+    /// was released. A message that does not have to wait is released before returning, otherwise it is queued for the
+    /// task of the link and this returns immediately.
+    ///
+    /// # Parameters
+    /// * 'delay' - how much longer the message has to be delayed, if at all.
+    /// * 'release' - releases the message.
+    pub async fn schedule<F>(&mut self, delay: Option<Duration>, release: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let waits_for_previous = self.ordered && self.pending() > 0;
+        if delay.is_none() && !waits_for_previous {
+            release.await;
+            return;
+        }
+        // The delay counts from now, also while waiting for the previous message
+        let mut due = self.clock.now() + delay.unwrap_or_default();
+        if waits_for_previous {
+            due = self.last_due.map_or(due, |last_due| due.max(last_due));
+        }
+        let span = info_span!(
+            "action",
+            delay_ms = delay.unwrap_or_default().as_millis() as u64
+        );
+        self.pending.fetch_add(1, AtomicOrdering::AcqRel);
+        let release = Release {
+            due,
+            position: self.positions,
+            release: Box::pin(release.instrument(span)),
+        };
+        self.positions += 1;
+        self.last_due = Some(due);
+        if let Err(mpsc::error::SendError(release)) = self.releases.send(release) {
+            // The task only stops once this schedule is dropped, so this is not expected
+            release.release.await;
+            self.pending.fetch_sub(1, AtomicOrdering::AcqRel);
+        }
+    }
+
+    /// Releases the queued messages of a link once they are due, in the order they are due, until the schedule is dropped
+    /// and every queued message was released.
+    ///
+    /// # Parameters
+    /// * 'queue' - the receiving side of the queue of delayed messages.
+    /// * 'clock' - the time in which the delays are measured.
+    /// * 'pending' - the amount of delayed messages that have not been released yet.
+    async fn release_loop(
+        mut queue: UnboundedReceiver<Release>,
+        clock: Arc<dyn Clock>,
+        pending: Arc<AtomicUsize>,
+    ) {
+        let mut waiting: BinaryHeap<Release> = BinaryHeap::new();
+        let mut open = true;
+        loop {
+            let until_due: BoxFuture<'static, ()> = match waiting.peek() {
+                Some(next) => clock.sleep(next.due.saturating_duration_since(clock.now())),
+                None if open => Box::pin(std::future::pending()),
+                None => return,
+            };
+            tokio::select! {
+                received = queue.recv(), if open => match received {
+                    Some(release) => waiting.push(release),
+                    None => open = false,
+                },
+                _ = until_due => {
+                    if let Some(release) = waiting.pop() {
+                        release.release.await;
+                        pending.fetch_sub(1, AtomicOrdering::AcqRel);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::clock::{Clock, TokioClock};
+    use crate::delay_schedule::DelaySchedule;
+    use proptest::prelude::*;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// Schedules messages with delays in ms, in the order of their index, and returns the indices in the order the
+    /// messages were released, under paused time.
+    fn release_order(delays: &[Option<u64>], ordered: bool) -> Vec<usize> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .start_paused(true)
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let released = Arc::new(Mutex::new(Vec::new()));
+            let mut schedule = DelaySchedule::new(ordered, Arc::new(TokioClock));
+            for (i, delay) in delays.iter().enumerate() {
+                let released = released.clone();
+                schedule
+                    .schedule(delay.map(Duration::from_millis), async move {
+                        released.lock().unwrap().push(i)
+                    })
+                    .await;
+            }
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            let released = released.lock().unwrap().clone();
+            released
+        })
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn short_delays_overtake_without_order() {
+        assert_eq!(
+            release_order(&[Some(100), None, Some(10)], false),
+            [1, 2, 0]
+        );
+        assert_eq!(release_order(&[Some(100), None, Some(10)], true), [0, 1, 2]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn thousands_of_delays_are_released_on_time() {
+        let clock = TokioClock;
+        let start = clock.now();
+        let released = Arc::new(Mutex::new(Vec::new()));
+        let mut schedule = DelaySchedule::new(false, Arc::new(clock));
+        // Scheduled in the reverse order they are due, without waiting for any of them
+        for delay in (1..=5000u64).rev() {
+            let released = released.clone();
+            schedule
+                .schedule(Some(Duration::from_millis(delay)), async move {
+                    released.lock().unwrap().push((delay, clock.elapsed(start)))
+                })
+                .await;
+        }
+        assert_eq!(clock.elapsed(start), Duration::ZERO);
+        assert_eq!(schedule.pending(), 5000);
+
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert_eq!(schedule.pending(), 0);
+        let released = released.lock().unwrap();
+        assert_eq!(released.len(), 5000);
+        for (i, (delay, elapsed)) in released.iter().enumerate() {
+            assert_eq!(*delay, i as u64 + 1);
+            assert_eq!(*elapsed, Duration::from_millis(*delay));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn ordered_messages_wait_for_the_previous_release() {
+        let clock = TokioClock;
+        let start = clock.now();
+        let released = Arc::new(Mutex::new(Vec::new()));
+        let mut schedule = DelaySchedule::new(true, Arc::new(clock));
+        for (i, delay) in [Some(300), None, Some(100), Some(500)]
+            .into_iter()
+            .enumerate()
+        {
+            let released = released.clone();
+            schedule
+                .schedule(delay.map(Duration::from_millis), async move {
+                    released.lock().unwrap().push((i, clock.elapsed(start)))
+                })
+                .await;
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(
+            *released.lock().unwrap(),
+            [
+                (0, Duration::from_millis(300)),
+                (1, Duration::from_millis(300)),
+                (2, Duration::from_millis(300)),
+                (3, Duration::from_millis(500)),
+            ]
+        );
+
+        // Once every delayed message was released, a message without delay is released immediately again
+        let released_now = released.clone();
+        schedule
+            .schedule(None, async move {
+                released_now.lock().unwrap().push((4, clock.elapsed(start)))
+            })
+            .await;
+        assert_eq!(schedule.pending(), 0);
+        assert_eq!(
+            released.lock().unwrap().last(),
+            Some(&(4, Duration::from_secs(1)))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn delayed_messages_are_released_after_the_schedule_is_dropped() {
+        let released = Arc::new(Mutex::new(0));
+        let mut schedule = DelaySchedule::new(false, Arc::new(TokioClock));
+        for _ in 0..10 {
+            let released = released.clone();
+            schedule
+                .schedule(Some(Duration::from_secs(1)), async move {
+                    *released.lock().unwrap() += 1
+                })
+                .await;
+        }
+        drop(schedule);
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(*released.lock().unwrap(), 10);
+    }
+
+    proptest! {
+        #[test]
+        // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+        fn ordered_links_release_in_read_order(
+            delays in prop::collection::vec(prop::option::of(0u64..1000), 0..50)
+        ) {
+            let order = release_order(&delays, true);
+            prop_assert_eq!(order, (0..delays.len()).collect::<Vec<_>>());
+        }
+
+        #[test]
+        // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+        fn every_message_is_released_once(
+            delays in prop::collection::vec(prop::option::of(0u64..1000), 0..50)
+        ) {
+            let mut order = release_order(&delays, false);
+            order.sort_unstable();
+            prop_assert_eq!(order, (0..delays.len()).collect::<Vec<_>>());
+        }
+    }
+}
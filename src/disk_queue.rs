@@ -0,0 +1,228 @@
+//! This module is responsible for a first-in-first-out queue of records that is stored on disk,
+//! such that bursts of messages can be buffered without keeping them in memory.
+//!
+//! Records are appended to segment files of a bounded size. Once all records of a segment have been read
+//! and newer records are written to a later segment, the segment file is deleted.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// The extension of the segment files.
+const SEGMENT_EXTENSION: &str = "seg";
+
+/// Struct that represents a queue of records stored in append-only segment files.
+#[derive(Debug)]
+pub struct DiskQueue {
+    /// The directory containing the segment files.
+    directory: PathBuf,
+    /// The size in bytes after which a new segment is started.
+    segment_size: u64,
+    /// The index of the segment that is written to.
+    write_segment: u64,
+    /// The segment file that is written to.
+    writer: File,
+    /// The amount of bytes written to the current write segment.
+    write_offset: u64,
+    /// The index of the segment that is read from.
+    read_segment: u64,
+    /// The segment file that is read from, if it has been opened.
+    reader: Option<File>,
+    /// The amount of records in the queue.
+    len: usize,
+}
+
+impl DiskQueue {
+    /// Initializes a new, empty DiskQueue. Segment files left behind in the directory by an earlier run are deleted.
+    ///
+    /// # Parameters
+    /// * 'directory' - the directory where the segment files are stored, it is created if it does not exist.
+    /// * 'segment_size' - the size in bytes after which a new segment is started.
+    pub fn new(directory: &Path, segment_size: u64) -> io::Result<Self> {
+        fs::create_dir_all(directory)?;
+        for entry in fs::read_dir(directory)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == SEGMENT_EXTENSION) {
+                fs::remove_file(path)?;
+            }
+        }
+
+        let writer = Self::create_segment(directory, 0)?;
+        Ok(Self {
+            directory: directory.to_path_buf(),
+            segment_size,
+            write_segment: 0,
+            writer,
+            write_offset: 0,
+            read_segment: 0,
+            reader: None,
+            len: 0,
+        })
+    }
+
+    /// Returns the path of a segment file.
+    ///
+    /// # Parameters
+    /// * 'directory' - the directory where the segment files are stored.
+    /// * 'segment' - the index of the segment.
+    fn segment_path(directory: &Path, segment: u64) -> PathBuf {
+        directory.join(format!("{:010}.{}", segment, SEGMENT_EXTENSION))
+    }
+
+    /// Creates a new, empty segment file to write to.
+    ///
+    /// # Parameters
+    /// * 'directory' - the directory where the segment files are stored.
+    /// * 'segment' - the index of the segment.
+    fn create_segment(directory: &Path, segment: u64) -> io::Result<File> {
+        OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(Self::segment_path(directory, segment))
+    }
+
+    /// Appends a record to the back of the queue.
+    ///
+    /// # Parameters
+    /// * 'record' - the record to be appended.
+    pub fn push(&mut self, record: &[u8]) -> io::Result<()> {
+        if self.write_offset >= self.segment_size {
+            self.write_segment += 1;
+            self.writer = Self::create_segment(&self.directory, self.write_segment)?;
+            self.write_offset = 0;
+        }
+
+        let mut frame = Vec::with_capacity(4 + record.len());
+        frame.extend_from_slice(&(record.len() as u32).to_le_bytes());
+        frame.extend_from_slice(record);
+        self.writer.write_all(&frame)?;
+        self.write_offset += frame.len() as u64;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes and returns the oldest record in the queue, or None if the queue is empty.
+    pub fn pop(&mut self) -> io::Result<Option<Vec<u8>>> {
+        if self.len == 0 {
+            return Ok(None);
+        }
+
+        loop {
+            let reader = match &mut self.reader {
+                Some(reader) => reader,
+                None => self.reader.insert(File::open(Self::segment_path(
+                    &self.directory,
+                    self.read_segment,
+                ))?),
+            };
+
+            let mut length = [0u8; 4];
+            match reader.read_exact(&mut length) {
+                Ok(()) => {
+                    let mut record = vec![0u8; u32::from_le_bytes(length) as usize];
+                    reader.read_exact(&mut record)?;
+                    self.len -= 1;
+                    return Ok(Some(record));
+                }
+                // The segment has been read completely, continue with the next one
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                    if self.read_segment >= self.write_segment {
+                        return Err(e);
+                    }
+                    self.reader = None;
+                    fs::remove_file(Self::segment_path(&self.directory, self.read_segment))?;
+                    self.read_segment += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Returns the amount of records in the queue.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the queue contains no records.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::disk_queue::DiskQueue;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("disk_queue_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn segment_count(directory: &PathBuf) -> usize {
+        fs::read_dir(directory).unwrap().count()
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn records_are_fifo() {
+        let directory = directory("fifo");
+        let mut queue = DiskQueue::new(&directory, 1024).unwrap();
+        assert_eq!(queue.pop().unwrap(), None);
+
+        queue.push(&[1, 2, 3]).unwrap();
+        queue.push(&[]).unwrap();
+        queue.push(&[4]).unwrap();
+        assert_eq!(queue.len(), 3);
+
+        assert_eq!(queue.pop().unwrap(), Some(vec![1, 2, 3]));
+        assert_eq!(queue.pop().unwrap(), Some(vec![]));
+        queue.push(&[5, 6]).unwrap();
+        assert_eq!(queue.pop().unwrap(), Some(vec![4]));
+        assert_eq!(queue.pop().unwrap(), Some(vec![5, 6]));
+        assert!(queue.is_empty());
+        assert_eq!(queue.pop().unwrap(), None);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn read_segments_are_deleted() {
+        let directory = directory("segments");
+        let mut queue = DiskQueue::new(&directory, 16).unwrap();
+        // Every record takes 14 bytes, so every segment of 16 bytes contains two records
+        for i in 0..10u8 {
+            queue.push(&[i; 10]).unwrap();
+        }
+        assert_eq!(segment_count(&directory), 5);
+
+        for i in 0..10u8 {
+            assert_eq!(queue.pop().unwrap(), Some(vec![i; 10]));
+        }
+        assert_eq!(segment_count(&directory), 1);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn old_segments_are_removed() {
+        let directory = directory("old");
+        {
+            let mut queue = DiskQueue::new(&directory, 16).unwrap();
+            for i in 0..5u8 {
+                queue.push(&[i; 10]).unwrap();
+            }
+        }
+        let mut queue = DiskQueue::new(&directory, 16).unwrap();
+        assert_eq!(segment_count(&directory), 1);
+        assert_eq!(queue.pop().unwrap(), None);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,22 +1,43 @@
 //! This module is responsible for setting up and tearing down the Docker containers who run the validator nodes.
 
-use log::{debug, info};
+use std::collections::HashMap;
 use std::env::current_dir;
+use std::error::Error;
 use std::fs;
 use std::io::Read;
 use std::io::Write;
+use std::net::Ipv4Addr;
+use std::path::Path;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
 
-use bollard::container::{CreateContainerOptions, RemoveContainerOptions};
+use bollard::container::{
+    CreateContainerOptions, ListContainersOptions, LogsOptions, NetworkingConfig,
+    RemoveContainerOptions, UpdateContainerOptions, WaitContainerOptions,
+};
 use bollard::exec::{CreateExecOptions, StartExecResults};
 use bollard::image::CreateImageOptions;
-use bollard::models::{HostConfig, Mount, MountTypeEnum, PortBinding, PortMap};
+use bollard::models::{
+    EndpointSettings, HostConfig, Ipam, IpamConfig, Mount, MountTypeEnum, PortBinding, PortMap,
+};
+use bollard::network::{CreateNetworkOptions, InspectNetworkOptions, ListNetworksOptions};
+use bollard::volume::{CreateVolumeOptions, ListVolumesOptions};
 use bollard::Docker;
 
+use crate::amendments;
+use crate::config::{
+    AddressingConfig, AmendmentConfig, ClockSkew, ClockSkewConfig, NodeRole, PersistenceConfig,
+    PortConfig, ResourceConfig, ResourceLimits, RoleConfig, ShadowConfig, ValidatorListConfig,
+};
 use crate::is_valid_unl_connection;
+use crate::keygen::{KeyType, NodeKeys, Seed};
 use crate::packet_client::proto;
 use crate::packet_client::PacketClient;
+use crate::port_allocation;
+use crate::run_id::{RunId, RUN_ID_LABEL};
+use crate::run_seed::{self, RunSeed};
+use crate::validator_list::{ValidatorListSite, PUBLISHED_FILE};
 use futures_util::stream::StreamExt;
 use futures_util::TryStreamExt;
 use serde::Deserialize;
@@ -25,20 +46,32 @@ use tokio::sync::Mutex;
 
 const IMAGE: &str = "xrpllabsofficial/xrpld:2.3.0";
 
-/// Struct that represents a response of a 'ValidationKeyCreate' request.
-#[derive(Debug, Deserialize)]
-struct ValidationKeyCreateResponse {
-    /// The result of the request.
-    result: ValidatorKeyData,
-}
+/// The maximum amount of peers of a hub node, far above the default of rippled, such that it accepts a link to every node.
+const HUB_PEERS_MAX: u32 = 1000;
+
+/// The directory the databases of a node are kept in, see `[database_path]` and `[node_db]` in rippled_base.cfg.
+const DATABASE_DIRECTORY: &str = "/var/lib/rippled/db";
+
+/// The name of the container that archives and restores the volumes of the nodes.
+const VOLUME_ARCHIVER: &str = "volume_archiver";
+
+/// The path libfaketime is mounted at in the containers of the nodes when clocks are skewed.
+const FAKETIME_LIBRARY: &str = "/usr/local/lib/faketime/libfaketime.so.1";
+
+/// The path of the file libfaketime reads the clock of a node from, which is mounted from the host.
+const FAKETIME_FILE: &str = "/etc/faketime";
+
+/// The name of the container that publishes the validator list.
+const VALIDATOR_LIST_CONTAINER: &str = "validator_list";
+
+/// The name of the Docker network the containers run in, if they do not run in the default bridge network.
+const NETWORK: &str = "rocket_network";
 
 /// Struct that represents all the data used for validation by nodes.
 #[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct ValidatorKeyData {
     /// The status of the request that was used to make this data.
     pub status: String,
-    /// The validation key of a node.
-    pub validation_key: String,
     /// The validation private key of a node.
     pub validation_private_key: String,
     /// The validation public key of a node.
@@ -64,6 +97,17 @@ pub struct DockerContainer {
     pub port_rpc: u32,
     /// The data of the keys of this node.
     pub key_data: ValidatorKeyData,
+    /// The role of this node in the network.
+    pub role: NodeRole,
+    /// The memory and CPU limits the container is started with.
+    pub limits: ResourceLimits,
+    /// How the clock of the node deviates from the real time, if it is skewed.
+    pub clock_skew: Option<ClockSkew>,
+    /// The DNS names of the node in the Docker network besides the name of its container, if it runs in a network of
+    /// its own.
+    pub aliases: Vec<String>,
+    /// The addresses Docker assigned to the container once it was started.
+    pub ip_addresses: Vec<String>,
 }
 
 /// Checks whether a certain `DockerContainer` is available by calling `server_info` and parsing the `success` value.
@@ -108,6 +152,170 @@ async fn check_validator_available(container: DockerContainer, docker: Docker) -
     false
 }
 
+/// Struct that represents how long the startup of a container took.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StartupTiming {
+    /// The name of the container.
+    pub name: String,
+    /// How long creating and starting the container took.
+    pub started_after: Duration,
+    /// How long it took until the node in the container answered `server_info`, since the network started waiting for
+    /// it, if it was waited for.
+    pub available_after: Option<Duration>,
+}
+
+/// Struct that represents where the nodes get their UNL from when a validator list is published.
+#[derive(Debug, Clone, PartialEq)]
+struct ValidatorListTrust {
+    /// The URL of the published list, as the nodes reach it.
+    site: String,
+    /// The master public key of the publisher in hex.
+    publisher_key: String,
+}
+
+/// Changes the CPU limit of a running container.
+///
+/// # Parameters
+/// * 'docker' - the Docker API the limit is changed through.
+/// * 'container_id' - the ID of the container.
+/// * 'cpus' - the amount of CPUs the container can use, unlimited if None.
+pub async fn set_cpu_limit(
+    docker: &Docker,
+    container_id: &str,
+    cpus: Option<f64>,
+) -> Result<(), bollard::errors::Error> {
+    docker
+        .update_container(
+            container_id,
+            UpdateContainerOptions::<String> {
+                // Docker removes the limit if it is 0
+                nano_cpus: Some(cpus.map(nano_cpus).unwrap_or(0)),
+                ..Default::default()
+            },
+        )
+        .await
+}
+
+/// Converts an amount of CPUs to the unit of the CPU limits of Docker, billionths of a CPU.
+///
+/// # Parameters
+/// * 'cpus' - the amount of CPUs.
+fn nano_cpus(cpus: f64) -> i64 {
+    (cpus * 1e9).round() as i64
+}
+
+/// Converts a clock skew to the `FAKETIME` specification of libfaketime: an offset in seconds relative to the real
+/// time, followed by the rate of the clock if it drifts.
+///
+/// # Parameters
+/// * 'skew' - the clock skew.
+fn faketime(skew: ClockSkew) -> String {
+    faketime_spec(skew.offset_secs * 1000, skew.drift_rate)
+}
+
+/// Returns the `FAKETIME` specification of libfaketime of a clock: an offset in seconds relative to the real time, with
+/// ms precision if needed, followed by the rate of the clock if it drifts.
+///
+/// # Parameters
+/// * 'offset_ms' - how many ms the clock is ahead of the real time, behind if negative.
+/// * 'drift_rate' - how fast the clock runs compared to the real time.
+pub fn faketime_spec(offset_ms: i64, drift_rate: f64) -> String {
+    let offset = if offset_ms % 1000 == 0 {
+        format!("{:+}", offset_ms / 1000)
+    } else {
+        format!("{:+.3}", offset_ms as f64 / 1000.0)
+    };
+    if drift_rate == 1.0 {
+        offset
+    } else {
+        format!("{} x{}", offset, drift_rate)
+    }
+}
+
+/// Sets the clock of a running node by writing its file, which libfaketime reads again within a second.
+///
+/// # Parameters
+/// * 'clock_file' - the path of the file on the host, see `DockerNetwork::clock_file`.
+/// * 'spec' - the `FAKETIME` specification of the clock, see `faketime_spec`.
+pub fn write_clock_file(clock_file: &str, spec: &str) -> std::io::Result<()> {
+    if let Some(parent) = Path::new(clock_file).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(clock_file, spec)
+}
+
+/// Removes the Docker resources left behind by runs that did not shut down, e.g. because the interceptor was killed:
+/// the containers and networks labeled with the identifier of a run, and optionally their volumes. Returns the names of
+/// the removed resources.
+///
+/// # Parameters
+/// * 'run_id' - the identifier of the run whose resources are removed, those of every run if None.
+/// * 'volumes' - whether volumes are removed as well, which hold the databases of the nodes if they persist across runs.
+pub async fn remove_orphans(
+    run_id: Option<&RunId>,
+    volumes: bool,
+) -> Result<Vec<String>, bollard::errors::Error> {
+    let docker = Docker::connect_with_local_defaults()?;
+    let label = match run_id {
+        Some(run_id) => format!("{}={}", RUN_ID_LABEL, run_id),
+        None => RUN_ID_LABEL.to_string(),
+    };
+    let filters: HashMap<&str, Vec<&str>> = [("label", vec![label.as_str()])].into_iter().collect();
+    let mut removed = Vec::new();
+    let containers = docker
+        .list_containers(Some(ListContainersOptions {
+            all: true,
+            filters: filters.clone(),
+            ..Default::default()
+        }))
+        .await?;
+    for container in containers {
+        let Some(id) = container.id else {
+            continue;
+        };
+        docker
+            .remove_container(
+                &id,
+                Some(RemoveContainerOptions {
+                    force: true,
+                    ..Default::default()
+                }),
+            )
+            .await?;
+        removed.push(format!(
+            "container {}",
+            // Docker container names always start with a slash
+            container
+                .names
+                .and_then(|names| names
+                    .first()
+                    .map(|name| name.trim_start_matches('/').to_string()))
+                .unwrap_or(id)
+        ));
+    }
+    let networks = docker
+        .list_networks(Some(ListNetworksOptions {
+            filters: filters.clone(),
+        }))
+        .await?;
+    for name in networks.into_iter().filter_map(|network| network.name) {
+        docker.remove_network(&name).await?;
+        removed.push(format!("network {}", name));
+    }
+    if volumes {
+        let volumes = docker
+            .list_volumes(Some(ListVolumesOptions { filters }))
+            .await?
+            .volumes
+            .unwrap_or_default();
+        for volume in volumes {
+            docker.remove_volume(&volume.name, None).await?;
+            removed.push(format!("volume {}", volume.name));
+        }
+    }
+    Ok(removed)
+}
+
 /// Struct that represents the whole network of Docker containers.
 #[derive(Debug)]
 pub struct DockerNetwork {
@@ -115,6 +323,47 @@ pub struct DockerNetwork {
     pub config: proto::Config,
     /// A Vec of all the individual Docker containers who run a rippled instance.
     pub containers: Vec<DockerContainer>,
+    /// The container of the shadow node, which is not part of any UNL, if it was started.
+    pub shadow: Option<DockerContainer>,
+    /// The host the ports of the containers are published on: an IPv4 or IPv6 address, or a hostname.
+    pub node_host: String,
+    /// The roles of the nodes, by their IDs.
+    roles: RoleConfig,
+    /// The amendments the nodes vote for and against.
+    amendments: AmendmentConfig,
+    /// The memory and CPU limits of the containers of the nodes.
+    resources: ResourceConfig,
+    /// The skewed clocks of the nodes, if any.
+    clock_skew: Option<ClockSkewConfig>,
+    /// The volumes the databases of the nodes are kept in, if they persist across runs.
+    persistence: Option<PersistenceConfig>,
+    /// Whether the volumes of a previous run are removed when the network is initialized.
+    fresh: bool,
+    /// The name of the network if several networks run at the same time, which prefixes the names of its containers.
+    namespace: Option<String>,
+    /// The labels of the containers and volumes created by the run, which include the run ID.
+    labels: HashMap<String, String>,
+    /// The configuration of the Docker network the containers run in.
+    addressing: AddressingConfig,
+    /// The IPv4 gateway of the Docker network the containers run in, through which the host reaches the nodes, if it
+    /// is known.
+    gateway: Option<String>,
+    /// The seed the keys are derived from, if the run is seeded.
+    seed: Option<RunSeed>,
+    /// The maximum amount of node containers that are created and started at the same time.
+    parallelism: usize,
+    /// How the host ports of the containers are checked or allocated.
+    ports: PortConfig,
+    /// Whether a shadow node is started after the nodes, which takes the ports following theirs.
+    with_shadow: bool,
+    /// How long the startup of every node container took, in the order the containers were started.
+    pub startup_timings: Vec<StartupTiming>,
+    /// The configuration of the validator list publisher, if the nodes should get their UNL from a published list.
+    validator_list: Option<ValidatorListConfig>,
+    /// Where the nodes get their UNL from, if a validator list is published.
+    validator_list_trust: Option<ValidatorListTrust>,
+    /// The site the validator list is published to, if it was started and not taken yet.
+    pub validator_list_site: Option<ValidatorListSite>,
     /// A Docker object to access the Docker API.
     docker: Docker,
 }
@@ -128,25 +377,377 @@ impl DockerNetwork {
         DockerNetwork {
             config,
             containers: Vec::new(),
+            shadow: None,
+            node_host: AddressingConfig::default().node_host,
+            roles: RoleConfig::default(),
+            amendments: AmendmentConfig::default(),
+            resources: ResourceConfig::default(),
+            clock_skew: None,
+            persistence: None,
+            fresh: false,
+            namespace: None,
+            labels: HashMap::new(),
+            addressing: AddressingConfig::default(),
+            gateway: None,
+            seed: None,
+            parallelism: 1,
+            ports: PortConfig::default(),
+            with_shadow: false,
+            startup_timings: Vec::new(),
+            validator_list: None,
+            validator_list_trust: None,
+            validator_list_site: None,
             docker: Docker::connect_with_local_defaults().unwrap(),
         }
     }
 
+    /// Sets the roles of the nodes, which determine their configuration when the network is initialized. Nodes without
+    /// a role are validators.
+    ///
+    /// # Parameters
+    /// * 'roles' - the roles of the nodes, by their IDs.
+    pub fn set_roles(&mut self, roles: RoleConfig) {
+        self.roles = roles;
+    }
+
+    /// Derives the keys of all nodes that are generated from now on from the seed of the run, such that the network is
+    /// the same in every run with the same seed.
+    ///
+    /// # Parameters
+    /// * 'seed' - the seed of the run.
+    pub fn set_seed(&mut self, seed: RunSeed) {
+        self.seed = Some(seed);
+    }
+
+    /// Sets the memory and CPU limits of the containers of the nodes, which they are started with. The shadow node gets
+    /// the limits of the node it observes.
+    ///
+    /// # Parameters
+    /// * 'resources' - the limits of the containers.
+    pub fn set_resources(&mut self, resources: ResourceConfig) {
+        self.resources = resources;
+    }
+
+    /// Skews the clocks of nodes, which they are started with. The shadow node gets the clock of the node it observes.
+    ///
+    /// # Parameters
+    /// * 'clock_skew' - the skewed clocks of the nodes.
+    pub fn set_clock_skew(&mut self, clock_skew: ClockSkewConfig) {
+        self.clock_skew = Some(clock_skew);
+    }
+
+    /// Keeps the databases of the nodes in volumes that persist across runs. Nodes whose volume already exists load the
+    /// last ledger from it instead of starting from the genesis ledger.
+    ///
+    /// # Parameters
+    /// * 'persistence' - the configuration of the volumes.
+    /// * 'fresh' - whether the volumes of a previous run are removed, such that all nodes start from the genesis ledger.
+    pub fn set_persistence(&mut self, persistence: PersistenceConfig, fresh: bool) {
+        self.persistence = Some(persistence);
+        self.fresh = fresh;
+    }
+
+    /// Separates the network from other networks that run at the same time: the names of its containers are prefixed
+    /// with the namespace, and its configurations are written to /network/\<namespace\>.
+    ///
+    /// # Parameters
+    /// * 'namespace' - the name of the network.
+    pub fn set_namespace(&mut self, namespace: String) {
+        self.namespace = Some(namespace);
+    }
+
+    /// Sets the labels of the containers and volumes the network creates, such that the resources of a run can be
+    /// found with `docker ps --filter label=...`.
+    ///
+    /// # Parameters
+    /// * 'labels' - the labels, including the run ID.
+    pub fn set_labels(&mut self, labels: HashMap<String, String>) {
+        self.labels = labels;
+    }
+
+    /// Sets the host the ports of the containers are reached on and the Docker network the containers run in: a
+    /// network of their own if it is dual-stack, has a chosen subnet or gives the nodes DNS names, otherwise the default
+    /// bridge network.
+    ///
+    /// # Parameters
+    /// * 'addressing' - the configuration of the addresses of the nodes.
+    pub fn set_addressing(&mut self, addressing: &AddressingConfig) {
+        self.node_host = addressing.node_host.clone();
+        self.addressing = addressing.clone();
+    }
+
+    /// Returns the name of the Docker network the containers run in, or None if they run in the default bridge
+    /// network.
+    fn network_name(&self) -> Option<String> {
+        self.addressing
+            .user_network()
+            .then(|| self.container_name(NETWORK))
+    }
+
+    /// Creates the Docker network the containers run in, if they do not run in the default bridge network. A network
+    /// left by a previous run is removed first, such that changes to its subnets take effect.
+    ///
+    /// # Panics
+    /// * If the network could not be created, e.g. because IPv6 is not enabled in the Docker daemon or the subnet
+    ///   overlaps with another network.
+    async fn prepare_network(&self) {
+        let Some(name) = self.network_name() else {
+            return;
+        };
+        if self
+            .docker
+            .inspect_network(&name, None::<InspectNetworkOptions<String>>)
+            .await
+            .is_ok()
+        {
+            self.docker
+                .remove_network(&name)
+                .await
+                .unwrap_or_else(|e| panic!("Failed to remove the network {}: {}", name, e));
+        }
+        let mut subnets = Vec::new();
+        if self.addressing.subnet.is_some() {
+            subnets.push(IpamConfig {
+                subnet: self.addressing.subnet.clone(),
+                gateway: self.addressing.gateway.clone(),
+                ..Default::default()
+            });
+        }
+        if self.addressing.dual_stack {
+            subnets.push(IpamConfig {
+                subnet: Some(self.addressing.ipv6_subnet.clone()),
+                ..Default::default()
+            });
+        }
+        self.docker
+            .create_network(CreateNetworkOptions {
+                name: name.as_str(),
+                driver: "bridge",
+                enable_ipv6: self.addressing.dual_stack,
+                ipam: Ipam {
+                    config: Some(subnets),
+                    ..Default::default()
+                },
+                labels: self.labels(),
+                ..Default::default()
+            })
+            .await
+            .unwrap_or_else(|e| panic!("Failed to create the network {}: {}", name, e));
+        info!("Created the Docker network {}", name);
+    }
+
+    /// Returns the IPv4 gateway of the Docker network the containers run in, or None if it can not be inspected.
+    async fn network_gateway(&self) -> Option<String> {
+        let name = self.network_name().unwrap_or_else(|| "bridge".to_string());
+        let network = self
+            .docker
+            .inspect_network(&name, None::<InspectNetworkOptions<String>>)
+            .await
+            .ok()?;
+        network
+            .ipam?
+            .config?
+            .into_iter()
+            .filter_map(|config| config.gateway)
+            .find(|gateway| gateway.parse::<Ipv4Addr>().is_ok())
+    }
+
+    /// Returns the addresses the admin RPC of the nodes is open to: the loopback address of the container, and the
+    /// gateway of the Docker network through which the interceptor reaches the published ports.
+    fn rpc_admin(&self) -> String {
+        match &self.gateway {
+            Some(gateway) => format!("127.0.0.1, {}", gateway),
+            None => "127.0.0.1".to_string(),
+        }
+    }
+
+    /// Returns the names other containers in the Docker network can reach a container by: the name of the container and
+    /// its aliases. Containers in the default bridge network have no DNS names.
+    ///
+    /// # Parameters
+    /// * 'container' - the container.
+    fn dns_names(&self, container: &DockerContainer) -> Vec<String> {
+        if self.network_name().is_none() {
+            return Vec::new();
+        }
+        std::iter::once(container.name.clone())
+            .chain(container.aliases.iter().cloned())
+            .collect()
+    }
+
+    /// Returns the addresses of a container in the Docker network it runs in: its IPv4 address and, if the network is
+    /// dual-stack, its IPv6 address.
+    ///
+    /// # Parameters
+    /// * 'id' - the ID of the container.
+    async fn ip_addresses(&self, id: &str) -> Vec<String> {
+        let Some(settings) = self
+            .docker
+            .inspect_container(id, None)
+            .await
+            .ok()
+            .and_then(|container| container.network_settings)
+        else {
+            return Vec::new();
+        };
+        let (ipv4, ipv6) = match self.network_name() {
+            Some(network_name) => settings
+                .networks
+                .and_then(|mut networks| networks.remove(&network_name))
+                .map(|endpoint| (endpoint.ip_address, endpoint.global_ipv6_address))
+                .unwrap_or_default(),
+            None => (settings.ip_address, settings.global_ipv6_address),
+        };
+        [ipv4, ipv6]
+            .into_iter()
+            .flatten()
+            .filter(|address| !address.is_empty())
+            .collect()
+    }
+
+    /// Returns the labels of the containers and volumes the network creates.
+    fn labels(&self) -> HashMap<&str, &str> {
+        self.labels
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect()
+    }
+
+    /// Returns the name of a container of this network, which is prefixed with the namespace of the network if it has
+    /// one.
+    ///
+    /// # Parameters
+    /// * 'name' - the name of the container within the network.
+    fn container_name(&self, name: &str) -> String {
+        match &self.namespace {
+            Some(namespace) => format!("{}_{}", namespace, name),
+            None => name.to_string(),
+        }
+    }
+
+    /// Returns the path of a file or directory of this network, relative to the working directory, in /network or in
+    /// /network/\<namespace\> if the network has a namespace.
+    ///
+    /// # Parameters
+    /// * 'name' - the path within the directory of the network.
+    pub fn directory(&self, name: &str) -> String {
+        match &self.namespace {
+            Some(namespace) => format!("network/{}/{}", namespace, name),
+            None => format!("network/{}", name),
+        }
+    }
+
+    /// Returns the absolute path of the file on the host the clock of a node is read from, or None if the clocks of the
+    /// nodes are not skewed, in which case the nodes use the real time.
+    ///
+    /// # Parameters
+    /// * 'container_name' - the name of the container of the node.
+    pub fn clock_file(&self, container_name: &str) -> Option<String> {
+        self.clock_skew.as_ref()?;
+        Some(format!(
+            "{}/{}",
+            current_dir().unwrap().to_str().unwrap(),
+            self.directory(&format!("clocks/{}", container_name))
+        ))
+    }
+
+    /// Returns a handle to the Docker API, e.g. to change the containers while the network runs.
+    pub fn docker(&self) -> Docker {
+        self.docker.clone()
+    }
+
+    /// Sets the maximum amount of node containers that are created and started at the same time.
+    ///
+    /// # Parameters
+    /// * 'parallelism' - the maximum amount of containers started at the same time, at least 1.
+    pub fn set_parallelism(&mut self, parallelism: usize) {
+        self.parallelism = parallelism.max(1);
+    }
+
+    /// Sets how the host ports of the containers are checked or allocated when the network is initialized.
+    ///
+    /// # Parameters
+    /// * 'ports' - the configuration of the ports.
+    /// * 'with_shadow' - whether a shadow node will be started, which needs a port of every range as well.
+    pub fn set_ports(&mut self, ports: PortConfig, with_shadow: bool) {
+        self.ports = ports;
+        self.with_shadow = with_shadow;
+    }
+
+    /// Makes sure the containers can be published on the ports of the network configuration, before any container is
+    /// started. In the `auto` mode, the base ports of the configuration are changed to free ranges if they can not be
+    /// used, which the nodes are then reported to the controller with.
+    ///
+    /// # Panics
+    /// * If the ports can not be used and no free ranges could be allocated.
+    fn reserve_ports(&mut self) {
+        let count = self.config.number_of_nodes + self.with_shadow as u32;
+        match port_allocation::reserve(&mut self.config, count, &self.ports) {
+            Ok(true) => info!(
+                "Allocated {} peer ports from {}, WebSocket ports from {}, admin WebSocket ports from {} and RPC ports from {}",
+                count,
+                self.config.base_port_peer,
+                self.config.base_port_ws,
+                self.config.base_port_ws_admin,
+                self.config.base_port_rpc
+            ),
+            Ok(false) => {}
+            Err(e) => panic!("The ports of the nodes can not be used: {}", e),
+        }
+    }
+
+    /// Sets the amendments the nodes vote for and against, which are written to their configuration when the network
+    /// is initialized. Nodes without votes keep the default votes of rippled.
+    ///
+    /// # Parameters
+    /// * 'amendments' - the votes of the nodes.
+    pub fn set_amendments(&mut self, amendments: AmendmentConfig) {
+        self.amendments = amendments;
+    }
+
+    /// Makes the nodes get their UNL from a validator list published inside the network, instead of a fixed list of
+    /// validators, when the network is initialized.
+    ///
+    /// # Parameters
+    /// * 'validator_list' - the configuration of the validator list publisher.
+    pub fn set_validator_list(&mut self, validator_list: ValidatorListConfig) {
+        self.validator_list = Some(validator_list);
+    }
+
     /// Initializes the docker network by generating keys for each configured node, and starting
     /// them using `bollard`. The containers that were started successfully are appended to
     /// the `containers` field in the struct.
     ///
+    /// The ports of the containers are checked, or allocated, before anything is started, see `reserve_ports`. The
+    /// validator list publisher is started first, as the configurations of the nodes point at it. The node
+    /// containers are then started concurrently, at most `parallelism` at a time, and how long every container took is
+    /// stored in `startup_timings`.
+    ///
     /// # Parameters
     /// * 'client' - a PacketClient to send the ValidatorNodeInfo to the controller.
     ///
     /// # Panics
+    /// * If the ports of the containers can not be used.
     /// * If an error occurred while sending the ValidatorNodeInfo to the controller.
     pub async fn initialize_network(&mut self, client: Arc<Mutex<PacketClient>>) {
         // Stop all running validator nodes before starting new network
         self.stop_network().await;
+        if self.fresh {
+            self.remove_volumes().await;
+        }
+        self.reserve_ports();
         self.download_image().await;
+        self.prepare_network().await;
+        self.gateway = self.network_gateway().await;
+        if self.gateway.is_none() {
+            warn!("Could not find the gateway of the Docker network, the admin RPC of the nodes is only local");
+        }
 
-        let validator_keys = self.generate_keys(self.config.number_of_nodes as u16).await;
+        let validator_keys = self.generate_keys(self.config.number_of_nodes as u16, "validator");
+        if let Some(validator_list) = self.validator_list.clone() {
+            self.start_validator_list(&validator_list, &validator_keys)
+                .await;
+        }
         let names_with_keys = self.generate_validator_configs(&validator_keys);
 
         let base_port_peer = self.config.base_port_peer;
@@ -156,8 +757,10 @@ impl DockerNetwork {
 
         let mut validator_node_info_list = vec![];
 
-        for (i, (name, keys)) in names_with_keys.iter().enumerate() {
-            let mut validator_container = DockerContainer {
+        let containers: Vec<DockerContainer> = names_with_keys
+            .iter()
+            .enumerate()
+            .map(|(i, (name, keys))| DockerContainer {
                 id: None,
                 name: name.clone(),
                 port_peer: base_port_peer + i as u32,
@@ -165,19 +768,48 @@ impl DockerNetwork {
                 port_ws_admin: base_port_ws_admin + i as u32,
                 port_rpc: base_port_rpc + i as u32,
                 key_data: keys.clone(),
-            };
-            self.start_validator(&mut validator_container).await;
-            info!("Started docker container {}", name.clone());
+                role: self.roles.role(i as u32),
+                limits: self.resources.limits(i as u32),
+                clock_skew: self.clock_skew_of(i as u32),
+                aliases: self.addressing.aliases(i as u32),
+                ip_addresses: Vec::new(),
+            })
+            .collect();
+        let network = &*self;
+        let started: Vec<(DockerContainer, Duration)> = futures_util::stream::iter(containers)
+            .map(|mut container| async move {
+                let start = Instant::now();
+                network.start_validator(&mut container, IMAGE).await;
+                (container, start.elapsed())
+            })
+            .buffered(self.parallelism)
+            .collect()
+            .await;
+
+        for (validator_container, started_after) in started {
+            info!(
+                "Started docker container {} as a {} node in {} ms",
+                validator_container.name,
+                validator_container.role.as_str(),
+                started_after.as_millis()
+            );
+            self.startup_timings.push(StartupTiming {
+                name: validator_container.name.clone(),
+                started_after,
+                available_after: None,
+            });
             validator_node_info_list.push(proto::ValidatorNodeInfo {
                 peer_port: validator_container.port_peer,
                 ws_public_port: validator_container.port_ws,
                 ws_admin_port: validator_container.port_ws_admin,
                 rpc_port: validator_container.port_rpc,
                 status: validator_container.key_data.status.clone(),
-                validation_key: validator_container.key_data.validation_key.clone(),
                 validation_private_key: validator_container.key_data.validation_private_key.clone(),
                 validation_public_key: validator_container.key_data.validation_public_key.clone(),
                 validation_seed: validator_container.key_data.validation_seed.clone(),
+                role: validator_container.role.as_str().to_string(),
+                ip_addresses: validator_container.ip_addresses.clone(),
+                dns_names: self.dns_names(&validator_container),
             });
             self.containers.push(validator_container);
         }
@@ -189,8 +821,185 @@ impl DockerNetwork {
             .unwrap();
     }
 
+    /// Starts the shadow node, which has the role of the observed node and trusts the same validators, but is not trusted
+    /// by any node. It gets the ports following those of the validators, and is stored in the `shadow` field.
+    ///
+    /// # Parameters
+    /// * 'shadow_config' - the observed node and the image of the shadow node.
+    ///
+    /// # Panics
+    /// * If the observed node does not exist.
+    /// * If the image of the shadow node could not be downloaded.
+    pub async fn start_shadow(&mut self, shadow_config: &ShadowConfig) {
+        let observed = self
+            .containers
+            .get(shadow_config.observed_node as usize)
+            .unwrap_or_else(|| {
+                panic!(
+                    "Invalid shadow configuration: node {} does not exist",
+                    shadow_config.observed_node
+                )
+            })
+            .clone();
+        let image = shadow_config.image.as_deref().unwrap_or(IMAGE);
+        if image != IMAGE {
+            self.download(image).await;
+        }
+        let key = self.generate_keys(1, "shadow").remove(0);
+        let name = self.container_name(&format!("shadow_{}", shadow_config.observed_node));
+        let unl_public_keys: Vec<String> = self
+            .containers
+            .iter()
+            .enumerate()
+            .filter(|(j, container)| {
+                container.role == NodeRole::Validator
+                    && is_valid_unl_connection(
+                        shadow_config.observed_node,
+                        *j as u32,
+                        &self.config.unl_partitions,
+                    )
+            })
+            .map(|(_, container)| container.key_data.validation_public_key.clone())
+            .collect();
+        Self::write_node_config(
+            &self.directory(&format!("shadow/{}/config", name)),
+            &key,
+            &unl_public_keys,
+            self.validator_list_trust.as_ref(),
+            &self.amendment_sections(shadow_config.observed_node),
+            observed.role,
+            &self.rpc_admin(),
+        );
+
+        let i = self.containers.len() as u32;
+        let mut shadow_container = DockerContainer {
+            id: None,
+            name: name.clone(),
+            port_peer: self.config.base_port_peer + i,
+            port_ws: self.config.base_port_ws + i,
+            port_ws_admin: self.config.base_port_ws_admin + i,
+            port_rpc: self.config.base_port_rpc + i,
+            key_data: key,
+            role: observed.role,
+            limits: self.resources.limits(shadow_config.observed_node),
+            clock_skew: self.clock_skew_of(shadow_config.observed_node),
+            aliases: Vec::new(),
+            ip_addresses: Vec::new(),
+        };
+        let start = Instant::now();
+        self.start_validator(&mut shadow_container, image).await;
+        info!(
+            "Started docker container {} running {} as the shadow of {}",
+            name, image, observed.name
+        );
+        self.startup_timings.push(StartupTiming {
+            name,
+            started_after: start.elapsed(),
+            available_after: None,
+        });
+        self.shadow = Some(shadow_container);
+    }
+
+    /// Starts the validator list publisher: a web server that serves the list from /network/validator_list, to which the
+    /// first list is published. The site is stored in the `validator_list_site` field.
+    ///
+    /// # Parameters
+    /// * 'validator_list' - the configuration of the validator list publisher.
+    /// * 'keys' - the keys of the nodes, by their IDs.
+    ///
+    /// # Panics
+    /// * If the image of the publisher could not be downloaded.
+    /// * If the first list could not be published.
+    /// * If the Docker container of the publisher could not be created, started or inspected.
+    async fn start_validator_list(
+        &mut self,
+        validator_list: &ValidatorListConfig,
+        keys: &[ValidatorKeyData],
+    ) {
+        self.download(&validator_list.image).await;
+        let directory = current_dir()
+            .unwrap()
+            .join(self.directory("validator_list"));
+        let container_name = self.container_name(VALIDATOR_LIST_CONTAINER);
+        let public_keys: Vec<String> = keys
+            .iter()
+            .map(|key| key.validation_public_key.clone())
+            .collect();
+        let mut site = ValidatorListSite::new(
+            directory.clone(),
+            &public_keys,
+            validator_list.expiration_days,
+            validator_list.refresh_interval_mins,
+            &mut run_seed::rng(self.seed, "validator_list"),
+        )
+        .unwrap_or_else(|e| panic!("Could not create the validator list: {}", e));
+        let validators = validator_list.validators.clone().unwrap_or_else(|| {
+            (0..keys.len() as u32)
+                .filter(|id| self.roles.role(*id) == NodeRole::Validator)
+                .collect()
+        });
+        site.publish(&validators)
+            .unwrap_or_else(|e| panic!("Could not publish the validator list: {}", e));
+
+        let container_config = bollard::container::Config {
+            image: Some(validator_list.image.as_str()),
+            labels: Some(self.labels()),
+            host_config: Some(HostConfig {
+                auto_remove: Some(true),
+                network_mode: self.network_name(),
+                mounts: Some(vec![Mount {
+                    target: Some(String::from("/usr/share/nginx/html")),
+                    source: Some(directory.to_str().unwrap().to_string()),
+                    typ: Some(MountTypeEnum::BIND),
+                    read_only: Some(true),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let id = self
+            .docker
+            .create_container::<&str, &str>(
+                Some(CreateContainerOptions {
+                    name: container_name.as_str(),
+                    ..Default::default()
+                }),
+                container_config,
+            )
+            .await
+            .unwrap_or_else(|e| panic!("Failed to create container: {}", e))
+            .id;
+        self.docker
+            .start_container::<String>(&id, None)
+            .await
+            .unwrap_or_else(|e| panic!("Failed to start the validator list publisher: {}", e));
+        // The nodes reach the publisher by its name in a network of their own, which Docker resolves, and by its address
+        // in the default bridge network
+        let host = match self.network_name() {
+            Some(_) => container_name.clone(),
+            None => self
+                .ip_addresses(&id)
+                .await
+                .into_iter()
+                .next()
+                .expect("The validator list publisher has no IP address"),
+        };
+        info!(
+            "Started docker container {} publishing validators {:?}",
+            container_name, validators
+        );
+        self.validator_list_trust = Some(ValidatorListTrust {
+            site: format!("http://{}/{}", host, PUBLISHED_FILE),
+            publisher_key: site.publisher_key(),
+        });
+        self.validator_list_site = Some(site);
+    }
+
     /// Stops the docker network, by looping over all running containers (`docker ps`)
-    /// and stopping all containers that start with `validator_` or `key_generator`.
+    /// and stopping all containers that start with `validator_` or `shadow_`, prefixed with the
+    /// namespace of the network if it has one. The validator list publisher is stopped as well, as its name starts with
+    /// `validator_`.
     ///
     /// # Panics
     /// * If it could not fetch the list of running containers from the Docker API.
@@ -201,12 +1010,14 @@ impl DockerNetwork {
             .list_containers::<String>(None)
             .await
             .expect("Could not fetch container list from docker");
+        // Docker container names always start with a slash
+        let validator_prefix = format!("/{}", self.container_name("validator_"));
+        let shadow_prefix = format!("/{}", self.container_name("shadow_"));
         for container in running_containers {
             if let Some(names) = container.names {
                 for name in names {
                     debug!("{}", name);
-                    // Docker container names always start with a slash
-                    if name.starts_with("/validator_") || name.eq("/key_generator") {
+                    if name.starts_with(&validator_prefix) || name.starts_with(&shadow_prefix) {
                         debug!(
                             "Stopping container (auto removed): {}",
                             container.id.clone().unwrap().as_str()
@@ -231,12 +1042,49 @@ impl DockerNetwork {
         }
     }
 
+    /// Returns the last lines of the output of every container, together with the name of the container.
+    /// Containers whose output could not be fetched are skipped.
+    ///
+    /// # Parameters
+    /// * 'tail' - the amount of lines fetched per container.
+    pub async fn container_logs(&self, tail: usize) -> Vec<(String, Vec<u8>)> {
+        let mut logs = Vec::new();
+        for container in self.containers.iter().chain(self.shadow.iter()) {
+            let output = self
+                .docker
+                .logs::<String>(
+                    container.name.as_str(),
+                    Some(LogsOptions {
+                        stdout: true,
+                        stderr: true,
+                        timestamps: true,
+                        tail: tail.to_string(),
+                        ..Default::default()
+                    }),
+                )
+                .try_collect::<Vec<_>>()
+                .await;
+            match output {
+                Ok(output) => logs.push((
+                    container.name.clone(),
+                    output
+                        .into_iter()
+                        .flat_map(|line| line.into_bytes())
+                        .collect(),
+                )),
+                Err(e) => warn!("Could not fetch the logs of {}: {}", container.name, e),
+            }
+        }
+        logs
+    }
+
     /// Loop over all containers in `self`, and poll them every 500ms, until
-    /// all containers are available.
-    pub async fn wait_for_startup(&self) {
+    /// all containers are available. How long every node container took is stored in `startup_timings`.
+    pub async fn wait_for_startup(&mut self) {
         let mut threads = vec![];
         let arc = self.docker.clone();
-        for container in self.containers.clone() {
+        let start = Instant::now();
+        for container in self.containers.iter().chain(self.shadow.iter()).cloned() {
             let _docker = arc.clone();
             let t = tokio::spawn(async move {
                 loop {
@@ -245,12 +1093,37 @@ impl DockerNetwork {
                     }
                     tokio::time::sleep(Duration::from_millis(500)).await;
                 }
+                (container.name, start.elapsed())
             });
             threads.push(t);
         }
 
         for t in threads {
-            t.await.expect("Wait for startup failed for thread");
+            let (name, available_after) = t.await.expect("Wait for startup failed for thread");
+            debug!(
+                "Container {} available after {} ms",
+                name,
+                available_after.as_millis()
+            );
+            if let Some(timing) = self
+                .startup_timings
+                .iter_mut()
+                .find(|timing| timing.name == name)
+            {
+                timing.available_after = Some(available_after);
+            }
+        }
+        if let Some(slowest) = self
+            .startup_timings
+            .iter()
+            .max_by_key(|timing| timing.available_after)
+        {
+            info!(
+                "All {} containers are available, the slowest was {} after {} ms",
+                self.startup_timings.len(),
+                slowest.name,
+                slowest.available_after.unwrap_or_default().as_millis()
+            );
         }
     }
 
@@ -259,10 +1132,21 @@ impl DockerNetwork {
     /// # Panics
     /// * If an error occurred while downloading the image.
     async fn download_image(&mut self) {
+        self.download(IMAGE).await;
+    }
+
+    /// Downloads an image from DockerHub.
+    ///
+    /// # Parameters
+    /// * 'image' - the name and tag of the image.
+    ///
+    /// # Panics
+    /// * If an error occurred while downloading the image.
+    async fn download(&self, image: &str) {
         self.docker
             .create_image(
                 Some(CreateImageOptions {
-                    from_image: IMAGE,
+                    from_image: image,
                     ..Default::default()
                 }),
                 None,
@@ -273,17 +1157,18 @@ impl DockerNetwork {
             .unwrap();
     }
 
-    /// Starts a validator node.
+    /// Starts a validator node, limited to the memory and CPUs of the container.
     /// It binds specific ports of the container to be able to communicate with the nodes.
     /// Besides, it starts the validator node with a specified ledger to have all amendments already included.
     ///
     /// # Parameters
     /// * 'container' - the container to be started.
+    /// * 'image' - the image the container runs.
     ///
     /// # Panics
     /// * If it could not format the directory path to the 'config' directory.
     /// * If the Docker container who runs the validator could not be created or started.
-    async fn start_validator(&self, container: &mut DockerContainer) {
+    async fn start_validator(&self, container: &mut DockerContainer, image: &str) {
         let mut port_map = PortMap::new();
         port_map.insert(
             String::from("51235/tcp"),
@@ -319,24 +1204,105 @@ impl DockerNetwork {
             ..Default::default()
         };
 
+        let config_directory = if container.name.starts_with(&self.container_name("shadow_")) {
+            "shadow"
+        } else {
+            "validators"
+        };
+        let mut start_args = "--start --ledgerfile /config/ledger.json";
+        let mut mounts = vec![Mount {
+            target: Some(String::from("/config")),
+            source: Some(format!(
+                "{}/{}",
+                current_dir().unwrap().to_str().unwrap(),
+                self.directory(&format!(
+                    "{}/{}/config",
+                    config_directory,
+                    container.name.as_str()
+                ))
+            )),
+            typ: Some(MountTypeEnum::BIND),
+            ..Default::default()
+        }];
+        if let Some(persistence) = &self.persistence {
+            let volume = persistence.volume(&container.name);
+            if self.prepare_volume(&volume).await {
+                info!(
+                    "Docker container {} loads its ledger from volume {}",
+                    container.name, volume
+                );
+                start_args = "--load";
+            }
+            mounts.push(Mount {
+                target: Some(String::from(DATABASE_DIRECTORY)),
+                source: Some(volume),
+                typ: Some(MountTypeEnum::VOLUME),
+                ..Default::default()
+            });
+        }
+        let mut env = vec![format!("ENV_ARGS={}", start_args)];
+        if let (Some(clock_file), Some(clock_skew)) =
+            (self.clock_file(&container.name), &self.clock_skew)
+        {
+            // Every node reads its clock from a file, such that the clock can also be changed while the node runs.
+            // Only the wall clock is skewed, the timers of rippled keep running at the real pace.
+            let skew = container.clock_skew.unwrap_or_default();
+            write_clock_file(&clock_file, &faketime(skew))
+                .unwrap_or_else(|e| panic!("Could not write the clock file {}: {}", clock_file, e));
+            env.push(format!("LD_PRELOAD={}", FAKETIME_LIBRARY));
+            env.push(format!("FAKETIME_TIMESTAMP_FILE={}", FAKETIME_FILE));
+            env.push("FAKETIME_CACHE_DURATION=1".to_string());
+            env.push("FAKETIME_DONT_FAKE_MONOTONIC=1".to_string());
+            mounts.push(Mount {
+                target: Some(String::from(FAKETIME_LIBRARY)),
+                source: Some(clock_skew.library.clone()),
+                typ: Some(MountTypeEnum::BIND),
+                read_only: Some(true),
+                ..Default::default()
+            });
+            mounts.push(Mount {
+                target: Some(String::from(FAKETIME_FILE)),
+                source: Some(clock_file),
+                typ: Some(MountTypeEnum::BIND),
+                read_only: Some(true),
+                ..Default::default()
+            });
+            if container.clock_skew.is_some() {
+                info!(
+                    "Skewing the clock of docker container {} by '{}'",
+                    container.name,
+                    faketime(skew)
+                );
+            }
+        }
+        let network_name = self.network_name();
         let container_config = bollard::container::Config {
-            image: Some(IMAGE),
-            env: Some(vec!["ENV_ARGS=--start --ledgerfile /config/ledger.json"]),
+            image: Some(image),
+            env: Some(env.iter().map(String::as_str).collect()),
+            labels: Some(self.labels()),
             host_config: Some(HostConfig {
                 auto_remove: Some(true),
+                network_mode: self.network_name(),
                 port_bindings: Some(port_map),
-                mounts: Some(vec![Mount {
-                    target: Some(String::from("/config")),
-                    source: Some(format!(
-                        "{}/network/validators/{}/config",
-                        current_dir().unwrap().to_str().unwrap(),
-                        container.name.as_str()
-                    )),
-                    typ: Some(MountTypeEnum::BIND),
-                    ..Default::default()
-                }]),
+                memory: container
+                    .limits
+                    .memory_mb
+                    .map(|memory_mb| (memory_mb * 1024 * 1024) as i64),
+                nano_cpus: container.limits.cpus.map(nano_cpus),
+                mounts: Some(mounts),
                 ..Default::default()
             }),
+            networking_config: network_name
+                .as_deref()
+                .map(|network_name| NetworkingConfig {
+                    endpoints_config: HashMap::from([(
+                        network_name,
+                        EndpointSettings {
+                            aliases: Some(container.aliases.clone()),
+                            ..Default::default()
+                        },
+                    )]),
+                }),
             ..Default::default()
         };
 
@@ -350,6 +1316,7 @@ impl DockerNetwork {
                 match self.docker.start_container::<String>(&id, None).await {
                     Ok(_) => {
                         container.id = Some(id.clone());
+                        container.ip_addresses = self.ip_addresses(&id).await;
                     }
                     Err(e) => {
                         panic!("Failed to start the xrpld container, try checking your base port configuration values to make sure they are not bound by another process: {}", e);
@@ -362,109 +1329,173 @@ impl DockerNetwork {
         }
     }
 
-    /// Generates `n` validator keys using a `rippled` instance.
+    /// Creates the volume the databases of a node are kept in, unless it exists already.
+    /// Returns whether the volume existed, in which case it holds the ledger history of a previous run.
     ///
     /// # Parameters
-    /// * 'n' - the amount of validator keys to generate.
+    /// * 'volume' - the name of the volume.
     ///
     /// # Panics
-    /// * If the JSON response from `rippled` cannot be correctly deserialized to the `ValidationKeyCreateResponse` struct.
-    /// * If an error occurred while creating or starting the Docker container who generates the keys.
-    /// * If an error occurred while creating or executing the 'validation_create' command.
-    /// * If an error occurred while removing the Docker container who generated the keys.
-    async fn generate_keys(&self, n: u16) -> Vec<ValidatorKeyData> {
-        let container_name = String::from("key_generator");
-        let create_options = CreateContainerOptions {
-            name: container_name.as_str(),
-            ..Default::default()
-        };
-
-        let container_config = bollard::container::Config {
-            image: Some(IMAGE),
-            host_config: Some(HostConfig {
-                auto_remove: Some(true),
-                mounts: Some(vec![Mount {
-                    target: Some(String::from("/config")),
-                    source: Some(format!(
-                        "{}/network/{}/config",
-                        current_dir().unwrap().to_str().unwrap(),
-                        container_name.as_str()
-                    )),
-                    typ: Some(MountTypeEnum::BIND),
-                    ..Default::default()
-                }]),
+    /// * If the volume could not be created.
+    async fn prepare_volume(&self, volume: &str) -> bool {
+        if self.docker.inspect_volume(volume).await.is_ok() {
+            return true;
+        }
+        self.docker
+            .create_volume(CreateVolumeOptions {
+                name: volume,
+                labels: self.labels(),
                 ..Default::default()
-            }),
-            ..Default::default()
-        };
+            })
+            .await
+            .unwrap_or_else(|e| panic!("Failed to create volume {}: {}", volume, e));
+        false
+    }
 
-        let id = self
+    /// Removes the volumes of the nodes of a previous run, such that every node starts from the genesis ledger.
+    ///
+    /// # Panics
+    /// * If the volumes could not be listed or removed.
+    async fn remove_volumes(&self) {
+        let Some(persistence) = &self.persistence else {
+            return;
+        };
+        let prefix = persistence.volume(&self.container_name(""));
+        let node_prefixes = [
+            persistence.volume(&self.container_name("validator_")),
+            persistence.volume(&self.container_name("shadow_")),
+        ];
+        let volumes = self
             .docker
-            .create_container::<&str, &str>(Some(create_options), container_config)
+            .list_volumes(Some(ListVolumesOptions {
+                filters: [("name", vec![prefix.as_str()])].into_iter().collect(),
+            }))
             .await
-            .unwrap()
-            .id;
+            .expect("Could not fetch volume list from docker")
+            .volumes
+            .unwrap_or_default();
+        for volume in volumes {
+            // The name filter of Docker matches any part of the name, and the prefix of the volumes of a network
+            // without a namespace is also the prefix of the volumes of the networks with one
+            if node_prefixes
+                .iter()
+                .any(|prefix| volume.name.starts_with(prefix))
+            {
+                info!("Removing volume {}", volume.name);
+                self.docker
+                    .remove_volume(&volume.name, None)
+                    .await
+                    .unwrap_or_else(|e| panic!("Failed to remove volume {}: {}", volume.name, e));
+            }
+        }
+    }
 
+    /// Archives the contents of a volume to `<volume>.tar` in a directory.
+    ///
+    /// # Parameters
+    /// * 'volume' - the name of the volume.
+    /// * 'directory' - the directory the archive is written to.
+    pub async fn archive_volume(
+        &self,
+        volume: &str,
+        directory: &Path,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
         self.docker
-            .start_container::<String>(&id, None)
+            .inspect_volume(volume)
             .await
-            .unwrap();
+            .map_err(|e| format!("Volume {} does not exist: {}", volume, e))?;
+        self.run_volume_archiver(
+            volume,
+            directory,
+            vec![
+                "-cf",
+                &format!("/archive/{}.tar", volume),
+                "-C",
+                "/volume",
+                ".",
+            ],
+        )
+        .await
+    }
 
-        loop {
-            if check_validator_available(
-                DockerContainer {
-                    id: Some(id.clone()),
-                    name: container_name.clone(),
-                    port_peer: 0,
-                    port_ws: 0,
-                    port_ws_admin: 0,
-                    port_rpc: 0,
-                    key_data: ValidatorKeyData {
-                        status: "success".to_string(),
-                        validation_key: "".to_string(),
-                        validation_private_key: "".to_string(),
-                        validation_public_key: "".to_string(),
-                        validation_seed: "".to_string(),
-                    },
-                },
-                self.docker.clone(),
-            )
-            .await
-            {
-                break;
-            }
-            tokio::time::sleep(Duration::from_millis(500)).await;
+    /// Replaces the contents of a volume with those of `<volume>.tar` in a directory, creating the volume if needed.
+    ///
+    /// # Parameters
+    /// * 'volume' - the name of the volume.
+    /// * 'directory' - the directory the archive is read from.
+    pub async fn restore_volume(
+        &self,
+        volume: &str,
+        directory: &Path,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if self.docker.inspect_volume(volume).await.is_ok() {
+            self.docker.remove_volume(volume, None).await?;
         }
+        self.prepare_volume(volume).await;
+        self.run_volume_archiver(
+            volume,
+            directory,
+            vec!["-xf", &format!("/archive/{}.tar", volume), "-C", "/volume"],
+        )
+        .await
+    }
 
-        // Generate the keys and parse the output
-        let mut key_vec: Vec<ValidatorKeyData> = Vec::new();
-        for _ in 0..n {
-            let exec = self
-                .docker
-                .create_exec(
-                    &id,
-                    CreateExecOptions {
-                        attach_stdout: Some(true),
-                        cmd: Some(vec!["rippled", "validation_create"]),
+    /// Runs `tar` in a container that mounts a volume at /volume and a directory of the host at /archive, and waits
+    /// until it finished.
+    ///
+    /// # Parameters
+    /// * 'volume' - the name of the volume.
+    /// * 'directory' - the directory of the host.
+    /// * 'tar_args' - the arguments of `tar`.
+    async fn run_volume_archiver(
+        &self,
+        volume: &str,
+        directory: &Path,
+        tar_args: Vec<&str>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.download(IMAGE).await;
+        let directory = fs::canonicalize(directory)?;
+        let container_config = bollard::container::Config {
+            image: Some(IMAGE),
+            entrypoint: Some(vec!["tar"]),
+            cmd: Some(tar_args),
+            labels: Some(self.labels()),
+            host_config: Some(HostConfig {
+                mounts: Some(vec![
+                    Mount {
+                        target: Some(String::from("/volume")),
+                        source: Some(volume.to_string()),
+                        typ: Some(MountTypeEnum::VOLUME),
                         ..Default::default()
                     },
-                )
-                .await
-                .unwrap()
-                .id;
-            if let StartExecResults::Attached { mut output, .. } =
-                self.docker.start_exec(&exec, None).await.unwrap()
-            {
-                while let Some(Ok(msg)) = output.next().await {
-                    let json_str = String::from_utf8(msg.as_ref().to_vec()).unwrap();
-                    let validator_key_data: ValidationKeyCreateResponse =
-                        serde_json::from_str(json_str.as_str()).unwrap();
-
-                    key_vec.push(validator_key_data.result);
-                }
-            }
-        }
-
+                    Mount {
+                        target: Some(String::from("/archive")),
+                        source: Some(directory.to_string_lossy().to_string()),
+                        typ: Some(MountTypeEnum::BIND),
+                        ..Default::default()
+                    },
+                ]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let id = self
+            .docker
+            .create_container(
+                Some(CreateContainerOptions {
+                    name: VOLUME_ARCHIVER,
+                    ..Default::default()
+                }),
+                container_config,
+            )
+            .await?
+            .id;
+        self.docker.start_container::<String>(&id, None).await?;
+        let result = self
+            .docker
+            .wait_container(&id, None::<WaitContainerOptions<String>>)
+            .try_collect::<Vec<_>>()
+            .await;
         self.docker
             .remove_container(
                 &id,
@@ -473,13 +1504,36 @@ impl DockerNetwork {
                     ..Default::default()
                 }),
             )
-            .await
-            .unwrap();
-        key_vec
+            .await?;
+        result
+            .map(|_| ())
+            .map_err(|e| format!("Could not archive or restore volume {}: {}", volume, e).into())
     }
 
-    /// Generates and writes the config files for every key in `keys` to disk. The configurations
-    /// are saved to /network/validators/\<name\>.
+    /// Generates `n` validator keys, derived the way `rippled validation_create` derives them. If the run is seeded,
+    /// every key is derived from a passphrase that is derived from the seed, the purpose and the index of the key.
+    ///
+    /// # Parameters
+    /// * 'n' - the amount of validator keys to generate.
+    /// * 'purpose' - what the keys are used for, e.g. 'validator', such that keys for other purposes differ.
+    pub fn generate_keys(&self, n: u16, purpose: &str) -> Vec<ValidatorKeyData> {
+        let mut rng = run_seed::rng(None, purpose);
+        (0..n)
+            .map(|i| {
+                let seed = match self.seed {
+                    Some(run_seed) => {
+                        Seed::from_passphrase(&run_seed.passphrase(purpose, i as usize))
+                    }
+                    None => Seed::generate(&mut rng),
+                };
+                NodeKeys::derive(seed, KeyType::Secp256k1).to_key_data()
+            })
+            .collect()
+    }
+
+    /// Generates and writes the config files for every key in `keys` to disk, as the node with the same ID in its role.
+    /// Only the validators are trusted by the nodes. The configurations are saved to /network/validators/\<name\>, in the
+    /// directory of the namespace of the network if it has one.
     ///
     /// # Panics
     /// * If the `rippled_base.cfg` cannot be read.
@@ -488,6 +1542,78 @@ impl DockerNetwork {
         &self,
         keys: &[ValidatorKeyData],
     ) -> Vec<(String, ValidatorKeyData)> {
+        let mut ret: Vec<(String, ValidatorKeyData)> = Vec::new();
+        for (i, key) in keys.iter().enumerate() {
+            let container_name = self.container_name(&format!("validator_{}", i));
+            let unl_public_keys: Vec<String> = keys
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| {
+                    self.roles.role(*j as u32) == NodeRole::Validator
+                        && is_valid_unl_connection(i as u32, *j as u32, &self.config.unl_partitions)
+                })
+                .map(|(_, k)| k.validation_public_key.to_string())
+                .collect();
+            Self::write_node_config(
+                &self.directory(&format!("validators/{}/config", container_name)),
+                key,
+                &unl_public_keys,
+                self.validator_list_trust.as_ref(),
+                &self.amendment_sections(i as u32),
+                self.roles.role(i as u32),
+                &self.rpc_admin(),
+            );
+
+            ret.push((container_name, key.clone()));
+        }
+        ret
+    }
+
+    /// Returns how the clock of a node deviates from the real time, if it is skewed.
+    ///
+    /// # Parameters
+    /// * 'node' - the ID of the node.
+    fn clock_skew_of(&self, node: u32) -> Option<ClockSkew> {
+        self.clock_skew
+            .as_ref()
+            .and_then(|clock_skew| clock_skew.skew(node))
+    }
+
+    /// Returns the sections of the configuration of a node that set its votes on amendments.
+    ///
+    /// # Parameters
+    /// * 'node' - the ID of the node.
+    fn amendment_sections(&self, node: u32) -> String {
+        let (enabled, vetoed) = self.amendments.votes_of(node);
+        amendments::config_sections(&enabled, &vetoed)
+    }
+
+    /// Writes the config files of a single node to a directory: its rippled.cfg, the validators it trusts and the
+    /// ledger it starts from. Only validators are given their validation seed, and hubs accept many more peers.
+    /// If a validator list is published, the node trusts the validators on the list instead of its UNL.
+    /// The votes of the node on amendments are appended to its rippled.cfg.
+    ///
+    /// # Parameters
+    /// * 'config_dir' - the directory the files are written to.
+    /// * 'key' - the keys of the node.
+    /// * 'unl_public_keys' - the validation public keys of the validators the node trusts.
+    /// * 'validator_list' - where the node gets its UNL from, if a validator list is published.
+    /// * 'amendment_sections' - the sections that set the votes of the node on amendments.
+    /// * 'role' - the role of the node.
+    /// * 'rpc_admin' - the comma-separated addresses the admin RPC of the node is open to.
+    ///
+    /// # Panics
+    /// * If the `rippled_base.cfg` cannot be read.
+    /// * If the config could not be written to disk (no permissions/directory does not exist).
+    fn write_node_config(
+        config_dir: &str,
+        key: &ValidatorKeyData,
+        unl_public_keys: &[String],
+        validator_list: Option<&ValidatorListTrust>,
+        amendment_sections: &str,
+        role: NodeRole,
+        rpc_admin: &str,
+    ) {
         let base_config_path = "network/rippled_base.cfg";
         let ledger_json_path = "network/ledger.json";
         let base_config_file = fs::File::open(base_config_path);
@@ -497,44 +1623,93 @@ impl DockerNetwork {
             .unwrap()
             .read_to_string(&mut base_config_contents)
             .unwrap_or_else(|_| panic!("Could not read file {}", base_config_path));
+        let base_config_contents = base_config_contents.replace("{rpc_admin}", rpc_admin);
+        let mut new_config_contents = match role {
+            NodeRole::Validator => {
+                base_config_contents.replace("{validation_seed}", key.validation_seed.as_str())
+            }
+            NodeRole::Tracking | NodeRole::Hub => {
+                base_config_contents.replace("[validation_seed]\n{validation_seed}\n", "")
+            }
+        };
+        if role == NodeRole::Hub {
+            new_config_contents.push_str(&format!("\n[peers_max]\n{}\n", HUB_PEERS_MAX));
+        }
+        new_config_contents.push_str(amendment_sections);
 
-        let mut ret: Vec<(String, ValidatorKeyData)> = Vec::new();
-        for (i, key) in keys.iter().enumerate() {
-            let container_name = format!("validator_{}", i);
-            let new_config_contents = base_config_contents
-                .clone()
-                .replace("{validation_seed}", key.validation_seed.as_str());
+        fs::create_dir_all(config_dir).expect("Could not create directory.");
 
-            let config_dir = format!("network/validators/{}/config", container_name.clone());
-            fs::create_dir_all(config_dir.as_str()).expect("Could not create directory.");
+        let mut config_file = fs::File::create(format!("{}/rippled.cfg", config_dir)).unwrap();
+        config_file
+            .write_all(new_config_contents.as_bytes())
+            .expect("Could not write to config file");
 
-            let mut config_file =
-                fs::File::create(format!("{}/rippled.cfg", config_dir.as_str())).unwrap();
-            config_file
-                .write_all(new_config_contents.as_bytes())
-                .expect("Could not write to config file");
+        let mut validators_file =
+            fs::File::create(format!("{}/validators.txt", config_dir)).unwrap();
+        let validators_contents = match validator_list {
+            Some(trust) => format!(
+                "[validator_list_sites]\n{}\n\n[validator_list_keys]\n{}",
+                trust.site, trust.publisher_key
+            ),
+            None => format!("[validators]\n{}", unl_public_keys.join("\n")),
+        };
+        validators_file
+            .write_all(validators_contents.as_bytes())
+            .expect("Could not write to config file");
 
-            let mut validators_file =
-                fs::File::create(format!("{}/validators.txt", config_dir)).unwrap();
+        fs::copy(ledger_json_path, format!("{}/ledger.json", config_dir)).unwrap();
+    }
+}
 
-            let unl_public_keys: Vec<String> = keys
-                .iter()
-                .enumerate()
-                .filter(|(j, _)| {
-                    is_valid_unl_connection(i as u32, *j as u32, &self.config.unl_partitions)
-                })
-                .map(|(_, k)| k.validation_public_key.to_string())
-                .collect();
+#[cfg(test)]
+mod unit_tests {
+    use crate::docker_manager::DockerNetwork;
+    use crate::keygen::{KeyType, NodeKeys, Seed};
+    use crate::packet_client::proto::Config;
+    use crate::run_seed::RunSeed;
 
-            validators_file
-                .write_all(format!("[validators]\n{}", unl_public_keys.join("\n")).as_bytes())
-                .expect("Could not write to config file");
+    // Tests the generate_keys function; assert that the keys differ, and are derived from the seed of a seeded run the
+    // way `rippled validation_create <passphrase>` derives them
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn test_generate_keys() {
+        let mut docker_network = DockerNetwork::new(Config {
+            base_port_peer: 60000,
+            base_port_ws: 61000,
+            base_port_ws_admin: 62000,
+            base_port_rpc: 63000,
+            number_of_nodes: 3,
+            net_partitions: vec![],
+            unl_partitions: vec![],
+        });
+        let keys = docker_network.generate_keys(3, "validator");
+        assert_eq!(keys.len(), 3);
+        assert_ne!(keys[0], keys[1]);
+        assert!(keys
+            .iter()
+            .all(|key| key.validation_public_key.starts_with('n')));
 
-            fs::copy(ledger_json_path, format!("{}/ledger.json", config_dir)).unwrap();
+        docker_network.set_seed(RunSeed(42));
+        let keys = docker_network.generate_keys(3, "validator");
+        assert_eq!(keys, docker_network.generate_keys(3, "validator"));
+        assert_ne!(keys[..1], docker_network.generate_keys(1, "shadow"));
+        let passphrase = RunSeed(42).passphrase("validator", 2);
+        assert_eq!(
+            keys[2],
+            NodeKeys::derive(Seed::from_passphrase(&passphrase), KeyType::Secp256k1).to_key_data()
+        );
 
-            ret.push((container_name, key.clone()));
-        }
-        ret
+        // The seed and key `rippled validation_create masterpassphrase` prints
+        let key_data = NodeKeys::derive(
+            Seed::from_passphrase("masterpassphrase"),
+            KeyType::Secp256k1,
+        )
+        .to_key_data();
+        assert_eq!(key_data.validation_seed, "snoPBrXtMeMyMHUVTgbuqAfg1SUTb");
+        assert_eq!(
+            key_data.validation_public_key,
+            "n94a1u4jAz288pZLtw6yFWVbi89YamiC6JBXPVUj5zmExe5fTVg9"
+        );
     }
 }
 
@@ -557,36 +1732,6 @@ mod integration_tests_docker {
         DockerNetwork::new(config)
     }
 
-    // Note: This test requires running a docker engine in clean state
-    // Tests the generate_keys function; assert that the keys are generated and the container is removed
-    #[tokio::test]
-    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
-    async fn test_generate_keys() {
-        let docker_network = docker_network_setup();
-        assert_eq!(
-            docker_network
-                .docker
-                .list_containers::<String>(None)
-                .await
-                .unwrap()
-                .len(),
-            0,
-            "This test requires a clean docker starting state"
-        );
-        let keys = docker_network.generate_keys(3).await;
-        assert_eq!(keys.len(), 3);
-        assert_eq!(
-            docker_network
-                .docker
-                .list_containers::<String>(None)
-                .await
-                .unwrap()
-                .len(),
-            0,
-            "Docker containers were not removed correctly"
-        );
-    }
-
     // Tests the generate_validator_configs function; assert that the config files are correctly created
     #[test]
     // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
@@ -594,14 +1739,12 @@ mod integration_tests_docker {
         let keys = vec![
             ValidatorKeyData {
                 status: "success".to_string(),
-                validation_key: "val_key1".to_string(),
                 validation_private_key: "priv_key1".to_string(),
                 validation_public_key: "pub_key1".to_string(),
                 validation_seed: "seed1".to_string(),
             },
             ValidatorKeyData {
                 status: "success".to_string(),
-                validation_key: "val_key2".to_string(),
                 validation_private_key: "priv_key2".to_string(),
                 validation_public_key: "pub_key2".to_string(),
                 validation_seed: "seed2".to_string(),
@@ -672,6 +1815,182 @@ mod integration_tests_docker {
         );
     }
 
+    // Tests the namespace of a network; assert that it prefixes the names of the containers and the directories
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn test_namespace() {
+        let mut docker_network = docker_network_setup();
+        assert_eq!(docker_network.container_name("validator_0"), "validator_0");
+        assert_eq!(
+            docker_network.directory("validator_list"),
+            "network/validator_list"
+        );
+
+        docker_network.set_namespace("a".to_string());
+        assert_eq!(
+            docker_network.container_name("validator_0"),
+            "a_validator_0"
+        );
+        assert_eq!(
+            docker_network.directory("validator_list"),
+            "network/a/validator_list"
+        );
+    }
+
+    // Tests the network_name function; assert that the containers only leave the default bridge network when they run
+    // dual-stack, and that the network is namespaced
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn test_network_name() {
+        let mut docker_network = docker_network_setup();
+        assert_eq!(docker_network.network_name(), None);
+
+        docker_network.set_addressing(&AddressingConfig {
+            dual_stack: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            docker_network.network_name(),
+            Some("rocket_network".to_string())
+        );
+        docker_network.set_namespace("a".to_string());
+        assert_eq!(
+            docker_network.network_name(),
+            Some("a_rocket_network".to_string())
+        );
+    }
+
+    // Tests the dns_names function; assert that containers are only reachable by name in a network of their own, by
+    // the name of their container and their aliases
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn test_dns_names() {
+        let mut docker_network = docker_network_setup();
+        let container = DockerContainer {
+            id: None,
+            name: "validator_0".to_string(),
+            port_peer: 60000,
+            port_ws: 61000,
+            port_ws_admin: 62000,
+            port_rpc: 63000,
+            key_data: ValidatorKeyData {
+                status: "success".to_string(),
+                validation_private_key: "".to_string(),
+                validation_public_key: "".to_string(),
+                validation_seed: "".to_string(),
+            },
+            role: NodeRole::Validator,
+            limits: ResourceLimits::default(),
+            clock_skew: None,
+            aliases: vec!["hub".to_string()],
+            ip_addresses: Vec::new(),
+        };
+        assert!(docker_network.dns_names(&container).is_empty());
+
+        docker_network.set_addressing(&AddressingConfig {
+            subnet: Some("172.30.0.0/16".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(
+            docker_network.dns_names(&container),
+            vec!["validator_0", "hub"]
+        );
+    }
+
+    // Tests the faketime function; assert that the offset is signed and the rate is only given if the clock drifts
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn test_faketime() {
+        assert_eq!(
+            faketime(ClockSkew {
+                offset_secs: 30,
+                drift_rate: 1.0,
+            }),
+            "+30"
+        );
+        assert_eq!(
+            faketime(ClockSkew {
+                offset_secs: -5,
+                drift_rate: 1.01,
+            }),
+            "-5 x1.01"
+        );
+        assert_eq!(faketime_spec(-1500, 1.0), "-1.500");
+        assert_eq!(faketime_spec(250, 2.0), "+0.250 x2");
+    }
+
+    // Tests the write_node_config function; assert that only validators get their validation seed
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn test_write_hub_config() {
+        let key = ValidatorKeyData {
+            status: "success".to_string(),
+            validation_private_key: "priv_key1".to_string(),
+            validation_public_key: "pub_key1".to_string(),
+            validation_seed: "seed1".to_string(),
+        };
+        let dir = std::env::temp_dir().join(format!("hub-config-{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        DockerNetwork::write_node_config(
+            dir,
+            &key,
+            &["pub_key2".to_string()],
+            None,
+            "",
+            NodeRole::Hub,
+            "127.0.0.1, 172.17.0.1",
+        );
+
+        let contents = fs::read_to_string(format!("{}/rippled.cfg", dir)).unwrap();
+        assert!(!contents.contains("[validation_seed]"));
+        assert!(!contents.contains("seed1"));
+        assert!(contents.contains(&format!("[peers_max]\n{}", HUB_PEERS_MAX)));
+        assert!(
+            contents.contains("[rpc]\nport = 5005\nip = 0.0.0.0\nadmin = 127.0.0.1, 172.17.0.1\n")
+        );
+        let validators = fs::read_to_string(format!("{}/validators.txt", dir)).unwrap();
+        assert_eq!(validators, "[validators]\npub_key2");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    // Tests the write_node_config function; assert that the published list replaces the UNL of the node,
+    // and that the votes on amendments are appended
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn test_write_validator_list_config() {
+        let key = ValidatorKeyData {
+            status: "success".to_string(),
+            validation_private_key: "priv_key1".to_string(),
+            validation_public_key: "pub_key1".to_string(),
+            validation_seed: "seed1".to_string(),
+        };
+        let trust = ValidatorListTrust {
+            site: "http://172.17.0.5/vl.json".to_string(),
+            publisher_key: "02ABCD".to_string(),
+        };
+        let dir = std::env::temp_dir().join(format!("vl-config-{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        DockerNetwork::write_node_config(
+            dir,
+            &key,
+            &["pub_key2".to_string()],
+            Some(&trust),
+            "\n[veto_amendments]\nABCD AMM\n",
+            NodeRole::Validator,
+            "127.0.0.1",
+        );
+
+        let contents = fs::read_to_string(format!("{}/rippled.cfg", dir)).unwrap();
+        assert!(contents.ends_with("\n[veto_amendments]\nABCD AMM\n"));
+
+        let validators = fs::read_to_string(format!("{}/validators.txt", dir)).unwrap();
+        assert_eq!(
+            validators,
+            "[validator_list_sites]\nhttp://172.17.0.5/vl.json\n\n[validator_list_keys]\n02ABCD"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
     // Note: This test requires running a docker engine in clean state and the controller to be running
     // Tests the initialize_network function; assert that the network is correctly initialized with the correct amount of nodes and names.
     // Also tests the stop_network function
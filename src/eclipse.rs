@@ -0,0 +1,300 @@
+//! This module is responsible for eclipsing a victim node: all its genuine links are cut, while the interceptor keeps
+//! the connections open and feeds the victim a controlled view of the network.
+//!
+//! The view consists of the messages of the visible peers that are let through, optionally limited to some message
+//! types, and of messages injected on behalf of any peer, which may be fabricated. An eclipse is started and ended
+//! through the local configuration, the admin API or the controller, see `follow_controller`.
+
+use crate::interceptor_state::InterceptorState;
+use crate::message_type::MessageType;
+use crate::packet_client::proto::eclipse_command::Kind;
+use crate::packet_client::proto::{self, EclipseCommand};
+use crate::packet_client::PacketClient;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+/// Struct that represents the eclipse of a victim node.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Eclipse {
+    /// The port of the victim node.
+    pub victim_port: u16,
+    /// The ports of the peers that are still connected to the victim, forming the view of the network it is fed.
+    #[serde(default)]
+    pub visible_peers: Vec<u16>,
+    /// The types of the messages of the visible peers that still reach the victim, all types if empty.
+    #[serde(default)]
+    pub visible_types: Vec<MessageType>,
+}
+
+impl Eclipse {
+    /// Returns whether a message on a link is cut off by the eclipse.
+    /// Messages to the victim only pass if they come from a visible peer and have a visible type,
+    /// messages from the victim only pass if they go to a visible peer. Other links are not affected.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer where the message came from.
+    /// * 'to_port' - the port of the peer the message is sent to.
+    /// * 'message_type' - the type of the message.
+    pub fn cuts(&self, from_port: u16, to_port: u16, message_type: MessageType) -> bool {
+        if to_port == self.victim_port {
+            !self.visible_peers.contains(&from_port)
+                || !(self.visible_types.is_empty() || self.visible_types.contains(&message_type))
+        } else if from_port == self.victim_port {
+            !self.visible_peers.contains(&to_port)
+        } else {
+            false
+        }
+    }
+}
+
+impl From<proto::Eclipse> for Eclipse {
+    fn from(eclipse: proto::Eclipse) -> Self {
+        Self {
+            victim_port: eclipse.victim_port as u16,
+            visible_peers: eclipse
+                .visible_peers
+                .iter()
+                .map(|port| *port as u16)
+                .collect(),
+            visible_types: eclipse
+                .visible_types
+                .iter()
+                .map(|value| MessageType::from(*value as u16))
+                .collect(),
+        }
+    }
+}
+
+/// Enum that represents the reasons a message can not be injected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InjectError {
+    /// The interceptor does not write from the first port to the second port.
+    NoLink(u16, u16),
+    /// The message is not a complete, uncompressed peer protocol message.
+    Malformed(String),
+}
+
+impl fmt::Display for InjectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InjectError::NoLink(from_port, to_port) => {
+                write!(f, "Link {}->{} does not exist", from_port, to_port)
+            }
+            InjectError::Malformed(reason) => write!(f, "Malformed message: {}", reason),
+        }
+    }
+}
+
+impl Error for InjectError {}
+
+/// Checks whether data is a single complete, uncompressed peer protocol message, and returns its type.
+///
+/// # Parameters
+/// * 'data' - the message including its 6 byte header.
+pub fn check_injection(data: &[u8]) -> Result<MessageType, InjectError> {
+    let Some(message_type) = MessageType::from_message(data) else {
+        return Err(InjectError::Malformed(
+            "shorter than the 6 byte header".to_string(),
+        ));
+    };
+    if data[0] & 0b1111_1100 != 0 {
+        return Err(InjectError::Malformed(
+            "compressed or unknown header".to_string(),
+        ));
+    }
+    let payload_size = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    if data.len() != 6 + payload_size {
+        return Err(InjectError::Malformed(format!(
+            "header announces a payload of {} bytes, but {} bytes follow",
+            payload_size,
+            data.len() - 6
+        )));
+    }
+    Ok(message_type)
+}
+
+/// Eclipses a node for a fixed period of the run, as configured locally.
+///
+/// # Parameters
+/// * 'eclipse' - the eclipse.
+/// * 'start_after' - how long after the links are started the eclipse starts.
+/// * 'duration' - how long the eclipse lasts, until the end of the run if None.
+/// * 'state' - the runtime state, where the eclipse is set.
+pub async fn run_scheduled(
+    eclipse: Eclipse,
+    start_after: Duration,
+    duration: Option<Duration>,
+    state: Arc<InterceptorState>,
+) {
+    let clock = state.clock();
+    clock.sleep(start_after).await;
+    info!(
+        "Eclipsing node {}, visible peers: {:?}",
+        eclipse.victim_port, eclipse.visible_peers
+    );
+    state.set_eclipse(Some(eclipse));
+    if let Some(duration) = duration {
+        clock.sleep(duration).await;
+        info!("Ending the eclipse");
+        state.set_eclipse(None);
+    }
+}
+
+/// Follows the eclipse commands of the controller until the controller closes the stream:
+/// the controller can start and end an eclipse, and inject messages into any link.
+/// Controllers that do not implement the commands are skipped.
+///
+/// # Parameters
+/// * 'client' - the PacketClient used to subscribe to the commands.
+/// * 'state' - the runtime state, where the eclipse is set and the messages are injected.
+pub async fn follow_controller(client: Arc<Mutex<PacketClient>>, state: Arc<InterceptorState>) {
+    let commands = client.lock().await.subscribe_eclipse().await;
+    let mut commands = match commands {
+        Ok(Some(commands)) => commands,
+        Ok(None) => return,
+        Err(e) => {
+            warn!(
+                "Could not subscribe to the eclipse commands of the controller: {}",
+                e
+            );
+            state
+                .statistics
+                .count_error("eclipse_subscription_failed", 1);
+            return;
+        }
+    };
+    loop {
+        match commands.message().await {
+            Ok(Some(command)) => apply(command, &state).await,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("The eclipse commands of the controller stopped: {}", e);
+                state
+                    .statistics
+                    .count_error("eclipse_subscription_failed", 1);
+                return;
+            }
+        }
+    }
+}
+
+/// Applies a single eclipse command of the controller.
+///
+/// # Parameters
+/// * 'command' - the command.
+/// * 'state' - the runtime state, where the eclipse is set and the messages are injected.
+async fn apply(command: EclipseCommand, state: &InterceptorState) {
+    match command.kind {
+        Some(Kind::Start(eclipse)) => {
+            let eclipse = Eclipse::from(eclipse);
+            info!("The controller eclipsed node {}", eclipse.victim_port);
+            state.set_eclipse(Some(eclipse));
+        }
+        Some(Kind::End(_)) => {
+            info!("The controller ended the eclipse");
+            state.set_eclipse(None);
+        }
+        Some(Kind::Inject(injection)) => {
+            if let Err(e) = state
+                .inject(
+                    injection.from_port as u16,
+                    injection.to_port as u16,
+                    injection.data,
+                )
+                .await
+            {
+                warn!("Could not inject the message of the controller: {}", e);
+                state.statistics.count_error("rejected_eclipse_command", 1);
+            }
+        }
+        None => {
+            warn!("Ignored an unknown eclipse command of the controller");
+            state.statistics.count_error("rejected_eclipse_command", 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::eclipse::{check_injection, run_scheduled, Eclipse, InjectError};
+    use crate::interceptor_state::InterceptorState;
+    use crate::message_type::MessageType;
+    use crate::packet_timeline::PacketTimeline;
+    use crate::ping::Ping;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn eclipse_cuts_genuine_links_of_the_victim() {
+        let eclipse = Eclipse {
+            victim_port: 60000,
+            visible_peers: vec![60001],
+            visible_types: vec![MessageType::Validation, MessageType::ProposeLedger],
+        };
+        assert!(eclipse.cuts(60002, 60000, MessageType::Validation));
+        assert!(!eclipse.cuts(60001, 60000, MessageType::Validation));
+        assert!(eclipse.cuts(60001, 60000, MessageType::Transaction));
+        assert!(eclipse.cuts(60000, 60002, MessageType::Transaction));
+        assert!(!eclipse.cuts(60000, 60001, MessageType::Transaction));
+        assert!(!eclipse.cuts(60001, 60002, MessageType::Transaction));
+
+        let isolated = Eclipse {
+            victim_port: 60000,
+            visible_peers: vec![],
+            visible_types: vec![],
+        };
+        assert!(isolated.cuts(60001, 60000, MessageType::Ping));
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn check_injected_messages() {
+        let ping = Ping::default().to_message();
+        assert_eq!(check_injection(&ping), Ok(MessageType::Ping));
+        assert!(matches!(
+            check_injection(&ping[..ping.len() - 1]),
+            Err(InjectError::Malformed(_))
+        ));
+        assert!(matches!(
+            check_injection(&[0, 0, 0]),
+            Err(InjectError::Malformed(_))
+        ));
+        assert!(matches!(
+            check_injection(&[0x80, 0, 0, 0, 0, 3]),
+            Err(InjectError::Malformed(_))
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn scheduled_eclipse_starts_and_ends_on_time() {
+        let state = Arc::new(InterceptorState::new(Arc::new(PacketTimeline::new(10))));
+        let eclipse = Eclipse {
+            victim_port: 60000,
+            visible_peers: vec![60001],
+            visible_types: vec![],
+        };
+        let scheduled = tokio::spawn(run_scheduled(
+            eclipse.clone(),
+            Duration::from_secs(60),
+            Some(Duration::from_secs(30)),
+            state.clone(),
+        ));
+
+        tokio::time::sleep(Duration::from_millis(59_999)).await;
+        assert_eq!(state.eclipse(), None);
+        tokio::time::sleep(Duration::from_millis(2)).await;
+        assert_eq!(state.eclipse(), Some(eclipse));
+        tokio::time::sleep(Duration::from_millis(29_998)).await;
+        assert!(state.eclipse().is_some());
+        tokio::time::sleep(Duration::from_millis(2)).await;
+        assert_eq!(state.eclipse(), None);
+        scheduled.await.unwrap();
+    }
+}
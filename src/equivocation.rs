@@ -0,0 +1,251 @@
+//! This module is responsible for the equivocation of proposals: a node that sends its proposal to all its peers is made
+//! to propose different things to different peers, e.g. another close time to node 1 than to node 2 and 3.
+//!
+//! Every copy of a proposal the node sends goes through its own link, so the variant of a peer is applied to the copy on
+//! the link towards it. The variant is signed again with the key of the node, such that every peer accepts the proposal
+//! it receives as a valid proposal of the node. The peers relay the variant they received, so the equivocation reaches
+//! the rest of the network through them.
+
+use crate::config::EquivocationRuleConfig;
+use crate::field_mutation;
+use crate::field_mutation::{FieldMutation, MutationError};
+use crate::message_type::MessageType;
+use bytes::Bytes;
+use secp256k1::SecretKey;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+
+/// Struct that represents a variant of the proposals of a node, which is delivered to some of its peers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProposalVariant {
+    /// The ports of the peers the variant is delivered to.
+    pub to_ports: Vec<u16>,
+    /// The mutations that turn a proposal into the variant, applied in order.
+    pub mutations: Vec<FieldMutation>,
+}
+
+/// Struct that represents an equivocation rule: the proposals of a node are delivered as a different variant to every
+/// group of its peers. Peers that are not in any variant receive the proposals as decided.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EquivocationRule {
+    /// The port of the node whose proposals are equivocated.
+    pub from_port: u16,
+    /// The variants, every peer is in at most one of them.
+    pub variants: Vec<ProposalVariant>,
+}
+
+impl EquivocationRule {
+    /// Creates the rule from its configuration. Returns an error if a node does not exist or the rule is not valid.
+    ///
+    /// # Parameters
+    /// * 'config' - the configuration of the rule.
+    /// * 'ports' - the peer ports of the nodes, by node ID.
+    pub fn from_config(
+        config: &EquivocationRuleConfig,
+        ports: &[u16],
+    ) -> Result<Self, EquivocationError> {
+        let port_of = |id: &u32| {
+            ports
+                .get(*id as usize)
+                .copied()
+                .ok_or(EquivocationError::UnknownNode(*id))
+        };
+        let mut variants = Vec::new();
+        for variant in config.variants.iter() {
+            variants.push(ProposalVariant {
+                to_ports: variant
+                    .to_nodes
+                    .iter()
+                    .map(port_of)
+                    .collect::<Result<_, _>>()?,
+                mutations: variant.mutations.clone(),
+            });
+        }
+        let rule = Self {
+            from_port: port_of(&config.node)?,
+            variants,
+        };
+        rule.validate()?;
+        Ok(rule)
+    }
+
+    /// Checks that every variant is delivered to a peer other than the node itself, that no peer is in two variants,
+    /// and that all paths address a field of a proposal.
+    pub fn validate(&self) -> Result<(), EquivocationError> {
+        let mut to_ports = Vec::new();
+        for variant in self.variants.iter() {
+            if variant.to_ports.is_empty() {
+                return Err(EquivocationError::NoPeers);
+            }
+            for to_port in variant.to_ports.iter() {
+                if *to_port == self.from_port || to_ports.contains(to_port) {
+                    return Err(EquivocationError::AmbiguousPeer(*to_port));
+                }
+                to_ports.push(*to_port);
+            }
+            field_mutation::validate_paths(MessageType::ProposeLedger, &variant.mutations)
+                .map_err(EquivocationError::Mutation)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the index and the variant that is delivered on a link, or None if the proposals on the link are not
+    /// equivocated.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer where the message came from.
+    /// * 'to_port' - the port of the peer the message is sent to.
+    pub fn variant(&self, from_port: u16, to_port: u16) -> Option<(usize, &ProposalVariant)> {
+        if from_port != self.from_port {
+            return None;
+        }
+        self.variants
+            .iter()
+            .enumerate()
+            .find(|(_, variant)| variant.to_ports.contains(&to_port))
+    }
+}
+
+/// Turns a proposal into a variant, and signs it again.
+///
+/// # Parameters
+/// * 'message' - the proposal including its 6 byte header.
+/// * 'variant' - the variant.
+/// * 'secret_key' - the key of the node that sends the proposal.
+pub fn equivocate(
+    message: &[u8],
+    variant: &ProposalVariant,
+    secret_key: &SecretKey,
+) -> Result<Bytes, MutationError> {
+    let mutated = field_mutation::apply(message, &variant.mutations)?;
+    field_mutation::resign(&mutated, secret_key)
+}
+
+/// Enum that represents the reasons an equivocation rule is not valid.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EquivocationError {
+    /// A node ID in the configuration does not exist.
+    UnknownNode(u32),
+    /// A variant that is not delivered to any peer.
+    NoPeers,
+    /// A peer that is in two variants, or the node itself.
+    AmbiguousPeer(u16),
+    /// A mutation that does not address a field of a proposal.
+    Mutation(MutationError),
+}
+
+impl fmt::Display for EquivocationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EquivocationError::UnknownNode(id) => write!(f, "node {} does not exist", id),
+            EquivocationError::NoPeers => write!(f, "a variant is not delivered to any peer"),
+            EquivocationError::AmbiguousPeer(port) => write!(
+                f,
+                "peer {} is in two variants or is the equivocating node",
+                port
+            ),
+            EquivocationError::Mutation(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for EquivocationError {}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::config::{EquivocationRuleConfig, ProposalVariantConfig};
+    use crate::equivocation::{equivocate, EquivocationError, EquivocationRule, ProposalVariant};
+    use crate::field_mutation::{frame, FieldMutation, FieldOperation};
+    use crate::message_type::MessageType;
+    use crate::signature;
+    use prost::encoding::encode_varint;
+    use secp256k1::{PublicKey, Secp256k1, SecretKey};
+    use std::collections::HashSet;
+
+    fn add_close_time(delta: i64) -> Vec<FieldMutation> {
+        vec![FieldMutation {
+            path: "closeTime".to_string(),
+            operation: FieldOperation::Add { delta },
+        }]
+    }
+
+    fn proposal(public_key: &[u8]) -> Vec<u8> {
+        let mut payload = Vec::new();
+        encode_varint(1 << 3, &mut payload);
+        encode_varint(3, &mut payload);
+        payload.extend_from_slice(&[0x12, 32]);
+        payload.extend_from_slice(&[0xAA; 32]);
+        payload.extend_from_slice(&[0x1A, public_key.len() as u8]);
+        payload.extend_from_slice(public_key);
+        encode_varint(4 << 3, &mut payload);
+        encode_varint(1000, &mut payload);
+        payload.extend_from_slice(&[0x2A, 2, 0xDE, 0xAD]);
+        payload.extend_from_slice(&[0x32, 32]);
+        payload.extend_from_slice(&[0xBB; 32]);
+        frame(MessageType::ProposeLedger, &payload).to_vec()
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn rule_from_config() {
+        let config = |to_nodes: Vec<Vec<u32>>| EquivocationRuleConfig {
+            node: 0,
+            variants: to_nodes
+                .into_iter()
+                .map(|to_nodes| ProposalVariantConfig {
+                    to_nodes,
+                    mutations: add_close_time(10),
+                })
+                .collect(),
+        };
+        let ports = [60000, 60001, 60002];
+        let rule = EquivocationRule::from_config(&config(vec![vec![1], vec![2]]), &ports).unwrap();
+        assert_eq!(rule.from_port, 60000);
+        assert_eq!(rule.variant(60000, 60002).unwrap().0, 1);
+        assert_eq!(rule.variant(60001, 60002), None);
+        assert_eq!(
+            EquivocationRule::from_config(&config(vec![vec![1], vec![1, 2]]), &ports),
+            Err(EquivocationError::AmbiguousPeer(60001))
+        );
+        assert_eq!(
+            EquivocationRule::from_config(&config(vec![vec![0]]), &ports),
+            Err(EquivocationError::AmbiguousPeer(60000))
+        );
+        assert_eq!(
+            EquivocationRule::from_config(&config(vec![vec![]]), &ports),
+            Err(EquivocationError::NoPeers)
+        );
+        assert_eq!(
+            EquivocationRule::from_config(&config(vec![vec![3]]), &ports),
+            Err(EquivocationError::UnknownNode(3))
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn variants_are_signed_again() {
+        let secp256k1 = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let public_key = PublicKey::from_secret_key(&secp256k1, &secret_key)
+            .serialize()
+            .to_vec();
+        let message = proposal(&public_key);
+        let variant = |delta| ProposalVariant {
+            to_ports: vec![60001],
+            mutations: add_close_time(delta),
+        };
+        let early = equivocate(&message, &variant(-5), &secret_key).unwrap();
+        let late = equivocate(&message, &variant(5), &secret_key).unwrap();
+        assert_ne!(early, late);
+        let known_keys = HashSet::from([public_key]);
+        assert_eq!(
+            signature::verify(&early, |key| known_keys.contains(key)),
+            Some(true)
+        );
+        assert_eq!(
+            signature::verify(&late, |key| known_keys.contains(key)),
+            Some(true)
+        );
+    }
+}
@@ -0,0 +1,410 @@
+//! This module is responsible for broadcasting structured events about the state of the links,
+//! such that external dashboards and test harnesses can react to the interceptor in real time.
+//!
+//! Events are published on an in-process bus and can be streamed as JSON over a WebSocket endpoint.
+
+use crate::breakpoint::BreakpointHit;
+use crate::message_type::MessageType;
+use futures_util::SinkExt;
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use tracing::{debug, error, info, warn};
+
+/// The amount of events that are buffered for a subscriber that is behind, before it misses events.
+pub const DEFAULT_EVENT_BUS_CAPACITY: usize = 1024;
+
+/// Enum that represents what happened in an event.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum EventKind {
+    /// The interceptor started handling the messages of a link.
+    LinkConnected {
+        from_port: u16,
+        to_port: u16,
+        /// The XRPL protocol version negotiated with the node the link reads from, if known.
+        protocol: Option<String>,
+    },
+    /// A link stopped, because its connection was closed or failed.
+    LinkDropped {
+        from_port: u16,
+        to_port: u16,
+        reason: String,
+    },
+    /// Nothing was read from a link for longer than the idle timeout.
+    LinkIdle {
+        from_port: u16,
+        to_port: u16,
+        idle_ms: u64,
+    },
+    /// A message was not forwarded.
+    PacketDropped {
+        from_port: u16,
+        to_port: u16,
+        message_type: String,
+        /// The position of the message on its link, if the message was read from the link.
+        sequence: Option<u64>,
+        /// Why the message was dropped, e.g. 'controller' or 'unsupported_by_protocol'.
+        reason: String,
+    },
+    /// A frame that violates the peer protocol was read from a link, and was not forwarded.
+    MalformedFrame {
+        from_port: u16,
+        to_port: u16,
+        /// How the frame violates the protocol: 'unknown_version', 'compressed' or 'too_large'.
+        reason: String,
+        /// The amount of bytes of the frame that were read when it was detected.
+        size: usize,
+        /// The file the bytes were stored in, if they could be stored.
+        path: Option<String>,
+        /// Whether nothing is read from the link anymore.
+        link_killed: bool,
+    },
+    /// The controller replaced the contents of a message.
+    MutationApplied {
+        from_port: u16,
+        to_port: u16,
+        message_type: String,
+        sequence: u64,
+        original_size: usize,
+        mutated_size: usize,
+    },
+    /// A link halted before handling a message, because it matched a breakpoint or the link was stepped.
+    BreakpointHit(BreakpointHit),
+    /// A halted link was continued or stepped.
+    LinkResumed {
+        from_port: u16,
+        to_port: u16,
+        /// Whether the link halts again at its next message.
+        step: bool,
+    },
+    /// The partitions of the network changed. Every partition lists the IDs of the nodes it contains.
+    PartitionChanged { partitions: Vec<Vec<u32>> },
+    /// A node was eclipsed, or the eclipse ended if there is no victim.
+    EclipseChanged {
+        victim_port: Option<u16>,
+        /// The peers that are still connected to the victim.
+        visible_peers: Vec<u16>,
+    },
+    /// Links were cut in one direction only, or healed if no links are cut.
+    OneWayPartitionChanged {
+        /// The links whose messages are dropped, by the ports of the peer the messages come from and go to.
+        cut_links: Vec<(u16, u16)>,
+    },
+    /// The links on which the relay of transactions is suppressed changed, no links if the suppression ended.
+    TxSuppressionChanged {
+        /// The links whose transactions are dropped, by the ports of the peer the messages come from and go to.
+        links: Vec<(u16, u16)>,
+    },
+    /// All messages of a type that a node sends started or stopped being dropped.
+    BlackholeChanged {
+        from_port: u16,
+        message_type: String,
+        /// Whether the messages are dropped from now on.
+        enabled: bool,
+    },
+    /// A message that was not read from a link was written to it.
+    MessageInjected {
+        from_port: u16,
+        to_port: u16,
+        message_type: String,
+        size: usize,
+    },
+    /// A node asked a peer to stop or resume relaying the proposals and validations of a validator.
+    SquelchSent {
+        from_port: u16,
+        to_port: u16,
+        /// The public key of the validator in hex.
+        validator_key: String,
+        /// Whether the relaying stops, or resumes if false.
+        squelch: bool,
+        duration_secs: Option<u32>,
+    },
+    /// A proposal of a node was replaced by the variant that an equivocation rule delivers to the peer.
+    ProposalEquivocated {
+        from_port: u16,
+        to_port: u16,
+        sequence: u64,
+        /// The index of the variant in the rule.
+        variant: usize,
+    },
+    /// A newer manifest of a master key was seen or announced, which delegates to another signing key or revokes it.
+    ManifestChanged {
+        /// The port of the peer the manifest came from, or of the node it was announced for.
+        from_port: u16,
+        /// The master key in hex.
+        master_key: String,
+        sequence: u32,
+        /// The signing key in hex, empty for a revocation.
+        signing_key: String,
+    },
+    /// The run entered a phase, after which messages are intercepted or passed through untouched.
+    PhaseChanged {
+        /// The phase: 'warm_up', 'fault' or 'cool_down'.
+        phase: String,
+    },
+    /// The circuit breaker of the controller channel opened, closed or became half-open.
+    CircuitBreakerChanged {
+        /// The new state: 'closed', 'open' or 'half_open'.
+        state: String,
+        reason: String,
+    },
+    /// A controller became unreachable, and the links pinned to it were moved to the other controllers.
+    ControllerFailover {
+        /// The address of the unreachable controller.
+        address: String,
+        /// The amount of links that were pinned to it.
+        links: usize,
+        reason: String,
+    },
+    /// The local rules were reloaded from the changed file they are read from.
+    RulesReloaded {
+        path: String,
+        /// The amount of blackholes from now on.
+        blackholes: usize,
+        /// The amount of mutation rules from now on.
+        mutation_rules: usize,
+    },
+    /// The CPU limit of the container of a node changed, because it was throttled or restored.
+    CpuLimitChanged {
+        node_id: u32,
+        /// The amount of CPUs the node can use from now on, unlimited if None.
+        cpus: Option<f64>,
+    },
+    /// The status of an amendment at a node changed, e.g. it got more votes, a majority or was enabled.
+    AmendmentVoting {
+        node_id: u32,
+        /// The ID of the amendment in hex.
+        amendment: String,
+        name: String,
+        enabled: bool,
+        /// Whether the node votes against the amendment.
+        vetoed: bool,
+        /// The amount of trusted validators that voted for the amendment, if it is not enabled.
+        count: Option<u64>,
+        /// The amount of votes the amendment needs for a majority, if it is not enabled.
+        threshold: Option<u64>,
+        /// Since when the amendment has a majority, in seconds since the Ripple epoch, if it has one.
+        majority: Option<u64>,
+    },
+    /// A node closed a ledger, as reported by the ledger stream of its public WebSocket port.
+    LedgerClosed {
+        node_id: u32,
+        ledger_index: u32,
+        /// The hash of the closed ledger in hex.
+        ledger_hash: String,
+        /// The amount of transactions in the ledger.
+        txn_count: u64,
+    },
+    /// An anomaly was detected in the traffic, e.g. a node that went silent.
+    Anomaly {
+        /// The kind of anomaly: 'silence', 'validation_burst', 'repeated_message' or 'mutation_rejected'.
+        anomaly: String,
+        /// The node, or the node the messages of the link come from.
+        from_port: u16,
+        /// The node the messages of the link go to, None if the anomaly is about a node.
+        to_port: Option<u16>,
+        detail: String,
+    },
+}
+
+impl EventKind {
+    /// Initializes a PacketDropped event.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer where the message came from.
+    /// * 'to_port' - the port of the peer the message was sent to.
+    /// * 'message_type' - the type of the message.
+    /// * 'sequence' - the position of the message on its link, if known.
+    /// * 'reason' - why the message was dropped.
+    pub fn packet_dropped(
+        from_port: u16,
+        to_port: u16,
+        message_type: MessageType,
+        sequence: Option<u64>,
+        reason: &str,
+    ) -> Self {
+        Self::PacketDropped {
+            from_port,
+            to_port,
+            message_type: message_type.to_string(),
+            sequence,
+            reason: reason.to_string(),
+        }
+    }
+}
+
+/// Struct that represents an event, together with the moment it happened.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Event {
+    /// The wall-clock time of the event, in nanoseconds since the UNIX epoch.
+    pub timestamp_ns: u64,
+    /// What happened.
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+/// Struct that represents the bus on which events are published to all subscribers.
+#[derive(Debug)]
+pub struct EventBus {
+    /// The sending side of the broadcast channel.
+    sender: broadcast::Sender<Arc<Event>>,
+}
+
+impl EventBus {
+    /// Initializes a new EventBus without subscribers.
+    ///
+    /// # Parameters
+    /// * 'capacity' - the amount of events buffered for a subscriber that is behind.
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    /// Publishes an event to all current subscribers. Events published without subscribers are discarded.
+    ///
+    /// # Parameters
+    /// * 'kind' - what happened.
+    pub fn emit(&self, kind: EventKind) {
+        let event = Event {
+            timestamp_ns: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos() as u64,
+            kind,
+        };
+        debug!("Event: {:?}", event);
+        let _ = self.sender.send(Arc::new(event));
+    }
+
+    /// Returns a receiver of all events published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Event>> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_EVENT_BUS_CAPACITY)
+    }
+}
+
+/// Accepts WebSocket connections and streams every event to them as a JSON text message.
+/// Runs until the listener fails.
+///
+/// # Parameters
+/// * 'listener' - the bound listener of the WebSocket endpoint.
+/// * 'bus' - the event bus whose events are streamed.
+pub async fn serve_websocket(listener: TcpListener, bus: Arc<EventBus>) {
+    info!(
+        "Streaming events over WebSocket on {:?}",
+        listener.local_addr()
+    );
+    loop {
+        match listener.accept().await {
+            Ok((stream, address)) => {
+                tokio::spawn(stream_events(stream, address, bus.subscribe()));
+            }
+            Err(e) => {
+                error!("Could not accept WebSocket connection: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+/// Streams events to a single WebSocket client until it disconnects.
+///
+/// # Parameters
+/// * 'stream' - the accepted TCP stream.
+/// * 'address' - the address of the client.
+/// * 'events' - the receiver of the events.
+async fn stream_events(
+    stream: TcpStream,
+    address: SocketAddr,
+    mut events: broadcast::Receiver<Arc<Event>>,
+) {
+    let mut websocket = match tokio_tungstenite::accept_async(stream).await {
+        Ok(websocket) => websocket,
+        Err(e) => {
+            warn!("WebSocket handshake with {} failed: {}", address, e);
+            return;
+        }
+    };
+    debug!("WebSocket client {} connected", address);
+
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(missed)) => {
+                warn!("WebSocket client {} missed {} events", address, missed);
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        let json = serde_json::to_string(event.as_ref()).expect("Events can always be serialized");
+        if websocket.send(WsMessage::Text(json)).await.is_err() {
+            debug!("WebSocket client {} disconnected", address);
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::event_bus::{Event, EventBus, EventKind};
+    use crate::message_type::MessageType;
+    use serde_json::json;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn event_json() {
+        let event = Event {
+            timestamp_ns: 5,
+            kind: EventKind::packet_dropped(
+                60000,
+                60001,
+                MessageType::Validation,
+                Some(3),
+                "controller",
+            ),
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            json!({
+                "timestamp_ns": 5,
+                "event": "packet_dropped",
+                "from_port": 60000,
+                "to_port": 60001,
+                "message_type": "mtVALIDATION",
+                "sequence": 3,
+                "reason": "controller"
+            })
+        );
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn subscribers_receive_events() {
+        let bus = EventBus::new(4);
+        // Events without subscribers are discarded
+        bus.emit(EventKind::PartitionChanged { partitions: vec![] });
+
+        let mut events = bus.subscribe();
+        bus.emit(EventKind::PartitionChanged {
+            partitions: vec![vec![0, 1]],
+        });
+        assert_eq!(
+            events.recv().await.unwrap().kind,
+            EventKind::PartitionChanged {
+                partitions: vec![vec![0, 1]]
+            }
+        );
+        assert!(events.try_recv().is_err());
+    }
+}
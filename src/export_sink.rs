@@ -0,0 +1,238 @@
+//! This module is responsible for exporting the metadata and decision of every handled message to a file,
+//! as JSON lines or CSV rows, such that experiments can be analysed afterwards with e.g. pandas.
+
+use crate::config::ExportFormat;
+use crate::packet_timeline::PacketRecord;
+use crate::record_sink::RecordSink;
+use crate::run_id::RunId;
+use serde::Serialize;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Struct that represents a single exported message. The fields are the columns of the CSV export.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExportRow {
+    /// The moment the message was read, in RFC 3339 format.
+    pub timestamp: String,
+    /// The moment the message was read, in nanoseconds since the UNIX epoch.
+    pub timestamp_ns: i64,
+    pub from_port: u16,
+    pub to_port: u16,
+    /// The direction of the message, e.g. '60000->60001'.
+    pub direction: String,
+    /// The position of the message on its link.
+    pub sequence: u64,
+    /// The name of the message type used by rippled, e.g. 'mtVALIDATION'.
+    pub message_type: String,
+    /// The numeric value of the message type.
+    pub message_type_id: u16,
+    pub ledger_sequence: Option<u32>,
+    /// The action taken: 'drop', 'duplicate', 'delay' or 'forward'.
+    pub action: &'static str,
+    pub delay_ms: u32,
+    pub send_amount: u32,
+    /// The size of the message as it was read, including the header.
+    pub size: usize,
+    /// The size of the message as it was sent, including the header.
+    pub sent_size: usize,
+    pub mutated: bool,
+    pub controller_latency_us: Option<u64>,
+    pub latency_us: u64,
+    pub hash: String,
+    /// The phase of the run the message was handled in: 'warm_up', 'fault' or 'cool_down'.
+    pub phase: &'static str,
+}
+
+impl From<&PacketRecord> for ExportRow {
+    fn from(record: &PacketRecord) -> Self {
+        Self {
+            timestamp: record.timestamp.to_rfc3339(),
+            timestamp_ns: record.timestamp.timestamp_nanos_opt().unwrap_or(0),
+            from_port: record.from_port,
+            to_port: record.to_port,
+            direction: format!("{}->{}", record.from_port, record.to_port),
+            sequence: record.sequence,
+            message_type: record.message_type.to_string(),
+            message_type_id: record.message_type.value(),
+            ledger_sequence: record.ledger_sequence,
+            action: record.action_name(),
+            delay_ms: record.action,
+            send_amount: record.send_amount,
+            size: record.size,
+            sent_size: record.sent_size,
+            mutated: record.sent_size != record.size,
+            controller_latency_us: record
+                .controller_latency
+                .map(|latency| latency.as_micros() as u64),
+            latency_us: record.latency.as_micros() as u64,
+            hash: record.hash.clone(),
+            phase: record.phase.name(),
+        }
+    }
+}
+
+/// Enum that represents the writer of the export file in the chosen format.
+enum ExportWriter {
+    Jsonl(BufWriter<File>),
+    Csv(csv::Writer<File>),
+}
+
+/// Struct that represents the sink exporting handled messages to a file.
+pub struct ExportSink {
+    /// The path of the export file.
+    path: PathBuf,
+    /// The writer of the export file.
+    writer: ExportWriter,
+}
+
+impl ExportSink {
+    /// Creates the export file of a new run in the given directory, named after the run ID.
+    ///
+    /// # Parameters
+    /// * 'directory' - the directory the export files of all runs are stored in.
+    /// * 'format' - the format of the export file.
+    /// * 'run_id' - the identifier of the run.
+    pub fn create(
+        directory: &str,
+        format: ExportFormat,
+        run_id: &RunId,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        fs::create_dir_all(directory)?;
+        let extension = match format {
+            ExportFormat::Jsonl => "jsonl",
+            ExportFormat::Csv => "csv",
+        };
+        let path = Path::new(directory).join(format!("run-{}.{}", run_id, extension));
+        Self::open(&path, format)
+    }
+
+    /// Creates or truncates an export file.
+    ///
+    /// # Parameters
+    /// * 'path' - the path of the export file.
+    /// * 'format' - the format of the export file.
+    pub fn open(path: &Path, format: ExportFormat) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let file = File::create(path)?;
+        let writer = match format {
+            ExportFormat::Jsonl => ExportWriter::Jsonl(BufWriter::new(file)),
+            ExportFormat::Csv => ExportWriter::Csv(csv::Writer::from_writer(file)),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer,
+        })
+    }
+}
+
+impl RecordSink for ExportSink {
+    fn name(&self) -> String {
+        format!("export file {}", self.path.display())
+    }
+
+    fn write(&mut self, records: &[PacketRecord]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for record in records {
+            let row = ExportRow::from(record);
+            match &mut self.writer {
+                ExportWriter::Jsonl(writer) => {
+                    serde_json::to_writer(&mut *writer, &row)?;
+                    writer.write_all(b"\n")?;
+                }
+                ExportWriter::Csv(writer) => writer.serialize(&row)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match &mut self.writer {
+            ExportWriter::Jsonl(writer) => writer.flush()?,
+            ExportWriter::Csv(writer) => writer.flush()?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::config::ExportFormat;
+    use crate::export_sink::{ExportRow, ExportSink};
+    use crate::packet_timeline::PacketRecord;
+    use crate::phase::Phase;
+    use crate::record_sink::RecordSink;
+    use std::fs;
+    use std::time::Duration;
+
+    fn record(send_amount: u32, action: u32, sent_size: usize) -> PacketRecord {
+        PacketRecord {
+            sequence: 4,
+            ledger_sequence: Some(12),
+            size: 100,
+            hash: "ab".to_string(),
+            sent_size,
+            action,
+            send_amount,
+            controller_latency: Some(Duration::from_micros(300)),
+            latency: Duration::from_millis(2),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn row_describes_action() {
+        assert_eq!(ExportRow::from(&record(0, 0, 100)).action, "drop");
+        assert_eq!(ExportRow::from(&record(1, 0, 100)).action, "forward");
+        assert_eq!(ExportRow::from(&record(1, 50, 100)).action, "delay");
+        assert_eq!(ExportRow::from(&record(3, 0, 100)).action, "duplicate");
+
+        let row = ExportRow::from(&record(1, 0, 90));
+        assert!(row.mutated);
+        assert_eq!(row.direction, "60000->60001");
+        assert_eq!(row.message_type, "mtVALIDATION");
+        assert_eq!(row.message_type_id, 41);
+        assert_eq!(row.controller_latency_us, Some(300));
+        assert_eq!(row.phase, "fault");
+
+        let mut warm_up = record(1, 0, 100);
+        warm_up.phase = Phase::WarmUp;
+        assert_eq!(ExportRow::from(&warm_up).phase, "warm_up");
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn export_jsonl_and_csv() {
+        let directory = std::env::temp_dir().join("rocket_export_sink_test");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let records = vec![record(1, 0, 100), record(0, 0, 100)];
+
+        let jsonl_path = directory.join("export.jsonl");
+        let mut sink = ExportSink::open(&jsonl_path, ExportFormat::Jsonl).unwrap();
+        sink.write(&records).unwrap();
+        sink.close().unwrap();
+        let lines: Vec<serde_json::Value> = fs::read_to_string(&jsonl_path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["action"], "drop");
+        assert_eq!(lines[1]["ledger_sequence"], 12);
+
+        let csv_path = directory.join("export.csv");
+        let mut sink = ExportSink::open(&csv_path, ExportFormat::Csv).unwrap();
+        sink.write(&records).unwrap();
+        sink.close().unwrap();
+        let csv = fs::read_to_string(&csv_path).unwrap();
+        let mut lines = csv.lines();
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("timestamp,timestamp_ns,from_port,to_port,direction"));
+        assert_eq!(lines.count(), 2);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,579 @@
+//! This module is responsible for field-level mutations of peer messages: fields of the protobuf payload are set,
+//! changed or cleared by path, e.g. `TMProposeSet.closeTime` is increased by 5 or `TMValidation.validation` is
+//! truncated, after which the message is framed again and optionally signed again by its sender.
+//!
+//! The payload is changed on the protobuf wire format, so all fields that are not mutated are kept byte for byte.
+//! Fields are named as in rippled's `ripple.proto` for the consensus messages, and can be addressed by their field
+//! number in all messages, also inside nested messages.
+
+use crate::keygen;
+use crate::message_type::MessageType;
+use crate::packet_client::proto;
+use crate::packet_client::proto::field_mutation::Operation;
+use crate::wire_format::{
+    decode_fields, encode_fields, WireField, WireValue, PROPOSE_FIELD_CLOSE_TIME,
+    PROPOSE_FIELD_CURRENT_TX_HASH, PROPOSE_FIELD_NODE_PUB_KEY, PROPOSE_FIELD_PREVIOUS_LEDGER,
+    PROPOSE_FIELD_SEQ, PROPOSE_FIELD_SIGNATURE, STATUS_CHANGE_FIELD_LEDGER_SEQ,
+    VALIDATION_FIELD_VALIDATION,
+};
+use bytes::{BufMut, Bytes, BytesMut};
+use secp256k1::SecretKey;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha512};
+use std::error::Error;
+use std::fmt;
+
+/// The prefix of the hash that is signed in a proposal, `HashPrefix::proposal` in rippled.
+const PROPOSAL_HASH_PREFIX: [u8; 4] = *b"PRP\0";
+
+/// Struct that represents a mutation of a single field of a message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldMutation {
+    /// The path of the field, e.g. 'closeTime', 'TMProposeSet.closeTime' or '1.2' for field 2 of the message in
+    /// field 1. Only the first segment can be a name.
+    pub path: String,
+    /// How the field is changed.
+    #[serde(flatten)]
+    pub operation: FieldOperation,
+}
+
+/// Enum that represents how a field is changed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum FieldOperation {
+    /// Sets a varint field, which is added if the message does not contain it.
+    Set { value: u64 },
+    /// Sets a length-delimited field to the hex encoded value, which is added if the message does not contain it.
+    SetBytes {
+        #[serde(with = "hex::serde")]
+        value: Vec<u8>,
+    },
+    /// Adds to a varint field.
+    Add { delta: i64 },
+    /// Removes all occurrences of a field.
+    Clear,
+    /// Truncates a length-delimited field, fields that are already shorter are kept.
+    Truncate { length: usize },
+}
+
+/// Enum that represents the reasons a message can not be mutated.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MutationError {
+    /// The message or one of its nested messages is not a valid protobuf message, or its header does not match it.
+    Malformed,
+    /// A path that does not address a field of the message type.
+    UnknownField(MessageType, String),
+    /// A field that is missing, or that does not have the wire type the operation works on.
+    WrongField(String),
+    /// An addition that makes a varint field negative or too large.
+    OutOfRange(String),
+    /// A mutation of the controller without an operation.
+    NoOperation(String),
+    /// A message of a type the interceptor can not sign.
+    NotResignable(MessageType),
+    /// A message of a node whose key the interceptor does not know.
+    NoSigningKey(u16),
+}
+
+impl fmt::Display for MutationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MutationError::Malformed => write!(f, "the message is not a valid protobuf message"),
+            MutationError::UnknownField(message_type, path) => {
+                write!(f, "{} has no field '{}'", message_type, path)
+            }
+            MutationError::WrongField(path) => write!(
+                f,
+                "field '{}' is missing or does not support the operation",
+                path
+            ),
+            MutationError::OutOfRange(path) => write!(f, "field '{}' is out of range", path),
+            MutationError::NoOperation(path) => {
+                write!(f, "mutation of field '{}' without operation", path)
+            }
+            MutationError::NotResignable(message_type) => {
+                write!(f, "{} messages can not be signed again", message_type)
+            }
+            MutationError::NoSigningKey(port) => {
+                write!(f, "the key of node {} is not known", port)
+            }
+        }
+    }
+}
+
+impl Error for MutationError {}
+
+impl TryFrom<&proto::FieldMutation> for FieldMutation {
+    type Error = MutationError;
+
+    fn try_from(mutation: &proto::FieldMutation) -> Result<Self, Self::Error> {
+        let operation = match &mutation.operation {
+            None => return Err(MutationError::NoOperation(mutation.path.clone())),
+            Some(Operation::Set(value)) => FieldOperation::Set { value: *value },
+            Some(Operation::SetBytes(value)) => FieldOperation::SetBytes {
+                value: value.to_vec(),
+            },
+            Some(Operation::Add(delta)) => FieldOperation::Add { delta: *delta },
+            Some(Operation::Clear(_)) => FieldOperation::Clear,
+            Some(Operation::Truncate(length)) => FieldOperation::Truncate {
+                length: *length as usize,
+            },
+        };
+        Ok(Self {
+            path: mutation.path.clone(),
+            operation,
+        })
+    }
+}
+
+/// Struct that represents a local mutation rule: the messages of a type on the matching links are mutated before they
+/// are sent, on top of the decision of the controller.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MutationRule {
+    /// The type of the mutated messages.
+    pub message_type: MessageType,
+    /// The port of the peer the mutated messages come from, all peers if not set.
+    #[serde(default)]
+    pub from_port: Option<u16>,
+    /// The port of the peer the mutated messages go to, all peers if not set.
+    #[serde(default)]
+    pub to_port: Option<u16>,
+    /// The mutations, applied in order.
+    pub mutations: Vec<FieldMutation>,
+    /// Whether the mutated messages are signed again with the key of the node they come from.
+    #[serde(default)]
+    pub resign: bool,
+}
+
+impl MutationRule {
+    /// Returns whether the rule mutates a message.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer where the message came from.
+    /// * 'to_port' - the port of the peer the message is sent to.
+    /// * 'message_type' - the type of the message.
+    pub fn matches(&self, from_port: u16, to_port: u16, message_type: MessageType) -> bool {
+        self.message_type == message_type
+            && self.from_port.map_or(true, |port| port == from_port)
+            && self.to_port.map_or(true, |port| port == to_port)
+    }
+
+    /// Checks that all paths of the rule address a field of its message type.
+    pub fn validate(&self) -> Result<(), MutationError> {
+        validate_paths(self.message_type, &self.mutations)
+    }
+}
+
+/// Checks that the paths of mutations address a field of a message type.
+///
+/// # Parameters
+/// * 'message_type' - the type of the mutated messages.
+/// * 'mutations' - the mutations.
+pub fn validate_paths(
+    message_type: MessageType,
+    mutations: &[FieldMutation],
+) -> Result<(), MutationError> {
+    for mutation in mutations.iter() {
+        resolve_path(message_type, &mutation.path)?;
+    }
+    Ok(())
+}
+
+/// Applies mutations to a message and frames it again. The mutations are applied in order.
+///
+/// # Parameters
+/// * 'message' - the message including its 6 byte header.
+/// * 'mutations' - the mutations.
+pub fn apply(message: &[u8], mutations: &[FieldMutation]) -> Result<Bytes, MutationError> {
+    let (message_type, mut payload) = split(message)?;
+    for mutation in mutations {
+        let path = resolve_path(message_type, &mutation.path)?;
+        payload = mutate_payload(&payload, &path, &mutation.operation, &mutation.path)?;
+    }
+    Ok(frame(message_type, &payload))
+}
+
+/// Signs a message again with the key of its sender, which is needed after its signed fields were mutated.
+/// Only mtPROPOSE_LEDGER messages can be signed again.
+///
+/// # Parameters
+/// * 'message' - the message including its 6 byte header.
+/// * 'secret_key' - the secret key of the node that sent the message.
+pub fn resign(message: &[u8], secret_key: &SecretKey) -> Result<Bytes, MutationError> {
+    let (message_type, payload) = split(message)?;
+    if message_type != MessageType::ProposeLedger {
+        return Err(MutationError::NotResignable(message_type));
+    }
+    let fields = decode_fields(&payload).ok_or(MutationError::Malformed)?;
+    let signing_hash = proposal_signing_hash(&fields).ok_or(MutationError::Malformed)?;
+    let signature = keygen::sign_digest(&secret_key.secret_bytes(), signing_hash);
+
+    let payload = mutate_payload(
+        &payload,
+        &[PROPOSE_FIELD_SIGNATURE],
+        &FieldOperation::SetBytes { value: signature },
+        "signature",
+    )?;
+    Ok(frame(message_type, &payload))
+}
+
+/// Returns the hash that is signed in a proposal, see `ConsensusProposal::signingHash` in rippled, or None if the
+/// proposal misses one of the signed fields.
+///
+/// # Parameters
+/// * 'fields' - the fields of the `TMProposeSet` payload.
+pub fn proposal_signing_hash(fields: &[WireField]) -> Option<[u8; 32]> {
+    let varint = |number| {
+        fields.iter().find_map(|field| match field.value {
+            WireValue::Varint(value) if field.number == number => Some(value as u32),
+            _ => None,
+        })
+    };
+    let bytes = |number| {
+        fields.iter().find_map(|field| match &field.value {
+            WireValue::LengthDelimited(value) if field.number == number => Some(value.as_slice()),
+            _ => None,
+        })
+    };
+    let mut hasher = Sha512::new();
+    hasher.update(PROPOSAL_HASH_PREFIX);
+    hasher.update(varint(PROPOSE_FIELD_SEQ)?.to_be_bytes());
+    hasher.update(varint(PROPOSE_FIELD_CLOSE_TIME)?.to_be_bytes());
+    hasher.update(bytes(PROPOSE_FIELD_PREVIOUS_LEDGER)?);
+    hasher.update(bytes(PROPOSE_FIELD_CURRENT_TX_HASH)?);
+    hasher.finalize()[..32].try_into().ok()
+}
+
+/// Splits a message into its type and payload.
+///
+/// # Parameters
+/// * 'message' - the message including its 6 byte header.
+fn split(message: &[u8]) -> Result<(MessageType, Vec<u8>), MutationError> {
+    let message_type = MessageType::from_message(message).ok_or(MutationError::Malformed)?;
+    let payload_size = u32::from_be_bytes(message[0..4].try_into().unwrap()) as usize;
+    if message.len() != 6 + payload_size {
+        return Err(MutationError::Malformed);
+    }
+    Ok((message_type, message[6..].to_vec()))
+}
+
+/// Frames a payload, by prepending the 6 byte header.
+///
+/// # Parameters
+/// * 'message_type' - the type of the message.
+/// * 'payload' - the payload.
+pub fn frame(message_type: MessageType, payload: &[u8]) -> Bytes {
+    let mut message = BytesMut::with_capacity(6 + payload.len());
+    message.put_u32(payload.len() as u32);
+    message.put_u16(message_type.value());
+    message.put_slice(payload);
+    message.freeze()
+}
+
+/// Returns the field numbers of a path.
+///
+/// # Parameters
+/// * 'message_type' - the type of the message.
+/// * 'path' - the path, optionally starting with the name of the payload, e.g. 'TMProposeSet.closeTime'.
+fn resolve_path(message_type: MessageType, path: &str) -> Result<Vec<u64>, MutationError> {
+    let unknown = || MutationError::UnknownField(message_type, path.to_string());
+    let mut segments = path.split('.').peekable();
+    if payload_name(message_type).is_some_and(|name| segments.peek() == Some(&name)) {
+        segments.next();
+    }
+    let numbers = segments
+        .enumerate()
+        .map(|(i, segment)| {
+            segment
+                .parse::<u64>()
+                .ok()
+                .filter(|number| *number > 0)
+                .or_else(|| match i {
+                    0 => field_number(message_type, segment),
+                    _ => None,
+                })
+                .ok_or_else(unknown)
+        })
+        .collect::<Result<Vec<u64>, MutationError>>()?;
+    if numbers.is_empty() {
+        return Err(unknown());
+    }
+    Ok(numbers)
+}
+
+/// Returns the name of the payload of a message type in rippled's `ripple.proto`, for the message types whose fields
+/// can be named.
+///
+/// # Parameters
+/// * 'message_type' - the type of the message.
+fn payload_name(message_type: MessageType) -> Option<&'static str> {
+    match message_type {
+        MessageType::Ping => Some("TMPing"),
+        MessageType::Transaction => Some("TMTransaction"),
+        MessageType::ProposeLedger => Some("TMProposeSet"),
+        MessageType::StatusChange => Some("TMStatusChange"),
+        MessageType::HaveSet => Some("TMHaveTransactionSet"),
+        MessageType::Validation => Some("TMValidation"),
+        _ => None,
+    }
+}
+
+/// Returns the number of a named field of the payload of a message type.
+///
+/// # Parameters
+/// * 'message_type' - the type of the message.
+/// * 'name' - the name of the field, as in rippled's `ripple.proto`.
+fn field_number(message_type: MessageType, name: &str) -> Option<u64> {
+    let fields: &[(&str, u64)] = match message_type {
+        MessageType::Ping => &[("type", 1), ("seq", 2), ("pingTime", 3), ("netTime", 4)],
+        MessageType::Transaction => &[
+            ("rawTransaction", 1),
+            ("status", 2),
+            ("receiveTimestamp", 3),
+            ("deferred", 4),
+        ],
+        MessageType::ProposeLedger => &[
+            ("proposeSeq", PROPOSE_FIELD_SEQ),
+            ("currentTxHash", PROPOSE_FIELD_CURRENT_TX_HASH),
+            ("nodePubKey", PROPOSE_FIELD_NODE_PUB_KEY),
+            ("closeTime", PROPOSE_FIELD_CLOSE_TIME),
+            ("signature", PROPOSE_FIELD_SIGNATURE),
+            ("previousledger", PROPOSE_FIELD_PREVIOUS_LEDGER),
+            ("checkedSignature", 7),
+            ("addedTransactions", 10),
+            ("removedTransactions", 11),
+            ("hops", 12),
+        ],
+        MessageType::StatusChange => &[
+            ("newStatus", 1),
+            ("newEvent", 2),
+            ("ledgerSeq", STATUS_CHANGE_FIELD_LEDGER_SEQ),
+            ("ledgerHash", 4),
+            ("ledgerHashPrevious", 5),
+            ("networkTime", 6),
+            ("firstSeq", 7),
+            ("lastSeq", 8),
+        ],
+        MessageType::HaveSet => &[("status", 1), ("hash", 2)],
+        MessageType::Validation => &[
+            ("validation", VALIDATION_FIELD_VALIDATION),
+            ("checkedSignature", 2),
+            ("hops", 3),
+        ],
+        _ => &[],
+    };
+    fields
+        .iter()
+        .find(|(field_name, _)| *field_name == name)
+        .map(|(_, number)| *number)
+}
+
+/// Applies an operation to the field at the end of a path in a protobuf message, and returns the changed message.
+///
+/// # Parameters
+/// * 'payload' - the protobuf message.
+/// * 'path' - the field numbers of the path, of which all but the last address nested messages.
+/// * 'operation' - the operation.
+/// * 'name' - the path as it was given, for errors.
+fn mutate_payload(
+    payload: &[u8],
+    path: &[u64],
+    operation: &FieldOperation,
+    name: &str,
+) -> Result<Vec<u8>, MutationError> {
+    let wrong_field = || MutationError::WrongField(name.to_string());
+    let mut fields = decode_fields(payload).ok_or(MutationError::Malformed)?;
+    let (&number, rest) = path.split_first().ok_or_else(wrong_field)?;
+    let field = fields.iter_mut().find(|field| field.number == number);
+
+    if !rest.is_empty() {
+        let Some(WireField {
+            value: WireValue::LengthDelimited(contents),
+            ..
+        }) = field
+        else {
+            return Err(wrong_field());
+        };
+        *contents = mutate_payload(contents, rest, operation, name)?;
+        return Ok(encode_fields(&fields));
+    }
+
+    match (operation, field) {
+        (FieldOperation::Clear, _) => fields.retain(|field| field.number != number),
+        (FieldOperation::Set { value }, None) => fields.push(WireField {
+            number,
+            value: WireValue::Varint(*value),
+        }),
+        (FieldOperation::Set { value }, Some(field)) => match &mut field.value {
+            WireValue::Varint(existing) => *existing = *value,
+            _ => return Err(wrong_field()),
+        },
+        (FieldOperation::SetBytes { value }, None) => fields.push(WireField {
+            number,
+            value: WireValue::LengthDelimited(value.clone()),
+        }),
+        (FieldOperation::SetBytes { value }, Some(field)) => match &mut field.value {
+            WireValue::LengthDelimited(existing) => existing.clone_from(value),
+            _ => return Err(wrong_field()),
+        },
+        (FieldOperation::Add { delta }, Some(field)) => match &mut field.value {
+            WireValue::Varint(existing) => {
+                *existing = existing
+                    .checked_add_signed(*delta)
+                    .ok_or_else(|| MutationError::OutOfRange(name.to_string()))?;
+            }
+            _ => return Err(wrong_field()),
+        },
+        (FieldOperation::Truncate { length }, Some(field)) => match &mut field.value {
+            WireValue::LengthDelimited(existing) => existing.truncate(*length),
+            _ => return Err(wrong_field()),
+        },
+        (FieldOperation::Add { .. } | FieldOperation::Truncate { .. }, None) => {
+            return Err(wrong_field())
+        }
+    }
+    Ok(encode_fields(&fields))
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::field_mutation::{
+        apply, frame, resign, FieldMutation, FieldOperation, MutationError,
+    };
+    use crate::message_type::MessageType;
+    use prost::encoding::encode_varint;
+    use secp256k1::ecdsa::Signature;
+    use secp256k1::{Message as CryptoMessage, PublicKey, Secp256k1, SecretKey};
+    use sha2::{Digest, Sha512};
+
+    fn proposal(close_time: u64) -> Vec<u8> {
+        let mut payload = Vec::new();
+        let mut varint = |number: u64, value: u64, payload: &mut Vec<u8>| {
+            encode_varint(number << 3, payload);
+            encode_varint(value, payload);
+        };
+        varint(1, 3, &mut payload);
+        payload.extend_from_slice(&[0x12, 32]);
+        payload.extend_from_slice(&[0xAA; 32]);
+        varint(4, close_time, &mut payload);
+        payload.extend_from_slice(&[0x2A, 2, 0xDE, 0xAD]);
+        payload.extend_from_slice(&[0x32, 32]);
+        payload.extend_from_slice(&[0xBB; 32]);
+        frame(MessageType::ProposeLedger, &payload).to_vec()
+    }
+
+    fn mutation(path: &str, operation: FieldOperation) -> FieldMutation {
+        FieldMutation {
+            path: path.to_string(),
+            operation,
+        }
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn add_to_close_time_by_name() {
+        let mutated = apply(
+            &proposal(1000),
+            &[mutation(
+                "TMProposeSet.closeTime",
+                FieldOperation::Add { delta: 5 },
+            )],
+        )
+        .unwrap();
+        assert_eq!(mutated, proposal(1005));
+
+        let mutated = apply(
+            &proposal(1000),
+            &[mutation("4", FieldOperation::Set { value: 7 })],
+        )
+        .unwrap();
+        assert_eq!(mutated, proposal(7));
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn truncate_clear_and_nested_fields() {
+        let validation = frame(MessageType::Validation, &[0x0A, 4, 1, 2, 3, 4, 0x18, 1]);
+        let mutated = apply(
+            &validation,
+            &[
+                mutation("validation", FieldOperation::Truncate { length: 2 }),
+                mutation("hops", FieldOperation::Clear),
+            ],
+        )
+        .unwrap();
+        assert_eq!(mutated, frame(MessageType::Validation, &[0x0A, 2, 1, 2]));
+
+        // Field 2 of the message that is nested in field 1
+        let nested = frame(MessageType::Endpoints, &[0x0A, 2, 0x10, 1]);
+        let mutated = apply(
+            &nested,
+            &[mutation("1.2", FieldOperation::Add { delta: 1 })],
+        )
+        .unwrap();
+        assert_eq!(mutated, frame(MessageType::Endpoints, &[0x0A, 2, 0x10, 2]));
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn invalid_mutations() {
+        let message = proposal(1000);
+        let error = |path: &str, operation| apply(&message, &[mutation(path, operation)]);
+        assert_eq!(
+            error("closingTime", FieldOperation::Clear),
+            Err(MutationError::UnknownField(
+                MessageType::ProposeLedger,
+                "closingTime".to_string()
+            ))
+        );
+        assert_eq!(
+            error("closeTime", FieldOperation::Add { delta: -1001 }),
+            Err(MutationError::OutOfRange("closeTime".to_string()))
+        );
+        assert_eq!(
+            error("signature", FieldOperation::Add { delta: 1 }),
+            Err(MutationError::WrongField("signature".to_string()))
+        );
+        assert_eq!(
+            error("hops", FieldOperation::Truncate { length: 0 }),
+            Err(MutationError::WrongField("hops".to_string()))
+        );
+        assert_eq!(apply(&message[..10], &[]), Err(MutationError::Malformed));
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn resign_proposal() {
+        let secret_key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let secp256k1 = Secp256k1::new();
+        let resigned = resign(&proposal(1005), &secret_key).unwrap();
+
+        let mut hasher = Sha512::new();
+        hasher.update(b"PRP\0");
+        hasher.update(3u32.to_be_bytes());
+        hasher.update(1005u32.to_be_bytes());
+        hasher.update([0xBB; 32]);
+        hasher.update([0xAA; 32]);
+        let digest = CryptoMessage::from_digest_slice(&hasher.finalize()[..32]).unwrap();
+        // The signature is field 5, after the sequence, the transaction set hash and the close time
+        let signature_start = 6 + 2 + 34 + 3;
+        let signature_length = resigned[signature_start + 1] as usize;
+        let signature = Signature::from_der(
+            &resigned[signature_start + 2..signature_start + 2 + signature_length],
+        )
+        .unwrap();
+        assert!(secp256k1
+            .verify_ecdsa(
+                &digest,
+                &signature,
+                &PublicKey::from_secret_key(&secp256k1, &secret_key)
+            )
+            .is_ok());
+
+        let validation = frame(MessageType::Validation, &[0x0A, 1, 1]);
+        assert_eq!(
+            resign(&validation, &secret_key),
+            Err(MutationError::NotResignable(MessageType::Validation))
+        );
+    }
+}
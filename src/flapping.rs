@@ -0,0 +1,302 @@
+//! This module is responsible for the connection-flapping fault: the two connections of a pair of links are closed
+//! and re-established periodically, such that the peer reconnection and resend logic of rippled is exercised under
+//! repeated churn.
+//!
+//! Closing a connection means that the interceptor drops both its halves, so the node sees the peer disconnect.
+//! While the connections are down, the messages that were already read are dropped with the reason 'link_down'.
+//! The connections are re-established with new handshakes on behalf of the same peers.
+
+use crate::config::FlappingConfig;
+use crate::connection_handler::{ConnectionChange, Node, WriteChange};
+use crate::event_bus::EventKind;
+use crate::handshake_response::HandshakeInfo;
+use crate::interceptor_state::InterceptorState;
+use crate::peer_connector::{PeerConnector, PeerIdentity};
+use crate::tls::TlsStream;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::ReadHalf;
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::{info, warn};
+
+/// Struct that represents how long the connections of a flapping link stay up and down.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlapTiming {
+    /// How long the connections stay up.
+    pub up: Duration,
+    /// How long the connections stay down.
+    pub down: Duration,
+}
+
+impl FlapTiming {
+    /// Returns the timing of the configured fault. A duty cycle replaces the configured up and down durations.
+    ///
+    /// # Parameters
+    /// * 'config' - the configuration of the fault.
+    pub fn from_config(config: &FlappingConfig) -> Result<Self, String> {
+        let (up_ms, down_ms) = match (config.period_ms, config.duty_cycle) {
+            (Some(period_ms), Some(duty_cycle)) => {
+                if duty_cycle <= 0.0 || duty_cycle >= 1.0 {
+                    return Err(format!(
+                        "the duty cycle must be between 0 and 1, but is {}",
+                        duty_cycle
+                    ));
+                }
+                let up_ms = (period_ms as f64 * duty_cycle).round() as u64;
+                (up_ms, period_ms - up_ms)
+            }
+            (None, None) => (config.up_ms, config.down_ms),
+            _ => return Err("the period and duty cycle must be set together".to_string()),
+        };
+        if down_ms == 0 {
+            return Err("the connections must be down for longer than 0 ms".to_string());
+        }
+        Ok(Self {
+            up: Duration::from_millis(up_ms),
+            down: Duration::from_millis(down_ms),
+        })
+    }
+}
+
+/// Struct that represents a pair of links whose connections flap, together with the senders through which the stages
+/// that use the connections are told about the changes.
+pub struct FlappingLink {
+    /// The first node, the interceptor pretends to be this node towards the second node.
+    node_1: PeerIdentity,
+    /// The second node, the interceptor pretends to be this node towards the first node.
+    node_2: PeerIdentity,
+    /// The changes of the connection to the first node, for the read stage of the link from the first node.
+    read_1: UnboundedSender<ConnectionChange<ReadHalf<TlsStream>>>,
+    /// The changes of the connection to the second node, for the read stage of the link from the second node.
+    read_2: UnboundedSender<ConnectionChange<ReadHalf<TlsStream>>>,
+    /// The changes of the connection to the first node, for the write stage of the second node.
+    write_to_1: UnboundedSender<WriteChange>,
+    /// The changes of the connection to the second node, for the write stage of the first node.
+    write_to_2: UnboundedSender<WriteChange>,
+}
+
+impl FlappingLink {
+    /// Allows the connections between two nodes to be closed and re-established.
+    /// Must be called before the messages of the nodes are handled.
+    ///
+    /// # Parameters
+    /// * 'nodes' - all nodes together with the peers they are connected to.
+    /// * 'node_1' - the identity of the first node, which is used for the handshakes with the second node.
+    /// * 'node_2' - the identity of the second node, which is used for the handshakes with the first node.
+    ///
+    /// # Panics
+    /// * If the nodes are not connected to each other.
+    pub fn new(nodes: &mut [Node], node_1: PeerIdentity, node_2: PeerIdentity) -> Self {
+        let (read_1, write_to_2) = Self::subscribe(nodes, node_1.port, node_2.port);
+        let (read_2, write_to_1) = Self::subscribe(nodes, node_2.port, node_1.port);
+        Self {
+            node_1,
+            node_2,
+            read_1,
+            read_2,
+            write_to_1,
+            write_to_2,
+        }
+    }
+
+    /// Subscribes to the changes of the link from one node to another.
+    /// Returns the senders for the read stage of the link and the write stage of the node.
+    ///
+    /// # Parameters
+    /// * 'nodes' - all nodes together with the peers they are connected to.
+    /// * 'from_port' - the port of the node the link reads from.
+    /// * 'to_port' - the port of the peer the link writes to.
+    ///
+    /// # Panics
+    /// * If the link does not exist.
+    #[allow(clippy::type_complexity)]
+    fn subscribe(
+        nodes: &mut [Node],
+        from_port: u16,
+        to_port: u16,
+    ) -> (
+        UnboundedSender<ConnectionChange<ReadHalf<TlsStream>>>,
+        UnboundedSender<WriteChange>,
+    ) {
+        let node = nodes
+            .iter_mut()
+            .find(|node| node.port == from_port)
+            .unwrap_or_else(|| panic!("Node {} does not exist", from_port));
+        let write_changes = node.subscribe_write_changes();
+        let read_changes = node
+            .peers
+            .iter_mut()
+            .find(|peer| peer.port == to_port)
+            .unwrap_or_else(|| panic!("Node {} is not connected to {}", from_port, to_port))
+            .subscribe_read_changes();
+        (read_changes, write_changes)
+    }
+
+    /// Closes and re-establishes the connections of the links periodically, until the end of the run.
+    /// If the connections can not be re-established, they stay down and are tried again after the next down period.
+    ///
+    /// # Parameters
+    /// * 'peer_connector' - the PeerConnector used for the new handshakes.
+    /// * 'timing' - how long the connections stay up and down.
+    /// * 'start_after' - how long after the links are started the connections go down for the first time.
+    /// * 'state' - the runtime state, where the changes of the links are published.
+    pub async fn run(
+        self,
+        peer_connector: PeerConnector,
+        timing: FlapTiming,
+        start_after: Duration,
+        state: Arc<InterceptorState>,
+    ) {
+        let (port_1, port_2) = (self.node_1.port, self.node_2.port);
+        tokio::time::sleep(start_after).await;
+        loop {
+            info!(
+                "Taking down the connections between {} and {}",
+                port_1, port_2
+            );
+            // Both halves of a connection are dropped by their stages, which closes it
+            let sent = self.read_1.send(ConnectionChange::Down).is_ok()
+                && self.read_2.send(ConnectionChange::Down).is_ok()
+                && self
+                    .write_to_1
+                    .send((port_1, ConnectionChange::Down))
+                    .is_ok()
+                && self
+                    .write_to_2
+                    .send((port_2, ConnectionChange::Down))
+                    .is_ok();
+            if !sent {
+                // The stages stopped, so the run is ending
+                return;
+            }
+            for (from_port, to_port) in [(port_1, port_2), (port_2, port_1)] {
+                state.events.emit(EventKind::LinkDropped {
+                    from_port,
+                    to_port,
+                    reason: "flapping".to_string(),
+                });
+            }
+
+            loop {
+                tokio::time::sleep(timing.down).await;
+                match peer_connector
+                    .connect_peers(&self.node_1, &self.node_2)
+                    .await
+                {
+                    Ok(((connection_1, handshake_1), (connection_2, handshake_2))) => {
+                        self.bring_up(connection_1, connection_2, &handshake_1, &handshake_2);
+                        for (from_port, to_port, handshake) in
+                            [(port_1, port_2, handshake_1), (port_2, port_1, handshake_2)]
+                        {
+                            state.events.emit(EventKind::LinkConnected {
+                                from_port,
+                                to_port,
+                                protocol: handshake.version.map(|version| version.to_string()),
+                            });
+                        }
+                        break;
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Could not reconnect {} and {}, trying again in {:?}: {}",
+                            port_1, port_2, timing.down, e
+                        );
+                        state.statistics.count_error("flapping_reconnect_failed", 1);
+                    }
+                }
+            }
+            info!(
+                "Re-established the connections between {} and {}",
+                port_1, port_2
+            );
+            tokio::time::sleep(timing.up).await;
+        }
+    }
+
+    /// Hands the halves of the re-established connections to the stages that use them.
+    ///
+    /// # Parameters
+    /// * 'connection_1' - the new connection to the first node.
+    /// * 'connection_2' - the new connection to the second node.
+    /// * 'handshake_1' - the response of the first node to the handshake.
+    /// * 'handshake_2' - the response of the second node to the handshake.
+    fn bring_up(
+        &self,
+        connection_1: TlsStream,
+        connection_2: TlsStream,
+        handshake_1: &HandshakeInfo,
+        handshake_2: &HandshakeInfo,
+    ) {
+        let (read_half_1, write_half_1) = tokio::io::split(connection_1);
+        let (read_half_2, write_half_2) = tokio::io::split(connection_2);
+        let protocol_1 = handshake_1
+            .version
+            .expect("The handshake did not negotiate a protocol version");
+        let protocol_2 = handshake_2
+            .version
+            .expect("The handshake did not negotiate a protocol version");
+        // Sending only fails when the run is ending, in which case the connections are dropped
+        let _ = self.read_1.send(ConnectionChange::Up(read_half_1));
+        let _ = self.read_2.send(ConnectionChange::Up(read_half_2));
+        let _ = self.write_to_1.send((
+            self.node_1.port,
+            ConnectionChange::Up((write_half_1, protocol_1)),
+        ));
+        let _ = self.write_to_2.send((
+            self.node_2.port,
+            ConnectionChange::Up((write_half_2, protocol_2)),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::config::FlappingConfig;
+    use crate::flapping::FlapTiming;
+    use std::time::Duration;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn timing_from_durations_and_duty_cycle() {
+        let config = FlappingConfig {
+            up_ms: 3000,
+            down_ms: 1000,
+            ..FlappingConfig::default()
+        };
+        assert_eq!(
+            FlapTiming::from_config(&config),
+            Ok(FlapTiming {
+                up: Duration::from_millis(3000),
+                down: Duration::from_millis(1000),
+            })
+        );
+
+        let config = FlappingConfig {
+            period_ms: Some(10_000),
+            duty_cycle: Some(0.8),
+            ..FlappingConfig::default()
+        };
+        assert_eq!(
+            FlapTiming::from_config(&config),
+            Ok(FlapTiming {
+                up: Duration::from_millis(8000),
+                down: Duration::from_millis(2000),
+            })
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn invalid_timing() {
+        let config = |period_ms, duty_cycle, down_ms| FlappingConfig {
+            period_ms,
+            duty_cycle,
+            down_ms,
+            ..FlappingConfig::default()
+        };
+        assert!(FlapTiming::from_config(&config(Some(1000), Some(1.0), 0)).is_err());
+        assert!(FlapTiming::from_config(&config(Some(1000), Some(0.0), 0)).is_err());
+        assert!(FlapTiming::from_config(&config(Some(1000), None, 1000)).is_err());
+        assert!(FlapTiming::from_config(&config(None, None, 0)).is_err());
+    }
+}
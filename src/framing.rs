@@ -0,0 +1,394 @@
+//! This module is responsible for splitting the data read from a link into the messages of the peer protocol.
+//!
+//! A read returns whatever the TLS stream had available, which can be part of a message or several messages at once.
+//! Messages are reassembled in a buffer that grows to the size of the message at the front, such that ledger data and
+//! transaction sets of several MB are handled as one message. Messages above a hard cap are not held whole, but handed
+//! on in parts as they are read, see `OversizedPolicy`.
+//!
+//! Frames that violate the peer protocol are split off as malformed instead: compressed frames, since compression is
+//! never negotiated, frames with a payload above the limit of rippled, and headers with an unknown version. The rest of
+//! a malformed frame whose size is known is discarded as it is read. A header with an unknown version does not tell where
+//! the frame ends, so everything read up to then is malformed.
+
+use crate::buffer_pool::BufferPool;
+use bytes::{Bytes, BytesMut};
+
+/// The size of the header of an uncompressed message: the payload size and the message type.
+pub const HEADER_SIZE: usize = 6;
+
+/// The size of the header of a compressed message, which also holds the size of the uncompressed payload.
+const COMPRESSED_HEADER_SIZE: usize = 10;
+
+/// The largest payload rippled accepts, in bytes.
+pub const MAX_PAYLOAD_SIZE: usize = 64 * 1024 * 1024;
+
+/// Struct that represents where a part of a message that is too large to be held whole is located in that message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Part {
+    /// The position of the first byte of the part in the message.
+    pub offset: usize,
+    /// The size of the whole message, including its header.
+    pub length: usize,
+}
+
+impl Part {
+    /// Returns whether the part starts the message, such that it contains the header.
+    pub fn is_first(&self) -> bool {
+        self.offset == 0
+    }
+
+    /// Returns whether a part of a certain size ends the message.
+    ///
+    /// # Parameters
+    /// * 'size' - the size of the part.
+    pub fn is_last(&self, size: usize) -> bool {
+        self.offset + size == self.length
+    }
+}
+
+/// Enum that represents what was split off the data read from a link.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Frame {
+    /// A whole message, including its header.
+    Whole(Bytes),
+    /// A part of a message above the hard cap, in the order the parts were read.
+    Part(Bytes, Part),
+    /// A frame that violates the peer protocol, or the part of it that was read when it was detected.
+    Malformed(Bytes, Malformation),
+}
+
+/// Enum that represents how a frame violates the peer protocol.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Malformation {
+    /// The header has a version the protocol does not define, by its first byte.
+    UnknownVersion(u8),
+    /// The frame is compressed, although compression is never negotiated.
+    Compressed,
+    /// The payload is larger than the limit, by its size.
+    TooLarge(usize),
+}
+
+impl Malformation {
+    /// Returns the name of the malformation, as used in events and file names.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Malformation::UnknownVersion(_) => "unknown_version",
+            Malformation::Compressed => "compressed",
+            Malformation::TooLarge(_) => "too_large",
+        }
+    }
+}
+
+/// Returns the size of the message at the start of some data, including its header, if the header is complete.
+/// Returns None if the header has an unknown version, or is not complete yet.
+///
+/// # Parameters
+/// * 'data' - the data, starting with the header of a message.
+pub fn frame_size(data: &[u8]) -> Option<usize> {
+    let first = *data.first()?;
+    let header_size = match first {
+        // The compressed bit, followed by the algorithm
+        _ if first & 0b1000_0000 != 0 => COMPRESSED_HEADER_SIZE,
+        _ if first & 0b1111_1100 == 0 => HEADER_SIZE,
+        _ => return None,
+    };
+    let size = u32::from_be_bytes(data.get(0..4)?.try_into().ok()?) & 0x03FF_FFFF;
+    (data.len() >= header_size).then_some(header_size + size as usize)
+}
+
+/// Returns how the frame at the start of some data violates the peer protocol, if it does.
+/// Returns None if the frame is valid, or if the header is not complete yet and the frame may still be valid.
+///
+/// # Parameters
+/// * 'data' - the data, starting with the header of a message.
+/// * 'max_payload' - the size of the largest payload that is allowed.
+pub fn malformation(data: &[u8], max_payload: usize) -> Option<Malformation> {
+    let first = *data.first()?;
+    if first & 0b1000_0000 != 0 {
+        return Some(Malformation::Compressed);
+    }
+    if first & 0b1111_1100 != 0 {
+        return Some(Malformation::UnknownVersion(first));
+    }
+    let payload_size = frame_size(data)? - HEADER_SIZE;
+    (payload_size > max_payload).then_some(Malformation::TooLarge(payload_size))
+}
+
+/// Struct that represents the reassembly of the messages read from one link.
+#[derive(Debug)]
+pub struct FrameReader {
+    /// The buffer that is read into.
+    pool: BufferPool,
+    /// The size of the largest message that is held whole.
+    max_buffered: usize,
+    /// The size of the largest payload that is allowed.
+    max_payload: usize,
+    /// The message above the hard cap that is being handed on in parts, if any.
+    streaming: Option<Part>,
+    /// The amount of bytes of a malformed frame that still have to be discarded.
+    skipping: usize,
+}
+
+impl FrameReader {
+    /// Initializes a new FrameReader.
+    ///
+    /// # Parameters
+    /// * 'chunk_size' - the minimum amount of spare capacity available for every read.
+    /// * 'max_buffered' - the size of the largest message that is held whole, including its header.
+    /// * 'max_payload' - the size of the largest payload that is allowed, larger frames are malformed.
+    pub fn new(chunk_size: usize, max_buffered: usize, max_payload: usize) -> Self {
+        Self {
+            pool: BufferPool::new(chunk_size),
+            max_buffered,
+            max_payload,
+            streaming: None,
+            skipping: 0,
+        }
+    }
+
+    /// Returns the buffer to read into. If the message at the front is held whole, the buffer has room for the rest of
+    /// it, such that it is not grown read by read.
+    pub fn buffer(&mut self) -> &mut BytesMut {
+        let buffered = self.pool.len();
+        let valid =
+            self.skipping == 0 && malformation(self.pool.data(), self.max_payload).is_none();
+        let missing = match (&self.streaming, self.pool_frame_size()) {
+            (None, Some(size)) if valid && size <= self.max_buffered => {
+                size.saturating_sub(buffered)
+            }
+            _ => 0,
+        };
+        let buffer = self.pool.buffer();
+        buffer.reserve(missing);
+        buffer
+    }
+
+    /// Returns the size of the message at the front of the buffer, if its header was read.
+    fn pool_frame_size(&self) -> Option<usize> {
+        frame_size(self.pool.data())
+    }
+
+    /// Splits the next message, or the next part of a message above the hard cap, off the data that was read. Returns
+    /// None if more data has to be read first. A malformed frame is split off as far as it was read, and the rest of it
+    /// is discarded as it is read.
+    pub fn next_frame(&mut self) -> Option<Frame> {
+        if self.skipping > 0 {
+            let size = self.skipping.min(self.pool.len());
+            self.skipping -= size;
+            self.pool.take(size);
+        }
+        if self.pool.is_empty() {
+            return None;
+        }
+        if let Some(part) = self.streaming {
+            let size = (part.length - part.offset).min(self.pool.len());
+            return Some(self.take_part(part, size));
+        }
+        if let Some(malformation) = malformation(self.pool.data(), self.max_payload) {
+            let size = match malformation {
+                Malformation::UnknownVersion(_) => self.pool.len(),
+                _ => self.pool_frame_size()?,
+            };
+            let taken = size.min(self.pool.len());
+            self.skipping = size - taken;
+            return Some(Frame::Malformed(self.pool.take(taken), malformation));
+        }
+        let size = self.pool_frame_size()?;
+        if size > self.max_buffered {
+            let part = Part {
+                offset: 0,
+                length: size,
+            };
+            let size = size.min(self.pool.len());
+            return Some(self.take_part(part, size));
+        }
+        (self.pool.len() >= size).then(|| Frame::Whole(self.pool.take(size)))
+    }
+
+    /// Takes a part of a message above the hard cap, and remembers where the next part starts.
+    ///
+    /// # Parameters
+    /// * 'part' - where the part starts in the message.
+    /// * 'size' - the size of the part.
+    fn take_part(&mut self, part: Part, size: usize) -> Frame {
+        self.streaming = (!part.is_last(size)).then_some(Part {
+            offset: part.offset + size,
+            length: part.length,
+        });
+        Frame::Part(self.pool.take(size), part)
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::framing::{
+        frame_size, malformation, Frame, FrameReader, Malformation, Part, HEADER_SIZE,
+        MAX_PAYLOAD_SIZE,
+    };
+    use bytes::BufMut;
+    use proptest::prelude::*;
+
+    fn message(payload_size: usize, message_type: u16) -> Vec<u8> {
+        let mut message = (payload_size as u32).to_be_bytes().to_vec();
+        message.extend_from_slice(&message_type.to_be_bytes());
+        message.extend(std::iter::repeat(7).take(payload_size));
+        message
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_frame_size() {
+        assert_eq!(frame_size(&message(100, 3)), Some(106));
+        assert_eq!(frame_size(&message(100, 3)[..5]), None);
+        let mut compressed = message(100, 3);
+        compressed[0] |= 0b1001_0000;
+        compressed.extend_from_slice(&[0, 0, 1, 0]);
+        assert_eq!(frame_size(&compressed), Some(110));
+        let mut unknown = message(100, 3);
+        unknown[0] |= 0b0010_0000;
+        assert_eq!(frame_size(&unknown), None);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn reassemble_split_and_joined_messages() {
+        let mut reader = FrameReader::new(1024, 1 << 20, MAX_PAYLOAD_SIZE);
+        let first = message(200_000, 32);
+        let second = message(10, 3);
+        reader.buffer().put_slice(&first[..70_000]);
+        assert_eq!(reader.next_frame(), None);
+        // The rest of the large message fits without growing the buffer
+        assert!(reader.buffer().capacity() >= first.len());
+        reader.buffer().put_slice(&first[70_000..]);
+        reader.buffer().put_slice(&second[..8]);
+        assert_eq!(reader.next_frame(), Some(Frame::Whole(first.into())));
+        assert_eq!(reader.next_frame(), None);
+        reader.buffer().put_slice(&second[8..]);
+        assert_eq!(reader.next_frame(), Some(Frame::Whole(second.into())));
+        assert_eq!(reader.next_frame(), None);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn stream_messages_above_the_cap() {
+        let mut reader = FrameReader::new(1024, 1000, MAX_PAYLOAD_SIZE);
+        let oversized = message(2000, 32);
+        let next = message(10, 3);
+        reader.buffer().put_slice(&oversized[..1500]);
+        let length = oversized.len();
+        assert_eq!(
+            reader.next_frame(),
+            Some(Frame::Part(
+                oversized[..1500].to_vec().into(),
+                Part { offset: 0, length }
+            ))
+        );
+        assert_eq!(reader.next_frame(), None);
+        reader.buffer().put_slice(&oversized[1500..]);
+        reader.buffer().put_slice(&next);
+        let Some(Frame::Part(data, part)) = reader.next_frame() else {
+            panic!("Expected the last part");
+        };
+        assert_eq!(part.offset, 1500);
+        assert!(part.is_last(data.len()));
+        assert_eq!(reader.next_frame(), Some(Frame::Whole(next.into())));
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn detect_malformed_frames() {
+        assert_eq!(malformation(&message(100, 3), MAX_PAYLOAD_SIZE), None);
+        assert_eq!(
+            malformation(&message(100, 3), 99),
+            Some(Malformation::TooLarge(100))
+        );
+        // The size is only known once the header is complete
+        assert_eq!(malformation(&message(100, 3)[..4], 99), None);
+        let mut compressed = message(100, 3);
+        compressed[0] |= 0b1001_0000;
+        assert_eq!(
+            malformation(&compressed[..1], MAX_PAYLOAD_SIZE),
+            Some(Malformation::Compressed)
+        );
+        let mut unknown = message(100, 3);
+        unknown[0] |= 0b0010_0000;
+        assert_eq!(
+            malformation(&unknown, MAX_PAYLOAD_SIZE),
+            Some(Malformation::UnknownVersion(0b0010_0000))
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn skip_malformed_frames() {
+        let mut reader = FrameReader::new(1024, 1 << 20, 1000);
+        let too_large = message(5000, 32);
+        let next = message(10, 3);
+        reader.buffer().put_slice(&too_large[..3000]);
+        assert_eq!(
+            reader.next_frame(),
+            Some(Frame::Malformed(
+                too_large[..3000].to_vec().into(),
+                Malformation::TooLarge(5000)
+            ))
+        );
+        // The rest of the frame is discarded, and the next message is framed again
+        reader.buffer().put_slice(&too_large[3000..]);
+        reader.buffer().put_slice(&next);
+        assert_eq!(reader.next_frame(), Some(Frame::Whole(next.into())));
+        assert_eq!(reader.next_frame(), None);
+
+        let mut unknown = message(10, 3);
+        unknown[0] |= 0b0100_0000;
+        reader.buffer().put_slice(&unknown);
+        reader.buffer().put_slice(&message(10, 3));
+        let Some(Frame::Malformed(data, Malformation::UnknownVersion(_))) = reader.next_frame()
+        else {
+            panic!("Expected an unknown version");
+        };
+        assert_eq!(data.len(), 32);
+        assert_eq!(reader.next_frame(), None);
+    }
+
+    proptest! {
+        #[test]
+        // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+        fn arbitrary_reads_reassemble_the_messages(
+            messages in prop::collection::vec((0usize..3000, 0u16..64), 0..20),
+            reads in prop::collection::vec(1usize..4096, 1..32),
+            max_buffered in prop::option::of(64usize..2048),
+        ) {
+            let messages: Vec<Vec<u8>> = messages
+                .iter()
+                .map(|(size, message_type)| message(*size, *message_type))
+                .collect();
+            let stream = messages.concat();
+            let max_buffered = max_buffered.unwrap_or(MAX_PAYLOAD_SIZE + HEADER_SIZE);
+            let mut reader = FrameReader::new(1024, max_buffered, MAX_PAYLOAD_SIZE);
+            let mut reassembled = Vec::new();
+            let mut streamed = Vec::new();
+            let mut position = 0;
+            let mut reads = reads.iter().cycle();
+            while position < stream.len() {
+                let end = (position + reads.next().unwrap()).min(stream.len());
+                reader.buffer().put_slice(&stream[position..end]);
+                position = end;
+                while let Some(frame) = reader.next_frame() {
+                    match frame {
+                        Frame::Whole(data) => reassembled.push(data.to_vec()),
+                        Frame::Part(data, part) => {
+                            streamed.extend_from_slice(&data);
+                            if part.is_last(data.len()) {
+                                reassembled.push(std::mem::take(&mut streamed));
+                            }
+                        }
+                        Frame::Malformed(_, malformation) => {
+                            prop_assert!(false, "Valid messages are malformed: {:?}", malformation)
+                        }
+                    }
+                }
+            }
+            prop_assert_eq!(reassembled, messages);
+        }
+    }
+}
@@ -0,0 +1,604 @@
+//! This module is responsible for the gray failures: named presets of partial faults, like a lossy WAN link or a node
+//! whose disk stalls now and then, which keep a node or link working but worse, instead of cutting it.
+//!
+//! Every preset combines the primitives of the interceptor into an impairment: latency, jitter (which reorders the
+//! messages of a link), loss, throttling of message types with token buckets, and periodic outages in which the
+//! messages are either held until the outage ends or dropped. The parameters of a preset have defaults, so naming the
+//! preset is enough. A preset applies to a single link, or to every link a node sends or receives on.
+
+use crate::config::GrayFailureRuleConfig;
+use crate::message_type::MessageType;
+use crate::traffic_shaping::{Shaped, ShapingAction, ShapingRule, TrafficShaper};
+use rand::rngs::StdRng;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Enum that represents a named gray failure preset with its parameters, of which every unset one has a default.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "preset", rename_all = "kebab-case")]
+pub enum Preset {
+    /// A link over a long, congested distance: every message is late by a varying amount, so messages overtake each
+    /// other, and some are lost.
+    LossyWan {
+        /// The latency added to every message in ms, 150 if not set.
+        latency_ms: Option<u32>,
+        /// The maximum extra latency in ms, drawn per message, 100 if not set.
+        jitter_ms: Option<u32>,
+        /// The probability between 0 and 1 that a message is lost, 0.02 if not set.
+        loss: Option<f64>,
+    },
+    /// A node whose disk stalls now and then: during a stall nothing it sends or receives gets through until the stall
+    /// ends, and the ledger data it serves is throttled.
+    FlakyDiskNode {
+        /// How often the disk stalls in ms, 15000 if not set.
+        every_ms: Option<u32>,
+        /// How long a stall lasts in ms, 2000 if not set.
+        stall_ms: Option<u32>,
+        /// The amount of mtLEDGER_DATA messages per second, 10 if not set.
+        fetch_rate: Option<f64>,
+    },
+    /// A node that is slow to take part in consensus: every message is late, and its proposals are throttled.
+    SlowLeader {
+        /// The latency added to every message in ms, 800 if not set.
+        latency_ms: Option<u32>,
+        /// The amount of mtPROPOSE_LEDGER messages per second, 0.5 if not set.
+        proposal_rate: Option<f64>,
+    },
+    /// A connection that keeps dropping out: it is up for a while, after which every message is dropped for a while.
+    IntermittentPartition {
+        /// How long the connection is up in ms, 10000 if not set.
+        up_ms: Option<u32>,
+        /// How long the connection is down in ms, 5000 if not set.
+        down_ms: Option<u32>,
+    },
+}
+
+impl Preset {
+    /// Returns the primitives the preset combines, with the defaults of its unset parameters.
+    pub fn impairment(&self) -> Impairment {
+        let ms = |value: Option<u32>, default: u32| {
+            Duration::from_millis(u64::from(value.unwrap_or(default)))
+        };
+        match *self {
+            Preset::LossyWan {
+                latency_ms,
+                jitter_ms,
+                loss,
+            } => Impairment {
+                latency: ms(latency_ms, 150),
+                jitter: ms(jitter_ms, 100),
+                loss: loss.unwrap_or(0.02),
+                ..Impairment::default()
+            },
+            Preset::FlakyDiskNode {
+                every_ms,
+                stall_ms,
+                fetch_rate,
+            } => Impairment {
+                throttle: vec![(MessageType::LedgerData, fetch_rate.unwrap_or(10.0))],
+                outage: Some(Outage {
+                    every: ms(every_ms, 15000),
+                    length: ms(stall_ms, 2000),
+                    drop: false,
+                }),
+                ..Impairment::default()
+            },
+            Preset::SlowLeader {
+                latency_ms,
+                proposal_rate,
+            } => Impairment {
+                latency: ms(latency_ms, 800),
+                throttle: vec![(MessageType::ProposeLedger, proposal_rate.unwrap_or(0.5))],
+                ..Impairment::default()
+            },
+            Preset::IntermittentPartition { up_ms, down_ms } => {
+                let down = ms(down_ms, 5000);
+                Impairment {
+                    outage: Some(Outage {
+                        every: ms(up_ms, 10000) + down,
+                        length: down,
+                        drop: true,
+                    }),
+                    ..Impairment::default()
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Preset {
+    type Err = GrayFailureError;
+
+    /// Parses the name of a preset, e.g. 'lossy-wan', into the preset with the defaults of all its parameters.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "lossy-wan" => Ok(Preset::LossyWan {
+                latency_ms: None,
+                jitter_ms: None,
+                loss: None,
+            }),
+            "flaky-disk-node" => Ok(Preset::FlakyDiskNode {
+                every_ms: None,
+                stall_ms: None,
+                fetch_rate: None,
+            }),
+            "slow-leader" => Ok(Preset::SlowLeader {
+                latency_ms: None,
+                proposal_rate: None,
+            }),
+            "intermittent-partition" => Ok(Preset::IntermittentPartition {
+                up_ms: None,
+                down_ms: None,
+            }),
+            _ => Err(GrayFailureError(format!("unknown preset '{}'", name))),
+        }
+    }
+}
+
+/// Struct that represents a recurring outage: the last part of every period.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Outage {
+    /// How often the outage recurs.
+    pub every: Duration,
+    /// How long the outage lasts.
+    pub length: Duration,
+    /// Whether the messages are dropped during the outage, instead of held until it ends.
+    pub drop: bool,
+}
+
+/// Struct that represents the primitives a preset combines.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Impairment {
+    /// The latency added to every message.
+    pub latency: Duration,
+    /// The maximum extra latency, drawn uniformly per message.
+    pub jitter: Duration,
+    /// The probability between 0 and 1 that a message is dropped.
+    pub loss: f64,
+    /// The message types that are limited, with their amount of messages per second. Messages above it are delayed.
+    pub throttle: Vec<(MessageType, f64)>,
+    /// The recurring outage, if any.
+    pub outage: Option<Outage>,
+}
+
+/// Enum that represents what a preset is applied to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// Every link the node with this port sends or receives on.
+    Node(u16),
+    /// The link from the peer with the first port to the peer with the second port.
+    Link(u16, u16),
+}
+
+impl Target {
+    /// Returns whether a link is targeted.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer the messages of the link come from.
+    /// * 'to_port' - the port of the peer the messages of the link go to.
+    pub fn matches(&self, from_port: u16, to_port: u16) -> bool {
+        match *self {
+            Target::Node(port) => port == from_port || port == to_port,
+            Target::Link(from, to) => from == from_port && to == to_port,
+        }
+    }
+}
+
+/// Struct that represents a preset applied to a node or a link.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GrayFailureRule {
+    /// The preset.
+    pub preset: Preset,
+    /// What the preset is applied to.
+    pub target: Target,
+}
+
+impl GrayFailureRule {
+    /// Creates the rule from its configuration. Returns an error if a node does not exist, or if not exactly a node or
+    /// both ends of a link are given.
+    ///
+    /// # Parameters
+    /// * 'config' - the configuration of the rule.
+    /// * 'ports' - the peer ports of the nodes, by node ID.
+    pub fn from_config(
+        config: &GrayFailureRuleConfig,
+        ports: &[u16],
+    ) -> Result<Self, GrayFailureError> {
+        let port_of = |id: u32| {
+            ports
+                .get(id as usize)
+                .copied()
+                .ok_or(GrayFailureError(format!("node {} does not exist", id)))
+        };
+        let target = match (config.node, config.from_node, config.to_node) {
+            (Some(node), None, None) => Target::Node(port_of(node)?),
+            (None, Some(from), Some(to)) if from != to => {
+                Target::Link(port_of(from)?, port_of(to)?)
+            }
+            _ => {
+                return Err(GrayFailureError(
+                    "a preset applies to either a node, or a link from_node to another to_node"
+                        .to_string(),
+                ))
+            }
+        };
+        let rule = Self {
+            preset: config.preset,
+            target,
+        };
+        rule.validate()?;
+        Ok(rule)
+    }
+
+    /// Checks whether the rule can be applied.
+    pub fn validate(&self) -> Result<(), GrayFailureError> {
+        let impairment = self.preset.impairment();
+        if !(0.0..=1.0).contains(&impairment.loss) {
+            return Err(GrayFailureError(format!(
+                "loss {} is not between 0 and 1",
+                impairment.loss
+            )));
+        }
+        if let Some(outage) = impairment.outage {
+            if outage.length.is_zero() || outage.length >= outage.every {
+                return Err(GrayFailureError(format!(
+                    "an outage of {:?} every {:?} is not a part of its period",
+                    outage.length, outage.every
+                )));
+            }
+        }
+        for rule in self.shaping_rules() {
+            rule.validate()
+                .map_err(|e| GrayFailureError(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the shaping rules that throttle the message types of the preset on the targeted links.
+    fn shaping_rules(&self) -> Vec<ShapingRule> {
+        let ends = match self.target {
+            Target::Node(port) => vec![(Some(port), None), (None, Some(port))],
+            Target::Link(from, to) => vec![(Some(from), Some(to))],
+        };
+        let mut rules = Vec::new();
+        for (message_type, rate) in self.preset.impairment().throttle {
+            for (from_port, to_port) in ends.iter().copied() {
+                rules.push(ShapingRule {
+                    message_type,
+                    from_port,
+                    to_port,
+                    rate,
+                    burst: None,
+                    action: ShapingAction::Delay,
+                });
+            }
+        }
+        rules
+    }
+
+    /// Parses the value of a preset option, `--gray-failure <preset>@<node>` for a node or `--gray-failure
+    /// <preset>@<from_node>-<to_node>` for a link, into the configuration of the preset with the defaults of its
+    /// parameters. Returns an error if the preset or target can not be parsed.
+    ///
+    /// # Parameters
+    /// * 'value' - the value of the option.
+    pub fn parse_option(value: &str) -> Result<GrayFailureRuleConfig, GrayFailureError> {
+        let invalid = || {
+            GrayFailureError(format!(
+                "'{}' is not <preset>@<node> or <preset>@<from_node>-<to_node>",
+                value
+            ))
+        };
+        let (name, target) = value.split_once('@').ok_or_else(invalid)?;
+        let node = |id: &str| id.parse::<u32>().map_err(|_| invalid());
+        let (node, from_node, to_node) = match target.split_once('-') {
+            Some((from, to)) => (None, Some(node(from)?), Some(node(to)?)),
+            None => (Some(node(target)?), None, None),
+        };
+        Ok(GrayFailureRuleConfig {
+            preset: name.parse()?,
+            node,
+            from_node,
+            to_node,
+        })
+    }
+}
+
+/// Struct that represents the reason a gray failure can not be applied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrayFailureError(pub String);
+
+impl fmt::Display for GrayFailureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid gray failure: {}", self.0)
+    }
+}
+
+impl Error for GrayFailureError {}
+
+/// Struct that represents the applied gray failures, with the token buckets of their throttling.
+#[derive(Debug)]
+pub struct GrayFailures {
+    /// The rules, each with the shaper that throttles its message types.
+    rules: Vec<(GrayFailureRule, TrafficShaper)>,
+    /// The moment the outages are measured from, which is the start of the first period.
+    start: Instant,
+}
+
+impl GrayFailures {
+    /// Initializes the GrayFailures of a set of rules. Returns an error if a rule is invalid.
+    ///
+    /// # Parameters
+    /// * 'rules' - the rules, which are all applied to the links they target.
+    /// * 'start' - the moment the outages are measured from.
+    pub fn new(rules: Vec<GrayFailureRule>, start: Instant) -> Result<Self, GrayFailureError> {
+        let mut shaped_rules = Vec::new();
+        for rule in rules {
+            rule.validate()?;
+            let mut shaper = TrafficShaper::default();
+            shaper.set_rules(rule.shaping_rules());
+            shaped_rules.push((rule, shaper));
+        }
+        Ok(Self {
+            rules: shaped_rules,
+            start,
+        })
+    }
+
+    /// Returns the rules.
+    pub fn rules(&self) -> Vec<GrayFailureRule> {
+        self.rules.iter().map(|(rule, _)| *rule).collect()
+    }
+
+    /// Returns how the rules that target a link treat a message: delayed by the sum of their delays, or dropped if any
+    /// of them drops it. Returns None if no rule targets the link.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer where the message came from.
+    /// * 'to_port' - the port of the peer the message is sent to.
+    /// * 'message_type' - the type of the message.
+    /// * 'now' - the current moment.
+    /// * 'rng' - the random number generator the jitter and the loss are drawn from.
+    pub fn impair(
+        &mut self,
+        from_port: u16,
+        to_port: u16,
+        message_type: MessageType,
+        now: Instant,
+        rng: &mut StdRng,
+    ) -> Option<Shaped> {
+        let mut total: Option<Duration> = None;
+        for (rule, shaper) in self.rules.iter_mut() {
+            if !rule.target.matches(from_port, to_port) {
+                continue;
+            }
+            let impairment = rule.preset.impairment();
+            let mut delay = impairment.latency;
+            if !impairment.jitter.is_zero() {
+                delay += impairment.jitter.mul_f64(rng.gen::<f64>());
+            }
+            if impairment.loss > 0.0 && rng.gen_bool(impairment.loss) {
+                return Some(Shaped::Dropped);
+            }
+            if let Some(outage) = impairment.outage {
+                let phase = Duration::from_nanos(
+                    (now.saturating_duration_since(self.start).as_nanos() % outage.every.as_nanos())
+                        as u64,
+                );
+                if phase >= outage.every - outage.length {
+                    if outage.drop {
+                        return Some(Shaped::Dropped);
+                    }
+                    // Held until the outage ends
+                    delay += outage.every - phase;
+                }
+            }
+            if let Some(Shaped::Delayed(throttled)) =
+                shaper.shape(from_port, to_port, message_type, now)
+            {
+                delay += throttled;
+            }
+            total = Some(total.unwrap_or_default() + delay);
+        }
+        total.map(Shaped::Delayed)
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::config::InterceptorConfig;
+    use crate::gray_failure::{GrayFailureRule, GrayFailures, Preset, Target};
+    use crate::message_type::MessageType;
+    use crate::traffic_shaping::Shaped;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    const PORTS: [u16; 3] = [60000, 60001, 60002];
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn presets_from_config_and_args() {
+        let config = InterceptorConfig::parse(
+            r#"
+            [gray_failure]
+            rules = [
+                { preset = "lossy-wan", from_node = 0, to_node = 1, loss = 0.1 },
+                { preset = "slow-leader", node = 2 },
+            ]
+            "#,
+        )
+        .unwrap();
+        let rules: Vec<GrayFailureRule> = config
+            .gray_failure
+            .unwrap()
+            .rules
+            .iter()
+            .map(|rule| GrayFailureRule::from_config(rule, &PORTS).unwrap())
+            .collect();
+        assert_eq!(
+            rules,
+            [
+                GrayFailureRule {
+                    preset: Preset::LossyWan {
+                        latency_ms: None,
+                        jitter_ms: None,
+                        loss: Some(0.1),
+                    },
+                    target: Target::Link(60000, 60001),
+                },
+                GrayFailureRule {
+                    preset: "slow-leader".parse().unwrap(),
+                    target: Target::Node(60002),
+                },
+            ]
+        );
+
+        let targets: Vec<Target> = ["intermittent-partition@0-2", "flaky-disk-node@1"]
+            .iter()
+            .map(|value| {
+                let config = GrayFailureRule::parse_option(value).unwrap();
+                GrayFailureRule::from_config(&config, &PORTS)
+                    .unwrap()
+                    .target
+            })
+            .collect();
+        assert_eq!(targets, [Target::Link(60000, 60002), Target::Node(60001)]);
+
+        for invalid in ["lossy-wan", "lossy-wan@x", "unknown@1"] {
+            assert!(GrayFailureRule::parse_option(invalid).is_err());
+        }
+        let unknown_node = GrayFailureRule::parse_option("slow-leader@3").unwrap();
+        assert!(GrayFailureRule::from_config(&unknown_node, &PORTS).is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn invalid_parameters_are_rejected() {
+        let rule = |preset| GrayFailureRule {
+            preset,
+            target: Target::Node(60000),
+        };
+        assert!(rule(Preset::LossyWan {
+            latency_ms: None,
+            jitter_ms: None,
+            loss: Some(1.5),
+        })
+        .validate()
+        .is_err());
+        assert!(rule(Preset::IntermittentPartition {
+            up_ms: Some(1000),
+            down_ms: Some(0),
+        })
+        .validate()
+        .is_err());
+        assert!(rule(Preset::SlowLeader {
+            latency_ms: None,
+            proposal_rate: Some(0.0),
+        })
+        .validate()
+        .is_err());
+        assert!(rule("flaky-disk-node".parse().unwrap()).validate().is_ok());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn outages_recur_on_the_targeted_links() {
+        let start = Instant::now();
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut failures = GrayFailures::new(
+            vec![
+                GrayFailureRule {
+                    preset: Preset::IntermittentPartition {
+                        up_ms: Some(1000),
+                        down_ms: Some(500),
+                    },
+                    target: Target::Link(60000, 60001),
+                },
+                GrayFailureRule {
+                    preset: Preset::FlakyDiskNode {
+                        every_ms: Some(1000),
+                        stall_ms: Some(200),
+                        fetch_rate: None,
+                    },
+                    target: Target::Node(60002),
+                },
+            ],
+            start,
+        )
+        .unwrap();
+        let mut impair = |from, to, ms| {
+            failures.impair(
+                from,
+                to,
+                MessageType::Validation,
+                start + Duration::from_millis(ms),
+                &mut rng,
+            )
+        };
+        let sent = Some(Shaped::Delayed(Duration::ZERO));
+        assert_eq!(impair(60000, 60001, 999), sent);
+        assert_eq!(impair(60000, 60001, 1000), Some(Shaped::Dropped));
+        assert_eq!(impair(60000, 60001, 1499), Some(Shaped::Dropped));
+        assert_eq!(impair(60000, 60001, 1500), sent);
+        // The link is targeted in one direction only
+        assert_eq!(impair(60001, 60000, 1200), None);
+
+        // A stalled node holds the messages it sends and receives until the stall ends
+        assert_eq!(impair(60002, 60000, 500), sent);
+        assert_eq!(
+            impair(60002, 60000, 850),
+            Some(Shaped::Delayed(Duration::from_millis(150)))
+        );
+        assert_eq!(
+            impair(60001, 60002, 1900),
+            Some(Shaped::Delayed(Duration::from_millis(100)))
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn latency_jitter_and_throttling_combine() {
+        let start = Instant::now();
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut failures = GrayFailures::new(
+            vec![
+                GrayFailureRule {
+                    preset: Preset::LossyWan {
+                        latency_ms: Some(100),
+                        jitter_ms: Some(50),
+                        loss: Some(0.0),
+                    },
+                    target: Target::Node(60000),
+                },
+                GrayFailureRule {
+                    preset: Preset::SlowLeader {
+                        latency_ms: Some(300),
+                        proposal_rate: Some(2.0),
+                    },
+                    target: Target::Node(60000),
+                },
+            ],
+            start,
+        )
+        .unwrap();
+        let mut delays = Vec::new();
+        for _ in 0..3 {
+            match failures.impair(60000, 60001, MessageType::ProposeLedger, start, &mut rng) {
+                Some(Shaped::Delayed(delay)) => delays.push(delay),
+                impaired => panic!("Unexpected {:?}", impaired),
+            }
+        }
+        // Each proposal is throttled 500 ms more than the one before it, on top of the latency and the jitter
+        for (i, delay) in delays.iter().enumerate() {
+            let base = Duration::from_millis(400 + 500 * i as u64);
+            assert!(*delay >= base && *delay <= base + Duration::from_millis(50));
+        }
+    }
+}
@@ -0,0 +1,264 @@
+//! This module is responsible for the gRPC server of the interceptor, through which the controller can query the run
+//! while it is going on, e.g. to escalate its delays until consensus slows down measurably, and through which the
+//! previous interceptor in a chain relays its messages, see `relay`.
+//!
+//! The statistics of every link are kept per direction: the messages from node A to node B and those from B to A are
+//! separate links. The counters cover the whole run, the latency percentiles the most recently handled messages, as
+//! they are kept in the timeline.
+
+use crate::bench::percentile_of;
+use crate::interceptor_state::InterceptorState;
+use crate::message_type::MessageType;
+use crate::packet_client::proto::interceptor_service_server::{
+    InterceptorService, InterceptorServiceServer,
+};
+use crate::packet_client::proto::{
+    LatencyPercentiles, LinkStats, MessageTypeStats, Packet, RelayDecision, Stats, StatsRequest,
+};
+use crate::packet_client::PacketClient;
+use crate::packet_timeline::PacketRecord;
+use crate::relay;
+use chrono::{Duration as ChronoDuration, Utc};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+use tracing::{error, info};
+
+/// Struct that represents the latencies of a set of handled messages.
+#[derive(Debug, Default)]
+struct Latencies {
+    /// From reading every message until writing it.
+    latency: Vec<Duration>,
+    /// Of the decisions of the controller, only for intercepted messages.
+    controller_latency: Vec<Duration>,
+}
+
+impl Latencies {
+    /// Adds the latencies of a handled message.
+    ///
+    /// # Parameters
+    /// * 'record' - the record of the message.
+    fn add(&mut self, record: &PacketRecord) {
+        self.latency.push(record.latency);
+        if let Some(controller_latency) = record.controller_latency {
+            self.controller_latency.push(controller_latency);
+        }
+    }
+
+    /// Returns the percentiles of the latency and the controller latency.
+    fn percentiles(mut self) -> (LatencyPercentiles, LatencyPercentiles) {
+        (
+            percentiles(&mut self.latency),
+            percentiles(&mut self.controller_latency),
+        )
+    }
+}
+
+/// Returns the percentiles of a set of latencies.
+///
+/// # Parameters
+/// * 'latencies' - the latencies, which are sorted.
+fn percentiles(latencies: &mut [Duration]) -> LatencyPercentiles {
+    latencies.sort();
+    let ms = |percentile| percentile_of(latencies, percentile).as_secs_f64() * 1000.0;
+    LatencyPercentiles {
+        samples: latencies.len() as u64,
+        p50_ms: ms(50.0),
+        p90_ms: ms(90.0),
+        p99_ms: ms(99.0),
+        max_ms: ms(100.0),
+    }
+}
+
+/// Collects the statistics of every link, per message type.
+///
+/// # Parameters
+/// * 'state' - the runtime state, containing the statistics and the timeline.
+/// * 'window' - the latencies are computed from the messages handled this long ago, from all messages in the timeline if None.
+pub fn stats(state: &InterceptorState, window: Option<Duration>) -> Stats {
+    let since =
+        window.map(|window| Utc::now() - ChronoDuration::milliseconds(window.as_millis() as i64));
+    let mut link_latencies: HashMap<(u16, u16), Latencies> = HashMap::new();
+    let mut type_latencies: HashMap<(u16, u16, MessageType), Latencies> = HashMap::new();
+    for record in state
+        .timeline
+        .latest(usize::MAX)
+        .iter()
+        .filter(|record| since.map_or(true, |since| record.timestamp >= since))
+    {
+        let link = (record.from_port, record.to_port);
+        link_latencies.entry(link).or_default().add(record);
+        type_latencies
+            .entry((link.0, link.1, record.message_type))
+            .or_default()
+            .add(record);
+    }
+
+    let now = Utc::now();
+    let summary = state.statistics.summarize(now, now, None);
+    let mut message_types = state.statistics.link_message_types();
+    let links = summary
+        .links
+        .iter()
+        .map(|link| {
+            let (latency, controller_latency) = link_latencies
+                .remove(&(link.from_port, link.to_port))
+                .unwrap_or_default()
+                .percentiles();
+            LinkStats {
+                from_port: u32::from(link.from_port),
+                to_port: u32::from(link.to_port),
+                counts: Some(link.counts.to_proto()),
+                latency: Some(latency),
+                controller_latency: Some(controller_latency),
+                message_types: message_types
+                    .remove(&(link.from_port, link.to_port))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|type_counts| {
+                        let (latency, controller_latency) = type_latencies
+                            .remove(&(link.from_port, link.to_port, type_counts.message_type))
+                            .unwrap_or_default()
+                            .percentiles();
+                        MessageTypeStats {
+                            message_type: type_counts.message_type.to_string(),
+                            counts: Some(type_counts.counts.to_proto()),
+                            latency: Some(latency),
+                            controller_latency: Some(controller_latency),
+                        }
+                    })
+                    .collect(),
+            }
+        })
+        .collect();
+    Stats {
+        links,
+        totals: Some(summary.totals.to_proto()),
+        errors: summary.errors.into_iter().collect(),
+    }
+}
+
+/// Struct that represents the implementation of the gRPC service of the interceptor.
+#[derive(Debug)]
+pub struct InterceptorServer {
+    state: Arc<InterceptorState>,
+    /// The PacketClient used to ask the controller about relayed messages.
+    client: Arc<Mutex<PacketClient>>,
+}
+
+#[tonic::async_trait]
+impl InterceptorService for InterceptorServer {
+    async fn get_stats(&self, request: Request<StatsRequest>) -> Result<Response<Stats>, Status> {
+        let window_ms = request.into_inner().window_ms;
+        let window = (window_ms > 0).then(|| Duration::from_millis(u64::from(window_ms)));
+        Ok(Response::new(stats(&self.state, window)))
+    }
+
+    async fn relay_packet(
+        &self,
+        request: Request<Packet>,
+    ) -> Result<Response<RelayDecision>, Status> {
+        relay::decide_relayed(
+            request.into_inner(),
+            self.client.clone(),
+            self.state.clone(),
+        )
+        .await
+        .map(Response::new)
+    }
+}
+
+/// Serves the gRPC service of the interceptor until the server fails.
+///
+/// # Parameters
+/// * 'address' - the address the server listens on.
+/// * 'state' - the runtime state that is queried, and decides on relayed messages.
+/// * 'client' - the PacketClient used to ask the controller about relayed messages.
+pub async fn serve(
+    address: SocketAddr,
+    state: Arc<InterceptorState>,
+    client: Arc<Mutex<PacketClient>>,
+) {
+    info!("Serving the gRPC service of the interceptor on {}", address);
+    if let Err(e) = Server::builder()
+        .add_service(InterceptorServiceServer::new(InterceptorServer {
+            state,
+            client,
+        }))
+        .serve(address)
+        .await
+    {
+        error!("The gRPC service of the interceptor stopped: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::grpc_server::stats;
+    use crate::interceptor_state::InterceptorState;
+    use crate::message_type::MessageType;
+    use crate::packet_timeline::{PacketRecord, PacketTimeline};
+    use chrono::Duration as ChronoDuration;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn record(to_port: u16, message_type: MessageType, latency_ms: u64) -> PacketRecord {
+        PacketRecord {
+            to_port,
+            message_type,
+            controller_latency: Some(Duration::from_millis(1)),
+            latency: Duration::from_millis(latency_ms),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn stats_per_link_and_message_type() {
+        let state = InterceptorState::new(Arc::new(PacketTimeline::new(100)));
+        let handle = |record: PacketRecord| {
+            state.statistics.tally(&record);
+            state.timeline.push(record);
+        };
+        for latency_ms in 1..=10 {
+            handle(record(60001, MessageType::Validation, latency_ms));
+        }
+        handle(record(60001, MessageType::Ping, 100));
+        handle(record(60002, MessageType::Validation, 5));
+        let mut old = record(60002, MessageType::Validation, 500);
+        old.timestamp -= ChronoDuration::seconds(60);
+        handle(old);
+
+        let all = stats(&state, None);
+        assert_eq!(all.links.len(), 2);
+        assert_eq!(all.totals.unwrap().handled, 13);
+        let link = &all.links[0];
+        assert_eq!((link.from_port, link.to_port), (60000, 60001));
+        assert_eq!(link.counts.as_ref().unwrap().handled, 11);
+        assert_eq!(link.latency.as_ref().unwrap().max_ms, 100.0);
+        assert_eq!(link.message_types[0].message_type, "mtVALIDATION");
+        let validations = link.message_types[0].latency.as_ref().unwrap();
+        assert_eq!(validations.samples, 10);
+        assert_eq!(validations.p50_ms, 5.0);
+        assert_eq!(validations.p90_ms, 9.0);
+        assert_eq!(
+            link.message_types[0]
+                .controller_latency
+                .as_ref()
+                .unwrap()
+                .p99_ms,
+            1.0
+        );
+
+        // The counters cover the whole run, the latencies only the window
+        let recent = stats(&state, Some(Duration::from_secs(10)));
+        let link = &recent.links[1];
+        assert_eq!(link.counts.as_ref().unwrap().handled, 2);
+        assert_eq!(link.latency.as_ref().unwrap().samples, 1);
+        assert_eq!(link.latency.as_ref().unwrap().max_ms, 5.0);
+    }
+}
@@ -0,0 +1,501 @@
+//! This module is responsible for parsing the response of a peer to the HTTP upgrade request of the handshake.
+//!
+//! The response consists of untrusted bytes from the node, so parsing never panics: every response that is not a valid
+//! '101 Switching Protocols' is classified as a handshake error. The module only depends on the protocol versions, such
+//! that it can be fuzzed on its own, see the `fuzz` directory.
+
+use crate::protocol_version::ProtocolVersion;
+use bytes::{Buf, BytesMut};
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+use tracing::{debug, error};
+
+/// Struct that represents the headers of the response of a peer to the upgrade request.
+/// Headers that are missing or could not be parsed are None.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HandshakeInfo {
+    /// The protocol the peer selected, e.g. 'XRPL/2.2'.
+    pub protocol: Option<String>,
+    /// The protocol version the peer selected, if it could be parsed.
+    pub version: Option<ProtocolVersion>,
+    /// The software version of the peer, e.g. 'rippled-2.1.1'.
+    pub server: Option<String>,
+    /// The node public key of the peer.
+    pub public_key: Option<String>,
+    /// The ID of the network of the peer.
+    pub network_id: Option<u32>,
+    /// The network time of the peer, in seconds since the Ripple epoch.
+    pub network_time: Option<u64>,
+    /// The hash of the last closed ledger of the peer.
+    pub closed_ledger: Option<String>,
+    /// The hash of the ledger before the last closed ledger of the peer.
+    pub previous_ledger: Option<String>,
+    /// Whether the peer allows to be crawled.
+    pub crawl: Option<bool>,
+    /// The random cookie that identifies the running instance of the peer.
+    pub instance_cookie: Option<u64>,
+}
+
+impl HandshakeInfo {
+    /// Collects the known headers of a response to the upgrade request. Header names are case-insensitive.
+    ///
+    /// # Parameters
+    /// * 'headers' - the parsed headers of the response.
+    fn from_headers(headers: &[httparse::Header]) -> Self {
+        let mut info = Self::default();
+        for header in headers.iter().filter(|h| **h != httparse::EMPTY_HEADER) {
+            let value = String::from_utf8_lossy(header.value).trim().to_string();
+            match header.name.to_ascii_lowercase().as_str() {
+                "upgrade" => {
+                    info.version = value.parse().ok();
+                    info.protocol = Some(value);
+                }
+                "server" => info.server = Some(value),
+                "public-key" => info.public_key = Some(value),
+                "network-id" => info.network_id = value.parse().ok(),
+                "network-time" => info.network_time = value.parse().ok(),
+                "closed-ledger" => info.closed_ledger = Some(value),
+                "previous-ledger" => info.previous_ledger = Some(value),
+                "crawl" => info.crawl = Some(value.eq_ignore_ascii_case("public")),
+                "instance-cookie" => info.instance_cookie = value.parse().ok(),
+                _ => (),
+            }
+        }
+        info
+    }
+}
+
+/// Enum that represents the reasons a handshake with a peer can fail.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HandshakeError {
+    /// The connection could not be established, or failed or was closed during the handshake.
+    Connection(String),
+    /// The response of the peer could not be parsed.
+    InvalidResponse(String),
+    /// The peer has no free slots for peers.
+    SlotsFull {
+        /// How long the peer asked to wait before trying again.
+        retry_after: Option<Duration>,
+    },
+    /// The peer is temporarily unable to accept connections.
+    ServiceUnavailable {
+        /// How long the peer asked to wait before trying again.
+        retry_after: Option<Duration>,
+    },
+    /// The peer is on a different network, containing the reason given by the peer.
+    WrongNetworkId(String),
+    /// The peer could not verify the public key or session signature, containing the reason given by the peer.
+    BadSignature(String),
+    /// The peer selected a protocol version that was not offered.
+    UnexpectedProtocol(ProtocolVersion),
+    /// A stage of the handshake did not finish in time.
+    Timeout {
+        /// The stage that timed out: 'connect', 'TLS handshake' or 'HTTP upgrade'.
+        stage: &'static str,
+        /// How long the stage was allowed to take.
+        timeout: Duration,
+    },
+    /// The peer rejected the handshake for another reason.
+    Rejected {
+        /// The HTTP status code of the response.
+        status: u16,
+        /// The body of the response.
+        body: String,
+    },
+}
+
+impl HandshakeError {
+    /// Classifies a response to the upgrade request that is not '101 Switching Protocols'.
+    ///
+    /// # Parameters
+    /// * 'status' - the HTTP status code of the response.
+    /// * 'reason' - the reason phrase of the response.
+    /// * 'retry_after' - the value of the Retry-After header, if any.
+    /// * 'body' - the body of the response.
+    fn from_rejection(status: u16, reason: &str, retry_after: Option<&str>, body: &str) -> Self {
+        let retry_after = retry_after
+            .and_then(|value| value.trim().parse().ok())
+            .map(Duration::from_secs);
+        let reason = reason.to_ascii_lowercase();
+        let lowercase_body = body.to_ascii_lowercase();
+        match status {
+            // rippled redirects to other peers when its slots are full
+            503 if reason.contains("slots")
+                || lowercase_body.contains("slots")
+                || lowercase_body.contains("peer-ips") =>
+            {
+                Self::SlotsFull { retry_after }
+            }
+            503 => Self::ServiceUnavailable { retry_after },
+            _ if lowercase_body.contains("network") => Self::WrongNetworkId(body.to_string()),
+            _ if lowercase_body.contains("signature")
+                || lowercase_body.contains("public key")
+                || lowercase_body.contains("verify") =>
+            {
+                Self::BadSignature(body.to_string())
+            }
+            _ => Self::Rejected {
+                status,
+                body: body.to_string(),
+            },
+        }
+    }
+
+    /// Returns whether trying again later could succeed.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::Connection(_)
+                | Self::SlotsFull { .. }
+                | Self::ServiceUnavailable { .. }
+                | Self::Timeout { .. }
+        )
+    }
+
+    /// Returns how long the peer asked to wait before trying again, if it did.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::SlotsFull { retry_after } | Self::ServiceUnavailable { retry_after } => {
+                *retry_after
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Connection(e) => write!(f, "connection failed: {}", e),
+            Self::InvalidResponse(e) => write!(f, "invalid response: {}", e),
+            Self::SlotsFull { .. } => write!(f, "the peer slots are full"),
+            Self::ServiceUnavailable { .. } => write!(f, "the peer is unavailable"),
+            Self::WrongNetworkId(body) => write!(f, "the peer is on a different network: {}", body),
+            Self::BadSignature(body) => write!(f, "the peer rejected the signature: {}", body),
+            Self::UnexpectedProtocol(version) => {
+                write!(f, "the peer selected {}, which was not offered", version)
+            }
+            Self::Timeout { stage, timeout } => {
+                write!(f, "the {} did not finish within {:?}", stage, timeout)
+            }
+            Self::Rejected { status, body } => {
+                write!(f, "rejected with status code {}: {}", status, body)
+            }
+        }
+    }
+}
+
+impl Error for HandshakeError {}
+
+/// Checks given a buffered HTTP response, whether it is a valid 101 switching protocol response.
+/// Returns the headers of the response, or the reason the handshake failed. Never panics, whatever the peer sent.
+///
+/// # Parameters
+/// * 'buffered_response' - the response to the upgrade request.
+pub fn parse(mut buffered_response: BytesMut) -> Result<HandshakeInfo, HandshakeError> {
+    let Some(n) = buffered_response.windows(4).position(|x| x == b"\r\n\r\n") else {
+        return Err(HandshakeError::InvalidResponse(
+            "could not separate the HTTP headers from the body".to_string(),
+        ));
+    };
+    let mut headers = [httparse::EMPTY_HEADER; 32];
+    let mut response = httparse::Response::new(&mut headers);
+
+    let status = response
+        .parse(&buffered_response[0..n + 4])
+        .map_err(|e| HandshakeError::InvalidResponse(e.to_string()))?;
+
+    if status.is_partial() {
+        return Err(HandshakeError::InvalidResponse(
+            "could not fully parse the response".to_string(),
+        ));
+    }
+
+    let response_status_code = response.code.unwrap_or_default();
+    let reason = response.reason.unwrap_or_default().to_string();
+
+    debug!(
+        "Peer Handshake Response: version: {}, status: {}, reason: {}",
+        response.version.unwrap_or_default(),
+        &response_status_code,
+        reason
+    );
+
+    debug!("Response headers:");
+    for header in headers.iter().filter(|h| **h != httparse::EMPTY_HEADER) {
+        debug!("{}: {}", header.name, String::from_utf8_lossy(header.value));
+    }
+
+    buffered_response.advance(n + 4);
+    let body = String::from_utf8_lossy(&buffered_response)
+        .trim()
+        .to_string();
+
+    // HTTP code 101: Switching Protocols
+    if response_status_code != 101 {
+        let retry_after = headers
+            .iter()
+            .find(|h| h.name.eq_ignore_ascii_case("Retry-After"))
+            .map(|h| String::from_utf8_lossy(h.value).to_string());
+        error!(
+            "Response status code expected to be 101 but was: {}\nBody of the response: {}",
+            response_status_code, body
+        );
+        return Err(HandshakeError::from_rejection(
+            response_status_code,
+            &reason,
+            retry_after.as_deref(),
+            &body,
+        ));
+    }
+
+    if !body.is_empty() {
+        debug!(
+            "Switching protocol response has an (unexpected) body: {}",
+            body
+        );
+    }
+
+    Ok(HandshakeInfo::from_headers(&headers))
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::handshake_response::{parse, HandshakeError, HandshakeInfo};
+    use crate::protocol_version::ProtocolVersion;
+    use bytes::BytesMut;
+    use std::time::Duration;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_handshake_info() {
+        let mut buffer = BytesMut::new();
+        buffer.extend_from_slice(
+            b"HTTP/1.1 101 Switching Protocols\r\n\
+            Upgrade: XRPL/2.2\r\n\
+            Server: rippled-2.1.1\r\n\
+            Crawl: private\r\n\
+            Network-ID: 21338\r\n\
+            Network-Time: 770391649\r\n\
+            Public-Key: n9M1Fh52PBMSrEjjs8Y64EmU8hfVzb29BBDaXoVNS3AaC1gM19CP\r\n\
+            Instance-Cookie: 16110088623413850902\r\n\
+            Closed-Ledger: 2D7DE9661AADBCDC6DD6630F0C616F5BE29803A5A5DC31486DD65E0F6A79DDB1\r\n\r\n",
+        );
+
+        let info = parse(buffer).unwrap();
+        assert_eq!(
+            info,
+            HandshakeInfo {
+                protocol: Some("XRPL/2.2".to_string()),
+                version: Some(ProtocolVersion::new(2, 2)),
+                server: Some("rippled-2.1.1".to_string()),
+                public_key: Some(
+                    "n9M1Fh52PBMSrEjjs8Y64EmU8hfVzb29BBDaXoVNS3AaC1gM19CP".to_string()
+                ),
+                network_id: Some(21338),
+                network_time: Some(770391649),
+                closed_ledger: Some(
+                    "2D7DE9661AADBCDC6DD6630F0C616F5BE29803A5A5DC31486DD65E0F6A79DDB1".to_string()
+                ),
+                previous_ledger: None,
+                crawl: Some(false),
+                instance_cookie: Some(16110088623413850902),
+            }
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_no_panic() {
+        let response = b"\
+            HTTP/1.1 101 Switching Protocol\r\n
+            Connection: Upgrade\r\n\
+            Upgrade: XRPL/2.2\r\n
+            Connect-As: Peer\r\n
+            Server: rippled-2.1.1\r\n
+            Crawl: private\r\n
+            X-Protocol-Ctl:\r\n
+            Network-Time: 770391649\r\n
+            Public-Key: n9M1Fh52PBMSrEjjs8Y64EmU8hfVzb29BBDaXoVNS3AaC1gM19CP\r\n
+            Session-Signature: MEUCIQCBsA3JThSv4geQ67ZlrLvBZGO0wiWWU5pfDsiKalvwKQIgb6CuAHAYnxGf4MYB4Jgsbox4of5GxT4IbRPWablVQ9w=\r\n\
+            Instance-Cookie: 16110088623413850902\r\n
+            Closed-Ledger: 2D7DE9661AADBCDC6DD6630F0C616F5BE29803A5A5DC31486DD65E0F6A79DDB1\r\n
+            Previous-Ledger: 0000000000000000000000000000000000000000000000000000000000000000\r\n\r\n
+        ";
+
+        let mut buffer = BytesMut::new();
+        buffer.extend_from_slice(response);
+        assert!(parse(buffer).is_ok());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_invalid_request() {
+        let mut buffer = BytesMut::new();
+        buffer.extend_from_slice(b"garbage\r\n\r\n");
+        assert!(matches!(
+            parse(buffer),
+            Err(HandshakeError::InvalidResponse(_))
+        ));
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_invalid_response() {
+        let mut buffer = BytesMut::new();
+        let data = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+        buffer.extend_from_slice(&data);
+        assert_eq!(
+            parse(buffer),
+            Err(HandshakeError::InvalidResponse(
+                "could not separate the HTTP headers from the body".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_wrong_status_code() {
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(b"HTTP/1.1 404 Not Found\r\n\r\n<body message>");
+
+        assert_eq!(
+            parse(buf),
+            Err(HandshakeError::Rejected {
+                status: 404,
+                body: "<body message>".to_string()
+            })
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_slots_full() {
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(
+            b"HTTP/1.1 503 Service Unavailable\r\nRetry-After: 3\r\n\r\n{\"peer-ips\":[]}",
+        );
+
+        let error = parse(buf).unwrap_err();
+        assert_eq!(
+            error,
+            HandshakeError::SlotsFull {
+                retry_after: Some(Duration::from_secs(3))
+            }
+        );
+        assert!(error.is_retryable());
+        assert_eq!(error.retry_after(), Some(Duration::from_secs(3)));
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn classify_rejections() {
+        assert_eq!(
+            HandshakeError::from_rejection(503, "Service Unavailable", None, ""),
+            HandshakeError::ServiceUnavailable { retry_after: None }
+        );
+        let wrong_network = HandshakeError::from_rejection(
+            400,
+            "Bad Request",
+            None,
+            "Peer is on a different network",
+        );
+        assert!(matches!(wrong_network, HandshakeError::WrongNetworkId(_)));
+        assert!(!wrong_network.is_retryable());
+        assert!(matches!(
+            HandshakeError::from_rejection(400, "Bad Request", None, "Invalid session signature"),
+            HandshakeError::BadSignature(_)
+        ));
+    }
+
+    /// Reads the response of a recorded handshake exchange, see `tests/fixtures/handshake`.
+    fn golden_response(case: &str) -> BytesMut {
+        let path = format!(
+            "{}/tests/fixtures/handshake/{}/response.http",
+            env!("CARGO_MANIFEST_DIR"),
+            case
+        );
+        let response =
+            std::fs::read(&path).unwrap_or_else(|e| panic!("Could not read {}: {}", path, e));
+        BytesMut::from(&response[..])
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn golden_responses() {
+        let switching =
+            |protocol: &str, server: &str, network_time, instance_cookie| HandshakeInfo {
+                protocol: Some(protocol.to_string()),
+                version: protocol.parse().ok(),
+                server: Some(server.to_string()),
+                public_key: Some(
+                    "n9M1Fh52PBMSrEjjs8Y64EmU8hfVzb29BBDaXoVNS3AaC1gM19CP".to_string(),
+                ),
+                network_id: None,
+                network_time: Some(network_time),
+                closed_ledger: Some(
+                    "2D7DE9661AADBCDC6DD6630F0C616F5BE29803A5A5DC31486DD65E0F6A79DDB1".to_string(),
+                ),
+                previous_ledger: Some(
+                    "6F37B6B2DA3A5D6B1ED8E2D0F5C8A4EE36F3E30A6DF3B2EAF2A5E3F8BC0E71D4".to_string(),
+                ),
+                crawl: Some(false),
+                instance_cookie: Some(instance_cookie),
+            };
+        let cases = [
+            (
+                "rippled-1.12.0",
+                Ok(switching(
+                    "XRPL/2.2",
+                    "rippled-1.12.0",
+                    770391641,
+                    3208467520316553187,
+                )),
+            ),
+            (
+                "rippled-2.1.1",
+                Ok(HandshakeInfo {
+                    network_id: Some(21338),
+                    crawl: Some(true),
+                    ..switching("XRPL/2.2", "rippled-2.1.1", 770391649, 16110088623413850902)
+                }),
+            ),
+            (
+                "rippled-2.2.3-protocol-2.1",
+                Ok(switching(
+                    "XRPL/2.1",
+                    "rippled-2.2.3",
+                    770391701,
+                    9713514519474052421,
+                )),
+            ),
+            (
+                "rippled-2.2.3-slots-full",
+                Err(HandshakeError::SlotsFull { retry_after: None }),
+            ),
+            (
+                "rippled-2.0.1-wrong-network",
+                Err(HandshakeError::WrongNetworkId(
+                    "Peer is on a different network".to_string(),
+                )),
+            ),
+            (
+                "rippled-2.2.3-bad-signature",
+                Err(HandshakeError::BadSignature(
+                    "Unable to verify handshake: Failed to verify session".to_string(),
+                )),
+            ),
+            (
+                "rippled-2.2.3-no-common-protocol",
+                Err(HandshakeError::Rejected {
+                    status: 400,
+                    body: "Unable to agree on a protocol version".to_string(),
+                }),
+            ),
+        ];
+        for (case, expected) in cases {
+            assert_eq!(parse(golden_response(case)), expected, "{}", case);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! This module is responsible for the heartbeats sent to the controller at a fixed interval while the interceptor runs.
+//!
+//! Every heartbeat carries the health of the interceptor, the state and counters of all links and the depths of all
+//! queues, such that the controller can tell a network that went quiet from an interceptor that died, and abort the
+//! experiment early. Heartbeats are streamed over a single call, so the controller also notices when the stream ends.
+//! If the controller is not keeping up with the stream, heartbeats are skipped rather than queued.
+
+use crate::event_bus::{Event, EventKind};
+use crate::interceptor_state::InterceptorState;
+use crate::packet_client::proto::{Heartbeat, LinkHealth, QueueDepth};
+use crate::packet_client::PacketClient;
+use chrono::Utc;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{broadcast, mpsc, Mutex};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::Code;
+use tracing::{debug, info, warn};
+
+/// Updates the states of the links with a link-state event.
+///
+/// # Parameters
+/// * 'link_states' - the state of every link that had an event, by the ports of the peer its messages come from and go to.
+/// * 'event' - the event.
+fn track(link_states: &mut HashMap<(u16, u16), &'static str>, event: &EventKind) {
+    let (link, link_state) = match event {
+        EventKind::LinkConnected {
+            from_port, to_port, ..
+        }
+        | EventKind::LinkResumed {
+            from_port, to_port, ..
+        } => ((*from_port, *to_port), "connected"),
+        EventKind::LinkIdle {
+            from_port, to_port, ..
+        } => ((*from_port, *to_port), "idle"),
+        EventKind::BreakpointHit(hit) => ((hit.from_port, hit.to_port), "halted"),
+        EventKind::LinkDropped {
+            from_port, to_port, ..
+        } => ((*from_port, *to_port), "dropped"),
+        _ => return,
+    };
+    link_states.insert(link, link_state);
+}
+
+/// Builds a heartbeat from the current state of the interceptor.
+///
+/// # Parameters
+/// * 'state' - the runtime state, containing the links, queues and statistics.
+/// * 'link_states' - the state of every link that had an event, links without events are connected.
+/// * 'sequence' - the position of the heartbeat in the stream.
+/// * 'uptime' - the time since the links were started.
+fn heartbeat(
+    state: &InterceptorState,
+    link_states: &HashMap<(u16, u16), &'static str>,
+    sequence: u64,
+    uptime: Duration,
+) -> Heartbeat {
+    let now = Utc::now();
+    let summary = state.statistics.summarize(now, now, None);
+    Heartbeat {
+        sequence,
+        timestamp_ns: now.timestamp_nanos_opt().unwrap_or_default() as u64,
+        uptime_ms: uptime.as_millis() as u64,
+        paused: state.is_paused(),
+        passthrough: state.is_passthrough(),
+        handled: summary.totals.handled,
+        links: state
+            .links()
+            .iter()
+            .map(|link| LinkHealth {
+                from_port: u32::from(link.from_port),
+                to_port: u32::from(link.to_port),
+                state: link_states
+                    .get(&(link.from_port, link.to_port))
+                    .copied()
+                    .unwrap_or("connected")
+                    .to_string(),
+                handled: link.handled(),
+                dropped: link.dropped(),
+                controller_rtt_ms: link
+                    .controller_rtt()
+                    .map_or(0.0, |rtt| rtt.as_secs_f64() * 1000.0),
+            })
+            .collect(),
+        queues: state
+            .queue_gauges()
+            .iter()
+            .map(|gauge| QueueDepth {
+                name: gauge.name.clone(),
+                depth: gauge.depth() as u64,
+                capacity: gauge.capacity as u64,
+                dropped: gauge.dropped(),
+            })
+            .collect(),
+        errors: summary.errors.into_iter().collect(),
+        circuit_breaker: state
+            .circuit_breaker()
+            .map(|breaker| breaker.summary().state.to_string())
+            .unwrap_or_default(),
+    }
+}
+
+/// Streams a heartbeat to the controller at every interval, until the controller ends the stream or the event bus is
+/// closed. Controllers that do not implement heartbeats are skipped.
+///
+/// # Parameters
+/// * 'client' - the PacketClient whose connection to the controller is used.
+/// * 'state' - the runtime state the heartbeats are built from.
+/// * 'events' - the receiver of the events, subscribed before the links are started, to follow the state of the links.
+/// * 'interval' - the time between two heartbeats.
+pub async fn run(
+    client: Arc<Mutex<PacketClient>>,
+    state: Arc<InterceptorState>,
+    mut events: broadcast::Receiver<Arc<Event>>,
+    interval: Duration,
+) {
+    let (sender, receiver) = mpsc::channel(1);
+    let (mut service, request) = {
+        let client = client.lock().await;
+        (
+            client.client.clone(),
+            client.request(ReceiverStream::new(receiver)),
+        )
+    };
+    let stream = tokio::spawn(async move { service.send_heartbeats(request).await });
+    let started = Instant::now();
+    let mut ticks = tokio::time::interval(interval);
+    let mut link_states = HashMap::new();
+    let mut sequence = 0;
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => track(&mut link_states, &event.kind),
+                Err(RecvError::Lagged(missed)) => warn!("Heartbeats missed {} events", missed),
+                Err(RecvError::Closed) => break,
+            },
+            _ = ticks.tick() => {
+                match sender.try_send(heartbeat(&state, &link_states, sequence, started.elapsed())) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(_)) => debug!("Skipped heartbeat {}, the controller is behind", sequence),
+                    Err(TrySendError::Closed(_)) => break,
+                }
+                sequence += 1;
+            }
+        }
+    }
+    drop(sender);
+    match stream.await {
+        Ok(Ok(_)) => info!("The controller ended the heartbeats"),
+        Ok(Err(status)) if status.code() == Code::Unimplemented => {
+            debug!("The controller does not receive heartbeats");
+        }
+        Ok(Err(status)) => {
+            warn!("The heartbeats to the controller stopped: {}", status);
+            state.statistics.count_error("heartbeat_failed", 1);
+        }
+        Err(e) => warn!("The heartbeats to the controller stopped: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::event_bus::EventKind;
+    use crate::heartbeat::{heartbeat, track};
+    use crate::interceptor_state::InterceptorState;
+    use crate::packet_timeline::PacketTimeline;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn heartbeat_contains_links_and_queues() {
+        let state = InterceptorState::new(Arc::new(PacketTimeline::new(10)));
+        state.register_link(60000, 60001, None).count(true);
+        state.register_link(60001, 60000, None);
+        state.register_queue("write 60001".to_string(), 100);
+        state.statistics.count_error("rejected_action", 2);
+        let mut link_states = HashMap::new();
+        track(
+            &mut link_states,
+            &EventKind::LinkDropped {
+                from_port: 60001,
+                to_port: 60000,
+                reason: "closed".to_string(),
+            },
+        );
+        track(
+            &mut link_states,
+            &EventKind::PartitionChanged { partitions: vec![] },
+        );
+
+        let heartbeat = heartbeat(&state, &link_states, 3, Duration::from_secs(2));
+        assert_eq!(heartbeat.sequence, 3);
+        assert_eq!(heartbeat.uptime_ms, 2000);
+        assert_eq!(heartbeat.links.len(), 2);
+        assert_eq!(heartbeat.links[0].state, "connected");
+        assert_eq!(heartbeat.links[0].dropped, 1);
+        assert_eq!(heartbeat.links[1].state, "dropped");
+        assert_eq!(heartbeat.queues[0].capacity, 100);
+        assert_eq!(heartbeat.errors["rejected_action"], 2);
+    }
+}
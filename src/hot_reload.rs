@@ -0,0 +1,422 @@
+//! This module is responsible for the local rules of a run, and for reloading them while running.
+//!
+//! The local rules are the interception modes of the message types and links, the blackholes, the mutation rules, the shaping
+//! rules and the base latencies of the links. They are
+//! read from the configuration file at startup, and when hot reloading is configured the file is watched for changes.
+//! A changed file is parsed and validated as a whole, after which all its rules replace the running ones at once,
+//! without dropping any link. A file that can not be parsed or contains an invalid rule leaves the running rules as
+//! they are.
+
+use crate::config::InterceptorConfig;
+use crate::event_bus::EventKind;
+use crate::field_mutation::MutationRule;
+use crate::interception_policy::InterceptionPolicy;
+use crate::interceptor_state::{Blackhole, InterceptorState};
+use crate::latency_matrix::LatencyMatrix;
+use crate::traffic_shaping::ShapingRule;
+use notify::{Event, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{error, info};
+
+/// Struct that represents the local rules of a run, which can be replaced while running.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LocalRules {
+    /// The policy deciding which message types are sent to the controller.
+    pub policy: InterceptionPolicy,
+    /// The message types that are dropped per node.
+    pub blackholes: Vec<Blackhole>,
+    /// The rules by which messages are mutated locally.
+    pub mutation_rules: Vec<MutationRule>,
+    /// The rules that limit the rate of message types per link.
+    pub shaping_rules: Vec<ShapingRule>,
+    /// The base latency of every link, by the ports of the peer its messages come from and go to.
+    pub base_latencies: HashMap<(u16, u16), Duration>,
+}
+
+impl LocalRules {
+    /// Creates the local rules from the configuration. Returns an error if any rule is invalid.
+    ///
+    /// # Parameters
+    /// * 'config' - the configuration containing the rules.
+    /// * 'ports' - the peer ports of the nodes, by node ID.
+    pub fn from_config(config: &InterceptorConfig, ports: &[u16]) -> Result<Self, String> {
+        let port_of = |id: u32| {
+            ports
+                .get(id as usize)
+                .copied()
+                .ok_or(format!("node {} does not exist", id))
+        };
+        let mut policy = InterceptionPolicy::from_config(&config.interception)
+            .map_err(|e| format!("invalid interception configuration: {}", e))?;
+        for [from, to] in config.interception.one_way.iter().copied() {
+            let invalid = |e: String| format!("invalid interception configuration: {}", e);
+            let (from_port, to_port) = (
+                port_of(from).map_err(invalid)?,
+                port_of(to).map_err(invalid)?,
+            );
+            if from == to {
+                return Err(invalid(format!("node {} has no link to itself", from)));
+            }
+            if !policy.intercept_one_way(from_port, to_port) {
+                return Err(invalid(format!(
+                    "both directions between nodes {} and {} are intercepted one way",
+                    from, to
+                )));
+            }
+        }
+        let mut blackholes = Vec::new();
+        for rule in config
+            .blackhole
+            .iter()
+            .flat_map(|config| config.rules.iter())
+        {
+            let blackhole = Blackhole {
+                from_port: port_of(rule.node)
+                    .map_err(|e| format!("invalid blackhole configuration: {}", e))?,
+                message_type: rule
+                    .message_type
+                    .parse()
+                    .map_err(|e| format!("invalid blackhole configuration: {}", e))?,
+            };
+            if !blackholes.contains(&blackhole) {
+                blackholes.push(blackhole);
+            }
+        }
+        let mut mutation_rules = Vec::new();
+        for rule in config
+            .mutation
+            .iter()
+            .flat_map(|config| config.rules.iter())
+        {
+            let invalid = |e: String| format!("invalid mutation configuration: {}", e);
+            let mutation_rule = MutationRule {
+                message_type: rule.message_type.parse().map_err(invalid)?,
+                from_port: rule.from_node.map(port_of).transpose().map_err(invalid)?,
+                to_port: rule.to_node.map(port_of).transpose().map_err(invalid)?,
+                mutations: rule.mutations.clone(),
+                resign: rule.resign,
+            };
+            mutation_rule
+                .validate()
+                .map_err(|e| invalid(e.to_string()))?;
+            mutation_rules.push(mutation_rule);
+        }
+        let mut shaping_rules = Vec::new();
+        for rule in config.shaping.iter().flat_map(|config| config.rules.iter()) {
+            let invalid = |e: String| format!("invalid shaping configuration: {}", e);
+            let shaping_rule = ShapingRule {
+                message_type: rule.message_type.parse().map_err(invalid)?,
+                from_port: rule.from_node.map(port_of).transpose().map_err(invalid)?,
+                to_port: rule.to_node.map(port_of).transpose().map_err(invalid)?,
+                rate: rule.rate,
+                burst: rule.burst,
+                action: rule.action,
+            };
+            shaping_rule
+                .validate()
+                .map_err(|e| invalid(e.to_string()))?;
+            shaping_rules.push(shaping_rule);
+        }
+        let mut base_latencies = HashMap::new();
+        if let Some(latency_config) = &config.latency {
+            let invalid = |e: String| format!("invalid latency configuration: {}", e);
+            let matrix = LatencyMatrix::from_config(latency_config).map_err(invalid)?;
+            if matrix.size() != ports.len() {
+                return Err(invalid(format!(
+                    "the matrix has {} rows, but there are {} nodes",
+                    matrix.size(),
+                    ports.len()
+                )));
+            }
+            for (from, from_port) in ports.iter().enumerate() {
+                for (to, to_port) in ports.iter().enumerate() {
+                    if from != to {
+                        base_latencies.insert((*from_port, *to_port), matrix.latency(from, to));
+                    }
+                }
+            }
+        }
+        Ok(Self {
+            policy,
+            blackholes,
+            mutation_rules,
+            shaping_rules,
+            base_latencies,
+        })
+    }
+}
+
+/// Reloads the local rules whenever the file they are read from changes, until the watcher fails.
+/// Changes that follow each other within the debounce time are reloaded once.
+///
+/// # Parameters
+/// * 'path' - the path of the file the rules are read from.
+/// * 'ports' - the peer ports of the nodes, by node ID.
+/// * 'debounce' - how long to wait for further changes before reloading.
+/// * 'state' - the runtime state the rules are applied to.
+pub async fn watch(
+    path: PathBuf,
+    ports: Vec<u16>,
+    debounce: Duration,
+    state: Arc<InterceptorState>,
+) {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let file_name = path.file_name().map(|name| name.to_os_string());
+    let mut watcher = match notify::recommended_watcher(move |result: notify::Result<Event>| {
+        if let Ok(event) = result {
+            if event.kind.is_modify() || event.kind.is_create() {
+                let _ = sender.send(event);
+            }
+        }
+    }) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            error!("Could not watch {} for changes: {}", path.display(), e);
+            return;
+        }
+    };
+    // The directory is watched, since editors often replace the file rather than writing to it
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    if let Err(e) = watcher.watch(&directory, RecursiveMode::NonRecursive) {
+        error!("Could not watch {} for changes: {}", path.display(), e);
+        return;
+    }
+    info!("Reloading the local rules when {} changes", path.display());
+
+    while let Some(event) = receiver.recv().await {
+        if !concerns(&event, file_name.as_deref()) {
+            continue;
+        }
+        // Wait until the file is completely written
+        tokio::time::sleep(debounce).await;
+        while receiver.try_recv().is_ok() {}
+        reload(&path, &ports, &state);
+    }
+}
+
+/// Returns whether an event in the watched directory concerns the file the rules are read from.
+///
+/// # Parameters
+/// * 'event' - the event.
+/// * 'file_name' - the name of the file.
+fn concerns(event: &Event, file_name: Option<&OsStr>) -> bool {
+    event.paths.iter().any(|path| path.file_name() == file_name)
+}
+
+/// Reads the local rules from a file and applies them, or leaves the running rules as they are if that fails.
+/// Returns whether the rules were applied.
+///
+/// # Parameters
+/// * 'path' - the path of the file the rules are read from.
+/// * 'ports' - the peer ports of the nodes, by node ID.
+/// * 'state' - the runtime state the rules are applied to.
+pub fn reload(path: &Path, ports: &[u16], state: &InterceptorState) -> bool {
+    let rules = InterceptorConfig::from_file(&path.to_string_lossy())
+        .map_err(|e| e.to_string())
+        .and_then(|config| LocalRules::from_config(&config, ports));
+    match rules {
+        Ok(rules) => {
+            let (blackholes, mutation_rules) = (rules.blackholes.len(), rules.mutation_rules.len());
+            state.apply_rules(rules);
+            info!(
+                "Reloaded the local rules from {}: {} blackholes, {} mutation rules",
+                path.display(),
+                blackholes,
+                mutation_rules
+            );
+            state.events.emit(EventKind::RulesReloaded {
+                path: path.display().to_string(),
+                blackholes,
+                mutation_rules,
+            });
+            true
+        }
+        Err(e) => {
+            error!(
+                "Could not reload the local rules from {}, keeping the running rules: {}",
+                path.display(),
+                e
+            );
+            state.statistics.count_error("reload_failed", 1);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::config::{InterceptionMode, InterceptorConfig};
+    use crate::hot_reload::{reload, LocalRules};
+    use crate::interceptor_state::{Blackhole, InterceptorState};
+    use crate::message_type::MessageType;
+    use crate::packet_timeline::PacketTimeline;
+    use crate::traffic_shaping::{ShapingAction, ShapingRule};
+    use std::fs;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const PORTS: [u16; 2] = [60000, 60001];
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn rules_from_config() {
+        let config = InterceptorConfig::parse(
+            r#"
+            [interception]
+            default = "passthrough"
+
+            [blackhole]
+            rules = [{ node = 1, message_type = "mtVALIDATION" }]
+
+            [[mutation.rules]]
+            message_type = "mtPROPOSE_LEDGER"
+            from_node = 0
+            mutations = [{ path = "closeTime", op = "add", delta = 5 }]
+
+            [shaping]
+            rules = [{ message_type = "mtTRANSACTION", from_node = 0, to_node = 1, rate = 50, action = "drop" }]
+            "#,
+        )
+        .unwrap();
+        let rules = LocalRules::from_config(&config, &PORTS).unwrap();
+        assert_eq!(
+            rules.policy.mode(MessageType::Validation),
+            InterceptionMode::Passthrough
+        );
+        assert_eq!(
+            rules.blackholes,
+            vec![Blackhole {
+                from_port: 60001,
+                message_type: MessageType::Validation,
+            }]
+        );
+        assert_eq!(rules.mutation_rules[0].from_port, Some(60000));
+        assert_eq!(
+            rules.shaping_rules,
+            vec![ShapingRule {
+                message_type: MessageType::Transaction,
+                from_port: Some(60000),
+                to_port: Some(60001),
+                rate: 50.0,
+                burst: None,
+                action: ShapingAction::Drop,
+            }]
+        );
+
+        let unknown_node = InterceptorConfig::parse(
+            r#"
+            [blackhole]
+            rules = [{ node = 2, message_type = "mtVALIDATION" }]
+            "#,
+        )
+        .unwrap();
+        assert!(LocalRules::from_config(&unknown_node, &PORTS).is_err());
+
+        let invalid_rate = InterceptorConfig::parse(
+            r#"
+            [shaping]
+            rules = [{ message_type = "mtVALIDATION", rate = 0 }]
+            "#,
+        )
+        .unwrap();
+        assert!(LocalRules::from_config(&invalid_rate, &PORTS).is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn one_way_interception_from_config() {
+        let parse = |one_way: &str| {
+            let config =
+                InterceptorConfig::parse(&format!("[interception]\none_way = {}\n", one_way))
+                    .unwrap();
+            LocalRules::from_config(&config, &PORTS)
+        };
+        let rules = parse("[[1, 0]]").unwrap();
+        assert_eq!(
+            rules
+                .policy
+                .link_mode(60001, 60000, MessageType::ProposeLedger),
+            InterceptionMode::Intercept
+        );
+        assert_eq!(
+            rules
+                .policy
+                .link_mode(60000, 60001, MessageType::ProposeLedger),
+            InterceptionMode::Passthrough
+        );
+
+        assert!(parse("[[1, 0], [0, 1]]").is_err());
+        assert!(parse("[[1, 1]]").is_err());
+        assert!(parse("[[0, 2]]").is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn base_latencies_from_config() {
+        let config = InterceptorConfig::parse(
+            r#"
+            [latency]
+            matrix = [[0, 120], [100, 0]]
+            round_trip = true
+            "#,
+        )
+        .unwrap();
+        let rules = LocalRules::from_config(&config, &PORTS).unwrap();
+        assert_eq!(rules.base_latencies.len(), 2);
+        assert_eq!(
+            rules.base_latencies[&(60000, 60001)],
+            Duration::from_millis(60)
+        );
+        assert_eq!(
+            rules.base_latencies[&(60001, 60000)],
+            Duration::from_millis(50)
+        );
+
+        let too_small = InterceptorConfig::parse("[latency]\nmatrix = [[0]]\n").unwrap();
+        assert!(LocalRules::from_config(&too_small, &PORTS).is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn reload_applies_valid_rules_only() {
+        let state = InterceptorState::new(Arc::new(PacketTimeline::new(10)));
+        let path = std::env::temp_dir().join(format!("rules-{}.toml", std::process::id()));
+        fs::write(
+            &path,
+            r#"
+            [blackhole]
+            rules = [{ node = 0, message_type = "mtVALIDATION" }]
+            "#,
+        )
+        .unwrap();
+        assert!(reload(&path, &PORTS, &state));
+        assert_eq!(state.blackholes().len(), 1);
+
+        // The invalid mutation rule keeps the blackhole from being removed
+        fs::write(
+            &path,
+            r#"
+            [[mutation.rules]]
+            message_type = "mtPROPOSE_LEDGER"
+            mutations = [{ path = "unknownField", op = "clear" }]
+            "#,
+        )
+        .unwrap();
+        assert!(!reload(&path, &PORTS, &state));
+        assert_eq!(state.blackholes().len(), 1);
+        assert!(state.mutation_rules().is_empty());
+
+        fs::write(&path, "").unwrap();
+        assert!(reload(&path, &PORTS, &state));
+        assert!(state.blackholes().is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,162 @@
+//! This module is responsible for deciding which intercepted messages are sent to the controller, based on their type
+//! and the direction of the link they are read from.
+
+use crate::config::{InterceptionConfig, InterceptionMode};
+use crate::message_type::MessageType;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+/// Struct that represents the interception mode of every message type.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InterceptionPolicy {
+    /// The mode of all message types that are not configured explicitly.
+    default: InterceptionMode,
+    /// The mode per explicitly configured message type.
+    modes: HashMap<MessageType, InterceptionMode>,
+    /// The links of which the messages are forwarded as-is regardless of their type, by the ports of the peer the
+    /// messages come from and go to, because only the other direction of their connection is intercepted.
+    passthrough_links: HashSet<(u16, u16)>,
+}
+
+impl InterceptionPolicy {
+    /// Creates the policy from the configuration.
+    /// Returns an error if the configuration contains an unknown message type.
+    ///
+    /// # Parameters
+    /// * 'config' - the configuration of which message types are sent to the controller.
+    pub fn from_config(config: &InterceptionConfig) -> Result<Self, String> {
+        let mut modes = HashMap::new();
+        for (name, mode) in config.message_types.iter() {
+            modes.insert(MessageType::from_str(name)?, *mode);
+        }
+        Ok(Self {
+            default: config.default,
+            modes,
+            passthrough_links: HashSet::new(),
+        })
+    }
+
+    /// Intercepts only one direction of a connection: the messages on the link in the opposite direction are forwarded
+    /// as-is from now on. Returns false if the opposite direction is already the only one intercepted, in which case
+    /// nothing changes.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer the messages of the intercepted direction come from.
+    /// * 'to_port' - the port of the peer the messages of the intercepted direction go to.
+    pub fn intercept_one_way(&mut self, from_port: u16, to_port: u16) -> bool {
+        if self.passthrough_links.contains(&(from_port, to_port)) {
+            return false;
+        }
+        self.passthrough_links.insert((to_port, from_port));
+        true
+    }
+
+    /// Returns how messages of the given type are handled.
+    ///
+    /// # Parameters
+    /// * 'message_type' - the type of the message.
+    pub fn mode(&self, message_type: MessageType) -> InterceptionMode {
+        *self.modes.get(&message_type).unwrap_or(&self.default)
+    }
+
+    /// Returns how messages of the given type are handled on a link.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer the message comes from.
+    /// * 'to_port' - the port of the peer the message goes to.
+    /// * 'message_type' - the type of the message.
+    pub fn link_mode(
+        &self,
+        from_port: u16,
+        to_port: u16,
+        message_type: MessageType,
+    ) -> InterceptionMode {
+        if self.passthrough_links.contains(&(from_port, to_port)) {
+            return InterceptionMode::Passthrough;
+        }
+        self.mode(message_type)
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::config::{InterceptionConfig, InterceptionMode};
+    use crate::interception_policy::InterceptionPolicy;
+    use crate::message_type::MessageType;
+    use std::collections::HashMap;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn default_policy_intercepts() {
+        let policy = InterceptionPolicy::default();
+        assert_eq!(
+            policy.mode(MessageType::Validation),
+            InterceptionMode::Intercept
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn policy_from_config() {
+        let config = InterceptionConfig {
+            default: InterceptionMode::Mirror,
+            message_types: HashMap::from([
+                ("mtPING".to_string(), InterceptionMode::Passthrough),
+                ("41".to_string(), InterceptionMode::Intercept),
+            ]),
+            one_way: Vec::new(),
+        };
+        let policy = InterceptionPolicy::from_config(&config).unwrap();
+
+        assert_eq!(
+            policy.mode(MessageType::Ping),
+            InterceptionMode::Passthrough
+        );
+        assert_eq!(
+            policy.mode(MessageType::Validation),
+            InterceptionMode::Intercept
+        );
+        assert_eq!(
+            policy.mode(MessageType::Transaction),
+            InterceptionMode::Mirror
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn policy_with_unknown_type() {
+        let config = InterceptionConfig {
+            default: InterceptionMode::Intercept,
+            message_types: HashMap::from([("mtNONSENSE".to_string(), InterceptionMode::Mirror)]),
+            one_way: Vec::new(),
+        };
+        assert!(InterceptionPolicy::from_config(&config).is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn one_way_interception() {
+        let config = InterceptionConfig {
+            default: InterceptionMode::Mirror,
+            ..InterceptionConfig::default()
+        };
+        let mut policy = InterceptionPolicy::from_config(&config).unwrap();
+        assert!(policy.intercept_one_way(60000, 60001));
+        assert!(policy.intercept_one_way(60000, 60001));
+        assert!(!policy.intercept_one_way(60001, 60000));
+
+        assert_eq!(
+            policy.link_mode(60000, 60001, MessageType::Validation),
+            InterceptionMode::Mirror
+        );
+        assert_eq!(
+            policy.link_mode(60001, 60000, MessageType::Validation),
+            InterceptionMode::Passthrough
+        );
+        // Other links follow the message types
+        assert_eq!(
+            policy.link_mode(60001, 60002, MessageType::Validation),
+            InterceptionMode::Mirror
+        );
+    }
+}
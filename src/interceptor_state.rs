@@ -0,0 +1,2015 @@
+//! This module contains the state that is shared between all intercepted links and can be changed while running.
+
+use crate::action::Decision;
+use crate::breakpoint::{Breakpoint, BreakpointHit, Breakpoints, LinkDebugger};
+use crate::broadcast::BroadcastTracker;
+use crate::capture_file::{CaptureFile, CapturedFrame};
+use crate::circuit_breaker::CircuitBreaker;
+use crate::clock::{Clock, TokioClock};
+use crate::config::{InterceptionMode, TimeoutAction};
+use crate::connection_handler::Message;
+use crate::consensus_round::RoundTracker;
+use crate::controller_pool::ControllerPool;
+use crate::eclipse::{self, Eclipse, InjectError};
+use crate::equivocation::{self, EquivocationError, EquivocationRule};
+use crate::event_bus::{EventBus, EventKind};
+use crate::field_mutation::{self, MutationError, MutationRule};
+use crate::gray_failure::{GrayFailureError, GrayFailureRule, GrayFailures};
+use crate::hot_reload::LocalRules;
+use crate::interception_policy::InterceptionPolicy;
+use crate::manifest::{
+    self, Manifest, ManifestAction, ManifestError, ManifestRule, ManifestTracker,
+};
+use crate::message_queue::{BoundedQueue, QueueGauge};
+use crate::message_type::MessageType;
+use crate::packet_client::proto::{Action, DestinationActions};
+use crate::packet_hook::{HookStage, PacketHook};
+use crate::packet_timeline::{PacketRecord, PacketTimeline};
+use crate::partition::OneWayPartition;
+use crate::passive::PassiveObserver;
+use crate::phase::Phase;
+use crate::record_sink::SinkHandle;
+use crate::relay::RelayClient;
+use crate::replay::CaptureBuffer;
+use crate::run_seed::{self, RunSeed};
+use crate::run_summary::RunStatistics;
+use crate::signature;
+use crate::squelch::{ActiveSquelch, Squelch, SquelchError, SquelchRule, SquelchTracker};
+use crate::traffic_shaping::{Shaped, ShapingRule, ShapingRuleError, TrafficShaper};
+use crate::tx_suppression::TxSuppression;
+use crate::wire_format::ledger_sequence;
+use bytes::Bytes;
+use rand::rngs::StdRng;
+use rand::Rng;
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::warn;
+
+/// Struct that represents the rules applied to the messages of a single link, on top of the decision of the controller.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LinkRule {
+    /// The delay in ms added to every message on the link.
+    pub delay_ms: u32,
+    /// The probability between 0 and 1 that a message on the link is dropped.
+    pub drop_probability: f64,
+}
+
+impl LinkRule {
+    /// Returns the delay added to every message on the link.
+    pub fn delay(&self) -> Duration {
+        Duration::from_millis(u64::from(self.delay_ms))
+    }
+
+    /// Checks whether the rule can be applied.
+    pub fn validate(&self) -> Result<(), LinkRuleError> {
+        if !(0.0..=1.0).contains(&self.drop_probability) {
+            return Err(LinkRuleError(format!(
+                "drop_probability {} is not between 0 and 1",
+                self.drop_probability
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Struct that represents a blackhole: all messages of a type that a node sends are dropped, on all its links.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Blackhole {
+    /// The port of the node whose messages are dropped.
+    pub from_port: u16,
+    /// The type of the dropped messages.
+    pub message_type: MessageType,
+}
+
+/// Struct that represents how long the controller can take to decide on a message, and what happens if it takes longer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecisionTimeout {
+    /// The time the controller has to decide, counting from the moment the message is sent to it.
+    pub budget: Duration,
+    /// The action applied to the message if the controller did not decide in time.
+    pub action: TimeoutAction,
+}
+
+/// Struct that represents the reason a LinkRule can not be applied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkRuleError(pub String);
+
+impl fmt::Display for LinkRuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid link rule: {}", self.0)
+    }
+}
+
+impl Error for LinkRuleError {}
+
+/// Struct that represents the reason a time dilation factor can not be applied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeDilationError(pub f64);
+
+impl fmt::Display for TimeDilationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid time dilation factor {}: it should be a finite number above 0",
+            self.0
+        )
+    }
+}
+
+impl Error for TimeDilationError {}
+
+/// Struct that represents an intercepted link, with its rules and counters, which can be read while the link is in use.
+#[derive(Debug)]
+pub struct Link {
+    /// The port of the peer the messages come from.
+    pub from_port: u16,
+    /// The port of the peer the messages go to.
+    pub to_port: u16,
+    /// The XRPL protocol version negotiated with the node the link reads from, if known.
+    pub protocol: Option<String>,
+    /// The rules applied to the messages on the link.
+    rule: RwLock<LinkRule>,
+    /// The amount of messages handled on the link.
+    handled: AtomicU64,
+    /// The amount of handled messages that were dropped, by the controller or by the rule of the link.
+    dropped: AtomicU64,
+    /// The moving average of the round trip time of the requests to the controller for the messages on the link, in
+    /// µs, 0 if none was measured yet.
+    controller_rtt_us: AtomicU64,
+    /// Whether the link is halted at a breakpoint.
+    pub debugger: LinkDebugger,
+    /// The generator of the random decisions of the rule of the link.
+    rng: Mutex<StdRng>,
+}
+
+impl Link {
+    /// Returns the rules applied to the messages on the link.
+    pub fn rule(&self) -> LinkRule {
+        *self.rule.read().unwrap()
+    }
+
+    /// Returns the amount of messages handled on the link.
+    pub fn handled(&self) -> u64 {
+        self.handled.load(Ordering::Relaxed)
+    }
+
+    /// Returns the amount of handled messages that were dropped.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Counts a handled message.
+    ///
+    /// # Parameters
+    /// * 'dropped' - whether the message was dropped.
+    pub fn count(&self, dropped: bool) {
+        self.handled.fetch_add(1, Ordering::Relaxed);
+        if dropped {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records the round trip time of a request to the controller for a message on the link, in the exponentially
+    /// weighted moving average with a weight of 1/8 for the new time.
+    ///
+    /// # Parameters
+    /// * 'rtt' - the round trip time of the request.
+    pub fn record_controller_rtt(&self, rtt: Duration) {
+        let sample = (rtt.as_micros() as u64).max(1);
+        let _ =
+            self.controller_rtt_us
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |average| {
+                    Some(match average {
+                        0 => sample,
+                        _ => (average * 7 + sample) / 8,
+                    })
+                });
+    }
+
+    /// Returns the moving average of the round trip time of the requests to the controller for the messages on the
+    /// link, or None if no request was sent yet.
+    pub fn controller_rtt(&self) -> Option<Duration> {
+        match self.controller_rtt_us.load(Ordering::Relaxed) {
+            0 => None,
+            average => Some(Duration::from_micros(average)),
+        }
+    }
+
+    /// Applies the rule of the link to a decision: the delay of the rule is added, and the message is dropped
+    /// with the probability of the rule. The decision is counted in the counters of the link.
+    /// Returns whether the message was dropped by the rule.
+    ///
+    /// # Parameters
+    /// * 'decision' - the decision made for the message.
+    pub fn apply_rule(&self, decision: &mut Decision) -> bool {
+        let rule = self.rule();
+        let dropped_by_rule = decision.send_amount > 0
+            && rule.drop_probability > 0.0
+            && self.rng.lock().unwrap().gen_bool(rule.drop_probability);
+        if dropped_by_rule {
+            decision.send_amount = 0;
+        }
+        decision.delay += rule.delay();
+        self.count(decision.send_amount == 0);
+        dropped_by_rule
+    }
+}
+
+/// Struct that represents the runtime state shared by all intercepted links.
+#[derive(Debug)]
+pub struct InterceptorState {
+    /// The timeline where every handled message is recorded.
+    pub timeline: Arc<PacketTimeline>,
+    /// The bus on which link-state events are published.
+    pub events: Arc<EventBus>,
+    /// The counts of the handled messages and errors of the run, summarized at shutdown.
+    pub statistics: RunStatistics,
+    /// The assignment of the handled messages to the consensus rounds they belong to.
+    pub rounds: RoundTracker,
+    /// Whether messages are forwarded as-is without asking the controller for an action.
+    passthrough: AtomicBool,
+    /// The phase of the run, outside the fault window messages are passed through untouched.
+    phase: RwLock<Phase>,
+    /// The gauges of all queues between the stages of the links.
+    queue_gauges: Mutex<Vec<Arc<QueueGauge>>>,
+    /// Which message types are sent to the controller.
+    policy: RwLock<InterceptionPolicy>,
+    /// All intercepted links, by the ports of the peer the messages come from and go to.
+    links: RwLock<BTreeMap<(u16, u16), Arc<Link>>>,
+    /// Whether forwarding is paused, in which case handled messages wait until it is resumed.
+    paused: watch::Sender<bool>,
+    /// The factor by which all injected delays are multiplied, stored as the bits of an f64.
+    time_dilation: AtomicU64,
+    /// The time in which the delays and the scheduled scenarios are measured.
+    clock: Arc<dyn Clock>,
+    /// How long the controller can take to decide on a message, if its decisions are not awaited indefinitely.
+    decision_timeout: RwLock<Option<DecisionTimeout>>,
+    /// The circuit breaker of the controller channel, if messages bypass the controller while it is unhealthy.
+    circuit_breaker: OnceLock<CircuitBreaker>,
+    /// The controllers the links are spread over, if there are multiple.
+    controller_pool: OnceLock<ControllerPool>,
+    /// The next interceptor in the chain, if messages are relayed to it.
+    relay: OnceLock<RelayClient>,
+    /// The breakpoints at which links halt.
+    breakpoints: RwLock<Breakpoints>,
+    /// The sinks every handled message is written to, in addition to the timeline.
+    sinks: RwLock<Vec<Arc<SinkHandle>>>,
+    /// The capture file every handled message is written to as it was read, if messages are captured to a file.
+    capture_file: RwLock<Option<Arc<CaptureFile>>>,
+    /// The ports of the observed node and its shadow node, if messages are mirrored to a shadow node.
+    shadow: RwLock<Option<(u16, u16)>>,
+    /// The eclipse of a victim node, if a node is eclipsed.
+    eclipse: RwLock<Option<Eclipse>>,
+    /// The links that are cut in one direction only.
+    one_way_partition: RwLock<OneWayPartition>,
+    /// The links on which the relay of transactions is suppressed.
+    tx_suppression: RwLock<TxSuppression>,
+    /// The message types that are dropped per node.
+    blackholes: RwLock<Vec<Blackhole>>,
+    /// The rules by which messages are mutated locally, on top of the decision of the controller.
+    mutation_rules: RwLock<Vec<MutationRule>>,
+    /// The rules that limit the rate of message types per link, with their token buckets.
+    shaper: Mutex<TrafficShaper>,
+    /// The gray failure presets applied to nodes and links, if any.
+    gray_failures: Mutex<Option<GrayFailures>>,
+    /// The random number generator of the gray failures, created once the first message is impaired.
+    gray_failure_rng: Mutex<Option<StdRng>>,
+    /// The base latency of every link, by the ports of the peer its messages come from and go to.
+    base_latencies: RwLock<HashMap<(u16, u16), Duration>>,
+    /// The hooks that decide on every sent message, with their stage, in the order they were registered.
+    hooks: RwLock<Vec<(HookStage, Arc<dyn PacketHook>)>>,
+    /// The secret keys of the nodes, by port, with which mutated messages are signed again.
+    signing_keys: RwLock<HashMap<u16, SecretKey>>,
+    /// The public keys of the nodes, against which the signatures of proposals and validations are verified.
+    public_keys: RwLock<HashSet<Vec<u8>>>,
+    /// The latest manifest of every master key that was seen or announced.
+    manifests: Mutex<ManifestTracker>,
+    /// The rules by which the manifests of nodes are rewritten on selected links.
+    manifest_rules: RwLock<Vec<ManifestRule>>,
+    /// The squelches the nodes sent that are in effect on every link.
+    squelches: Mutex<SquelchTracker>,
+    /// The rules by which the squelches of validators are overridden on selected links.
+    squelch_rules: RwLock<Vec<SquelchRule>>,
+    /// The rules by which the proposals of nodes are equivocated.
+    equivocation_rules: RwLock<Vec<EquivocationRule>>,
+    /// The buffer where messages are captured to be replayed, if messages are captured.
+    capture_buffer: OnceLock<Arc<CaptureBuffer>>,
+    /// The measurement of the added latency, if messages are only observed.
+    passive: OnceLock<PassiveObserver>,
+    /// The tracking of the copies of every broadcast, if the copies are tracked.
+    broadcasts: OnceLock<BroadcastTracker>,
+    /// The seed the random decisions on the links are derived from, if the run is seeded.
+    seed: OnceLock<RunSeed>,
+    /// The queues of the write stages of all nodes, by port, through which messages are injected.
+    write_queues: RwLock<HashMap<u16, Arc<BoundedQueue<Message>>>>,
+}
+
+impl InterceptorState {
+    /// Initializes a new InterceptorState, in which all messages are intercepted.
+    ///
+    /// # Parameters
+    /// * 'timeline' - the timeline where every handled message is recorded.
+    pub fn new(timeline: Arc<PacketTimeline>) -> Self {
+        Self {
+            timeline,
+            events: Arc::new(EventBus::default()),
+            statistics: RunStatistics::default(),
+            rounds: RoundTracker::default(),
+            passthrough: AtomicBool::new(false),
+            phase: RwLock::new(Phase::default()),
+            queue_gauges: Mutex::new(Vec::new()),
+            policy: RwLock::new(InterceptionPolicy::default()),
+            links: RwLock::new(BTreeMap::new()),
+            paused: watch::Sender::new(false),
+            time_dilation: AtomicU64::new(1.0f64.to_bits()),
+            clock: Arc::new(TokioClock),
+            decision_timeout: RwLock::new(None),
+            circuit_breaker: OnceLock::new(),
+            controller_pool: OnceLock::new(),
+            relay: OnceLock::new(),
+            breakpoints: RwLock::new(Breakpoints::default()),
+            sinks: RwLock::new(Vec::new()),
+            capture_file: RwLock::new(None),
+            shadow: RwLock::new(None),
+            eclipse: RwLock::new(None),
+            one_way_partition: RwLock::new(OneWayPartition::default()),
+            tx_suppression: RwLock::new(TxSuppression::default()),
+            blackholes: RwLock::new(Vec::new()),
+            mutation_rules: RwLock::new(Vec::new()),
+            shaper: Mutex::new(TrafficShaper::default()),
+            gray_failures: Mutex::new(None),
+            gray_failure_rng: Mutex::new(None),
+            base_latencies: RwLock::new(HashMap::new()),
+            hooks: RwLock::new(Vec::new()),
+            signing_keys: RwLock::new(HashMap::new()),
+            public_keys: RwLock::new(HashSet::new()),
+            manifests: Mutex::new(ManifestTracker::default()),
+            manifest_rules: RwLock::new(Vec::new()),
+            squelches: Mutex::new(SquelchTracker::default()),
+            squelch_rules: RwLock::new(Vec::new()),
+            equivocation_rules: RwLock::new(Vec::new()),
+            capture_buffer: OnceLock::new(),
+            passive: OnceLock::new(),
+            broadcasts: OnceLock::new(),
+            seed: OnceLock::new(),
+            write_queues: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Records a handled message in the timeline and the statistics, and sends it to all sinks.
+    ///
+    /// # Parameters
+    /// * 'record' - the record of the message.
+    pub fn record(&self, record: PacketRecord) {
+        self.statistics.tally(&record);
+        for sink in self.sinks.read().unwrap().iter() {
+            sink.send(record.clone());
+        }
+        self.timeline.push(record);
+    }
+
+    /// Adds a sink every handled message is written to from now on.
+    ///
+    /// # Parameters
+    /// * 'sink' - the handle of the sink, created with `record_sink::spawn`.
+    pub fn add_sink(&self, sink: Arc<SinkHandle>) {
+        self.sinks.write().unwrap().push(sink);
+    }
+
+    /// Removes all sinks, such that their threads write the remaining records and close them.
+    pub fn close_sinks(&self) {
+        for sink in self.sinks.write().unwrap().drain(..) {
+            if sink.dropped() > 0 {
+                self.statistics
+                    .count_error("records_not_written", sink.dropped());
+                warn!(
+                    "{} records were not written to {}, because it could not keep up",
+                    sink.dropped(),
+                    sink.name
+                );
+            }
+        }
+    }
+
+    /// Writes every handled message to a capture file from now on, as it was read.
+    ///
+    /// # Parameters
+    /// * 'capture_file' - the handle of the capture file, created with `CaptureFile::create`.
+    pub fn set_capture_file(&self, capture_file: Arc<CaptureFile>) {
+        *self.capture_file.write().unwrap() = Some(capture_file);
+    }
+
+    /// Writes a handled message to the capture file as it was read, if messages are captured to a file.
+    ///
+    /// # Parameters
+    /// * 'frame' - the message with the link it was read from.
+    pub fn capture_to_file(&self, frame: CapturedFrame) {
+        if let Some(capture_file) = self.capture_file.read().unwrap().as_ref() {
+            capture_file.capture(frame);
+        }
+    }
+
+    /// Stops writing to the capture file, such that its thread writes the remaining messages and closes it.
+    pub fn close_capture_file(&self) {
+        if let Some(capture_file) = self.capture_file.write().unwrap().take() {
+            if capture_file.dropped() > 0 {
+                self.statistics
+                    .count_error("messages_not_captured", capture_file.dropped());
+                warn!(
+                    "{} messages were not captured to {}, because it could not keep up",
+                    capture_file.dropped(),
+                    capture_file.path.display()
+                );
+            }
+        }
+    }
+
+    /// Mirrors every message delivered to the observed node to the shadow node from now on.
+    ///
+    /// # Parameters
+    /// * 'observed_port' - the port of the observed node.
+    /// * 'shadow_port' - the port of the shadow node.
+    pub fn set_shadow(&self, observed_port: u16, shadow_port: u16) {
+        *self.shadow.write().unwrap() = Some((observed_port, shadow_port));
+    }
+
+    /// Returns the port of the shadow node a message delivered to the given node is mirrored to, if any.
+    ///
+    /// # Parameters
+    /// * 'to_port' - the port of the node the message is delivered to.
+    pub fn shadow_port(&self, to_port: u16) -> Option<u16> {
+        self.shadow
+            .read()
+            .unwrap()
+            .filter(|(observed_port, _)| *observed_port == to_port)
+            .map(|(_, shadow_port)| shadow_port)
+    }
+
+    /// Starts or ends the eclipse of a victim node, replacing the current eclipse if there is one.
+    ///
+    /// # Parameters
+    /// * 'eclipse' - the eclipse, or None to reconnect the victim to all its peers.
+    pub fn set_eclipse(&self, eclipse: Option<Eclipse>) {
+        self.events.emit(EventKind::EclipseChanged {
+            victim_port: eclipse.as_ref().map(|eclipse| eclipse.victim_port),
+            visible_peers: eclipse
+                .as_ref()
+                .map(|eclipse| eclipse.visible_peers.clone())
+                .unwrap_or_default(),
+        });
+        *self.eclipse.write().unwrap() = eclipse;
+    }
+
+    /// Returns the current eclipse, if a node is eclipsed.
+    pub fn eclipse(&self) -> Option<Eclipse> {
+        self.eclipse.read().unwrap().clone()
+    }
+
+    /// Returns whether a message on a link is cut off by the current eclipse.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer where the message came from.
+    /// * 'to_port' - the port of the peer the message is sent to.
+    /// * 'message_type' - the type of the message.
+    pub fn is_eclipsed(&self, from_port: u16, to_port: u16, message_type: MessageType) -> bool {
+        self.eclipse
+            .read()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|eclipse| eclipse.cuts(from_port, to_port, message_type))
+    }
+
+    /// Replaces the one-way partition of the network.
+    ///
+    /// # Parameters
+    /// * 'partition' - the partition, without cut links to heal the partition.
+    pub fn set_one_way_partition(&self, partition: OneWayPartition) {
+        self.events.emit(EventKind::OneWayPartitionChanged {
+            cut_links: partition.cut_links.clone(),
+        });
+        *self.one_way_partition.write().unwrap() = partition;
+    }
+
+    /// Returns the current one-way partition, which has no cut links if the network is not partitioned one-way.
+    pub fn one_way_partition(&self) -> OneWayPartition {
+        self.one_way_partition.read().unwrap().clone()
+    }
+
+    /// Replaces the links on which the relay of transactions is suppressed, and publishes the change as an event.
+    ///
+    /// # Parameters
+    /// * 'suppression' - the new suppression, which ends it if it has no links.
+    pub fn set_tx_suppression(&self, suppression: TxSuppression) {
+        self.events.emit(EventKind::TxSuppressionChanged {
+            links: suppression.links.clone(),
+        });
+        *self.tx_suppression.write().unwrap() = suppression;
+    }
+
+    /// Returns the links on which the relay of transactions is suppressed, none if it is not suppressed.
+    pub fn tx_suppression(&self) -> TxSuppression {
+        self.tx_suppression.read().unwrap().clone()
+    }
+
+    /// Starts dropping all messages of a type that a node sends. Returns false if they were already dropped.
+    ///
+    /// # Parameters
+    /// * 'blackhole' - the node and the type of the messages.
+    pub fn add_blackhole(&self, blackhole: Blackhole) -> bool {
+        let mut blackholes = self.blackholes.write().unwrap();
+        if blackholes.contains(&blackhole) {
+            return false;
+        }
+        blackholes.push(blackhole);
+        self.events.emit(EventKind::BlackholeChanged {
+            from_port: blackhole.from_port,
+            message_type: blackhole.message_type.to_string(),
+            enabled: true,
+        });
+        true
+    }
+
+    /// Stops dropping all messages of a type that a node sends. Returns false if they were not dropped.
+    ///
+    /// # Parameters
+    /// * 'blackhole' - the node and the type of the messages.
+    pub fn remove_blackhole(&self, blackhole: Blackhole) -> bool {
+        let mut blackholes = self.blackholes.write().unwrap();
+        let length = blackholes.len();
+        blackholes.retain(|existing| *existing != blackhole);
+        if blackholes.len() == length {
+            return false;
+        }
+        self.events.emit(EventKind::BlackholeChanged {
+            from_port: blackhole.from_port,
+            message_type: blackhole.message_type.to_string(),
+            enabled: false,
+        });
+        true
+    }
+
+    /// Returns all blackholes, in the order they were added.
+    pub fn blackholes(&self) -> Vec<Blackhole> {
+        self.blackholes.read().unwrap().clone()
+    }
+
+    /// Returns why a message on a link is cut off, if it is: 'eclipse', 'partition', 'blackhole' or 'tx_suppression'.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer where the message came from.
+    /// * 'to_port' - the port of the peer the message is sent to.
+    /// * 'message_type' - the type of the message.
+    pub fn cut_reason(
+        &self,
+        from_port: u16,
+        to_port: u16,
+        message_type: MessageType,
+    ) -> Option<&'static str> {
+        if self.is_eclipsed(from_port, to_port, message_type) {
+            Some("eclipse")
+        } else if self
+            .one_way_partition
+            .read()
+            .unwrap()
+            .cuts(from_port, to_port)
+        {
+            Some("partition")
+        } else if self.blackholes.read().unwrap().contains(&Blackhole {
+            from_port,
+            message_type,
+        }) {
+            Some("blackhole")
+        } else if self
+            .tx_suppression
+            .read()
+            .unwrap()
+            .suppresses(from_port, to_port, message_type)
+        {
+            Some("tx_suppression")
+        } else {
+            None
+        }
+    }
+
+    /// Replaces the interception policy, the blackholes, the mutation rules, the shaping rules and the base latencies at
+    /// once.
+    /// Every blackhole that is added or removed is published as an event.
+    ///
+    /// # Parameters
+    /// * 'rules' - the new local rules.
+    pub fn apply_rules(&self, rules: LocalRules) {
+        let mut policy = self.policy.write().unwrap();
+        let mut blackholes = self.blackholes.write().unwrap();
+        let mut mutation_rules = self.mutation_rules.write().unwrap();
+        let removed: Vec<Blackhole> = blackholes
+            .iter()
+            .filter(|blackhole| !rules.blackholes.contains(blackhole))
+            .copied()
+            .collect();
+        let added: Vec<Blackhole> = rules
+            .blackholes
+            .iter()
+            .filter(|blackhole| !blackholes.contains(blackhole))
+            .copied()
+            .collect();
+        *policy = rules.policy;
+        *blackholes = rules.blackholes;
+        *mutation_rules = rules.mutation_rules;
+        self.shaper.lock().unwrap().set_rules(rules.shaping_rules);
+        *self.base_latencies.write().unwrap() = rules.base_latencies;
+        drop((policy, blackholes, mutation_rules));
+        for (changed, enabled) in removed
+            .into_iter()
+            .map(|blackhole| (blackhole, false))
+            .chain(added.into_iter().map(|blackhole| (blackhole, true)))
+        {
+            self.events.emit(EventKind::BlackholeChanged {
+                from_port: changed.from_port,
+                message_type: changed.message_type.to_string(),
+                enabled,
+            });
+        }
+    }
+
+    /// Replaces the rules by which messages are mutated locally.
+    ///
+    /// # Parameters
+    /// * 'rules' - the new rules, which are all applied in order to the messages they match.
+    pub fn set_mutation_rules(&self, rules: Vec<MutationRule>) {
+        *self.mutation_rules.write().unwrap() = rules;
+    }
+
+    /// Returns the rules by which messages are mutated locally.
+    pub fn mutation_rules(&self) -> Vec<MutationRule> {
+        self.mutation_rules.read().unwrap().clone()
+    }
+
+    /// Replaces the rules that limit the rate of message types per link. The token buckets start full again, unless the
+    /// rules did not change.
+    ///
+    /// # Parameters
+    /// * 'rules' - the new rules, of which the first matching one limits a message.
+    pub fn set_shaping_rules(&self, rules: Vec<ShapingRule>) -> Result<(), ShapingRuleError> {
+        for rule in rules.iter() {
+            rule.validate()?;
+        }
+        self.shaper.lock().unwrap().set_rules(rules);
+        Ok(())
+    }
+
+    /// Returns the rules that limit the rate of message types per link.
+    pub fn shaping_rules(&self) -> Vec<ShapingRule> {
+        self.shaper.lock().unwrap().rules().to_vec()
+    }
+
+    /// Takes a token of the shaping rule that limits a message, and returns whether the message is delayed or dropped
+    /// to stay within its rate. Returns None if no rule limits the message.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer where the message came from.
+    /// * 'to_port' - the port of the peer the message is sent to.
+    /// * 'message_type' - the type of the message.
+    pub fn shape(&self, from_port: u16, to_port: u16, message_type: MessageType) -> Option<Shaped> {
+        let now = self.clock.now();
+        self.shaper
+            .lock()
+            .unwrap()
+            .shape(from_port, to_port, message_type, now)
+    }
+
+    /// Replaces the gray failure presets applied to nodes and links. Their outages are measured from now.
+    ///
+    /// # Parameters
+    /// * 'rules' - the new presets with their targets, which are all applied to the links they target.
+    pub fn set_gray_failures(&self, rules: Vec<GrayFailureRule>) -> Result<(), GrayFailureError> {
+        let gray_failures = if rules.is_empty() {
+            None
+        } else {
+            Some(GrayFailures::new(rules, self.clock.now())?)
+        };
+        *self.gray_failures.lock().unwrap() = gray_failures;
+        Ok(())
+    }
+
+    /// Returns the gray failure presets applied to nodes and links.
+    pub fn gray_failures(&self) -> Vec<GrayFailureRule> {
+        self.gray_failures
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(Vec::new(), GrayFailures::rules)
+    }
+
+    /// Applies the gray failure presets that target the link of a message, and returns whether the message is delayed
+    /// or dropped by them. Returns None if no preset targets the link.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer where the message came from.
+    /// * 'to_port' - the port of the peer the message is sent to.
+    /// * 'message_type' - the type of the message.
+    pub fn impair(
+        &self,
+        from_port: u16,
+        to_port: u16,
+        message_type: MessageType,
+    ) -> Option<Shaped> {
+        let mut gray_failures = self.gray_failures.lock().unwrap();
+        let gray_failures = gray_failures.as_mut()?;
+        // Created on first use, since the seed of the run is set after the rules
+        let mut rng = self.gray_failure_rng.lock().unwrap();
+        let rng =
+            rng.get_or_insert_with(|| run_seed::rng(self.seed.get().copied(), "gray_failure"));
+        gray_failures.impair(from_port, to_port, message_type, self.clock.now(), rng)
+    }
+
+    /// Returns the base latency of a link, which is zero if no latency matrix is configured.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer where the messages come from.
+    /// * 'to_port' - the port of the peer the messages are sent to.
+    pub fn base_latency(&self, from_port: u16, to_port: u16) -> Duration {
+        self.base_latencies
+            .read()
+            .unwrap()
+            .get(&(from_port, to_port))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Applies the mutation rules that match a message. Returns None if no rule matches.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer where the message came from.
+    /// * 'to_port' - the port of the peer the message is sent to.
+    /// * 'message_type' - the type of the message.
+    /// * 'message' - the message including its 6 byte header.
+    pub fn mutate(
+        &self,
+        from_port: u16,
+        to_port: u16,
+        message_type: MessageType,
+        message: &Bytes,
+    ) -> Option<Result<Bytes, MutationError>> {
+        let rules = self.mutation_rules.read().unwrap();
+        let mut matching = rules
+            .iter()
+            .filter(|rule| rule.matches(from_port, to_port, message_type))
+            .peekable();
+        matching.peek()?;
+        let mut message = message.clone();
+        for rule in matching {
+            let result = field_mutation::apply(&message, &rule.mutations).and_then(|mutated| {
+                if rule.resign {
+                    self.resign(from_port, &mutated)
+                } else {
+                    Ok(mutated)
+                }
+            });
+            match result {
+                Ok(mutated) => message = mutated,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        Some(Ok(message))
+    }
+
+    /// Registers a hook, which is executed after the hooks of its stage that were registered before.
+    ///
+    /// # Parameters
+    /// * 'stage' - the point in the handling of a message at which the hook is executed.
+    /// * 'hook' - the hook.
+    pub fn register_hook(&self, stage: HookStage, hook: Arc<dyn PacketHook>) {
+        self.hooks.write().unwrap().push((stage, hook));
+    }
+
+    /// Returns the hooks of a stage, in the order they are executed.
+    ///
+    /// # Parameters
+    /// * 'stage' - the stage of the hooks.
+    pub fn hooks(&self, stage: HookStage) -> Vec<Arc<dyn PacketHook>> {
+        self.hooks
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(hook_stage, _)| *hook_stage == stage)
+            .map(|(_, hook)| hook.clone())
+            .collect()
+    }
+
+    /// Registers the secret key of a node, such that its mutated messages can be signed again, and its proposals and
+    /// validations are verified against its public key.
+    ///
+    /// # Parameters
+    /// * 'port' - the port of the node.
+    /// * 'secret_key' - the secret key of the node.
+    pub fn register_signing_key(&self, port: u16, secret_key: SecretKey) {
+        let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key);
+        self.public_keys
+            .write()
+            .unwrap()
+            .insert(public_key.serialize().to_vec());
+        self.signing_keys.write().unwrap().insert(port, secret_key);
+    }
+
+    /// Returns whether a proposal or validation is signed by one of the nodes with a valid signature, or None for other
+    /// messages. A node signs with its own key, or with the signing key its latest manifest delegates to.
+    ///
+    /// # Parameters
+    /// * 'message' - the message including its 6 byte header.
+    pub fn signature_valid(&self, message: &[u8]) -> Option<bool> {
+        if !matches!(
+            MessageType::from_message(message),
+            Some(MessageType::ProposeLedger | MessageType::Validation)
+        ) {
+            return None;
+        }
+        let public_keys = self.public_keys.read().unwrap();
+        let delegated = self.manifests.lock().unwrap().signing_keys(&public_keys);
+        signature::verify(message, |key| {
+            public_keys.contains(key) || delegated.iter().any(|signing_key| signing_key == key)
+        })
+    }
+
+    /// Tracks the manifests of an mtMANIFESTS message whose signatures are valid, and publishes every newer manifest
+    /// as an event. Manifests with invalid signatures are counted as errors, since rippled rejects them.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer the message came from.
+    /// * 'message' - the message including its 6 byte header, other messages are ignored.
+    pub fn track_manifests(&self, from_port: u16, message: &[u8]) {
+        let Some(stobjects) = manifest::decode_message(message) else {
+            return;
+        };
+        for stobject in stobjects {
+            match Manifest::decode(&stobject).filter(|manifest| manifest.verify(&stobject)) {
+                Some(manifest) => self.track_manifest(from_port, manifest),
+                None => self.statistics.count_error("manifest_invalid", 1),
+            }
+        }
+    }
+
+    /// Tracks a manifest, and publishes it as an event if it is newer than the tracked manifest of its master key.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer the manifest came from, or of the node it was announced for.
+    /// * 'manifest' - the manifest, whose signatures were verified.
+    fn track_manifest(&self, from_port: u16, manifest: Manifest) {
+        let event = EventKind::ManifestChanged {
+            from_port,
+            master_key: hex::encode_upper(&manifest.master_key),
+            sequence: manifest.sequence,
+            signing_key: hex::encode_upper(&manifest.signing_key),
+        };
+        if self.manifests.lock().unwrap().track(manifest) {
+            self.events.emit(event);
+        }
+    }
+
+    /// Returns the latest manifest of every master key that was seen or announced, ordered by master key.
+    pub fn manifests(&self) -> Vec<Manifest> {
+        self.manifests.lock().unwrap().manifests()
+    }
+
+    /// Replaces the rules by which the manifests of nodes are rewritten.
+    ///
+    /// # Parameters
+    /// * 'rules' - the new rules, of which the first one that matches a link and master key rewrites a manifest.
+    pub fn set_manifest_rules(&self, rules: Vec<ManifestRule>) {
+        *self.manifest_rules.write().unwrap() = rules;
+    }
+
+    /// Returns the rules by which the manifests of nodes are rewritten.
+    pub fn manifest_rules(&self) -> Vec<ManifestRule> {
+        self.manifest_rules.read().unwrap().clone()
+    }
+
+    /// Rewrites the manifests of an mtMANIFESTS message by the rules that match its link. Returns None if no rule
+    /// matches, otherwise the rewritten message or None if all its manifests were suppressed.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer where the message came from.
+    /// * 'to_port' - the port of the peer the message is sent to.
+    /// * 'message' - the message including its 6 byte header.
+    pub fn rewrite_manifests(
+        &self,
+        from_port: u16,
+        to_port: u16,
+        message: &[u8],
+    ) -> Option<Result<Option<Bytes>, ManifestError>> {
+        let rules = self.manifest_rules.read().unwrap();
+        let mut matching = rules
+            .iter()
+            .filter(|rule| rule.matches(from_port, to_port))
+            .peekable();
+        matching.peek()?;
+        let targets = matching
+            .map(|rule| match rule.node_port {
+                Some(port) => self
+                    .master_key(port)
+                    .map(|(master_key, _)| (Some(master_key), rule.action))
+                    .ok_or(ManifestError::NoNodeKey(port)),
+                None => Ok((None, rule.action)),
+            })
+            .collect::<Result<Vec<_>, _>>();
+        let secret_key = |master_key: &[u8]| {
+            self.signing_keys
+                .read()
+                .unwrap()
+                .values()
+                .find(|secret_key| {
+                    PublicKey::from_secret_key(&Secp256k1::new(), secret_key).serialize()
+                        == master_key
+                })
+                .copied()
+        };
+        Some(targets.and_then(|targets| manifest::rewrite(message, &targets, secret_key)))
+    }
+
+    /// Announces a manifest of a node that rotates its signing key or revokes its master key, by injecting it on the
+    /// links of the node. The manifest follows the latest manifest of the node, or its key if it has none, and is tracked.
+    ///
+    /// # Parameters
+    /// * 'node_port' - the port of the node.
+    /// * 'action' - whether the signing key is rotated or the master key revoked.
+    /// * 'to_port' - the port of the peer it is announced to, all peers of the node if None.
+    pub async fn announce_manifest(
+        &self,
+        node_port: u16,
+        action: ManifestAction,
+        to_port: Option<u16>,
+    ) -> Result<Manifest, ManifestError> {
+        let (master_key, secret_key) = self
+            .master_key(node_port)
+            .ok_or(ManifestError::NoNodeKey(node_port))?;
+        let latest = self
+            .manifests
+            .lock()
+            .unwrap()
+            .latest(&master_key)
+            .cloned()
+            .unwrap_or(Manifest {
+                sequence: 0,
+                signing_key: master_key.clone(),
+                master_key,
+            });
+        let (manifest, stobject) = latest
+            .successor(action, &secret_key)
+            .ok_or(ManifestError::NoSuccessor(action))?;
+        let links: Vec<Arc<Link>> = self
+            .links()
+            .into_iter()
+            .filter(|link| {
+                link.from_port == node_port && to_port.map_or(true, |port| port == link.to_port)
+            })
+            .collect();
+        if links.is_empty() {
+            return Err(ManifestError::NoLink(node_port, to_port));
+        }
+        let message = manifest::encode_message(&[stobject]);
+        for link in links {
+            if let Err(e) = self.inject(node_port, link.to_port, message.clone()).await {
+                warn!(
+                    "Could not announce the manifest of node {}: {}",
+                    node_port, e
+                );
+            }
+        }
+        self.track_manifest(node_port, manifest.clone());
+        Ok(manifest)
+    }
+
+    /// Tracks the squelch of an mtSQUELCH message as it was sent by a node, before it is overridden, and publishes it as an
+    /// event.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the node that sent the message.
+    /// * 'to_port' - the port of the peer the message is sent to.
+    /// * 'message' - the message including its 6 byte header, other messages are ignored.
+    pub fn track_squelch(&self, from_port: u16, to_port: u16, message: &[u8]) {
+        let Some(squelch) = Squelch::from_message(message) else {
+            return;
+        };
+        self.squelches
+            .lock()
+            .unwrap()
+            .track(from_port, to_port, &squelch, self.clock.now());
+        self.events.emit(EventKind::SquelchSent {
+            from_port,
+            to_port,
+            validator_key: hex::encode_upper(&squelch.validator_key),
+            squelch: squelch.squelch,
+            duration_secs: squelch.duration_secs,
+        });
+    }
+
+    /// Returns the squelches the nodes sent or that were forged that are still in effect, ordered by link and validator.
+    pub fn squelches(&self) -> Vec<ActiveSquelch> {
+        self.squelches.lock().unwrap().active(self.clock.now())
+    }
+
+    /// Replaces the rules by which the squelches of validators are overridden.
+    ///
+    /// # Parameters
+    /// * 'rules' - the new rules, of which the first one that matches a link and validator overrides a squelch.
+    pub fn set_squelch_rules(&self, rules: Vec<SquelchRule>) {
+        *self.squelch_rules.write().unwrap() = rules;
+    }
+
+    /// Returns the rules by which the squelches of validators are overridden.
+    pub fn squelch_rules(&self) -> Vec<SquelchRule> {
+        self.squelch_rules.read().unwrap().clone()
+    }
+
+    /// Overrides the squelch of an mtSQUELCH message by the first rule that matches its link and validator. Returns None
+    /// if no rule matches, otherwise the overridden message or None if it is suppressed.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer where the message came from.
+    /// * 'to_port' - the port of the peer the message is sent to.
+    /// * 'message' - the message including its 6 byte header.
+    pub fn override_squelch(
+        &self,
+        from_port: u16,
+        to_port: u16,
+        message: &[u8],
+    ) -> Option<Result<Option<Bytes>, SquelchError>> {
+        let rules = self.squelch_rules.read().unwrap();
+        let mut matching = rules
+            .iter()
+            .filter(|rule| rule.matches(from_port, to_port))
+            .peekable();
+        matching.peek()?;
+        let Some(squelch) = Squelch::from_message(message) else {
+            return Some(Err(SquelchError::Malformed));
+        };
+        for rule in matching {
+            let matches_validator = match rule.node_port {
+                Some(port) => match self.master_key(port) {
+                    Some((public_key, _)) => public_key == squelch.validator_key,
+                    None => return Some(Err(SquelchError::NoNodeKey(port))),
+                },
+                None => true,
+            };
+            if matches_validator {
+                return Some(Ok(squelch
+                    .apply(rule.action)
+                    .map(|squelch| squelch.to_message())));
+            }
+        }
+        None
+    }
+
+    /// Forges a squelch on behalf of a node, by injecting it on the link from the node to the peer, and tracks it like
+    /// the squelches the node sent itself.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the node the squelch is sent on behalf of.
+    /// * 'to_port' - the port of the peer the squelch is sent to.
+    /// * 'squelch' - the squelch.
+    pub async fn forge_squelch(
+        &self,
+        from_port: u16,
+        to_port: u16,
+        squelch: &Squelch,
+    ) -> Result<(), InjectError> {
+        let message = squelch.to_message();
+        self.inject(from_port, to_port, message.clone()).await?;
+        self.track_squelch(from_port, to_port, &message);
+        Ok(())
+    }
+
+    /// Replaces the rules by which the proposals of nodes are equivocated.
+    ///
+    /// # Parameters
+    /// * 'rules' - the new rules, of which the first one of a node equivocates its proposals.
+    pub fn set_equivocation_rules(
+        &self,
+        rules: Vec<EquivocationRule>,
+    ) -> Result<(), EquivocationError> {
+        for rule in rules.iter() {
+            rule.validate()?;
+        }
+        *self.equivocation_rules.write().unwrap() = rules;
+        Ok(())
+    }
+
+    /// Returns the rules by which the proposals of nodes are equivocated.
+    pub fn equivocation_rules(&self) -> Vec<EquivocationRule> {
+        self.equivocation_rules.read().unwrap().clone()
+    }
+
+    /// Turns a proposal into the variant that the first equivocation rule of its node delivers on its link, signed
+    /// again with the key of the node. Returns None if the proposals on the link are not equivocated, otherwise the
+    /// index of the variant and the variant.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer where the message came from.
+    /// * 'to_port' - the port of the peer the message is sent to.
+    /// * 'message_type' - the type of the message.
+    /// * 'message' - the message including its 6 byte header.
+    pub fn equivocate(
+        &self,
+        from_port: u16,
+        to_port: u16,
+        message_type: MessageType,
+        message: &[u8],
+    ) -> Option<Result<(usize, Bytes), MutationError>> {
+        if message_type != MessageType::ProposeLedger {
+            return None;
+        }
+        let rules = self.equivocation_rules.read().unwrap();
+        let rule = rules.iter().find(|rule| rule.from_port == from_port)?;
+        let (index, variant) = rule.variant(from_port, to_port)?;
+        let Some(secret_key) = self.signing_keys.read().unwrap().get(&from_port).copied() else {
+            return Some(Err(MutationError::NoSigningKey(from_port)));
+        };
+        Some(
+            equivocation::equivocate(message, variant, &secret_key)
+                .map(|equivocated| (index, equivocated)),
+        )
+    }
+
+    /// Returns the public key and secret key of a node, which is its master key since nodes validate with a seed.
+    ///
+    /// # Parameters
+    /// * 'port' - the port of the node.
+    fn master_key(&self, port: u16) -> Option<(Vec<u8>, SecretKey)> {
+        let secret_key = *self.signing_keys.read().unwrap().get(&port)?;
+        let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key);
+        Some((public_key.serialize().to_vec(), secret_key))
+    }
+
+    /// Signs a mutated message again with the key of the node it comes from.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the node the message comes from.
+    /// * 'message' - the message including its 6 byte header.
+    pub fn resign(&self, from_port: u16, message: &[u8]) -> Result<Bytes, MutationError> {
+        let secret_key = *self
+            .signing_keys
+            .read()
+            .unwrap()
+            .get(&from_port)
+            .ok_or(MutationError::NoSigningKey(from_port))?;
+        field_mutation::resign(message, &secret_key)
+    }
+
+    /// Starts capturing messages to be replayed. Can only be called once.
+    ///
+    /// # Parameters
+    /// * 'capture_buffer' - the buffer where the messages are captured.
+    ///
+    /// # Panics
+    /// * If messages are already captured.
+    pub fn enable_capture(&self, capture_buffer: Arc<CaptureBuffer>) {
+        self.capture_buffer
+            .set(capture_buffer)
+            .expect("Messages are already captured");
+    }
+
+    /// Returns the buffer where messages are captured to be replayed, if messages are captured.
+    pub fn capture_buffer(&self) -> Option<Arc<CaptureBuffer>> {
+        self.capture_buffer.get().cloned()
+    }
+
+    /// Switches to the passive observation mode, in which messages are forwarded as soon as they are read and only
+    /// observed. Messages are no longer sent to the controller, also by the proxies. Can only be called once.
+    ///
+    /// # Parameters
+    /// * 'observer' - the measurement of the added latency.
+    ///
+    /// # Panics
+    /// * If messages are already only observed.
+    pub fn enable_passive(&self, observer: PassiveObserver) {
+        self.passive
+            .set(observer)
+            .expect("Messages are already only observed");
+    }
+
+    /// Returns the measurement of the added latency, if messages are only observed.
+    pub fn passive(&self) -> Option<&PassiveObserver> {
+        self.passive.get()
+    }
+
+    /// Returns the phase of the run, which is the fault window if the run has no phases.
+    pub fn phase(&self) -> Phase {
+        *self.phase.read().unwrap()
+    }
+
+    /// Sets the phase of the run. Outside the fault window, messages are passed through untouched.
+    ///
+    /// # Parameters
+    /// * 'phase' - the phase that starts.
+    pub fn set_phase(&self, phase: Phase) {
+        *self.phase.write().unwrap() = phase;
+    }
+
+    /// Starts tracking the copies of every broadcast. Can only be called once.
+    ///
+    /// # Parameters
+    /// * 'broadcasts' - the tracking of the broadcasts.
+    ///
+    /// # Panics
+    /// * If the broadcasts are already tracked.
+    pub fn enable_broadcast_tracking(&self, broadcasts: BroadcastTracker) {
+        self.broadcasts
+            .set(broadcasts)
+            .expect("The broadcasts are already tracked");
+    }
+
+    /// Returns the tracking of the copies of every broadcast, if the copies are tracked.
+    pub fn broadcasts(&self) -> Option<&BroadcastTracker> {
+        self.broadcasts.get()
+    }
+
+    /// Remembers the actions of the controller for the copies of a broadcast that are sent to other nodes. Returns false
+    /// if the broadcasts are not tracked or the message is not a broadcast, in which case the actions are ignored.
+    ///
+    /// # Parameters
+    /// * 'message' - the message including its 6 byte header, before the controller mutated it.
+    /// * 'destinations' - the actions by the port of the node the copies are sent to.
+    pub fn set_fan_out(&self, message: &[u8], destinations: Vec<DestinationActions>) -> bool {
+        self.broadcasts()
+            .is_some_and(|broadcasts| broadcasts.set_fan_out(message, destinations))
+    }
+
+    /// Returns the actions of the controller for a copy of a broadcast that is sent to a node, or None if there are none.
+    ///
+    /// # Parameters
+    /// * 'to_port' - the port of the node the copy is sent to.
+    /// * 'message' - the message including its 6 byte header.
+    pub fn fan_out(&self, to_port: u16, message: &[u8]) -> Option<Vec<Action>> {
+        self.broadcasts()?.fan_out(to_port, message)
+    }
+
+    /// Registers the queue of the write stage of a node, such that messages can be injected on its behalf.
+    ///
+    /// # Parameters
+    /// * 'port' - the port of the node.
+    /// * 'write_queue' - the queue of the write stage of the node.
+    pub fn register_write_queue(&self, port: u16, write_queue: Arc<BoundedQueue<Message>>) {
+        self.write_queues.write().unwrap().insert(port, write_queue);
+    }
+
+    /// Injects a message into a link, as if it was sent by the peer the link comes from.
+    /// The message is written as-is, without asking the controller and regardless of the eclipse or pause.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer the message is sent on behalf of.
+    /// * 'to_port' - the port of the node the message is sent to.
+    /// * 'data' - the complete message, including its 6 byte header.
+    pub async fn inject(
+        &self,
+        from_port: u16,
+        to_port: u16,
+        data: Bytes,
+    ) -> Result<(), InjectError> {
+        let message_type = eclipse::check_injection(&data)?;
+        let write_queue = self
+            .link(from_port, to_port)
+            .and_then(|_| self.write_queues.read().unwrap().get(&from_port).cloned())
+            .ok_or(InjectError::NoLink(from_port, to_port))?;
+        let size = data.len();
+        write_queue.push(Message::new(data, to_port)).await;
+        self.events.emit(EventKind::MessageInjected {
+            from_port,
+            to_port,
+            message_type: message_type.to_string(),
+            size,
+        });
+        Ok(())
+    }
+
+    /// Returns how messages of the given type are handled on a link.
+    /// In passthrough mode, no messages are sent to the controller regardless of their type and link.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer the message comes from.
+    /// * 'to_port' - the port of the peer the message goes to.
+    /// * 'message_type' - the type of the message.
+    pub fn interception_mode(
+        &self,
+        from_port: u16,
+        to_port: u16,
+        message_type: MessageType,
+    ) -> InterceptionMode {
+        if self.is_passthrough() {
+            return InterceptionMode::Passthrough;
+        }
+        self.policy
+            .read()
+            .unwrap()
+            .link_mode(from_port, to_port, message_type)
+    }
+
+    /// Returns whether messages are forwarded without asking the controller, which is always the case while messages
+    /// are only observed.
+    pub fn is_passthrough(&self) -> bool {
+        self.passthrough.load(Ordering::SeqCst) || self.passive.get().is_some()
+    }
+
+    /// Sets whether messages are forwarded without asking the controller.
+    ///
+    /// # Parameters
+    /// * 'passthrough' - true to forward messages as-is, false to intercept them.
+    pub fn set_passthrough(&self, passthrough: bool) {
+        self.passthrough.store(passthrough, Ordering::SeqCst);
+    }
+
+    /// Creates and registers the gauges of a new queue.
+    ///
+    /// # Parameters
+    /// * 'name' - the name of the queue.
+    /// * 'capacity' - the maximum amount of messages in the queue.
+    pub fn register_queue(&self, name: String, capacity: usize) -> Arc<QueueGauge> {
+        let gauge = Arc::new(QueueGauge::new(name, capacity));
+        self.queue_gauges.lock().unwrap().push(gauge.clone());
+        gauge
+    }
+
+    /// Returns the gauges of all registered queues.
+    pub fn queue_gauges(&self) -> Vec<Arc<QueueGauge>> {
+        self.queue_gauges.lock().unwrap().clone()
+    }
+
+    /// Derives the random decisions on the links that are registered from now on from the seed of the run.
+    ///
+    /// # Parameters
+    /// * 'seed' - the seed of the run.
+    ///
+    /// # Panics
+    /// * If the seed was already set.
+    pub fn set_seed(&self, seed: RunSeed) {
+        self.seed.set(seed).expect("The seed was already set");
+    }
+
+    /// Creates and registers a new link without rules.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer the messages come from.
+    /// * 'to_port' - the port of the peer the messages go to.
+    /// * 'protocol' - the XRPL protocol version negotiated with the node the link reads from, if known.
+    pub fn register_link(
+        &self,
+        from_port: u16,
+        to_port: u16,
+        protocol: Option<String>,
+    ) -> Arc<Link> {
+        let link = Arc::new(Link {
+            from_port,
+            to_port,
+            protocol,
+            rule: RwLock::new(LinkRule::default()),
+            handled: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            controller_rtt_us: AtomicU64::new(0),
+            debugger: LinkDebugger::new(),
+            rng: Mutex::new(run_seed::rng(
+                self.seed.get().copied(),
+                &format!("link-{}-{}", from_port, to_port),
+            )),
+        });
+        self.links
+            .write()
+            .unwrap()
+            .insert((from_port, to_port), link.clone());
+        link
+    }
+
+    /// Returns a registered link, if it exists.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer the messages come from.
+    /// * 'to_port' - the port of the peer the messages go to.
+    pub fn link(&self, from_port: u16, to_port: u16) -> Option<Arc<Link>> {
+        self.links
+            .read()
+            .unwrap()
+            .get(&(from_port, to_port))
+            .cloned()
+    }
+
+    /// Returns all registered links, ordered by their ports.
+    pub fn links(&self) -> Vec<Arc<Link>> {
+        self.links.read().unwrap().values().cloned().collect()
+    }
+
+    /// Replaces the rules of a registered link.
+    /// Returns the link, or None if it does not exist.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer the messages come from.
+    /// * 'to_port' - the port of the peer the messages go to.
+    /// * 'rule' - the new rules.
+    pub fn set_link_rule(
+        &self,
+        from_port: u16,
+        to_port: u16,
+        rule: LinkRule,
+    ) -> Result<Option<Arc<Link>>, LinkRuleError> {
+        rule.validate()?;
+        let link = self.link(from_port, to_port);
+        if let Some(link) = &link {
+            *link.rule.write().unwrap() = rule;
+        }
+        Ok(link)
+    }
+
+    /// Returns whether forwarding is paused.
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// Pauses or resumes forwarding.
+    /// While paused, handled messages wait before they are queued to be written, and the links buffer what they read.
+    ///
+    /// # Parameters
+    /// * 'paused' - true to pause forwarding, false to resume it.
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.send_replace(paused);
+    }
+
+    /// Returns the time in which the delays and the scheduled scenarios are measured.
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        self.clock.clone()
+    }
+
+    /// Returns the factor by which all injected delays are multiplied, 1 means delays are applied as decided.
+    pub fn time_dilation(&self) -> f64 {
+        f64::from_bits(self.time_dilation.load(Ordering::SeqCst))
+    }
+
+    /// Sets the factor by which all injected delays are multiplied, such that the network can be run in slow motion.
+    /// The factor applies to messages handled after it is set.
+    ///
+    /// # Parameters
+    /// * 'factor' - the factor, above 1 to slow down and below 1 to speed up.
+    pub fn set_time_dilation(&self, factor: f64) -> Result<(), TimeDilationError> {
+        if !factor.is_finite() || factor <= 0.0 {
+            return Err(TimeDilationError(factor));
+        }
+        self.time_dilation.store(factor.to_bits(), Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Returns an injected delay scaled by the time dilation factor.
+    ///
+    /// # Parameters
+    /// * 'delay' - the injected delay.
+    pub fn dilate(&self, delay: Duration) -> Duration {
+        delay.mul_f64(self.time_dilation())
+    }
+
+    /// Returns how long the controller can take to decide on a message, None if its decisions are awaited indefinitely.
+    pub fn decision_timeout(&self) -> Option<DecisionTimeout> {
+        *self.decision_timeout.read().unwrap()
+    }
+
+    /// Sets how long the controller can take to decide on a message, and what happens if it takes longer.
+    ///
+    /// # Parameters
+    /// * 'timeout' - the budget and action, or None to await the decisions of the controller indefinitely.
+    pub fn set_decision_timeout(&self, timeout: Option<DecisionTimeout>) {
+        *self.decision_timeout.write().unwrap() = timeout;
+    }
+
+    /// Enables the circuit breaker of the controller channel.
+    ///
+    /// # Parameters
+    /// * 'circuit_breaker' - the circuit breaker.
+    ///
+    /// # Panics
+    /// * If the circuit breaker was already enabled.
+    pub fn enable_circuit_breaker(&self, circuit_breaker: CircuitBreaker) {
+        self.circuit_breaker
+            .set(circuit_breaker)
+            .expect("The circuit breaker was already enabled");
+    }
+
+    /// Returns the circuit breaker of the controller channel, if it is enabled.
+    pub fn circuit_breaker(&self) -> Option<&CircuitBreaker> {
+        self.circuit_breaker.get()
+    }
+
+    /// Spreads the links over multiple controllers.
+    ///
+    /// # Parameters
+    /// * 'controller_pool' - the controllers.
+    ///
+    /// # Panics
+    /// * If the links were already spread over multiple controllers.
+    pub fn enable_controller_pool(&self, controller_pool: ControllerPool) {
+        self.controller_pool
+            .set(controller_pool)
+            .expect("The controller pool was already enabled");
+    }
+
+    /// Returns the controllers the links are spread over, if there are multiple.
+    pub fn controller_pool(&self) -> Option<&ControllerPool> {
+        self.controller_pool.get()
+    }
+
+    /// Relays every message that is sent to the next interceptor in the chain.
+    ///
+    /// # Parameters
+    /// * 'relay' - the connection to the next interceptor.
+    ///
+    /// # Panics
+    /// * If messages are already relayed.
+    pub fn enable_relay(&self, relay: RelayClient) {
+        self.relay.set(relay).expect("Messages are already relayed");
+    }
+
+    /// Returns the next interceptor in the chain, if messages are relayed to it.
+    pub fn relay(&self) -> Option<&RelayClient> {
+        self.relay.get()
+    }
+
+    /// Adds a breakpoint and returns it with its assigned ID.
+    ///
+    /// # Parameters
+    /// * 'breakpoint' - the breakpoint, of which the ID is ignored.
+    pub fn add_breakpoint(&self, breakpoint: Breakpoint) -> Breakpoint {
+        self.breakpoints.write().unwrap().add(breakpoint)
+    }
+
+    /// Removes a breakpoint. Returns whether it existed.
+    /// Links halted at the breakpoint stay halted until they are continued.
+    ///
+    /// # Parameters
+    /// * 'id' - the ID of the breakpoint.
+    pub fn remove_breakpoint(&self, id: u32) -> bool {
+        self.breakpoints.write().unwrap().remove(id)
+    }
+
+    /// Returns all breakpoints in the order they were added.
+    pub fn breakpoints(&self) -> Vec<Breakpoint> {
+        self.breakpoints.read().unwrap().list()
+    }
+
+    /// Returns where a link should halt before handling a message, if it should:
+    /// when the message matches a breakpoint, or when the link is being stepped through.
+    ///
+    /// # Parameters
+    /// * 'link' - the link the message was read from.
+    /// * 'message' - the message including its header.
+    /// * 'sequence' - the position of the message on its link.
+    pub fn breakpoint_hit(
+        &self,
+        link: &Link,
+        message: &[u8],
+        sequence: u64,
+    ) -> Option<BreakpointHit> {
+        let breakpoints = self.breakpoints.read().unwrap();
+        let breakpoint_id = breakpoints
+            .find(link.from_port, link.to_port, message)
+            .map(|breakpoint| breakpoint.id);
+        if breakpoint_id.is_none() && !link.debugger.is_stepping() {
+            return None;
+        }
+        Some(BreakpointHit {
+            breakpoint_id,
+            from_port: link.from_port,
+            to_port: link.to_port,
+            sequence,
+            message_type: MessageType::from_message(message).unwrap_or(MessageType::Unknown(0)),
+            ledger_sequence: ledger_sequence(message),
+        })
+    }
+
+    /// Waits until forwarding is not paused, returns immediately if it is not.
+    pub async fn wait_until_resumed(&self) {
+        let mut paused = self.paused.subscribe();
+        // The sender is owned by self, so it can not be dropped while waiting
+        let _ = paused.wait_for(|paused| !paused).await;
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::action::Decision;
+    use crate::breakpoint::Breakpoint;
+    use crate::config::{InterceptionConfig, InterceptionMode, PassiveConfig};
+    use crate::equivocation::{EquivocationError, EquivocationRule, ProposalVariant};
+    use crate::field_mutation::{FieldMutation, FieldOperation, MutationError, MutationRule};
+    use crate::hot_reload::LocalRules;
+    use crate::interception_policy::InterceptionPolicy;
+    use crate::interceptor_state::{Blackhole, InterceptorState, LinkRule};
+    use crate::message_type::MessageType;
+    use crate::packet_timeline::PacketTimeline;
+    use crate::partition::OneWayPartition;
+    use crate::passive::PassiveObserver;
+    use crate::ping::Ping;
+    use crate::run_seed::RunSeed;
+    use crate::tx_suppression::TxSuppression;
+    use bytes::Bytes;
+    use proptest::prelude::*;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn passthrough_toggle() {
+        let state = InterceptorState::new(Arc::new(PacketTimeline::new(10)));
+        assert!(!state.is_passthrough());
+        state.set_passthrough(true);
+        assert!(state.is_passthrough());
+        state.set_passthrough(false);
+        assert!(!state.is_passthrough());
+
+        // Messages that are only observed are never sent to the controller
+        state.enable_passive(PassiveObserver::new(&PassiveConfig::default()));
+        assert!(state.is_passthrough());
+        assert_eq!(
+            state.interception_mode(60000, 60001, MessageType::Validation),
+            InterceptionMode::Passthrough
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn interception_mode_follows_policy_and_passthrough() {
+        let state = InterceptorState::new(Arc::new(PacketTimeline::new(10)));
+        assert_eq!(
+            state.interception_mode(60000, 60001, MessageType::Ping),
+            InterceptionMode::Intercept
+        );
+
+        let config = InterceptionConfig {
+            default: InterceptionMode::Intercept,
+            message_types: HashMap::from([("mtPING".to_string(), InterceptionMode::Mirror)]),
+            one_way: Vec::new(),
+        };
+        state.apply_rules(LocalRules {
+            policy: InterceptionPolicy::from_config(&config).unwrap(),
+            ..LocalRules::default()
+        });
+        assert_eq!(
+            state.interception_mode(60000, 60001, MessageType::Ping),
+            InterceptionMode::Mirror
+        );
+
+        state.set_passthrough(true);
+        assert_eq!(
+            state.interception_mode(60000, 60001, MessageType::Ping),
+            InterceptionMode::Passthrough
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn shadow_port_only_for_observed_node() {
+        let state = InterceptorState::new(Arc::new(PacketTimeline::new(10)));
+        assert_eq!(state.shadow_port(60001), None);
+        state.set_shadow(60001, 60003);
+        assert_eq!(state.shadow_port(60001), Some(60003));
+        assert_eq!(state.shadow_port(60000), None);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn one_way_partition_cuts_one_direction() {
+        let state = InterceptorState::new(Arc::new(PacketTimeline::new(10)));
+        assert_eq!(state.cut_reason(60000, 60001, MessageType::Ping), None);
+        state.set_one_way_partition(OneWayPartition {
+            cut_links: vec![(60000, 60001)],
+        });
+        assert_eq!(
+            state.cut_reason(60000, 60001, MessageType::Ping),
+            Some("partition")
+        );
+        assert_eq!(state.cut_reason(60001, 60000, MessageType::Ping), None);
+        state.set_one_way_partition(OneWayPartition::default());
+        assert_eq!(state.cut_reason(60000, 60001, MessageType::Ping), None);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn blackhole_drops_one_type_of_one_node() {
+        let state = InterceptorState::new(Arc::new(PacketTimeline::new(10)));
+        let blackhole = Blackhole {
+            from_port: 60002,
+            message_type: MessageType::Validation,
+        };
+        assert!(state.add_blackhole(blackhole));
+        assert!(!state.add_blackhole(blackhole));
+        assert_eq!(state.blackholes(), vec![blackhole]);
+        assert_eq!(
+            state.cut_reason(60002, 60000, MessageType::Validation),
+            Some("blackhole")
+        );
+        assert_eq!(
+            state.cut_reason(60002, 60001, MessageType::Validation),
+            Some("blackhole")
+        );
+        assert_eq!(
+            state.cut_reason(60002, 60000, MessageType::ProposeLedger),
+            None
+        );
+        assert_eq!(
+            state.cut_reason(60000, 60002, MessageType::Validation),
+            None
+        );
+
+        assert!(state.remove_blackhole(blackhole));
+        assert!(!state.remove_blackhole(blackhole));
+        assert_eq!(
+            state.cut_reason(60002, 60000, MessageType::Validation),
+            None
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn tx_suppression_cuts_only_transactions() {
+        let state = InterceptorState::new(Arc::new(PacketTimeline::new(10)));
+        state.set_tx_suppression(TxSuppression {
+            links: vec![(60000, 60001)],
+        });
+        assert_eq!(
+            state.cut_reason(60000, 60001, MessageType::Transaction),
+            Some("tx_suppression")
+        );
+        assert_eq!(
+            state.cut_reason(60000, 60001, MessageType::ProposeLedger),
+            None
+        );
+        assert_eq!(
+            state.cut_reason(60001, 60000, MessageType::Transaction),
+            None
+        );
+        state.set_tx_suppression(TxSuppression::default());
+        assert_eq!(
+            state.cut_reason(60000, 60001, MessageType::Transaction),
+            None
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn register_queue_gauges() {
+        let state = InterceptorState::new(Arc::new(PacketTimeline::new(10)));
+        let gauge = state.register_queue("write:60000".to_string(), 8);
+        assert_eq!(gauge.capacity, 8);
+
+        let gauges = state.queue_gauges();
+        assert_eq!(gauges.len(), 1);
+        assert_eq!(gauges[0].name, "write:60000");
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn link_rules() {
+        let state = InterceptorState::new(Arc::new(PacketTimeline::new(10)));
+        let link = state.register_link(60000, 60001, None);
+        assert_eq!(link.rule(), LinkRule::default());
+
+        let rule = LinkRule {
+            delay_ms: 100,
+            drop_probability: 0.5,
+        };
+        assert!(state.set_link_rule(60000, 60001, rule).unwrap().is_some());
+        assert_eq!(link.rule(), rule);
+        assert!(state.set_link_rule(60001, 60000, rule).unwrap().is_none());
+
+        let invalid = LinkRule {
+            delay_ms: 0,
+            drop_probability: 1.5,
+        };
+        assert!(state.set_link_rule(60000, 60001, invalid).is_err());
+        assert_eq!(link.rule(), rule);
+
+        link.count(false);
+        link.count(true);
+        assert_eq!(link.handled(), 2);
+        assert_eq!(link.dropped(), 1);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn seeded_drops_are_reproducible() {
+        let drops = |seed: u64| {
+            let state = InterceptorState::new(Arc::new(PacketTimeline::new(10)));
+            state.set_seed(RunSeed(seed));
+            let link = state.register_link(60000, 60001, None);
+            let rule = LinkRule {
+                delay_ms: 0,
+                drop_probability: 0.5,
+            };
+            state.set_link_rule(60000, 60001, rule).unwrap();
+            (0..64)
+                .map(|_| link.apply_rule(&mut Decision::forward(Bytes::new())))
+                .collect::<Vec<bool>>()
+        };
+        assert_eq!(drops(42), drops(42));
+        assert_ne!(drops(42), drops(43));
+    }
+
+    proptest! {
+        #[test]
+        // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+        fn seeded_rules_are_deterministic(
+            seed: u64,
+            drop_probability in 0.0f64..=1.0,
+            delay_ms in 0u32..1000,
+            other_messages in 0usize..64,
+        ) {
+            let decisions = |other_messages: usize| {
+                let state = InterceptorState::new(Arc::new(PacketTimeline::new(10)));
+                state.set_seed(RunSeed(seed));
+                // The messages on another link do not change the decisions on this link
+                let other = state.register_link(60001, 60000, None);
+                state
+                    .set_link_rule(60001, 60000, LinkRule { delay_ms: 0, drop_probability: 0.5 })
+                    .unwrap();
+                for _ in 0..other_messages {
+                    other.apply_rule(&mut Decision::forward(Bytes::new()));
+                }
+                let link = state.register_link(60000, 60001, None);
+                let rule = LinkRule { delay_ms, drop_probability };
+                state.set_link_rule(60000, 60001, rule).unwrap();
+                (0..64)
+                    .map(|_| {
+                        let mut decision = Decision::forward(Bytes::new());
+                        let dropped = link.apply_rule(&mut decision);
+                        (dropped, decision.send_amount, decision.delay)
+                    })
+                    .collect::<Vec<_>>()
+            };
+            prop_assert_eq!(decisions(0), decisions(other_messages));
+        }
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn controller_rtt_is_averaged() {
+        let state = InterceptorState::new(Arc::new(PacketTimeline::new(10)));
+        let link = state.register_link(60000, 60001, None);
+        assert_eq!(link.controller_rtt(), None);
+
+        link.record_controller_rtt(std::time::Duration::from_millis(8));
+        assert_eq!(
+            link.controller_rtt(),
+            Some(std::time::Duration::from_millis(8))
+        );
+        link.record_controller_rtt(std::time::Duration::from_millis(16));
+        assert_eq!(
+            link.controller_rtt(),
+            Some(std::time::Duration::from_millis(9))
+        );
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn pause_and_resume() {
+        let state = Arc::new(InterceptorState::new(Arc::new(PacketTimeline::new(10))));
+        state.wait_until_resumed().await;
+
+        state.set_paused(true);
+        assert!(state.is_paused());
+        let waiting = tokio::spawn({
+            let state = state.clone();
+            async move { state.wait_until_resumed().await }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+
+        state.set_paused(false);
+        waiting.await.unwrap();
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn time_dilation() {
+        let state = InterceptorState::new(Arc::new(PacketTimeline::new(10)));
+        let delay = std::time::Duration::from_millis(100);
+        assert_eq!(state.dilate(delay), delay);
+
+        state.set_time_dilation(2.5).unwrap();
+        assert_eq!(state.time_dilation(), 2.5);
+        assert_eq!(state.dilate(delay), std::time::Duration::from_millis(250));
+
+        assert!(state.set_time_dilation(0.0).is_err());
+        assert!(state.set_time_dilation(f64::NAN).is_err());
+        assert_eq!(state.time_dilation(), 2.5);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn breakpoint_hits() {
+        let state = InterceptorState::new(Arc::new(PacketTimeline::new(10)));
+        let link = state.register_link(60000, 60001, None);
+        let ping = [0, 0, 0, 2, 0, 3, 8, 0];
+        assert_eq!(state.breakpoint_hit(&link, &ping, 0), None);
+
+        let breakpoint = state.add_breakpoint(Breakpoint {
+            id: 0,
+            message_type: Some(MessageType::Ping),
+            from_port: None,
+            to_port: None,
+            ledger_sequence: None,
+        });
+        assert_eq!(state.breakpoints(), vec![breakpoint.clone()]);
+        let hit = state.breakpoint_hit(&link, &ping, 7).unwrap();
+        assert_eq!(hit.breakpoint_id, Some(breakpoint.id));
+        assert_eq!(hit.sequence, 7);
+        assert_eq!(hit.message_type, MessageType::Ping);
+
+        assert!(state.remove_breakpoint(breakpoint.id));
+        assert_eq!(state.breakpoint_hit(&link, &ping, 8), None);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn mutation_rules_mutate_matching_messages() {
+        let state = InterceptorState::new(Arc::new(PacketTimeline::new(10)));
+        let ping = |seq| {
+            Ping {
+                seq: Some(seq),
+                ..Ping::default()
+            }
+            .to_message()
+        };
+        let rule = |resign| MutationRule {
+            message_type: MessageType::Ping,
+            from_port: Some(60000),
+            to_port: None,
+            mutations: vec![FieldMutation {
+                path: "seq".to_string(),
+                operation: FieldOperation::Add { delta: 1 },
+            }],
+            resign,
+        };
+        state.set_mutation_rules(vec![rule(false), rule(false)]);
+        assert_eq!(state.mutation_rules().len(), 2);
+
+        assert_eq!(
+            state.mutate(60000, 60001, MessageType::Ping, &ping(1)),
+            Some(Ok(ping(3)))
+        );
+        assert_eq!(
+            state.mutate(60001, 60000, MessageType::Ping, &ping(1)),
+            None
+        );
+        assert_eq!(
+            state.mutate(60000, 60001, MessageType::Validation, &ping(1)),
+            None
+        );
+
+        state.set_mutation_rules(vec![rule(true)]);
+        assert_eq!(
+            state.mutate(60000, 60001, MessageType::Ping, &ping(1)),
+            Some(Err(MutationError::NoSigningKey(60000)))
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn equivocation_rules_match_the_proposals_of_the_node() {
+        let state = InterceptorState::new(Arc::new(PacketTimeline::new(10)));
+        let rule = |to_ports| EquivocationRule {
+            from_port: 60000,
+            variants: vec![ProposalVariant {
+                to_ports,
+                mutations: vec![FieldMutation {
+                    path: "closeTime".to_string(),
+                    operation: FieldOperation::Add { delta: 5 },
+                }],
+            }],
+        };
+        assert_eq!(
+            state.set_equivocation_rules(vec![rule(vec![60000])]),
+            Err(EquivocationError::AmbiguousPeer(60000))
+        );
+        state
+            .set_equivocation_rules(vec![rule(vec![60001])])
+            .unwrap();
+        assert_eq!(state.equivocation_rules(), vec![rule(vec![60001])]);
+
+        let proposal = [0, 0, 0, 0, 0, 33];
+        assert_eq!(
+            state.equivocate(60000, 60002, MessageType::ProposeLedger, &proposal),
+            None
+        );
+        assert_eq!(
+            state.equivocate(60001, 60000, MessageType::ProposeLedger, &proposal),
+            None
+        );
+        assert_eq!(
+            state.equivocate(60000, 60001, MessageType::Validation, &proposal),
+            None
+        );
+        assert_eq!(
+            state.equivocate(60000, 60001, MessageType::ProposeLedger, &proposal),
+            Some(Err(MutationError::NoSigningKey(60000)))
+        );
+    }
+}
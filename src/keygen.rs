@@ -0,0 +1,392 @@
+//! This module is responsible for generating the keys of nodes the way `rippled validation_create` does, such that the
+//! interceptor can mint identities itself instead of relying on key material from the node image.
+//!
+//! A seed of 16 bytes is derived from a passphrase or generated randomly, and a secp256k1 or ed25519 key pair is derived
+//! from it, see `generateSecretKey` in rippled. Seeds and keys are encoded as base58 tokens with a type prefix and a
+//! checksum, in the alphabet of the XRPL.
+
+use crate::docker_manager::ValidatorKeyData;
+use ed25519_dalek::Signer;
+use rand::Rng;
+use secp256k1::{Message as CryptoMessage, PublicKey, Secp256k1, SecretKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256, Sha512};
+use std::fmt;
+use std::iter;
+use std::str::FromStr;
+
+/// The alphabet of the base58 encoding of the XRPL.
+const ALPHABET: &[u8; 58] = b"rpshnaf39wBUDNEGHJKLM4PQRST7VWXYZ2bcdeCg65jkm8oFqi1tuvAxyz";
+/// The type prefix of a node public key, which encodes as 'n...'.
+pub const NODE_PUBLIC_KEY: u8 = 0x1C;
+/// The type prefix of a node private key, which encodes as 'p...'.
+pub const NODE_PRIVATE_KEY: u8 = 0x20;
+/// The type prefix of a seed, which encodes as 's...'.
+pub const FAMILY_SEED: u8 = 0x21;
+/// The byte an ed25519 public key is prefixed with, to tell it apart from a compressed secp256k1 public key.
+pub const ED25519_PREFIX: u8 = 0xED;
+
+/// Enum that represents the type of a key pair.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyType {
+    /// The key type of node identities and validation keys, which rippled requires for both.
+    #[default]
+    Secp256k1,
+    /// The key type that is common for the master keys of validators.
+    Ed25519,
+}
+
+impl FromStr for KeyType {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "secp256k1" => Ok(KeyType::Secp256k1),
+            "ed25519" => Ok(KeyType::Ed25519),
+            _ => Err(format!(
+                "'{}' is not a key type, which is secp256k1 or ed25519",
+                value
+            )),
+        }
+    }
+}
+
+/// Struct that represents the seed a key pair is derived from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Seed(pub [u8; 16]);
+
+impl Seed {
+    /// Derives a seed from a passphrase, like `rippled validation_create <passphrase>`.
+    ///
+    /// # Parameters
+    /// * 'passphrase' - the passphrase.
+    pub fn from_passphrase(passphrase: &str) -> Self {
+        let hash = Sha512::digest(passphrase.as_bytes());
+        Seed(hash[..16].try_into().unwrap())
+    }
+
+    /// Generates a random seed.
+    ///
+    /// # Parameters
+    /// * 'rng' - the generator of the seed.
+    pub fn generate<R: Rng>(rng: &mut R) -> Self {
+        Seed(rng.gen())
+    }
+}
+
+impl FromStr for Seed {
+    type Err = String;
+
+    /// Parses a seed in the base58 encoding of the XRPL, e.g. a validation seed.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let payload = decode_token(FAMILY_SEED, value)?;
+        payload
+            .try_into()
+            .map(Seed)
+            .map_err(|_| format!("'{}' is not a seed", value))
+    }
+}
+
+impl fmt::Display for Seed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", encode_token(FAMILY_SEED, &self.0))
+    }
+}
+
+/// Struct that represents the key pair of a node, derived from a seed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeKeys {
+    /// The type of the key pair.
+    pub key_type: KeyType,
+    /// The seed the key pair is derived from.
+    pub seed: Seed,
+    /// The secret key of 32 bytes.
+    pub secret_key: [u8; 32],
+    /// The public key of 33 bytes: compressed for secp256k1, or 0xED followed by the key for ed25519.
+    pub public_key: Vec<u8>,
+}
+
+impl NodeKeys {
+    /// Derives the key pair of a seed the way rippled does. A secp256k1 secret key is the root key of the seed: the
+    /// first half of the SHA-512 hash of the seed and a sequence of 4 bytes, the lowest sequence that gives a valid key.
+    /// An ed25519 secret key is the first half of the SHA-512 hash of the seed.
+    ///
+    /// # Parameters
+    /// * 'seed' - the seed.
+    /// * 'key_type' - the type of the key pair.
+    pub fn derive(seed: Seed, key_type: KeyType) -> Self {
+        let (secret_key, public_key) = match key_type {
+            KeyType::Secp256k1 => {
+                let secret_key = (0u32..)
+                    .find_map(|sequence| {
+                        let mut data = seed.0.to_vec();
+                        data.extend(sequence.to_be_bytes());
+                        SecretKey::from_slice(&Sha512::digest(&data)[..32]).ok()
+                    })
+                    .unwrap();
+                let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key);
+                (secret_key.secret_bytes(), public_key.serialize().to_vec())
+            }
+            KeyType::Ed25519 => {
+                let secret_key: [u8; 32] = Sha512::digest(seed.0)[..32].try_into().unwrap();
+                let verifying_key =
+                    ed25519_dalek::SigningKey::from_bytes(&secret_key).verifying_key();
+                let public_key = iter::once(ED25519_PREFIX)
+                    .chain(verifying_key.to_bytes())
+                    .collect();
+                (secret_key, public_key)
+            }
+        };
+        NodeKeys {
+            key_type,
+            seed,
+            secret_key,
+            public_key,
+        }
+    }
+
+    /// Returns the public key in the base58 encoding of the XRPL, 'n...'.
+    pub fn public_key_base58(&self) -> String {
+        encode_token(NODE_PUBLIC_KEY, &self.public_key)
+    }
+
+    /// Returns the secret key in the base58 encoding of the XRPL, 'p...'.
+    pub fn private_key_base58(&self) -> String {
+        encode_token(NODE_PRIVATE_KEY, &self.secret_key)
+    }
+
+    /// Returns the secret key for signing, if it is a secp256k1 key.
+    pub fn secp256k1_secret_key(&self) -> Option<SecretKey> {
+        match self.key_type {
+            KeyType::Secp256k1 => SecretKey::from_slice(&self.secret_key).ok(),
+            KeyType::Ed25519 => None,
+        }
+    }
+
+    /// Signs data with the secret key, see `sign`.
+    ///
+    /// # Parameters
+    /// * 'data' - the signed data.
+    pub fn sign(&self, data: &[u8]) -> Vec<u8> {
+        sign(self.key_type, &self.secret_key, data)
+    }
+
+    /// Returns the keys as a node is configured with them.
+    pub fn to_key_data(&self) -> ValidatorKeyData {
+        ValidatorKeyData {
+            status: "success".to_string(),
+            validation_private_key: self.private_key_base58(),
+            validation_public_key: self.public_key_base58(),
+            validation_seed: self.seed.to_string(),
+        }
+    }
+}
+
+/// Signs data the way rippled signs objects: with secp256k1 over the SHA-512Half of the data, encoded in DER, or with
+/// ed25519 over the data itself.
+///
+/// # Parameters
+/// * 'key_type' - the type of the secret key.
+/// * 'secret_key' - the secret key.
+/// * 'data' - the signed data.
+pub fn sign(key_type: KeyType, secret_key: &[u8; 32], data: &[u8]) -> Vec<u8> {
+    match key_type {
+        KeyType::Secp256k1 => {
+            sign_digest(secret_key, Sha512::digest(data)[..32].try_into().unwrap())
+        }
+        KeyType::Ed25519 => ed25519_dalek::SigningKey::from_bytes(secret_key)
+            .sign(data)
+            .to_bytes()
+            .to_vec(),
+    }
+}
+
+/// Signs a digest with a secp256k1 secret key, encoded in DER, like `signDigest` in rippled does for a hash that was
+/// computed already, e.g. the signing hash of a proposal.
+///
+/// # Parameters
+/// * 'secret_key' - the secp256k1 secret key.
+/// * 'digest' - the signed digest.
+pub fn sign_digest(secret_key: &[u8; 32], digest: [u8; 32]) -> Vec<u8> {
+    let digest = CryptoMessage::from_digest_slice(&digest).unwrap();
+    let secret_key = SecretKey::from_slice(secret_key).unwrap();
+    Secp256k1::new()
+        .sign_ecdsa(&digest, &secret_key)
+        .serialize_der()
+        .to_vec()
+}
+
+/// Encodes a payload as a base58 token of the XRPL: the type prefix, the payload and the first 4 bytes of the double
+/// SHA-256 hash of both.
+///
+/// # Parameters
+/// * 'token_type' - the type prefix, e.g. `NODE_PUBLIC_KEY`.
+/// * 'payload' - the encoded bytes.
+pub fn encode_token(token_type: u8, payload: &[u8]) -> String {
+    let mut bytes = vec![token_type];
+    bytes.extend(payload);
+    let checksum = checksum(&bytes);
+    bytes.extend(checksum);
+    encode_base58(&bytes)
+}
+
+/// Decodes a base58 token of the XRPL, and returns its payload.
+///
+/// # Parameters
+/// * 'token_type' - the type prefix the token should have.
+/// * 'token' - the token.
+pub fn decode_token(token_type: u8, token: &str) -> Result<Vec<u8>, String> {
+    let bytes = decode_base58(token).ok_or_else(|| format!("'{}' is not valid base58", token))?;
+    if bytes.len() < 5 || bytes[0] != token_type {
+        return Err(format!("'{}' is not a token of type {}", token, token_type));
+    }
+    let (content, checksum_bytes) = bytes.split_at(bytes.len() - 4);
+    if checksum(content) != checksum_bytes {
+        return Err(format!("'{}' has an invalid checksum", token));
+    }
+    Ok(content[1..].to_vec())
+}
+
+/// Returns the checksum of a token, the first 4 bytes of the double SHA-256 hash of its prefix and payload.
+///
+/// # Parameters
+/// * 'bytes' - the prefix and payload.
+fn checksum(bytes: &[u8]) -> [u8; 4] {
+    Sha256::digest(Sha256::digest(bytes))[..4]
+        .try_into()
+        .unwrap()
+}
+
+/// Encodes bytes in base58, every leading zero byte as the first letter of the alphabet.
+///
+/// # Parameters
+/// * 'bytes' - the encoded bytes.
+fn encode_base58(bytes: &[u8]) -> String {
+    let zeros = bytes.iter().take_while(|byte| **byte == 0).count();
+    // The digits of the number the bytes represent, least significant first
+    let mut digits: Vec<u8> = Vec::new();
+    for byte in &bytes[zeros..] {
+        let mut carry = u32::from(*byte);
+        for digit in digits.iter_mut() {
+            carry += u32::from(*digit) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    iter::repeat(ALPHABET[0])
+        .take(zeros)
+        .chain(digits.iter().rev().map(|digit| ALPHABET[*digit as usize]))
+        .map(char::from)
+        .collect()
+}
+
+/// Decodes base58, or returns None if it contains a letter outside the alphabet.
+///
+/// # Parameters
+/// * 'text' - the encoded text.
+fn decode_base58(text: &str) -> Option<Vec<u8>> {
+    let zeros = text
+        .bytes()
+        .take_while(|letter| *letter == ALPHABET[0])
+        .count();
+    // The bytes of the number the letters represent, least significant first
+    let mut bytes: Vec<u8> = Vec::new();
+    for letter in text.bytes().skip(zeros) {
+        let mut carry = ALPHABET.iter().position(|a| *a == letter)? as u32;
+        for byte in bytes.iter_mut() {
+            carry += u32::from(*byte) * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+    Some(
+        iter::repeat(0)
+            .take(zeros)
+            .chain(bytes.into_iter().rev())
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::keygen::{decode_token, encode_token, KeyType, NodeKeys, Seed, NODE_PUBLIC_KEY};
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn keys_match_rippled() {
+        // The keys `rippled validation_create masterpassphrase` prints
+        let seed = Seed::from_passphrase("masterpassphrase");
+        assert_eq!(seed.to_string(), "snoPBrXtMeMyMHUVTgbuqAfg1SUTb");
+        let keys = NodeKeys::derive(seed, KeyType::Secp256k1);
+        assert_eq!(
+            keys.public_key_base58(),
+            "n94a1u4jAz288pZLtw6yFWVbi89YamiC6JBXPVUj5zmExe5fTVg9"
+        );
+
+        let seed: Seed = "shM8uxbqE5g43G3VwKt6TM2pLvFan".parse().unwrap();
+        let keys = NodeKeys::derive(seed, KeyType::Secp256k1);
+        let key_data = keys.to_key_data();
+        assert_eq!(
+            key_data.validation_private_key,
+            "paAgnNZ9NaKTACGT3dGBV2eNHRxXNo8hRhNQNEWRJ23m5isp93t"
+        );
+        assert_eq!(
+            key_data.validation_public_key,
+            "n9KjTKEaHJ12Kuon5PDZ7fQAo5ExZ6cKH4h3L8q6m9YhoYqeBDho"
+        );
+        assert_eq!(key_data.validation_seed, "shM8uxbqE5g43G3VwKt6TM2pLvFan");
+        assert!(keys.secp256k1_secret_key().is_some());
+
+        let keys = NodeKeys::derive(seed, KeyType::Ed25519);
+        assert_eq!(keys.public_key[0], 0xED);
+        assert_eq!(
+            keys.public_key_base58(),
+            "nHUgHNykZgVggGa59CFTUTwn4eCEe63QBQLUJgfbQ97dALayqQ8D"
+        );
+        assert_eq!(
+            keys.private_key_base58(),
+            "pnPStD7N1yopzyipKsZVLAH9ekBvSBq6bPE4rb4UqveeuMttn3p"
+        );
+        assert!(keys.secp256k1_secret_key().is_none());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn tokens_are_checked() {
+        let token = encode_token(NODE_PUBLIC_KEY, &[0, 0, 1]);
+        assert_eq!(
+            decode_token(NODE_PUBLIC_KEY, &token).unwrap(),
+            vec![0, 0, 1]
+        );
+        // A different type, a changed letter and letters outside the alphabet
+        assert!(decode_token(0x21, &token).is_err());
+        let changed: String = token
+            .chars()
+            .enumerate()
+            .map(|(i, c)| {
+                if i == 3 {
+                    if c == 'p' {
+                        's'
+                    } else {
+                        'p'
+                    }
+                } else {
+                    c
+                }
+            })
+            .collect();
+        assert!(decode_token(NODE_PUBLIC_KEY, &changed).is_err());
+        assert!(decode_token(NODE_PUBLIC_KEY, "n9-0OIl").is_err());
+        assert!("snoPBrXtMeMyMHUVTgbuqAfg1SUT".parse::<Seed>().is_err());
+        assert!("ed448".parse::<KeyType>().is_err());
+    }
+}
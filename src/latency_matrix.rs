@@ -0,0 +1,173 @@
+//! This module is responsible for the latency matrix, which emulates a geographically distributed network.
+//!
+//! The matrix contains the latency of the messages from every node to every other node, e.g. derived from measured
+//! round trip times between datacenters. Its latencies are added to the delays of the messages on the links as a base
+//! latency, before the rules of the links are applied.
+
+use crate::config::LatencyConfig;
+use std::time::Duration;
+
+/// Struct that represents the latency between every pair of nodes, by their IDs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyMatrix {
+    /// The latency of the messages from the node of the row to the node of the column.
+    latencies: Vec<Vec<Duration>>,
+}
+
+impl LatencyMatrix {
+    /// Creates the latency matrix from the configuration, reading it from its file if one is configured.
+    /// Returns an error if the file can not be read, or the matrix is not square or contains an invalid latency.
+    ///
+    /// # Parameters
+    /// * 'latency_config' - the configuration of the matrix.
+    pub fn from_config(latency_config: &LatencyConfig) -> Result<Self, String> {
+        let matrix = match &latency_config.file {
+            Some(file) => {
+                let content = std::fs::read_to_string(file)
+                    .map_err(|e| format!("could not read {}: {}", file, e))?;
+                Self::parse_csv(&content)?
+            }
+            None => latency_config.matrix.clone(),
+        };
+        Self::new(&matrix, latency_config.round_trip)
+    }
+
+    /// Creates a latency matrix from latencies in ms. Returns an error if the matrix is not square or contains a
+    /// negative latency.
+    ///
+    /// # Parameters
+    /// * 'matrix' - the latency in ms from the node of the row to the node of the column.
+    /// * 'round_trip' - whether the matrix contains round trip times, of which half is applied in each direction.
+    pub fn new(matrix: &[Vec<f64>], round_trip: bool) -> Result<Self, String> {
+        let mut latencies = Vec::new();
+        for (from, row) in matrix.iter().enumerate() {
+            if row.len() != matrix.len() {
+                return Err(format!(
+                    "row {} has {} latencies, but the matrix has {} rows",
+                    from,
+                    row.len(),
+                    matrix.len()
+                ));
+            }
+            let row = row
+                .iter()
+                .enumerate()
+                .map(|(to, latency_ms)| {
+                    if !latency_ms.is_finite() || *latency_ms < 0.0 {
+                        return Err(format!(
+                            "the latency from node {} to node {} is invalid: {}",
+                            from, to, latency_ms
+                        ));
+                    }
+                    let latency_ms = if round_trip {
+                        latency_ms / 2.0
+                    } else {
+                        *latency_ms
+                    };
+                    Ok(Duration::from_micros((latency_ms * 1000.0).round() as u64))
+                })
+                .collect::<Result<Vec<Duration>, String>>()?;
+            latencies.push(row);
+        }
+        Ok(Self { latencies })
+    }
+
+    /// Parses a matrix of latencies in ms from CSV. A header row and a label column, e.g. with the names of the
+    /// datacenters, are skipped if they are not numeric.
+    ///
+    /// # Parameters
+    /// * 'content' - the CSV content.
+    pub fn parse_csv(content: &str) -> Result<Vec<Vec<f64>>, String> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .comment(Some(b'#'))
+            .from_reader(content.as_bytes());
+        let mut records = Vec::new();
+        for record in reader.records() {
+            let record = record.map_err(|e| format!("invalid CSV: {}", e))?;
+            records.push(record.iter().map(str::to_string).collect::<Vec<String>>());
+        }
+        let is_numeric = |field: &String| field.parse::<f64>().is_ok();
+        if records
+            .first()
+            .is_some_and(|header| !header.iter().skip(1).all(is_numeric))
+        {
+            records.remove(0);
+        }
+        records
+            .iter()
+            .map(|record| {
+                let fields = match record.first() {
+                    Some(label) if !is_numeric(label) => &record[1..],
+                    _ => &record[..],
+                };
+                fields
+                    .iter()
+                    .map(|field| {
+                        field
+                            .parse::<f64>()
+                            .map_err(|_| format!("'{}' is not a latency", field))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Returns the amount of nodes in the matrix.
+    pub fn size(&self) -> usize {
+        self.latencies.len()
+    }
+
+    /// Returns the latency of the messages from one node to another.
+    ///
+    /// # Parameters
+    /// * 'from' - the ID of the node the messages come from.
+    /// * 'to' - the ID of the node the messages go to.
+    ///
+    /// # Panics
+    /// * If a node is not part of the matrix.
+    pub fn latency(&self, from: usize, to: usize) -> Duration {
+        self.latencies[from][to]
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::latency_matrix::LatencyMatrix;
+    use std::time::Duration;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn round_trip_times_are_halved() {
+        let matrix = vec![vec![0.0, 80.0], vec![90.0, 0.0]];
+        let one_way = LatencyMatrix::new(&matrix, false).unwrap();
+        assert_eq!(one_way.size(), 2);
+        assert_eq!(one_way.latency(0, 1), Duration::from_millis(80));
+        assert_eq!(one_way.latency(1, 0), Duration::from_millis(90));
+
+        let round_trip = LatencyMatrix::new(&matrix, true).unwrap();
+        assert_eq!(round_trip.latency(0, 1), Duration::from_millis(40));
+
+        assert!(LatencyMatrix::new(&[vec![0.0, 1.0]], false).is_err());
+        assert!(LatencyMatrix::new(&[vec![-1.0]], false).is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_labelled_csv() {
+        let csv = "# round trip times between datacenters\n\
+            ,frankfurt,virginia,tokyo\n\
+            frankfurt,0,88,226\n\
+            virginia,88,0,145.5\n\
+            tokyo,226,145.5,0\n";
+        let matrix = LatencyMatrix::parse_csv(csv).unwrap();
+        assert_eq!(matrix.len(), 3);
+        assert_eq!(matrix[1], vec![88.0, 0.0, 145.5]);
+
+        let plain = LatencyMatrix::parse_csv("0,10\n10,0\n").unwrap();
+        assert_eq!(plain, vec![vec![0.0, 10.0], vec![10.0, 0.0]]);
+        assert!(LatencyMatrix::parse_csv("0,10\n10,far\n").is_err());
+    }
+}
@@ -0,0 +1,134 @@
+//! This module is responsible for monitoring the ledger closes of the nodes. Every node is subscribed to through the
+//! ledger stream of its public WebSocket port, and every ledger it closes is published as a `LedgerClosed` event.
+
+use crate::address;
+use crate::event_bus::{EventBus, EventKind};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use tracing::{debug, info, warn};
+
+/// The time between two attempts to subscribe to the ledger stream of a node.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Returns the event of a message of the ledger stream, or None if the message does not report a closed ledger.
+///
+/// # Parameters
+/// * 'node_id' - the ID of the node the message came from.
+/// * 'message' - the message of the ledger stream.
+pub fn ledger_closed(node_id: u32, message: &Value) -> Option<EventKind> {
+    if message["type"] != "ledgerClosed" {
+        return None;
+    }
+    Some(EventKind::LedgerClosed {
+        node_id,
+        ledger_index: u32::try_from(message["ledger_index"].as_u64()?).ok()?,
+        ledger_hash: message["ledger_hash"].as_str()?.to_string(),
+        txn_count: message["txn_count"].as_u64().unwrap_or(0),
+    })
+}
+
+/// Publishes the ledger closes of a node until the task is aborted.
+/// The node is subscribed to again when it can not be reached or closes the connection, e.g. while it starts.
+///
+/// # Parameters
+/// * 'node_id' - the ID of the node.
+/// * 'host' - the host the ports of the node are published on.
+/// * 'port' - the public WebSocket port of the node.
+/// * 'events' - the bus the ledger closes are published on.
+pub async fn monitor(node_id: u32, host: String, port: u16, events: Arc<EventBus>) {
+    let url = format!("ws://{}", address::host_port(&host, port));
+    let mut reported = false;
+    loop {
+        match follow(node_id, &url, &events).await {
+            Ok(()) => debug!("The ledger stream of node {} was closed", node_id),
+            Err(e) if !reported => {
+                warn!(
+                    "Could not follow the ledger stream of node {} at {}, retrying: {}",
+                    node_id, url, e
+                );
+                reported = true;
+            }
+            Err(e) => debug!(
+                "Could not follow the ledger stream of node {}: {}",
+                node_id, e
+            ),
+        }
+        tokio::time::sleep(RECONNECT_INTERVAL).await;
+    }
+}
+
+/// Subscribes to the ledger stream of a node, and publishes its ledger closes until the connection is closed.
+///
+/// # Parameters
+/// * 'node_id' - the ID of the node.
+/// * 'url' - the URL of the public WebSocket port of the node.
+/// * 'events' - the bus the ledger closes are published on.
+async fn follow(
+    node_id: u32,
+    url: &str,
+    events: &EventBus,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await?;
+    let subscribe = json!({ "id": 1, "command": "subscribe", "streams": ["ledger"] });
+    socket.send(WsMessage::Text(subscribe.to_string())).await?;
+    info!("Following the ledger stream of node {}", node_id);
+    while let Some(message) = socket.next().await {
+        let WsMessage::Text(text) = message? else {
+            continue;
+        };
+        let Ok(message) = serde_json::from_str::<Value>(&text) else {
+            continue;
+        };
+        if let Some(event) = ledger_closed(node_id, &message) {
+            events.emit(event);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::event_bus::EventKind;
+    use crate::ledger_monitor::ledger_closed;
+    use serde_json::json;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn ledger_closes_of_the_stream() {
+        let closed = json!({
+            "type": "ledgerClosed",
+            "fee_base": 10,
+            "ledger_hash": "8B7A1D3C",
+            "ledger_index": 42,
+            "ledger_time": 775_000_000,
+            "txn_count": 3,
+            "validated_ledgers": "2-42"
+        });
+        assert_eq!(
+            ledger_closed(2, &closed),
+            Some(EventKind::LedgerClosed {
+                node_id: 2,
+                ledger_index: 42,
+                ledger_hash: "8B7A1D3C".to_string(),
+                txn_count: 3,
+            })
+        );
+
+        // The response to the subscription is not a ledger close
+        let response = json!({
+            "id": 1,
+            "type": "response",
+            "status": "success",
+            "result": { "ledger_hash": "8B7A1D3C", "ledger_index": 41 }
+        });
+        assert_eq!(ledger_closed(2, &response), None);
+        assert_eq!(
+            ledger_closed(2, &json!({ "type": "ledgerClosed", "ledger_index": 42 })),
+            None
+        );
+    }
+}
@@ -0,0 +1,173 @@
+//! This module is responsible for setting up the logging of the interceptor.
+//!
+//! Logging is based on `tracing`: every intercepted link runs inside a `link` span and every
+//! intercepted message inside a `message` span, so each log line carries the link and message it belongs to.
+//! Every log line also carries the run ID, such that the logs of concurrent runs can be told apart.
+
+use crate::config::{LogFormat, LoggingConfig, OpenTelemetryConfig};
+use crate::run_id::RunId;
+use crate::telemetry;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::sync::Mutex;
+use tracing::{Event, Subscriber};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::format::{FmtSpan, Writer};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+/// Initializes the global logger, and the OpenTelemetry export if it is configured.
+/// The `RUST_LOG` environment variable takes precedence over the configured level.
+///
+/// # Parameters
+/// * 'config' - the logging configuration.
+/// * 'opentelemetry_config' - the OpenTelemetry configuration, if spans should be exported.
+/// * 'run_id' - the identifier of the run, added to every log line.
+///
+/// # Panics
+/// * If the configured log file could not be opened.
+/// * If the OpenTelemetry exporter could not be set up.
+/// * If a global logger was already set.
+pub fn init(
+    config: &LoggingConfig,
+    opentelemetry_config: Option<&OpenTelemetryConfig>,
+    run_id: &RunId,
+) {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(config.level.as_str()));
+
+    let writer = match &config.file {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .unwrap_or_else(|e| panic!("Could not open log file {}: {}", path, e));
+            BoxMakeWriter::new(Mutex::new(file))
+        }
+        None => BoxMakeWriter::new(std::io::stderr),
+    };
+
+    let span_events = if config.span_events {
+        FmtSpan::CLOSE
+    } else {
+        FmtSpan::NONE
+    };
+
+    let fmt_layer = match config.format {
+        LogFormat::Text => tracing_subscriber::fmt::layer()
+            .with_writer(writer)
+            .with_span_events(span_events)
+            .map_event_format(|format| RunIdFormat::new(format, run_id, false))
+            .boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .with_writer(writer)
+            .with_span_events(span_events)
+            .map_event_format(|format| RunIdFormat::new(format, run_id, true))
+            .boxed(),
+    };
+
+    // The exported spans are filtered independently of the log output, such that traces are
+    // complete even if only errors are logged.
+    let opentelemetry_layer = opentelemetry_config.map(|opentelemetry_config| {
+        let level = opentelemetry_config
+            .level
+            .parse::<LevelFilter>()
+            .unwrap_or(LevelFilter::INFO);
+        telemetry::layer(opentelemetry_config)
+            .expect("Could not set up the OpenTelemetry exporter")
+            .with_filter(level)
+    });
+
+    tracing_subscriber::registry()
+        .with(fmt_layer.with_filter(filter))
+        .with(opentelemetry_layer)
+        .init();
+}
+
+/// Struct that represents an event format that adds the run ID to every log line formatted by another format: as a
+/// `run=<id>` prefix of text lines, or as the `run_id` field of JSON lines.
+struct RunIdFormat<F> {
+    /// The format of the log lines without the run ID.
+    inner: F,
+    /// The identifier of the run.
+    run_id: String,
+    /// Whether the inner format writes JSON objects.
+    json: bool,
+}
+
+impl<F> RunIdFormat<F> {
+    /// Wraps a format.
+    ///
+    /// # Parameters
+    /// * 'inner' - the format of the log lines without the run ID.
+    /// * 'run_id' - the identifier of the run.
+    /// * 'json' - whether the inner format writes JSON objects.
+    fn new(inner: F, run_id: &RunId, json: bool) -> Self {
+        Self {
+            inner,
+            run_id: run_id.0.clone(),
+            json,
+        }
+    }
+}
+
+impl<S, N, F> FormatEvent<S, N> for RunIdFormat<F>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+    F: FormatEvent<S, N>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        if !self.json {
+            write!(writer, "run={} ", self.run_id)?;
+            return self.inner.format_event(ctx, writer, event);
+        }
+        let mut line = String::new();
+        self.inner
+            .format_event(ctx, Writer::new(&mut line), event)?;
+        write!(writer, "{}", with_run_id(&line, &self.run_id))
+    }
+}
+
+/// Adds the run ID as the first field of a JSON object. Lines that are not JSON objects are returned unchanged.
+/// The run ID is not escaped, since it may only contain letters, digits, dots, dashes and underscores.
+///
+/// # Parameters
+/// * 'line' - the JSON object.
+/// * 'run_id' - the identifier of the run.
+fn with_run_id(line: &str, run_id: &str) -> String {
+    match line.strip_prefix('{') {
+        Some("}") | Some("}\n") => format!("{{\"run_id\":\"{}\"{}", run_id, &line[1..]),
+        Some(rest) => format!("{{\"run_id\":\"{}\",{}", run_id, rest),
+        None => line.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::logging::with_run_id;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn json_lines_carry_the_run_id() {
+        assert_eq!(
+            with_run_id("{\"level\":\"INFO\"}\n", "run-1"),
+            "{\"run_id\":\"run-1\",\"level\":\"INFO\"}\n"
+        );
+        assert_eq!(with_run_id("{}\n", "run-1"), "{\"run_id\":\"run-1\"}\n");
+        assert_eq!(with_run_id("not json\n", "run-1"), "not json\n");
+    }
+}
@@ -1,16 +1,167 @@
 // #![feature(coverage_attribute)]  // This feature is required to use the #[coverage(off)] attribute, only available in nightly builds
+mod action;
+mod address;
+mod admin_api;
+mod amendments;
+mod anomaly;
+mod assertion_engine;
+mod bench;
+mod breakpoint;
+mod broadcast;
+mod buffer_pool;
+mod capture_file;
+mod checkpoint;
+mod ci_report;
+mod circuit_breaker;
+mod cli;
+mod clock;
+mod close_timeline;
+mod config;
 mod connection_handler;
+mod consensus_round;
+mod console;
+mod container_stats;
+mod controller_pool;
+mod cpu_throttle;
+mod crash_bundle;
+mod dashboard;
+mod delay_schedule;
+mod disk_queue;
 mod docker_manager;
+mod eclipse;
+mod equivocation;
+mod event_bus;
+mod export_sink;
+mod field_mutation;
+mod flapping;
+mod framing;
+mod gray_failure;
+mod grpc_server;
+mod handshake_response;
+mod heartbeat;
+mod hot_reload;
+mod interception_policy;
+mod interceptor_state;
+mod keygen;
+mod latency_matrix;
+mod ledger_monitor;
+mod logging;
+mod manifest;
+mod message_decoder;
+mod message_queue;
+mod message_type;
+mod nemesis;
+mod node_rpc;
 mod packet_client;
+mod packet_hook;
+mod packet_timeline;
+mod partition;
+mod passive;
 mod peer_connector;
+mod peer_stream;
+mod phase;
+mod ping;
+mod port_allocation;
+mod protocol_version;
+mod proxy;
+mod record_sink;
+mod relay;
+mod replay;
+mod rpc_proxy;
+mod run_id;
+mod run_seed;
+mod run_summary;
+mod session_store;
+mod signature;
+#[cfg(all(test, feature = "e2e"))]
+mod smoke_test;
+mod squelch;
+mod stream_sink;
+mod sybil;
+mod telemetry;
+mod termination;
+mod tls;
+mod topology;
+mod traffic_shaping;
+mod tx_generator;
+mod tx_suppression;
+mod validate;
+mod validator_list;
+mod wasm_plugin;
+mod wire_format;
+mod ws_proxy;
+use crate::amendments::AmendmentMonitor;
+use crate::anomaly::{AnomalyDetector, AnomalySink};
+use crate::assertion_engine::AssertionEngine;
+use crate::broadcast::BroadcastTracker;
+use crate::capture_file::{CaptureFile, InspectFilter};
+use crate::checkpoint::SessionState;
+use crate::ci_report::{CiReport, RunOutcome};
+use crate::circuit_breaker::CircuitBreaker;
+use crate::cli::{Cli, Command, RunMode};
+use crate::close_timeline::{CloseTimeline, CloseTimelineSink};
+use crate::config::{
+    CompressionConfig, FlappingConfig, GrpcServerConfig, HandshakeConfig, InterceptorConfig,
+    KeepaliveConfig, LargeMessageConfig, MalformedFrameConfig, QueueConfig, SummaryConfig,
+    SybilConfig, TenantConfig, TimeoutConfig, TlsConfig, TruncationConfig,
+};
 use crate::connection_handler::{Node, Peer};
-use crate::docker_manager::DockerNetwork;
+use crate::console::Console;
+use crate::controller_pool::{ControllerEndpoint, ControllerPool};
+use crate::cpu_throttle::{CpuThrottle, ThrottledNode};
+use crate::crash_bundle::CrashBundle;
+use crate::dashboard::Dashboard;
+use crate::docker_manager::{DockerContainer, DockerNetwork};
+use crate::eclipse::Eclipse;
+use crate::equivocation::EquivocationRule;
+use crate::event_bus::EventKind;
+use crate::export_sink::ExportSink;
+use crate::flapping::{FlapTiming, FlappingLink};
+use crate::gray_failure::GrayFailureRule;
+use crate::hot_reload::LocalRules;
+use crate::interceptor_state::{DecisionTimeout, InterceptorState};
+use crate::manifest::ManifestRule;
+use crate::nemesis::{Nemesis, NemesisNode};
+use crate::node_rpc::NodeRpcClient;
 use crate::packet_client::proto::Partition;
-use crate::peer_connector::PeerConnector;
+use crate::packet_client::{PacketClient, DEFAULT_CONTROLLER_ADDRESS};
+use crate::packet_hook::HookStage;
+use crate::packet_timeline::{PacketTimeline, DEFAULT_TIMELINE_CAPACITY};
+use crate::partition::OneWayPartition;
+use crate::passive::PassiveObserver;
+use crate::peer_connector::{
+    HandshakeHeaders, HandshakeTimeouts, PeerConnector, PeerIdentity, RetryPolicy,
+};
+use crate::relay::RelayClient;
+use crate::replay::{CaptureBuffer, ReplayRequest};
+use crate::rpc_proxy::RpcProxy;
+use crate::run_id::RunId;
+use crate::run_seed::RunSeed;
+use crate::run_summary::RunSummary;
+use crate::session_store::SqliteSink;
+use crate::squelch::SquelchRule;
+use crate::stream_sink::StreamSink;
+use crate::sybil::SybilPeer;
+use crate::termination::{StopReason, Termination};
+use crate::topology::Topology;
+use crate::tx_generator::TxGenerator;
+use crate::tx_suppression::{TxSuppression, TxSuppressionMeter};
+use crate::wasm_plugin::WasmPlugin;
+use crate::ws_proxy::WebSocketProxy;
+use chrono::Utc;
+use clap::Parser;
+use serde_json::json;
+use std::collections::HashMap;
+use std::error::Error;
 use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::{error, info, info_span, warn, Instrument};
 
 /// Function that checks whether a connection between two peers should be established or not.
 ///
@@ -61,6 +212,747 @@ fn is_valid_unl_connection(node_1_id: u32, node_2_id: u32, partitions: &Vec<Part
     false
 }
 
+/// Returns the identity the interceptor uses when it pretends to be a node.
+/// Ledger hashes that are not configured are taken from the last closed ledger of the node, and omitted if it can not be reached.
+///
+/// # Parameters
+/// * 'container' - the container of the node.
+/// * 'host' - the host the ports of the node are published on.
+/// * 'handshake_config' - the configured values of the handshake headers.
+///
+/// # Panics
+/// * If no or an invalid protocol version is configured.
+async fn peer_identity(
+    container: &DockerContainer,
+    host: &str,
+    handshake_config: &HandshakeConfig,
+) -> PeerIdentity {
+    let protocols = handshake_config
+        .protocols
+        .iter()
+        .map(|protocol| protocol.parse())
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_else(|e| panic!("Invalid handshake configuration: {}", e));
+    if protocols.is_empty() {
+        panic!("Invalid handshake configuration: no protocol versions configured");
+    }
+    let mut headers = HandshakeHeaders {
+        protocols,
+        network_id: handshake_config.network_id,
+        closed_ledger: handshake_config.closed_ledger.clone(),
+        previous_ledger: handshake_config.previous_ledger.clone(),
+        crawl: handshake_config.crawl,
+    };
+    if headers.closed_ledger.is_none() || headers.previous_ledger.is_none() {
+        let rpc_client = NodeRpcClient::new(host.to_string(), container.port_rpc as u16);
+        match rpc_client
+            .request("ledger", json!({ "ledger_index": "closed" }))
+            .await
+        {
+            Ok(ledger) => {
+                headers.closed_ledger = headers
+                    .closed_ledger
+                    .or(ledger["ledger_hash"].as_str().map(str::to_string));
+                headers.previous_ledger = headers
+                    .previous_ledger
+                    .or(ledger["ledger"]["parent_hash"].as_str().map(str::to_string));
+            }
+            Err(e) => warn!(
+                "Could not get the closed ledger of node {}, omitting it from the handshake: {}",
+                container.port_peer, e
+            ),
+        }
+    }
+
+    PeerIdentity {
+        port: container.port_peer as u16,
+        public_key: container.key_data.validation_public_key.clone(),
+        seed: container.key_data.validation_seed.clone(),
+        headers,
+    }
+}
+
+/// Returns the PeerConnector used for all handshakes, as configured.
+///
+/// # Parameters
+/// * 'host' - the host the ports of the nodes are published on.
+/// * 'handshake_config' - how failed handshakes are retried.
+/// * 'timeout_config' - how long every stage of the handshakes is allowed to take.
+/// * 'tls_config' - the configuration of the TLS sessions.
+fn peer_connector(
+    host: &str,
+    handshake_config: &HandshakeConfig,
+    timeout_config: &TimeoutConfig,
+    tls_config: &TlsConfig,
+) -> PeerConnector {
+    PeerConnector::new(
+        host.to_string(),
+        RetryPolicy {
+            retries: handshake_config.retries,
+            initial_backoff: Duration::from_millis(handshake_config.retry_backoff_ms),
+            max_backoff: Duration::from_millis(handshake_config.max_retry_backoff_ms),
+        },
+        HandshakeTimeouts {
+            connect: Duration::from_millis(timeout_config.connect_ms),
+            tls_handshake: Duration::from_millis(timeout_config.tls_handshake_ms),
+            upgrade: Duration::from_millis(timeout_config.upgrade_ms),
+        },
+        tls_config.clone(),
+    )
+}
+
+/// Establishes the intercepted connections between the nodes of the network that are linked in the topology.
+/// Returns every node together with the peers it is connected to.
+///
+/// If the network has a shadow node, every peer of the observed node is also connected to the shadow node,
+/// such that the messages delivered to the observed node can be mirrored to it on behalf of the same peer.
+///
+/// # Parameters
+/// * 'network' - the network whose nodes should be connected.
+/// * 'observed_node' - the ID of the node that is mirrored to the shadow node of the network, if any.
+/// * 'topology' - the pairs of nodes that get a link.
+/// * 'handshake_config' - the configured values of the handshake headers and how failed handshakes are retried.
+/// * 'timeout_config' - how long every stage of the handshakes is allowed to take.
+/// * 'tls_config' - the configuration of the TLS sessions.
+///
+/// # Panics
+/// * If a handshake failed permanently, or kept failing after all retries.
+async fn connect_nodes(
+    network: &DockerNetwork,
+    observed_node: Option<u32>,
+    topology: &Topology,
+    handshake_config: &HandshakeConfig,
+    timeout_config: &TimeoutConfig,
+    tls_config: &TlsConfig,
+) -> Vec<Node> {
+    let peer_connector = peer_connector(
+        &network.node_host,
+        handshake_config,
+        timeout_config,
+        tls_config,
+    );
+
+    let mut nodes = Vec::new();
+    let mut identities = Vec::new();
+    for node in network.containers.iter() {
+        nodes.push(Node::new(
+            node.port_peer as u16,
+            node.key_data.validation_public_key.clone(),
+        ));
+        identities.push(peer_identity(node, &network.node_host, handshake_config).await);
+    }
+
+    let nodes_length = network.containers.len();
+    for (i, container1) in network.containers.iter().enumerate() {
+        for (j, container2) in network.containers[(i + 1)..nodes_length].iter().enumerate() {
+            let j = i + j + 1; // Adjust 'j' to be the correct index in 'nodes'
+            if !topology.contains(i as u32, j as u32) {
+                continue;
+            }
+            let ((connection_half_1, handshake_1), (connection_half_2, handshake_2)) =
+                peer_connector
+                    .connect_peers(&identities[i], &identities[j])
+                    .await
+                    .unwrap_or_else(|e| {
+                        panic!(
+                            "Could not connect node {} to node {}: {}",
+                            container1.port_peer, container2.port_peer, e
+                        )
+                    });
+            let (read_half_1, write_half_1) = tokio::io::split(connection_half_1);
+            let (read_half_2, write_half_2) = tokio::io::split(connection_half_2);
+
+            // Every leg negotiates its own protocol version, which is used when writing to it
+            let protocol_1 = handshake_1
+                .version
+                .expect("The handshake did not negotiate a protocol version");
+            let protocol_2 = handshake_2
+                .version
+                .expect("The handshake did not negotiate a protocol version");
+            let node_1 = &mut nodes[i];
+            node_1.add_peer(Peer::new(
+                container2.port_peer as u16,
+                container2.key_data.validation_public_key.clone(),
+                handshake_1,
+                protocol_2,
+                write_half_2,
+                read_half_1,
+            ));
+            let node_2 = &mut nodes[j];
+            node_2.add_peer(Peer::new(
+                container1.port_peer as u16,
+                container1.key_data.validation_public_key.clone(),
+                handshake_2,
+                protocol_1,
+                write_half_1,
+                read_half_2,
+            ));
+        }
+    }
+
+    if let (Some(shadow), Some(observed_node)) = (&network.shadow, observed_node) {
+        for (i, container) in network.containers.iter().enumerate() {
+            if !topology.contains(i as u32, observed_node) {
+                continue;
+            }
+            let (connection, handshake) = peer_connector
+                .connect_half(shadow.port_peer as u16, &identities[i])
+                .await
+                .unwrap_or_else(|e| {
+                    panic!(
+                        "Could not connect node {} to shadow node {}: {}",
+                        container.port_peer, shadow.port_peer, e
+                    )
+                });
+            let (read_half, write_half) = tokio::io::split(connection);
+            let protocol = handshake
+                .version
+                .expect("The handshake did not negotiate a protocol version");
+            nodes[i].shadow = Some(Peer::new(
+                shadow.port_peer as u16,
+                shadow.key_data.validation_public_key.clone(),
+                handshake,
+                protocol,
+                write_half,
+                read_half,
+            ));
+        }
+    }
+    nodes
+}
+
+/// Generates a key for every Sybil peer and connects the Sybil peers to their target node.
+/// Returns the Sybil peers the target node accepted.
+///
+/// # Parameters
+/// * 'network' - the network containing the target node.
+/// * 'sybil_config' - the target node and the amount of Sybil peers.
+/// * 'handshake_config' - the configured values of the handshake headers and how failed handshakes are retried.
+/// * 'timeout_config' - how long every stage of the handshakes is allowed to take.
+/// * 'tls_config' - the configuration of the TLS sessions.
+///
+/// # Panics
+/// * If the target node does not exist.
+async fn connect_sybils(
+    network: &DockerNetwork,
+    sybil_config: &SybilConfig,
+    handshake_config: &HandshakeConfig,
+    timeout_config: &TimeoutConfig,
+    tls_config: &TlsConfig,
+) -> Vec<SybilPeer> {
+    let target = network
+        .containers
+        .get(sybil_config.target as usize)
+        .unwrap_or_else(|| {
+            panic!(
+                "Invalid sybil configuration: node {} does not exist",
+                sybil_config.target
+            )
+        });
+    let keys = network.generate_keys(sybil_config.peers, "sybil");
+    let headers = peer_identity(target, &network.node_host, handshake_config)
+        .await
+        .headers;
+    sybil::connect(
+        &peer_connector(
+            &network.node_host,
+            handshake_config,
+            timeout_config,
+            tls_config,
+        ),
+        target.port_peer as u16,
+        sybil::identities(keys, sybil_config.base_port, &headers),
+    )
+    .await
+}
+
+/// Allows the connections of the configured pairs of nodes to be closed and re-established during the run.
+/// Returns the links that flap, which are started with 'FlappingLink::run'.
+///
+/// # Parameters
+/// * 'network' - the network containing the nodes.
+/// * 'nodes' - the nodes together with the peers they are connected to, whose messages are not handled yet.
+/// * 'flapping_config' - the pairs of nodes whose links flap.
+/// * 'topology' - the pairs of nodes that get a link.
+/// * 'handshake_config' - the configured values of the handshake headers.
+///
+/// # Panics
+/// * If a node does not exist, or two nodes are not connected to each other.
+async fn flapping_links(
+    network: &DockerNetwork,
+    nodes: &mut [Node],
+    flapping_config: &FlappingConfig,
+    topology: &Topology,
+    handshake_config: &HandshakeConfig,
+) -> Vec<FlappingLink> {
+    let mut links = Vec::new();
+    for [id_1, id_2] in flapping_config.links.iter().copied() {
+        let container = |id: u32| {
+            network.containers.get(id as usize).unwrap_or_else(|| {
+                panic!("Invalid flapping configuration: node {} does not exist", id)
+            })
+        };
+        if !topology.contains(id_1, id_2) {
+            panic!(
+                "Invalid flapping configuration: node {} and {} are not connected",
+                id_1, id_2
+            );
+        }
+        links.push(FlappingLink::new(
+            nodes,
+            peer_identity(container(id_1), &network.node_host, handshake_config).await,
+            peer_identity(container(id_2), &network.node_host, handshake_config).await,
+        ));
+    }
+    links
+}
+
+/// Starts handling the messages of all nodes. Returns the handles of all spawned threads.
+///
+/// # Parameters
+/// * 'nodes' - the nodes together with the peers they are connected to.
+/// * 'client' - the PacketClient used to request actions from the controller.
+/// * 'state' - the runtime state shared by all links.
+/// * 'queue_config' - the configuration of the queues between the stages of every link.
+/// * 'keepalive' - the configuration of the local handling of pings, if pings should be answered locally.
+/// * 'idle_read_timeout' - after how long without data from a node a link event is logged, if at all.
+/// * 'large_messages' - the cap on the messages held whole, and how larger messages are handled.
+/// * 'malformed_frames' - the limit on the payload size, and how frames that violate the protocol are handled.
+#[allow(clippy::too_many_arguments)]
+fn handle_messages(
+    nodes: Vec<Node>,
+    client: Arc<Mutex<PacketClient>>,
+    state: Arc<InterceptorState>,
+    queue_config: &QueueConfig,
+    keepalive: Option<&KeepaliveConfig>,
+    idle_read_timeout: Option<Duration>,
+    large_messages: &LargeMessageConfig,
+    malformed_frames: &MalformedFrameConfig,
+) -> Vec<JoinHandle<()>> {
+    // All write queues are created up front, since pings are answered through the write stage of the other node
+    let write_queues: HashMap<_, _> = nodes
+        .iter()
+        .map(|node| (node.port, node.write_queue(&state, queue_config)))
+        .collect();
+    for (port, write_queue) in write_queues.iter() {
+        state.register_write_queue(*port, write_queue.clone());
+    }
+    let mut message_handlers = Vec::new();
+    for node in nodes {
+        let (mut read_threads, write_thread) = node.handle_messages(
+            client.clone(),
+            state.clone(),
+            queue_config,
+            keepalive,
+            &write_queues,
+            idle_read_timeout,
+            large_messages,
+            malformed_frames,
+        );
+        message_handlers.push(write_thread);
+        message_handlers.append(&mut read_threads);
+    }
+    message_handlers
+}
+
+/// Prints the summary of the run, writes it as JSON and reports it to the controller, as configured.
+///
+/// # Parameters
+/// * 'summary' - the summary of the run.
+/// * 'summary_config' - where the summary should be written and reported.
+/// * 'client' - the PacketClient used to report the summary to the controller.
+/// * 'run_id' - the identifier of the run, which names the summary file.
+async fn report_run(
+    summary: &RunSummary,
+    summary_config: &SummaryConfig,
+    client: Arc<Mutex<PacketClient>>,
+    run_id: &RunId,
+) {
+    println!("{}", summary);
+    if let Some(directory) = &summary_config.directory {
+        match summary.save(directory, run_id) {
+            Ok(path) => info!("Wrote the run summary to {}", path.display()),
+            Err(e) => warn!("Could not write the run summary: {}", e),
+        }
+    }
+    if summary_config.report_to_controller {
+        if let Err(e) = client
+            .lock()
+            .await
+            .report_run_result(summary.to_proto())
+            .await
+        {
+            warn!("Could not report the run summary to the controller: {}", e);
+        }
+    }
+}
+
+/// Starts a crash bundle with the files that are available even if the network was never set up:
+/// the reason, the configuration file and the log file of the interceptor.
+///
+/// # Parameters
+/// * 'reason' - why the run failed.
+/// * 'log_file' - the file the interceptor logs to, if it does not log to stderr.
+fn crash_bundle(reason: &str, log_file: Option<&str>) -> CrashBundle {
+    let mut bundle = CrashBundle::new(reason);
+    bundle.add_file("interceptor.toml", &InterceptorConfig::path());
+    if let Some(log_file) = log_file {
+        bundle.add_file("interceptor.log", log_file);
+    }
+    bundle
+}
+
+/// Writes a crash bundle and logs where it was written.
+///
+/// # Parameters
+/// * 'bundle' - the crash bundle.
+/// * 'directory' - the directory all crash bundles are written to.
+/// * 'run_id' - the identifier of the run, which names the bundle.
+fn write_crash_bundle(bundle: &CrashBundle, directory: &str, run_id: &RunId) {
+    match bundle.write(directory, run_id) {
+        Ok(path) => warn!("Wrote a crash bundle to {}", path.display()),
+        Err(e) => warn!("Could not write a crash bundle: {}", e),
+    }
+}
+
+/// Describes why a message handler stopped before it was aborted.
+///
+/// # Parameters
+/// * 'error' - the error the handler stopped with.
+fn task_failure(error: tokio::task::JoinError) -> String {
+    if !error.is_panic() {
+        return error.to_string();
+    }
+    let panic = error.into_panic();
+    let message = panic
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string());
+    format!("A task panicked: {}", message)
+}
+
+/// Creates the clients of the channels to a controller besides the channel of its client, which only connect once they
+/// are used.
+///
+/// # Parameters
+/// * 'client' - the client of the first channel to the controller, after the version has been negotiated.
+/// * 'address' - the address of the controller.
+/// * 'channels' - the amount of channels to the controller, including the first one.
+///
+/// # Panics
+/// * If the address is invalid.
+fn extra_channels(
+    client: &PacketClient,
+    address: &str,
+    channels: usize,
+) -> Vec<Arc<Mutex<PacketClient>>> {
+    (1..channels)
+        .map(|_| {
+            let channel = client
+                .another_channel(address)
+                .unwrap_or_else(|e| panic!("Invalid controller address {}: {}", address, e));
+            Arc::new(Mutex::new(channel))
+        })
+        .collect()
+}
+
+/// Creates the clients of every controller after the first one, which only connect once they are used.
+/// Returns every controller, together with why it is unreachable, if it is.
+///
+/// # Parameters
+/// * 'addresses' - the addresses of the controllers after the first one.
+/// * 'channels' - the amount of channels to every controller.
+/// * 'truncation' - the truncation of large messages, if they should be truncated.
+/// * 'compression' - the compression of the intercepted messages and of the decisions of the controllers.
+/// * 'run_id' - the identifier of the run, attached to every request to the controllers.
+///
+/// # Panics
+/// * If an address is invalid.
+async fn secondary_controllers(
+    addresses: &[String],
+    channels: usize,
+    truncation: Option<&TruncationConfig>,
+    compression: CompressionConfig,
+    run_id: &RunId,
+) -> Vec<ControllerEndpoint> {
+    let mut controllers = Vec::new();
+    for address in addresses {
+        let mut client = PacketClient::connect_lazy(address)
+            .unwrap_or_else(|e| panic!("Invalid controller address {}: {}", address, e));
+        client.set_run_id(run_id.clone());
+        client.set_truncation(truncation.cloned());
+        client.set_compression(compression);
+        let unreachable = match client.negotiate_version().await {
+            Ok(proto_version) => {
+                info!(
+                    "Using protocol version {} with the controller at {}",
+                    proto_version, address
+                );
+                None
+            }
+            Err(e) => Some(e.to_string()),
+        };
+        let extra_channels = extra_channels(&client, address, channels);
+        let mut channels = vec![Arc::new(Mutex::new(client))];
+        channels.extend(extra_channels);
+        controllers.push(ControllerEndpoint {
+            address: address.clone(),
+            channels,
+            unreachable,
+        });
+    }
+    controllers
+}
+
+/// Creates the runtime state and configures how it decides on messages: the local rules, the gray failures, the time
+/// dilation, the requests to the controllers, the hooks and the next interceptor in the chain.
+///
+/// # Parameters
+/// * 'interceptor_config' - the configuration.
+/// * 'ports' - the peer ports of the nodes, by node ID.
+/// * 'controllers' - the controllers with their channels, the first one being the controller that sets up the network.
+///
+/// # Panics
+/// * If the local rules, the gray failures, the forwarding configuration, a plugin or the address of the next
+///   interceptor is invalid.
+fn decision_state(
+    interceptor_config: &InterceptorConfig,
+    ports: &[u16],
+    controllers: Vec<ControllerEndpoint>,
+) -> Arc<InterceptorState> {
+    let state = Arc::new(InterceptorState::new(Arc::new(PacketTimeline::new(
+        DEFAULT_TIMELINE_CAPACITY,
+    ))));
+    // The local rules are read from the file they are reloaded from, if it is not the configuration file itself
+    let rules_config = match interceptor_config
+        .hot_reload
+        .as_ref()
+        .and_then(|hot_reload_config| hot_reload_config.file.as_deref())
+    {
+        Some(file) => InterceptorConfig::from_file(file)
+            .unwrap_or_else(|e| panic!("Could not load rules file {}: {}", file, e)),
+        None => interceptor_config.clone(),
+    };
+    state.apply_rules(
+        LocalRules::from_config(&rules_config, ports)
+            .unwrap_or_else(|e| panic!("Invalid configuration: {}", e)),
+    );
+    let gray_failures = interceptor_config
+        .gray_failure
+        .iter()
+        .flat_map(|config| config.rules.iter())
+        .map(|rule| GrayFailureRule::from_config(rule, ports))
+        .collect::<Result<Vec<_>, _>>()
+        .and_then(|rules| state.set_gray_failures(rules));
+    if let Err(e) = gray_failures {
+        panic!("Invalid gray failure configuration: {}", e);
+    }
+    let manifest_rules = interceptor_config
+        .manifests
+        .iter()
+        .flat_map(|config| config.rules.iter())
+        .map(|rule| ManifestRule::from_config(rule, ports))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_else(|e| panic!("Invalid manifest configuration: {}", e));
+    state.set_manifest_rules(manifest_rules);
+    let squelch_rules = interceptor_config
+        .squelch
+        .iter()
+        .flat_map(|config| config.rules.iter())
+        .map(|rule| SquelchRule::from_config(rule, ports))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_else(|e| panic!("Invalid squelch configuration: {}", e));
+    state.set_squelch_rules(squelch_rules);
+    let equivocation_rules = interceptor_config
+        .equivocation
+        .iter()
+        .flat_map(|config| config.rules.iter())
+        .map(|rule| EquivocationRule::from_config(rule, ports))
+        .collect::<Result<Vec<_>, _>>()
+        .and_then(|rules| state.set_equivocation_rules(rules));
+    if let Err(e) = equivocation_rules {
+        panic!("Invalid equivocation configuration: {}", e);
+    }
+    state
+        .set_time_dilation(interceptor_config.forwarding.time_dilation)
+        .unwrap_or_else(|e| panic!("Invalid forwarding configuration: {}", e));
+    let controller_config = &interceptor_config.controller;
+    state.set_decision_timeout((controller_config.decision_timeout_ms > 0).then(|| {
+        DecisionTimeout {
+            budget: Duration::from_millis(controller_config.decision_timeout_ms),
+            action: controller_config.timeout_action,
+        }
+    }));
+    if let Some(circuit_breaker_config) = &controller_config.circuit_breaker {
+        state.enable_circuit_breaker(CircuitBreaker::new(
+            circuit_breaker_config.clone(),
+            state.events.clone(),
+        ));
+    }
+    // A single controller with a single channel is asked directly
+    if controllers.len() > 1
+        || controllers
+            .iter()
+            .any(|endpoint| endpoint.channels.len() > 1)
+    {
+        let pool = ControllerPool::new(
+            controllers,
+            Duration::from_millis(controller_config.failover_retry_ms),
+            state.events.clone(),
+        );
+        info!(
+            "Spreading the links over {} controllers with {} channels each",
+            pool.controller_count(),
+            controller_config.channels
+        );
+        state.enable_controller_pool(pool);
+    }
+    if let Some(plugin_config) = &interceptor_config.plugins {
+        for path in &plugin_config.wasm {
+            let plugin = WasmPlugin::load(Path::new(path), plugin_config.fuel)
+                .unwrap_or_else(|e| panic!("Invalid plugin {}: {}", path, e));
+            info!("Loaded plugin {}", plugin.name);
+            state.register_hook(HookStage::AfterController, Arc::new(plugin));
+        }
+    }
+    if let Some(relay_config) = &interceptor_config.relay {
+        let relay = RelayClient::connect_lazy(&relay_config.next).unwrap_or_else(|e| {
+            panic!(
+                "Invalid address of the next interceptor {}: {}",
+                relay_config.next, e
+            )
+        });
+        info!(
+            "Relaying messages to the next interceptor at {}",
+            relay.address()
+        );
+        state.enable_relay(relay);
+    }
+    state
+}
+
+/// Returns the address the gRPC service of the interceptor listens on.
+///
+/// # Parameters
+/// * 'grpc_server_config' - the configuration of the gRPC service.
+///
+/// # Panics
+/// * If the address is invalid.
+fn grpc_server_address(grpc_server_config: &GrpcServerConfig) -> SocketAddr {
+    grpc_server_config.address.parse().unwrap_or_else(|e| {
+        panic!(
+            "Invalid gRPC server address {}: {}",
+            grpc_server_config.address, e
+        )
+    })
+}
+
+/// Creates a client for the RPC port of every node in the network, in the order of their IDs.
+///
+/// # Parameters
+/// * 'network' - the network whose nodes should be reached.
+fn rpc_clients(network: &DockerNetwork) -> Vec<NodeRpcClient> {
+    network
+        .containers
+        .iter()
+        .map(|container| NodeRpcClient::new(network.node_host.clone(), container.port_rpc as u16))
+        .collect()
+}
+
+/// Runs a subcommand that does not run a network: it validates the configuration, replays or inspects a capture file,
+/// cleans up the Docker resources of earlier runs, queries the database of a run, or saves or restores a checkpoint.
+///
+/// # Parameters
+/// * 'command' - the subcommand.
+/// * 'run_id' - the identifier given with `--run-id`, if any.
+async fn subcommand(
+    command: Command,
+    run_id: Option<RunId>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let persistence_config = || {
+        InterceptorConfig::load()
+            .persistence
+            .ok_or(checkpoint::CHECKPOINT_USAGE)
+    };
+    match command {
+        Command::Validate {
+            config,
+            nodes,
+            base_port,
+        } => validate::validate_command(
+            &config.unwrap_or_else(InterceptorConfig::path),
+            nodes,
+            base_port,
+        ),
+        Command::Replay {
+            capture,
+            controller,
+            speed,
+        } => {
+            let address = controller
+                .or_else(|| {
+                    InterceptorConfig::load()
+                        .controller
+                        .endpoints
+                        .first()
+                        .cloned()
+                })
+                .unwrap_or(DEFAULT_CONTROLLER_ADDRESS.to_string());
+            let run_id = run_id.unwrap_or_else(RunId::generate);
+            capture_file::replay_command(&capture, &address, speed, run_id).await
+        }
+        Command::Inspect {
+            capture,
+            links,
+            ports,
+            message_types,
+            from,
+            to,
+            hex,
+        } => {
+            let filter = InspectFilter {
+                links,
+                ports,
+                message_types,
+                from,
+                to,
+            };
+            capture_file::inspect_command(&capture, &filter, hex)
+        }
+        Command::Clean { volumes } => {
+            let removed = docker_manager::remove_orphans(run_id.as_ref(), volumes).await?;
+            for resource in removed.iter() {
+                println!("Removed {}", resource);
+            }
+            println!("Removed {} Docker resources", removed.len());
+            Ok(())
+        }
+        Command::Console { address } => {
+            let address = match address {
+                Some(address) => address,
+                None => {
+                    InterceptorConfig::load()
+                        .console
+                        .ok_or("The console is served while the [console] section is configured")?
+                        .address
+                }
+            };
+            console::attach_command(&address).await
+        }
+        Command::Query { args } => session_store::query_command(&args),
+        Command::Checkpoint { name } => {
+            checkpoint::checkpoint_command(&[name], &persistence_config()?).await
+        }
+        Command::Restore { name } => {
+            checkpoint::restore_command(&[name], &persistence_config()?).await
+        }
+        Command::Run | Command::Relay | Command::Bench => {
+            unreachable!("Runs are started by main")
+        }
+    }
+}
+
 /// The entrypoint for the packet interceptor application.
 ///
 /// This async function first sets up all the Docker containers who run the validator nodes.
@@ -68,12 +960,41 @@ fn is_valid_unl_connection(node_1_id: u32, node_2_id: u32, partitions: &Vec<Part
 /// Then, it starts all the threads that handle the messages sent between the peers.
 /// Finally, it waits for a Ctrl+C signal to correctly exit.
 ///
+/// The command line is described by `cli::Cli`. The `relay` subcommand does not set up a network, but decides on the
+/// messages relayed by the previous interceptor in a chain, see `relay`, and the `bench` subcommand measures the overhead
+/// of the interception, see `bench::run`. The other subcommands do not run a network at all, see `subcommand`.
+/// When tenants are configured, it runs the network of every tenant at the same time, see `run_tenants`.
+///
+/// The exit code tells the outcome of the run apart, see `ci_report::RunOutcome`.
+///
 /// # Panics:
 /// - If the Ctrl+C handler could not be setup
-/// - If the PacketClient could not be setup
-/// - If the configuration request failed
+/// - If the tenant configuration is invalid
+/// - If the network could not be set up, see `run`
 #[tokio::main]
 async fn main() -> io::Result<()> {
+    let cli = Cli::parse();
+    let mode = match cli.command.clone().unwrap_or(Command::Run) {
+        Command::Run => RunMode::Network,
+        Command::Relay => RunMode::Relay,
+        Command::Bench => RunMode::Bench,
+        command => {
+            if let Err(e) = subcommand(command, cli.run_id).await {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+    };
+    let Cli {
+        seed,
+        run_id,
+        fresh,
+        gray_failures,
+        ..
+    } = cli;
+    let run_id = run_id.unwrap_or_else(RunId::generate);
+
     let running = Arc::new(AtomicBool::new(true));
     let running_cloned = running.clone();
 
@@ -82,82 +1003,1159 @@ async fn main() -> io::Result<()> {
     })
     .expect("Unable to set Ctrl+C handler");
 
-    env_logger::init();
+    let mut interceptor_config = InterceptorConfig::load();
+    if !gray_failures.is_empty() {
+        interceptor_config
+            .gray_failure
+            .get_or_insert_with(Default::default)
+            .rules
+            .extend(gray_failures);
+    }
+    logging::init(
+        &interceptor_config.logging,
+        interceptor_config.opentelemetry.as_ref(),
+        &run_id,
+    );
+    info!("Starting run {}", run_id);
 
-    let client = match packet_client::PacketClient::new().await {
+    // Setting up the network or the controller fails by panicking on the main thread, which ends the process.
+    // Any other panic, e.g. of a link that lost its node or the controller, ends the run such that it is still reported.
+    let default_panic_hook = std::panic::take_hook();
+    let running_on_panic = running.clone();
+    let panic_log_file = interceptor_config.logging.file.clone();
+    let panic_crash_bundle = interceptor_config.crash_bundle.clone();
+    let panic_run_id = run_id.clone();
+    std::panic::set_hook(Box::new(move |info| {
+        default_panic_hook(info);
+        if std::thread::current().name() != Some("main") {
+            running_on_panic.store(false, Ordering::SeqCst);
+            return;
+        }
+        if panic_crash_bundle.enabled {
+            let bundle = crash_bundle(
+                &format!("Setup failed: {}", info),
+                panic_log_file.as_deref(),
+            );
+            write_crash_bundle(&bundle, &panic_crash_bundle.directory, &panic_run_id);
+        }
+        std::process::exit(RunOutcome::InfrastructureFailure.exit_code());
+    }));
+
+    let outcome = if interceptor_config.tenants.is_empty() {
+        run(interceptor_config, None, run_id, mode, seed, fresh, running).await
+    } else {
+        TenantConfig::validate(&interceptor_config.tenants)
+            .unwrap_or_else(|e| panic!("Invalid tenant configuration: {}", e));
+        run_tenants(
+            interceptor_config.tenants,
+            run_id,
+            mode,
+            seed,
+            fresh,
+            running,
+        )
+        .await
+    };
+    telemetry::shutdown().await;
+    std::process::exit(outcome.exit_code());
+}
+
+/// Runs the networks of several controllers at the same time, each with its own configuration file, and returns the
+/// worst outcome of their runs. Every network runs as if it was run on its own, see `run`, except that the names of its
+/// containers are prefixed with its name and its run ID is suffixed with its name. A network whose setup fails stops
+/// the other networks as well.
+///
+/// # Parameters
+/// * 'tenants' - the networks.
+/// * 'run_id' - the identifier of the process, which the run IDs of the networks are derived from.
+/// * 'mode' - what the runs do with their networks.
+/// * 'seed' - the seed of the runs, if any.
+/// * 'fresh' - whether the volumes of previous runs are removed.
+/// * 'running' - whether the runs continue, cleared on Ctrl+C.
+///
+/// # Panics
+/// - If the configuration file of a network could not be loaded
+async fn run_tenants(
+    tenants: Vec<TenantConfig>,
+    run_id: RunId,
+    mode: RunMode,
+    seed: Option<RunSeed>,
+    fresh: bool,
+    running: Arc<AtomicBool>,
+) -> RunOutcome {
+    let mut runs = Vec::new();
+    for tenant in tenants {
+        let config = InterceptorConfig::from_file(&tenant.config).unwrap_or_else(|e| {
+            panic!(
+                "Could not load configuration file {} of network {}: {}",
+                tenant.config, tenant.name, e
+            )
+        });
+        info!(
+            "Starting network {} configured by {}",
+            tenant.name, tenant.config
+        );
+        let span = info_span!("network", name = %tenant.name);
+        let name = tenant.name.clone();
+        let run = tokio::spawn(
+            run(
+                config,
+                Some(tenant.name),
+                run_id.tenant(&name),
+                mode,
+                seed,
+                fresh,
+                running.clone(),
+            )
+            .instrument(span),
+        );
+        runs.push((name, run));
+    }
+    let mut outcome = RunOutcome::Clean;
+    for (name, run) in runs {
+        let network_outcome = match run.await {
+            Ok(network_outcome) => network_outcome,
+            Err(e) => {
+                error!("Network {} failed: {}", name, task_failure(e));
+                RunOutcome::InfrastructureFailure
+            }
+        };
+        info!("Network {} ended with outcome {:?}", name, network_outcome);
+        if network_outcome.exit_code() > outcome.exit_code() {
+            outcome = network_outcome;
+        }
+    }
+    outcome
+}
+
+/// Runs a network: sets up its containers, connects its nodes and handles their messages until the run is stopped, and
+/// returns the outcome of the run.
+///
+/// # Parameters
+/// * 'interceptor_config' - the local configuration of the network.
+/// * 'namespace' - the name of the network if several networks run at the same time, which prefixes its containers.
+/// * 'run_id' - the identifier of the run.
+/// * 'mode' - what the run does with the network.
+/// * 'seed' - the seed of the run, if any.
+/// * 'fresh' - whether the volumes of a previous run are removed.
+/// * 'running' - whether the run continues, cleared on Ctrl+C.
+///
+/// # Panics:
+/// - If the PacketClient could not be setup
+/// - If the version negotiation failed
+/// - If the configuration request failed
+async fn run(
+    interceptor_config: InterceptorConfig,
+    namespace: Option<String>,
+    run_id: RunId,
+    mode: RunMode,
+    seed: Option<RunSeed>,
+    fresh: bool,
+    running: Arc<AtomicBool>,
+) -> RunOutcome {
+    let controller_addresses = &interceptor_config.controller.endpoints;
+    let primary_address = controller_addresses
+        .first()
+        .map_or(DEFAULT_CONTROLLER_ADDRESS, String::as_str);
+    let client = match packet_client::PacketClient::connect(primary_address).await {
         Ok(client) => Arc::new(Mutex::new(client)),
         error => panic!("Error creating client: {:?}", error),
     };
 
+    let primary_channels = {
+        let mut client = client.lock().await;
+        client.set_run_id(run_id.clone());
+        let proto_version = client
+            .negotiate_version()
+            .await
+            .expect("Could not negotiate the protocol version with the controller");
+        client.set_truncation(interceptor_config.truncation.clone());
+        client.set_compression(interceptor_config.controller.compression);
+        info!(
+            "Using protocol version {} with the controller, which supports actions {:?}",
+            proto_version,
+            client.controller_actions()
+        );
+        extra_channels(
+            &client,
+            primary_address,
+            interceptor_config.controller.channels,
+        )
+    };
+    let mut controllers = vec![ControllerEndpoint {
+        address: primary_address.to_string(),
+        channels: [vec![client.clone()], primary_channels].concat(),
+        unreachable: None,
+    }];
+    controllers.extend(
+        secondary_controllers(
+            controller_addresses.get(1..).unwrap_or_default(),
+            interceptor_config.controller.channels,
+            interceptor_config.truncation.as_ref(),
+            interceptor_config.controller.compression,
+            &run_id,
+        )
+        .await,
+    );
+
     // Get config from controller
-    let network_config = client
+    let mut network_config = client
         .lock()
         .await
         .get_config()
         .await
         .expect("Could not get config from controller");
 
+    if mode == RunMode::Relay {
+        let ports: Vec<u16> = (0..network_config.number_of_nodes)
+            .map(|i| (network_config.base_port_peer + i) as u16)
+            .collect();
+        let state = decision_state(&interceptor_config, &ports, controllers);
+        if let Some(seed) = seed {
+            state.set_seed(seed);
+        }
+        let address =
+            grpc_server_address(&interceptor_config.grpc_server.clone().unwrap_or_default());
+        info!(
+            "Deciding on the messages relayed to {} as a relay layer",
+            address
+        );
+        let server = tokio::spawn(grpc_server::serve(address, state, client));
+        // Wait for Ctrl+C signal
+        while running.load(Ordering::SeqCst) && !server.is_finished() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        server.abort();
+        return RunOutcome::Clean;
+    }
+
+    let bench_mode = mode == RunMode::Bench;
+    if bench_mode {
+        bench::adjust_network_config(&mut network_config);
+    }
+    let mut topology = match &interceptor_config.topology {
+        Some(topology_config) => {
+            let mut topology_config = topology_config.clone();
+            if let Some(seed) = seed {
+                topology_config.seed = seed.derive("topology");
+            }
+            let topology = Topology::from_config(&topology_config, network_config.number_of_nodes)
+                .and_then(|topology| topology.validate(&network_config).map(|_| topology))
+                .unwrap_or_else(|e| panic!("Invalid topology: {}", e));
+            info!("Linking {} pairs of nodes", topology.len());
+            topology
+        }
+        None => Topology::from_partitions(
+            network_config.number_of_nodes,
+            &network_config.net_partitions,
+        ),
+    };
+    let roles = interceptor_config.roles.clone().unwrap_or_default();
+    roles
+        .validate(network_config.number_of_nodes)
+        .unwrap_or_else(|e| panic!("Invalid role configuration: {}", e));
+    topology.link_hubs(
+        &roles.hubs,
+        network_config.number_of_nodes,
+        &network_config.net_partitions,
+    );
+
+    if let Some(resource_config) = &interceptor_config.resources {
+        resource_config
+            .validate(network_config.number_of_nodes)
+            .unwrap_or_else(|e| panic!("Invalid resource configuration: {}", e));
+    }
+    if let Some(clock_skew_config) = &interceptor_config.clock_skew {
+        clock_skew_config
+            .validate(network_config.number_of_nodes)
+            .unwrap_or_else(|e| panic!("Invalid clock skew configuration: {}", e));
+    }
+    if let Some(amendment_config) = &interceptor_config.amendments {
+        amendment_config
+            .validate(network_config.number_of_nodes)
+            .unwrap_or_else(|e| panic!("Invalid amendment configuration: {}", e));
+    }
+    if let Some(validator_list_config) = &interceptor_config.validator_list {
+        validator_list_config
+            .validate(&roles, network_config.number_of_nodes)
+            .unwrap_or_else(|e| panic!("Invalid validator list configuration: {}", e));
+    }
+
+    interceptor_config
+        .addressing
+        .validate(network_config.number_of_nodes)
+        .unwrap_or_else(|e| panic!("Invalid addressing configuration: {}", e));
+    interceptor_config
+        .ports
+        .validate()
+        .unwrap_or_else(|e| panic!("Invalid port configuration: {}", e));
+    if let Some(tx_generator_config) = &interceptor_config.tx_generator {
+        tx_generator_config
+            .validate()
+            .unwrap_or_else(|e| panic!("Invalid transaction generator configuration: {}", e));
+    }
+
     // Init docker network
     let mut network = DockerNetwork::new(network_config.clone());
+    if let Some(namespace) = namespace {
+        network.set_namespace(namespace);
+    }
+    network.set_labels(run_id.labels(&interceptor_config.labels));
+    network.set_addressing(&interceptor_config.addressing);
+    if let Some(seed) = seed {
+        info!(
+            "Deriving the keys and random decisions of the run from seed {}",
+            seed.0
+        );
+        network.set_seed(seed);
+    }
+    network.set_roles(roles);
+    network.set_parallelism(interceptor_config.startup.parallelism);
+    network.set_ports(
+        interceptor_config.ports.clone(),
+        interceptor_config.shadow.is_some(),
+    );
+    if let Some(resource_config) = &interceptor_config.resources {
+        network.set_resources(resource_config.clone());
+    }
+    if let Some(clock_skew_config) = &interceptor_config.clock_skew {
+        network.set_clock_skew(clock_skew_config.clone());
+    }
+    if let Some(persistence_config) = &interceptor_config.persistence {
+        if seed.is_none() {
+            warn!("The databases of the nodes persist across runs, but without --seed the nodes get new keys");
+        }
+        network.set_persistence(persistence_config.clone(), fresh);
+    } else if fresh {
+        warn!("--fresh has no effect without the [persistence] section");
+    }
+    if let Some(amendment_config) = &interceptor_config.amendments {
+        network.set_amendments(amendment_config.clone());
+    }
+    if let Some(validator_list_config) = &interceptor_config.validator_list {
+        network.set_validator_list(validator_list_config.clone());
+    }
     network.initialize_network(client.clone()).await;
+    // The ports of the nodes may have been allocated instead of taken from the controller
+    network_config = network.config.clone();
+    if let Some(shadow_config) = &interceptor_config.shadow {
+        network.start_shadow(shadow_config).await;
+    }
     network.wait_for_startup().await;
 
-    let peer_connector = PeerConnector::new("127.0.0.1".to_string());
+    let observed_node = interceptor_config
+        .shadow
+        .as_ref()
+        .map(|shadow_config| shadow_config.observed_node);
+    let mut nodes = connect_nodes(
+        &network,
+        observed_node,
+        &topology,
+        &interceptor_config.handshake,
+        &interceptor_config.timeouts,
+        &interceptor_config.tls,
+    )
+    .await;
+    let flapping_links = match &interceptor_config.flapping {
+        Some(flapping_config) => {
+            flapping_links(
+                &network,
+                &mut nodes,
+                flapping_config,
+                &topology,
+                &interceptor_config.handshake,
+            )
+            .await
+        }
+        None => Vec::new(),
+    };
+    let sybils = match &interceptor_config.sybil {
+        Some(sybil_config) => {
+            connect_sybils(
+                &network,
+                sybil_config,
+                &interceptor_config.handshake,
+                &interceptor_config.timeouts,
+                &interceptor_config.tls,
+            )
+            .await
+        }
+        None => Vec::new(),
+    };
 
-    let mut nodes = Vec::new();
-    for node in network.containers.iter() {
-        nodes.push(Node::new(node.port_peer as u16));
+    let ports: Vec<u16> = network
+        .containers
+        .iter()
+        .map(|container| container.port_peer as u16)
+        .collect();
+    let state = decision_state(&interceptor_config, &ports, controllers);
+    let timeline = state.timeline.clone();
+    if let Some(seed) = seed {
+        state.set_seed(seed);
+    }
+    state.set_paused(interceptor_config.forwarding.start_paused);
+    if let (Some(shadow), Some(observed_node)) = (&network.shadow, observed_node) {
+        state.set_shadow(
+            network.containers[observed_node as usize].port_peer as u16,
+            shadow.port_peer as u16,
+        );
+    }
+    for container in network.containers.iter() {
+        state.register_signing_key(
+            container.port_peer as u16,
+            peer_connector::secret_key(&container.key_data.validation_seed),
+        );
+    }
+    if let Some(replay_config) = &interceptor_config.replay {
+        let message_types = replay_config
+            .message_types
+            .iter()
+            .map(|name| {
+                name.parse()
+                    .unwrap_or_else(|e| panic!("Invalid replay configuration: {}", e))
+            })
+            .collect();
+        state.enable_capture(Arc::new(CaptureBuffer::new(
+            replay_config.capacity,
+            message_types,
+        )));
+    }
+    if let Some(broadcast_config) = &interceptor_config.broadcasts {
+        state.enable_broadcast_tracking(BroadcastTracker::new(broadcast_config));
+    }
+    if let Some(passive_config) = &interceptor_config.passive {
+        info!(
+            "Only observing messages: they are forwarded as read, without asking the controller or applying local rules"
+        );
+        state.enable_passive(PassiveObserver::new(passive_config));
+    }
+    if let Some(sybil_config) = &interceptor_config.sybil {
+        let rejected = sybil_config.peers as usize - sybils.len();
+        if rejected > 0 {
+            state
+                .statistics
+                .count_error("sybil_rejected", rejected as u64);
+        }
     }
 
-    let nodes_length = network.containers.len();
-    for (i, container1) in network.containers.iter().enumerate() {
-        for (j, container2) in network.containers[(i + 1)..nodes_length].iter().enumerate() {
-            let j = i + j + 1; // Adjust 'j' to be the correct index in 'nodes'
-            if !is_valid_connection(i as u32, j as u32, network_config.net_partitions.as_ref()) {
-                continue;
+    let mut sink_threads = Vec::new();
+    let mut session_database = None;
+    if let Some(storage_config) = &interceptor_config.storage {
+        let sink = SqliteSink::create(&storage_config.directory, &run_id)
+            .unwrap_or_else(|e| panic!("Could not create the session database: {}", e));
+        session_database = Some(sink.path().to_path_buf());
+        let (sink, sink_thread) = record_sink::spawn(Box::new(sink), storage_config.capacity);
+        state.add_sink(sink);
+        sink_threads.push(sink_thread);
+    }
+    if interceptor_config.persistence.is_some() {
+        let session = SessionState {
+            seed: seed.map(|seed| seed.0),
+            containers: network
+                .containers
+                .iter()
+                .chain(network.shadow.iter())
+                .map(|container| container.name.clone())
+                .collect(),
+            session_database,
+        };
+        if let Err(e) = session.write(Path::new(&network.directory("session.json"))) {
+            warn!("Could not write the state of the session: {}", e);
+        }
+    }
+    if let Some(export_config) = &interceptor_config.export {
+        let sink = ExportSink::create(&export_config.directory, export_config.format, &run_id)
+            .unwrap_or_else(|e| panic!("Could not create the export file: {}", e));
+        let (sink, sink_thread) = record_sink::spawn(Box::new(sink), export_config.capacity);
+        state.add_sink(sink);
+        sink_threads.push(sink_thread);
+    }
+    if let Some(capture_file_config) = &interceptor_config.capture_file {
+        let (capture_file, capture_thread) = CaptureFile::create(capture_file_config, &run_id)
+            .unwrap_or_else(|e| panic!("Could not create the capture file: {}", e));
+        info!("Capturing messages to {}", capture_file.path.display());
+        state.set_capture_file(capture_file);
+        sink_threads.push(capture_thread);
+    }
+    if let Some(stream_config) = &interceptor_config.stream {
+        let sink = StreamSink::connect(stream_config)
+            .unwrap_or_else(|e| panic!("Could not connect to the stream: {}", e));
+        let (sink, sink_thread) = record_sink::spawn(Box::new(sink), stream_config.capacity);
+        state.add_sink(sink);
+        sink_threads.push(sink_thread);
+    }
+    let close_timeline = interceptor_config
+        .close_timeline
+        .as_ref()
+        .map(|close_timeline_config| {
+            let close_timeline = Arc::new(CloseTimeline::new(close_timeline_config));
+            let (sink, sink_thread) = record_sink::spawn(
+                Box::new(CloseTimelineSink(close_timeline.clone())),
+                close_timeline_config.capacity,
+            );
+            state.add_sink(sink);
+            sink_threads.push(sink_thread);
+            close_timeline
+        });
+    let tx_suppression_meter = interceptor_config
+        .tx_suppression
+        .as_ref()
+        .map(|_| Arc::new(TxSuppressionMeter::default()));
+    let anomaly_detector = interceptor_config.anomaly.as_ref().map(|anomaly_config| {
+        let detector = Arc::new(AnomalyDetector::new(anomaly_config, state.events.clone()));
+        let (sink, sink_thread) = record_sink::spawn(
+            Box::new(AnomalySink(detector.clone())),
+            anomaly::RECORD_CAPACITY,
+        );
+        state.add_sink(sink);
+        sink_threads.push(sink_thread);
+        detector
+    });
+
+    // Serve the event stream before the links are started, such that no link events are missed
+    let mut event_server = None;
+    if let Some(events_config) = &interceptor_config.events {
+        let listener = tokio::net::TcpListener::bind(&events_config.websocket_address)
+            .await
+            .unwrap_or_else(|e| {
+                panic!(
+                    "Could not listen for WebSocket connections on {}: {}",
+                    events_config.websocket_address, e
+                )
+            });
+        event_server = Some(tokio::spawn(event_bus::serve_websocket(
+            listener,
+            state.events.clone(),
+        )));
+    }
+    // Subscribe the dashboard before the links are started, such that it sees every link connect
+    let dashboard = interceptor_config.dashboard.clone().map(|dashboard_config| {
+        if interceptor_config.logging.file.is_none() {
+            warn!("The dashboard is shown while logging to stderr, set [logging] file to keep them apart");
+        }
+        Dashboard::new(dashboard_config, state.clone(), running.clone())
+    });
+    // Subscribe the hooks and the heartbeats before the links are started as well
+    let hook_events = state.events.subscribe();
+    let heartbeat_events = interceptor_config
+        .heartbeat
+        .as_ref()
+        .map(|_| state.events.subscribe());
+    let close_timeline_events = close_timeline.as_ref().map(|_| state.events.subscribe());
+    let tx_suppression_events = tx_suppression_meter
+        .as_ref()
+        .map(|_| state.events.subscribe());
+    let anomaly_events = anomaly_detector.as_ref().map(|_| state.events.subscribe());
+    let alert_events = interceptor_config
+        .anomaly
+        .as_ref()
+        .filter(|anomaly_config| anomaly_config.report_to_controller)
+        .map(|_| state.events.subscribe());
+    state.events.emit(EventKind::PartitionChanged {
+        partitions: network_config
+            .net_partitions
+            .iter()
+            .map(|partition| partition.nodes.clone())
+            .collect(),
+    });
+
+    let started_at = Utc::now();
+    let start_ledger = run_summary::validated_ledger(&rpc_clients(&network)).await;
+    // Pass the messages through untouched until the fault window starts, and again after it ended
+    let (phases, cool_down_ended) = match interceptor_config.phases.clone() {
+        Some(phase_config) => {
+            let (phases, cool_down_ended) = phase::start(phase_config, state.clone());
+            (Some(phases), Some(cool_down_ended))
+        }
+        None => (None, None),
+    };
+    let mut message_handlers = handle_messages(
+        nodes,
+        client.clone(),
+        state.clone(),
+        &interceptor_config.queues,
+        interceptor_config.keepalive.as_ref(),
+        (interceptor_config.timeouts.idle_read_secs > 0)
+            .then(|| Duration::from_secs(interceptor_config.timeouts.idle_read_secs)),
+        &interceptor_config.large_messages,
+        &interceptor_config.malformed_frames,
+    );
+    for sybil in sybils {
+        message_handlers.extend(sybil.handle_messages(state.clone(), &interceptor_config.queues));
+    }
+    message_handlers.push(tokio::spawn(packet_hook::dispatch_link_events(
+        hook_events,
+        state.clone(),
+    )));
+    if let Some((close_timeline, events)) = close_timeline.as_ref().zip(close_timeline_events) {
+        message_handlers.push(tokio::spawn(close_timeline.clone().follow(events)));
+    }
+    if let Some((meter, events)) = tx_suppression_meter.as_ref().zip(tx_suppression_events) {
+        message_handlers.push(tokio::spawn(meter.clone().follow(events)));
+    }
+    // The ledger closes are monitored for the close timeline and the report of the transaction suppression
+    if close_timeline.is_some() || tx_suppression_meter.is_some() {
+        for (i, container) in network.containers.iter().enumerate() {
+            message_handlers.push(tokio::spawn(ledger_monitor::monitor(
+                i as u32,
+                network.node_host.clone(),
+                container.port_ws as u16,
+                state.events.clone(),
+            )));
+        }
+    }
+    if let Some((anomaly_detector, events)) = anomaly_detector.as_ref().zip(anomaly_events) {
+        message_handlers.push(tokio::spawn(anomaly_detector.clone().watch(events)));
+    }
+    if let Some(events) = alert_events {
+        message_handlers.push(tokio::spawn(anomaly::report(client.clone(), events)));
+    }
+    if let (Some(heartbeat_config), Some(events)) =
+        (&interceptor_config.heartbeat, heartbeat_events)
+    {
+        message_handlers.push(tokio::spawn(heartbeat::run(
+            client.clone(),
+            state.clone(),
+            events,
+            Duration::from_millis(heartbeat_config.interval_ms),
+        )));
+    }
+    if let Some(container_stats_config) = &interceptor_config.container_stats {
+        message_handlers.push(tokio::spawn(container_stats::run(
+            client.clone(),
+            network.docker(),
+            network
+                .containers
+                .iter()
+                .chain(network.shadow.iter())
+                .cloned()
+                .collect(),
+            Duration::from_millis(container_stats_config.interval_ms),
+        )));
+    }
+    if let Some(hot_reload_config) = &interceptor_config.hot_reload {
+        let path = hot_reload_config
+            .file
+            .clone()
+            .unwrap_or_else(InterceptorConfig::path);
+        message_handlers.push(tokio::spawn(hot_reload::watch(
+            PathBuf::from(path),
+            ports.clone(),
+            Duration::from_millis(hot_reload_config.debounce_ms),
+            state.clone(),
+        )));
+    }
+    if let Some(flapping_config) = &interceptor_config.flapping {
+        let timing = FlapTiming::from_config(flapping_config)
+            .unwrap_or_else(|e| panic!("Invalid flapping configuration: {}", e));
+        for link in flapping_links {
+            message_handlers.push(tokio::spawn(link.run(
+                peer_connector(
+                    &network.node_host,
+                    &interceptor_config.handshake,
+                    &interceptor_config.timeouts,
+                    &interceptor_config.tls,
+                ),
+                timing,
+                Duration::from_secs(flapping_config.start_after_secs),
+                state.clone(),
+            )));
+        }
+    }
+
+    if interceptor_config.queues.gauge_interval_secs > 0 {
+        message_handlers.push(tokio::spawn(message_queue::report_gauges(
+            state.clone(),
+            Duration::from_secs(interceptor_config.queues.gauge_interval_secs),
+        )));
+    }
+    if let (Some(validator_list_config), Some(site)) = (
+        &interceptor_config.validator_list,
+        network.validator_list_site.take(),
+    ) {
+        message_handlers.push(tokio::spawn(validator_list::run_rotations(
+            site,
+            validator_list_config.rotations.clone(),
+        )));
+    }
+    message_handlers.extend(event_server);
+    message_handlers.push(tokio::spawn(eclipse::follow_controller(
+        client.clone(),
+        state.clone(),
+    )));
+    if let Some(eclipse_config) = &interceptor_config.eclipse {
+        let port_of = |id: u32| {
+            network
+                .containers
+                .get(id as usize)
+                .unwrap_or_else(|| {
+                    panic!("Invalid eclipse configuration: node {} does not exist", id)
+                })
+                .port_peer as u16
+        };
+        let eclipse = Eclipse {
+            victim_port: port_of(eclipse_config.victim),
+            visible_peers: eclipse_config
+                .visible_peers
+                .iter()
+                .map(|id| port_of(*id))
+                .collect(),
+            visible_types: eclipse_config
+                .visible_types
+                .iter()
+                .map(|name| name.parse())
+                .collect::<Result<_, _>>()
+                .unwrap_or_else(|e| panic!("Invalid eclipse configuration: {}", e)),
+        };
+        message_handlers.push(tokio::spawn(eclipse::run_scheduled(
+            eclipse,
+            Duration::from_secs(eclipse_config.start_after_secs),
+            (eclipse_config.duration_secs > 0)
+                .then(|| Duration::from_secs(eclipse_config.duration_secs)),
+            state.clone(),
+        )));
+    }
+    if let Some(throttle_config) = &interceptor_config.cpu_throttle {
+        if throttle_config.cpus <= 0.0 {
+            panic!("Invalid CPU throttle configuration: the amount of CPUs has to be positive");
+        }
+        let throttle = CpuThrottle {
+            nodes: throttle_config
+                .nodes
+                .iter()
+                .map(|id| {
+                    let container = network.containers.get(*id as usize).unwrap_or_else(|| {
+                        panic!(
+                            "Invalid CPU throttle configuration: node {} does not exist",
+                            id
+                        )
+                    });
+                    ThrottledNode {
+                        node_id: *id,
+                        container_id: container.id.clone().unwrap_or_default(),
+                        original_cpus: container.limits.cpus,
+                    }
+                })
+                .collect(),
+            cpus: throttle_config.cpus,
+        };
+        message_handlers.push(tokio::spawn(cpu_throttle::run_scheduled(
+            throttle,
+            network.docker(),
+            Duration::from_secs(throttle_config.start_after_secs),
+            (throttle_config.duration_secs > 0)
+                .then(|| Duration::from_secs(throttle_config.duration_secs)),
+            state.events.clone(),
+        )));
+    }
+    if let Some(partition_config) = &interceptor_config.one_way_partition {
+        let port_of = |id: u32| {
+            network
+                .containers
+                .get(id as usize)
+                .unwrap_or_else(|| {
+                    panic!(
+                        "Invalid one-way partition configuration: node {} does not exist",
+                        id
+                    )
+                })
+                .port_peer as u16
+        };
+        let partition = OneWayPartition {
+            cut_links: partition_config
+                .cut_links
+                .iter()
+                .map(|[from_id, to_id]| (port_of(*from_id), port_of(*to_id)))
+                .collect(),
+        };
+        message_handlers.push(tokio::spawn(partition::run_scheduled(
+            partition,
+            Duration::from_secs(partition_config.start_after_secs),
+            (partition_config.duration_secs > 0)
+                .then(|| Duration::from_secs(partition_config.duration_secs)),
+            state.clone(),
+        )));
+    }
+    if let Some(suppression_config) = &interceptor_config.tx_suppression {
+        let ports: Vec<u16> = network
+            .containers
+            .iter()
+            .map(|container| container.port_peer as u16)
+            .collect();
+        let port_of = |id: u32| {
+            *ports.get(id as usize).unwrap_or_else(|| {
+                panic!(
+                    "Invalid transaction suppression configuration: node {} does not exist",
+                    id
+                )
+            })
+        };
+        let mut links: Vec<(u16, u16)> = suppression_config
+            .links
+            .iter()
+            .map(|[from_id, to_id]| (port_of(*from_id), port_of(*to_id)))
+            .collect();
+        for node in suppression_config.nodes.iter() {
+            let node_port = port_of(*node);
+            for port in ports.iter().filter(|port| **port != node_port) {
+                links.extend([(node_port, *port), (*port, node_port)]);
             }
-            let (connection_half_1, connection_half_2) = peer_connector
-                .connect_peers(
-                    container1.port_peer as u16,
-                    container2.port_peer as u16,
-                    container1.key_data.validation_public_key.as_str(),
-                    container2.key_data.validation_public_key.as_str(),
-                    container1.key_data.validation_seed.as_str(),
-                    container2.key_data.validation_seed.as_str(),
+        }
+        links.sort();
+        links.dedup();
+        message_handlers.push(tokio::spawn(tx_suppression::run_scheduled(
+            TxSuppression { links },
+            Duration::from_secs(suppression_config.start_after_secs),
+            (suppression_config.duration_secs > 0)
+                .then(|| Duration::from_secs(suppression_config.duration_secs)),
+            state.clone(),
+        )));
+    }
+    if let Some(replay_config) = &interceptor_config.replay {
+        let port_of = |id: u32| {
+            network
+                .containers
+                .get(id as usize)
+                .unwrap_or_else(|| {
+                    panic!("Invalid replay configuration: node {} does not exist", id)
+                })
+                .port_peer as u16
+        };
+        for replay_config in replay_config.replays.iter() {
+            let request = ReplayRequest {
+                message_type: replay_config.message_type.as_ref().map(|name| {
+                    name.parse()
+                        .unwrap_or_else(|e| panic!("Invalid replay configuration: {}", e))
+                }),
+                from_port: replay_config.from_node.map(port_of),
+                to_port: replay_config.to_node.map(port_of),
+                ledgers_ago: replay_config.ledgers_ago,
+                limit: replay_config.limit,
+            };
+            message_handlers.push(tokio::spawn(replay::run_scheduled(
+                request,
+                Duration::from_secs(replay_config.after_secs),
+                state.clone(),
+            )));
+        }
+    }
+
+    if let Some(admin_config) = &interceptor_config.admin {
+        let listener = tokio::net::TcpListener::bind(&admin_config.address)
+            .await
+            .unwrap_or_else(|e| {
+                panic!(
+                    "Could not listen for admin API requests on {}: {}",
+                    admin_config.address, e
                 )
-                .await;
-            let (read_half_1, write_half_1) = tokio::io::split(connection_half_1);
-            let (read_half_2, write_half_2) = tokio::io::split(connection_half_2);
+            });
+        message_handlers.push(tokio::spawn(admin_api::serve(listener, state.clone())));
+    }
+    if let Some(nemesis_config) = &interceptor_config.nemesis {
+        let listener = tokio::net::TcpListener::bind(&nemesis_config.address)
+            .await
+            .unwrap_or_else(|e| {
+                panic!(
+                    "Could not listen for nemesis requests on {}: {}",
+                    nemesis_config.address, e
+                )
+            });
+        let nodes = network
+            .containers
+            .iter()
+            .enumerate()
+            .map(|(id, container)| NemesisNode {
+                node_id: id as u32,
+                port: container.port_peer as u16,
+                container_id: container.id.clone().unwrap_or_default(),
+                clock: network
+                    .clock_file(&container.name)
+                    .map(|clock_file| (clock_file, container.clock_skew.unwrap_or_default())),
+            })
+            .collect();
+        let nemesis = Nemesis::new(state.clone(), network.docker(), nodes);
+        message_handlers.push(tokio::spawn(nemesis::serve(listener, Arc::new(nemesis))));
+    }
+    if let Some(console_config) = &interceptor_config.console {
+        let listener = tokio::net::TcpListener::bind(&console_config.address)
+            .await
+            .unwrap_or_else(|e| {
+                panic!(
+                    "Could not listen for console sessions on {}: {}",
+                    console_config.address, e
+                )
+            });
+        let ports = network
+            .containers
+            .iter()
+            .map(|container| container.port_peer as u16)
+            .collect();
+        let console = Console::new(state.clone(), ports);
+        message_handlers.push(tokio::spawn(console::serve(listener, Arc::new(console))));
+    }
+    if let Some(grpc_server_config) = &interceptor_config.grpc_server {
+        message_handlers.push(tokio::spawn(grpc_server::serve(
+            grpc_server_address(grpc_server_config),
+            state.clone(),
+            client.clone(),
+        )));
+    }
+    if let Some(proxy_config) = &interceptor_config.websocket_proxy {
+        for (i, container) in network.containers.iter().enumerate() {
+            let proxy_port = proxy_config.base_port + i as u16;
+            let listener = tokio::net::TcpListener::bind(("127.0.0.1", proxy_port))
+                .await
+                .unwrap_or_else(|e| {
+                    panic!(
+                        "Could not listen for WebSocket clients on port {}: {}",
+                        proxy_port, e
+                    )
+                });
+            let proxy = WebSocketProxy {
+                proxy_port,
+                node_host: network.node_host.clone(),
+                node_port: container.port_ws as u16,
+                node_key: container.key_data.validation_public_key.clone(),
+                config: proxy_config.clone(),
+                client: client.clone(),
+                state: state.clone(),
+            };
+            message_handlers.push(tokio::spawn(proxy.serve(listener)));
+        }
+    }
+    if let Some(proxy_config) = &interceptor_config.rpc_proxy {
+        for (i, container) in network.containers.iter().enumerate() {
+            let proxy_port = proxy_config.base_port + i as u16;
+            let listener = tokio::net::TcpListener::bind(("127.0.0.1", proxy_port))
+                .await
+                .unwrap_or_else(|e| {
+                    panic!(
+                        "Could not listen for JSON-RPC clients on port {}: {}",
+                        proxy_port, e
+                    )
+                });
+            let proxy = RpcProxy {
+                proxy_port,
+                node_host: network.node_host.clone(),
+                node_port: container.port_rpc as u16,
+                node_key: container.key_data.validation_public_key.clone(),
+                config: proxy_config.clone(),
+                client: client.clone(),
+                state: state.clone(),
+            };
+            message_handlers.push(tokio::spawn(proxy.serve(listener)));
+        }
+    }
+    if let Some(dashboard) = dashboard {
+        message_handlers.push(tokio::task::spawn_blocking(move || dashboard.run()));
+    }
 
-            let node_1 = &mut nodes[i];
-            node_1.add_peer(Peer::new(
-                container2.port_peer as u16,
-                write_half_2,
-                read_half_1,
-            ));
-            let node_2 = &mut nodes[j];
-            node_2.add_peer(Peer::new(
-                container1.port_peer as u16,
-                write_half_1,
-                read_half_2,
-            ));
+    // Check the configured properties for as long as the network is running
+    let mut violations = None;
+    if let Some(assertion_config) = interceptor_config.assertions {
+        let nodes = rpc_clients(&network)
+            .into_iter()
+            .enumerate()
+            .map(|(i, rpc_client)| (i as u32, rpc_client))
+            .collect();
+        let assertion_engine = AssertionEngine::new(assertion_config, nodes, timeline);
+        violations = Some(assertion_engine.violations());
+        message_handlers.push(tokio::spawn(assertion_engine.run()));
+    }
+
+    // Publish the changes in the amendment voting of every node
+    if let Some(amendment_config) = &interceptor_config.amendments {
+        if amendment_config.monitor_interval_ms > 0 {
+            let nodes = rpc_clients(&network)
+                .into_iter()
+                .enumerate()
+                .map(|(i, rpc_client)| (i as u32, rpc_client))
+                .collect();
+            let monitor = AmendmentMonitor::new(
+                nodes,
+                Duration::from_millis(amendment_config.monitor_interval_ms),
+                state.events.clone(),
+            );
+            message_handlers.push(tokio::spawn(monitor.run()));
         }
     }
 
-    let mut message_handlers = Vec::new();
-    for node in nodes {
-        let (mut read_threads, write_thread) = node.handle_messages(client.clone());
-        message_handlers.push(write_thread);
-        message_handlers.append(&mut read_threads);
+    // Start submitting transactions once all links are being intercepted
+    if let Some(tx_generator_config) = interceptor_config.tx_generator {
+        let tx_generator = TxGenerator::new(tx_generator_config, rpc_clients(&network), seed);
+        message_handlers.push(tokio::spawn(tx_generator.run()));
     }
 
-    // Wait for Ctrl+C signal
-    while running.load(Ordering::SeqCst) {}
+    let stop_reason = if bench_mode {
+        let report = bench::run(
+            interceptor_config.bench,
+            &network,
+            client.clone(),
+            state.clone(),
+            running.clone(),
+        )
+        .await;
+        println!("{}", report);
+        StopReason::Interrupted
+    } else {
+        // Wait for Ctrl+C signal, or for the scenario to be complete
+        let termination = Termination::new(
+            interceptor_config.termination.clone(),
+            rpc_clients(&network),
+            start_ledger,
+            violations.clone(),
+            cool_down_ended,
+        );
+        termination.wait(running.clone(), client.clone()).await
+    };
+    info!("Stopping the run: {}", stop_reason);
 
+    // Stops the dashboard, which restores the terminal
+    running.store(false, Ordering::SeqCst);
+    if let Some(phases) = phases {
+        phases.abort();
+    }
+    // Every handler runs until it is aborted, so a handler that already finished has failed
+    let mut infrastructure_failures = Vec::new();
     for message_handler in message_handlers {
-        message_handler.abort();
+        if !message_handler.is_finished() {
+            message_handler.abort();
+            continue;
+        }
+        if let Err(e) = message_handler.await {
+            infrastructure_failures.push(task_failure(e));
+        }
+    }
+    // Let the sinks write the remaining records
+    state.close_sinks();
+    state.close_capture_file();
+    for sink_thread in sink_threads {
+        let _ = sink_thread.await;
+    }
+
+    let end_ledger = run_summary::validated_ledger(&rpc_clients(&network)).await;
+    let ledgers_closed = start_ledger
+        .zip(end_ledger)
+        .map(|(start, end)| end.saturating_sub(start));
+    let mut summary = state
+        .statistics
+        .summarize(started_at, Utc::now(), ledgers_closed);
+    summary.stop_reason = Some(stop_reason.to_string());
+    report_run(
+        &summary,
+        &interceptor_config.summary,
+        client.clone(),
+        &run_id,
+    )
+    .await;
+    if let Some(directory) = &interceptor_config.summary.directory {
+        match state.statistics.round_timeline().save(directory, &run_id) {
+            Ok(path) => info!("Wrote the round timeline to {}", path.display()),
+            Err(e) => warn!("Could not write the round timeline: {}", e),
+        }
+    }
+    if let Some(observer) = state.passive() {
+        let added_latency = observer.added_latency();
+        info!(
+            "Added latency of {} observed messages: mean {} µs, max {} µs, {} above the budget of {} µs",
+            added_latency.messages,
+            added_latency.mean_us,
+            added_latency.max_us,
+            added_latency.over_budget,
+            added_latency.budget_us
+        );
+    }
+    if let Some((broadcasts, broadcast_config)) = state
+        .broadcasts()
+        .zip(interceptor_config.broadcasts.as_ref())
+    {
+        match broadcasts
+            .report()
+            .save(&broadcast_config.directory, &run_id)
+        {
+            Ok(path) => info!("Wrote the broadcast report to {}", path.display()),
+            Err(e) => warn!("Could not write the broadcast report: {}", e),
+        }
+    }
+    if let Some(close_timeline) = &close_timeline {
+        match close_timeline.save(&run_id) {
+            Ok(path) => info!("Wrote the close timeline to {}", path.display()),
+            Err(e) => warn!("Could not write the close timeline: {}", e),
+        }
+    }
+    if let Some((meter, suppression_config)) = tx_suppression_meter
+        .as_ref()
+        .zip(interceptor_config.tx_suppression.as_ref())
+    {
+        match meter.save(suppression_config, &run_id) {
+            Ok(path) => info!(
+                "Wrote the transaction suppression report to {}",
+                path.display()
+            ),
+            Err(e) => warn!("Could not write the transaction suppression report: {}", e),
+        }
+    }
+
+    infrastructure_failures.extend(
+        summary
+            .errors
+            .iter()
+            .map(|(kind, amount)| format!("{} {} error(s) while handling messages", amount, kind)),
+    );
+    let ci_report = CiReport {
+        scenario: interceptor_config.ci.scenario.clone(),
+        duration: (Utc::now() - started_at).to_std().unwrap_or_default(),
+        properties_checked: violations.is_some(),
+        violations: violations
+            .map(|violations| violations.lock().unwrap().clone())
+            .unwrap_or_default(),
+        infrastructure_failures,
+    };
+    if let Some(junit_path) = &interceptor_config.ci.junit_path {
+        match ci_report.save(junit_path) {
+            Ok(()) => info!("Wrote the JUnit report to {}", junit_path),
+            Err(e) => warn!("Could not write the JUnit report: {}", e),
+        }
+    }
+
+    let outcome = ci_report.outcome();
+    info!("Run outcome: {:?}", outcome);
+    // The containers are removed when they are stopped, so their logs are collected first
+    if outcome != RunOutcome::Clean && interceptor_config.crash_bundle.enabled {
+        let mut reason = format!("Run outcome: {:?}", outcome);
+        for failure in ci_report.infrastructure_failures.iter() {
+            reason.push_str(&format!("\n{}", failure));
+        }
+        for violation in ci_report.violations.iter() {
+            reason.push_str(&format!(
+                "\nProperty '{}' violated: {}",
+                violation.property, violation.description
+            ));
+        }
+        let mut bundle = crash_bundle(&reason, interceptor_config.logging.file.as_deref());
+        bundle.add("network_config.txt", format!("{:#?}", network_config));
+        bundle.add(
+            "summary.json",
+            serde_json::to_vec_pretty(&summary).unwrap_or_default(),
+        );
+        bundle.add(
+            "recent_packets.txt",
+            state
+                .timeline
+                .latest(interceptor_config.crash_bundle.recent_packets)
+                .iter()
+                .map(|record| format!("{}\n", record))
+                .collect::<String>(),
+        );
+        for (name, logs) in network
+            .container_logs(interceptor_config.crash_bundle.container_log_lines)
+            .await
+        {
+            bundle.add(&format!("containers/{}.log", name), logs);
+        }
+        write_crash_bundle(&bundle, &interceptor_config.crash_bundle.directory, &run_id);
     }
 
     network.stop_network().await;
-    Ok(())
+    outcome
 }
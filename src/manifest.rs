@@ -0,0 +1,596 @@
+//! This module is responsible for the manifests of validators, which delegate from the master key of a validator to the
+//! key it signs with, see `Manifest` in rippled. The manifests in mtMANIFESTS messages are decoded, and the latest
+//! manifest of every master key is tracked, such that the signing keys of the nodes are known.
+//!
+//! The manifests of a node can be rewritten on selected links: suppressed, or replaced by a rotation to a new signing key
+//! or by a revocation, both signed with the master key of the node that the interceptor holds. Since nodes configured
+//! with a validation seed never send a manifest, such manifests can be announced on the links of a node as well.
+
+use crate::config::ManifestRuleConfig;
+use crate::field_mutation;
+use crate::keygen::{self, KeyType, NodeKeys, Seed, ED25519_PREFIX};
+use crate::message_decoder::{
+    self, ObjectField, SF_MASTER_SIGNATURE, SF_PUBLIC_KEY, SF_SEQUENCE, SF_SIGNATURE,
+    SF_SIGNING_PUB_KEY, ST_BLOB, ST_UINT32,
+};
+use crate::message_type::MessageType;
+use crate::signature;
+use crate::validator_list::{
+    push_blob, FIELD_MASTER_SIGNATURE, FIELD_PUBLIC_KEY, FIELD_SEQUENCE, FIELD_SIGNATURE,
+    FIELD_SIGNING_PUBLIC_KEY, MANIFEST_HASH_PREFIX,
+};
+use crate::wire_format::{decode_fields, encode_fields, WireField, WireValue};
+use bytes::Bytes;
+use secp256k1::SecretKey;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fmt;
+
+/// The sequence of a manifest that revokes its master key, see `Manifest::revoked` in rippled.
+pub const REVOKED_SEQUENCE: u32 = u32::MAX;
+/// The field number of the manifests in `TMManifests`.
+const MANIFESTS_FIELD_LIST: u64 = 1;
+/// The field number of the serialized manifest in `TMManifest`.
+const MANIFEST_FIELD_STOBJECT: u64 = 1;
+
+/// Struct that represents a decoded manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Manifest {
+    /// The sequence, which increases with every new signing key.
+    pub sequence: u32,
+    /// The master key of the validator.
+    #[serde(serialize_with = "serialize_key")]
+    pub master_key: Vec<u8>,
+    /// The key the validator signs with, empty for a revocation.
+    #[serde(serialize_with = "serialize_key")]
+    pub signing_key: Vec<u8>,
+}
+
+impl Manifest {
+    /// Decodes a serialized manifest. Returns None if it misses its sequence or master key.
+    ///
+    /// # Parameters
+    /// * 'stobject' - the serialized manifest.
+    pub fn decode(stobject: &[u8]) -> Option<Self> {
+        let fields: Vec<ObjectField> = message_decoder::object_fields(stobject).collect();
+        let field =
+            |type_code, field_code| message_decoder::object_field(&fields, type_code, field_code);
+        Some(Manifest {
+            sequence: u32::from_be_bytes(field(ST_UINT32, SF_SEQUENCE)?.try_into().ok()?),
+            master_key: field(ST_BLOB, SF_PUBLIC_KEY)?.to_vec(),
+            signing_key: field(ST_BLOB, SF_SIGNING_PUB_KEY)
+                .unwrap_or_default()
+                .to_vec(),
+        })
+    }
+
+    /// Returns whether the manifest revokes its master key.
+    pub fn revoked(&self) -> bool {
+        self.sequence == REVOKED_SEQUENCE
+    }
+
+    /// Returns whether a serialized manifest is signed by its master key, and by its signing key unless it is a
+    /// revocation, see `Manifest::verify` in rippled.
+    ///
+    /// # Parameters
+    /// * 'stobject' - the serialized manifest this manifest was decoded from.
+    pub fn verify(&self, stobject: &[u8]) -> bool {
+        let fields: Vec<ObjectField> = message_decoder::object_fields(stobject).collect();
+        // Both signatures sign the manifest without its signatures
+        let without_signature = message_decoder::without_field(stobject, ST_BLOB, SF_SIGNATURE)
+            .unwrap_or_else(|| stobject.to_vec());
+        let Some(unsigned) =
+            message_decoder::without_field(&without_signature, ST_BLOB, SF_MASTER_SIGNATURE)
+        else {
+            return false;
+        };
+        let data = [MANIFEST_HASH_PREFIX.as_slice(), &unsigned].concat();
+        let signed_by = |key: &[u8], field_code| {
+            message_decoder::object_field(&fields, ST_BLOB, field_code)
+                .is_some_and(|signature| signature::verify_data(key, &data, signature))
+        };
+        (self.revoked() || signed_by(&self.signing_key, SF_SIGNATURE))
+            && signed_by(&self.master_key, SF_MASTER_SIGNATURE)
+    }
+
+    /// Serializes the manifest, signed with the master key, and with the signing key unless it is a revocation.
+    ///
+    /// # Parameters
+    /// * 'master_secret_key' - the secret key of the master key.
+    /// * 'signing_keys' - the keys of the signing key, None for a revocation.
+    pub fn sign(&self, master_secret_key: &[u8; 32], signing_keys: Option<&NodeKeys>) -> Vec<u8> {
+        let mut stobject = FIELD_SEQUENCE.to_vec();
+        stobject.extend(self.sequence.to_be_bytes());
+        push_blob(&mut stobject, &FIELD_PUBLIC_KEY, &self.master_key);
+        if !self.signing_key.is_empty() {
+            push_blob(&mut stobject, &FIELD_SIGNING_PUBLIC_KEY, &self.signing_key);
+        }
+        let data = [MANIFEST_HASH_PREFIX.as_slice(), &stobject].concat();
+        if let Some(signing_keys) = signing_keys {
+            push_blob(&mut stobject, &FIELD_SIGNATURE, &signing_keys.sign(&data));
+        }
+        let master_key_type = match self.master_key.first() {
+            Some(&ED25519_PREFIX) => KeyType::Ed25519,
+            _ => KeyType::Secp256k1,
+        };
+        push_blob(
+            &mut stobject,
+            &FIELD_MASTER_SIGNATURE,
+            &keygen::sign(master_key_type, master_secret_key, &data),
+        );
+        stobject
+    }
+
+    /// Returns the manifest that replaces this one by a rewriting action, and its serialization: a rotation to a new
+    /// signing key with the next sequence, or a revocation. The new signing key is derived from the master key and the
+    /// sequence, such that a run rotates to the same keys every time. Returns None for the suppress action, and for a
+    /// manifest that can not be followed because it is revoked.
+    ///
+    /// # Parameters
+    /// * 'action' - the rewriting action.
+    /// * 'master_secret_key' - the secret key of the master key, which is a secp256k1 key as the keys of the nodes are.
+    pub fn successor(
+        &self,
+        action: ManifestAction,
+        master_secret_key: &SecretKey,
+    ) -> Option<(Manifest, Vec<u8>)> {
+        if self.revoked() {
+            return None;
+        }
+        let master_secret_key = master_secret_key.secret_bytes();
+        match action {
+            ManifestAction::Suppress => None,
+            ManifestAction::Rotate => {
+                let sequence = self
+                    .sequence
+                    .checked_add(1)
+                    .filter(|s| *s != REVOKED_SEQUENCE)?;
+                let seed = Seed::from_passphrase(&format!(
+                    "{}:{}",
+                    hex::encode(master_secret_key),
+                    sequence
+                ));
+                let signing_keys = NodeKeys::derive(seed, KeyType::Secp256k1);
+                let manifest = Manifest {
+                    sequence,
+                    master_key: self.master_key.clone(),
+                    signing_key: signing_keys.public_key.clone(),
+                };
+                let stobject = manifest.sign(&master_secret_key, Some(&signing_keys));
+                Some((manifest, stobject))
+            }
+            ManifestAction::Revoke => {
+                let manifest = Manifest {
+                    sequence: REVOKED_SEQUENCE,
+                    master_key: self.master_key.clone(),
+                    signing_key: Vec::new(),
+                };
+                let stobject = manifest.sign(&master_secret_key, None);
+                Some((manifest, stobject))
+            }
+        }
+    }
+}
+
+/// Serializes a key in hex, empty if there is no key.
+///
+/// # Parameters
+/// * 'key' - the key.
+/// * 'serializer' - the serializer.
+fn serialize_key<S: Serializer>(key: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&hex::encode_upper(key))
+}
+
+/// Enum that represents how the manifests of a node are rewritten.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ManifestAction {
+    /// The manifests are removed, and a message without manifests left is dropped.
+    Suppress,
+    /// The manifests are replaced by a manifest that rotates to a new signing key.
+    Rotate,
+    /// The manifests are replaced by a manifest that revokes the master key.
+    Revoke,
+}
+
+/// Struct that represents a rule by which the manifests of a node are rewritten on the matching links.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestRule {
+    /// The port of the peer the rewritten messages come from, all peers if not set.
+    #[serde(default)]
+    pub from_port: Option<u16>,
+    /// The port of the peer the rewritten messages go to, all peers if not set.
+    #[serde(default)]
+    pub to_port: Option<u16>,
+    /// The port of the node whose manifests are rewritten, the manifests of all master keys if not set. Only manifests
+    /// of nodes can be rotated or revoked, since their master keys are held by the interceptor.
+    #[serde(default)]
+    pub node_port: Option<u16>,
+    /// How the manifests are rewritten.
+    pub action: ManifestAction,
+}
+
+impl ManifestRule {
+    /// Creates the rule from its configuration. Returns an error if a node does not exist.
+    ///
+    /// # Parameters
+    /// * 'config' - the configuration of the rule.
+    /// * 'ports' - the peer ports of the nodes, by node ID.
+    pub fn from_config(config: &ManifestRuleConfig, ports: &[u16]) -> Result<Self, ManifestError> {
+        let port_of = |id: u32| {
+            ports
+                .get(id as usize)
+                .copied()
+                .ok_or(ManifestError::UnknownNode(id))
+        };
+        Ok(Self {
+            from_port: config.from_node.map(port_of).transpose()?,
+            to_port: config.to_node.map(port_of).transpose()?,
+            node_port: config.node.map(port_of).transpose()?,
+            action: config.action,
+        })
+    }
+
+    /// Returns whether the rule rewrites the manifests on a link.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer where the message came from.
+    /// * 'to_port' - the port of the peer the message is sent to.
+    pub fn matches(&self, from_port: u16, to_port: u16) -> bool {
+        self.from_port.map_or(true, |port| port == from_port)
+            && self.to_port.map_or(true, |port| port == to_port)
+    }
+}
+
+/// Enum that represents an error of decoding or rewriting manifests.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ManifestError {
+    /// The message is not an mtMANIFESTS message, or a manifest can not be decoded.
+    Malformed,
+    /// A node ID in the configuration does not exist.
+    UnknownNode(u32),
+    /// The manifest of a master key can not be signed, because its secret key is not held, with the key in hex.
+    NoMasterKey(String),
+    /// The key of the node with the port is not held.
+    NoNodeKey(u16),
+    /// The action can not be announced, because it is a suppression or the master key is revoked.
+    NoSuccessor(ManifestAction),
+    /// The node with the port has no link to announce on, to the peer with the port if set.
+    NoLink(u16, Option<u16>),
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestError::Malformed => write!(f, "malformed manifests"),
+            ManifestError::UnknownNode(id) => write!(f, "node {} does not exist", id),
+            ManifestError::NoMasterKey(key) => {
+                write!(f, "the secret key of master key {} is not held", key)
+            }
+            ManifestError::NoNodeKey(port) => write!(f, "the key of node {} is not held", port),
+            ManifestError::NoSuccessor(action) => {
+                write!(f, "a {:?} can not be announced for this master key", action)
+            }
+            ManifestError::NoLink(port, None) => write!(f, "node {} has no links", port),
+            ManifestError::NoLink(port, Some(to_port)) => {
+                write!(f, "there is no link from {} to {}", port, to_port)
+            }
+        }
+    }
+}
+
+impl Error for ManifestError {}
+
+/// Struct that represents the latest manifest of every master key that was seen.
+#[derive(Debug, Default)]
+pub struct ManifestTracker {
+    /// The latest manifest, by master key.
+    latest: BTreeMap<Vec<u8>, Manifest>,
+}
+
+impl ManifestTracker {
+    /// Tracks a manifest if it has a higher sequence than the tracked manifest of its master key, and returns whether
+    /// it has, like rippled only accepts newer manifests.
+    ///
+    /// # Parameters
+    /// * 'manifest' - the manifest, whose signatures were verified.
+    pub fn track(&mut self, manifest: Manifest) -> bool {
+        if self
+            .latest
+            .get(&manifest.master_key)
+            .is_some_and(|latest| latest.sequence >= manifest.sequence)
+        {
+            return false;
+        }
+        self.latest.insert(manifest.master_key.clone(), manifest);
+        true
+    }
+
+    /// Returns the latest manifest of a master key, if one was seen.
+    ///
+    /// # Parameters
+    /// * 'master_key' - the master key.
+    pub fn latest(&self, master_key: &[u8]) -> Option<&Manifest> {
+        self.latest.get(master_key)
+    }
+
+    /// Returns the latest manifests, ordered by master key.
+    pub fn manifests(&self) -> Vec<Manifest> {
+        self.latest.values().cloned().collect()
+    }
+
+    /// Returns the signing keys that some master keys currently delegate to, leaving out revoked master keys.
+    ///
+    /// # Parameters
+    /// * 'master_keys' - the master keys.
+    pub fn signing_keys(&self, master_keys: &HashSet<Vec<u8>>) -> Vec<Vec<u8>> {
+        self.latest
+            .values()
+            .filter(|manifest| !manifest.revoked() && master_keys.contains(&manifest.master_key))
+            .map(|manifest| manifest.signing_key.clone())
+            .collect()
+    }
+}
+
+/// Returns the serialized manifests of an mtMANIFESTS message, or None if it is not one or can not be decoded.
+///
+/// # Parameters
+/// * 'message' - the message including its 6 byte header.
+pub fn decode_message(message: &[u8]) -> Option<Vec<Vec<u8>>> {
+    if MessageType::from_message(message)? != MessageType::Manifests {
+        return None;
+    }
+    let payload_size = u32::from_be_bytes(message[0..4].try_into().unwrap()) as usize;
+    decode_fields(message.get(6..6 + payload_size)?)?
+        .into_iter()
+        .filter(|field| field.number == MANIFESTS_FIELD_LIST)
+        .map(|field| match field.value {
+            WireValue::LengthDelimited(manifest) => stobject(&decode_fields(&manifest)?),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Frames serialized manifests as an mtMANIFESTS message.
+///
+/// # Parameters
+/// * 'stobjects' - the serialized manifests.
+pub fn encode_message(stobjects: &[Vec<u8>]) -> Bytes {
+    let fields: Vec<WireField> = stobjects
+        .iter()
+        .map(|stobject| manifest_field(stobject.clone()))
+        .collect();
+    field_mutation::frame(MessageType::Manifests, &encode_fields(&fields))
+}
+
+/// Rewrites the manifests of an mtMANIFESTS message: every manifest is rewritten by the first target that matches its
+/// master key. The other fields of the message are kept. Returns the rewritten message, or None if all manifests were
+/// suppressed.
+///
+/// # Parameters
+/// * 'message' - the message including its 6 byte header.
+/// * 'targets' - the master keys whose manifests are rewritten, all master keys if None, and how.
+/// * 'master_secret_key' - returns the secret key of a master key, if it is held.
+pub fn rewrite(
+    message: &[u8],
+    targets: &[(Option<Vec<u8>>, ManifestAction)],
+    master_secret_key: impl Fn(&[u8]) -> Option<SecretKey>,
+) -> Result<Option<Bytes>, ManifestError> {
+    if MessageType::from_message(message) != Some(MessageType::Manifests) {
+        return Err(ManifestError::Malformed);
+    }
+    let payload_size = u32::from_be_bytes(message[0..4].try_into().unwrap()) as usize;
+    let fields = message
+        .get(6..6 + payload_size)
+        .and_then(decode_fields)
+        .ok_or(ManifestError::Malformed)?;
+    let mut rewritten = Vec::new();
+    let mut manifests = 0;
+    for field in fields {
+        let contents = match &field.value {
+            WireValue::LengthDelimited(contents) if field.number == MANIFESTS_FIELD_LIST => {
+                contents
+            }
+            _ => {
+                rewritten.push(field);
+                continue;
+            }
+        };
+        let stobject = decode_fields(contents)
+            .and_then(|fields| stobject(&fields))
+            .ok_or(ManifestError::Malformed)?;
+        let manifest = Manifest::decode(&stobject).ok_or(ManifestError::Malformed)?;
+        let action = targets
+            .iter()
+            .find(|(master_key, _)| {
+                master_key
+                    .as_ref()
+                    .map_or(true, |key| *key == manifest.master_key)
+            })
+            .map(|(_, action)| *action);
+        let stobject = match action {
+            None => stobject,
+            Some(ManifestAction::Suppress) => continue,
+            Some(action) => {
+                let secret_key = master_secret_key(&manifest.master_key).ok_or_else(|| {
+                    ManifestError::NoMasterKey(hex::encode_upper(&manifest.master_key))
+                })?;
+                match manifest.successor(action, &secret_key) {
+                    Some((_, successor)) => successor,
+                    // A revoked manifest stays revoked
+                    None => stobject,
+                }
+            }
+        };
+        rewritten.push(manifest_field(stobject));
+        manifests += 1;
+    }
+    if manifests == 0 {
+        return Ok(None);
+    }
+    Ok(Some(field_mutation::frame(
+        MessageType::Manifests,
+        &encode_fields(&rewritten),
+    )))
+}
+
+/// Returns the serialized manifest of the fields of a `TMManifest`.
+///
+/// # Parameters
+/// * 'fields' - the fields of the `TMManifest`.
+fn stobject(fields: &[WireField]) -> Option<Vec<u8>> {
+    fields.iter().find_map(|field| match &field.value {
+        WireValue::LengthDelimited(stobject) if field.number == MANIFEST_FIELD_STOBJECT => {
+            Some(stobject.clone())
+        }
+        _ => None,
+    })
+}
+
+/// Returns the field of a `TMManifests` that contains a serialized manifest.
+///
+/// # Parameters
+/// * 'stobject' - the serialized manifest.
+fn manifest_field(stobject: Vec<u8>) -> WireField {
+    let manifest = WireField {
+        number: MANIFEST_FIELD_STOBJECT,
+        value: WireValue::LengthDelimited(stobject),
+    };
+    WireField {
+        number: MANIFESTS_FIELD_LIST,
+        value: WireValue::LengthDelimited(encode_fields(&[manifest])),
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::keygen::{KeyType, NodeKeys, Seed};
+    use crate::manifest::{
+        decode_message, encode_message, rewrite, Manifest, ManifestAction, ManifestError,
+        ManifestTracker, REVOKED_SEQUENCE,
+    };
+    use std::collections::HashSet;
+
+    fn node(name: &str) -> NodeKeys {
+        NodeKeys::derive(Seed::from_passphrase(name), KeyType::Secp256k1)
+    }
+
+    fn manifest(master: &NodeKeys, sequence: u32, ephemeral: &NodeKeys) -> Vec<u8> {
+        Manifest {
+            sequence,
+            master_key: master.public_key.clone(),
+            signing_key: ephemeral.public_key.clone(),
+        }
+        .sign(&master.secret_key, Some(ephemeral))
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn manifests_are_decoded_and_verified() {
+        let master = node("master");
+        let ephemeral = NodeKeys::derive(Seed::from_passphrase("ephemeral"), KeyType::Ed25519);
+        let stobject = manifest(&master, 3, &ephemeral);
+        let message = encode_message(&[stobject.clone()]);
+        assert_eq!(decode_message(&message), Some(vec![stobject.clone()]));
+
+        let manifest = Manifest::decode(&stobject).unwrap();
+        assert_eq!(manifest.sequence, 3);
+        assert_eq!(manifest.master_key, master.public_key);
+        assert_eq!(manifest.signing_key, ephemeral.public_key);
+        assert!(manifest.verify(&stobject));
+        // A manifest that claims the master key of another validator
+        let other = Manifest {
+            master_key: node("other").public_key,
+            ..manifest.clone()
+        };
+        assert!(!other.verify(&other.sign(&master.secret_key, Some(&ephemeral))));
+
+        let (revocation, stobject) = manifest
+            .successor(
+                ManifestAction::Revoke,
+                &master.secp256k1_secret_key().unwrap(),
+            )
+            .unwrap();
+        assert!(revocation.revoked());
+        assert!(revocation.signing_key.is_empty());
+        assert_eq!(Manifest::decode(&stobject), Some(revocation.clone()));
+        assert!(revocation.verify(&stobject));
+        assert_eq!(
+            revocation.successor(
+                ManifestAction::Rotate,
+                &master.secp256k1_secret_key().unwrap()
+            ),
+            None
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn manifests_are_rewritten_per_master_key() {
+        let (first, second) = (node("first"), node("second"));
+        let message = encode_message(&[
+            manifest(&first, 1, &node("first ephemeral")),
+            manifest(&second, 1, &node("second ephemeral")),
+        ]);
+        let secret_key = |key: &[u8]| {
+            (key == first.public_key.as_slice()).then(|| first.secp256k1_secret_key().unwrap())
+        };
+
+        let rotated = rewrite(
+            &message,
+            &[(Some(first.public_key.clone()), ManifestAction::Rotate)],
+            secret_key,
+        )
+        .unwrap()
+        .unwrap();
+        let stobjects = decode_message(&rotated).unwrap();
+        let rotation = Manifest::decode(&stobjects[0]).unwrap();
+        assert_eq!(rotation.sequence, 2);
+        assert_ne!(rotation.signing_key, node("first ephemeral").public_key);
+        assert!(rotation.verify(&stobjects[0]));
+        // The manifest of the other master key is kept as-is
+        assert_eq!(stobjects[1], decode_message(&message).unwrap()[1]);
+
+        let suppressed = rewrite(
+            &message,
+            &[(Some(second.public_key.clone()), ManifestAction::Suppress)],
+            secret_key,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(decode_message(&suppressed).unwrap().len(), 1);
+        assert_eq!(
+            rewrite(&message, &[(None, ManifestAction::Suppress)], secret_key),
+            Ok(None)
+        );
+        assert_eq!(
+            rewrite(&message, &[(None, ManifestAction::Revoke)], secret_key),
+            Err(ManifestError::NoMasterKey(hex::encode_upper(
+                &second.public_key
+            )))
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn only_newer_manifests_are_tracked() {
+        let master = node("master");
+        let mut tracker = ManifestTracker::default();
+        let manifest = |sequence, signing_key: &[u8]| Manifest {
+            sequence,
+            master_key: master.public_key.clone(),
+            signing_key: signing_key.to_vec(),
+        };
+        assert!(tracker.track(manifest(2, &[2; 33])));
+        assert!(!tracker.track(manifest(1, &[1; 33])));
+        assert!(!tracker.track(manifest(2, &[3; 33])));
+        let masters = HashSet::from([master.public_key.clone()]);
+        assert_eq!(tracker.signing_keys(&masters), vec![vec![2; 33]]);
+        assert_eq!(tracker.signing_keys(&HashSet::new()), Vec::<Vec<u8>>::new());
+
+        assert!(tracker.track(manifest(REVOKED_SEQUENCE, &[])));
+        assert!(tracker.latest(&master.public_key).unwrap().revoked());
+        assert!(tracker.signing_keys(&masters).is_empty());
+    }
+}
@@ -0,0 +1,645 @@
+//! This module is responsible for decoding messages of the peer protocol into a human-readable form: the key fields of
+//! their payload, the key they were signed with and the ledger sequence they refer to, such that captured messages can
+//! be read without decoding hex dumps by hand.
+//!
+//! Fields are named as in rippled's `ripple.proto`, and the fields of serialized objects as in the XRPL binary format.
+//! Hashes are shortened to their first 8 bytes, keys and accounts are printed whole such that they can be looked up.
+//! The type and field codes of the XRPL binary format are declared here for all modules that read serialized objects.
+
+use crate::message_type::MessageType;
+use crate::wire_format;
+use crate::wire_format::{decode_fields, WireField, WireValue};
+use std::fmt;
+
+/// The amount of leading bytes of a hash that is printed.
+const SHORT_HASH_LENGTH: usize = 8;
+/// The flag of a full validation in `STValidation`, `vfFullValidation` in rippled.
+const VALIDATION_FLAG_FULL: u32 = 0x0000_0001;
+/// The type code of UInt16 fields in the XRPL binary format.
+pub const ST_UINT16: u8 = 1;
+/// The type code of UInt32 fields in the XRPL binary format.
+pub const ST_UINT32: u8 = 2;
+/// The type code of Hash256 fields in the XRPL binary format.
+pub const ST_HASH256: u8 = 5;
+/// The type code of Amount fields in the XRPL binary format.
+pub const ST_AMOUNT: u8 = 6;
+/// The type code of Blob fields in the XRPL binary format.
+pub const ST_BLOB: u8 = 7;
+/// The type code of AccountID fields in the XRPL binary format.
+pub const ST_ACCOUNT: u8 = 8;
+/// The field code of sfTransactionType, which is a UInt16 field.
+pub const SF_TRANSACTION_TYPE: u8 = 2;
+/// The field code of sfFlags, which is a UInt32 field.
+pub const SF_FLAGS: u8 = 2;
+/// The field code of sfSequence, which is a UInt32 field.
+pub const SF_SEQUENCE: u8 = 4;
+/// The field code of sfLedgerSequence, which is a UInt32 field.
+pub const SF_LEDGER_SEQUENCE: u8 = 6;
+/// The field code of sfSigningTime, which is a UInt32 field.
+pub const SF_SIGNING_TIME: u8 = 9;
+/// The field code of sfLedgerHash, which is a Hash256 field.
+pub const SF_LEDGER_HASH: u8 = 1;
+/// The field code of sfFee, which is an Amount field.
+pub const SF_FEE: u8 = 8;
+/// The field code of sfPublicKey, which is a Blob field.
+pub const SF_PUBLIC_KEY: u8 = 1;
+/// The field code of sfSigningPubKey, which is a Blob field.
+pub const SF_SIGNING_PUB_KEY: u8 = 3;
+/// The field code of sfSignature, which is a Blob field.
+pub const SF_SIGNATURE: u8 = 6;
+/// The field code of sfMasterSignature, which is a Blob field.
+pub const SF_MASTER_SIGNATURE: u8 = 18;
+/// The field code of sfAccount, which is an AccountID field.
+pub const SF_ACCOUNT: u8 = 1;
+
+/// Struct that represents a decoded message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedMessage {
+    /// The type of the message.
+    pub message_type: MessageType,
+    /// The ledger sequence contained in the message, for the message types that contain one.
+    pub ledger_sequence: Option<u32>,
+    /// The public key the message was signed with, for the message types that are signed.
+    pub signer: Option<Vec<u8>>,
+    /// The key fields of the message, by name, in the order they are printed.
+    pub fields: Vec<(&'static str, String)>,
+}
+
+impl DecodedMessage {
+    /// Adds a field to the decoded message, if the message contains it.
+    ///
+    /// # Parameters
+    /// * 'name' - the name of the field.
+    /// * 'value' - the value of the field as it is printed, None if the message does not contain it.
+    fn push(&mut self, name: &'static str, value: Option<String>) {
+        if let Some(value) = value {
+            self.fields.push((name, value));
+        }
+    }
+}
+
+impl fmt::Display for DecodedMessage {
+    /// Formats the key fields as 'name=value', followed by the key the message was signed with.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut fields: Vec<String> = self
+            .fields
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        if let Some(signer) = &self.signer {
+            fields.push(format!("signer={}", hex::encode_upper(signer)));
+        }
+        write!(f, "{}", fields.join(" "))
+    }
+}
+
+/// Struct that represents the fields of a protobuf payload.
+struct Payload(Vec<WireField>);
+
+impl Payload {
+    /// Returns the value of the first varint field with the given number.
+    ///
+    /// # Parameters
+    /// * 'number' - the field number.
+    fn varint(&self, number: u64) -> Option<u64> {
+        self.0.iter().find_map(|field| match field.value {
+            WireValue::Varint(value) if field.number == number => Some(value),
+            _ => None,
+        })
+    }
+
+    /// Returns the contents of the first length-delimited field with the given number.
+    ///
+    /// # Parameters
+    /// * 'number' - the field number.
+    fn bytes(&self, number: u64) -> Option<&[u8]> {
+        self.0.iter().find_map(|field| match &field.value {
+            WireValue::LengthDelimited(value) if field.number == number => Some(value.as_slice()),
+            _ => None,
+        })
+    }
+
+    /// Returns how many times a repeated field occurs.
+    ///
+    /// # Parameters
+    /// * 'number' - the field number.
+    fn count(&self, number: u64) -> usize {
+        self.0.iter().filter(|field| field.number == number).count()
+    }
+}
+
+/// Decodes a message. Returns None if its payload is not a valid protobuf message.
+///
+/// # Parameters
+/// * 'message' - the message including its 6 byte header.
+pub fn decode(message: &[u8]) -> Option<DecodedMessage> {
+    let message_type = MessageType::from_message(message)?;
+    let payload_size = u32::from_be_bytes(message[0..4].try_into().unwrap()) as usize;
+    let payload = Payload(decode_fields(message.get(6..6 + payload_size)?)?);
+    let mut decoded = DecodedMessage {
+        message_type,
+        ledger_sequence: wire_format::ledger_sequence(message),
+        signer: None,
+        fields: Vec::new(),
+    };
+    let number = |value: Option<u64>| value.map(|value| value.to_string());
+    let hash = |value: Option<&[u8]>| value.map(short_hash);
+    match message_type {
+        MessageType::Manifests => {
+            let manifests: Vec<Vec<u8>> = payload
+                .0
+                .iter()
+                .filter_map(|field| match &field.value {
+                    WireValue::LengthDelimited(manifest) if field.number == 1 => {
+                        Payload(decode_fields(manifest)?)
+                            .bytes(1)
+                            .map(<[u8]>::to_vec)
+                    }
+                    _ => None,
+                })
+                .collect();
+            decoded.push("manifests", Some(manifests.len().to_string()));
+            // A single manifest is announced when a validator rotates its key, which is worth telling apart
+            if let [manifest] = manifests.as_slice() {
+                let manifest: Vec<ObjectField> = object_fields(manifest).collect();
+                decoded.push(
+                    "sequence",
+                    uint32(object_field(&manifest, ST_UINT32, SF_SEQUENCE)).map(|s| s.to_string()),
+                );
+                decoded.push(
+                    "signingKey",
+                    object_field(&manifest, ST_BLOB, SF_SIGNING_PUB_KEY).map(hex::encode_upper),
+                );
+                decoded.signer =
+                    object_field(&manifest, ST_BLOB, SF_PUBLIC_KEY).map(<[u8]>::to_vec);
+            }
+        }
+        MessageType::Ping => {
+            decoded.push(
+                "type",
+                name(payload.varint(1), &[(0, "ptPING"), (1, "ptPONG")]),
+            );
+            decoded.push("seq", number(payload.varint(2)));
+        }
+        MessageType::Transaction => {
+            let transaction: Vec<ObjectField> = payload
+                .bytes(1)
+                .map(object_fields)
+                .into_iter()
+                .flatten()
+                .collect();
+            decoded.push(
+                "transactionType",
+                object_field(&transaction, ST_UINT16, SF_TRANSACTION_TYPE)
+                    .and_then(|value| Some(u16::from_be_bytes(value.try_into().ok()?)))
+                    .map(transaction_type),
+            );
+            decoded.push(
+                "account",
+                object_field(&transaction, ST_ACCOUNT, SF_ACCOUNT).map(hex::encode_upper),
+            );
+            decoded.push(
+                "sequence",
+                uint32(object_field(&transaction, ST_UINT32, SF_SEQUENCE)).map(|s| s.to_string()),
+            );
+            decoded.push(
+                "fee",
+                object_field(&transaction, ST_AMOUNT, SF_FEE).and_then(drops),
+            );
+            decoded.push(
+                "status",
+                name(
+                    payload.varint(2),
+                    &[
+                        (1, "tsNEW"),
+                        (2, "tsCURRENT"),
+                        (3, "tsCOMMITED"),
+                        (4, "tsREJECTED"),
+                        (5, "tsHELD"),
+                    ],
+                ),
+            );
+            decoded.signer = object_field(&transaction, ST_BLOB, SF_SIGNING_PUB_KEY)
+                .filter(|key| !key.is_empty())
+                .map(<[u8]>::to_vec);
+        }
+        MessageType::GetLedger => {
+            decoded.push(
+                "itype",
+                name(
+                    payload.varint(1),
+                    &[
+                        (0, "liBASE"),
+                        (1, "liTX_NODE"),
+                        (2, "liAS_NODE"),
+                        (3, "liTS_CANDIDATE"),
+                    ],
+                ),
+            );
+            decoded.push("ledgerHash", hash(payload.bytes(3)));
+            decoded.push("nodeIDs", Some(payload.count(5).to_string()));
+        }
+        MessageType::LedgerData => {
+            decoded.push(
+                "type",
+                name(
+                    payload.varint(3),
+                    &[
+                        (0, "liBASE"),
+                        (1, "liTX_NODE"),
+                        (2, "liAS_NODE"),
+                        (3, "liTS_CANDIDATE"),
+                    ],
+                ),
+            );
+            decoded.push("ledgerHash", hash(payload.bytes(1)));
+            decoded.push("nodes", Some(payload.count(4).to_string()));
+            decoded.push("error", number(payload.varint(6)));
+        }
+        MessageType::ProposeLedger => {
+            decoded.push("proposeSeq", number(payload.varint(1)));
+            decoded.push("closeTime", number(payload.varint(4)));
+            decoded.push("currentTxHash", hash(payload.bytes(2)));
+            decoded.push("previousledger", hash(payload.bytes(6)));
+            decoded.signer = payload.bytes(3).map(<[u8]>::to_vec);
+        }
+        MessageType::StatusChange => {
+            decoded.push(
+                "newStatus",
+                name(
+                    payload.varint(1),
+                    &[
+                        (1, "nsCONNECTING"),
+                        (2, "nsCONNECTED"),
+                        (3, "nsMONITORING"),
+                        (4, "nsVALIDATING"),
+                        (5, "nsSHUTTING"),
+                    ],
+                ),
+            );
+            decoded.push(
+                "newEvent",
+                name(
+                    payload.varint(2),
+                    &[
+                        (1, "neCLOSING_LEDGER"),
+                        (2, "neACCEPTED_LEDGER"),
+                        (3, "neSWITCHED_LEDGER"),
+                        (4, "neLOST_SYNC"),
+                    ],
+                ),
+            );
+            decoded.push("ledgerHash", hash(payload.bytes(4)));
+        }
+        MessageType::HaveSet => {
+            decoded.push(
+                "status",
+                name(
+                    payload.varint(1),
+                    &[
+                        (1, "tsNEW"),
+                        (2, "tsCURRENT"),
+                        (3, "tsCOMMITED"),
+                        (4, "tsREJECTED"),
+                        (5, "tsHELD"),
+                    ],
+                ),
+            );
+            decoded.push("hash", hash(payload.bytes(2)));
+        }
+        MessageType::Validation => {
+            let validation: Vec<ObjectField> = payload
+                .bytes(1)
+                .map(object_fields)
+                .into_iter()
+                .flatten()
+                .collect();
+            decoded.push(
+                "full",
+                uint32(object_field(&validation, ST_UINT32, SF_FLAGS))
+                    .map(|flags| (flags & VALIDATION_FLAG_FULL != 0).to_string()),
+            );
+            decoded.push(
+                "signingTime",
+                uint32(object_field(&validation, ST_UINT32, SF_SIGNING_TIME))
+                    .map(|time| time.to_string()),
+            );
+            decoded.push(
+                "ledgerHash",
+                hash(object_field(&validation, ST_HASH256, SF_LEDGER_HASH)),
+            );
+            decoded.signer =
+                object_field(&validation, ST_BLOB, SF_SIGNING_PUB_KEY).map(<[u8]>::to_vec);
+        }
+        MessageType::GetObjects => {
+            decoded.push("type", number(payload.varint(1)));
+            decoded.push("seq", number(payload.varint(3)));
+            decoded.push("ledgerHash", hash(payload.bytes(4)));
+            decoded.push("objects", Some(payload.count(6).to_string()));
+        }
+        MessageType::Squelch => {
+            decoded.push("squelch", number(payload.varint(1)));
+            decoded.push("validatorPubKey", payload.bytes(2).map(hex::encode_upper));
+            decoded.push("squelchDuration", number(payload.varint(3)));
+        }
+        MessageType::ValidatorList => {
+            decoded.push("version", number(payload.varint(4)));
+        }
+        MessageType::ValidatorListCollection => {
+            decoded.push("version", number(payload.varint(1)));
+            decoded.push("blobs", Some(payload.count(3).to_string()));
+        }
+        MessageType::HaveTransactions => {
+            decoded.push("hashes", Some(payload.count(1).to_string()));
+        }
+        MessageType::Transactions => {
+            decoded.push("transactions", Some(payload.count(1).to_string()));
+        }
+        _ => (),
+    }
+    Some(decoded)
+}
+
+/// A field of a serialized object of the XRPL binary format: its type code, field code and value.
+pub type ObjectField<'a> = (u8, u8, &'a [u8]);
+
+/// Returns the leading fields of a serialized object of the XRPL binary format, in the order they are serialized.
+/// Fields are serialized in order of their type code, so the fields up to the first nested object or array, or the
+/// first field of a type that is not known, are all the fields of the types that are known.
+///
+/// # Parameters
+/// * 'object' - the serialized object.
+pub fn object_fields<'a>(object: &'a [u8]) -> impl Iterator<Item = ObjectField<'a>> {
+    sized_object_fields(object).map(|(field, _)| field)
+}
+
+/// Returns a serialized object without one of its fields, e.g. the data a signed object is signed over, which is the
+/// object without its signature. Returns None if the object does not contain the field among its leading fields.
+///
+/// # Parameters
+/// * 'object' - the serialized object.
+/// * 'type_code' - the type code of the field.
+/// * 'field_code' - the field code of the field.
+pub fn without_field(object: &[u8], type_code: u8, field_code: u8) -> Option<Vec<u8>> {
+    let mut start = 0;
+    for ((field_type, field, _), size) in sized_object_fields(object) {
+        if (field_type, field) == (type_code, field_code) {
+            return Some([&object[..start], &object[start + size..]].concat());
+        }
+        start += size;
+    }
+    None
+}
+
+/// Returns the leading fields of a serialized object like `object_fields`, with the size of every field including its
+/// header and length prefix.
+///
+/// # Parameters
+/// * 'object' - the serialized object.
+fn sized_object_fields<'a>(mut object: &'a [u8]) -> impl Iterator<Item = (ObjectField<'a>, usize)> {
+    std::iter::from_fn(move || {
+        let header = *object.first()?;
+        let (type_code, field_code, header_size) = match (header >> 4, header & 0x0F) {
+            (0, 0) => (*object.get(1)?, *object.get(2)?, 3),
+            (0, field_code) => (*object.get(1)?, field_code, 2),
+            (type_code, 0) => (type_code, *object.get(1)?, 2),
+            (type_code, field_code) => (type_code, field_code, 1),
+        };
+        let rest = object.get(header_size..)?;
+        let (prefix_size, size) = match type_code {
+            1 => (0, 2),
+            2 => (0, 4),
+            3 => (0, 8),
+            4 => (0, 16),
+            5 => (0, 32),
+            // Amounts of XRP are 8 bytes, amounts of issued currencies 48 bytes
+            6 if rest.first()? & 0x80 == 0 => (0, 8),
+            6 => (0, 48),
+            // Blob, AccountID and Vector256 are prefixed with their length
+            7 | 8 | 19 => variable_length(rest)?,
+            16 => (0, 1),
+            17 => (0, 20),
+            _ => return None,
+        };
+        let value = rest.get(prefix_size..prefix_size + size)?;
+        object = &rest[prefix_size + size..];
+        Some((
+            (type_code, field_code, value),
+            header_size + prefix_size + size,
+        ))
+    })
+}
+
+/// Returns the size of the length prefix of a variable length field of the XRPL binary format and its length.
+///
+/// # Parameters
+/// * 'field' - the field, starting with its length prefix.
+fn variable_length(field: &[u8]) -> Option<(usize, usize)> {
+    let byte = |i: usize| field.get(i).map(|byte| *byte as usize);
+    match byte(0)? {
+        length @ 0..=192 => Some((1, length)),
+        first @ 193..=240 => Some((2, 193 + ((first - 193) << 8) + byte(1)?)),
+        first @ 241..=254 => Some((
+            3,
+            12481 + ((first - 241) << 16) + (byte(1)? << 8) + byte(2)?,
+        )),
+        _ => None,
+    }
+}
+
+/// Returns the value of a field of a serialized object.
+///
+/// # Parameters
+/// * 'fields' - the fields of the object.
+/// * 'type_code' - the type code of the field.
+/// * 'field_code' - the field code of the field.
+pub fn object_field<'a>(
+    fields: &[ObjectField<'a>],
+    type_code: u8,
+    field_code: u8,
+) -> Option<&'a [u8]> {
+    fields
+        .iter()
+        .find(|(field_type, field, _)| (*field_type, *field) == (type_code, field_code))
+        .map(|(_, _, value)| *value)
+}
+
+/// Returns the value of a UInt32 field.
+///
+/// # Parameters
+/// * 'value' - the value of the field, None if the object does not contain it.
+fn uint32(value: Option<&[u8]>) -> Option<u32> {
+    Some(u32::from_be_bytes(value?.try_into().ok()?))
+}
+
+/// Returns an amount of XRP in drops, or None for amounts of issued currencies.
+///
+/// # Parameters
+/// * 'amount' - the value of an Amount field.
+fn drops(amount: &[u8]) -> Option<String> {
+    let amount = u64::from_be_bytes(amount.try_into().ok()?);
+    // The highest bit tells XRP apart, the next bit is set for positive amounts
+    Some((amount & 0x3FFF_FFFF_FFFF_FFFF).to_string())
+}
+
+/// Returns the name of an enum value as in rippled's `ripple.proto`, or the value if it has no name.
+///
+/// # Parameters
+/// * 'value' - the value, None if the message does not contain it.
+/// * 'names' - the names of the values.
+fn name(value: Option<u64>, names: &[(u64, &str)]) -> Option<String> {
+    let value = value?;
+    Some(
+        names
+            .iter()
+            .find(|(named, _)| *named == value)
+            .map_or(value.to_string(), |(_, name)| name.to_string()),
+    )
+}
+
+/// Returns the name of a transaction type, or its value if it is not a common one.
+///
+/// # Parameters
+/// * 'value' - the value of sfTransactionType.
+fn transaction_type(value: u16) -> String {
+    match value {
+        0 => "Payment",
+        1 => "EscrowCreate",
+        2 => "EscrowFinish",
+        3 => "AccountSet",
+        4 => "EscrowCancel",
+        5 => "SetRegularKey",
+        7 => "OfferCreate",
+        8 => "OfferCancel",
+        10 => "TicketCreate",
+        12 => "SignerListSet",
+        20 => "TrustSet",
+        100 => "EnableAmendment",
+        101 => "SetFee",
+        102 => "UNLModify",
+        value => return value.to_string(),
+    }
+    .to_string()
+}
+
+/// Returns the first bytes of a hash in hex.
+///
+/// # Parameters
+/// * 'hash' - the hash.
+fn short_hash(hash: &[u8]) -> String {
+    hex::encode_upper(&hash[..hash.len().min(SHORT_HASH_LENGTH)])
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::message_decoder::decode;
+    use crate::message_type::MessageType;
+    use prost::encoding::encode_varint;
+
+    fn message(message_type: MessageType, payload: &[u8]) -> Vec<u8> {
+        let mut message = (payload.len() as u32).to_be_bytes().to_vec();
+        message.extend_from_slice(&message_type.value().to_be_bytes());
+        message.extend_from_slice(payload);
+        message
+    }
+
+    fn bytes_field(payload: &mut Vec<u8>, number: u64, contents: &[u8]) {
+        encode_varint(number << 3 | 2, payload);
+        encode_varint(contents.len() as u64, payload);
+        payload.extend_from_slice(contents);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn consensus_messages_are_decoded() {
+        let key = [0x02; 33];
+        let mut payload = Vec::new();
+        encode_varint(1 << 3, &mut payload);
+        encode_varint(3, &mut payload);
+        bytes_field(&mut payload, 2, &[0xAB; 32]);
+        bytes_field(&mut payload, 3, &key);
+        encode_varint(4 << 3, &mut payload);
+        encode_varint(770_000_000, &mut payload);
+        bytes_field(&mut payload, 6, &[0xCD; 32]);
+        let decoded = decode(&message(MessageType::ProposeLedger, &payload)).unwrap();
+        assert_eq!(decoded.signer, Some(key.to_vec()));
+        assert_eq!(decoded.ledger_sequence, None);
+        assert_eq!(
+            decoded.to_string(),
+            format!(
+                "proposeSeq=3 closeTime=770000000 currentTxHash=ABABABABABABABAB previousledger=CDCDCDCDCDCDCDCD \
+                 signer={}",
+                "02".repeat(33)
+            )
+        );
+
+        // sfFlags, sfLedgerSequence, sfSigningTime, sfLedgerHash and sfSigningPubKey
+        let mut validation = vec![
+            0x22, 0x80, 0, 0, 1, 0x26, 0, 0, 0, 9, 0x29, 0, 0, 0, 42, 0x51,
+        ];
+        validation.extend_from_slice(&[0xEF; 32]);
+        validation.extend_from_slice(&[0x73, 33]);
+        validation.extend_from_slice(&key);
+        let mut payload = Vec::new();
+        bytes_field(&mut payload, 1, &validation);
+        let decoded = decode(&message(MessageType::Validation, &payload)).unwrap();
+        assert_eq!(decoded.ledger_sequence, Some(9));
+        assert_eq!(decoded.signer, Some(key.to_vec()));
+        assert_eq!(
+            decoded.fields,
+            vec![
+                ("full", "true".to_string()),
+                ("signingTime", "42".to_string()),
+                ("ledgerHash", "EFEFEFEFEFEFEFEF".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn other_messages_are_decoded() {
+        // sfTransactionType, sfSequence, sfFee, sfSigningPubKey and sfAccount
+        let mut transaction = vec![
+            0x12, 0, 0, 0x24, 0, 0, 0, 7, 0x68, 0x40, 0, 0, 0, 0, 0, 0, 12,
+        ];
+        transaction.extend_from_slice(&[0x73, 0]);
+        transaction.extend_from_slice(&[0x81, 20]);
+        transaction.extend_from_slice(&[0x11; 20]);
+        let mut payload = Vec::new();
+        bytes_field(&mut payload, 1, &transaction);
+        encode_varint(2 << 3, &mut payload);
+        encode_varint(1, &mut payload);
+        let decoded = decode(&message(MessageType::Transaction, &payload)).unwrap();
+        assert_eq!(decoded.signer, None);
+        assert_eq!(
+            decoded.to_string(),
+            format!(
+                "transactionType=Payment account={} sequence=7 fee=12 status=tsNEW",
+                "11".repeat(20)
+            )
+        );
+
+        let mut payload = Vec::new();
+        encode_varint(2 << 3, &mut payload);
+        encode_varint(2, &mut payload);
+        encode_varint(3 << 3, &mut payload);
+        encode_varint(12, &mut payload);
+        let decoded = decode(&message(MessageType::StatusChange, &payload)).unwrap();
+        assert_eq!(decoded.ledger_sequence, Some(12));
+        assert_eq!(decoded.to_string(), "newEvent=neACCEPTED_LEDGER");
+
+        let mut payload = Vec::new();
+        encode_varint(1 << 3, &mut payload);
+        encode_varint(9, &mut payload);
+        let decoded = decode(&message(MessageType::Ping, &payload)).unwrap();
+        assert_eq!(decoded.to_string(), "type=9");
+
+        assert_eq!(decode(&message(MessageType::Ping, &[0xFF])), None);
+        assert_eq!(
+            decode(&message(MessageType::Cluster, &[]))
+                .unwrap()
+                .to_string(),
+            ""
+        );
+    }
+}
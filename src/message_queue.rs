@@ -0,0 +1,488 @@
+//! This module is responsible for the bounded queues between the read, decision and write stages of a link.
+//!
+//! Every queue has a fixed capacity and an overflow policy that decides what happens when a message is pushed
+//! to a full queue, such that a slow controller can not make the memory usage of the interceptor grow unbounded.
+//! A queue can also spill to disk when it is full, such that bursts are buffered without losing messages.
+//! A queue can also prioritize its items, such that under congestion the items of the highest priority are popped first
+//! and the items of the lowest priority are dropped first.
+
+use crate::config::OverflowPolicy;
+use crate::disk_queue::DiskQueue;
+use crate::interceptor_state::InterceptorState;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::{error, info};
+
+/// Struct that represents the gauges of a single queue, which can be read while the queue is in use.
+#[derive(Debug)]
+pub struct QueueGauge {
+    /// The name of the queue, identifying the stage and link it belongs to.
+    pub name: String,
+    /// The maximum amount of messages in the queue.
+    pub capacity: usize,
+    /// The current amount of messages in the queue.
+    depth: AtomicUsize,
+    /// The highest amount of messages that was in the queue at once.
+    max_depth: AtomicUsize,
+    /// The amount of messages that were dropped because the queue was full.
+    dropped: AtomicU64,
+    /// The amount of messages that were spilled to disk because the queue was full.
+    spilled: AtomicU64,
+}
+
+impl QueueGauge {
+    /// Initializes a new QueueGauge for an empty queue.
+    ///
+    /// # Parameters
+    /// * 'name' - the name of the queue.
+    /// * 'capacity' - the maximum amount of messages in the queue.
+    pub fn new(name: String, capacity: usize) -> Self {
+        Self {
+            name,
+            capacity,
+            depth: AtomicUsize::new(0),
+            max_depth: AtomicUsize::new(0),
+            dropped: AtomicU64::new(0),
+            spilled: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the current amount of messages in the queue.
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed)
+    }
+
+    /// Returns the highest amount of messages that was in the queue at once.
+    pub fn max_depth(&self) -> usize {
+        self.max_depth.load(Ordering::Relaxed)
+    }
+
+    /// Returns the amount of messages that were dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Returns the amount of messages that were spilled to disk because the queue was full.
+    pub fn spilled(&self) -> u64 {
+        self.spilled.load(Ordering::Relaxed)
+    }
+
+    /// Records the current amount of messages in the queue.
+    fn set_depth(&self, depth: usize) {
+        self.depth.store(depth, Ordering::Relaxed);
+        self.max_depth.fetch_max(depth, Ordering::Relaxed);
+    }
+
+    /// Records that a message was dropped.
+    fn record_drop(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records that a message was spilled to disk.
+    fn record_spill(&self) {
+        self.spilled.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Struct that represents the disk storage a queue spills to, together with the functions that convert
+/// the items of the queue to and from records on disk.
+#[derive(Debug)]
+struct Spill<T> {
+    /// The queue on disk, containing items that are newer than all items in memory.
+    disk_queue: DiskQueue,
+    /// Converts an item into a record.
+    encode: fn(&T) -> Vec<u8>,
+    /// Converts a record back into an item, returns None if the record is invalid.
+    decode: fn(&[u8]) -> Option<T>,
+}
+
+/// Struct that represents the items of a queue, in memory and possibly on disk.
+#[derive(Debug)]
+struct Contents<T> {
+    /// The items in memory, oldest first.
+    memory: VecDeque<T>,
+    /// The disk storage the queue spills to, if it can spill.
+    spill: Option<Spill<T>>,
+}
+
+impl<T> Contents<T> {
+    /// Returns the amount of items in memory and on disk.
+    fn len(&self) -> usize {
+        self.memory.len()
+            + self
+                .spill
+                .as_ref()
+                .map_or(0, |spill| spill.disk_queue.len())
+    }
+}
+
+/// Struct that represents a bounded first-in-first-out queue between two stages of a link, which can prioritize its
+/// items.
+#[derive(Debug)]
+pub struct BoundedQueue<T> {
+    /// The maximum amount of items in the queue.
+    capacity: usize,
+    /// What happens when an item is pushed to a full queue.
+    overflow: OverflowPolicy,
+    /// The items in the queue.
+    contents: Mutex<Contents<T>>,
+    /// Returns the priority of an item, higher is popped earlier, if the items are prioritized.
+    priority: Option<fn(&T) -> u8>,
+    /// Notified when an item was pushed.
+    not_empty: Notify,
+    /// Notified when an item was popped.
+    not_full: Notify,
+    /// The gauges of the queue.
+    gauge: Arc<QueueGauge>,
+}
+
+impl<T> BoundedQueue<T> {
+    /// Initializes a new, empty BoundedQueue.
+    ///
+    /// # Parameters
+    /// * 'overflow' - what happens when an item is pushed to a full queue. Spilling to disk requires
+    ///   the queue to be created with `with_spill`, otherwise it behaves like blocking.
+    /// * 'gauge' - the gauges of the queue, which also contain its capacity. A capacity of 0 is treated as 1.
+    pub fn new(overflow: OverflowPolicy, gauge: Arc<QueueGauge>) -> Self {
+        let capacity = gauge.capacity.max(1);
+        Self {
+            capacity,
+            overflow,
+            contents: Mutex::new(Contents {
+                memory: VecDeque::with_capacity(capacity),
+                spill: None,
+            }),
+            priority: None,
+            not_empty: Notify::new(),
+            not_full: Notify::new(),
+            gauge,
+        }
+    }
+
+    /// Initializes a new, empty BoundedQueue that spills to disk once it is full.
+    ///
+    /// # Parameters
+    /// * 'gauge' - the gauges of the queue, which also contain its capacity in memory. A capacity of 0 is treated as 1.
+    /// * 'disk_queue' - the empty queue on disk the items are spilled to.
+    /// * 'encode' - converts an item into a record.
+    /// * 'decode' - converts a record back into an item, returns None if the record is invalid.
+    pub fn with_spill(
+        gauge: Arc<QueueGauge>,
+        disk_queue: DiskQueue,
+        encode: fn(&T) -> Vec<u8>,
+        decode: fn(&[u8]) -> Option<T>,
+    ) -> Self {
+        let queue = Self::new(OverflowPolicy::SpillToDisk, gauge);
+        queue.contents.lock().unwrap().spill = Some(Spill {
+            disk_queue,
+            encode,
+            decode,
+        });
+        queue
+    }
+
+    /// Prioritizes the items of the queue: the oldest item of the highest priority in memory is popped first, and if the
+    /// oldest item is dropped when the queue is full, the oldest item of the lowest priority is dropped instead. An
+    /// incoming item that does not have a higher priority than that item is dropped itself. Items of the same priority
+    /// keep their order.
+    ///
+    /// # Parameters
+    /// * 'priority' - returns the priority of an item, higher is popped earlier.
+    pub fn with_priority(mut self, priority: fn(&T) -> u8) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    /// Pushes an item to the back of the queue.
+    /// If the queue is full, this either waits until there is space, drops the oldest item or spills the item to disk,
+    /// depending on the overflow policy. Once an item has been spilled, newer items are spilled as well until
+    /// the disk has been emptied, such that the order of the items is kept.
+    ///
+    /// # Parameters
+    /// * 'item' - the item to be pushed.
+    ///
+    /// # Panics
+    /// * If the item could not be spilled to disk.
+    pub async fn push(&self, item: T) {
+        let mut item = Some(item);
+        loop {
+            {
+                let mut contents = self.contents.lock().unwrap();
+                let Contents { memory, spill } = &mut *contents;
+                if let Some(spill) = spill {
+                    if !spill.disk_queue.is_empty() || memory.len() >= self.capacity {
+                        spill
+                            .disk_queue
+                            .push(&(spill.encode)(item.as_ref().unwrap()))
+                            .expect("Could not spill message to disk");
+                        self.gauge.record_spill();
+                        self.gauge.set_depth(contents.len());
+                        drop(contents);
+                        self.not_empty.notify_one();
+                        return;
+                    }
+                }
+                if memory.len() >= self.capacity && self.overflow == OverflowPolicy::DropOldest {
+                    let lowest = self.priority.and_then(|priority| {
+                        (0..memory.len()).min_by_key(|i| priority(&memory[*i]))
+                    });
+                    match (self.priority, lowest) {
+                        // The incoming item is dropped instead, if it does not have a higher priority than any item
+                        (Some(priority), Some(i))
+                            if priority(item.as_ref().unwrap()) <= priority(&memory[i]) =>
+                        {
+                            self.gauge.record_drop();
+                            return;
+                        }
+                        (Some(_), lowest) => lowest.and_then(|i| memory.remove(i)),
+                        (None, _) => memory.pop_front(),
+                    };
+                    self.gauge.record_drop();
+                }
+                if memory.len() < self.capacity {
+                    memory.push_back(item.take().unwrap());
+                    self.gauge.set_depth(contents.len());
+                    drop(contents);
+                    self.not_empty.notify_one();
+                    return;
+                }
+            }
+            self.not_full.notified().await;
+        }
+    }
+
+    /// Pops the oldest item from the queue, or the oldest item of the highest priority if the items are prioritized,
+    /// waiting until there is one. Items in memory are always older than the items spilled to disk, and are all popped
+    /// before them.
+    ///
+    /// # Panics
+    /// * If a spilled item could not be read from disk.
+    pub async fn pop(&self) -> T {
+        loop {
+            {
+                let mut contents = self.contents.lock().unwrap();
+                let Contents { memory, spill } = &mut *contents;
+                let next = match self.priority {
+                    // The first of the items of the highest priority, as max_by_key returns the last of equal items
+                    Some(priority) => (0..memory.len())
+                        .rev()
+                        .max_by_key(|i| priority(&memory[*i]))
+                        .and_then(|i| memory.remove(i)),
+                    None => memory.pop_front(),
+                };
+                let item = match (next, spill) {
+                    (Some(item), _) => Some(item),
+                    (None, Some(spill)) => spill
+                        .disk_queue
+                        .pop()
+                        .expect("Could not read spilled message from disk")
+                        .and_then(|record| {
+                            let item = (spill.decode)(&record);
+                            if item.is_none() {
+                                error!("Dropped invalid message that was spilled to disk");
+                                self.gauge.record_drop();
+                            }
+                            item
+                        }),
+                    (None, None) => None,
+                };
+                if let Some(item) = item {
+                    self.gauge.set_depth(contents.len());
+                    drop(contents);
+                    self.not_full.notify_one();
+                    return item;
+                }
+                if contents.len() > 0 {
+                    // An invalid record was dropped, but there are more items
+                    continue;
+                }
+            }
+            self.not_empty.notified().await;
+        }
+    }
+
+    /// Returns the amount of items in the queue, in memory and on disk.
+    pub fn len(&self) -> usize {
+        self.contents.lock().unwrap().len()
+    }
+
+    /// Returns whether the queue contains no items.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Periodically logs the gauges of all registered queues, for as long as the interceptor is running.
+///
+/// # Parameters
+/// * 'state' - the runtime state in which the queues are registered.
+/// * 'interval' - the time between two reports.
+pub async fn report_gauges(state: Arc<InterceptorState>, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        for gauge in state.queue_gauges() {
+            info!(
+                queue = gauge.name.as_str(),
+                depth = gauge.depth(),
+                max_depth = gauge.max_depth(),
+                capacity = gauge.capacity,
+                dropped = gauge.dropped(),
+                spilled = gauge.spilled(),
+                "Queue gauge"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::config::OverflowPolicy;
+    use crate::disk_queue::DiskQueue;
+    use crate::message_queue::{BoundedQueue, QueueGauge};
+    use std::fs;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn queue(capacity: usize, overflow: OverflowPolicy) -> BoundedQueue<u32> {
+        BoundedQueue::new(
+            overflow,
+            Arc::new(QueueGauge::new("test".to_string(), capacity)),
+        )
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn queue_is_fifo() {
+        let queue = queue(10, OverflowPolicy::Block);
+        for i in 0..5 {
+            queue.push(i).await;
+        }
+        assert_eq!(queue.len(), 5);
+        for i in 0..5 {
+            assert_eq!(queue.pop().await, i);
+        }
+        assert!(queue.is_empty());
+        assert_eq!(queue.gauge.max_depth(), 5);
+        assert_eq!(queue.gauge.depth(), 0);
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn drop_oldest_when_full() {
+        let queue = queue(2, OverflowPolicy::DropOldest);
+        for i in 0..4 {
+            queue.push(i).await;
+        }
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.gauge.dropped(), 2);
+        assert_eq!(queue.pop().await, 2);
+        assert_eq!(queue.pop().await, 3);
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn highest_priority_first() {
+        // The priority is the tens of an item
+        let queue = queue(10, OverflowPolicy::Block).with_priority(|item| (item / 10) as u8);
+        for item in [1, 20, 10, 21, 2, 11] {
+            queue.push(item).await;
+        }
+        let mut popped = Vec::new();
+        while !queue.is_empty() {
+            popped.push(queue.pop().await);
+        }
+        assert_eq!(popped, [20, 21, 10, 11, 1, 2]);
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn drop_lowest_priority_when_full() {
+        let queue = queue(3, OverflowPolicy::DropOldest).with_priority(|item| (item / 10) as u8);
+        for item in [20, 1, 10, 2, 21] {
+            queue.push(item).await;
+        }
+        assert_eq!(queue.gauge.dropped(), 2);
+        assert_eq!(queue.pop().await, 20);
+        assert_eq!(queue.pop().await, 21);
+        assert_eq!(queue.pop().await, 10);
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn drop_incoming_item_of_lower_priority_when_full() {
+        let queue = queue(2, OverflowPolicy::DropOldest).with_priority(|item| (item / 10) as u8);
+        for item in [20, 21, 1] {
+            queue.push(item).await;
+        }
+        assert_eq!(queue.gauge.dropped(), 1);
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.pop().await, 20);
+        assert_eq!(queue.pop().await, 21);
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn block_when_full() {
+        let queue = Arc::new(queue(1, OverflowPolicy::Block));
+        queue.push(1).await;
+
+        let pusher = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.push(2).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!pusher.is_finished());
+        assert_eq!(queue.len(), 1);
+
+        assert_eq!(queue.pop().await, 1);
+        pusher.await.unwrap();
+        assert_eq!(queue.pop().await, 2);
+        assert_eq!(queue.gauge.dropped(), 0);
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn pop_waits_for_push() {
+        let queue = Arc::new(queue(1, OverflowPolicy::Block));
+        let popper = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.pop().await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!popper.is_finished());
+        queue.push(7).await;
+        assert_eq!(popper.await.unwrap(), 7);
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn spill_to_disk_when_full() {
+        let directory = std::env::temp_dir().join(format!("spill_queue_{}", std::process::id()));
+        let queue = BoundedQueue::with_spill(
+            Arc::new(QueueGauge::new("test".to_string(), 2)),
+            DiskQueue::new(&directory, 1024).unwrap(),
+            |item: &u32| item.to_le_bytes().to_vec(),
+            |record| Some(u32::from_le_bytes(record.try_into().ok()?)),
+        );
+        for i in 0..5 {
+            queue.push(i).await;
+        }
+        assert_eq!(queue.len(), 5);
+        assert_eq!(queue.gauge.spilled(), 3);
+
+        // Pushing while items are on disk keeps the order, even though there is space in memory
+        assert_eq!(queue.pop().await, 0);
+        queue.push(5).await;
+        for i in 1..6 {
+            assert_eq!(queue.pop().await, i);
+        }
+        assert!(queue.is_empty());
+        assert_eq!(queue.gauge.dropped(), 0);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,249 @@
+//! This module is responsible for identifying the type of XRPL peer protocol messages.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// The numeric values of all message types that are known by name.
+const KNOWN_VALUES: [u16; 21] = [
+    2, 3, 5, 15, 30, 31, 32, 33, 34, 35, 41, 42, 54, 55, 56, 57, 58, 59, 60, 63, 64,
+];
+
+/// Enum that represents the type of a message of the XRPL peer protocol.
+/// The values correspond to the `MessageType` enum in rippled's `ripple.proto`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageType {
+    Manifests,
+    Ping,
+    Cluster,
+    Endpoints,
+    Transaction,
+    GetLedger,
+    LedgerData,
+    ProposeLedger,
+    StatusChange,
+    HaveSet,
+    Validation,
+    GetObjects,
+    ValidatorList,
+    Squelch,
+    ValidatorListCollection,
+    ProofPathRequest,
+    ProofPathResponse,
+    ReplayDeltaRequest,
+    ReplayDeltaResponse,
+    HaveTransactions,
+    Transactions,
+    Unknown(u16),
+}
+
+impl MessageType {
+    /// Parses the message type from the header of a message.
+    /// Returns None if the message is too short to contain a header.
+    ///
+    /// # Parameters
+    /// * 'message' - the message including its 6 byte header.
+    pub fn from_message(message: &[u8]) -> Option<Self> {
+        if message.len() < 6 {
+            return None;
+        }
+        Some(Self::from(u16::from_be_bytes([message[4], message[5]])))
+    }
+
+    /// Returns the numeric value of the message type, as used in the message header.
+    pub fn value(&self) -> u16 {
+        match self {
+            MessageType::Manifests => 2,
+            MessageType::Ping => 3,
+            MessageType::Cluster => 5,
+            MessageType::Endpoints => 15,
+            MessageType::Transaction => 30,
+            MessageType::GetLedger => 31,
+            MessageType::LedgerData => 32,
+            MessageType::ProposeLedger => 33,
+            MessageType::StatusChange => 34,
+            MessageType::HaveSet => 35,
+            MessageType::Validation => 41,
+            MessageType::GetObjects => 42,
+            MessageType::ValidatorList => 54,
+            MessageType::Squelch => 55,
+            MessageType::ValidatorListCollection => 56,
+            MessageType::ProofPathRequest => 57,
+            MessageType::ProofPathResponse => 58,
+            MessageType::ReplayDeltaRequest => 59,
+            MessageType::ReplayDeltaResponse => 60,
+            MessageType::HaveTransactions => 63,
+            MessageType::Transactions => 64,
+            MessageType::Unknown(value) => *value,
+        }
+    }
+
+    /// Returns whether messages of the type carry the votes of consensus: proposals and validations.
+    pub fn is_consensus(&self) -> bool {
+        matches!(self, MessageType::ProposeLedger | MessageType::Validation)
+    }
+
+    /// Returns whether messages of the type relay transactions, or request or deliver ledger data.
+    pub fn is_relay(&self) -> bool {
+        matches!(
+            self,
+            MessageType::Transaction
+                | MessageType::Transactions
+                | MessageType::HaveTransactions
+                | MessageType::GetLedger
+                | MessageType::LedgerData
+                | MessageType::GetObjects
+                | MessageType::ProofPathRequest
+                | MessageType::ProofPathResponse
+                | MessageType::ReplayDeltaRequest
+                | MessageType::ReplayDeltaResponse
+        )
+    }
+}
+
+impl From<u16> for MessageType {
+    fn from(value: u16) -> Self {
+        match value {
+            2 => MessageType::Manifests,
+            3 => MessageType::Ping,
+            5 => MessageType::Cluster,
+            15 => MessageType::Endpoints,
+            30 => MessageType::Transaction,
+            31 => MessageType::GetLedger,
+            32 => MessageType::LedgerData,
+            33 => MessageType::ProposeLedger,
+            34 => MessageType::StatusChange,
+            35 => MessageType::HaveSet,
+            41 => MessageType::Validation,
+            42 => MessageType::GetObjects,
+            54 => MessageType::ValidatorList,
+            55 => MessageType::Squelch,
+            56 => MessageType::ValidatorListCollection,
+            57 => MessageType::ProofPathRequest,
+            58 => MessageType::ProofPathResponse,
+            59 => MessageType::ReplayDeltaRequest,
+            60 => MessageType::ReplayDeltaResponse,
+            63 => MessageType::HaveTransactions,
+            64 => MessageType::Transactions,
+            value => MessageType::Unknown(value),
+        }
+    }
+}
+
+impl fmt::Display for MessageType {
+    /// Formats the message type with the name used by rippled, e.g. 'mtVALIDATION'.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MessageType::Manifests => "mtMANIFESTS",
+            MessageType::Ping => "mtPING",
+            MessageType::Cluster => "mtCLUSTER",
+            MessageType::Endpoints => "mtENDPOINTS",
+            MessageType::Transaction => "mtTRANSACTION",
+            MessageType::GetLedger => "mtGET_LEDGER",
+            MessageType::LedgerData => "mtLEDGER_DATA",
+            MessageType::ProposeLedger => "mtPROPOSE_LEDGER",
+            MessageType::StatusChange => "mtSTATUS_CHANGE",
+            MessageType::HaveSet => "mtHAVE_SET",
+            MessageType::Validation => "mtVALIDATION",
+            MessageType::GetObjects => "mtGET_OBJECTS",
+            MessageType::ValidatorList => "mtVALIDATORLIST",
+            MessageType::Squelch => "mtSQUELCH",
+            MessageType::ValidatorListCollection => "mtVALIDATORLISTCOLLECTION",
+            MessageType::ProofPathRequest => "mtPROOF_PATH_REQ",
+            MessageType::ProofPathResponse => "mtPROOF_PATH_RESPONSE",
+            MessageType::ReplayDeltaRequest => "mtREPLAY_DELTA_REQ",
+            MessageType::ReplayDeltaResponse => "mtREPLAY_DELTA_RESPONSE",
+            MessageType::HaveTransactions => "mtHAVE_TRANSACTIONS",
+            MessageType::Transactions => "mtTRANSACTIONS",
+            MessageType::Unknown(value) => return write!(f, "mtUNKNOWN({})", value),
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for MessageType {
+    type Err = String;
+
+    /// Parses a message type from its name used by rippled, e.g. 'mtVALIDATION', or from its numeric value.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(value) = s.parse::<u16>() {
+            return Ok(Self::from(value));
+        }
+        KNOWN_VALUES
+            .iter()
+            .map(|value| Self::from(*value))
+            .find(|message_type| message_type.to_string() == s)
+            .ok_or_else(|| format!("Unknown message type: {}", s))
+    }
+}
+
+impl Serialize for MessageType {
+    /// Serializes the message type as its name used by rippled.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for MessageType {
+    /// Deserializes the message type from its name used by rippled, or from its numeric value as a string.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Self::from_str(&name).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::message_type::MessageType;
+    use std::str::FromStr;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn message_type_from_str() {
+        assert_eq!(
+            MessageType::from_str("mtVALIDATION"),
+            Ok(MessageType::Validation)
+        );
+        assert_eq!(MessageType::from_str("3"), Ok(MessageType::Ping));
+        assert_eq!(MessageType::from_str("99"), Ok(MessageType::Unknown(99)));
+        assert!(MessageType::from_str("mtNONSENSE").is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn from_message_test() {
+        let message = vec![0, 0, 0, 2, 0, 41, 1, 2];
+        assert_eq!(
+            MessageType::from_message(&message),
+            Some(MessageType::Validation)
+        );
+        assert_eq!(MessageType::from_message(&[0, 0, 0]), None);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn value_round_trip() {
+        for value in 0..100u16 {
+            assert_eq!(MessageType::from(value).value(), value);
+        }
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn display_test() {
+        assert_eq!(MessageType::ProposeLedger.to_string(), "mtPROPOSE_LEDGER");
+        assert_eq!(MessageType::Unknown(99).to_string(), "mtUNKNOWN(99)");
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn serde_round_trip() {
+        let json = serde_json::to_string(&MessageType::Validation).unwrap();
+        assert_eq!(json, "\"mtVALIDATION\"");
+        assert_eq!(
+            serde_json::from_str::<MessageType>(&json).unwrap(),
+            MessageType::Validation
+        );
+        assert!(serde_json::from_str::<MessageType>("\"mtNONSENSE\"").is_err());
+    }
+}
@@ -0,0 +1,667 @@
+//! This module is responsible for the nemesis API, through which Jepsen-style test harnesses inject faults into a running
+//! network. The harness posts operations to `POST /nemesis` in the shape of the operations of a Jepsen nemesis, e.g.
+//! `{"type": "invoke", "f": "start-partition", "value": [[0, 1], [2]]}`, and gets the completed operation back with the
+//! type `info` and what was done as its value.
+//!
+//! Operations, by their `f`:
+//! * `start-partition` - cuts links, given a grudge (`{"2": [0, 1]}`: node 2 stops receiving from nodes 0 and 1) or
+//!   components (`[[0, 1], [2]]`: every node stops receiving from the nodes outside its component).
+//! * `stop-partition` - heals all cut links.
+//! * `start-delay` - delays the messages on the links of the given nodes, all nodes if not given, e.g.
+//!   `{"delay_ms": 200, "drop_probability": 0.1, "nodes": [0]}`.
+//! * `stop-delay` - removes the delay from all links.
+//! * `kill` and `start` - freezes and unfreezes the containers of the given nodes, all nodes if not given. The processes
+//!   are frozen instead of killed, since a restarted node would lose its connections through the interceptor.
+//! * `bump-clock` and `reset-clock` - moves the clocks of nodes by ms (`{"0": 5000}`), and sets the clocks of the given
+//!   nodes, all nodes if not given, to the real time. Only available when the clocks are skewed, see `[clock_skew]`.
+//! * `heal` - undoes all of the above.
+
+use crate::config::ClockSkew;
+use crate::docker_manager;
+use crate::interceptor_state::{InterceptorState, LinkRule};
+use crate::partition::OneWayPartition;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::post;
+use axum::{Json, Router};
+use bollard::Docker;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tracing::{error, info};
+
+/// Enum that represents the type of a Jepsen operation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OperationType {
+    /// The operation is started.
+    #[default]
+    Invoke,
+    /// The operation completed with an effect that is not checked, which is how a nemesis completes its operations.
+    Info,
+    /// The operation completed successfully.
+    Ok,
+    /// The operation did not take effect.
+    Fail,
+}
+
+/// Struct that represents a Jepsen operation, as it is sent to and returned by the API.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Operation {
+    /// The type of the operation.
+    #[serde(rename = "type", default)]
+    pub kind: OperationType,
+    /// The function of the operation, e.g. 'start-partition'.
+    pub f: String,
+    /// The argument of the operation, and what was done once it completed.
+    #[serde(default)]
+    pub value: Value,
+    /// The other fields of the operation, e.g. its process and time, which are returned as they were sent.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// Enum that represents the reason an operation could not be completed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NemesisError {
+    /// The operation is unknown, or its value does not fit it.
+    Invalid(String),
+    /// The operation could not be carried out, e.g. because Docker failed.
+    Failed(String),
+}
+
+impl fmt::Display for NemesisError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NemesisError::Invalid(reason) => write!(f, "Invalid nemesis operation: {}", reason),
+            NemesisError::Failed(reason) => write!(f, "Nemesis operation failed: {}", reason),
+        }
+    }
+}
+
+impl Error for NemesisError {}
+
+/// Struct that represents a node the nemesis can inject faults into.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NemesisNode {
+    /// The ID of the node.
+    pub node_id: u32,
+    /// The peer port of the node.
+    pub port: u16,
+    /// The ID of the container of the node.
+    pub container_id: String,
+    /// The file on the host the clock of the node is read from, with the skew the node was started with, if the clocks
+    /// of the nodes are skewed.
+    pub clock: Option<(String, ClockSkew)>,
+}
+
+/// Struct that represents the nemesis of a running network, with the faults it injected.
+#[derive(Debug)]
+pub struct Nemesis {
+    /// The runtime state, where the partitions and delays are set.
+    state: Arc<InterceptorState>,
+    /// The Docker API the containers of the nodes are frozen through.
+    docker: Docker,
+    /// The nodes, by their IDs.
+    nodes: Vec<NemesisNode>,
+    /// The IDs of the nodes whose containers are frozen.
+    killed: Mutex<BTreeSet<u32>>,
+    /// How many ms the clocks of the nodes were moved ahead of the real time, behind if negative, by node ID.
+    clock_offsets: Mutex<BTreeMap<u32, i64>>,
+}
+
+impl Nemesis {
+    /// Initializes a new Nemesis that did not inject any faults yet.
+    ///
+    /// # Parameters
+    /// * 'state' - the runtime state, where the partitions and delays are set.
+    /// * 'docker' - the Docker API the containers of the nodes are frozen through.
+    /// * 'nodes' - the nodes, by their IDs.
+    pub fn new(state: Arc<InterceptorState>, docker: Docker, nodes: Vec<NemesisNode>) -> Self {
+        let clock_offsets = nodes
+            .iter()
+            .filter_map(|node| {
+                let (_, skew) = node.clock.as_ref()?;
+                Some((node.node_id, skew.offset_secs * 1000))
+            })
+            .collect();
+        Self {
+            state,
+            docker,
+            nodes,
+            killed: Mutex::new(BTreeSet::new()),
+            clock_offsets: Mutex::new(clock_offsets),
+        }
+    }
+
+    /// Carries out an operation, and returns it completed with the type `info` and what was done as its value.
+    ///
+    /// # Parameters
+    /// * 'operation' - the operation.
+    pub async fn invoke(&self, operation: Operation) -> Result<Operation, NemesisError> {
+        let value = match operation.f.as_str() {
+            "start-partition" => self.start_partition(&operation.value)?,
+            "stop-partition" => self.stop_partition(),
+            "start-delay" => self.start_delay(&operation.value)?,
+            "stop-delay" => self.stop_delay(),
+            "kill" => self.kill(&operation.value).await?,
+            "start" => self.start(&operation.value).await?,
+            "bump-clock" => self.bump_clock(&operation.value)?,
+            "reset-clock" => self.reset_clock(&operation.value)?,
+            "heal" => self.heal().await?,
+            f => return Err(NemesisError::Invalid(format!("unknown f '{}'", f))),
+        };
+        info!("Nemesis {}: {}", operation.f, value);
+        Ok(Operation {
+            kind: OperationType::Info,
+            value,
+            ..operation
+        })
+    }
+
+    /// Cuts the links of a grudge or of components, and returns the cut links.
+    ///
+    /// # Parameters
+    /// * 'value' - the grudge, by the node that stops receiving, or the components.
+    fn start_partition(&self, value: &Value) -> Result<Value, NemesisError> {
+        let mut grudge: BTreeMap<u32, BTreeSet<u32>> = BTreeMap::new();
+        match value {
+            Value::Object(object) => {
+                for (to, froms) in object {
+                    let to = self.node(&Value::String(to.clone()))?.node_id;
+                    grudge.entry(to).or_default().extend(self.nodes_of(froms)?);
+                }
+            }
+            Value::Array(components) => {
+                let components = components
+                    .iter()
+                    .map(|component| self.nodes_of(component))
+                    .collect::<Result<Vec<_>, _>>()?;
+                for component in components.iter() {
+                    let others: BTreeSet<u32> = self
+                        .nodes
+                        .iter()
+                        .map(|node| node.node_id)
+                        .filter(|id| !component.contains(id))
+                        .collect();
+                    for to in component {
+                        grudge.entry(*to).or_default().extend(others.iter());
+                    }
+                }
+            }
+            _ => {
+                return Err(NemesisError::Invalid(
+                    "a partition is a grudge object or an array of components".to_string(),
+                ))
+            }
+        }
+        let cut_links: Vec<(u16, u16)> = grudge
+            .iter()
+            .flat_map(|(to, froms)| {
+                froms
+                    .iter()
+                    .filter(move |from| *from != to)
+                    .map(move |from| {
+                        (
+                            self.nodes[*from as usize].port,
+                            self.nodes[*to as usize].port,
+                        )
+                    })
+            })
+            .collect();
+        self.state.set_one_way_partition(OneWayPartition {
+            cut_links: cut_links.clone(),
+        });
+        Ok(json!({ "cut_links": cut_links }))
+    }
+
+    /// Heals all cut links.
+    fn stop_partition(&self) -> Value {
+        self.state.set_one_way_partition(OneWayPartition::default());
+        json!("healed")
+    }
+
+    /// Sets the rule of the links of nodes, and returns the amount of changed links.
+    ///
+    /// # Parameters
+    /// * 'value' - the delay in ms, the drop probability and the nodes, all nodes if not given.
+    fn start_delay(&self, value: &Value) -> Result<Value, NemesisError> {
+        let invalid = || {
+            NemesisError::Invalid(
+                "a delay is an object with delay_ms, and optionally drop_probability and nodes"
+                    .to_string(),
+            )
+        };
+        let rule = LinkRule {
+            delay_ms: value
+                .get("delay_ms")
+                .and_then(Value::as_u64)
+                .and_then(|delay_ms| u32::try_from(delay_ms).ok())
+                .ok_or_else(invalid)?,
+            drop_probability: match value.get("drop_probability") {
+                None => 0.0,
+                Some(probability) => probability.as_f64().ok_or_else(invalid)?,
+            },
+        };
+        rule.validate()
+            .map_err(|e| NemesisError::Invalid(e.to_string()))?;
+        let ports: BTreeSet<u16> = self
+            .nodes_or_all(value.get("nodes").unwrap_or(&Value::Null))?
+            .iter()
+            .map(|id| self.nodes[*id as usize].port)
+            .collect();
+        let mut changed = 0;
+        for link in self.state.links() {
+            if ports.contains(&link.from_port) || ports.contains(&link.to_port) {
+                let _ = self.state.set_link_rule(link.from_port, link.to_port, rule);
+                changed += 1;
+            }
+        }
+        Ok(json!({ "delayed_links": changed }))
+    }
+
+    /// Removes the delay and drop probability from all links.
+    fn stop_delay(&self) -> Value {
+        for link in self.state.links() {
+            let _ = self
+                .state
+                .set_link_rule(link.from_port, link.to_port, LinkRule::default());
+        }
+        json!("undelayed")
+    }
+
+    /// Freezes the containers of nodes, and returns the frozen nodes.
+    ///
+    /// # Parameters
+    /// * 'value' - the nodes, all nodes if not given.
+    async fn kill(&self, value: &Value) -> Result<Value, NemesisError> {
+        let nodes = self.nodes_or_all(value)?;
+        for id in nodes.iter() {
+            if self.killed.lock().unwrap().contains(id) {
+                continue;
+            }
+            self.docker
+                .pause_container(&self.nodes[*id as usize].container_id)
+                .await
+                .map_err(|e| {
+                    NemesisError::Failed(format!("could not freeze node {}: {}", id, e))
+                })?;
+            self.killed.lock().unwrap().insert(*id);
+        }
+        Ok(json!({ "killed": nodes }))
+    }
+
+    /// Unfreezes the frozen containers of nodes, and returns the unfrozen nodes.
+    ///
+    /// # Parameters
+    /// * 'value' - the nodes, all frozen nodes if not given.
+    async fn start(&self, value: &Value) -> Result<Value, NemesisError> {
+        let killed = self.killed.lock().unwrap().clone();
+        let nodes: BTreeSet<u32> = self
+            .nodes_or_all(value)?
+            .into_iter()
+            .filter(|id| killed.contains(id))
+            .collect();
+        for id in nodes.iter() {
+            self.docker
+                .unpause_container(&self.nodes[*id as usize].container_id)
+                .await
+                .map_err(|e| {
+                    NemesisError::Failed(format!("could not unfreeze node {}: {}", id, e))
+                })?;
+            self.killed.lock().unwrap().remove(id);
+        }
+        Ok(json!({ "started": nodes }))
+    }
+
+    /// Moves the clocks of nodes, and returns the offsets of their clocks in ms.
+    ///
+    /// # Parameters
+    /// * 'value' - the ms to move the clock by, by node.
+    fn bump_clock(&self, value: &Value) -> Result<Value, NemesisError> {
+        let Value::Object(bumps) = value else {
+            return Err(NemesisError::Invalid(
+                "a clock bump is an object of ms by node".to_string(),
+            ));
+        };
+        let mut offsets = BTreeMap::new();
+        for (node, delta) in bumps {
+            let id = self.node(&Value::String(node.clone()))?.node_id;
+            let delta = delta.as_i64().ok_or_else(|| {
+                NemesisError::Invalid(format!("'{}' is not a number of ms", delta))
+            })?;
+            let offset = self
+                .clock_offsets
+                .lock()
+                .unwrap()
+                .get(&id)
+                .copied()
+                .unwrap_or(0)
+                + delta;
+            offsets.insert(id, offset);
+        }
+        self.set_clocks(&offsets)
+    }
+
+    /// Sets the clocks of nodes to the real time, and returns the offsets of their clocks in ms.
+    ///
+    /// # Parameters
+    /// * 'value' - the nodes, all nodes if not given.
+    fn reset_clock(&self, value: &Value) -> Result<Value, NemesisError> {
+        let offsets = self
+            .nodes_or_all(value)?
+            .into_iter()
+            .map(|id| (id, 0))
+            .collect();
+        self.set_clocks(&offsets)
+    }
+
+    /// Writes the clocks of nodes, which are no longer drifting afterwards, and returns their offsets in ms.
+    ///
+    /// # Parameters
+    /// * 'offsets' - how many ms the clocks should be ahead of the real time, behind if negative, by node.
+    fn set_clocks(&self, offsets: &BTreeMap<u32, i64>) -> Result<Value, NemesisError> {
+        for (id, offset) in offsets {
+            let Some((clock_file, _)) = &self.nodes[*id as usize].clock else {
+                return Err(NemesisError::Invalid(
+                    "the clocks of the nodes can only be changed when [clock_skew] is configured"
+                        .to_string(),
+                ));
+            };
+            docker_manager::write_clock_file(
+                clock_file,
+                &docker_manager::faketime_spec(*offset, 1.0),
+            )
+            .map_err(|e| {
+                NemesisError::Failed(format!("could not set the clock of node {}: {}", id, e))
+            })?;
+            self.clock_offsets.lock().unwrap().insert(*id, *offset);
+        }
+        Ok(json!({ "clock_offsets_ms": offsets }))
+    }
+
+    /// Heals the partition, removes the delays, unfreezes the frozen nodes and sets the clocks to the real time, if they
+    /// can be changed.
+    async fn heal(&self) -> Result<Value, NemesisError> {
+        self.stop_partition();
+        self.stop_delay();
+        let started = self.start(&Value::Null).await?;
+        if self.nodes.iter().all(|node| node.clock.is_some()) {
+            self.reset_clock(&Value::Null)?;
+        }
+        Ok(json!({ "healed": true, "started": started["started"] }))
+    }
+
+    /// Returns the node with an ID, given as a number or a string like '2' or 'n2'.
+    ///
+    /// # Parameters
+    /// * 'value' - the ID.
+    fn node(&self, value: &Value) -> Result<&NemesisNode, NemesisError> {
+        let id = match value {
+            Value::Number(number) => number.as_u64(),
+            Value::String(name) => name.trim_start_matches('n').parse().ok(),
+            _ => None,
+        };
+        id.and_then(|id| self.nodes.get(id as usize))
+            .ok_or_else(|| NemesisError::Invalid(format!("node {} does not exist", value)))
+    }
+
+    /// Returns the IDs of the nodes in an array.
+    ///
+    /// # Parameters
+    /// * 'value' - the array of nodes.
+    fn nodes_of(&self, value: &Value) -> Result<BTreeSet<u32>, NemesisError> {
+        let Value::Array(nodes) = value else {
+            return Err(NemesisError::Invalid(format!(
+                "{} is not an array of nodes",
+                value
+            )));
+        };
+        nodes
+            .iter()
+            .map(|node| self.node(node).map(|node| node.node_id))
+            .collect()
+    }
+
+    /// Returns the IDs of the nodes in an array, or of all nodes if the value is null.
+    ///
+    /// # Parameters
+    /// * 'value' - the array of nodes, or null.
+    fn nodes_or_all(&self, value: &Value) -> Result<BTreeSet<u32>, NemesisError> {
+        match value {
+            Value::Null => Ok(self.nodes.iter().map(|node| node.node_id).collect()),
+            _ => self.nodes_of(value),
+        }
+    }
+}
+
+/// Returns the router with the endpoint of the nemesis API.
+///
+/// # Parameters
+/// * 'nemesis' - the nemesis the operations are carried out by.
+pub fn router(nemesis: Arc<Nemesis>) -> Router {
+    Router::new()
+        .route("/nemesis", post(invoke))
+        .with_state(nemesis)
+}
+
+/// Serves the nemesis API until the listener fails.
+///
+/// # Parameters
+/// * 'listener' - the bound listener of the nemesis API.
+/// * 'nemesis' - the nemesis the operations are carried out by.
+pub async fn serve(listener: TcpListener, nemesis: Arc<Nemesis>) {
+    info!("Serving the nemesis API on {:?}", listener.local_addr());
+    if let Err(e) = axum::serve(listener, router(nemesis)).await {
+        error!("The nemesis API stopped: {}", e);
+    }
+}
+
+/// Carries out an operation.
+async fn invoke(
+    State(nemesis): State<Arc<Nemesis>>,
+    Json(operation): Json<Operation>,
+) -> Result<Json<Operation>, (StatusCode, String)> {
+    match nemesis.invoke(operation).await {
+        Ok(operation) => Ok(Json(operation)),
+        Err(e @ NemesisError::Invalid(_)) => Err((StatusCode::BAD_REQUEST, e.to_string())),
+        Err(e @ NemesisError::Failed(_)) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::config::ClockSkew;
+    use crate::interceptor_state::{InterceptorState, LinkRule};
+    use crate::nemesis::{invoke, Nemesis, NemesisNode, Operation, OperationType};
+    use crate::packet_timeline::PacketTimeline;
+    use axum::extract::State;
+    use axum::http::StatusCode;
+    use axum::Json;
+    use bollard::Docker;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn nemesis(clock_directory: Option<&str>) -> Arc<Nemesis> {
+        let state = Arc::new(InterceptorState::new(Arc::new(PacketTimeline::new(10))));
+        for from in 0..3u16 {
+            for to in (0..3u16).filter(|to| *to != from) {
+                state.register_link(60000 + from, 60000 + to, None);
+            }
+        }
+        let nodes = (0..3)
+            .map(|id| NemesisNode {
+                node_id: id,
+                port: 60000 + id as u16,
+                container_id: format!("validator_{}", id),
+                clock: clock_directory.map(|directory| {
+                    (
+                        format!("{}/validator_{}", directory, id),
+                        ClockSkew::default(),
+                    )
+                }),
+            })
+            .collect();
+        Arc::new(Nemesis::new(
+            state,
+            Docker::connect_with_local_defaults().unwrap(),
+            nodes,
+        ))
+    }
+
+    fn operation(f: &str, value: serde_json::Value) -> Json<Operation> {
+        Json(
+            serde_json::from_value(
+                json!({ "type": "invoke", "f": f, "value": value, "process": "nemesis" }),
+            )
+            .unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn partitions_from_grudges_and_components() {
+        let nemesis = nemesis(None);
+        let Json(completed) = invoke(
+            State(nemesis.clone()),
+            operation("start-partition", json!([[0, 1], ["n2"]])),
+        )
+        .await
+        .unwrap();
+        assert_eq!(completed.kind, OperationType::Info);
+        assert_eq!(completed.extra["process"], "nemesis");
+        let cut_links = vec![
+            (60002, 60000),
+            (60002, 60001),
+            (60000, 60002),
+            (60001, 60002),
+        ];
+        assert_eq!(completed.value, json!({ "cut_links": cut_links }));
+        assert_eq!(nemesis.state.one_way_partition().cut_links, cut_links);
+
+        invoke(
+            State(nemesis.clone()),
+            operation("start-partition", json!({ "1": [0] })),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            nemesis.state.one_way_partition().cut_links,
+            [(60000, 60001)]
+        );
+
+        invoke(
+            State(nemesis.clone()),
+            operation("stop-partition", json!(null)),
+        )
+        .await
+        .unwrap();
+        assert!(nemesis.state.one_way_partition().cut_links.is_empty());
+
+        for (f, value) in [
+            ("start-partition", json!([[0], [3]])),
+            ("start-partition", json!(7)),
+            ("pause-everything", json!(null)),
+        ] {
+            let error = invoke(State(nemesis.clone()), operation(f, value))
+                .await
+                .unwrap_err();
+            assert_eq!(error.0, StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn delays_and_heal() {
+        let nemesis = nemesis(None);
+        let Json(completed) = invoke(
+            State(nemesis.clone()),
+            operation("start-delay", json!({ "delay_ms": 200, "nodes": [2] })),
+        )
+        .await
+        .unwrap();
+        assert_eq!(completed.value, json!({ "delayed_links": 4 }));
+        let delayed = LinkRule {
+            delay_ms: 200,
+            drop_probability: 0.0,
+        };
+        assert_eq!(nemesis.state.link(60002, 60000).unwrap().rule(), delayed);
+        assert_eq!(
+            nemesis.state.link(60000, 60001).unwrap().rule(),
+            LinkRule::default()
+        );
+
+        let error = invoke(
+            State(nemesis.clone()),
+            operation(
+                "start-delay",
+                json!({ "delay_ms": 200, "drop_probability": 2 }),
+            ),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.0, StatusCode::BAD_REQUEST);
+
+        invoke(
+            State(nemesis.clone()),
+            operation("start-partition", json!([[0], [1, 2]])),
+        )
+        .await
+        .unwrap();
+        // Without frozen nodes and changeable clocks, healing does not need Docker or the clock files
+        invoke(State(nemesis.clone()), operation("heal", json!(null)))
+            .await
+            .unwrap();
+        assert!(nemesis.state.one_way_partition().cut_links.is_empty());
+        assert_eq!(
+            nemesis.state.link(60002, 60000).unwrap().rule(),
+            LinkRule::default()
+        );
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn clocks_are_bumped_and_reset() {
+        let error = invoke(
+            State(nemesis(None)),
+            operation("bump-clock", json!({ "0": 1000 })),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.0, StatusCode::BAD_REQUEST);
+
+        let directory = std::env::temp_dir().join(format!("nemesis_clocks_{}", std::process::id()));
+        let nemesis = nemesis(directory.to_str());
+        let clock =
+            |id: u32| std::fs::read_to_string(directory.join(format!("validator_{}", id))).unwrap();
+        invoke(
+            State(nemesis.clone()),
+            operation("bump-clock", json!({ "0": 1500, "n2": -30000 })),
+        )
+        .await
+        .unwrap();
+        let Json(completed) = invoke(
+            State(nemesis.clone()),
+            operation("bump-clock", json!({ "0": 1500 })),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            completed.value,
+            json!({ "clock_offsets_ms": { "0": 3000 } })
+        );
+        assert_eq!(clock(0), "+3");
+        assert_eq!(clock(2), "-30");
+
+        invoke(State(nemesis.clone()), operation("reset-clock", json!([2])))
+            .await
+            .unwrap();
+        assert_eq!(clock(2), "+0");
+        assert_eq!(clock(0), "+3");
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
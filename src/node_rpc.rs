@@ -0,0 +1,135 @@
+//! This module is responsible for making JSON-RPC requests to the rippled nodes.
+
+use crate::address;
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Struct that represents a client for the JSON-RPC port of a single node.
+#[derive(Debug, Clone)]
+pub struct NodeRpcClient {
+    /// The host of the node: an IPv4 or IPv6 address, or a hostname.
+    pub host: String,
+    /// The port where the node listens for RPC requests.
+    pub port: u16,
+}
+
+impl NodeRpcClient {
+    /// Initializes a new NodeRpcClient.
+    ///
+    /// # Parameters
+    /// * 'host' - the IPv4 or IPv6 address or hostname of the node.
+    /// * 'port' - the port where the node listens for RPC requests.
+    pub fn new(host: String, port: u16) -> Self {
+        Self { host, port }
+    }
+
+    /// Calls an RPC method on the node and returns the `result` object of the response.
+    ///
+    /// # Parameters
+    /// * 'method' - the name of the method, e.g. 'server_info'.
+    /// * 'params' - the parameters of the method as a JSON object.
+    pub async fn request(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> Result<Value, Box<dyn std::error::Error>> {
+        let body = json!({ "method": method, "params": [params] }).to_string();
+        let request = Self::format_http_request(self.host.as_str(), self.port, &body);
+
+        let mut tcp_stream = address::connect(&self.host, self.port).await?;
+        tcp_stream.write_all(request.as_bytes()).await?;
+
+        let mut response = Vec::new();
+        tcp_stream.read_to_end(&mut response).await?;
+
+        let response_body = Self::parse_http_response(&response)?;
+        let json: Value = serde_json::from_slice(response_body)?;
+        Ok(json["result"].clone())
+    }
+
+    /// Formats an HTTP request that posts the JSON body to the root of the node.
+    ///
+    /// # Parameters
+    /// * 'host' - the host of the node, used for the host header.
+    /// * 'port' - the RPC port of the node, used for the host header.
+    /// * 'body' - the JSON body of the request.
+    fn format_http_request(host: &str, port: u16, body: &str) -> String {
+        format!(
+            "\
+            POST / HTTP/1.1\r\n\
+            Host: {}\r\n\
+            Content-Type: application/json\r\n\
+            Content-Length: {}\r\n\
+            Connection: close\r\n\
+            \r\n\
+            {}",
+            address::host_port(host, port),
+            body.len(),
+            body
+        )
+    }
+
+    /// Parses an HTTP response and returns its body.
+    ///
+    /// # Parameters
+    /// * 'response' - the full HTTP response.
+    fn parse_http_response(response: &[u8]) -> Result<&[u8], Box<dyn std::error::Error>> {
+        let mut headers = [httparse::EMPTY_HEADER; 32];
+        let mut parsed = httparse::Response::new(&mut headers);
+        let header_length = match parsed.parse(response)? {
+            httparse::Status::Complete(length) => length,
+            httparse::Status::Partial => return Err("Received a partial HTTP response".into()),
+        };
+
+        match parsed.code {
+            Some(200) => Ok(&response[header_length..]),
+            code => Err(format!(
+                "RPC request failed with status code {:?}: {}",
+                code,
+                String::from_utf8_lossy(&response[header_length..]).trim()
+            )
+            .into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::node_rpc::NodeRpcClient;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn format_http_request_test() {
+        let expected = String::from(
+            "\
+            POST / HTTP/1.1\r\n\
+            Host: 127.0.0.1:63000\r\n\
+            Content-Type: application/json\r\n\
+            Content-Length: 2\r\n\
+            Connection: close\r\n\
+            \r\n\
+            {}",
+        );
+        assert_eq!(
+            expected,
+            NodeRpcClient::format_http_request("127.0.0.1", 63000, "{}")
+        );
+        assert!(NodeRpcClient::format_http_request("::1", 63000, "{}")
+            .contains("Host: [::1]:63000\r\n"));
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_http_response_ok() {
+        let response = b"HTTP/1.1 200 OK\r\nContent-Length: 13\r\n\r\n{\"result\":{}}";
+        let body = NodeRpcClient::parse_http_response(response).unwrap();
+        assert_eq!(body, b"{\"result\":{}}");
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_http_response_error_status() {
+        let response = b"HTTP/1.1 403 Forbidden\r\n\r\nForbidden";
+        assert!(NodeRpcClient::parse_http_response(response).is_err());
+    }
+}
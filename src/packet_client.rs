@@ -1,38 +1,229 @@
 //! This module is responsible for making and handling requests to the controller.
 
-use crate::packet_client::proto::{Config, GetConfig, PacketAck};
-use log::{debug, info};
+use crate::action::{
+    LEGACY_PROTO_VERSION, PROTO_VERSION, SUPPORTED_ACTIONS, TRUNCATION_PROTO_VERSION,
+};
+use crate::address;
+use crate::config::{Compression, CompressionConfig, TruncationConfig};
+use crate::packet_client::proto::{
+    Channel, Config, EclipseCommand, EclipseSubscription, EndSignal, EndSubscription, GetConfig,
+    PacketAck, RunResult,
+};
+use crate::run_id::{RunId, RUN_ID_METADATA};
+use crate::telemetry;
+use bytes::Bytes;
 use proto::packet_service_client::PacketServiceClient;
-use proto::{Packet, ValidatorNodeInfo};
+use proto::{Packet, ValidatorNodeInfo, VersionRequest};
+use sha2::{Digest, Sha256};
+use tonic::codec::CompressionEncoding;
+use tonic::Code;
+use tracing::{debug, info, warn};
 
 pub mod proto {
     tonic::include_proto!("packet");
 }
 
+/// The address of the controller if none is configured.
+pub const DEFAULT_CONTROLLER_ADDRESS: &str = "http://localhost:50051";
+
+/// Struct that represents the information about an intercepted message that is sent to the controller along with it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PacketMetadata {
+    /// The validation public key of the node where the message came from.
+    pub from_node: String,
+    /// The validation public key of the node where the message is sent to.
+    pub to_node: String,
+    /// The position of the message on its link, counting from 0.
+    pub sequence: u64,
+    /// The wall-clock time the message was read, in nanoseconds since the UNIX epoch.
+    pub capture_timestamp_ns: u64,
+    /// The traffic the message was intercepted from.
+    pub channel: Channel,
+    /// Whether a proposal or validation is signed by one of the nodes with a valid signature, None for other messages.
+    pub signature_valid: Option<bool>,
+}
+
 /// Struct that represents the object that is able to call the controller module.
 #[derive(Debug)]
 pub struct PacketClient {
     pub client: PacketServiceClient<tonic::transport::Channel>,
+    /// The protocol version agreed on with the controller.
+    proto_version: u32,
+    /// The actions the controller reported it can decide on.
+    controller_actions: Vec<String>,
+    /// The truncation of large messages, if they should be truncated.
+    truncation: Option<TruncationConfig>,
+    /// The compression of the intercepted messages and of the decisions of the controller.
+    compression: CompressionConfig,
+    /// The identifier of the run, attached to every request to the controller.
+    run_id: Option<RunId>,
 }
 
 impl PacketClient {
-    /// Initializes a new PacketClient that connects to the controller.
+    /// Initializes a new PacketClient that connects to the controller at the default address.
+    /// Until the version has been negotiated, the legacy protocol is used.
     pub async fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let client = PacketServiceClient::connect("http://[::1]:50051").await?;
-        Ok(Self { client })
+        Self::connect(DEFAULT_CONTROLLER_ADDRESS).await
+    }
+
+    /// Initializes a new PacketClient that connects to a controller.
+    /// Until the version has been negotiated, the legacy protocol is used.
+    ///
+    /// # Parameters
+    /// * 'address' - the address of the controller, e.g. "http://[::1]:50051", "[::1]:50051" or "controller:50051".
+    pub async fn connect(address: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let client = PacketServiceClient::connect(address::controller_uri(address)).await?;
+        Ok(Self::with_client(client))
+    }
+
+    /// Initializes a new PacketClient that only connects to a controller once it is used, and reconnects to it
+    /// after it became unreachable. Returns an error if the address is invalid.
+    ///
+    /// # Parameters
+    /// * 'address' - the address of the controller, e.g. "http://[::1]:50051", "[::1]:50051" or "controller:50051".
+    pub fn connect_lazy(address: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let channel = tonic::transport::Endpoint::from_shared(address::controller_uri(address))?
+            .connect_lazy();
+        Ok(Self::with_client(PacketServiceClient::new(channel)))
+    }
+
+    /// Initializes a new PacketClient with its own channel to the controller this client is connected to, which only
+    /// connects once it is used. The negotiated protocol version, the supported actions, the truncation, the
+    /// compression and the run ID are copied, such that the version does not have to be negotiated again. Returns an error if the
+    /// address is invalid.
+    ///
+    /// # Parameters
+    /// * 'address' - the address of the controller this client is connected to.
+    pub fn another_channel(&self, address: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut client = Self::connect_lazy(address)?;
+        client.proto_version = self.proto_version;
+        client.controller_actions = self.controller_actions.clone();
+        client.truncation = self.truncation.clone();
+        client.compression = self.compression;
+        client.run_id = self.run_id.clone();
+        Ok(client)
+    }
+
+    /// Initializes a new PacketClient that only connects to the controller at the default address once it is used.
+    #[cfg(test)]
+    pub fn lazy() -> Self {
+        Self::connect_lazy(DEFAULT_CONTROLLER_ADDRESS).unwrap()
+    }
+
+    /// Wraps a generated client, using the legacy protocol until the version has been negotiated.
+    ///
+    /// # Parameters
+    /// * 'client' - the generated client.
+    fn with_client(client: PacketServiceClient<tonic::transport::Channel>) -> Self {
+        Self {
+            client,
+            proto_version: LEGACY_PROTO_VERSION,
+            controller_actions: Vec::new(),
+            truncation: None,
+            compression: CompressionConfig::default(),
+            run_id: None,
+        }
+    }
+
+    /// Sets whether large messages are truncated when they are sent to the controller.
+    /// Messages are only truncated if the controller supports it, according to the negotiated protocol version.
+    ///
+    /// # Parameters
+    /// * 'truncation' - the truncation of large messages, or None to always send the full message.
+    pub fn set_truncation(&mut self, truncation: Option<TruncationConfig>) {
+        self.truncation = truncation;
+    }
+
+    /// Sets how the intercepted messages sent to the controller and its decisions are compressed. The other requests to
+    /// the controller are never compressed.
+    ///
+    /// # Parameters
+    /// * 'compression' - the compression per direction.
+    pub fn set_compression(&mut self, compression: CompressionConfig) {
+        self.compression = compression;
+    }
+
+    /// Sets the identifier of the run, which is attached to every request to the controller as the `x-run-id`
+    /// metadata, such that a controller serving several runs can tell them apart.
+    ///
+    /// # Parameters
+    /// * 'run_id' - the identifier of the run.
+    pub fn set_run_id(&mut self, run_id: RunId) {
+        self.run_id = Some(run_id);
+    }
+
+    /// Wraps a message in a request to the controller, with the run ID in its metadata if it is set.
+    ///
+    /// # Parameters
+    /// * 'message' - the message of the request.
+    pub fn request<T>(&self, message: T) -> tonic::Request<T> {
+        let mut request = tonic::Request::new(message);
+        if let Some(value) = self
+            .run_id
+            .as_ref()
+            .and_then(|run_id| run_id.0.parse().ok())
+        {
+            request.metadata_mut().insert(RUN_ID_METADATA, value);
+        }
+        request
+    }
+
+    /// Returns the protocol version agreed on with the controller.
+    pub fn proto_version(&self) -> u32 {
+        self.proto_version
+    }
+
+    /// Returns the actions the controller reported it can decide on.
+    pub fn controller_actions(&self) -> &[String] {
+        &self.controller_actions
+    }
+
+    /// Exchanges versions and supported actions with the controller, and agrees on the highest protocol version both support.
+    /// Controllers that do not implement the version request are assumed to use the legacy protocol.
+    /// Returns the agreed protocol version.
+    pub async fn negotiate_version(&mut self) -> Result<u32, Box<dyn std::error::Error>> {
+        let request = self.request(VersionRequest {
+            interceptor_version: env!("CARGO_PKG_VERSION").to_string(),
+            proto_version: PROTO_VERSION,
+            supported_actions: SUPPORTED_ACTIONS.iter().map(|a| a.to_string()).collect(),
+        });
+        let response = match self.client.get_version(request).await {
+            Ok(response) => response.into_inner(),
+            Err(status) if status.code() == Code::Unimplemented => {
+                warn!("The controller does not support version negotiation, using the legacy protocol");
+                self.proto_version = LEGACY_PROTO_VERSION;
+                return Ok(self.proto_version);
+            }
+            Err(status) => return Err(status.into()),
+        };
+        info!("Response: {:?}", response);
+
+        if response.proto_version < LEGACY_PROTO_VERSION {
+            return Err(format!(
+                "Controller uses unsupported protocol version {}",
+                response.proto_version
+            )
+            .into());
+        }
+        self.proto_version = response.proto_version.min(PROTO_VERSION);
+        self.controller_actions = response.supported_actions;
+        Ok(self.proto_version)
     }
 
     /// Sends an intercepted message to the controller, asking for an action.
+    /// Large messages are truncated if configured, and sent again in full if the controller asks for it.
     ///
     /// # Parameters
     /// * 'packet_data' - the data of the intercepted message.
     /// * 'packet_from_port' - the port of the node where the message came from.
     /// * 'packet_to_port' - the port of the node where the message is sent to.
+    /// * 'metadata' - the identities of the nodes, the sequence number and the capture time of the message.
     pub async fn send_packet(
         &mut self,
-        packet_data: Vec<u8>,
+        packet_data: Bytes,
         packet_from_port: u32,
         packet_to_port: u32,
+        metadata: &PacketMetadata,
     ) -> Result<PacketAck, Box<dyn std::error::Error>> {
         if packet_data.is_empty() {
             return Err("Packet data is empty".into());
@@ -48,15 +239,35 @@ impl PacketClient {
             port => port,
         };
 
-        let packet = Packet {
-            data: packet_data.clone(),
-            from_port: packet_from_port,
-            to_port: packet_to_port,
-        };
-
-        let request = tonic::Request::new(packet);
+        let preview_bytes = self
+            .truncation
+            .as_ref()
+            .filter(|truncation| {
+                self.proto_version >= TRUNCATION_PROTO_VERSION
+                    && packet_data.len() > truncation.threshold_bytes
+            })
+            .map(|truncation| truncation.preview_bytes);
+
+        let packet = Self::build_packet(
+            &packet_data,
+            packet_from_port,
+            packet_to_port,
+            metadata,
+            preview_bytes,
+        );
+        let mut response = self.request_action(packet).await?;
+        if preview_bytes.is_some() && response.needs_full_payload {
+            debug!("Controller requested the full payload of a truncated packet");
+            let packet = Self::build_packet(
+                &packet_data,
+                packet_from_port,
+                packet_to_port,
+                metadata,
+                None,
+            );
+            response = self.request_action(packet).await?;
+        }
 
-        let response = self.client.send_packet(request).await?.into_inner(); // we send to controller and are waiting for the response
         debug!(
             "action: {}, from_port: {}, to_port: {}, original_data: {}, possibly_mutated_data: {}",
             response.action,
@@ -69,6 +280,108 @@ impl PacketClient {
         Ok(response)
     }
 
+    /// Sends the first bytes, the SHA-256 digest and the length of a message that was forwarded without a decision to
+    /// the controller, e.g. because it was too large to be held whole. The action of the controller is ignored.
+    ///
+    /// # Parameters
+    /// * 'preview' - the first bytes of the message.
+    /// * 'digest' - the SHA-256 digest of the whole message.
+    /// * 'length' - the length of the whole message.
+    /// * 'packet_from_port' - the port of the node where the message came from.
+    /// * 'packet_to_port' - the port of the node where the message is sent to.
+    /// * 'metadata' - the identities of the nodes, the sequence number and the capture time of the message.
+    pub async fn send_preview(
+        &mut self,
+        preview: Bytes,
+        digest: Bytes,
+        length: u64,
+        packet_from_port: u32,
+        packet_to_port: u32,
+        metadata: &PacketMetadata,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if self.proto_version < TRUNCATION_PROTO_VERSION {
+            return Err(format!(
+                "Controller uses protocol version {}, which does not support truncated packets",
+                self.proto_version
+            )
+            .into());
+        }
+        let packet = Packet {
+            data: preview,
+            from_port: packet_from_port,
+            to_port: packet_to_port,
+            from_node: metadata.from_node.clone(),
+            to_node: metadata.to_node.clone(),
+            sequence: metadata.sequence,
+            capture_timestamp_ns: metadata.capture_timestamp_ns,
+            truncated: true,
+            digest,
+            length,
+            channel: metadata.channel as i32,
+            signature_valid: metadata.signature_valid,
+        };
+        self.request_action(packet).await.map(|_| ())
+    }
+
+    /// Builds the packet that is sent to the controller for an intercepted message.
+    ///
+    /// # Parameters
+    /// * 'packet_data' - the data of the intercepted message.
+    /// * 'packet_from_port' - the port of the node where the message came from.
+    /// * 'packet_to_port' - the port of the node where the message is sent to.
+    /// * 'metadata' - the identities of the nodes, the sequence number and the capture time of the message.
+    /// * 'preview_bytes' - the amount of bytes that are sent if the message should be truncated, or None to send it in full.
+    pub fn build_packet(
+        packet_data: &Bytes,
+        packet_from_port: u32,
+        packet_to_port: u32,
+        metadata: &PacketMetadata,
+        preview_bytes: Option<usize>,
+    ) -> Packet {
+        let (data, digest) = match preview_bytes {
+            Some(preview_bytes) => (
+                packet_data.slice(0..preview_bytes.min(packet_data.len())),
+                Bytes::copy_from_slice(&Sha256::digest(packet_data)),
+            ),
+            None => (packet_data.clone(), Bytes::new()),
+        };
+        Packet {
+            data,
+            from_port: packet_from_port,
+            to_port: packet_to_port,
+            from_node: metadata.from_node.clone(),
+            to_node: metadata.to_node.clone(),
+            sequence: metadata.sequence,
+            capture_timestamp_ns: metadata.capture_timestamp_ns,
+            truncated: preview_bytes.is_some(),
+            digest,
+            length: packet_data.len() as u64,
+            channel: metadata.channel as i32,
+            signature_valid: metadata.signature_valid,
+        }
+    }
+
+    /// Sends a packet to the controller and waits for its response.
+    ///
+    /// # Parameters
+    /// * 'packet' - the packet to be sent.
+    async fn request_action(
+        &mut self,
+        packet: Packet,
+    ) -> Result<PacketAck, Box<dyn std::error::Error>> {
+        let mut request = self.request(packet);
+        telemetry::inject_current_context(request.metadata_mut());
+
+        let mut client = self.client.clone();
+        if let Some(encoding) = encoding(self.compression.requests) {
+            client = client.send_compressed(encoding);
+        }
+        if let Some(encoding) = encoding(self.compression.responses) {
+            client = client.accept_compressed(encoding);
+        }
+        Ok(client.send_packet(request).await?.into_inner()) // we send to controller and are waiting for the response
+    }
+
     /// Sends the info of all ValidatorNodes to the controller.
     ///
     /// # Parameters
@@ -77,7 +390,7 @@ impl PacketClient {
         &mut self,
         validator_node_info_list: Vec<ValidatorNodeInfo>,
     ) -> Result<String, Box<dyn std::error::Error>> {
-        let request = tonic::Request::new(tokio_stream::iter(validator_node_info_list.into_iter()));
+        let request = self.request(tokio_stream::iter(validator_node_info_list.into_iter()));
         let response = self
             .client
             .send_validator_node_info(request)
@@ -90,12 +403,150 @@ impl PacketClient {
 
     /// Sends a request to the controller asking for the network configuration.
     pub async fn get_config(&mut self) -> Result<Config, Box<dyn std::error::Error>> {
-        let request = tonic::Request::new(GetConfig {});
+        let request = self.request(GetConfig {});
         let response = self.client.get_config(request).await?.into_inner();
         info!("Response: {:?}", response);
 
         Ok(response)
     }
+
+    /// Reports the summary of the run to the controller.
+    /// Controllers that do not implement the report are skipped with a warning.
+    ///
+    /// # Parameters
+    /// * 'result' - the summary of the run.
+    pub async fn report_run_result(
+        &mut self,
+        result: RunResult,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let request = self.request(result);
+        match self.client.report_run_result(request).await {
+            Ok(_) => Ok(()),
+            Err(status) if status.code() == Code::Unimplemented => {
+                warn!("The controller does not support run result reports");
+                Ok(())
+            }
+            Err(status) => Err(status.into()),
+        }
+    }
+
+    /// Subscribes to the eclipse commands of the controller.
+    /// Returns None if the controller does not implement them.
+    pub async fn subscribe_eclipse(
+        &mut self,
+    ) -> Result<Option<tonic::Streaming<EclipseCommand>>, tonic::Status> {
+        let request = self.request(EclipseSubscription {});
+        match self.client.subscribe_eclipse(request).await {
+            Ok(response) => Ok(Some(response.into_inner())),
+            Err(status) if status.code() == Code::Unimplemented => {
+                debug!("The controller does not send eclipse commands");
+                Ok(None)
+            }
+            Err(status) => Err(status),
+        }
+    }
+
+    /// Subscribes to the signal the controller sends once its scenario is complete.
+    /// Returns None if the controller does not implement it.
+    pub async fn subscribe_end(
+        &mut self,
+    ) -> Result<Option<tonic::Streaming<EndSignal>>, tonic::Status> {
+        let request = self.request(EndSubscription {});
+        match self.client.subscribe_end(request).await {
+            Ok(response) => Ok(Some(response.into_inner())),
+            Err(status) if status.code() == Code::Unimplemented => {
+                debug!("The controller does not signal the end of its scenario");
+                Ok(None)
+            }
+            Err(status) => Err(status),
+        }
+    }
+}
+
+/// Returns the encoding of a compression, or None if the messages are not compressed.
+///
+/// # Parameters
+/// * 'compression' - the compression.
+fn encoding(compression: Compression) -> Option<CompressionEncoding> {
+    match compression {
+        Compression::None => None,
+        Compression::Gzip => Some(CompressionEncoding::Gzip),
+        Compression::Zstd => Some(CompressionEncoding::Zstd),
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::config::{Compression, CompressionConfig};
+    use crate::packet_client::{encoding, PacketClient, PacketMetadata};
+    use crate::run_id::{RunId, RUN_ID_METADATA};
+    use bytes::Bytes;
+    use sha2::{Digest, Sha256};
+    use tonic::codec::CompressionEncoding;
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn requests_carry_the_run_id() {
+        let mut client = PacketClient::lazy();
+        assert!(client.request(()).metadata().get(RUN_ID_METADATA).is_none());
+
+        client.set_run_id(RunId("20240101-120000-1a2b3c4d".to_string()));
+        let channel = client.another_channel("http://[::1]:50051").unwrap();
+        for client in [&client, &channel] {
+            let request = client.request(());
+            assert_eq!(
+                request.metadata().get(RUN_ID_METADATA).unwrap(),
+                "20240101-120000-1a2b3c4d"
+            );
+        }
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn build_full_packet() {
+        let data = Bytes::from(vec![7u8; 100]);
+        let packet =
+            PacketClient::build_packet(&data, 60000, 60001, &PacketMetadata::default(), None);
+
+        assert_eq!(packet.data, data);
+        assert!(!packet.truncated);
+        assert!(packet.digest.is_empty());
+        assert_eq!(packet.length, 100);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn compression_is_copied_to_another_channel() {
+        assert_eq!(encoding(Compression::None), None);
+        assert_eq!(encoding(Compression::Gzip), Some(CompressionEncoding::Gzip));
+        assert_eq!(encoding(Compression::Zstd), Some(CompressionEncoding::Zstd));
+
+        let compression = CompressionConfig {
+            requests: Compression::Zstd,
+            responses: Compression::Gzip,
+        };
+        let mut client = PacketClient::lazy();
+        client.set_compression(compression);
+        let channel = client.another_channel("http://[::1]:50051").unwrap();
+        assert_eq!(channel.compression, compression);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn build_truncated_packet() {
+        let data = Bytes::from((0..100u8).collect::<Vec<u8>>());
+        let packet =
+            PacketClient::build_packet(&data, 60000, 60001, &PacketMetadata::default(), Some(10));
+
+        assert_eq!(packet.data, data.slice(0..10));
+        assert!(packet.truncated);
+        assert_eq!(packet.digest.as_ref(), Sha256::digest(&data).as_slice());
+        assert_eq!(packet.length, 100);
+
+        let packet =
+            PacketClient::build_packet(&data, 60000, 60001, &PacketMetadata::default(), Some(1000));
+        assert_eq!(packet.data, data);
+    }
 }
 
 // Note: these tests require the controller to be running
@@ -118,13 +569,14 @@ mod integration_tests_grpc {
                 ws_admin_port: 62000,
                 rpc_port: 63000,
                 status: "active".to_string(),
-                validation_key: "READ SOIL DASH FUND ISLE LEN SOD OUT MACE ERIC DRAG MILT"
-                    .to_string(),
                 validation_private_key: "paAgnNZ9NaKTACGT3dGBV2eNHRxXNo8hRhNQNEWRJ23m5isp93t"
                     .to_string(),
                 validation_public_key: "n9KjTKEaHJ12Kuon5PDZ7fQAo5ExZ6cKH4h3L8q6m9YhoYqeBDho"
                     .to_string(),
                 validation_seed: "shM8uxbqE5g43G3VwKt6TM2pLvFan".to_string(),
+                role: "validator".to_string(),
+                ip_addresses: vec![],
+                dns_names: vec![],
             },
             ValidatorNodeInfo {
                 peer_port: 60001,
@@ -132,13 +584,14 @@ mod integration_tests_grpc {
                 ws_admin_port: 62001,
                 rpc_port: 63001,
                 status: "active".to_string(),
-                validation_key: "DEAR SOIL DASH FUND ISLE LEN SOD OUT MACE ERIC DRAG MILT"
-                    .to_string(),
                 validation_private_key: "qaAgnNZ9NaKTACGT3dGBV2eNHRxXNo8hRhNQNEWRJ23m5isp93t"
                     .to_string(),
                 validation_public_key: "N9KjTKEaHJ12Kuon5PDZ7fQAo5ExZ6cKH4h3L8q6m9YhoYqeBDho"
                     .to_string(),
                 validation_seed: "ShM8uxbqE5g43G3VwKt6TM2pLvFan".to_string(),
+                role: "validator".to_string(),
+                ip_addresses: vec![],
+                dns_names: vec![],
             },
         ];
 
@@ -162,7 +615,21 @@ mod integration_tests_grpc {
         ];
 
         // Call the async function and obtain the result
-        let result = client.send_packet(packet_data, 60000, 60001).await;
+        let result = client
+            .send_packet(
+                Bytes::from(packet_data),
+                60000,
+                60001,
+                &PacketMetadata {
+                    from_node: "n9KjTKEaHJ12Kuon5PDZ7fQAo5ExZ6cKH4h3L8q6m9YhoYqeBDho".to_string(),
+                    to_node: "N9KjTKEaHJ12Kuon5PDZ7fQAo5ExZ6cKH4h3L8q6m9YhoYqeBDho".to_string(),
+                    sequence: 0,
+                    capture_timestamp_ns: 1_700_000_000_000_000_000,
+                    channel: Channel::Peer,
+                    signature_valid: Some(true),
+                },
+            )
+            .await;
 
         // Assert that the result is Ok
         assert!(
@@ -179,7 +646,9 @@ mod integration_tests_grpc {
         let packet_data: Vec<u8> = vec![]; // Empty data
 
         // Call the async function and obtain the result
-        let result = client.send_packet(packet_data, 2, 3).await;
+        let result = client
+            .send_packet(Bytes::from(packet_data), 2, 3, &PacketMetadata::default())
+            .await;
 
         // Assert that the result is not Ok (i.e., Err)
         assert!(result.is_err());
@@ -194,7 +663,14 @@ mod integration_tests_grpc {
         let packet_from_port: u32 = u32::MAX;
 
         // Call the async function and obtain the result
-        let result = client.send_packet(packet_data, packet_from_port, 3).await;
+        let result = client
+            .send_packet(
+                Bytes::from(packet_data),
+                packet_from_port,
+                3,
+                &PacketMetadata::default(),
+            )
+            .await;
 
         // Assert that the result is not Ok (i.e., Err)
         assert!(result.is_err());
@@ -209,7 +685,14 @@ mod integration_tests_grpc {
         let packet_to_port: u32 = u32::MAX;
 
         // Call the async function and obtain the result
-        let result = client.send_packet(packet_data, 2, packet_to_port).await;
+        let result = client
+            .send_packet(
+                Bytes::from(packet_data),
+                2,
+                packet_to_port,
+                &PacketMetadata::default(),
+            )
+            .await;
 
         // Assert that the result is not Ok (i.e., Err)
         assert!(result.is_err());
@@ -225,12 +708,14 @@ mod integration_tests_grpc {
             ws_admin_port: 62000,
             rpc_port: 63000,
             status: "active".to_string(),
-            validation_key: "READ SOIL DASH FUND ISLE LEN SOD OUT MACE ERIC DRAG MILT".to_string(),
             validation_private_key: "paAgnNZ9NaKTACGT3dGBV2eNHRxXNo8hRhNQNEWRJ23m5isp93t"
                 .to_string(),
             validation_public_key: "n9KjTKEaHJ12Kuon5PDZ7fQAo5ExZ6cKH4h3L8q6m9YhoYqeBDho"
                 .to_string(),
             validation_seed: "shM8uxbqE5g43G3VwKt6TM2pLvFan".to_string(),
+            role: "validator".to_string(),
+            ip_addresses: vec![],
+            dns_names: vec![],
         }];
         let result = client
             .send_validator_node_info(validator_node_info_list)
@@ -243,6 +728,17 @@ mod integration_tests_grpc {
         assert_eq!(result.unwrap(), "Received validator node info".to_string());
     }
 
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn negotiate_version_ok() {
+        let mut client = setup().await;
+        let proto_version = client.negotiate_version().await.unwrap();
+
+        assert!(proto_version >= LEGACY_PROTO_VERSION);
+        assert!(proto_version <= PROTO_VERSION);
+        assert_eq!(client.proto_version(), proto_version);
+    }
+
     #[tokio::test]
     // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
     async fn get_config_ok() {
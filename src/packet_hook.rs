@@ -0,0 +1,299 @@
+//! This module is responsible for packet hooks: custom logic, implemented in Rust, that decides on messages at a defined
+//! point of their handling and is told about the changes of the links.
+//!
+//! Hooks are registered on the `InterceptorState` for a stage. The hooks of the `BeforeController` stage decide on a
+//! message before the controller is asked, and can change the message the controller sees or drop it, in which case
+//! the controller is not asked. The hooks of the `AfterController` stage decide on the message as the controller left
+//! it. Within a stage, hooks are executed in the order they were registered, each on the decision of the previous one.
+//! The configured WASM plugins are `AfterController` hooks, registered at startup.
+//!
+//! The interceptor has no library target, so hooks are implemented and registered inside this crate, in `main.rs`.
+
+use crate::action::Decision;
+use crate::event_bus::{Event, EventKind};
+use crate::interceptor_state::InterceptorState;
+use crate::message_type::MessageType;
+use async_trait::async_trait;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{error, warn};
+
+/// The error a hook fails with, after which its decision is ignored.
+pub type HookError = Box<dyn Error + Send + Sync>;
+
+/// Enum that represents the point in the handling of a message at which a hook is executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookStage {
+    /// Before the controller is asked, or before the message is forwarded if the controller is not asked.
+    BeforeController,
+    /// After the decision of the controller, before the local mutation rules and the rule of the link are applied.
+    AfterController,
+}
+
+/// Struct that represents the message a hook decides on, apart from its data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketContext {
+    /// The port of the peer where the message came from.
+    pub from_port: u16,
+    /// The port of the peer the message is sent to.
+    pub to_port: u16,
+    /// The type of the message.
+    pub message_type: MessageType,
+    /// The position of the message on its link.
+    pub sequence: u64,
+}
+
+/// Trait for custom logic that decides on messages and is told about the changes of the links.
+#[async_trait]
+pub trait PacketHook: Send + Sync + fmt::Debug {
+    /// Returns the name of the hook, which is used in logs.
+    fn name(&self) -> &str;
+
+    /// Decides on a message. Returns the decision made so far to leave the message as it is, or a decision that
+    /// changes it, e.g. `Decision::dropped`. If the hook fails, its decision is ignored.
+    ///
+    /// # Parameters
+    /// * 'context' - the link, type and sequence of the message.
+    /// * 'decision' - the decision made for the message so far, which contains the message as it is sent.
+    async fn on_packet(
+        &self,
+        context: &PacketContext,
+        decision: Decision,
+    ) -> Result<Decision, HookError>;
+
+    /// Is told that a link connected, dropped or became idle. Does nothing by default.
+    ///
+    /// # Parameters
+    /// * 'event' - the event of the link.
+    async fn on_link_event(&self, _event: &Event) {}
+}
+
+/// Executes the hooks of a stage in order, each on the decision of the previous one.
+/// Drops and mutations are published as events, failing hooks are counted and ignored.
+///
+/// # Parameters
+/// * 'stage' - the stage whose hooks are executed.
+/// * 'decision' - the decision made for the message so far.
+/// * 'context' - the link, type and sequence of the message.
+/// * 'state' - the runtime state, containing the hooks and the event bus.
+pub async fn run_hooks(
+    stage: HookStage,
+    mut decision: Decision,
+    context: &PacketContext,
+    state: &InterceptorState,
+) -> Decision {
+    for hook in state.hooks(stage) {
+        if decision.send_amount == 0 {
+            break;
+        }
+        let original_data = decision.data.clone();
+        match hook.on_packet(context, decision.clone()).await {
+            Ok(hook_decision) => {
+                if hook_decision.send_amount == 0 {
+                    state.events.emit(EventKind::packet_dropped(
+                        context.from_port,
+                        context.to_port,
+                        context.message_type,
+                        Some(context.sequence),
+                        "hook",
+                    ));
+                } else if hook_decision.data != original_data {
+                    state.events.emit(EventKind::MutationApplied {
+                        from_port: context.from_port,
+                        to_port: context.to_port,
+                        message_type: context.message_type.to_string(),
+                        sequence: context.sequence,
+                        original_size: original_data.len(),
+                        mutated_size: hook_decision.data.len(),
+                    });
+                }
+                decision = hook_decision;
+            }
+            Err(e) => {
+                error!(
+                    "Hook {} failed on {}, ignoring its decision: {}",
+                    hook.name(),
+                    context.message_type,
+                    e
+                );
+                state.statistics.count_error("hook_failed", 1);
+            }
+        }
+    }
+    decision
+}
+
+/// Tells all hooks about the changes of the links, until the event bus is closed.
+///
+/// # Parameters
+/// * 'events' - the receiver of the events, subscribed before the links are started.
+/// * 'state' - the runtime state, containing the hooks.
+pub async fn dispatch_link_events(
+    mut events: broadcast::Receiver<Arc<Event>>,
+    state: Arc<InterceptorState>,
+) {
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(missed)) => {
+                warn!("Hooks missed {} events", missed);
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        if !matches!(
+            event.kind,
+            EventKind::LinkConnected { .. }
+                | EventKind::LinkDropped { .. }
+                | EventKind::LinkIdle { .. }
+        ) {
+            continue;
+        }
+        for stage in [HookStage::BeforeController, HookStage::AfterController] {
+            for hook in state.hooks(stage) {
+                hook.on_link_event(&event).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::action::Decision;
+    use crate::event_bus::{Event, EventKind};
+    use crate::interceptor_state::InterceptorState;
+    use crate::message_type::MessageType;
+    use crate::packet_hook::{
+        dispatch_link_events, run_hooks, HookError, HookStage, PacketContext, PacketHook,
+    };
+    use crate::packet_timeline::PacketTimeline;
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// A hook that appends a byte to every message, drops messages of one type, and records the link events.
+    #[derive(Debug, Default)]
+    struct TestHook {
+        byte: u8,
+        dropped_type: Option<MessageType>,
+        link_events: Mutex<Vec<EventKind>>,
+    }
+
+    #[async_trait]
+    impl PacketHook for TestHook {
+        fn name(&self) -> &str {
+            "test"
+        }
+
+        async fn on_packet(
+            &self,
+            context: &PacketContext,
+            decision: Decision,
+        ) -> Result<Decision, HookError> {
+            if Some(context.message_type) == self.dropped_type {
+                return Ok(Decision::dropped(decision.data));
+            }
+            if self.byte == 0 {
+                return Err("zero byte".into());
+            }
+            let mut data = decision.data.to_vec();
+            data.push(self.byte);
+            Ok(Decision {
+                data: Bytes::from(data),
+                ..decision
+            })
+        }
+
+        async fn on_link_event(&self, event: &Event) {
+            self.link_events.lock().unwrap().push(event.kind.clone());
+        }
+    }
+
+    fn context(message_type: MessageType) -> PacketContext {
+        PacketContext {
+            from_port: 60000,
+            to_port: 60001,
+            message_type,
+            sequence: 0,
+        }
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn hooks_run_in_order_per_stage() {
+        let state = InterceptorState::new(Arc::new(PacketTimeline::new(10)));
+        let hook = |byte, dropped_type| {
+            Arc::new(TestHook {
+                byte,
+                dropped_type,
+                ..TestHook::default()
+            })
+        };
+        state.register_hook(HookStage::AfterController, hook(2, None));
+        state.register_hook(HookStage::BeforeController, hook(1, None));
+        state.register_hook(HookStage::AfterController, hook(0, None));
+        state.register_hook(
+            HookStage::AfterController,
+            hook(3, Some(MessageType::Validation)),
+        );
+        let decision = Decision {
+            delay: Duration::from_millis(10),
+            ..Decision::forward(Bytes::from_static(&[0]))
+        };
+
+        let before = run_hooks(
+            HookStage::BeforeController,
+            decision.clone(),
+            &context(MessageType::Ping),
+            &state,
+        )
+        .await;
+        assert_eq!(before.data, Bytes::from_static(&[0, 1]));
+        // The failing hook is ignored
+        let after = run_hooks(
+            HookStage::AfterController,
+            decision.clone(),
+            &context(MessageType::Ping),
+            &state,
+        )
+        .await;
+        assert_eq!(after.data, Bytes::from_static(&[0, 2, 3]));
+        assert_eq!(after.delay, Duration::from_millis(10));
+        let dropped = run_hooks(
+            HookStage::AfterController,
+            decision,
+            &context(MessageType::Validation),
+            &state,
+        )
+        .await;
+        assert_eq!(dropped.send_amount, 0);
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn hooks_receive_link_events() {
+        let state = Arc::new(InterceptorState::new(Arc::new(PacketTimeline::new(10))));
+        let hook = Arc::new(TestHook::default());
+        state.register_hook(HookStage::BeforeController, hook.clone());
+        tokio::spawn(dispatch_link_events(
+            state.events.subscribe(),
+            state.clone(),
+        ));
+
+        state
+            .events
+            .emit(EventKind::PartitionChanged { partitions: vec![] });
+        let dropped = EventKind::LinkDropped {
+            from_port: 60000,
+            to_port: 60001,
+            reason: "closed".to_string(),
+        };
+        state.events.emit(dropped.clone());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(*hook.link_events.lock().unwrap(), vec![dropped]);
+    }
+}
@@ -0,0 +1,232 @@
+//! This module is responsible for keeping track of the most recently intercepted messages,
+//! such that they can be included when reporting on the state of the network.
+
+use crate::message_type::MessageType;
+use crate::phase::Phase;
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The default amount of messages that are remembered by the timeline.
+pub const DEFAULT_TIMELINE_CAPACITY: usize = 10_000;
+
+/// Struct that represents a message that was intercepted and handled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PacketRecord {
+    /// The moment the message was read.
+    pub timestamp: DateTime<Utc>,
+    /// The port of the peer where the message came from.
+    pub from_port: u16,
+    /// The port of the peer the message was sent to.
+    pub to_port: u16,
+    /// The position of the message on its link.
+    pub sequence: u64,
+    /// The type of the message.
+    pub message_type: MessageType,
+    /// The ledger sequence contained in the message, for the message types that contain one.
+    pub ledger_sequence: Option<u32>,
+    /// The consensus round the message was read in, None until the first validation was read.
+    pub round: Option<u32>,
+    /// The phase of the run the message was handled in.
+    pub phase: Phase,
+    /// The size of the message in bytes, including the header.
+    pub size: usize,
+    /// The hex-encoded SHA-256 hash of the message as it was read, including the header.
+    pub hash: String,
+    /// The size of the message as it was sent in bytes, which differs from 'size' if the message was mutated.
+    pub sent_size: usize,
+    /// The delay in ms the controller applied to the message.
+    pub action: u32,
+    /// The amount of times the message was sent, 0 means it was dropped.
+    pub send_amount: u32,
+    /// The time it took the controller to decide on the action, None if the controller was not asked.
+    pub controller_latency: Option<Duration>,
+    /// The time between reading the message and queueing it to be written,
+    /// including the controller decision and any delay.
+    pub latency: Duration,
+}
+
+impl PacketRecord {
+    /// Returns the name of the action taken on the message: 'drop', 'duplicate', 'delay' or 'forward'.
+    pub fn action_name(&self) -> &'static str {
+        match self.send_amount {
+            0 => "drop",
+            1 if self.action > 0 => "delay",
+            1 => "forward",
+            _ => "duplicate",
+        }
+    }
+}
+
+/// A forwarded validation from port 60000 to 60001, which tests override the fields they need of.
+#[cfg(test)]
+impl Default for PacketRecord {
+    fn default() -> Self {
+        Self {
+            timestamp: Utc::now(),
+            from_port: 60000,
+            to_port: 60001,
+            sequence: 0,
+            message_type: MessageType::Validation,
+            ledger_sequence: None,
+            round: None,
+            phase: Phase::Fault,
+            size: 50,
+            hash: String::new(),
+            sent_size: 50,
+            action: 0,
+            send_amount: 1,
+            controller_latency: None,
+            latency: Duration::ZERO,
+        }
+    }
+}
+
+impl fmt::Display for PacketRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} -> {} {} ({} bytes) action: {}, send_amount: {}, latency: {:?}",
+            self.timestamp.format("%H:%M:%S%.3f"),
+            self.from_port,
+            self.to_port,
+            self.message_type,
+            self.size,
+            self.action,
+            self.send_amount,
+            self.latency
+        )
+    }
+}
+
+/// Struct that represents a bounded, thread-safe timeline of the most recently handled messages.
+#[derive(Debug)]
+pub struct PacketTimeline {
+    /// The maximum amount of records kept in the timeline.
+    capacity: usize,
+    /// The records in the order they were handled, oldest first.
+    records: Mutex<VecDeque<PacketRecord>>,
+}
+
+impl PacketTimeline {
+    /// Initializes a new PacketTimeline.
+    ///
+    /// # Parameters
+    /// * 'capacity' - the maximum amount of records kept, older records are discarded.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            records: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Adds a record to the timeline, discarding the oldest record if the timeline is full.
+    ///
+    /// # Parameters
+    /// * 'record' - the record to be added.
+    pub fn push(&self, record: PacketRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut records = self.records.lock().unwrap();
+        if records.len() == self.capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// Returns all records with a timestamp in the given range, oldest first.
+    ///
+    /// # Parameters
+    /// * 'from' - the start of the range, inclusive.
+    /// * 'to' - the end of the range, exclusive.
+    pub fn between(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<PacketRecord> {
+        self.records
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|record| record.timestamp >= from && record.timestamp < to)
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the `n` most recent records, oldest first.
+    ///
+    /// # Parameters
+    /// * 'n' - the maximum amount of records returned.
+    pub fn latest(&self, n: usize) -> Vec<PacketRecord> {
+        let records = self.records.lock().unwrap();
+        records
+            .iter()
+            .skip(records.len().saturating_sub(n))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::packet_timeline::{PacketRecord, PacketTimeline};
+    use chrono::{Duration as ChronoDuration, Utc};
+    use std::time::Duration;
+
+    fn record(from_port: u16) -> PacketRecord {
+        PacketRecord {
+            from_port,
+            size: 100,
+            sent_size: 100,
+            latency: Duration::from_millis(1),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn timeline_discards_oldest() {
+        let timeline = PacketTimeline::new(2);
+        timeline.push(record(1));
+        timeline.push(record(2));
+        timeline.push(record(3));
+
+        let latest = timeline.latest(10);
+        assert_eq!(latest.len(), 2);
+        assert_eq!(latest[0].from_port, 2);
+        assert_eq!(latest[1].from_port, 3);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn latest_returns_most_recent() {
+        let timeline = PacketTimeline::new(10);
+        for port in 0..5 {
+            timeline.push(record(port));
+        }
+
+        let latest = timeline.latest(2);
+        assert_eq!(latest.len(), 2);
+        assert_eq!(latest[0].from_port, 3);
+        assert_eq!(latest[1].from_port, 4);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn between_filters_on_timestamp() {
+        let timeline = PacketTimeline::new(10);
+        let start = Utc::now();
+        for port in 0..5 {
+            let mut packet = record(port);
+            packet.timestamp = start + ChronoDuration::seconds(port as i64);
+            timeline.push(packet);
+        }
+
+        let between = timeline.between(
+            start + ChronoDuration::seconds(1),
+            start + ChronoDuration::seconds(3),
+        );
+        assert_eq!(between.len(), 2);
+        assert_eq!(between[0].from_port, 1);
+        assert_eq!(between[1].from_port, 2);
+    }
+}
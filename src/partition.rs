@@ -0,0 +1,70 @@
+//! This module is responsible for one-way partitions: links that are broken in only one direction, e.g. the messages
+//! from A to B are dropped while the messages from B to A are delivered.
+//!
+//! Every direction of a connection has its own read and decision stage, so a direction is cut independently of the
+//! other one. The connections themselves stay open, and pings on a cut link are answered by the interceptor.
+
+use crate::interceptor_state::InterceptorState;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::info;
+
+/// Struct that represents a one-way partition of the network.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OneWayPartition {
+    /// The links whose messages are dropped, by the ports of the peer the messages come from and go to.
+    pub cut_links: Vec<(u16, u16)>,
+}
+
+impl OneWayPartition {
+    /// Returns whether the messages on a link are dropped by the partition.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer where the message came from.
+    /// * 'to_port' - the port of the peer the message is sent to.
+    pub fn cuts(&self, from_port: u16, to_port: u16) -> bool {
+        self.cut_links.contains(&(from_port, to_port))
+    }
+}
+
+/// Partitions the network one-way for a fixed period of the run, as configured locally.
+///
+/// # Parameters
+/// * 'partition' - the partition.
+/// * 'start_after' - how long after the links are started the partition starts.
+/// * 'duration' - how long the partition lasts, until the end of the run if None.
+/// * 'state' - the runtime state, where the partition is set.
+pub async fn run_scheduled(
+    partition: OneWayPartition,
+    start_after: Duration,
+    duration: Option<Duration>,
+    state: Arc<InterceptorState>,
+) {
+    let clock = state.clock();
+    clock.sleep(start_after).await;
+    info!("Cutting links one-way: {:?}", partition.cut_links);
+    state.set_one_way_partition(partition);
+    if let Some(duration) = duration {
+        clock.sleep(duration).await;
+        info!("Healing the one-way partition");
+        state.set_one_way_partition(OneWayPartition::default());
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::partition::OneWayPartition;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn partition_cuts_one_direction() {
+        let partition = OneWayPartition {
+            cut_links: vec![(60000, 60001)],
+        };
+        assert!(partition.cuts(60000, 60001));
+        assert!(!partition.cuts(60001, 60000));
+        assert!(!partition.cuts(60000, 60002));
+        assert!(!OneWayPartition::default().cuts(60000, 60001));
+    }
+}
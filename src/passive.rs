@@ -0,0 +1,108 @@
+//! This module is responsible for the passive observation mode, the control condition of experiments. In this mode
+//! every message is forwarded as soon as it is read: the controller is never asked, and no local rule, hook, partition,
+//! breakpoint or pause applies to it. Messages are decoded, recorded and counted only after they were queued to be
+//! written, such that the latency the interceptor adds is only reading and queueing them, which is measured per message.
+
+use crate::config::PassiveConfig;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Struct that represents the latency the interceptor added to the messages so far, as it is reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct AddedLatency {
+    /// The amount of messages that were forwarded.
+    pub messages: u64,
+    /// The latency in µs above which a message is counted as over budget.
+    pub budget_us: u64,
+    /// The mean latency in µs between reading a message and queueing it to be written.
+    pub mean_us: u64,
+    /// The highest latency in µs between reading a message and queueing it to be written.
+    pub max_us: u64,
+    /// The amount of messages of which the latency exceeded the budget.
+    pub over_budget: u64,
+}
+
+/// Struct that represents the measurement of the latency the interceptor adds in the passive observation mode.
+#[derive(Debug)]
+pub struct PassiveObserver {
+    budget: Duration,
+    messages: AtomicU64,
+    total_ns: AtomicU64,
+    max_ns: AtomicU64,
+    over_budget: AtomicU64,
+}
+
+impl PassiveObserver {
+    /// Initializes a new PassiveObserver that has not measured any messages.
+    ///
+    /// # Parameters
+    /// * 'config' - the configuration of the passive observation mode.
+    pub fn new(config: &PassiveConfig) -> Self {
+        Self {
+            budget: Duration::from_micros(config.latency_budget_us),
+            messages: AtomicU64::new(0),
+            total_ns: AtomicU64::new(0),
+            max_ns: AtomicU64::new(0),
+            over_budget: AtomicU64::new(0),
+        }
+    }
+
+    /// Measures the latency added to a forwarded message, and returns whether it exceeded the budget.
+    ///
+    /// # Parameters
+    /// * 'latency' - the time between reading the message and queueing it to be written.
+    pub fn measure(&self, latency: Duration) -> bool {
+        let latency_ns = latency.as_nanos().min(u64::MAX as u128) as u64;
+        self.messages.fetch_add(1, Ordering::Relaxed);
+        self.total_ns.fetch_add(latency_ns, Ordering::Relaxed);
+        self.max_ns.fetch_max(latency_ns, Ordering::Relaxed);
+        let over_budget = latency > self.budget;
+        if over_budget {
+            self.over_budget.fetch_add(1, Ordering::Relaxed);
+        }
+        over_budget
+    }
+
+    /// Returns the latency added to the messages so far.
+    pub fn added_latency(&self) -> AddedLatency {
+        let messages = self.messages.load(Ordering::Relaxed);
+        AddedLatency {
+            messages,
+            budget_us: self.budget.as_micros() as u64,
+            mean_us: self.total_ns.load(Ordering::Relaxed) / messages.max(1) / 1000,
+            max_us: self.max_ns.load(Ordering::Relaxed) / 1000,
+            over_budget: self.over_budget.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::config::PassiveConfig;
+    use crate::passive::{AddedLatency, PassiveObserver};
+    use std::time::Duration;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn added_latency_is_measured_against_the_budget() {
+        let observer = PassiveObserver::new(&PassiveConfig {
+            latency_budget_us: 500,
+        });
+        assert_eq!(observer.added_latency().mean_us, 0);
+
+        assert!(!observer.measure(Duration::from_micros(100)));
+        assert!(!observer.measure(Duration::from_micros(500)));
+        assert!(observer.measure(Duration::from_micros(1200)));
+        assert_eq!(
+            observer.added_latency(),
+            AddedLatency {
+                messages: 3,
+                budget_us: 500,
+                mean_us: 600,
+                max_us: 1200,
+                over_budget: 1,
+            }
+        );
+    }
+}
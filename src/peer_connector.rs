@@ -1,276 +1,394 @@
 //! This module is responsible for setting up connections between peers.
 
+use crate::address;
+use crate::config::TlsConfig;
+use crate::handshake_response::{self, HandshakeError, HandshakeInfo};
+use crate::keygen::{KeyType, NodeKeys, Seed};
+use crate::peer_stream::PeerStream;
+use crate::protocol_version::{ProtocolVersion, DEFAULT_PROTOCOL_VERSIONS};
+use crate::tls::{self, TlsStream};
 use base64::engine::general_purpose;
 use base64::Engine;
-use basex_rs::{BaseX, ALPHABET_RIPPLE};
-use bytes::{Buf, BytesMut};
-use log::{debug, error};
-use openssl::sha::Sha512;
-use openssl::ssl::{Ssl, SslContext, SslMethod};
+use bytes::BytesMut;
 use secp256k1::{Message as CryptoMessage, Secp256k1, SecretKey};
-use std::net::{IpAddr, SocketAddr};
-use std::pin::Pin;
-use std::str::FromStr;
+use std::cmp::min;
+use std::error::Error;
+use std::future::Future;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
-use tokio_openssl::SslStream;
+use tracing::{debug, warn};
+
+/// The amount of seconds between the UNIX epoch and the Ripple epoch (2000-01-01T00:00:00Z).
+pub const RIPPLE_EPOCH_OFFSET_SECS: u64 = 946_684_800;
+
+/// Struct that represents the values of the headers of the upgrade request that can differ per peer,
+/// which describe the state of the peer the interceptor pretends to be.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HandshakeHeaders {
+    /// The protocol versions offered in the Upgrade header, in order of preference.
+    pub protocols: Vec<ProtocolVersion>,
+    /// The ID of the network, the header is omitted if not set.
+    pub network_id: Option<u32>,
+    /// The hash of the last closed ledger in hex, the header is omitted if not set.
+    pub closed_ledger: Option<String>,
+    /// The hash of the ledger before the last closed ledger in hex, the header is omitted if not set.
+    pub previous_ledger: Option<String>,
+    /// Whether the peer allows to be crawled.
+    pub crawl: bool,
+}
+
+impl Default for HandshakeHeaders {
+    fn default() -> Self {
+        Self {
+            protocols: DEFAULT_PROTOCOL_VERSIONS.to_vec(),
+            network_id: None,
+            closed_ledger: None,
+            previous_ledger: None,
+            crawl: false,
+        }
+    }
+}
+
+/// Struct that represents a peer the interceptor pretends to be when connecting to another peer.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PeerIdentity {
+    /// The port of the peer.
+    pub port: u16,
+    /// The validation public key of the peer.
+    pub public_key: String,
+    /// The validation seed of the peer, used to sign the session.
+    pub seed: String,
+    /// The values of the optional headers sent on behalf of the peer.
+    pub headers: HandshakeHeaders,
+}
+
+/// Struct that represents how often and how fast failed handshakes are retried.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// The amount of times a failed handshake is retried.
+    pub retries: u32,
+    /// The time to wait before the first retry, which doubles after every retry.
+    pub initial_backoff: Duration,
+    /// The longest time to wait before a retry.
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Returns how long to wait before a retry, if the peer did not ask for a specific time.
+    ///
+    /// # Parameters
+    /// * 'attempt' - the amount of retries that have been made before.
+    fn backoff(&self, attempt: u32) -> Duration {
+        min(
+            self.initial_backoff
+                .saturating_mul(2u32.saturating_pow(attempt)),
+            self.max_backoff,
+        )
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Struct that represents how long every stage of the handshake with a peer is allowed to take.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HandshakeTimeouts {
+    /// The timeout of establishing the TCP connection.
+    pub connect: Duration,
+    /// The timeout of the TLS handshake.
+    pub tls_handshake: Duration,
+    /// The timeout of sending the upgrade request and receiving its response.
+    pub upgrade: Duration,
+}
+
+impl HandshakeTimeouts {
+    /// Runs a stage of the handshake, which fails with a timeout if it takes too long.
+    ///
+    /// # Parameters
+    /// * 'stage' - the name of the stage, used in the error.
+    /// * 'timeout' - how long the stage is allowed to take.
+    /// * 'future' - the stage.
+    async fn run<T>(
+        stage: &'static str,
+        timeout: Duration,
+        future: impl Future<Output = Result<T, HandshakeError>>,
+    ) -> Result<T, HandshakeError> {
+        tokio::time::timeout(timeout, future)
+            .await
+            .map_err(|_| HandshakeError::Timeout { stage, timeout })?
+    }
+}
+
+impl Default for HandshakeTimeouts {
+    fn default() -> Self {
+        Self {
+            connect: Duration::from_secs(5),
+            tls_handshake: Duration::from_secs(5),
+            upgrade: Duration::from_secs(10),
+        }
+    }
+}
 
 /// Struct that represents the object that connects peers with each other.
 #[derive(Clone)]
 pub struct PeerConnector {
-    /// The IP address of every peer. Only the ports of the peers differ.
-    pub ip_addr: String,
+    /// The host of every peer: an IPv4 or IPv6 address, or a hostname. Only the ports of the peers differ.
+    pub host: String,
+    /// How failed handshakes are retried.
+    pub retry_policy: RetryPolicy,
+    /// How long every stage of the handshake is allowed to take.
+    pub timeouts: HandshakeTimeouts,
+    /// The configuration of the TLS sessions.
+    pub tls_config: TlsConfig,
 }
 
 impl PeerConnector {
     /// Initializes a new PeerConnector.
     ///
     /// # Parameters
-    /// * 'ip_addr' - the IP address of all peers.
-    pub fn new(ip_addr: String) -> Self {
-        Self { ip_addr }
+    /// * 'host' - the IPv4 or IPv6 address or hostname of all peers.
+    /// * 'retry_policy' - how failed handshakes are retried.
+    /// * 'timeouts' - how long every stage of the handshake is allowed to take.
+    /// * 'tls_config' - the configuration of the TLS sessions.
+    pub fn new(
+        host: String,
+        retry_policy: RetryPolicy,
+        timeouts: HandshakeTimeouts,
+        tls_config: TlsConfig,
+    ) -> Self {
+        Self {
+            host,
+            retry_policy,
+            timeouts,
+            tls_config,
+        }
     }
 
-    /// Connects two peers with each other. Returns both halves of the connection, so the interceptor is in between.
+    /// Connects two peers with each other. Returns both halves of the connection, so the interceptor is in between,
+    /// each together with the response of the peer to the handshake.
     ///
     /// # Parameters
-    /// * 'port_peer_1' - the port of the first peer.
-    /// * 'port_peer_2' - the port of the second peer.
-    /// * 'pub_key_peer_1' - the public key of the first peer.
-    /// * 'pub_key_peer_2' - the public key of the second peer.
-    /// * 'seed_peer_1' - the validation seed of the first peer.
-    /// * 'seed_peer_2' - the validation seed of the second peer.
+    /// * 'peer_1' - the first peer.
+    /// * 'peer_2' - the second peer.
+    #[allow(clippy::type_complexity)]
     pub async fn connect_peers(
         &self,
-        port_peer_1: u16,
-        port_peer_2: u16,
-        pub_key_peer_1: &str,
-        pub_key_peer_2: &str,
-        seed_peer_1: &str,
-        seed_peer_2: &str,
-    ) -> (SslStream<TcpStream>, SslStream<TcpStream>) {
-        let connection_half_1 = Self::setup_connection_half(
-            self.ip_addr.as_str(),
-            port_peer_1,
-            pub_key_peer_2,
-            seed_peer_2,
-        )
-        .await;
-        let connection_half_2 = Self::setup_connection_half(
-            self.ip_addr.as_str(),
-            port_peer_2,
-            pub_key_peer_1,
-            seed_peer_1,
-        )
-        .await;
-        (connection_half_1, connection_half_2)
+        peer_1: &PeerIdentity,
+        peer_2: &PeerIdentity,
+    ) -> Result<((TlsStream, HandshakeInfo), (TlsStream, HandshakeInfo)), HandshakeError> {
+        let connection_half_1 = self
+            .setup_connection_half_with_retry(peer_1.port, peer_2)
+            .await?;
+        let connection_half_2 = self
+            .setup_connection_half_with_retry(peer_2.port, peer_1)
+            .await?;
+        Ok((connection_half_1, connection_half_2))
+    }
+
+    /// Connects to a single peer on behalf of another peer, without connecting the other way around.
+    /// Returns the connection together with the response of the peer to the handshake.
+    ///
+    /// # Parameters
+    /// * 'port' - the port of the peer that is connected to.
+    /// * 'initiator' - the peer we pretend to be.
+    pub async fn connect_half(
+        &self,
+        port: u16,
+        initiator: &PeerIdentity,
+    ) -> Result<(TlsStream, HandshakeInfo), HandshakeError> {
+        self.setup_connection_half_with_retry(port, initiator).await
+    }
+
+    /// Sets up a connection half, retrying with backoff as long as the failure is temporary.
+    /// If the peer asked to wait for a specific time with Retry-After, that time is used instead of the backoff.
+    ///
+    /// # Parameters
+    /// * 'port' - the port to which we connect to.
+    /// * 'initiator' - the peer we pretend to be.
+    async fn setup_connection_half_with_retry(
+        &self,
+        port: u16,
+        initiator: &PeerIdentity,
+    ) -> Result<(TlsStream, HandshakeInfo), HandshakeError> {
+        let mut attempt = 0;
+        loop {
+            match Self::setup_connection_half(
+                self.host.as_str(),
+                port,
+                initiator,
+                &self.timeouts,
+                &self.tls_config,
+            )
+            .await
+            {
+                Err(e) if e.is_retryable() && attempt < self.retry_policy.retries => {
+                    let backoff = e
+                        .retry_after()
+                        .unwrap_or_else(|| self.retry_policy.backoff(attempt));
+                    warn!(
+                        "Handshake with peer {} failed, retrying in {:?}: {}",
+                        port, backoff, e
+                    );
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
     }
 
     /// Sets up a connection half from a peer to another peer.
-    /// Connects to the peer at ip:port.
+    /// Connects to the peer at host:port.
     /// We pretend to be the other peer with its public key.
     /// This way we can intercept the connection.
     ///
     /// # Parameters
-    /// * 'ip' - the ip to which we connect to.
+    /// * 'host' - the host to which we connect to.
     /// * 'port' - the port to which we connect to.
-    /// * 'initiator_public_key' - the public key of the peer we pretend to be.
-    /// * 'initiator_seed' - the validation seed of the peer we pretend to be.
+    /// * 'initiator' - the peer we pretend to be.
+    /// * 'timeouts' - how long every stage of the handshake is allowed to take.
+    /// * 'tls_config' - the configuration of the TLS session.
+    async fn setup_connection_half(
+        host: &str,
+        port: u16,
+        initiator: &PeerIdentity,
+        timeouts: &HandshakeTimeouts,
+        tls_config: &TlsConfig,
+    ) -> Result<(TlsStream, HandshakeInfo), HandshakeError> {
+        let tls_stream =
+            Self::create_and_connect_tls_stream(host, port, timeouts, tls_config).await?;
+        Self::upgrade(tls_stream, port, initiator, timeouts.upgrade).await
+    }
+
+    /// Upgrades an established stream with a peer to a peer protocol connection: sends the upgrade request on behalf of
+    /// another peer, signed with its key, and checks the response.
+    /// Returns the stream together with the response of the peer to the handshake.
+    ///
+    /// # Parameters
+    /// * 'stream' - the established stream with the peer.
+    /// * 'port' - the port of the peer, used in the logs.
+    /// * 'initiator' - the peer we pretend to be.
+    /// * 'timeout' - how long sending the request and receiving the response is allowed to take.
     ///
     /// # Panics
-    /// * If an error occurred while creating and connecting the SslStream.
-    /// * If an error occurred while reading or writing to/from the SslStream.
-    /// * If the response of the upgrade request is invalid.
-    async fn setup_connection_half(
-        ip: &str,
+    /// * If the validation seed of the initiator is invalid.
+    /// * If no protocol version was offered.
+    async fn upgrade<S: PeerStream>(
+        mut stream: S,
         port: u16,
-        initiator_public_key: &str,
-        initiator_seed: &str,
-    ) -> SslStream<TcpStream> {
-        let mut ssl_stream =
-            Self::create_and_connect_ssl_stream(ip, port, initiator_public_key, initiator_seed)
-                .await;
+        initiator: &PeerIdentity,
+        timeout: Duration,
+    ) -> Result<(S, HandshakeInfo), HandshakeError> {
+        let connection_error = |e: &dyn Error| HandshakeError::Connection(e.to_string());
 
+        // The following block of code is responsible for computing the Session-Signature
+        // for the Handshake, which is required to establish a connection between two nodes.
+        // See https://github.com/XRPLF/rippled/blob/f64cf9187affd69650907d0d92e097eb29693945/src/xrpld/overlay/detail/Handshake.cpp#L199-L203
+        // for the original implementation by the XRPLF.
+        let shared_value = stream.shared_value();
+        debug!("Shared value of the session: {:?}", shared_value);
+        let msg = CryptoMessage::from_digest_slice(&shared_value[0..32]).unwrap();
+
+        let secp256k1_ctx = Secp256k1::new();
+        let sk = secret_key(&initiator.seed);
+        let sig = secp256k1_ctx.sign_ecdsa(&msg, &sk).serialize_der();
+        let b64sig = general_purpose::STANDARD.encode(sig);
+
+        let content = Self::format_upgrade_request_content(
+            initiator.public_key.as_str(),
+            b64sig.as_str(),
+            &initiator.headers,
+            Self::network_time(),
+        );
         let mut buf = BytesMut::new();
         let mut vec = vec![0; 4096];
-        let size = ssl_stream
-            .read(&mut vec)
-            .await
-            .expect("Unable to read handshake response");
+        let size = HandshakeTimeouts::run("HTTP upgrade", timeout, async {
+            stream
+                .write_all(content.as_bytes())
+                .await
+                .map_err(|e| connection_error(&e))?;
+            stream
+                .read(&mut vec)
+                .await
+                .map_err(|e| connection_error(&e))
+        })
+        .await?;
         vec.resize(size, 0);
         buf.extend_from_slice(&vec);
 
         if size == 0 {
-            error!("Current buffer: {}", String::from_utf8_lossy(&buf).trim());
-            panic!("Socket closed");
+            return Err(HandshakeError::Connection(
+                "the socket was closed before a response was received".to_string(),
+            ));
         }
 
-        Self::check_upgrade_request_response(buf);
-
-        ssl_stream
-    }
-
-    /// This method checks given a buffered HTTP response, whether it is a valid 101 switching protocol response.
-    ///
-    /// # Panics
-    /// * If it could not parse the response.
-    /// * If it received a partial message.
-    /// * If it could not separate the HTTP headers from the body.
-    /// * If the response code is not '101' as expected to be.
-    fn check_upgrade_request_response(mut buffered_response: BytesMut) {
-        if let Some(n) = buffered_response.windows(4).position(|x| x == b"\r\n\r\n") {
-            let mut headers = [httparse::EMPTY_HEADER; 32];
-            let mut response = httparse::Response::new(&mut headers);
-
-            let status = response
-                .parse(&buffered_response[0..n + 4])
-                .expect("Parsing response failed.");
-
-            if status.is_partial() {
-                panic!("Could not fully parse the response.");
+        let mut handshake_info = handshake_response::parse(buf)?;
+        debug!("Handshake with peer {}: {:?}", port, handshake_info);
+        match handshake_info.version {
+            Some(version) if !initiator.headers.protocols.contains(&version) => {
+                return Err(HandshakeError::UnexpectedProtocol(version))
             }
-
-            let response_status_code = response.code.unwrap();
-
-            debug!(
-                "Peer Handshake Response: version: {}, status: {}, reason: {}",
-                response.version.unwrap(),
-                &response_status_code,
-                response.reason.unwrap()
-            );
-
-            debug!("Response headers:");
-            for header in headers.iter().filter(|h| **h != httparse::EMPTY_HEADER) {
-                debug!("{}: {}", header.name, String::from_utf8_lossy(header.value));
-            }
-
-            buffered_response.advance(n + 4);
-
-            // HTTP code 101: Switching Protocols
-            if response_status_code != 101 {
-                panic!(
-                    "Response status code expected to be 101 but was: {}\n\
-                    Body of the response: {}",
-                    response_status_code,
-                    String::from_utf8_lossy(&buffered_response).trim()
-                );
-            }
-
-            if !buffered_response.is_empty() {
-                debug!(
-                    "Switching protocol response has an (unexpected) body: {}",
-                    String::from_utf8_lossy(&buffered_response).trim()
+            Some(_) => (),
+            None => {
+                warn!(
+                    "Peer {} did not select a known protocol ({:?}), assuming {}",
+                    port, handshake_info.protocol, initiator.headers.protocols[0]
                 );
+                handshake_info.version = Some(initiator.headers.protocols[0]);
             }
-        } else {
-            panic!("Could not separate HTTP headers from body. Response is invalid.")
         }
+
+        Ok((stream, handshake_info))
     }
 
-    /// Creates a SslStream and connects to the specified IP address + port.
+    /// Creates a TlsStream and connects to the specified host + port. A hostname is resolved, and every address it
+    /// resolves to is tried until one accepts the connection.
     ///
     /// # Parameters
-    /// * 'ip' - the IP address to which a connection should be made.
+    /// * 'host' - the IPv4 or IPv6 address or hostname to which a connection should be made.
     /// * 'port' - the port to which a connection should be made.
-    /// * 'public_key' - the public key of the node initiating the connection.
-    /// * 'seed' - the validation seed of the node initiating the connection.
-    ///
-    /// # Panics
-    /// * If the ip:port specified is invalid.
-    /// * If the SslStream could not be created or connected to.
-    async fn create_and_connect_ssl_stream(
-        ip: &str,
+    /// * 'timeouts' - how long every stage of the handshake is allowed to take.
+    /// * 'tls_config' - the configuration of the TLS session.
+    async fn create_and_connect_tls_stream(
+        host: &str,
         port: u16,
-        public_key: &str,
-        seed: &str,
-    ) -> SslStream<TcpStream> {
-        let socket_address = SocketAddr::new(IpAddr::from_str(ip).unwrap(), port);
-        let tcp_stream = TcpStream::connect(socket_address).await.unwrap();
+        timeouts: &HandshakeTimeouts,
+        tls_config: &TlsConfig,
+    ) -> Result<TlsStream, HandshakeError> {
+        let connection_error = |e: &dyn Error| HandshakeError::Connection(e.to_string());
+        let tcp_stream = HandshakeTimeouts::run("connect", timeouts.connect, async {
+            address::connect(host, port)
+                .await
+                .map_err(|e| connection_error(&e))
+        })
+        .await?;
+        let socket_address = tcp_stream.peer_addr().map_err(|e| connection_error(&e))?;
 
         tcp_stream
             .set_nodelay(true)
-            .expect("Enable TCP_NODELAY failed.");
-        let ssl_context = SslContext::builder(SslMethod::tls()).unwrap().build();
-        let ssl_session = Ssl::new(&ssl_context).unwrap();
-        let mut ssl_stream = SslStream::<TcpStream>::new(ssl_session, tcp_stream).unwrap();
-        SslStream::connect(Pin::new(&mut ssl_stream))
-            .await
-            .expect("SSL connection failed.");
-
-        // The following block of code is responsible for computing the Session-Signature
-        // for the Handshake, which is required to establish a connection between two nodes.
-        // See https://github.com/XRPLF/rippled/blob/f64cf9187affd69650907d0d92e097eb29693945/src/xrpld/overlay/detail/Handshake.cpp#L199-L203
-        // for the original implementation by the XRPLF.
-        let ssl_ref = ssl_stream.ssl();
-        let mut buf = vec![0; 1024];
-
-        // Get the contents of the last message sent to the peer.
-        let mut size = ssl_ref.finished(&mut buf[..]);
-        if size > buf.len() {
-            buf.resize(size, 0);
-            size = ssl_ref.finished(&mut buf[..]);
-        }
-        // Hash the finished message
-        let mut ctx_sha512_message1 = Sha512::new();
-        ctx_sha512_message1.update(&buf[..size]);
-        let message1_hash = &ctx_sha512_message1.finish();
-        debug!("Finished message SHA512: {:?}", message1_hash);
-
-        // Get the contents of the last received message from the peer.
-        let mut size = ssl_ref.peer_finished(&mut buf[..]);
-        if size > buf.len() {
-            buf.resize(size, 0);
-            size = ssl_ref.peer_finished(&mut buf[..]);
-        }
-        // Hash the received message
-        let mut ctx_sha512_message2 = Sha512::new();
-        ctx_sha512_message2.update(&buf[..size]);
-        let message2_hash = &ctx_sha512_message2.finish();
-        debug!("Received message SHA512: {:?}", message2_hash);
-
-        // XOR the contents of both finished messages
-        let message_xor = message1_hash
-            .iter()
-            .zip(message2_hash.iter())
-            .map(|(a, b)| a ^ b)
-            .collect::<Vec<u8>>();
-        debug!("XOR of messages: {:?}", message_xor);
-
-        let mut ctx_sha512_xor = Sha512::new();
-        ctx_sha512_xor.update(&message_xor[..]);
-        let xor_hash = ctx_sha512_xor.finish();
-        let msg = CryptoMessage::from_digest_slice(&xor_hash[0..32]).unwrap();
-
-        let mut seed_bytes = BaseX::with_alphabet(ALPHABET_RIPPLE)
-            .from_bs58(&String::from(seed))
-            .unwrap();
-        let mut ctx_sha512_seed = Sha512::new();
-
-        // Set last 4 bytes (bytes 18-21) to 0
-        // These bytes are the "Root key sequence", and signify how many times the key had to
-        // be regenerated before being a valid secp256k1 secret key. Anything over 0 is highly
-        // unlikely, thus hardcoded here. If not set to zero, they seem to take on a random value,
-        // which causes the signature to become invalid.
-        // https://xrpl.org/docs/concepts/accounts/cryptographic-keys#secp256k1-key-derivation
-        seed_bytes[17] = 0u8;
-        seed_bytes[18] = 0u8;
-        seed_bytes[19] = 0u8;
-        seed_bytes[20] = 0u8;
-
-        ctx_sha512_seed.update(&seed_bytes[1..]);
-        let seed_hash = ctx_sha512_seed.finish();
-        let secp256k1_ctx = Secp256k1::new();
-        let sk = SecretKey::from_slice(&seed_hash[..32]).unwrap();
-        let sig = secp256k1_ctx.sign_ecdsa(&msg, &sk).serialize_der();
-        let b64sig = general_purpose::STANDARD.encode(sig);
-
-        let content = Self::format_upgrade_request_content(public_key, b64sig.as_str());
-        ssl_stream
-            .write_all(content.as_bytes())
-            .await
-            .expect("Could not send XRPL handshake request.");
+            .map_err(|e| connection_error(&e))?;
+        HandshakeTimeouts::run("TLS handshake", timeouts.tls_handshake, async {
+            tls::connect(tcp_stream, socket_address.ip(), tls_config)
+                .await
+                .map_err(|e| connection_error(&e))
+        })
+        .await
+    }
 
-        ssl_stream
+    /// Returns the current network time, in seconds since the Ripple epoch.
+    fn network_time() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            .saturating_sub(RIPPLE_EPOCH_OFFSET_SECS)
     }
 
     /// Creates a request message which wil upgrade the connection between peer and interceptor
@@ -280,31 +398,94 @@ impl PeerConnector {
     /// # Parameters
     /// * 'public_key' - the public key to be filled in into the request.
     /// * 'base64_sig' - the base64 encoded session signature to be filled in into the request.
-    fn format_upgrade_request_content(public_key: &str, base64_sig: &str) -> String {
-        format!(
+    /// * 'headers' - the values of the optional headers, headers without a value are omitted.
+    /// * 'network_time' - the network time in seconds since the Ripple epoch.
+    fn format_upgrade_request_content(
+        public_key: &str,
+        base64_sig: &str,
+        headers: &HandshakeHeaders,
+        network_time: u64,
+    ) -> String {
+        let mut content = format!(
             "\
             GET / HTTP/1.1\r\n\
-            Upgrade: XRPL/2.2\r\n\
+            Upgrade: {}\r\n\
             Connection: Upgrade\r\n\
             Connect-As: Peer\r\n\
             Public-Key: {}\r\n\
             Session-Signature: {}\r\n\
-            \r\n",
-            public_key, base64_sig
-        )
+            Network-Time: {}\r\n\
+            Crawl: {}\r\n",
+            ProtocolVersion::format_list(&headers.protocols),
+            public_key,
+            base64_sig,
+            network_time,
+            if headers.crawl { "public" } else { "private" }
+        );
+        if let Some(network_id) = headers.network_id {
+            content.push_str(&format!("Network-ID: {}\r\n", network_id));
+        }
+        if let Some(closed_ledger) = &headers.closed_ledger {
+            content.push_str(&format!("Closed-Ledger: {}\r\n", closed_ledger));
+        }
+        if let Some(previous_ledger) = &headers.previous_ledger {
+            content.push_str(&format!("Previous-Ledger: {}\r\n", previous_ledger));
+        }
+        content.push_str("\r\n");
+        content
     }
 }
 
+/// Returns the secret key of a node, derived from its validation seed, with which the node signs its handshakes and
+/// proposals.
+///
+/// # Parameters
+/// * 'seed' - the validation seed of the node, in the base58 encoding of the XRPL.
+///
+/// # Panics
+/// * If the seed is not a valid seed.
+pub fn secret_key(seed: &str) -> SecretKey {
+    let seed: Seed = seed.parse().unwrap();
+    NodeKeys::derive(seed, KeyType::Secp256k1)
+        .secp256k1_secret_key()
+        .unwrap()
+}
+
 #[cfg(test)]
 mod unit_tests {
-    use crate::peer_connector::PeerConnector;
-    use bytes::BytesMut;
+    use crate::config::TlsConfig;
+    use crate::handshake_response::HandshakeError;
+    use crate::peer_connector::{
+        HandshakeHeaders, HandshakeTimeouts, PeerConnector, PeerIdentity, RetryPolicy,
+    };
+    use crate::protocol_version::ProtocolVersion;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
 
     #[test]
     // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
     fn peer_connector_new_test() {
-        let peer_connector = PeerConnector::new("127.0.0.1".to_string());
-        assert_eq!(peer_connector.ip_addr, "127.0.0.1".to_string());
+        let peer_connector = PeerConnector::new(
+            "127.0.0.1".to_string(),
+            RetryPolicy::default(),
+            HandshakeTimeouts::default(),
+            TlsConfig::default(),
+        );
+        assert_eq!(peer_connector.host, "127.0.0.1".to_string());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn retry_policy_backoff() {
+        let retry_policy = RetryPolicy {
+            retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(300),
+        };
+        assert_eq!(retry_policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(retry_policy.backoff(1), Duration::from_millis(200));
+        assert_eq!(retry_policy.backoff(2), Duration::from_millis(300));
+        assert_eq!(retry_policy.backoff(40), Duration::from_millis(300));
     }
 
     #[test]
@@ -313,71 +494,209 @@ mod unit_tests {
         let expected = String::from(
             "\
             GET / HTTP/1.1\r\n\
-            Upgrade: XRPL/2.2\r\n\
+            Upgrade: XRPL/2.2, XRPL/2.1\r\n\
             Connection: Upgrade\r\n\
             Connect-As: Peer\r\n\
             Public-Key: 123456789abcdefg\r\n\
             Session-Signature: 123456789abcdefg\r\n\
+            Network-Time: 770391649\r\n\
+            Crawl: private\r\n\
             \r\n",
         );
         assert_eq!(
             expected,
-            PeerConnector::format_upgrade_request_content("123456789abcdefg", "123456789abcdefg"),
+            PeerConnector::format_upgrade_request_content(
+                "123456789abcdefg",
+                "123456789abcdefg",
+                &HandshakeHeaders::default(),
+                770391649
+            ),
         )
     }
 
     #[test]
     // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
-    fn check_upgrade_request_response_no_panic() {
-        let response = b"\
-            HTTP/1.1 101 Switching Protocol\r\n
-            Connection: Upgrade\r\n\
-            Upgrade: XRPL/2.2\r\n
-            Connect-As: Peer\r\n
-            Server: rippled-2.1.1\r\n
-            Crawl: private\r\n
-            X-Protocol-Ctl:\r\n
-            Network-Time: 770391649\r\n
-            Public-Key: n9M1Fh52PBMSrEjjs8Y64EmU8hfVzb29BBDaXoVNS3AaC1gM19CP\r\n
-            Session-Signature: MEUCIQCBsA3JThSv4geQ67ZlrLvBZGO0wiWWU5pfDsiKalvwKQIgb6CuAHAYnxGf4MYB4Jgsbox4of5GxT4IbRPWablVQ9w=\r\n\
-            Instance-Cookie: 16110088623413850902\r\n
-            Closed-Ledger: 2D7DE9661AADBCDC6DD6630F0C616F5BE29803A5A5DC31486DD65E0F6A79DDB1\r\n
-            Previous-Ledger: 0000000000000000000000000000000000000000000000000000000000000000\r\n\r\n
-        ";
-
-        let mut buffer = BytesMut::new();
-        buffer.extend_from_slice(response);
-        PeerConnector::check_upgrade_request_response(buffer);
+    fn upgrade_request_all_headers_test() {
+        let headers = HandshakeHeaders {
+            protocols: vec![ProtocolVersion::new(2, 1)],
+            network_id: Some(10),
+            closed_ledger: Some("AB".to_string()),
+            previous_ledger: Some("CD".to_string()),
+            crawl: true,
+        };
+        let content = PeerConnector::format_upgrade_request_content("key", "sig", &headers, 1);
+        assert!(content.contains("Upgrade: XRPL/2.1\r\n"));
+        assert!(content.contains("Crawl: public\r\n"));
+        assert!(content.contains("Network-ID: 10\r\n"));
+        assert!(content.contains("Closed-Ledger: AB\r\n"));
+        assert!(content.contains("Previous-Ledger: CD\r\n"));
+        assert!(content.ends_with("\r\n\r\n"));
+    }
+
+    /// Reads a file of a recorded handshake exchange, see `tests/fixtures/handshake`.
+    fn golden_file(case: &str, file: &str) -> Vec<u8> {
+        let path = format!(
+            "{}/tests/fixtures/handshake/{}/{}",
+            env!("CARGO_MANIFEST_DIR"),
+            case,
+            file
+        );
+        std::fs::read(&path).unwrap_or_else(|e| panic!("Could not read {}: {}", path, e))
     }
 
     #[test]
     // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
-    #[should_panic(expected = "Parsing response failed.")]
-    fn check_upgrade_request_response_invalid_request() {
-        let mut buffer = BytesMut::new();
-        buffer.extend_from_slice(b"garbage\r\n\r\n");
-        PeerConnector::check_upgrade_request_response(buffer);
+    fn golden_upgrade_requests() {
+        let closed_ledger = "2D7DE9661AADBCDC6DD6630F0C616F5BE29803A5A5DC31486DD65E0F6A79DDB1";
+        let previous_ledger = "6F37B6B2DA3A5D6B1ED8E2D0F5C8A4EE36F3E30A6DF3B2EAF2A5E3F8BC0E71D4";
+        let only = |major, minor| HandshakeHeaders {
+            protocols: vec![ProtocolVersion::new(major, minor)],
+            ..HandshakeHeaders::default()
+        };
+        let cases = [
+            ("rippled-1.12.0", HandshakeHeaders::default(), 770391640),
+            (
+                "rippled-2.1.1",
+                HandshakeHeaders {
+                    network_id: Some(21338),
+                    closed_ledger: Some(closed_ledger.to_string()),
+                    previous_ledger: Some(previous_ledger.to_string()),
+                    crawl: true,
+                    ..HandshakeHeaders::default()
+                },
+                770391649,
+            ),
+            ("rippled-2.2.3-protocol-2.1", only(2, 1), 770391700),
+            (
+                "rippled-2.2.3-slots-full",
+                HandshakeHeaders::default(),
+                770391702,
+            ),
+            (
+                "rippled-2.0.1-wrong-network",
+                HandshakeHeaders {
+                    network_id: Some(1),
+                    ..HandshakeHeaders::default()
+                },
+                770391710,
+            ),
+            (
+                "rippled-2.2.3-bad-signature",
+                HandshakeHeaders::default(),
+                770391720,
+            ),
+            ("rippled-2.2.3-no-common-protocol", only(3, 0), 770391730),
+        ];
+        for (case, headers, network_time) in cases {
+            let request = PeerConnector::format_upgrade_request_content(
+                "n9KjTKEaHJ12Kuon5PDZ7fQAo5ExZ6cKH4h3L8q6m9YhoYqeBDho",
+                "MEUCIQCBsA3JThSv4geQ67ZlrLvBZGO0wiWWU5pfDsiKalvwKQIgb6CuAHAYnxGf4MYB4Jgsbox4of5GxT4IbRPWablVQ9w=",
+                &headers,
+                network_time,
+            );
+            assert_eq!(
+                request,
+                String::from_utf8(golden_file(case, "request.http")).unwrap(),
+                "The upgrade request of {} changed",
+                case
+            );
+        }
     }
 
-    #[test]
+    #[tokio::test]
     // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
-    #[should_panic(expected = "Could not separate HTTP headers from body. Response is invalid.")]
-    fn check_upgrade_request_response_invalid_response() {
-        let mut buffer = BytesMut::new();
-        let data = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
-        buffer.extend_from_slice(&data);
-        PeerConnector::check_upgrade_request_response(buffer);
+    async fn handshake_stage_timeout() {
+        let timeout = Duration::from_millis(10);
+        let result: Result<(), HandshakeError> =
+            HandshakeTimeouts::run("connect", timeout, std::future::pending()).await;
+        let error = result.unwrap_err();
+        assert_eq!(
+            error,
+            HandshakeError::Timeout {
+                stage: "connect",
+                timeout
+            }
+        );
+        assert!(error.is_retryable());
     }
 
-    #[test]
+    fn initiator() -> PeerIdentity {
+        PeerIdentity {
+            port: 60000,
+            public_key: "n9KjTKEaHJ12Kuon5PDZ7fQAo5ExZ6cKH4h3L8q6m9YhoYqeBDho".to_string(),
+            seed: "shM8uxbqE5g43G3VwKt6TM2pLvFan".to_string(),
+            headers: HandshakeHeaders::default(),
+        }
+    }
+
+    /// Answers the upgrade request read from the stream with a response, and returns the request.
+    async fn answer_upgrade(mut peer: DuplexStream, response: &'static [u8]) -> String {
+        let mut request = vec![0; 4096];
+        let size = peer.read(&mut request).await.unwrap();
+        peer.write_all(response).await.unwrap();
+        String::from_utf8(request[..size].to_vec()).unwrap()
+    }
+
+    #[tokio::test]
     // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
-    #[should_panic(
-        expected = "Response status code expected to be 101 but was: 404\nBody of the response: <body message>"
-    )]
-    fn check_upgrade_request_response_wrong_status_code() {
-        let mut buf = BytesMut::new();
-        buf.extend_from_slice(b"HTTP/1.1 404 Not Found\r\n\r\n<body message>");
+    async fn upgrade_over_in_memory_stream() {
+        let (stream, peer) = tokio::io::duplex(8192);
+        let answer = tokio::spawn(answer_upgrade(
+            peer,
+            b"HTTP/1.1 101 Switching Protocols\r\n\
+            Connection: Upgrade\r\n\
+            Upgrade: XRPL/2.2\r\n\
+            Public-Key: n9M1Fh52PBMSrEjjs8Y64EmU8hfVzb29BBDaXoVNS3AaC1gM19CP\r\n\r\n",
+        ));
+
+        let (_stream, info) =
+            PeerConnector::upgrade(stream, 60001, &initiator(), Duration::from_secs(5))
+                .await
+                .unwrap();
+        assert_eq!(info.version, Some(ProtocolVersion::new(2, 2)));
+        assert_eq!(
+            info.public_key.as_deref(),
+            Some("n9M1Fh52PBMSrEjjs8Y64EmU8hfVzb29BBDaXoVNS3AaC1gM19CP")
+        );
+
+        let request = answer.await.unwrap();
+        assert!(request.starts_with("GET / HTTP/1.1\r\n"));
+        assert!(request
+            .contains("Public-Key: n9KjTKEaHJ12Kuon5PDZ7fQAo5ExZ6cKH4h3L8q6m9YhoYqeBDho\r\n"));
+        assert!(request.contains("Session-Signature: "));
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn upgrade_rejected_over_in_memory_stream() {
+        let (stream, peer) = tokio::io::duplex(8192);
+        tokio::spawn(answer_upgrade(
+            peer,
+            b"HTTP/1.1 503 Service Unavailable\r\nRetry-After: 3\r\n\r\n",
+        ));
+
+        let error = PeerConnector::upgrade(stream, 60001, &initiator(), Duration::from_secs(5))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error,
+            HandshakeError::SlotsFull {
+                retry_after: Some(Duration::from_secs(3))
+            }
+        );
+    }
 
-        PeerConnector::check_upgrade_request_response(buf);
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn upgrade_without_response_times_out() {
+        let (stream, _peer) = tokio::io::duplex(8192);
+        let error = PeerConnector::upgrade(stream, 60001, &initiator(), Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error, HandshakeError::Timeout { .. }),
+            "{:?}",
+            error
+        );
     }
 }
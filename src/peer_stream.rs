@@ -0,0 +1,26 @@
+//! This module is responsible for the abstraction of the streams with the nodes. The handshake and the stages of the
+//! links work on any `PeerStream`: the TLS streams with the nodes during a run, and in-memory streams in tests, such that
+//! they can be tested without Docker, rippled or a TLS library.
+
+use crate::tls::{self, TlsStream};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Trait that represents a stream with a node, over which the handshake is made and the messages are read and written.
+pub trait PeerStream: AsyncRead + AsyncWrite + Send + Unpin + 'static {
+    /// Returns the shared value of the session, which the Session-Signature of the handshake signs.
+    fn shared_value(&self) -> [u8; 64];
+}
+
+impl PeerStream for TlsStream {
+    fn shared_value(&self) -> [u8; 64] {
+        tls::shared_value(self)
+    }
+}
+
+/// An in-memory stream has no session, so its shared value is all zeros.
+#[cfg(test)]
+impl PeerStream for tokio::io::DuplexStream {
+    fn shared_value(&self) -> [u8; 64] {
+        [0; 64]
+    }
+}
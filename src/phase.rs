@@ -0,0 +1,134 @@
+//! This module is responsible for the phases of a run: a warm-up before the fault window and a cool-down after it. During
+//! both, every message is passed through untouched like in the passive observation mode, such that the baseline and the
+//! recovery of the network are captured in the same run. Every handled message is recorded with the phase it was read in.
+
+use crate::config::PhaseConfig;
+use crate::event_bus::EventKind;
+use crate::interceptor_state::InterceptorState;
+use serde::Serialize;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tracing::info;
+
+/// Enum that represents the phase of a run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    /// Messages are passed through untouched before the fault window.
+    WarmUp,
+    /// Messages are intercepted, which is the whole run if it has no phases.
+    #[default]
+    Fault,
+    /// Messages are passed through untouched after the fault window.
+    CoolDown,
+}
+
+impl Phase {
+    /// Returns whether messages are intercepted in this phase.
+    pub fn intercepts(&self) -> bool {
+        *self == Phase::Fault
+    }
+
+    /// Returns the name of the phase as it is exported.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::WarmUp => "warm_up",
+            Phase::Fault => "fault",
+            Phase::CoolDown => "cool_down",
+        }
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Enters the first phase of a run and moves through the others in the background.
+/// Returns the task moving through the phases, and a receiver that is completed once the cool-down ended, which is never
+/// if the fault window or the cool-down lasts until the run ends.
+///
+/// # Parameters
+/// * 'config' - the durations of the phases.
+/// * 'state' - the runtime state, where the phase is set.
+pub fn start(
+    config: PhaseConfig,
+    state: Arc<InterceptorState>,
+) -> (JoinHandle<()>, oneshot::Receiver<()>) {
+    let (complete, completed) = oneshot::channel();
+    let warm_up = Duration::from_secs(config.warm_up_secs);
+    if !warm_up.is_zero() {
+        enter(&state, Phase::WarmUp);
+    }
+    let phases = tokio::spawn(async move {
+        let clock = state.clock();
+        clock.sleep(warm_up).await;
+        enter(&state, Phase::Fault);
+        let Some(fault_secs) = config.fault_secs else {
+            return;
+        };
+        clock.sleep(Duration::from_secs(fault_secs)).await;
+        enter(&state, Phase::CoolDown);
+        let Some(cool_down_secs) = config.cool_down_secs else {
+            return;
+        };
+        clock.sleep(Duration::from_secs(cool_down_secs)).await;
+        info!("The cool-down ended");
+        let _ = complete.send(());
+    });
+    (phases, completed)
+}
+
+/// Sets the phase of the run and publishes the change.
+///
+/// # Parameters
+/// * 'state' - the runtime state, where the phase is set.
+/// * 'phase' - the phase that starts.
+fn enter(state: &InterceptorState, phase: Phase) {
+    if state.phase() == phase {
+        return;
+    }
+    info!("Entering the {} phase", phase);
+    state.set_phase(phase);
+    state.events.emit(EventKind::PhaseChanged {
+        phase: phase.to_string(),
+    });
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::config::PhaseConfig;
+    use crate::interceptor_state::InterceptorState;
+    use crate::packet_timeline::PacketTimeline;
+    use crate::phase::{self, Phase};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test(start_paused = true)]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn phases_follow_each_other() {
+        let state = Arc::new(InterceptorState::new(Arc::new(PacketTimeline::new(10))));
+        let config = PhaseConfig {
+            warm_up_secs: 10,
+            fault_secs: Some(60),
+            cool_down_secs: Some(20),
+        };
+        let (_phases, mut completed) = phase::start(config, state.clone());
+        // The warm-up starts before the first message can be read
+        assert_eq!(state.phase(), Phase::WarmUp);
+        assert!(!state.phase().intercepts());
+
+        tokio::time::sleep(Duration::from_secs(11)).await;
+        assert_eq!(state.phase(), Phase::Fault);
+        assert!(state.phase().intercepts());
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert_eq!(state.phase(), Phase::CoolDown);
+        assert!(completed.try_recv().is_err());
+        tokio::time::sleep(Duration::from_secs(20)).await;
+        assert!(completed.try_recv().is_ok());
+    }
+}
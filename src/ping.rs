@@ -0,0 +1,161 @@
+//! This module is responsible for reading and creating mtPING messages, which peers use as keepalive.
+//! The payload is a `TMPing` message from rippled's `ripple.proto`.
+
+use crate::message_type::MessageType;
+use bytes::{BufMut, Bytes, BytesMut};
+use prost::encoding::{decode_varint, encode_varint};
+
+/// The field number of the ping type in `TMPing`.
+const FIELD_TYPE: u64 = 1;
+/// The field number of the sequence number in `TMPing`.
+const FIELD_SEQ: u64 = 2;
+/// The field number of the ping time in `TMPing`.
+const FIELD_PING_TIME: u64 = 3;
+/// The field number of the network time in `TMPing`.
+const FIELD_NET_TIME: u64 = 4;
+/// The value of the ping type for a pong (`ptPONG`), a ping is 0 (`ptPING`).
+const TYPE_PONG: u64 = 1;
+/// The protobuf wire type of varint fields, which all fields of `TMPing` are.
+const WIRE_TYPE_VARINT: u64 = 0;
+
+/// Struct that represents a `TMPing` message.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Ping {
+    /// Whether this is a reply to a ping.
+    pub pong: bool,
+    /// The sequence number chosen by the sender of the ping.
+    pub seq: Option<u32>,
+    /// The time the ping was sent, as chosen by the sender.
+    pub ping_time: Option<u64>,
+    /// The network time of the sender.
+    pub net_time: Option<u64>,
+}
+
+impl Ping {
+    /// Parses a `TMPing` payload. Returns None if the payload is not a valid `TMPing`.
+    ///
+    /// # Parameters
+    /// * 'payload' - the payload of the message, without header.
+    pub fn decode(mut payload: &[u8]) -> Option<Self> {
+        let mut ping = Self::default();
+        let mut has_type = false;
+        while !payload.is_empty() {
+            let key = decode_varint(&mut payload).ok()?;
+            if key & 0b111 != WIRE_TYPE_VARINT {
+                return None;
+            }
+            let value = decode_varint(&mut payload).ok()?;
+            match key >> 3 {
+                FIELD_TYPE => {
+                    ping.pong = value == TYPE_PONG;
+                    has_type = true;
+                }
+                FIELD_SEQ => ping.seq = Some(value as u32),
+                FIELD_PING_TIME => ping.ping_time = Some(value),
+                FIELD_NET_TIME => ping.net_time = Some(value),
+                _ => (),
+            }
+        }
+        has_type.then_some(ping)
+    }
+
+    /// Parses a complete message, including its header. Returns None if it is not a valid mtPING message.
+    ///
+    /// # Parameters
+    /// * 'message' - the message including its 6 byte header.
+    pub fn from_message(message: &[u8]) -> Option<Self> {
+        if MessageType::from_message(message)? != MessageType::Ping {
+            return None;
+        }
+        let payload_size = u32::from_be_bytes(message[0..4].try_into().ok()?) as usize;
+        Self::decode(message.get(6..6 + payload_size)?)
+    }
+
+    /// Returns the pong that answers this ping.
+    pub fn reply(&self) -> Self {
+        Self {
+            pong: true,
+            ..*self
+        }
+    }
+
+    /// Encodes the `TMPing` payload.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut payload = Vec::new();
+        let mut put = |field: u64, value: u64| {
+            encode_varint(field << 3 | WIRE_TYPE_VARINT, &mut payload);
+            encode_varint(value, &mut payload);
+        };
+        put(FIELD_TYPE, if self.pong { TYPE_PONG } else { 0 });
+        if let Some(seq) = self.seq {
+            put(FIELD_SEQ, seq as u64);
+        }
+        if let Some(ping_time) = self.ping_time {
+            put(FIELD_PING_TIME, ping_time);
+        }
+        if let Some(net_time) = self.net_time {
+            put(FIELD_NET_TIME, net_time);
+        }
+        payload
+    }
+
+    /// Encodes the complete message, including its header.
+    pub fn to_message(&self) -> Bytes {
+        let payload = self.encode();
+        let mut message = BytesMut::with_capacity(6 + payload.len());
+        message.put_u32(payload.len() as u32);
+        message.put_u16(MessageType::Ping.value());
+        message.put_slice(&payload);
+        message.freeze()
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::ping::Ping;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn decode_ping() {
+        // ptPING with seq 5 and pingTime 1000
+        let ping = Ping::decode(&[0x08, 0x00, 0x10, 0x05, 0x18, 0xe8, 0x07]).unwrap();
+        assert!(!ping.pong);
+        assert_eq!(ping.seq, Some(5));
+        assert_eq!(ping.ping_time, Some(1000));
+        assert_eq!(ping.net_time, None);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn decode_invalid_ping() {
+        assert_eq!(Ping::decode(&[]), None);
+        assert_eq!(Ping::decode(&[0x10, 0x05]), None);
+        assert_eq!(Ping::decode(&[0x0a, 0x01, 0x00]), None);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn reply_roundtrip() {
+        let ping = Ping {
+            pong: false,
+            seq: Some(42),
+            ping_time: Some(123_456_789),
+            net_time: None,
+        };
+        let message = ping.reply().to_message();
+        assert_eq!(&message[4..6], &[0, 3]);
+
+        let pong = Ping::from_message(&message).unwrap();
+        assert!(pong.pong);
+        assert_eq!(pong.seq, Some(42));
+        assert_eq!(pong.ping_time, Some(123_456_789));
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn from_message_ignores_other_types() {
+        let mut message = Ping::default().to_message().to_vec();
+        message[5] = 41;
+        assert_eq!(Ping::from_message(&message), None);
+    }
+}
@@ -0,0 +1,270 @@
+//! This module is responsible for the host ports the containers of the nodes are published on. The controller provides a
+//! base port for the peer, WebSocket, admin WebSocket and RPC ports, from which every node takes the next port. Before
+//! any container is started, these ranges are checked against each other and against the ports already in use on the
+//! host, such that a collision fails the run up front instead of when Docker publishes the port of some node.
+//!
+//! In the `auto` mode, free ranges are picked instead, and the nodes are reported to the controller with the ports they
+//! got. Ports picked by a network are reserved for the whole process, such that networks that run at the same time do
+//! not pick the same ranges before their containers are started.
+
+use crate::config::{PortAllocation, PortConfig};
+use crate::packet_client::proto::Config;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::net::TcpListener;
+use std::sync::Mutex;
+use tracing::info;
+
+/// The ports reserved by the networks of this process, which are considered in use even if nothing listens on them yet.
+static RESERVED: Mutex<BTreeSet<u16>> = Mutex::new(BTreeSet::new());
+
+/// Enum that represents why the ports of the nodes can not be used.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PortError {
+    /// A range does not fit below port 65536, by the name of the range.
+    OutOfRange(&'static str),
+    /// Two ranges share ports, by the names of the ranges.
+    Overlap(&'static str, &'static str),
+    /// A port of a range is in use on the host, by the name of the range and the port.
+    InUse(&'static str, u16),
+    /// The search range has no room for a range of the amount of ports.
+    NoFreeRange(u32),
+}
+
+impl fmt::Display for PortError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortError::OutOfRange(range) => write!(f, "the {} ports exceed port 65535", range),
+            PortError::Overlap(range_1, range_2) => {
+                write!(
+                    f,
+                    "the {} ports overlap with the {} ports",
+                    range_1, range_2
+                )
+            }
+            PortError::InUse(range, port) => {
+                write!(f, "{} port {} is already in use on the host", range, port)
+            }
+            PortError::NoFreeRange(count) => {
+                write!(f, "no range of {} free ports left to allocate", count)
+            }
+        }
+    }
+}
+
+/// Returns the base ports of the ranges of a network configuration, by the names of the ranges.
+///
+/// # Parameters
+/// * 'config' - the network configuration.
+fn base_ports(config: &Config) -> [(&'static str, u32); 4] {
+    [
+        ("peer", config.base_port_peer),
+        ("WebSocket", config.base_port_ws),
+        ("admin WebSocket", config.base_port_ws_admin),
+        ("RPC", config.base_port_rpc),
+    ]
+}
+
+/// Returns whether a port is free: nothing listens on it and no network of this process reserved it.
+///
+/// # Parameters
+/// * 'port' - the port.
+/// * 'reserved' - the ports reserved by the networks of this process.
+fn is_free(port: u16, reserved: &BTreeSet<u16>) -> bool {
+    !reserved.contains(&port) && TcpListener::bind(("0.0.0.0", port)).is_ok()
+}
+
+/// Checks that the ranges of a network configuration fit below port 65536, do not overlap and are free, and reserves
+/// them if they are.
+///
+/// # Parameters
+/// * 'config' - the network configuration.
+/// * 'count' - the amount of ports of every range, one per container.
+/// * 'reserved' - the ports reserved by the networks of this process.
+fn check(config: &Config, count: u32, reserved: &mut BTreeSet<u16>) -> Result<(), PortError> {
+    let ranges = base_ports(config);
+    for (i, &(name, base)) in ranges.iter().enumerate() {
+        if base + count > u16::MAX as u32 + 1 {
+            return Err(PortError::OutOfRange(name));
+        }
+        for &(other_name, other_base) in ranges[..i].iter() {
+            if base < other_base + count && other_base < base + count {
+                return Err(PortError::Overlap(other_name, name));
+            }
+        }
+    }
+    for (name, base) in ranges {
+        for port in base..base + count {
+            if !is_free(port as u16, reserved) {
+                return Err(PortError::InUse(name, port as u16));
+            }
+        }
+    }
+    reserved.extend(
+        ranges
+            .iter()
+            .flat_map(|(_, base)| (*base..base + count).map(|port| port as u16)),
+    );
+    Ok(())
+}
+
+/// Picks the lowest free ranges of the search range for the ranges of a network configuration, one after the other,
+/// changes the base ports of the configuration to them and reserves them.
+///
+/// # Parameters
+/// * 'config' - the network configuration.
+/// * 'count' - the amount of ports of every range, one per container.
+/// * 'search' - the first and last port that may be picked.
+/// * 'reserved' - the ports reserved by the networks of this process.
+fn allocate(
+    config: &mut Config,
+    count: u32,
+    search: (u16, u16),
+    reserved: &mut BTreeSet<u16>,
+) -> Result<(), PortError> {
+    let mut bases = Vec::new();
+    let mut base = search.0 as u32;
+    while bases.len() < 4 {
+        if count == 0 || base + count > search.1 as u32 + 1 {
+            return Err(PortError::NoFreeRange(count));
+        }
+        match (base..base + count).find(|port| !is_free(*port as u16, reserved)) {
+            // Continue after the port in use, no range that contains it is free
+            Some(port) => base = port + 1,
+            None => {
+                bases.push(base);
+                base += count;
+            }
+        }
+    }
+    config.base_port_peer = bases[0];
+    config.base_port_ws = bases[1];
+    config.base_port_ws_admin = bases[2];
+    config.base_port_rpc = bases[3];
+    reserved.extend(
+        bases
+            .iter()
+            .flat_map(|base| (*base..base + count).map(|port| port as u16)),
+    );
+    Ok(())
+}
+
+/// Makes sure the containers of a network can be published on the ports of its configuration: checks them, or in the
+/// `auto` mode picks free ranges if they can not be used. The ports are reserved for the rest of the process. Returns
+/// whether the base ports of the configuration were changed.
+///
+/// # Parameters
+/// * 'config' - the network configuration, whose base ports may be changed.
+/// * 'count' - the amount of ports of every range, one per container.
+/// * 'port_config' - how the ports are allocated.
+pub fn reserve(
+    config: &mut Config,
+    count: u32,
+    port_config: &PortConfig,
+) -> Result<bool, PortError> {
+    let mut reserved = RESERVED.lock().unwrap();
+    match check(config, count, &mut reserved) {
+        Ok(()) => Ok(false),
+        Err(e) if port_config.allocation == PortAllocation::Auto => {
+            info!(
+                "The configured ports can not be used ({}), allocating free ports",
+                e
+            );
+            allocate(
+                config,
+                count,
+                (port_config.search_from, port_config.search_to),
+                &mut reserved,
+            )
+            .map(|_| true)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::packet_client::proto::Config;
+    use crate::port_allocation::{allocate, check, PortError};
+    use std::collections::BTreeSet;
+    use std::net::TcpListener;
+
+    fn config(base_port: u32) -> Config {
+        Config {
+            base_port_peer: base_port,
+            base_port_ws: base_port + 1000,
+            base_port_ws_admin: base_port + 2000,
+            base_port_rpc: base_port + 3000,
+            number_of_nodes: 3,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn check_reserves_free_ports() {
+        let mut reserved = BTreeSet::new();
+        let config = config(43000);
+        assert_eq!(check(&config, 3, &mut reserved), Ok(()));
+        assert_eq!(reserved.len(), 12);
+        assert!(reserved.contains(&46002));
+        // A second network with the same ports collides with the reservation
+        assert_eq!(
+            check(&config, 3, &mut reserved),
+            Err(PortError::InUse("peer", 43000))
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn check_rejects_invalid_ranges() {
+        let mut reserved = BTreeSet::new();
+        let mut overlapping = config(43000);
+        overlapping.base_port_ws = 43002;
+        assert_eq!(
+            check(&overlapping, 3, &mut reserved),
+            Err(PortError::Overlap("peer", "WebSocket"))
+        );
+        let mut out_of_range = config(43000);
+        out_of_range.base_port_rpc = 65534;
+        assert_eq!(
+            check(&out_of_range, 3, &mut reserved),
+            Err(PortError::OutOfRange("RPC"))
+        );
+        assert!(reserved.is_empty());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn check_detects_ports_in_use() {
+        let listener = TcpListener::bind("0.0.0.0:0").unwrap();
+        let port = listener.local_addr().unwrap().port() as u32;
+        let mut config = config(43000);
+        config.base_port_rpc = port - 1;
+        assert_eq!(
+            check(&config, 3, &mut BTreeSet::new()),
+            Err(PortError::InUse("RPC", port as u16))
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn allocate_skips_used_ports() {
+        let mut reserved: BTreeSet<u16> = [44002].into();
+        let mut config = config(0);
+        assert_eq!(
+            allocate(&mut config, 3, (44000, 44100), &mut reserved),
+            Ok(())
+        );
+        assert_eq!(config.base_port_peer, 44003);
+        assert_eq!(config.base_port_ws, 44006);
+        assert_eq!(config.base_port_ws_admin, 44009);
+        assert_eq!(config.base_port_rpc, 44012);
+        assert_eq!(reserved.len(), 13);
+
+        assert_eq!(
+            allocate(&mut config, 3, (44000, 44010), &mut reserved),
+            Err(PortError::NoFreeRange(3))
+        );
+    }
+}
@@ -0,0 +1,135 @@
+//! This module is responsible for the versions of the XRPL peer protocol that are negotiated in the handshake.
+//!
+//! Every leg of a link negotiates its version independently, so both nodes of a link can speak a different version.
+//! All versions use the same framing, but newer versions introduce message types older versions do not understand.
+//! Compression is never negotiated by the interceptor, so messages are uncompressed on every leg regardless of the version.
+
+use crate::message_type::MessageType;
+use std::fmt;
+use std::str::FromStr;
+
+/// The name of the protocol in the Upgrade header.
+const PROTOCOL_NAME: &str = "XRPL";
+
+/// The protocol versions offered if none are configured, in order of preference.
+pub const DEFAULT_PROTOCOL_VERSIONS: [ProtocolVersion; 2] =
+    [ProtocolVersion::new(2, 2), ProtocolVersion::new(2, 1)];
+
+/// Struct that represents a version of the XRPL peer protocol, e.g. 'XRPL/2.2'.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ProtocolVersion {
+    /// The major version.
+    pub major: u16,
+    /// The minor version.
+    pub minor: u16,
+}
+
+impl ProtocolVersion {
+    /// Initializes a new ProtocolVersion.
+    ///
+    /// # Parameters
+    /// * 'major' - the major version.
+    /// * 'minor' - the minor version.
+    pub const fn new(major: u16, minor: u16) -> Self {
+        Self { major, minor }
+    }
+
+    /// Formats a list of versions as used in the Upgrade header.
+    ///
+    /// # Parameters
+    /// * 'versions' - the versions.
+    pub fn format_list(versions: &[Self]) -> String {
+        versions
+            .iter()
+            .map(Self::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Returns the version that introduced a message type. Message types that are not listed exist in every version.
+    ///
+    /// # Parameters
+    /// * 'message_type' - the type of the message.
+    fn introduced_in(message_type: MessageType) -> Self {
+        match message_type {
+            MessageType::ValidatorListCollection => Self::new(2, 1),
+            MessageType::HaveTransactions | MessageType::Transactions => Self::new(2, 2),
+            _ => Self::new(0, 0),
+        }
+    }
+
+    /// Returns whether a peer that speaks this version understands messages of a type.
+    ///
+    /// # Parameters
+    /// * 'message_type' - the type of the message.
+    pub fn supports(&self, message_type: MessageType) -> bool {
+        *self >= Self::introduced_in(message_type)
+    }
+}
+
+impl FromStr for ProtocolVersion {
+    type Err = String;
+
+    /// Parses a version as used in the Upgrade header, e.g. 'XRPL/2.2'.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid protocol version '{}'", s);
+        let (name, version) = s.trim().split_once('/').ok_or_else(invalid)?;
+        if name != PROTOCOL_NAME {
+            return Err(invalid());
+        }
+        let (major, minor) = version.split_once('.').ok_or_else(invalid)?;
+        Ok(Self::new(
+            major.parse().map_err(|_| invalid())?,
+            minor.parse().map_err(|_| invalid())?,
+        ))
+    }
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}.{}", PROTOCOL_NAME, self.major, self.minor)
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::message_type::MessageType;
+    use crate::protocol_version::ProtocolVersion;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_versions() {
+        assert_eq!(
+            "XRPL/2.2".parse::<ProtocolVersion>(),
+            Ok(ProtocolVersion::new(2, 2))
+        );
+        assert_eq!(
+            " XRPL/10.0".parse::<ProtocolVersion>(),
+            Ok(ProtocolVersion::new(10, 0))
+        );
+        assert!("HTTP/1.1".parse::<ProtocolVersion>().is_err());
+        assert!("XRPL/2".parse::<ProtocolVersion>().is_err());
+        assert!("XRPL/a.b".parse::<ProtocolVersion>().is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn format_versions() {
+        let versions = vec![ProtocolVersion::new(2, 2), ProtocolVersion::new(2, 1)];
+        assert_eq!(
+            ProtocolVersion::format_list(&versions),
+            "XRPL/2.2, XRPL/2.1"
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn supported_message_types() {
+        let v2_1 = ProtocolVersion::new(2, 1);
+        assert!(v2_1.supports(MessageType::Validation));
+        assert!(v2_1.supports(MessageType::ValidatorListCollection));
+        assert!(!v2_1.supports(MessageType::HaveTransactions));
+        assert!(ProtocolVersion::new(2, 2).supports(MessageType::Transactions));
+        assert!(!ProtocolVersion::new(2, 0).supports(MessageType::ValidatorListCollection));
+    }
+}
@@ -0,0 +1,189 @@
+//! This module contains what the proxies in front of the client-facing ports of the nodes share:
+//! the links of both directions of a proxy, and deciding on the messages sent through them.
+//!
+//! Both directions of a proxy are registered as a link from the proxy port to the port of the node and back,
+//! such that their rules can be changed and their forwarding paused like those of the peer links.
+
+use crate::action::Decision;
+use crate::event_bus::EventKind;
+use crate::interceptor_state::{InterceptorState, Link};
+use crate::packet_client::proto::Channel;
+use crate::packet_client::{PacketClient, PacketMetadata};
+use bytes::Bytes;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+use tracing::error;
+
+/// The identity used for the clients of a proxy in the metadata sent to the controller.
+pub const CLIENT_IDENTITY: &str = "client";
+
+/// Struct that represents one direction of a proxy, shared by all connections through it.
+#[derive(Debug)]
+pub struct ProxyDirection {
+    /// The link of this direction.
+    pub link: Arc<Link>,
+    /// The traffic of the proxy, sent to the controller.
+    channel: Channel,
+    /// The identity of the sender, sent to the controller.
+    from_node: String,
+    /// The identity of the receiver, sent to the controller.
+    to_node: String,
+    /// Whether the messages in this direction are sent to the controller.
+    intercept: bool,
+    /// The amount of messages decided on in this direction.
+    sequence: AtomicU64,
+}
+
+impl ProxyDirection {
+    /// Registers the link of a direction of a proxy and announces it on the event bus.
+    ///
+    /// # Parameters
+    /// * 'state' - the runtime state, where the link is registered.
+    /// * 'from_port' - the port the messages come from.
+    /// * 'to_port' - the port the messages go to.
+    /// * 'channel' - the traffic of the proxy.
+    /// * 'from_node' - the identity of the sender.
+    /// * 'to_node' - the identity of the receiver.
+    /// * 'intercept' - whether the messages are sent to the controller.
+    pub fn register(
+        state: &InterceptorState,
+        from_port: u16,
+        to_port: u16,
+        channel: Channel,
+        from_node: String,
+        to_node: String,
+        intercept: bool,
+    ) -> Self {
+        let protocol = Some(channel.as_str_name().to_lowercase());
+        let link = state.register_link(from_port, to_port, protocol.clone());
+        state.events.emit(EventKind::LinkConnected {
+            from_port,
+            to_port,
+            protocol,
+        });
+        Self {
+            link,
+            channel,
+            from_node,
+            to_node,
+            intercept,
+            sequence: AtomicU64::new(0),
+        }
+    }
+
+    /// Decides on a message: the controller is asked unless the interceptor is in passthrough mode or the direction
+    /// is not intercepted, after which the rule of the link and the time dilation are applied.
+    /// If the controller can not be reached, the message is forwarded unchanged.
+    ///
+    /// # Parameters
+    /// * 'data' - the message, e.g. the payload of a WebSocket frame or the body of an HTTP request.
+    /// * 'client' - the PacketClient used to ask the controller for a decision.
+    /// * 'state' - the runtime state, containing the statistics where failures are counted.
+    pub async fn decide(
+        &self,
+        data: Bytes,
+        client: &Mutex<PacketClient>,
+        state: &InterceptorState,
+    ) -> Decision {
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+        let mut decision = if self.intercept && !state.is_passthrough() {
+            let metadata = PacketMetadata {
+                from_node: self.from_node.clone(),
+                to_node: self.to_node.clone(),
+                sequence,
+                capture_timestamp_ns: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_nanos() as u64,
+                channel: self.channel,
+                signature_valid: None,
+            };
+            let mut client = client.lock().await;
+            let proto_version = client.proto_version();
+            match client
+                .send_packet(
+                    data.clone(),
+                    u32::from(self.link.from_port),
+                    u32::from(self.link.to_port),
+                    &metadata,
+                )
+                .await
+            {
+                Ok(ack) => Decision::from_ack(data.clone(), ack, proto_version).unwrap_or_else(
+                    |e| {
+                        error!(
+                            "Rejected action from the controller for {:?} traffic, forwarding unchanged: {}",
+                            self.channel, e
+                        );
+                        state.statistics.count_error("rejected_action", 1);
+                        Decision::forward(data)
+                    },
+                ),
+                Err(e) => {
+                    error!(
+                        "Could not request an action for {:?} traffic, forwarding unchanged: {}",
+                        self.channel, e
+                    );
+                    state.statistics.count_error("proxy_controller_failed", 1);
+                    Decision::forward(data)
+                }
+            }
+        } else {
+            Decision::forward(data)
+        };
+        self.link.apply_rule(&mut decision);
+        decision.delay = state.dilate(decision.delay);
+        decision
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::interceptor_state::{InterceptorState, LinkRule};
+    use crate::packet_client::proto::Channel;
+    use crate::packet_client::PacketClient;
+    use crate::packet_timeline::PacketTimeline;
+    use crate::proxy::{ProxyDirection, CLIENT_IDENTITY};
+    use bytes::Bytes;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::Mutex;
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn unintercepted_direction_applies_link_rule() {
+        let state = InterceptorState::new(Arc::new(PacketTimeline::new(10)));
+        let direction = ProxyDirection::register(
+            &state,
+            6100,
+            6005,
+            Channel::Websocket,
+            CLIENT_IDENTITY.to_string(),
+            "node".to_string(),
+            false,
+        );
+        assert_eq!(direction.link.protocol.as_deref(), Some("websocket"));
+        state
+            .set_link_rule(
+                6100,
+                6005,
+                LinkRule {
+                    delay_ms: 40,
+                    drop_probability: 0.0,
+                },
+            )
+            .unwrap();
+        state.set_time_dilation(2.0).unwrap();
+
+        // The controller is not asked, so the client is never connected
+        let client = Mutex::new(PacketClient::lazy());
+        let decision = direction
+            .decide(Bytes::from("{\"method\":\"submit\"}"), &client, &state)
+            .await;
+        assert_eq!(decision.send_amount, 1);
+        assert_eq!(decision.delay, Duration::from_millis(80));
+        assert_eq!(direction.link.handled(), 1);
+    }
+}
@@ -0,0 +1,170 @@
+//! This module is responsible for writing the records of handled messages to external storage while the interceptor runs.
+//!
+//! Every sink is written by its own blocking thread, which receives the records through a bounded channel.
+//! If a sink can not keep up, records for that sink are dropped instead of slowing down the links.
+
+use crate::packet_timeline::PacketRecord;
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+/// The most records that are written to a sink at once.
+const MAX_BATCH_SIZE: usize = 1024;
+
+/// Trait for storage the records of handled messages are written to.
+pub trait RecordSink: Send {
+    /// Returns the name of the sink, used in logs.
+    fn name(&self) -> String;
+
+    /// Writes a batch of records, in the order they were recorded.
+    ///
+    /// # Parameters
+    /// * 'records' - the records.
+    fn write(&mut self, records: &[PacketRecord]) -> Result<(), Box<dyn Error + Send + Sync>>;
+
+    /// Flushes everything that was written, called once before the sink is closed.
+    fn close(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Ok(())
+    }
+}
+
+/// Struct that represents the sending side of a sink that is written by its own thread.
+#[derive(Debug)]
+pub struct SinkHandle {
+    /// The name of the sink.
+    pub name: String,
+    /// The channel to the thread writing the sink.
+    sender: mpsc::Sender<PacketRecord>,
+    /// The amount of records that were dropped because the sink could not keep up.
+    dropped: AtomicU64,
+}
+
+impl SinkHandle {
+    /// Sends a record to the sink, or drops it if the sink is behind.
+    ///
+    /// # Parameters
+    /// * 'record' - the record.
+    pub fn send(&self, record: PacketRecord) {
+        match self.sender.try_send(record) {
+            Ok(()) => (),
+            Err(TrySendError::Full(_)) => {
+                if self.dropped.fetch_add(1, Ordering::Relaxed) == 0 {
+                    warn!(
+                        "Sink {} can not keep up, records are dropped for it",
+                        self.name
+                    );
+                }
+            }
+            Err(TrySendError::Closed(_)) => (),
+        }
+    }
+
+    /// Returns the amount of records that were dropped because the sink could not keep up.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Starts the thread writing a sink. The thread closes the sink and stops once all handles are dropped
+/// and every record sent before has been written.
+///
+/// # Parameters
+/// * 'sink' - the sink.
+/// * 'capacity' - the amount of records buffered for the sink.
+pub fn spawn(mut sink: Box<dyn RecordSink>, capacity: usize) -> (Arc<SinkHandle>, JoinHandle<()>) {
+    let (sender, mut receiver) = mpsc::channel(capacity.max(1));
+    let name = sink.name();
+    let handle = Arc::new(SinkHandle {
+        name: name.clone(),
+        sender,
+        dropped: AtomicU64::new(0),
+    });
+    let thread = tokio::task::spawn_blocking(move || {
+        let mut batch = Vec::with_capacity(MAX_BATCH_SIZE);
+        let mut written = 0u64;
+        while let Some(record) = receiver.blocking_recv() {
+            batch.push(record);
+            while batch.len() < MAX_BATCH_SIZE {
+                match receiver.try_recv() {
+                    Ok(record) => batch.push(record),
+                    Err(_) => break,
+                }
+            }
+            match sink.write(&batch) {
+                Ok(()) => written += batch.len() as u64,
+                Err(e) => error!("Could not write {} records to {}: {}", batch.len(), name, e),
+            }
+            batch.clear();
+        }
+        if let Err(e) = sink.close() {
+            error!("Could not close {}: {}", name, e);
+        }
+        info!("Wrote {} records to {}", written, name);
+    });
+    (handle, thread)
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::message_type::MessageType;
+    use crate::packet_timeline::PacketRecord;
+    use crate::record_sink::{spawn, RecordSink};
+    use std::error::Error;
+    use std::sync::{Arc, Mutex};
+
+    struct MemorySink {
+        records: Arc<Mutex<Vec<u64>>>,
+        closed: Arc<Mutex<bool>>,
+    }
+
+    impl RecordSink for MemorySink {
+        fn name(&self) -> String {
+            "memory".to_string()
+        }
+
+        fn write(&mut self, records: &[PacketRecord]) -> Result<(), Box<dyn Error + Send + Sync>> {
+            let mut written = self.records.lock().unwrap();
+            written.extend(records.iter().map(|record| record.sequence));
+            Ok(())
+        }
+
+        fn close(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+            *self.closed.lock().unwrap() = true;
+            Ok(())
+        }
+    }
+
+    fn record(sequence: u64) -> PacketRecord {
+        PacketRecord {
+            sequence,
+            message_type: MessageType::Ping,
+            size: 8,
+            sent_size: 8,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn sink_writes_all_records_in_order_before_closing() {
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let closed = Arc::new(Mutex::new(false));
+        let sink = MemorySink {
+            records: records.clone(),
+            closed: closed.clone(),
+        };
+        let (handle, thread) = spawn(Box::new(sink), 100);
+        for sequence in 0..50 {
+            handle.send(record(sequence));
+        }
+        drop(handle);
+        thread.await.unwrap();
+
+        assert_eq!(*records.lock().unwrap(), (0..50).collect::<Vec<u64>>());
+        assert!(*closed.lock().unwrap());
+    }
+}
@@ -0,0 +1,277 @@
+//! This module is responsible for chaining interceptors, such that layered experiments can be composed, e.g. one
+//! interceptor emulating latency and the next one mutating messages as a Byzantine node.
+//!
+//! The first interceptor of the chain holds the connections to the nodes. After it decided on a message, it relays the
+//! message as it was left to the next interceptor, which is started as a relay layer and has its own controller, rules
+//! and hooks. The layer decides on the message as if it intercepted it, and can relay it further itself. Its decision is
+//! combined with the decisions of the previous layers: the message is dropped if any layer drops it, the delays are
+//! added, and the last layer determines the data. Only the first interceptor delivers the message, and signs it again if
+//! a layer asks for it, since only it holds the keys of the nodes.
+
+use crate::action::Decision;
+use crate::connection_handler::Node;
+use crate::event_bus::EventKind;
+use crate::interceptor_state::InterceptorState;
+use crate::message_type::MessageType;
+use crate::packet_client::proto::interceptor_service_client::InterceptorServiceClient;
+use crate::packet_client::proto::{Packet, RelayDecision};
+use crate::packet_client::{PacketClient, PacketMetadata};
+use crate::packet_hook::PacketContext;
+use crate::packet_timeline::PacketRecord;
+use crate::wire_format;
+use chrono::DateTime;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Request, Status};
+use tracing::{error, info_span, Instrument};
+
+/// Struct that represents the connection to the next interceptor in the chain.
+#[derive(Debug)]
+pub struct RelayClient {
+    address: String,
+    client: InterceptorServiceClient<Channel>,
+}
+
+impl RelayClient {
+    /// Initializes a new RelayClient that only connects to the next interceptor once it is used, and reconnects to it
+    /// after it became unreachable. Returns an error if the address is invalid.
+    ///
+    /// # Parameters
+    /// * 'address' - the address of the gRPC service of the next interceptor.
+    pub fn connect_lazy(address: &str) -> Result<Self, tonic::transport::Error> {
+        let channel = Endpoint::from_shared(address.to_string())?.connect_lazy();
+        Ok(Self {
+            address: address.to_string(),
+            client: InterceptorServiceClient::new(channel),
+        })
+    }
+
+    /// Returns the address of the next interceptor.
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Asks the next interceptor to decide on a message.
+    ///
+    /// # Parameters
+    /// * 'packet' - the message as it was left by this interceptor.
+    async fn relay_packet(&self, packet: Packet) -> Result<RelayDecision, Status> {
+        let mut client = self.client.clone();
+        Ok(client
+            .relay_packet(Request::new(packet))
+            .await?
+            .into_inner())
+    }
+}
+
+/// Relays a message that is sent to the next interceptor in the chain, if there is one, and combines its decision with
+/// the decision made so far. If the next interceptor fails, the decision made so far is kept.
+///
+/// # Parameters
+/// * 'decision' - the decision made for the message so far.
+/// * 'metadata' - the identities of the nodes, the sequence number and the capture time of the message.
+/// * 'context' - the link, type and sequence of the message.
+/// * 'state' - the runtime state, containing the next interceptor, the signing keys and the statistics.
+pub async fn relay(
+    decision: Decision,
+    metadata: &PacketMetadata,
+    context: &PacketContext,
+    state: &InterceptorState,
+) -> Decision {
+    let Some(relay_client) = state.relay() else {
+        return decision;
+    };
+    if decision.send_amount == 0 {
+        return decision;
+    }
+    // The decided message may be mutated, so its signature is verified again, as the next interceptor has no keys
+    let packet = Packet {
+        signature_valid: state.signature_valid(&decision.data),
+        ..PacketClient::build_packet(
+            &decision.data,
+            u32::from(context.from_port),
+            u32::from(context.to_port),
+            metadata,
+            None,
+        )
+    };
+    let next = match relay_client
+        .relay_packet(packet)
+        .instrument(info_span!("relay"))
+        .await
+    {
+        Ok(next) => next,
+        Err(status) => {
+            error!(
+                "Could not relay {} to the next interceptor at {}, keeping the decision of this one: {}",
+                context.message_type,
+                relay_client.address(),
+                status
+            );
+            state.statistics.count_error("relay_failed", 1);
+            return decision;
+        }
+    };
+    let mut next = Decision {
+        data: next.data,
+        delay: Duration::from_millis(u64::from(next.delay_ms)),
+        send_amount: next.send_amount,
+        resign: next.resign,
+    };
+    if next.send_amount == 0 {
+        state.events.emit(EventKind::packet_dropped(
+            context.from_port,
+            context.to_port,
+            context.message_type,
+            Some(context.sequence),
+            "relay",
+        ));
+    } else if next.resign && next.data != decision.data {
+        match state.resign(context.from_port, &next.data) {
+            Ok(resigned) => next.data = resigned,
+            Err(e) => {
+                error!(
+                    "Could not sign the message mutated by the next interceptor again, sending it as mutated: {}",
+                    e
+                );
+                state.statistics.count_error("resign_failed", 1);
+            }
+        }
+    }
+    decision.then(next)
+}
+
+/// Decides on a message relayed by the previous interceptor in the chain, as if it was intercepted by this one.
+/// The message is recorded, but not delivered, and the delay is not dilated.
+///
+/// # Parameters
+/// * 'packet' - the message as it was left by the previous interceptor.
+/// * 'client' - the PacketClient used to send a request to the controller of this interceptor.
+/// * 'state' - the runtime state of this interceptor.
+pub async fn decide_relayed(
+    packet: Packet,
+    client: Arc<Mutex<PacketClient>>,
+    state: Arc<InterceptorState>,
+) -> Result<RelayDecision, Status> {
+    let started = Instant::now();
+    let from_port = u16::try_from(packet.from_port)
+        .map_err(|_| Status::invalid_argument("from_port is not a port"))?;
+    let to_port = u16::try_from(packet.to_port)
+        .map_err(|_| Status::invalid_argument("to_port is not a port"))?;
+    if packet.data.is_empty() {
+        return Err(Status::invalid_argument("data is empty"));
+    }
+    let message_type = MessageType::from_message(&packet.data).unwrap_or(MessageType::Unknown(0));
+    let metadata = PacketMetadata {
+        from_node: packet.from_node.clone(),
+        to_node: packet.to_node.clone(),
+        sequence: packet.sequence,
+        capture_timestamp_ns: packet.capture_timestamp_ns,
+        channel: packet.channel(),
+        signature_valid: packet.signature_valid,
+    };
+    let (decision, controller_latency) = Node::decide(
+        packet.data.clone(),
+        metadata,
+        client,
+        state.clone(),
+        from_port,
+        to_port,
+        message_type,
+    )
+    .await;
+
+    state.record(PacketRecord {
+        timestamp: DateTime::from_timestamp_nanos(packet.capture_timestamp_ns as i64),
+        from_port,
+        to_port,
+        sequence: packet.sequence,
+        message_type,
+        ledger_sequence: wire_format::ledger_sequence(&packet.data),
+        round: state.rounds.round(&packet.data),
+        phase: state.phase(),
+        size: packet.data.len(),
+        hash: hex::encode(Sha256::digest(&packet.data)),
+        sent_size: decision.data.len(),
+        action: decision.delay_ms(),
+        send_amount: decision.send_amount,
+        controller_latency,
+        latency: started.elapsed(),
+    });
+    Ok(RelayDecision {
+        delay_ms: decision.delay_ms(),
+        send_amount: decision.send_amount,
+        resign: decision.resign,
+        data: decision.data,
+    })
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::config::InterceptorConfig;
+    use crate::hot_reload::LocalRules;
+    use crate::interceptor_state::{InterceptorState, LinkRule};
+    use crate::packet_client::proto::Packet;
+    use crate::packet_client::PacketClient;
+    use crate::packet_timeline::PacketTimeline;
+    use crate::relay::decide_relayed;
+    use bytes::Bytes;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+    use tonic::Code;
+
+    #[tokio::test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    async fn relayed_message_is_decided_and_recorded() {
+        let state = Arc::new(InterceptorState::new(Arc::new(PacketTimeline::new(10))));
+        let config = InterceptorConfig::parse(
+            r#"
+            [interception]
+            default = "passthrough"
+            "#,
+        )
+        .unwrap();
+        state.apply_rules(LocalRules::from_config(&config, &[]).unwrap());
+        state.register_link(60000, 60001, None);
+        state
+            .set_link_rule(
+                60000,
+                60001,
+                LinkRule {
+                    delay_ms: 40,
+                    drop_probability: 0.0,
+                },
+            )
+            .unwrap();
+        // The controller is not asked, so the client is never connected
+        let client = Arc::new(Mutex::new(PacketClient::lazy()));
+        let data = Bytes::from_static(&[0, 0, 0, 2, 0, 3, 8, 1]);
+        let packet = Packet {
+            data: data.clone(),
+            from_port: 60000,
+            to_port: 60001,
+            sequence: 7,
+            ..Packet::default()
+        };
+
+        let decision = decide_relayed(packet.clone(), client.clone(), state.clone())
+            .await
+            .unwrap();
+        assert_eq!(decision.data, data);
+        assert_eq!(decision.delay_ms, 40);
+        assert_eq!(decision.send_amount, 1);
+        let records = state.timeline.latest(10);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].sequence, 7);
+
+        let invalid = Packet {
+            to_port: 70000,
+            ..packet
+        };
+        let status = decide_relayed(invalid, client, state).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+}
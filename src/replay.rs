@@ -0,0 +1,229 @@
+//! This module is responsible for the replay fault: selected messages are captured as they are handled, and re-injected
+//! onto a link later, to evaluate how nodes treat out-of-date consensus messages, e.g. validations from 10 ledgers ago.
+//!
+//! Only the messages of the captured types are kept, in a bounded buffer that discards the oldest message when it is
+//! full. Replayed messages are written as they were read, see `InterceptorState::inject`.
+
+use crate::interceptor_state::InterceptorState;
+use crate::message_type::MessageType;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Struct that represents a message that was captured to be replayed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedMessage {
+    /// The port of the peer where the message came from.
+    pub from_port: u16,
+    /// The port of the peer the message was sent to.
+    pub to_port: u16,
+    /// The type of the message.
+    pub message_type: MessageType,
+    /// The ledger sequence contained in the message, for the message types that contain one.
+    pub ledger_sequence: Option<u32>,
+    /// The message as it was read, including its 6 byte header.
+    pub data: Bytes,
+}
+
+/// Struct that represents a bounded, thread-safe buffer of the most recently captured messages.
+#[derive(Debug)]
+pub struct CaptureBuffer {
+    /// The maximum amount of messages kept in the buffer.
+    capacity: usize,
+    /// The types of the messages that are captured.
+    message_types: Vec<MessageType>,
+    /// The captured messages in the order they were handled, oldest first.
+    messages: Mutex<VecDeque<CapturedMessage>>,
+}
+
+impl CaptureBuffer {
+    /// Initializes a new CaptureBuffer.
+    ///
+    /// # Parameters
+    /// * 'capacity' - the maximum amount of messages kept, older messages are discarded.
+    /// * 'message_types' - the types of the messages that are captured.
+    pub fn new(capacity: usize, message_types: Vec<MessageType>) -> Self {
+        Self {
+            capacity,
+            message_types,
+            messages: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Captures a handled message if it has one of the captured types,
+    /// discarding the oldest message if the buffer is full.
+    ///
+    /// # Parameters
+    /// * 'message' - the message.
+    pub fn capture(&self, message: CapturedMessage) {
+        if self.capacity == 0 || !self.message_types.contains(&message.message_type) {
+            return;
+        }
+        let mut messages = self.messages.lock().unwrap();
+        if messages.len() == self.capacity {
+            messages.pop_front();
+        }
+        messages.push_back(message);
+    }
+
+    /// Returns the captured messages selected by a replay request, oldest first.
+    ///
+    /// # Parameters
+    /// * 'request' - the request that selects the messages.
+    pub fn select(&self, request: &ReplayRequest) -> Vec<CapturedMessage> {
+        let messages = self.messages.lock().unwrap();
+        let ledger_sequence = request.ledgers_ago.map(|ledgers_ago| {
+            messages
+                .iter()
+                .filter_map(|message| message.ledger_sequence)
+                .max()
+                .unwrap_or_default()
+                .saturating_sub(ledgers_ago)
+        });
+        let selected = messages.iter().filter(|message| {
+            request
+                .message_type
+                .map_or(true, |message_type| message.message_type == message_type)
+                && request
+                    .from_port
+                    .map_or(true, |from_port| message.from_port == from_port)
+                && ledger_sequence.map_or(true, |ledger_sequence| {
+                    message.ledger_sequence == Some(ledger_sequence)
+                })
+        });
+        match request.limit {
+            Some(limit) => selected.take(limit).cloned().collect(),
+            None => selected.cloned().collect(),
+        }
+    }
+}
+
+/// Struct that represents a request to replay captured messages. Conditions that are not set select all messages.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReplayRequest {
+    /// The type of the replayed messages.
+    pub message_type: Option<MessageType>,
+    /// The port of the peer the replayed messages came from.
+    pub from_port: Option<u16>,
+    /// The port of the node the messages are replayed to, the node they were originally sent to if not set.
+    pub to_port: Option<u16>,
+    /// How many ledgers the ledger sequence of the replayed messages is behind the most recent captured ledger sequence.
+    pub ledgers_ago: Option<u32>,
+    /// The maximum amount of replayed messages, the oldest selected messages are replayed first.
+    pub limit: Option<usize>,
+}
+
+/// Struct that represents the outcome of a replay.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct ReplayOutcome {
+    /// The amount of messages that were injected.
+    pub replayed: usize,
+    /// The amount of selected messages that could not be injected, because the interceptor does not write from the
+    /// peer they came from to the node they are replayed to.
+    pub skipped: usize,
+}
+
+/// Replays the captured messages selected by a request. Returns None if no messages are captured.
+///
+/// # Parameters
+/// * 'request' - the request that selects the messages and the node they are replayed to.
+/// * 'state' - the runtime state, containing the captured messages and through which the messages are injected.
+pub async fn replay(request: &ReplayRequest, state: &InterceptorState) -> Option<ReplayOutcome> {
+    let messages = state.capture_buffer()?.select(request);
+    let mut outcome = ReplayOutcome::default();
+    for message in messages {
+        let to_port = request.to_port.unwrap_or(message.to_port);
+        match state.inject(message.from_port, to_port, message.data).await {
+            Ok(()) => outcome.replayed += 1,
+            Err(e) => {
+                warn!("Could not replay {}: {}", message.message_type, e);
+                outcome.skipped += 1;
+            }
+        }
+    }
+    info!(
+        "Replayed {} messages, skipped {}",
+        outcome.replayed, outcome.skipped
+    );
+    Some(outcome)
+}
+
+/// Replays captured messages once, at a fixed moment of the run, as configured locally.
+///
+/// # Parameters
+/// * 'request' - the request that selects the messages and the node they are replayed to.
+/// * 'after' - how long after the links are started the messages are replayed.
+/// * 'state' - the runtime state, containing the captured messages and through which the messages are injected.
+pub async fn run_scheduled(request: ReplayRequest, after: Duration, state: Arc<InterceptorState>) {
+    state.clock().sleep(after).await;
+    if let Some(outcome) = replay(&request, &state).await {
+        if outcome.skipped > 0 {
+            state
+                .statistics
+                .count_error("replay_skipped", outcome.skipped as u64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::message_type::MessageType;
+    use crate::replay::{CaptureBuffer, CapturedMessage, ReplayRequest};
+    use bytes::Bytes;
+
+    fn validation(from_port: u16, ledger_sequence: u32) -> CapturedMessage {
+        CapturedMessage {
+            from_port,
+            to_port: 60000,
+            message_type: MessageType::Validation,
+            ledger_sequence: Some(ledger_sequence),
+            data: Bytes::from_static(&[0, 0, 0, 0, 0, 41]),
+        }
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn capture_only_selected_types_up_to_capacity() {
+        let buffer = CaptureBuffer::new(2, vec![MessageType::Validation]);
+        buffer.capture(CapturedMessage {
+            message_type: MessageType::Transaction,
+            ledger_sequence: None,
+            ..validation(60001, 1)
+        });
+        buffer.capture(validation(60001, 1));
+        buffer.capture(validation(60001, 2));
+        buffer.capture(validation(60001, 3));
+
+        let all = buffer.select(&ReplayRequest::default());
+        assert_eq!(all, vec![validation(60001, 2), validation(60001, 3)]);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn select_messages_from_ledgers_ago() {
+        let buffer = CaptureBuffer::new(10, vec![MessageType::Validation]);
+        for ledger_sequence in 1..=12 {
+            buffer.capture(validation(60001, ledger_sequence));
+            buffer.capture(validation(60002, ledger_sequence));
+        }
+        let request = ReplayRequest {
+            ledgers_ago: Some(10),
+            ..ReplayRequest::default()
+        };
+        assert_eq!(
+            buffer.select(&request),
+            vec![validation(60001, 2), validation(60002, 2)]
+        );
+
+        let request = ReplayRequest {
+            from_port: Some(60002),
+            limit: Some(1),
+            ..ReplayRequest::default()
+        };
+        assert_eq!(buffer.select(&request), vec![validation(60002, 8)]);
+    }
+}
@@ -0,0 +1,246 @@
+//! This module is responsible for intercepting the HTTP traffic between clients and the JSON-RPC port of every node,
+//! such that admin and submission requests and their responses are subject to the same controller decisions and
+//! link rules as the messages between peers.
+//!
+//! Every node gets a proxy port, see `proxy::ProxyDirection` for how the bodies are decided on.
+//! A dropped request never reaches the node, and a dropped response never reaches the client:
+//! in both cases the client receives '504 Gateway Timeout'. A duplicated request is sent to the node multiple
+//! times, of which the first response is returned. Responses can not be duplicated.
+
+use crate::address;
+use crate::config::RpcProxyConfig;
+use crate::interceptor_state::InterceptorState;
+use crate::packet_client::proto::Channel;
+use crate::packet_client::PacketClient;
+use crate::proxy::{ProxyDirection, CLIENT_IDENTITY};
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+/// The largest body of a request or response that is proxied.
+const MAX_BODY_SIZE: usize = 64 * 1024 * 1024;
+
+/// Struct that represents the proxy in front of the JSON-RPC port of a single node.
+#[derive(Debug, Clone)]
+pub struct RpcProxy {
+    /// The port the proxy listens on.
+    pub proxy_port: u16,
+    /// The host the ports of the node are published on.
+    pub node_host: String,
+    /// The JSON-RPC port of the node.
+    pub node_port: u16,
+    /// The validation public key of the node.
+    pub node_key: String,
+    /// The configuration of the proxy.
+    pub config: RpcProxyConfig,
+    /// The PacketClient used to ask the controller for decisions.
+    pub client: Arc<Mutex<PacketClient>>,
+    /// The runtime state, containing the links of the proxy.
+    pub state: Arc<InterceptorState>,
+}
+
+/// Struct that represents everything a proxied request needs.
+struct ProxyContext {
+    /// The URL of the JSON-RPC port of the node, without a path.
+    node_url: String,
+    /// The direction from the client to the node.
+    requests: ProxyDirection,
+    /// The direction from the node to the client.
+    responses: ProxyDirection,
+    /// The PacketClient used to ask the controller for decisions.
+    client: Arc<Mutex<PacketClient>>,
+    /// The runtime state.
+    state: Arc<InterceptorState>,
+    /// The HTTP client used to send the requests to the node.
+    http: reqwest::Client,
+}
+
+impl RpcProxy {
+    /// Registers the links of the proxy and serves clients until the listener fails.
+    ///
+    /// # Parameters
+    /// * 'listener' - the listener bound to the proxy port.
+    pub async fn serve(self, listener: TcpListener) {
+        info!(
+            "Proxying JSON-RPC clients on port {} to port {}",
+            self.proxy_port, self.node_port
+        );
+        let context = Arc::new(ProxyContext {
+            node_url: format!(
+                "http://{}",
+                address::host_port(&self.node_host, self.node_port)
+            ),
+            requests: ProxyDirection::register(
+                &self.state,
+                self.proxy_port,
+                self.node_port,
+                Channel::Rpc,
+                CLIENT_IDENTITY.to_string(),
+                self.node_key.clone(),
+                self.config.intercept_requests,
+            ),
+            responses: ProxyDirection::register(
+                &self.state,
+                self.node_port,
+                self.proxy_port,
+                Channel::Rpc,
+                self.node_key.clone(),
+                CLIENT_IDENTITY.to_string(),
+                self.config.intercept_responses,
+            ),
+            client: self.client,
+            state: self.state,
+            http: reqwest::Client::new(),
+        });
+        let router = Router::new().fallback(proxy_request).with_state(context);
+        if let Err(e) = axum::serve(listener, router).await {
+            error!("JSON-RPC proxy on port {} stopped: {}", self.proxy_port, e);
+        }
+    }
+}
+
+/// Proxies a single request to the node and its response back to the client.
+///
+/// # Parameters
+/// * 'context' - the proxy the request was sent to.
+/// * 'request' - the request of the client.
+async fn proxy_request(State(context): State<Arc<ProxyContext>>, request: Request) -> Response {
+    let (parts, body) = request.into_parts();
+    let body = match axum::body::to_bytes(body, MAX_BODY_SIZE).await {
+        Ok(body) => body,
+        Err(e) => return (StatusCode::PAYLOAD_TOO_LARGE, e.to_string()).into_response(),
+    };
+
+    let decision = context
+        .requests
+        .decide(body, &context.client, &context.state)
+        .await;
+    hold(decision.delay, &context.state).await;
+    if decision.send_amount == 0 {
+        return dropped("request");
+    }
+    let url = format!(
+        "{}{}",
+        context.node_url,
+        parts
+            .uri
+            .path_and_query()
+            .map(|path| path.as_str())
+            .unwrap_or("/")
+    );
+    let mut first_response = None;
+    for _ in 0..decision.send_amount {
+        let response = context
+            .http
+            .request(parts.method.clone(), url.as_str())
+            .headers(forwarded_headers(&parts.headers))
+            .body(decision.data.clone())
+            .send()
+            .await;
+        first_response.get_or_insert(response);
+    }
+    let response = match first_response {
+        Some(Ok(response)) => response,
+        Some(Err(e)) => {
+            warn!("Could not proxy a JSON-RPC request to {}: {}", url, e);
+            return (StatusCode::BAD_GATEWAY, e.to_string()).into_response();
+        }
+        None => return dropped("request"),
+    };
+
+    let status = response.status();
+    let headers = forwarded_headers(response.headers());
+    let body = match response.bytes().await {
+        Ok(body) => body,
+        Err(e) => return (StatusCode::BAD_GATEWAY, e.to_string()).into_response(),
+    };
+    let decision = context
+        .responses
+        .decide(body, &context.client, &context.state)
+        .await;
+    hold(decision.delay, &context.state).await;
+    if decision.send_amount == 0 {
+        return dropped("response");
+    }
+    (status, headers, Body::from(decision.data)).into_response()
+}
+
+/// Waits for the delay of a decision, and then for as long as forwarding is paused.
+///
+/// # Parameters
+/// * 'delay' - the delay of the decision.
+/// * 'state' - the runtime state, containing whether forwarding is paused.
+async fn hold(delay: Duration, state: &InterceptorState) {
+    if !delay.is_zero() {
+        tokio::time::sleep(delay).await;
+    }
+    if state.is_paused() {
+        state.wait_until_resumed().await;
+    }
+}
+
+/// Returns the response a client receives when its request or the response to it was dropped.
+///
+/// # Parameters
+/// * 'what' - what was dropped.
+fn dropped(what: &str) -> Response {
+    (
+        StatusCode::GATEWAY_TIMEOUT,
+        format!("The {} was dropped by the interceptor", what),
+    )
+        .into_response()
+}
+
+/// Returns the headers that are passed on by the proxy. The headers describing the connection or the length of
+/// the body are left out, since the body may be mutated and the connection is not passed on.
+///
+/// # Parameters
+/// * 'headers' - the headers of the original request or response.
+fn forwarded_headers(headers: &HeaderMap) -> HeaderMap {
+    let mut forwarded = headers.clone();
+    for name in [
+        header::HOST,
+        header::CONTENT_LENGTH,
+        header::TRANSFER_ENCODING,
+        header::CONNECTION,
+    ] {
+        forwarded.remove(name);
+    }
+    forwarded
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::rpc_proxy::{dropped, forwarded_headers};
+    use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn forward_headers_without_length_and_connection() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::HOST, HeaderValue::from_static("127.0.0.1:6200"));
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from_static("42"));
+        headers.insert(header::CONNECTION, HeaderValue::from_static("close"));
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+
+        let forwarded = forwarded_headers(&headers);
+        assert_eq!(forwarded.len(), 1);
+        assert_eq!(forwarded[header::CONTENT_TYPE], "application/json");
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn dropped_is_a_gateway_timeout() {
+        assert_eq!(dropped("request").status(), StatusCode::GATEWAY_TIMEOUT);
+    }
+}
@@ -0,0 +1,115 @@
+//! This module is responsible for the identifier of a run, which tells concurrent and historical runs apart. It is
+//! generated at startup, or given with `--run-id`, and attached to everything the run produces: the labels of its Docker
+//! resources, the metadata of every request to the controller, every log line and the names of its artifacts.
+
+use chrono::Utc;
+use rand::Rng;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// The Docker label that holds the identifier of the run that created a resource.
+pub const RUN_ID_LABEL: &str = "rocket-interceptor.run-id";
+
+/// The gRPC metadata key that holds the identifier of the run that sent a request.
+pub const RUN_ID_METADATA: &str = "x-run-id";
+
+/// Struct that represents the identifier of a run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunId(pub String);
+
+impl RunId {
+    /// Generates a new identifier from the current time and a random suffix, e.g. `20240101-120000-1a2b3c4d`, such that
+    /// identifiers sort by the time the run started.
+    pub fn generate() -> Self {
+        RunId(format!(
+            "{}-{:08x}",
+            Utc::now().format("%Y%m%d-%H%M%S"),
+            rand::thread_rng().gen::<u32>()
+        ))
+    }
+
+    /// Parses an identifier, which may only contain letters, digits, dots, dashes and underscores, since it is used in
+    /// file names, Docker labels and gRPC metadata.
+    ///
+    /// # Parameters
+    /// * 'value' - the identifier.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        if value.is_empty()
+            || !value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+        {
+            return Err(format!(
+                "'{}' is not a valid run ID, use letters, digits, dots, dashes and underscores",
+                value
+            ));
+        }
+        Ok(RunId(value.to_string()))
+    }
+
+    /// Returns the identifier of the run of a tenant, which is the identifier of the process with the name of the
+    /// tenant appended.
+    ///
+    /// # Parameters
+    /// * 'tenant' - the name of the tenant.
+    pub fn tenant(&self, tenant: &str) -> Self {
+        RunId(format!("{}-{}", self.0, tenant))
+    }
+
+    /// Returns the labels of the Docker resources of the run: the configured labels and the run ID label.
+    ///
+    /// # Parameters
+    /// * 'labels' - the labels from the configuration.
+    pub fn labels(&self, labels: &BTreeMap<String, String>) -> HashMap<String, String> {
+        let mut labels: HashMap<String, String> = labels.clone().into_iter().collect();
+        labels.insert(RUN_ID_LABEL.to_string(), self.0.clone());
+        labels
+    }
+}
+
+impl fmt::Display for RunId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::run_id::{RunId, RUN_ID_LABEL};
+    use std::collections::BTreeMap;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn generated_ids_are_valid_and_unique() {
+        let first = RunId::generate();
+        let second = RunId::generate();
+        assert_eq!(first.0.len(), "20240101-120000-1a2b3c4d".len());
+        assert_eq!(RunId::parse(&first.0), Ok(first.clone()));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_run_id() {
+        assert_eq!(
+            RunId::parse("nightly-42"),
+            Ok(RunId("nightly-42".to_string()))
+        );
+        assert_eq!(RunId::parse("a.b_c"), Ok(RunId("a.b_c".to_string())));
+        assert!(RunId::parse("").is_err());
+        assert!(RunId::parse("../x").is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn labels_include_the_run_id() {
+        let run_id = RunId("run".to_string());
+        let labels = BTreeMap::from([("team".to_string(), "consensus".to_string())]);
+        let labels = run_id.tenant("mainnet").labels(&labels);
+        assert_eq!(labels.get("team").map(String::as_str), Some("consensus"));
+        assert_eq!(
+            labels.get(RUN_ID_LABEL).map(String::as_str),
+            Some("run-mainnet")
+        );
+    }
+}
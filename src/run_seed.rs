@@ -0,0 +1,105 @@
+//! This module is responsible for the seed of a run, from which all randomness of the run is derived when the
+//! interceptor is started with `--seed`: the keys of the nodes, the generated topology, the keys of the validator list
+//! publisher and every random decision on the links, such that a run can be reproduced on any machine.
+//!
+//! Every use of randomness derives its own value from the seed by its purpose, so adding randomness in one place does
+//! not change the randomness in any other place.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use sha2::{Digest, Sha512};
+use std::str::FromStr;
+
+/// Struct that represents the seed all randomness of a run is derived from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunSeed(pub u64);
+
+impl RunSeed {
+    /// Derives a number from the seed for a purpose. The same seed and purpose always derive the same number, on any
+    /// machine.
+    ///
+    /// # Parameters
+    /// * 'purpose' - what the number is used for, e.g. 'topology'.
+    pub fn derive(&self, purpose: &str) -> u64 {
+        let mut hasher = Sha512::new();
+        hasher.update(self.0.to_be_bytes());
+        hasher.update(purpose.as_bytes());
+        let hash = hasher.finalize();
+        u64::from_be_bytes(hash[..8].try_into().unwrap())
+    }
+
+    /// Returns a random number generator seeded for a purpose.
+    ///
+    /// # Parameters
+    /// * 'purpose' - what the generator is used for.
+    pub fn rng(&self, purpose: &str) -> StdRng {
+        StdRng::seed_from_u64(self.derive(purpose))
+    }
+
+    /// Returns the passphrase a key is derived from, for the key with an index among the keys generated for a purpose.
+    ///
+    /// # Parameters
+    /// * 'purpose' - what the keys are used for, e.g. 'validator'.
+    /// * 'index' - the index of the key.
+    pub fn passphrase(&self, purpose: &str, index: usize) -> String {
+        format!("{:016x}", self.derive(&format!("{}-{}", purpose, index)))
+    }
+}
+
+impl FromStr for RunSeed {
+    type Err = String;
+
+    /// Parses the seed given with `--seed`, which is a number.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        value
+            .parse()
+            .map(RunSeed)
+            .map_err(|_| format!("'{}' is not a valid seed", value))
+    }
+}
+
+/// Returns a random number generator for a purpose: derived from the seed of the run if there is one, otherwise seeded
+/// randomly.
+///
+/// # Parameters
+/// * 'seed' - the seed of the run, if any.
+/// * 'purpose' - what the generator is used for.
+pub fn rng(seed: Option<RunSeed>, purpose: &str) -> StdRng {
+    match seed {
+        Some(seed) => seed.rng(purpose),
+        None => StdRng::seed_from_u64(rand::thread_rng().gen()),
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::run_seed::RunSeed;
+    use rand::Rng;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_seed() {
+        assert_eq!("42".parse(), Ok(RunSeed(42)));
+        assert!("".parse::<RunSeed>().is_err());
+        assert!("x".parse::<RunSeed>().is_err());
+        assert!("-1".parse::<RunSeed>().is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn derived_values_are_reproducible() {
+        let seed = RunSeed(42);
+        assert_eq!(seed.derive("topology"), RunSeed(42).derive("topology"));
+        assert_ne!(seed.derive("topology"), seed.derive("validator_list"));
+        assert_ne!(seed.derive("topology"), RunSeed(43).derive("topology"));
+        assert_eq!(
+            seed.rng("link").gen::<u64>(),
+            RunSeed(42).rng("link").gen::<u64>()
+        );
+        assert_eq!(seed.passphrase("validator", 0).len(), 16);
+        assert_ne!(
+            seed.passphrase("validator", 0),
+            seed.passphrase("validator", 1)
+        );
+    }
+}
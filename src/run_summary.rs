@@ -0,0 +1,494 @@
+//! This module is responsible for summarizing a run when the interceptor shuts down:
+//! how many messages of every link and message type were handled, which actions were taken on them,
+//! which errors occurred, and how many ledgers were closed.
+
+use crate::consensus_round::{RoundTally, RoundTimeline};
+use crate::message_type::MessageType;
+use crate::node_rpc::NodeRpcClient;
+use crate::packet_client::proto;
+use crate::packet_timeline::PacketRecord;
+use crate::run_id::RunId;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::debug;
+
+/// Struct that represents the amount of messages and the actions taken on them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ActionCounts {
+    /// The amount of handled messages.
+    pub handled: u64,
+    /// The amount of messages forwarded once without delay.
+    pub forwarded: u64,
+    /// The amount of messages forwarded once after a delay.
+    pub delayed: u64,
+    /// The amount of messages that were dropped.
+    pub dropped: u64,
+    /// The amount of messages that were sent more than once.
+    pub duplicated: u64,
+    /// The amount of messages whose content was changed.
+    pub mutated: u64,
+}
+
+impl ActionCounts {
+    /// Counts a handled message.
+    ///
+    /// # Parameters
+    /// * 'record' - the record of the message.
+    pub fn count(&mut self, record: &PacketRecord) {
+        self.handled += 1;
+        match record.action_name() {
+            "drop" => self.dropped += 1,
+            "delay" => self.delayed += 1,
+            "duplicate" => self.duplicated += 1,
+            _ => self.forwarded += 1,
+        }
+        if record.send_amount > 0 && record.sent_size != record.size {
+            self.mutated += 1;
+        }
+    }
+
+    /// Adds the counts of another set of messages.
+    ///
+    /// # Parameters
+    /// * 'other' - the counts of the other messages.
+    fn add(&mut self, other: &ActionCounts) {
+        self.handled += other.handled;
+        self.forwarded += other.forwarded;
+        self.delayed += other.delayed;
+        self.dropped += other.dropped;
+        self.duplicated += other.duplicated;
+        self.mutated += other.mutated;
+    }
+
+    /// Converts the counts to the message reported to the controller.
+    pub fn to_proto(self) -> proto::ActionCounts {
+        proto::ActionCounts {
+            handled: self.handled,
+            forwarded: self.forwarded,
+            delayed: self.delayed,
+            dropped: self.dropped,
+            duplicated: self.duplicated,
+            mutated: self.mutated,
+        }
+    }
+}
+
+/// Struct that represents the counts of the messages of a single link.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LinkCounts {
+    pub from_port: u16,
+    pub to_port: u16,
+    #[serde(flatten)]
+    pub counts: ActionCounts,
+}
+
+/// Struct that represents the counts of the messages of a single message type.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MessageTypeCounts {
+    pub message_type: MessageType,
+    #[serde(flatten)]
+    pub counts: ActionCounts,
+}
+
+/// Struct that represents the tallies that are kept while the interceptor runs.
+#[derive(Debug, Default)]
+struct Tallies {
+    links: BTreeMap<(u16, u16), ActionCounts>,
+    message_types: HashMap<MessageType, ActionCounts>,
+    link_message_types: HashMap<(u16, u16, MessageType), ActionCounts>,
+    rounds: BTreeMap<u32, RoundTally>,
+    errors: BTreeMap<String, u64>,
+}
+
+/// Struct that represents the statistics of the current run, kept in the interceptor state.
+#[derive(Debug, Default)]
+pub struct RunStatistics {
+    tallies: Mutex<Tallies>,
+}
+
+impl RunStatistics {
+    /// Counts a handled message for its link and message type, and for its consensus round if it has one.
+    ///
+    /// # Parameters
+    /// * 'record' - the record of the message.
+    pub fn tally(&self, record: &PacketRecord) {
+        let mut tallies = self.tallies.lock().unwrap();
+        tallies
+            .links
+            .entry((record.from_port, record.to_port))
+            .or_default()
+            .count(record);
+        tallies
+            .message_types
+            .entry(record.message_type)
+            .or_default()
+            .count(record);
+        tallies
+            .link_message_types
+            .entry((record.from_port, record.to_port, record.message_type))
+            .or_default()
+            .count(record);
+        if let Some(round) = record.round {
+            tallies
+                .rounds
+                .entry(round)
+                .or_insert_with(|| RoundTally::new(record.timestamp))
+                .count(record);
+        }
+    }
+
+    /// Returns the counts of the messages of every consensus round per node and message type, ordered by round.
+    pub fn round_timeline(&self) -> RoundTimeline {
+        let tallies = self.tallies.lock().unwrap();
+        RoundTimeline {
+            rounds: tallies
+                .rounds
+                .iter()
+                .map(|(&round, tally)| tally.to_counts(round))
+                .collect(),
+        }
+    }
+
+    /// Returns the counts of the messages of every link per message type, the most frequent type first.
+    pub fn link_message_types(&self) -> BTreeMap<(u16, u16), Vec<MessageTypeCounts>> {
+        let tallies = self.tallies.lock().unwrap();
+        let mut links: BTreeMap<(u16, u16), Vec<MessageTypeCounts>> = BTreeMap::new();
+        for (&(from_port, to_port, message_type), &counts) in tallies.link_message_types.iter() {
+            links
+                .entry((from_port, to_port))
+                .or_default()
+                .push(MessageTypeCounts {
+                    message_type,
+                    counts,
+                });
+        }
+        for message_types in links.values_mut() {
+            sort_by_frequency(message_types);
+        }
+        links
+    }
+
+    /// Counts an error that occurred while handling messages.
+    ///
+    /// # Parameters
+    /// * 'kind' - the kind of the error, e.g. 'rejected_action'.
+    /// * 'amount' - the amount of errors of that kind.
+    pub fn count_error(&self, kind: &str, amount: u64) {
+        *self
+            .tallies
+            .lock()
+            .unwrap()
+            .errors
+            .entry(kind.to_string())
+            .or_default() += amount;
+    }
+
+    /// Summarizes the run up to now.
+    ///
+    /// # Parameters
+    /// * 'started_at' - the moment the links started.
+    /// * 'ended_at' - the moment the run ended.
+    /// * 'ledgers_closed' - the amount of ledgers validated during the run, if it could be determined.
+    pub fn summarize(
+        &self,
+        started_at: DateTime<Utc>,
+        ended_at: DateTime<Utc>,
+        ledgers_closed: Option<u64>,
+    ) -> RunSummary {
+        let tallies = self.tallies.lock().unwrap();
+        let mut totals = ActionCounts::default();
+        let links: Vec<LinkCounts> = tallies
+            .links
+            .iter()
+            .map(|(&(from_port, to_port), counts)| {
+                totals.add(counts);
+                LinkCounts {
+                    from_port,
+                    to_port,
+                    counts: *counts,
+                }
+            })
+            .collect();
+        let mut message_types: Vec<MessageTypeCounts> = tallies
+            .message_types
+            .iter()
+            .map(|(&message_type, &counts)| MessageTypeCounts {
+                message_type,
+                counts,
+            })
+            .collect();
+        sort_by_frequency(&mut message_types);
+        RunSummary {
+            started_at: started_at.to_rfc3339(),
+            ended_at: ended_at.to_rfc3339(),
+            duration_secs: (ended_at - started_at).num_milliseconds() as f64 / 1000.0,
+            ledgers_closed,
+            totals,
+            links,
+            message_types,
+            errors: tallies.errors.clone(),
+            stop_reason: None,
+        }
+    }
+}
+
+/// Sorts the counts of message types such that the most frequent type comes first.
+///
+/// # Parameters
+/// * 'message_types' - the counts per message type.
+pub fn sort_by_frequency(message_types: &mut [MessageTypeCounts]) {
+    message_types.sort_by(|a, b| {
+        b.counts
+            .handled
+            .cmp(&a.counts.handled)
+            .then(a.message_type.value().cmp(&b.message_type.value()))
+    });
+}
+
+/// Struct that represents the summary of a run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunSummary {
+    /// The moment the links started, in RFC 3339 format.
+    pub started_at: String,
+    /// The moment the run ended, in RFC 3339 format.
+    pub ended_at: String,
+    pub duration_secs: f64,
+    /// The amount of ledgers validated during the run, None if the nodes could not be reached.
+    pub ledgers_closed: Option<u64>,
+    /// The counts of all messages.
+    pub totals: ActionCounts,
+    /// The counts per link, ordered by link.
+    pub links: Vec<LinkCounts>,
+    /// The counts per message type, the most frequent first.
+    pub message_types: Vec<MessageTypeCounts>,
+    /// The amount of errors per kind.
+    pub errors: BTreeMap<String, u64>,
+    /// Why the run ended, None while it is running.
+    pub stop_reason: Option<String>,
+}
+
+impl RunSummary {
+    /// Writes the summary as JSON to a new file in the given directory, and returns the path of that file.
+    ///
+    /// # Parameters
+    /// * 'directory' - the directory the summaries of all runs are stored in.
+    /// * 'run_id' - the identifier of the run, which names the file.
+    pub fn save(&self, directory: &str, run_id: &RunId) -> Result<PathBuf, Box<dyn Error>> {
+        fs::create_dir_all(directory)?;
+        let path = Path::new(directory).join(format!("summary-{}.json", run_id));
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+
+    /// Converts the summary to the message reported to the controller.
+    pub fn to_proto(&self) -> proto::RunResult {
+        proto::RunResult {
+            started_at: self.started_at.clone(),
+            ended_at: self.ended_at.clone(),
+            duration_ms: (self.duration_secs * 1000.0) as u64,
+            ledgers_closed: self.ledgers_closed.unwrap_or(0),
+            ledgers_closed_known: self.ledgers_closed.is_some(),
+            totals: Some(self.totals.to_proto()),
+            links: self
+                .links
+                .iter()
+                .map(|link| proto::LinkResult {
+                    from_port: u32::from(link.from_port),
+                    to_port: u32::from(link.to_port),
+                    counts: Some(link.counts.to_proto()),
+                })
+                .collect(),
+            message_types: self
+                .message_types
+                .iter()
+                .map(|message_type| proto::MessageTypeResult {
+                    message_type: message_type.message_type.to_string(),
+                    counts: Some(message_type.counts.to_proto()),
+                })
+                .collect(),
+            errors: self.errors.clone().into_iter().collect(),
+            stop_reason: self.stop_reason.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// Writes a row of the counts table.
+///
+/// # Parameters
+/// * 'f' - the formatter.
+/// * 'name' - the name of the row.
+/// * 'counts' - the counts of the row.
+fn write_counts(f: &mut fmt::Formatter<'_>, name: &str, counts: &ActionCounts) -> fmt::Result {
+    writeln!(
+        f,
+        "{:<26} {:>9} {:>9} {:>9} {:>9} {:>10} {:>9}",
+        name,
+        counts.handled,
+        counts.forwarded,
+        counts.delayed,
+        counts.dropped,
+        counts.duplicated,
+        counts.mutated
+    )
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Run summary")?;
+        writeln!(f, "Duration: {:.1} s", self.duration_secs)?;
+        if let Some(stop_reason) = &self.stop_reason {
+            writeln!(f, "Stopped: {}", stop_reason)?;
+        }
+        match self.ledgers_closed {
+            Some(ledgers_closed) => writeln!(f, "Ledgers closed: {}", ledgers_closed)?,
+            None => writeln!(f, "Ledgers closed: unknown")?,
+        }
+        if self.errors.is_empty() {
+            writeln!(f, "Errors: none")?;
+        } else {
+            let errors: Vec<String> = self
+                .errors
+                .iter()
+                .map(|(kind, amount)| format!("{} {}", amount, kind))
+                .collect();
+            writeln!(f, "Errors: {}", errors.join(", "))?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<26} {:>9} {:>9} {:>9} {:>9} {:>10} {:>9}",
+            "", "handled", "forwarded", "delayed", "dropped", "duplicated", "mutated"
+        )?;
+        for link in &self.links {
+            write_counts(
+                f,
+                &format!("{} -> {}", link.from_port, link.to_port),
+                &link.counts,
+            )?;
+        }
+        writeln!(f)?;
+        for message_type in &self.message_types {
+            write_counts(
+                f,
+                &message_type.message_type.to_string(),
+                &message_type.counts,
+            )?;
+        }
+        writeln!(f)?;
+        write_counts(f, "total", &self.totals)
+    }
+}
+
+/// Returns the highest validated ledger index of the nodes, or None if none of them could be reached.
+///
+/// # Parameters
+/// * 'nodes' - the RPC clients of the nodes.
+pub async fn validated_ledger(nodes: &[NodeRpcClient]) -> Option<u64> {
+    let mut highest = None;
+    for node in nodes {
+        match node
+            .request("ledger", json!({ "ledger_index": "validated" }))
+            .await
+        {
+            Ok(result) => {
+                if let Some(ledger_index) = result["ledger_index"].as_u64() {
+                    highest = highest.max(Some(ledger_index));
+                }
+            }
+            Err(e) => debug!(
+                "Could not fetch validated ledger of port {}: {}",
+                node.port, e
+            ),
+        }
+    }
+    highest
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::message_type::MessageType;
+    use crate::packet_timeline::PacketRecord;
+    use crate::run_summary::{ActionCounts, RunStatistics};
+    use chrono::{Duration as ChronoDuration, Utc};
+
+    fn record(
+        from_port: u16,
+        message_type: MessageType,
+        send_amount: u32,
+        action: u32,
+    ) -> PacketRecord {
+        PacketRecord {
+            from_port,
+            message_type,
+            action,
+            send_amount,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn summarize_counts_per_link_and_type() {
+        let statistics = RunStatistics::default();
+        statistics.tally(&record(60000, MessageType::Validation, 1, 0));
+        statistics.tally(&record(60000, MessageType::Validation, 0, 0));
+        statistics.tally(&record(60002, MessageType::Validation, 1, 200));
+        statistics.tally(&record(60002, MessageType::Ping, 2, 0));
+        let mut mutated = record(60002, MessageType::ProposeLedger, 1, 0);
+        mutated.sent_size = 40;
+        statistics.tally(&mutated);
+        statistics.count_error("rejected_action", 1);
+        statistics.count_error("rejected_action", 2);
+
+        let started_at = Utc::now();
+        let summary = statistics.summarize(
+            started_at,
+            started_at + ChronoDuration::seconds(90),
+            Some(12),
+        );
+
+        assert_eq!(summary.duration_secs, 90.0);
+        assert_eq!(summary.ledgers_closed, Some(12));
+        assert_eq!(
+            summary.totals,
+            ActionCounts {
+                handled: 5,
+                forwarded: 2,
+                delayed: 1,
+                dropped: 1,
+                duplicated: 1,
+                mutated: 1,
+            }
+        );
+        assert_eq!(summary.links.len(), 2);
+        assert_eq!(summary.links[0].from_port, 60000);
+        assert_eq!(summary.links[0].counts.handled, 2);
+        assert_eq!(
+            summary.message_types[0].message_type,
+            MessageType::Validation
+        );
+        assert_eq!(summary.message_types[0].counts.handled, 3);
+        assert_eq!(summary.errors["rejected_action"], 3);
+
+        let json = serde_json::to_value(&summary).unwrap();
+        assert_eq!(json["links"][1]["from_port"], 60002);
+        assert_eq!(json["links"][1]["handled"], 3);
+        assert_eq!(json["message_types"][0]["message_type"], "mtVALIDATION");
+
+        let proto = summary.to_proto();
+        assert_eq!(proto.duration_ms, 90_000);
+        assert_eq!(proto.totals.unwrap().handled, 5);
+        assert_eq!(proto.errors["rejected_action"], 3);
+
+        let table = summary.to_string();
+        assert!(table.contains("60000 -> 60001"));
+        assert!(table.contains("Errors: 3 rejected_action"));
+    }
+}
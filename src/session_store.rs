@@ -0,0 +1,413 @@
+//! This module is responsible for storing the metadata of every handled message of a run in an SQLite database,
+//! and for the `query` subcommand that slices that data after the run.
+
+use crate::packet_timeline::PacketRecord;
+use crate::record_sink::RecordSink;
+use crate::run_id::RunId;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The schema of the database, created when the database is opened for a run.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS messages (
+    id INTEGER PRIMARY KEY,
+    timestamp_ns INTEGER NOT NULL,
+    from_port INTEGER NOT NULL,
+    to_port INTEGER NOT NULL,
+    sequence INTEGER NOT NULL,
+    message_type TEXT NOT NULL,
+    ledger_sequence INTEGER,
+    size INTEGER NOT NULL,
+    sent_size INTEGER NOT NULL,
+    hash TEXT NOT NULL,
+    delay_ms INTEGER NOT NULL,
+    send_amount INTEGER NOT NULL,
+    controller_latency_us INTEGER,
+    latency_us INTEGER NOT NULL,
+    phase TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS messages_by_type_and_ledger ON messages (message_type, ledger_sequence);
+";
+
+/// The columns that are printed by the `query` subcommand.
+const QUERY_COLUMNS: &str =
+    "timestamp_ns, from_port, to_port, sequence, message_type, ledger_sequence, size, \
+    delay_ms, send_amount, controller_latency_us, hash, phase";
+
+/// The usage of the `query` subcommand.
+pub const QUERY_USAGE: &str = "Usage: rocket-interceptor query <database> [options]
+Options:
+  --type <message type>    only messages of this type, e.g. mtVALIDATION
+  --from <port>            only messages from the node with this peer port
+  --to <port>              only messages to the node with this peer port
+  --dropped                only messages that were dropped
+  --delayed                only messages that were delayed
+  --from-ledger <index>    only messages with a ledger sequence of at least this index
+  --to-ledger <index>      only messages with a ledger sequence of at most this index
+  --phase <phase>          only messages handled in this phase: warm_up, fault or cool_down
+  --limit <n>              print at most this many messages, 100 by default
+  --count                  only print the amount of matching messages
+  --sql <statement>        run this SQL statement instead, the table is called 'messages'";
+
+/// Struct that represents the sink writing handled messages to the database of a run.
+pub struct SqliteSink {
+    /// The path of the database.
+    path: PathBuf,
+    /// The connection to the database.
+    connection: Connection,
+}
+
+impl SqliteSink {
+    /// Creates the database of a new run in the given directory, named after the run ID.
+    ///
+    /// # Parameters
+    /// * 'directory' - the directory the databases of all runs are stored in.
+    /// * 'run_id' - the identifier of the run.
+    pub fn create(directory: &str, run_id: &RunId) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        fs::create_dir_all(directory)?;
+        let path = Path::new(directory).join(format!("run-{}.sqlite", run_id));
+        Self::open(&path)
+    }
+
+    /// Opens a database and creates its schema if it does not exist yet.
+    ///
+    /// # Parameters
+    /// * 'path' - the path of the database.
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let connection = Connection::open(path)?;
+        // Losing the last records when the machine crashes is acceptable, slowing down the interception is not
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.pragma_update(None, "synchronous", "NORMAL")?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            connection,
+        })
+    }
+
+    /// Returns the path of the database.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl RecordSink for SqliteSink {
+    fn name(&self) -> String {
+        format!("SQLite database {}", self.path.display())
+    }
+
+    fn write(&mut self, records: &[PacketRecord]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let transaction = self.connection.transaction()?;
+        {
+            let mut statement = transaction.prepare_cached(
+                "INSERT INTO messages (timestamp_ns, from_port, to_port, sequence, message_type, ledger_sequence, \
+                 size, sent_size, hash, delay_ms, send_amount, controller_latency_us, latency_us, phase) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            )?;
+            for record in records {
+                statement.execute(params![
+                    record.timestamp.timestamp_nanos_opt().unwrap_or(0),
+                    record.from_port,
+                    record.to_port,
+                    record.sequence as i64,
+                    record.message_type.to_string(),
+                    record.ledger_sequence,
+                    record.size as i64,
+                    record.sent_size as i64,
+                    record.hash,
+                    record.action,
+                    record.send_amount,
+                    record
+                        .controller_latency
+                        .map(|latency| latency.as_micros() as i64),
+                    record.latency.as_micros() as i64,
+                    record.phase.name(),
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+}
+
+/// Struct that represents the filters of the `query` subcommand.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    /// The path of the database.
+    pub database: String,
+    pub message_type: Option<String>,
+    pub from_port: Option<u16>,
+    pub to_port: Option<u16>,
+    pub dropped: bool,
+    pub delayed: bool,
+    pub from_ledger: Option<u32>,
+    pub to_ledger: Option<u32>,
+    pub phase: Option<String>,
+    pub limit: u32,
+    pub count: bool,
+    /// A raw SQL statement, which replaces all filters.
+    pub sql: Option<String>,
+}
+
+impl Query {
+    /// Parses the arguments of the `query` subcommand, the arguments following `query`.
+    ///
+    /// # Parameters
+    /// * 'args' - the arguments.
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut args = args.iter();
+        let mut query = Query {
+            database: args.next().ok_or("Missing the database")?.clone(),
+            limit: 100,
+            ..Query::default()
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| format!("Missing the value of {}", arg))
+            };
+            let number = |value: String| {
+                value
+                    .parse::<u32>()
+                    .map_err(|_| format!("Invalid value of {}: {}", arg, value))
+            };
+            match arg.as_str() {
+                "--type" => query.message_type = Some(value()?),
+                "--from" => query.from_port = Some(number(value()?)? as u16),
+                "--to" => query.to_port = Some(number(value()?)? as u16),
+                "--dropped" => query.dropped = true,
+                "--delayed" => query.delayed = true,
+                "--from-ledger" => query.from_ledger = Some(number(value()?)?),
+                "--to-ledger" => query.to_ledger = Some(number(value()?)?),
+                "--phase" => query.phase = Some(value()?),
+                "--limit" => query.limit = number(value()?)?,
+                "--count" => query.count = true,
+                "--sql" => query.sql = Some(value()?),
+                _ => return Err(format!("Unknown option {}", arg)),
+            }
+        }
+        Ok(query)
+    }
+
+    /// Returns the SQL statement and its parameters.
+    pub fn to_sql(&self) -> (String, Vec<Value>) {
+        if let Some(sql) = &self.sql {
+            return (sql.clone(), Vec::new());
+        }
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+        let mut condition = |condition: &str, value: Value| {
+            values.push(value);
+            conditions.push(format!("{} ?{}", condition, values.len()));
+        };
+        if let Some(message_type) = &self.message_type {
+            condition("message_type =", Value::Text(message_type.clone()));
+        }
+        if let Some(from_port) = self.from_port {
+            condition("from_port =", Value::Integer(from_port.into()));
+        }
+        if let Some(to_port) = self.to_port {
+            condition("to_port =", Value::Integer(to_port.into()));
+        }
+        if let Some(from_ledger) = self.from_ledger {
+            condition("ledger_sequence >=", Value::Integer(from_ledger.into()));
+        }
+        if let Some(to_ledger) = self.to_ledger {
+            condition("ledger_sequence <=", Value::Integer(to_ledger.into()));
+        }
+        if let Some(phase) = &self.phase {
+            condition("phase =", Value::Text(phase.clone()));
+        }
+        if self.dropped {
+            conditions.push("send_amount = 0".to_string());
+        }
+        if self.delayed {
+            conditions.push("delay_ms > 0".to_string());
+        }
+
+        let columns = if self.count {
+            "COUNT(*)"
+        } else {
+            QUERY_COLUMNS
+        };
+        let mut sql = format!("SELECT {} FROM messages", columns);
+        if !conditions.is_empty() {
+            sql += &format!(" WHERE {}", conditions.join(" AND "));
+        }
+        if !self.count {
+            sql += &format!(" ORDER BY timestamp_ns LIMIT {}", self.limit);
+        }
+        (sql, values)
+    }
+
+    /// Runs the query and returns the names of the columns and the rows, formatted as text.
+    pub fn run(&self) -> Result<(Vec<String>, Vec<Vec<String>>), Box<dyn Error + Send + Sync>> {
+        if !Path::new(&self.database).exists() {
+            return Err(format!("Database {} does not exist", self.database).into());
+        }
+        let connection = Connection::open(&self.database)?;
+        let (sql, values) = self.to_sql();
+        let mut statement = connection.prepare(&sql)?;
+        let columns: Vec<String> = statement
+            .column_names()
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        let rows = statement
+            .query_map(params_from_iter(values), |row| {
+                (0..columns.len())
+                    .map(|i| {
+                        Ok(match row.get::<_, Value>(i)? {
+                            Value::Null => "-".to_string(),
+                            Value::Integer(value) => value.to_string(),
+                            Value::Real(value) => value.to_string(),
+                            Value::Text(value) => value,
+                            Value::Blob(value) => hex::encode(value),
+                        })
+                    })
+                    .collect::<Result<Vec<String>, rusqlite::Error>>()
+            })?
+            .collect::<Result<Vec<Vec<String>>, rusqlite::Error>>()?;
+        Ok((columns, rows))
+    }
+}
+
+/// Runs the `query` subcommand and prints the result as a table.
+///
+/// # Parameters
+/// * 'args' - the arguments following `query`.
+pub fn query_command(args: &[String]) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let query = Query::parse(args).map_err(|e| format!("{}\n{}", e, QUERY_USAGE))?;
+    let (columns, rows) = query.run()?;
+    let widths: Vec<usize> = (0..columns.len())
+        .map(|i| {
+            rows.iter()
+                .map(|row| row[i].len())
+                .chain([columns[i].len()])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let format_row = |row: &[String]| {
+        row.iter()
+            .zip(&widths)
+            .map(|(value, width)| format!("{:<width$}", value, width = width))
+            .collect::<Vec<String>>()
+            .join("  ")
+    };
+    println!("{}", format_row(&columns));
+    for row in &rows {
+        println!("{}", format_row(row));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::packet_timeline::PacketRecord;
+    use crate::record_sink::RecordSink;
+    use crate::run_id::RunId;
+    use crate::session_store::{Query, SqliteSink};
+    use std::fs;
+    use std::time::Duration;
+
+    fn record(sequence: u64, ledger_sequence: u32, send_amount: u32) -> PacketRecord {
+        PacketRecord {
+            sequence,
+            ledger_sequence: Some(ledger_sequence),
+            size: 100,
+            hash: format!("{:064x}", sequence),
+            sent_size: 100,
+            send_amount,
+            controller_latency: Some(Duration::from_micros(250)),
+            latency: Duration::from_millis(1),
+            ..Default::default()
+        }
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_query_arguments() {
+        let query = Query::parse(&args(&[
+            "run.sqlite",
+            "--type",
+            "mtVALIDATION",
+            "--dropped",
+            "--from-ledger",
+            "5",
+            "--to-ledger",
+            "10",
+            "--phase",
+            "cool_down",
+        ]))
+        .unwrap();
+        assert_eq!(query.database, "run.sqlite");
+        assert_eq!(query.message_type.as_deref(), Some("mtVALIDATION"));
+        assert!(query.dropped);
+        assert_eq!(query.from_ledger, Some(5));
+        assert_eq!(query.to_ledger, Some(10));
+        assert_eq!(query.phase.as_deref(), Some("cool_down"));
+        assert_eq!(query.limit, 100);
+
+        assert!(Query::parse(&[]).is_err());
+        assert!(Query::parse(&args(&["run.sqlite", "--limit"])).is_err());
+        assert!(Query::parse(&args(&["run.sqlite", "--limit", "many"])).is_err());
+        assert!(Query::parse(&args(&["run.sqlite", "--nonsense"])).is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn store_and_query_dropped_validations() {
+        let directory = std::env::temp_dir().join("rocket_session_store_test");
+        let _ = fs::remove_dir_all(&directory);
+        let mut sink =
+            SqliteSink::create(directory.to_str().unwrap(), &RunId("test".to_string())).unwrap();
+        let records: Vec<PacketRecord> = (0..20)
+            .map(|i| record(i, i as u32, if i % 2 == 0 { 0 } else { 1 }))
+            .collect();
+        sink.write(&records).unwrap();
+        let path = sink.path.clone();
+        drop(sink);
+
+        let query = Query::parse(&args(&[
+            path.to_str().unwrap(),
+            "--type",
+            "mtVALIDATION",
+            "--dropped",
+            "--from-ledger",
+            "4",
+            "--to-ledger",
+            "10",
+        ]))
+        .unwrap();
+        let (columns, rows) = query.run().unwrap();
+        assert_eq!(columns[5], "ledger_sequence");
+        let ledgers: Vec<&str> = rows.iter().map(|row| row[5].as_str()).collect();
+        assert_eq!(ledgers, vec!["4", "6", "8", "10"]);
+        assert_eq!(rows[0][9], "250");
+
+        let count = Query {
+            count: true,
+            ..query
+        };
+        let (_, rows) = count.run().unwrap();
+        assert_eq!(rows, vec![vec!["4".to_string()]]);
+
+        let warm_up = Query {
+            phase: Some("warm_up".to_string()),
+            ..count
+        };
+        let (_, rows) = warm_up.run().unwrap();
+        assert_eq!(rows, vec![vec!["0".to_string()]]);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
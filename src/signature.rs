@@ -0,0 +1,246 @@
+//! This module is responsible for verifying the signatures of intercepted proposals and validations against the keys of
+//! the nodes of the network, such that the controller can tell when nodes are fed forged messages, e.g. mutated
+//! messages that were not signed again or messages of identities that are not part of the network.
+//!
+//! A proposal is signed over its signing hash, see `ConsensusProposal::signingHash` in rippled. A validation is signed
+//! over its serialized `STValidation` without the signature, prefixed with `HashPrefix::validation`, see `STObject` in
+//! rippled: hashed with SHA-512Half for secp256k1 keys, and as-is for ed25519 keys.
+
+use crate::field_mutation;
+use crate::keygen::ED25519_PREFIX;
+use crate::message_decoder::{self, ObjectField, SF_SIGNATURE, SF_SIGNING_PUB_KEY, ST_BLOB};
+use crate::message_type::MessageType;
+use crate::wire_format::{
+    decode_fields, WireValue, PROPOSE_FIELD_NODE_PUB_KEY, PROPOSE_FIELD_SIGNATURE,
+    VALIDATION_FIELD_VALIDATION,
+};
+use ed25519_dalek::Verifier;
+use secp256k1::ecdsa::Signature;
+use secp256k1::{Message as CryptoMessage, PublicKey, Secp256k1};
+use sha2::{Digest, Sha512};
+
+/// The prefix of the data that is signed in a validation, `HashPrefix::validation` in rippled.
+const VALIDATION_HASH_PREFIX: [u8; 4] = *b"VAL\0";
+
+/// Verifies the signature of a proposal or validation. Returns whether it is signed by one of the known keys with a
+/// valid signature, or None if the message is neither a proposal nor a validation. A proposal or validation that can
+/// not be decoded is not valid.
+///
+/// # Parameters
+/// * 'message' - the message including its 6 byte header.
+/// * 'is_known' - whether a public key is one of the keys of the nodes of the network.
+pub fn verify(message: &[u8], is_known: impl Fn(&[u8]) -> bool) -> Option<bool> {
+    let message_type = MessageType::from_message(message)?;
+    if !matches!(
+        message_type,
+        MessageType::ProposeLedger | MessageType::Validation
+    ) {
+        return None;
+    }
+    let verified = || {
+        let payload_size = u32::from_be_bytes(message[0..4].try_into().unwrap()) as usize;
+        let fields = decode_fields(message.get(6..6 + payload_size)?)?;
+        let bytes = |number| {
+            fields.iter().find_map(|field| match &field.value {
+                WireValue::LengthDelimited(value) if field.number == number => {
+                    Some(value.as_slice())
+                }
+                _ => None,
+            })
+        };
+        if message_type == MessageType::ProposeLedger {
+            let public_key = bytes(PROPOSE_FIELD_NODE_PUB_KEY)?;
+            let signing_hash = field_mutation::proposal_signing_hash(&fields)?;
+            return Some(
+                is_known(public_key)
+                    && verify_digest(public_key, signing_hash, bytes(PROPOSE_FIELD_SIGNATURE)?),
+            );
+        }
+        let validation = bytes(VALIDATION_FIELD_VALIDATION)?;
+        let object: Vec<ObjectField> = message_decoder::object_fields(validation).collect();
+        let public_key = message_decoder::object_field(&object, ST_BLOB, SF_SIGNING_PUB_KEY)?;
+        let signature = message_decoder::object_field(&object, ST_BLOB, SF_SIGNATURE)?;
+        let mut data = VALIDATION_HASH_PREFIX.to_vec();
+        data.extend(message_decoder::without_field(
+            validation,
+            ST_BLOB,
+            SF_SIGNATURE,
+        )?);
+        Some(is_known(public_key) && verify_data(public_key, &data, signature))
+    };
+    Some(verified().unwrap_or(false))
+}
+
+/// Verifies a signature over data, with ed25519 for a key that starts with 0xED and with secp256k1 over the SHA-512Half
+/// of the data otherwise.
+///
+/// # Parameters
+/// * 'public_key' - the public key of the signer.
+/// * 'data' - the signed data.
+/// * 'signature' - the signature.
+pub fn verify_data(public_key: &[u8], data: &[u8], signature: &[u8]) -> bool {
+    match public_key.split_first() {
+        Some((&ED25519_PREFIX, key)) => {
+            let Some(key) = <[u8; 32]>::try_from(key)
+                .ok()
+                .and_then(|key| ed25519_dalek::VerifyingKey::from_bytes(&key).ok())
+            else {
+                return false;
+            };
+            let Ok(signature) = ed25519_dalek::Signature::from_slice(signature) else {
+                return false;
+            };
+            key.verify(data, &signature).is_ok()
+        }
+        _ => verify_digest(
+            public_key,
+            Sha512::digest(data)[..32].try_into().unwrap(),
+            signature,
+        ),
+    }
+}
+
+/// Verifies a DER encoded secp256k1 signature over a digest. Like rippled, a signature with a high S is accepted too.
+///
+/// # Parameters
+/// * 'public_key' - the compressed public key of the signer.
+/// * 'digest' - the signed digest.
+/// * 'signature' - the signature.
+fn verify_digest(public_key: &[u8], digest: [u8; 32], signature: &[u8]) -> bool {
+    let (Ok(public_key), Ok(mut signature)) = (
+        PublicKey::from_slice(public_key),
+        Signature::from_der(signature),
+    ) else {
+        return false;
+    };
+    signature.normalize_s();
+    let digest = CryptoMessage::from_digest_slice(&digest).unwrap();
+    Secp256k1::verification_only()
+        .verify_ecdsa(&digest, &signature, &public_key)
+        .is_ok()
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::field_mutation::{self, frame, FieldMutation, FieldOperation};
+    use crate::keygen::{KeyType, NodeKeys, Seed};
+    use crate::message_type::MessageType;
+    use crate::signature::verify;
+    use ed25519_dalek::Signer;
+    use prost::encoding::encode_varint;
+    use secp256k1::{Message as CryptoMessage, Secp256k1};
+    use sha2::{Digest, Sha512};
+    use std::collections::HashSet;
+
+    fn proposal(keys: &NodeKeys, close_time: u64) -> Vec<u8> {
+        let mut payload = Vec::new();
+        encode_varint(1 << 3, &mut payload);
+        encode_varint(3, &mut payload);
+        payload.extend_from_slice(&[0x12, 32]);
+        payload.extend_from_slice(&[0xAA; 32]);
+        payload.extend_from_slice(&[0x1A, 33]);
+        payload.extend_from_slice(&keys.public_key);
+        encode_varint(4 << 3, &mut payload);
+        encode_varint(close_time, &mut payload);
+        payload.extend_from_slice(&[0x2A, 2, 0xDE, 0xAD]);
+        payload.extend_from_slice(&[0x32, 32]);
+        payload.extend_from_slice(&[0xBB; 32]);
+        let proposal = frame(MessageType::ProposeLedger, &payload);
+        field_mutation::resign(&proposal, &keys.secp256k1_secret_key().unwrap())
+            .unwrap()
+            .to_vec()
+    }
+
+    fn validation(keys: &NodeKeys, ledger_seq: u32) -> Vec<u8> {
+        // sfFlags, sfLedgerSequence, sfSigningTime and sfSigningPubKey
+        let mut validation = vec![0x22, 0x80, 0, 0, 1, 0x26];
+        validation.extend_from_slice(&ledger_seq.to_be_bytes());
+        validation.extend_from_slice(&[0x29, 0, 0, 0, 9, 0x73, 33]);
+        validation.extend_from_slice(&keys.public_key);
+        let mut data = b"VAL\0".to_vec();
+        data.extend_from_slice(&validation);
+        let signature = match keys.key_type {
+            KeyType::Secp256k1 => {
+                let digest =
+                    CryptoMessage::from_digest_slice(&Sha512::digest(&data)[..32]).unwrap();
+                Secp256k1::new()
+                    .sign_ecdsa(&digest, &keys.secp256k1_secret_key().unwrap())
+                    .serialize_der()
+                    .to_vec()
+            }
+            KeyType::Ed25519 => ed25519_dalek::SigningKey::from_bytes(&keys.secret_key)
+                .sign(&data)
+                .to_bytes()
+                .to_vec(),
+        };
+        // sfSignature
+        validation.extend_from_slice(&[0x76, signature.len() as u8]);
+        validation.extend_from_slice(&signature);
+        let mut payload = Vec::new();
+        encode_varint(1 << 3 | 2, &mut payload);
+        encode_varint(validation.len() as u64, &mut payload);
+        payload.extend_from_slice(&validation);
+        frame(MessageType::Validation, &payload).to_vec()
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn proposals_of_known_nodes_are_valid() {
+        let node = NodeKeys::derive(Seed::from_passphrase("node"), KeyType::Secp256k1);
+        let sybil = NodeKeys::derive(Seed::from_passphrase("sybil"), KeyType::Secp256k1);
+        let known_keys = HashSet::from([node.public_key.clone()]);
+
+        let message = proposal(&node, 1000);
+        assert_eq!(verify(&message, |key| known_keys.contains(key)), Some(true));
+        // A mutated close time that was not signed again
+        let forged = field_mutation::apply(
+            &message,
+            &[FieldMutation {
+                path: "closeTime".to_string(),
+                operation: FieldOperation::Add { delta: 5 },
+            }],
+        )
+        .unwrap();
+        assert_eq!(verify(&forged, |key| known_keys.contains(key)), Some(false));
+        // A valid signature of an identity that is not part of the network
+        assert_eq!(
+            verify(&proposal(&sybil, 1000), |key| known_keys.contains(key)),
+            Some(false)
+        );
+        assert_eq!(
+            verify(&message[..20], |key| known_keys.contains(key)),
+            Some(false)
+        );
+
+        let ping = frame(MessageType::Ping, &[0x08, 0]);
+        assert_eq!(verify(&ping, |key| known_keys.contains(key)), None);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn validations_of_both_key_types_are_verified() {
+        let seed = Seed::from_passphrase("node");
+        let secp256k1 = NodeKeys::derive(seed, KeyType::Secp256k1);
+        let ed25519 = NodeKeys::derive(seed, KeyType::Ed25519);
+        let known_keys = HashSet::from([secp256k1.public_key.clone(), ed25519.public_key.clone()]);
+
+        for keys in [&secp256k1, &ed25519] {
+            let mut validation = validation(keys, 7);
+            assert_eq!(
+                verify(&validation, |key| known_keys.contains(key)),
+                Some(true)
+            );
+            // The last byte of the ledger sequence, which is signed
+            validation[6 + 2 + 9] ^= 1;
+            assert_eq!(
+                verify(&validation, |key| known_keys.contains(key)),
+                Some(false)
+            );
+        }
+        let unknown = HashSet::from([secp256k1.public_key.clone()]);
+        assert_eq!(
+            verify(&validation(&ed25519, 7), |key| unknown.contains(key)),
+            Some(false)
+        );
+    }
+}
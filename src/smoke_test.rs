@@ -0,0 +1,355 @@
+//! This module is responsible for the end-to-end smoke test, which checks that the interceptor still does its job after
+//! a change: a network of three validators is started in Docker with a mock controller that forwards every message,
+//! after which every link has to come up and carry messages, and the validators have to keep closing ledgers.
+//!
+//! The test needs a Docker engine, but no controller, and is only compiled with the `e2e` feature, see TESTING.md.
+
+use crate::action::{PROTO_VERSION, SUPPORTED_ACTIONS};
+use crate::config::{InterceptorConfig, PortAllocation, PortConfig};
+use crate::controller_pool::ControllerEndpoint;
+use crate::docker_manager::DockerNetwork;
+use crate::event_bus::EventKind;
+use crate::packet_client::proto::packet_service_server::{PacketService, PacketServiceServer};
+use crate::packet_client::proto::{
+    Alert, AlertAck, Config, ContainerStats, ContainerStatsAck, EclipseCommand,
+    EclipseSubscription, EndSignal, EndSubscription, GetConfig, Heartbeat, HeartbeatAck, Packet,
+    PacketAck, RunResult, RunResultAck, ValidatorNodeInfo, ValidatorNodeInfoAck, VersionRequest,
+    VersionResponse,
+};
+use crate::packet_client::PacketClient;
+use crate::topology::Topology;
+use crate::{connect_nodes, decision_state, handle_messages, rpc_clients, run_summary};
+use std::collections::{HashMap, HashSet};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Mutex as AsyncMutex;
+use tokio::time::Instant;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+use tracing::info;
+
+/// How long the links are given to carry their first messages.
+const LINK_TIMEOUT: Duration = Duration::from_secs(60);
+/// How many ledgers have to be validated after the links came up.
+const LEDGERS_TO_CLOSE: u64 = 3;
+/// How long the validators are given to validate these ledgers.
+const LEDGER_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Struct that represents a controller that forwards every message unchanged, and remembers what the interceptor sent.
+#[derive(Debug)]
+struct MockController {
+    /// The network configuration handed to the interceptor.
+    config: Config,
+    /// The nodes the interceptor reported after starting them.
+    nodes: Mutex<Vec<ValidatorNodeInfo>>,
+    /// The amount of messages asked about, by the ports of the peers they came from and went to.
+    packets: Mutex<HashMap<(u32, u32), u64>>,
+}
+
+impl MockController {
+    /// Initializes a new MockController that has not been asked anything yet.
+    ///
+    /// # Parameters
+    /// * 'config' - the network configuration handed to the interceptor.
+    fn new(config: Config) -> Self {
+        Self {
+            config,
+            nodes: Mutex::new(Vec::new()),
+            packets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the links that carried at least one message.
+    fn active_links(&self) -> HashSet<(u32, u32)> {
+        self.packets.lock().unwrap().keys().copied().collect()
+    }
+}
+
+#[tonic::async_trait]
+impl PacketService for MockController {
+    async fn get_version(
+        &self,
+        _request: Request<VersionRequest>,
+    ) -> Result<Response<VersionResponse>, Status> {
+        Ok(Response::new(VersionResponse {
+            controller_version: "mock".to_string(),
+            proto_version: PROTO_VERSION,
+            supported_actions: SUPPORTED_ACTIONS.iter().map(|a| a.to_string()).collect(),
+        }))
+    }
+
+    async fn send_packet(&self, request: Request<Packet>) -> Result<Response<PacketAck>, Status> {
+        let packet = request.into_inner();
+        *self
+            .packets
+            .lock()
+            .unwrap()
+            .entry((packet.from_port, packet.to_port))
+            .or_default() += 1;
+        // Without actions the message is forwarded unchanged
+        Ok(Response::new(PacketAck::default()))
+    }
+
+    async fn send_validator_node_info(
+        &self,
+        request: Request<Streaming<ValidatorNodeInfo>>,
+    ) -> Result<Response<ValidatorNodeInfoAck>, Status> {
+        let mut stream = request.into_inner();
+        while let Some(node) = stream.message().await? {
+            self.nodes.lock().unwrap().push(node);
+        }
+        Ok(Response::new(ValidatorNodeInfoAck {
+            status: "ok".to_string(),
+        }))
+    }
+
+    async fn get_config(&self, _request: Request<GetConfig>) -> Result<Response<Config>, Status> {
+        Ok(Response::new(self.config.clone()))
+    }
+
+    async fn report_run_result(
+        &self,
+        _request: Request<RunResult>,
+    ) -> Result<Response<RunResultAck>, Status> {
+        Ok(Response::new(RunResultAck {}))
+    }
+
+    type subscribe_eclipseStream = tokio_stream::Pending<Result<EclipseCommand, Status>>;
+
+    async fn subscribe_eclipse(
+        &self,
+        _request: Request<EclipseSubscription>,
+    ) -> Result<Response<Self::subscribe_eclipseStream>, Status> {
+        Ok(Response::new(tokio_stream::pending()))
+    }
+
+    async fn send_heartbeats(
+        &self,
+        request: Request<Streaming<Heartbeat>>,
+    ) -> Result<Response<HeartbeatAck>, Status> {
+        let mut stream = request.into_inner();
+        while stream.message().await?.is_some() {}
+        Ok(Response::new(HeartbeatAck {}))
+    }
+
+    async fn send_container_stats(
+        &self,
+        request: Request<Streaming<ContainerStats>>,
+    ) -> Result<Response<ContainerStatsAck>, Status> {
+        let mut stream = request.into_inner();
+        while stream.message().await?.is_some() {}
+        Ok(Response::new(ContainerStatsAck {}))
+    }
+
+    async fn send_alerts(
+        &self,
+        request: Request<Streaming<Alert>>,
+    ) -> Result<Response<AlertAck>, Status> {
+        let mut stream = request.into_inner();
+        while stream.message().await?.is_some() {}
+        Ok(Response::new(AlertAck {}))
+    }
+
+    type subscribe_endStream = tokio_stream::Pending<Result<EndSignal, Status>>;
+
+    async fn subscribe_end(
+        &self,
+        _request: Request<EndSubscription>,
+    ) -> Result<Response<Self::subscribe_endStream>, Status> {
+        Ok(Response::new(tokio_stream::pending()))
+    }
+}
+
+/// Serves a mock controller on a free local port, and returns a PacketClient connected to it.
+///
+/// # Parameters
+/// * 'controller' - the mock controller.
+///
+/// # Panics
+/// * If no port is free, or the client could not connect within a few seconds.
+async fn serve_mock_controller(controller: Arc<MockController>) -> (String, PacketClient) {
+    let address: SocketAddr = TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .expect("No free port for the mock controller");
+    tokio::spawn(
+        Server::builder()
+            .add_service(PacketServiceServer::from_arc(controller))
+            .serve(address),
+    );
+    let address = format!("http://{}", address);
+    for _ in 0..50 {
+        if let Ok(mut client) = PacketClient::connect(&address).await {
+            client
+                .negotiate_version()
+                .await
+                .expect("Could not negotiate the protocol version with the mock controller");
+            return (address, client);
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("Could not connect to the mock controller at {}", address);
+}
+
+/// Runs the checks of the smoke test on a started network, and returns what failed, if anything.
+///
+/// # Parameters
+/// * 'network' - the started network, whose nodes are not connected yet.
+/// * 'interceptor_config' - the configuration of the interceptor.
+/// * 'controller' - the mock controller.
+/// * 'address' - the address of the mock controller.
+/// * 'client' - the PacketClient connected to the mock controller.
+async fn check_network(
+    network: &DockerNetwork,
+    interceptor_config: &InterceptorConfig,
+    controller: &MockController,
+    address: String,
+    client: Arc<AsyncMutex<PacketClient>>,
+) -> Result<(), String> {
+    let reported = controller.nodes.lock().unwrap().len();
+    if reported != 3 {
+        return Err(format!(
+            "{} nodes were reported to the controller",
+            reported
+        ));
+    }
+
+    let topology = Topology::from_partitions(3, &Vec::new());
+    let nodes = connect_nodes(
+        network,
+        None,
+        &topology,
+        &interceptor_config.handshake,
+        &interceptor_config.timeouts,
+        &interceptor_config.tls,
+    )
+    .await;
+    let ports: Vec<u16> = network
+        .containers
+        .iter()
+        .map(|container| container.port_peer as u16)
+        .collect();
+    let state = decision_state(
+        interceptor_config,
+        &ports,
+        vec![ControllerEndpoint {
+            address,
+            channels: vec![client.clone()],
+            unreachable: None,
+        }],
+    );
+    let mut events = state.events.subscribe();
+    let message_handlers = handle_messages(
+        nodes,
+        client,
+        state.clone(),
+        &interceptor_config.queues,
+        interceptor_config.keepalive.as_ref(),
+        None,
+        &interceptor_config.large_messages,
+        &interceptor_config.malformed_frames,
+    );
+    let result = async {
+        // Every pair of the three nodes is linked in both directions
+        let expected: HashSet<(u32, u32)> = ports
+            .iter()
+            .flat_map(|from| {
+                ports
+                    .iter()
+                    .filter(move |to| *to != from)
+                    .map(move |to| (*from as u32, *to as u32))
+            })
+            .collect();
+        let mut connected = HashSet::new();
+        while let Ok(event) = events.try_recv() {
+            if let EventKind::LinkConnected {
+                from_port, to_port, ..
+            } = &event.kind
+            {
+                connected.insert((*from_port as u32, *to_port as u32));
+            }
+        }
+        if connected != expected {
+            return Err(format!("Only links {:?} were connected", connected));
+        }
+
+        let deadline = Instant::now() + LINK_TIMEOUT;
+        while controller.active_links() != expected {
+            if Instant::now() > deadline {
+                return Err(format!(
+                    "Only links {:?} carried messages within {:?}",
+                    controller.active_links(),
+                    LINK_TIMEOUT
+                ));
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+        info!("All links carry messages");
+
+        let rpc_clients = rpc_clients(network);
+        let deadline = Instant::now() + LEDGER_TIMEOUT;
+        let start = run_summary::validated_ledger(&rpc_clients).await;
+        loop {
+            let current = run_summary::validated_ledger(&rpc_clients).await;
+            if let (Some(start), Some(current)) = (start, current) {
+                if current >= start + LEDGERS_TO_CLOSE {
+                    info!("Ledgers {} to {} were validated", start, current);
+                    return Ok(());
+                }
+            }
+            if Instant::now() > deadline {
+                return Err(format!(
+                    "The validated ledger went from {:?} to {:?} within {:?}",
+                    start, current, LEDGER_TIMEOUT
+                ));
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+    .await;
+    for message_handler in message_handlers {
+        message_handler.abort();
+    }
+    result
+}
+
+// Note: This test requires a running docker engine, and stops the validator containers that are running
+#[tokio::test(flavor = "multi_thread")]
+// #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+async fn docker_three_node_smoke_test() {
+    let interceptor_config = InterceptorConfig {
+        ports: PortConfig {
+            allocation: PortAllocation::Auto,
+            ..PortConfig::default()
+        },
+        ..InterceptorConfig::default()
+    };
+    let controller = Arc::new(MockController::new(Config {
+        base_port_peer: 60000,
+        base_port_ws: 61000,
+        base_port_ws_admin: 62000,
+        base_port_rpc: 63000,
+        number_of_nodes: 3,
+        net_partitions: vec![],
+        unl_partitions: vec![],
+    }));
+    let (address, client) = serve_mock_controller(controller.clone()).await;
+    let client = Arc::new(AsyncMutex::new(client));
+    let network_config = client
+        .lock()
+        .await
+        .get_config()
+        .await
+        .expect("Could not get config from the mock controller");
+
+    let mut network = DockerNetwork::new(network_config);
+    network.set_ports(interceptor_config.ports.clone(), false);
+    network.initialize_network(client.clone()).await;
+    network.wait_for_startup().await;
+
+    let result = check_network(&network, &interceptor_config, &controller, address, client).await;
+    network.stop_network().await;
+    if let Err(e) = result {
+        panic!("The smoke test failed: {}", e);
+    }
+}
@@ -0,0 +1,344 @@
+//! This module is responsible for the squelches of reduce-relay, see `reduce-relay` in rippled: with an mtSQUELCH message
+//! a node asks a peer to stop relaying the proposals and validations of a validator to it for a while, or to resume
+//! relaying them. The squelches the nodes send are decoded and tracked per link, such that it is known which peers are
+//! asked to stay silent about which validators.
+//!
+//! The squelches can be overridden on selected links: suppressed, such that the peer keeps relaying, or amplified, such
+//! that the peer stays silent for as long as rippled allows. Squelches can also be forged on behalf of a node.
+
+use crate::config::SquelchRuleConfig;
+use crate::field_mutation;
+use crate::message_type::MessageType;
+use crate::wire_format::{decode_fields, encode_fields, WireField, WireValue};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// The shortest squelch a node accepts, `MIN_UNSQUELCH_EXPIRE` in rippled.
+pub const MIN_SQUELCH_DURATION_SECS: u32 = 300;
+/// The longest squelch a node accepts from a peer, `MAX_UNSQUELCH_EXPIRE_PEERS` in rippled.
+pub const MAX_SQUELCH_DURATION_SECS: u32 = 3600;
+/// The field number of whether the validator is squelched in `TMSquelch`.
+const SQUELCH_FIELD_SQUELCH: u64 = 1;
+/// The field number of the public key of the validator in `TMSquelch`.
+const SQUELCH_FIELD_VALIDATOR_PUB_KEY: u64 = 2;
+/// The field number of the duration of the squelch in `TMSquelch`.
+const SQUELCH_FIELD_DURATION: u64 = 3;
+
+/// Struct that represents a decoded mtSQUELCH message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Squelch {
+    /// Whether the relaying of the validator stops, or resumes if false.
+    pub squelch: bool,
+    /// The public key of the validator.
+    #[serde(with = "hex::serde")]
+    pub validator_key: Vec<u8>,
+    /// How long the relaying stops in seconds, if set.
+    #[serde(default)]
+    pub duration_secs: Option<u32>,
+}
+
+impl Squelch {
+    /// Decodes an mtSQUELCH message. Returns None if it is not one, or if it misses a required field.
+    ///
+    /// # Parameters
+    /// * 'message' - the message including its 6 byte header.
+    pub fn from_message(message: &[u8]) -> Option<Self> {
+        if MessageType::from_message(message)? != MessageType::Squelch {
+            return None;
+        }
+        let payload_size = u32::from_be_bytes(message[0..4].try_into().unwrap()) as usize;
+        let fields = decode_fields(message.get(6..6 + payload_size)?)?;
+        let varint = |number| {
+            fields.iter().find_map(|field| match field.value {
+                WireValue::Varint(value) if field.number == number => Some(value),
+                _ => None,
+            })
+        };
+        let validator_key = fields.iter().find_map(|field| match &field.value {
+            WireValue::LengthDelimited(key) if field.number == SQUELCH_FIELD_VALIDATOR_PUB_KEY => {
+                Some(key.clone())
+            }
+            _ => None,
+        })?;
+        Some(Self {
+            squelch: varint(SQUELCH_FIELD_SQUELCH)? != 0,
+            validator_key,
+            duration_secs: varint(SQUELCH_FIELD_DURATION).map(|duration| duration as u32),
+        })
+    }
+
+    /// Frames the squelch as an mtSQUELCH message.
+    pub fn to_message(&self) -> Bytes {
+        let mut fields = vec![
+            WireField {
+                number: SQUELCH_FIELD_SQUELCH,
+                value: WireValue::Varint(self.squelch as u64),
+            },
+            WireField {
+                number: SQUELCH_FIELD_VALIDATOR_PUB_KEY,
+                value: WireValue::LengthDelimited(self.validator_key.clone()),
+            },
+        ];
+        if let Some(duration_secs) = self.duration_secs {
+            fields.push(WireField {
+                number: SQUELCH_FIELD_DURATION,
+                value: WireValue::Varint(duration_secs as u64),
+            });
+        }
+        field_mutation::frame(MessageType::Squelch, &encode_fields(&fields))
+    }
+
+    /// Returns how long the relaying stops, or None if the squelch resumes it or would be rejected by the peer for its
+    /// duration.
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration_secs
+            .filter(|duration| {
+                self.squelch
+                    && (MIN_SQUELCH_DURATION_SECS..=MAX_SQUELCH_DURATION_SECS).contains(duration)
+            })
+            .map(|duration| Duration::from_secs(duration as u64))
+    }
+
+    /// Applies an override to the squelch. Returns the squelch as it is sent on, or None if it is suppressed.
+    ///
+    /// # Parameters
+    /// * 'action' - the override.
+    pub fn apply(&self, action: SquelchAction) -> Option<Self> {
+        match action {
+            SquelchAction::Suppress => None,
+            SquelchAction::Amplify { duration_secs } => Some(Self {
+                squelch: true,
+                validator_key: self.validator_key.clone(),
+                duration_secs: Some(duration_secs.unwrap_or(MAX_SQUELCH_DURATION_SECS)),
+            }),
+        }
+    }
+}
+
+/// Enum that represents how the squelches of a validator are overridden.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum SquelchAction {
+    /// The squelches and unsquelches are dropped, such that the peer keeps relaying as it did.
+    Suppress,
+    /// Every squelch lasts for the duration, and every unsquelch is turned into such a squelch, such that the peer stays
+    /// silent about the validator.
+    Amplify {
+        /// The duration of the squelches in seconds, the longest one rippled accepts (3600) if not set.
+        #[serde(default)]
+        duration_secs: Option<u32>,
+    },
+}
+
+/// Struct that represents a rule by which the squelches of a validator are overridden on the matching links.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SquelchRule {
+    /// The port of the node that sends the squelches, all nodes if not set.
+    #[serde(default)]
+    pub from_port: Option<u16>,
+    /// The port of the peer the squelches are sent to, all peers if not set.
+    #[serde(default)]
+    pub to_port: Option<u16>,
+    /// The port of the node whose validations are squelched, all validators if not set.
+    #[serde(default)]
+    pub node_port: Option<u16>,
+    /// How the squelches are overridden.
+    #[serde(flatten)]
+    pub action: SquelchAction,
+}
+
+impl SquelchRule {
+    /// Creates the rule from its configuration. Returns an error if a node does not exist.
+    ///
+    /// # Parameters
+    /// * 'config' - the configuration of the rule.
+    /// * 'ports' - the peer ports of the nodes, by node ID.
+    pub fn from_config(config: &SquelchRuleConfig, ports: &[u16]) -> Result<Self, SquelchError> {
+        let port_of = |id: u32| {
+            ports
+                .get(id as usize)
+                .copied()
+                .ok_or(SquelchError::UnknownNode(id))
+        };
+        Ok(Self {
+            from_port: config.from_node.map(port_of).transpose()?,
+            to_port: config.to_node.map(port_of).transpose()?,
+            node_port: config.node.map(port_of).transpose()?,
+            action: config.action,
+        })
+    }
+
+    /// Returns whether the rule overrides the squelches on a link.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer where the message came from.
+    /// * 'to_port' - the port of the peer the message is sent to.
+    pub fn matches(&self, from_port: u16, to_port: u16) -> bool {
+        self.from_port.map_or(true, |port| port == from_port)
+            && self.to_port.map_or(true, |port| port == to_port)
+    }
+}
+
+/// Enum that represents an error of overriding squelches.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SquelchError {
+    /// The message is not an mtSQUELCH message, or it misses a required field.
+    Malformed,
+    /// A node ID in the configuration does not exist.
+    UnknownNode(u32),
+    /// The key of the node with the port is not held, so its squelches can not be recognized.
+    NoNodeKey(u16),
+}
+
+impl fmt::Display for SquelchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SquelchError::Malformed => write!(f, "malformed squelch"),
+            SquelchError::UnknownNode(id) => write!(f, "node {} does not exist", id),
+            SquelchError::NoNodeKey(port) => write!(f, "the key of node {} is not held", port),
+        }
+    }
+}
+
+impl Error for SquelchError {}
+
+/// Struct that represents a squelch that is in effect, as it is returned by the API.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ActiveSquelch {
+    /// The port of the node that asked to stop relaying.
+    pub from_port: u16,
+    /// The port of the peer that was asked to stop relaying.
+    pub to_port: u16,
+    /// The public key of the validator in hex.
+    pub validator_key: String,
+    /// How long the squelch is still in effect in seconds.
+    pub remaining_secs: u64,
+}
+
+/// Struct that represents the squelches that are in effect on every link.
+#[derive(Debug, Default)]
+pub struct SquelchTracker {
+    /// The moment every squelch ends, by the ports of its link and the key of its validator.
+    until: BTreeMap<(u16, u16, Vec<u8>), Instant>,
+}
+
+impl SquelchTracker {
+    /// Tracks a squelch that was sent on a link: a squelch that the peer accepts is in effect until its duration
+    /// passed, and any other squelch ends the squelch of its validator on the link, like the peer does.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the node that sent the squelch.
+    /// * 'to_port' - the port of the peer the squelch was sent to.
+    /// * 'squelch' - the squelch.
+    /// * 'now' - the current moment.
+    pub fn track(&mut self, from_port: u16, to_port: u16, squelch: &Squelch, now: Instant) {
+        let key = (from_port, to_port, squelch.validator_key.clone());
+        match squelch.duration() {
+            Some(duration) => {
+                self.until.insert(key, now + duration);
+            }
+            None => {
+                self.until.remove(&key);
+            }
+        }
+    }
+
+    /// Returns the squelches that are in effect, ordered by link and validator, and forgets the ones that ended.
+    ///
+    /// # Parameters
+    /// * 'now' - the current moment.
+    pub fn active(&mut self, now: Instant) -> Vec<ActiveSquelch> {
+        self.until.retain(|_, until| *until > now);
+        self.until
+            .iter()
+            .map(
+                |((from_port, to_port, validator_key), until)| ActiveSquelch {
+                    from_port: *from_port,
+                    to_port: *to_port,
+                    validator_key: hex::encode_upper(validator_key),
+                    remaining_secs: until.duration_since(now).as_secs(),
+                },
+            )
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::field_mutation::frame;
+    use crate::message_type::MessageType;
+    use crate::squelch::{Squelch, SquelchAction, SquelchTracker, MAX_SQUELCH_DURATION_SECS};
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn squelches_are_decoded_and_overridden() {
+        // squelch = true, validatorPubKey = 33 times 0x02 and squelchDuration = 600
+        let mut payload = vec![0x08, 1, 0x12, 33];
+        payload.extend_from_slice(&[0x02; 33]);
+        payload.extend_from_slice(&[0x18, 0xD8, 0x04]);
+        let message = frame(MessageType::Squelch, &payload);
+        let squelch = Squelch::from_message(&message).unwrap();
+        assert_eq!(
+            squelch,
+            Squelch {
+                squelch: true,
+                validator_key: vec![0x02; 33],
+                duration_secs: Some(600),
+            }
+        );
+        assert_eq!(squelch.to_message(), message);
+        assert_eq!(squelch.duration(), Some(Duration::from_secs(600)));
+        assert_eq!(Squelch::from_message(&message[..20]), None);
+        assert_eq!(
+            Squelch::from_message(&frame(MessageType::Ping, &[0x08, 0])),
+            None
+        );
+
+        assert_eq!(squelch.apply(SquelchAction::Suppress), None);
+        let unsquelch = Squelch {
+            squelch: false,
+            duration_secs: None,
+            ..squelch
+        };
+        assert_eq!(unsquelch.duration(), None);
+        let amplified = unsquelch
+            .apply(SquelchAction::Amplify {
+                duration_secs: None,
+            })
+            .unwrap();
+        assert!(amplified.squelch);
+        assert_eq!(amplified.duration_secs, Some(MAX_SQUELCH_DURATION_SECS));
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn squelches_end_after_their_duration_or_an_unsquelch() {
+        let mut tracker = SquelchTracker::default();
+        let now = Instant::now();
+        let squelch = |validator: u8, squelch, duration_secs| Squelch {
+            squelch,
+            validator_key: vec![validator; 33],
+            duration_secs: Some(duration_secs),
+        };
+        tracker.track(60000, 60001, &squelch(1, true, 300), now);
+        tracker.track(60000, 60001, &squelch(2, true, 600), now);
+        // A duration the peer rejects does not squelch
+        tracker.track(60000, 60002, &squelch(1, true, 10), now);
+        let active = tracker.active(now + Duration::from_secs(100));
+        assert_eq!(active.len(), 2);
+        assert_eq!(active[0].remaining_secs, 200);
+        assert_eq!(active[1].validator_key, hex::encode_upper([2; 33]));
+
+        let active = tracker.active(now + Duration::from_secs(300));
+        assert_eq!(active.len(), 1);
+        tracker.track(60000, 60001, &squelch(2, false, 0), now);
+        assert!(tracker.active(now).is_empty());
+    }
+}
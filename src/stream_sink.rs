@@ -0,0 +1,139 @@
+//! This module is responsible for publishing every handled message and its decision to a Kafka topic or NATS subject,
+//! such that external analysis pipelines can consume the interception data while the interceptor runs.
+//!
+//! Every message is published as the same JSON object that is written to a JSON lines export file.
+
+use crate::config::{StreamBackend, StreamConfig};
+use crate::export_sink::ExportRow;
+use crate::packet_timeline::PacketRecord;
+use crate::record_sink::RecordSink;
+use kafka::producer::{Producer, Record, RequiredAcks};
+use std::error::Error;
+use std::time::Duration;
+
+/// The time the Kafka brokers get to acknowledge a batch of messages.
+const KAFKA_ACK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Enum that represents the connection to the streaming platform.
+enum Publisher {
+    Kafka(Producer),
+    Nats(nats::Connection),
+}
+
+/// Struct that represents the sink publishing handled messages to a Kafka topic or NATS subject.
+pub struct StreamSink {
+    /// The Kafka topic or NATS subject the messages are published to.
+    topic: String,
+    /// The connection to the streaming platform.
+    publisher: Publisher,
+}
+
+impl StreamSink {
+    /// Connects to the streaming platform.
+    ///
+    /// # Parameters
+    /// * 'config' - the configuration of the stream.
+    pub fn connect(config: &StreamConfig) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let publisher = match config.backend {
+            StreamBackend::Kafka => Publisher::Kafka(
+                Producer::from_hosts(config.servers.clone())
+                    .with_ack_timeout(KAFKA_ACK_TIMEOUT)
+                    .with_required_acks(RequiredAcks::One)
+                    .create()?,
+            ),
+            StreamBackend::Nats => Publisher::Nats(nats::connect(config.servers.join(","))?),
+        };
+        Ok(Self {
+            topic: config.topic.clone(),
+            publisher,
+        })
+    }
+}
+
+/// Returns the key and payload of the published message of a record.
+/// The key is the direction of the link, such that Kafka keeps the messages of a link in order.
+///
+/// # Parameters
+/// * 'record' - the record.
+pub fn publication(record: &PacketRecord) -> Result<(String, Vec<u8>), serde_json::Error> {
+    let row = ExportRow::from(record);
+    let payload = serde_json::to_vec(&row)?;
+    Ok((row.direction, payload))
+}
+
+impl RecordSink for StreamSink {
+    fn name(&self) -> String {
+        match self.publisher {
+            Publisher::Kafka(_) => format!("Kafka topic {}", self.topic),
+            Publisher::Nats(_) => format!("NATS subject {}", self.topic),
+        }
+    }
+
+    fn write(&mut self, records: &[PacketRecord]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let publications = records
+            .iter()
+            .map(publication)
+            .collect::<Result<Vec<(String, Vec<u8>)>, serde_json::Error>>()?;
+        match &mut self.publisher {
+            Publisher::Kafka(producer) => {
+                let messages: Vec<Record<String, Vec<u8>>> = publications
+                    .into_iter()
+                    .map(|(key, payload)| Record::from_key_value(&self.topic, key, payload))
+                    .collect();
+                producer.send_all(&messages)?;
+            }
+            Publisher::Nats(connection) => {
+                for (_, payload) in publications {
+                    connection.publish(&self.topic, payload)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if let Publisher::Nats(connection) = &self.publisher {
+            connection.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::message_type::MessageType;
+    use crate::packet_timeline::PacketRecord;
+    use crate::phase::Phase;
+    use crate::stream_sink::publication;
+    use chrono::Utc;
+    use std::time::Duration;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn publication_is_keyed_by_link() {
+        let record = PacketRecord {
+            timestamp: Utc::now(),
+            from_port: 60002,
+            to_port: 60000,
+            sequence: 7,
+            message_type: MessageType::ProposeLedger,
+            ledger_sequence: None,
+            round: None,
+            phase: Phase::Fault,
+            size: 120,
+            hash: "cd".to_string(),
+            sent_size: 120,
+            action: 100,
+            send_amount: 1,
+            controller_latency: None,
+            latency: Duration::from_millis(1),
+        };
+        let (key, payload) = publication(&record).unwrap();
+        assert_eq!(key, "60002->60000");
+        let payload: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(payload["message_type"], "mtPROPOSE_LEDGER");
+        assert_eq!(payload["action"], "delay");
+        assert_eq!(payload["delay_ms"], 100);
+        assert_eq!(payload["sequence"], 7);
+    }
+}
@@ -0,0 +1,263 @@
+//! This module is responsible for the Sybil peers: additional, distinct peers the interceptor pretends to be towards a
+//! target node. Every Sybil peer has its own generated key and its own handshake on the peer port of the target, such
+//! that the behavior of a node with a polluted peer set can be tested without running a node for every peer.
+//!
+//! A Sybil peer only keeps its connection alive: it answers the pings of the target and discards its other messages.
+//! Messages are sent on behalf of a Sybil peer by injecting them into its link, see `InterceptorState::inject`.
+
+use crate::buffer_pool::BufferPool;
+use crate::config::QueueConfig;
+use crate::connection_handler::{Message, SIZE_64KB};
+use crate::docker_manager::ValidatorKeyData;
+use crate::event_bus::EventKind;
+use crate::handshake_response::HandshakeInfo;
+use crate::interceptor_state::{InterceptorState, Link};
+use crate::message_queue::BoundedQueue;
+use crate::message_type::MessageType;
+use crate::peer_connector::{HandshakeHeaders, PeerConnector, PeerIdentity};
+use crate::ping::Ping;
+use crate::tls::TlsStream;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// Struct that represents a Sybil peer that is connected to its target node.
+pub struct SybilPeer {
+    /// The port that identifies the Sybil peer, nothing listens on it.
+    pub port: u16,
+    /// The port of the target node.
+    pub target_port: u16,
+    /// The response of the target node to the handshake.
+    pub handshake: HandshakeInfo,
+    /// The connection to the target node.
+    pub connection: TlsStream,
+}
+
+/// Returns the identities of the Sybil peers, one for every key.
+///
+/// # Parameters
+/// * 'keys' - the generated keys of the Sybil peers.
+/// * 'base_port' - the port that identifies the first Sybil peer.
+/// * 'headers' - the values of the optional handshake headers, which are the same for all Sybil peers.
+pub fn identities(
+    keys: Vec<ValidatorKeyData>,
+    base_port: u16,
+    headers: &HandshakeHeaders,
+) -> Vec<PeerIdentity> {
+    keys.into_iter()
+        .enumerate()
+        .map(|(i, key)| PeerIdentity {
+            port: base_port + i as u16,
+            public_key: key.validation_public_key,
+            seed: key.validation_seed,
+            headers: headers.clone(),
+        })
+        .collect()
+}
+
+/// Connects every Sybil peer to the target node, each with a separate handshake.
+/// Returns the Sybil peers the target node accepted, the others are logged and left out.
+///
+/// # Parameters
+/// * 'peer_connector' - the PeerConnector used for the handshakes.
+/// * 'target_port' - the peer port of the target node.
+/// * 'identities' - the identities of the Sybil peers.
+pub async fn connect(
+    peer_connector: &PeerConnector,
+    target_port: u16,
+    identities: Vec<PeerIdentity>,
+) -> Vec<SybilPeer> {
+    let mut sybils = Vec::new();
+    for identity in identities {
+        match peer_connector.connect_half(target_port, &identity).await {
+            Ok((connection, handshake)) => {
+                debug!(
+                    "Sybil peer {} connected to node {} as {}",
+                    identity.port, target_port, identity.public_key
+                );
+                sybils.push(SybilPeer {
+                    port: identity.port,
+                    target_port,
+                    handshake,
+                    connection,
+                });
+            }
+            Err(e) => warn!(
+                "Node {} rejected Sybil peer {}: {}",
+                target_port, identity.port, e
+            ),
+        }
+    }
+    info!(
+        "Connected {} Sybil peers to node {}",
+        sybils.len(),
+        target_port
+    );
+    sybils
+}
+
+impl SybilPeer {
+    /// Registers the links of the Sybil peer and starts keeping its connection alive.
+    /// Returns the handles of the read and write threads.
+    ///
+    /// # Parameters
+    /// * 'state' - the runtime state, where the links and the write queue of the Sybil peer are registered.
+    /// * 'queue_config' - the capacity and overflow policy of the write queue.
+    pub fn handle_messages(
+        self,
+        state: Arc<InterceptorState>,
+        queue_config: &QueueConfig,
+    ) -> Vec<JoinHandle<()>> {
+        let (read_half, write_half) = tokio::io::split(self.connection);
+        let write_queue = Arc::new(BoundedQueue::new(
+            queue_config.overflow,
+            state.register_queue(format!("write:{}", self.port), queue_config.capacity),
+        ));
+        state.register_write_queue(self.port, write_queue.clone());
+        state.register_link(self.port, self.target_port, None);
+        let incoming =
+            state.register_link(self.target_port, self.port, self.handshake.protocol.clone());
+        state.events.emit(EventKind::LinkConnected {
+            from_port: self.target_port,
+            to_port: self.port,
+            protocol: self.handshake.protocol,
+        });
+
+        vec![
+            tokio::spawn(Self::read_loop(
+                read_half,
+                self.port,
+                self.target_port,
+                incoming,
+                write_queue.clone(),
+                state.clone(),
+            )),
+            tokio::spawn(Self::write_loop(
+                write_half,
+                self.port,
+                self.target_port,
+                write_queue,
+                state,
+            )),
+        ]
+    }
+
+    /// Reads the messages of the target node until the connection is lost.
+    /// Pings are answered through the write queue of the Sybil peer, all other messages are counted and discarded.
+    ///
+    /// # Parameters
+    /// * 'read_half' - the half of the connection that reads from the target node.
+    /// * 'port' - the port that identifies the Sybil peer.
+    /// * 'target_port' - the port of the target node.
+    /// * 'link' - the link from the target node to the Sybil peer.
+    /// * 'write_queue' - the write queue of the Sybil peer.
+    /// * 'state' - the runtime state, where the lost connection is published and counted.
+    async fn read_loop(
+        mut read_half: ReadHalf<TlsStream>,
+        port: u16,
+        target_port: u16,
+        link: Arc<Link>,
+        write_queue: Arc<BoundedQueue<Message>>,
+        state: Arc<InterceptorState>,
+    ) {
+        let mut buffer_pool = BufferPool::new(SIZE_64KB);
+        let reason = loop {
+            match read_half.read_buf(buffer_pool.buffer()).await {
+                Ok(0) => break "closed".to_string(),
+                Ok(size_read) => {
+                    let data = buffer_pool.take(size_read);
+                    link.count(false);
+                    match Ping::from_message(&data) {
+                        Some(ping) if !ping.pong => {
+                            write_queue
+                                .push(Message::new(ping.reply().to_message(), target_port))
+                                .await;
+                        }
+                        _ => debug!(
+                            "Sybil peer {} discarded {}",
+                            port,
+                            MessageType::from_message(&data).unwrap_or(MessageType::Unknown(0))
+                        ),
+                    }
+                }
+                Err(e) => break e.to_string(),
+            }
+        };
+        warn!(
+            "Sybil peer {} lost its connection to node {}: {}",
+            port, target_port, reason
+        );
+        state.events.emit(EventKind::LinkDropped {
+            from_port: target_port,
+            to_port: port,
+            reason,
+        });
+        state.statistics.count_error("sybil_connection_lost", 1);
+    }
+
+    /// Writes the pongs and injected messages of the Sybil peer to the target node, until writing fails.
+    ///
+    /// # Parameters
+    /// * 'write_half' - the half of the connection that writes to the target node.
+    /// * 'port' - the port that identifies the Sybil peer.
+    /// * 'target_port' - the port of the target node.
+    /// * 'write_queue' - the write queue of the Sybil peer.
+    /// * 'state' - the runtime state, where the lost connection is published.
+    async fn write_loop(
+        mut write_half: WriteHalf<TlsStream>,
+        port: u16,
+        target_port: u16,
+        write_queue: Arc<BoundedQueue<Message>>,
+        state: Arc<InterceptorState>,
+    ) {
+        loop {
+            let message = write_queue.pop().await;
+            if let Err(e) = write_half.write_all(&message.data).await {
+                warn!(
+                    "Could not write to node {} on behalf of Sybil peer {}: {}",
+                    target_port, port, e
+                );
+                state.events.emit(EventKind::LinkDropped {
+                    from_port: port,
+                    to_port: target_port,
+                    reason: e.to_string(),
+                });
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::docker_manager::ValidatorKeyData;
+    use crate::peer_connector::HandshakeHeaders;
+    use crate::sybil::identities;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn identities_are_distinct() {
+        let key = |public_key: &str, seed: &str| ValidatorKeyData {
+            status: "success".to_string(),
+            validation_private_key: "".to_string(),
+            validation_public_key: public_key.to_string(),
+            validation_seed: seed.to_string(),
+        };
+        let headers = HandshakeHeaders {
+            network_id: Some(10),
+            ..HandshakeHeaders::default()
+        };
+        let identities = identities(
+            vec![key("key1", "seed1"), key("key2", "seed2")],
+            61000,
+            &headers,
+        );
+        assert_eq!(identities.len(), 2);
+        assert_eq!(identities[0].port, 61000);
+        assert_eq!(identities[0].public_key, "key1");
+        assert_eq!(identities[1].port, 61001);
+        assert_eq!(identities[1].seed, "seed2");
+        assert_eq!(identities[1].headers, headers);
+    }
+}
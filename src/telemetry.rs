@@ -0,0 +1,106 @@
+//! This module is responsible for exporting the tracing spans of the interceptor to an OpenTelemetry collector,
+//! and for propagating the trace context to the controller so both sides end up in the same trace.
+
+use crate::config::OpenTelemetryConfig;
+use opentelemetry::propagation::Injector;
+use opentelemetry::trace::TraceError;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::Tracer;
+use opentelemetry_sdk::Resource;
+use tonic::metadata::{MetadataKey, MetadataMap, MetadataValue};
+use tracing::Span;
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Struct that allows the trace context to be written into the metadata of a gRPC request.
+struct MetadataInjector<'a>(&'a mut MetadataMap);
+
+impl Injector for MetadataInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(key), Ok(value)) = (
+            MetadataKey::from_bytes(key.as_bytes()),
+            MetadataValue::try_from(value.as_str()),
+        ) {
+            self.0.insert(key, value);
+        }
+    }
+}
+
+/// Creates a tracing layer that exports all spans to the configured OTLP endpoint.
+/// This has to be called from within the Tokio runtime, since the spans are exported in batches on it.
+///
+/// # Parameters
+/// * 'config' - the OpenTelemetry configuration.
+pub fn layer<S>(config: &OpenTelemetryConfig) -> Result<OpenTelemetryLayer<S, Tracer>, TraceError>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+{
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(config.endpoint.as_str()),
+        )
+        .with_trace_config(
+            opentelemetry_sdk::trace::config().with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                config.service_name.clone(),
+            )])),
+        )
+        .install_batch(opentelemetry_sdk::runtime::Tokio)?;
+
+    Ok(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+/// Writes the trace context of the current span into the metadata of a gRPC request,
+/// such that the controller can continue the trace. Does nothing if no trace is being exported.
+///
+/// # Parameters
+/// * 'metadata' - the metadata of the outgoing request.
+pub fn inject_current_context(metadata: &mut MetadataMap) {
+    let context = Span::current().context();
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut MetadataInjector(metadata))
+    });
+}
+
+/// Flushes all spans that were not exported yet and shuts the exporter down.
+pub async fn shutdown() {
+    tokio::task::spawn_blocking(opentelemetry::global::shutdown_tracer_provider)
+        .await
+        .unwrap_or_default();
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::telemetry::MetadataInjector;
+    use opentelemetry::propagation::Injector;
+    use tonic::metadata::MetadataMap;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn metadata_injector_sets_header() {
+        let mut metadata = MetadataMap::new();
+        MetadataInjector(&mut metadata).set(
+            "traceparent",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01".to_string(),
+        );
+        assert_eq!(
+            metadata.get("traceparent").unwrap(),
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn metadata_injector_ignores_invalid_header() {
+        let mut metadata = MetadataMap::new();
+        MetadataInjector(&mut metadata).set("invalid header", "value".to_string());
+        assert!(metadata.is_empty());
+    }
+}
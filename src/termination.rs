@@ -0,0 +1,274 @@
+//! This module is responsible for ending a run by itself once its scenario is complete: after a number of validated
+//! ledgers, after a wall-clock duration, at the first violated property or when the controller signals the end.
+//! A run with phases also ends once its cool-down ended. The run then shuts down and is reported like a run that was
+//! interrupted with Ctrl+C, which still ends it otherwise.
+
+use crate::assertion_engine::Violation;
+use crate::config::TerminationConfig;
+use crate::node_rpc::NodeRpcClient;
+use crate::packet_client::PacketClient;
+use crate::run_summary;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, Mutex as AsyncMutex};
+use tracing::{info, warn};
+
+/// How often the conditions that do not need the nodes are checked.
+const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Enum that represents why a run ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    /// The run was interrupted with Ctrl+C, or by a failure of the interceptor.
+    Interrupted,
+    /// The configured amount of ledgers was validated.
+    ValidatedLedgers(u64),
+    /// The run ran for the configured duration.
+    Duration(Duration),
+    /// A property was violated, with the description of the violation.
+    Violation(String),
+    /// The controller signalled that its scenario is complete, with the reason it gave.
+    ControllerSignal(String),
+    /// The cool-down after the fault window ended.
+    CoolDownEnded,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Interrupted => write!(f, "interrupted"),
+            StopReason::ValidatedLedgers(ledgers) => write!(f, "{} ledgers validated", ledgers),
+            StopReason::Duration(duration) => write!(f, "ran for {} s", duration.as_secs()),
+            StopReason::Violation(description) => write!(f, "property violated: {}", description),
+            StopReason::ControllerSignal(reason) if reason.is_empty() => {
+                write!(f, "ended by the controller")
+            }
+            StopReason::ControllerSignal(reason) => {
+                write!(f, "ended by the controller: {}", reason)
+            }
+            StopReason::CoolDownEnded => write!(f, "cool-down ended"),
+        }
+    }
+}
+
+/// Struct that represents the conditions under which a run ends by itself.
+pub struct Termination {
+    config: TerminationConfig,
+    /// The RPC clients of the nodes, whose validated ledger is polled.
+    nodes: Vec<NodeRpcClient>,
+    /// The validated ledger at the start of the run, None until it could be fetched.
+    start_ledger: Option<u64>,
+    /// The violations detected by the assertion engine, if properties are checked.
+    violations: Option<Arc<Mutex<Vec<Violation>>>>,
+    /// Completed once the cool-down ended, if the run has phases.
+    cool_down_ended: Option<oneshot::Receiver<()>>,
+}
+
+impl Termination {
+    /// Creates the conditions of a run.
+    ///
+    /// # Parameters
+    /// * 'config' - the configured conditions, None if the run only ends when it is interrupted.
+    /// * 'nodes' - the RPC clients of the nodes.
+    /// * 'start_ledger' - the validated ledger at the start of the run, if it could be fetched.
+    /// * 'violations' - the violations detected by the assertion engine, if properties are checked.
+    /// * 'cool_down_ended' - completed once the cool-down ended, if the run has phases.
+    pub fn new(
+        config: Option<TerminationConfig>,
+        nodes: Vec<NodeRpcClient>,
+        start_ledger: Option<u64>,
+        violations: Option<Arc<Mutex<Vec<Violation>>>>,
+        cool_down_ended: Option<oneshot::Receiver<()>>,
+    ) -> Self {
+        Self {
+            config: config.unwrap_or_default(),
+            nodes,
+            start_ledger,
+            violations,
+            cool_down_ended,
+        }
+    }
+
+    /// Waits until the run is interrupted or one of the conditions is met, and returns why the run ended.
+    ///
+    /// # Parameters
+    /// * 'running' - whether the run continues, cleared on Ctrl+C.
+    /// * 'client' - the PacketClient used to subscribe to the end signal of the controller.
+    pub async fn wait(
+        mut self,
+        running: Arc<AtomicBool>,
+        client: Arc<AsyncMutex<PacketClient>>,
+    ) -> StopReason {
+        let started = Instant::now();
+        let (signal_sender, mut signal) = oneshot::channel();
+        let follower = self
+            .config
+            .on_controller_signal
+            .then(|| tokio::spawn(follow_controller(client, signal_sender)));
+        let poll_interval = Duration::from_millis(self.config.poll_interval_ms);
+        let mut last_poll: Option<Instant> = None;
+        let mut validated_ledgers = None;
+
+        let reason = loop {
+            if !running.load(Ordering::SeqCst) {
+                break StopReason::Interrupted;
+            }
+            if let Ok(reason) = signal.try_recv() {
+                break StopReason::ControllerSignal(reason);
+            }
+            if self
+                .cool_down_ended
+                .as_mut()
+                .is_some_and(|cool_down_ended| cool_down_ended.try_recv().is_ok())
+            {
+                break StopReason::CoolDownEnded;
+            }
+            if self.config.validated_ledgers.is_some()
+                && last_poll.map_or(true, |last_poll| last_poll.elapsed() >= poll_interval)
+            {
+                last_poll = Some(Instant::now());
+                validated_ledgers = self.validated_ledgers().await.or(validated_ledgers);
+            }
+            if let Some(reason) = self.reached(started.elapsed(), validated_ledgers) {
+                break reason;
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        };
+        if let Some(follower) = follower {
+            follower.abort();
+        }
+        reason
+    }
+
+    /// Returns the amount of ledgers validated since the start of the run, or None if the nodes could not be reached.
+    /// If the validated ledger could not be fetched at the start, the run counts from the first one that is fetched.
+    async fn validated_ledgers(&mut self) -> Option<u64> {
+        let ledger = run_summary::validated_ledger(&self.nodes).await?;
+        let start_ledger = *self.start_ledger.get_or_insert(ledger);
+        Some(ledger.saturating_sub(start_ledger))
+    }
+
+    /// Returns why the run ends if one of the conditions that do not need the controller is met, or None if it
+    /// continues.
+    ///
+    /// # Parameters
+    /// * 'elapsed' - the wall-clock time since the run started.
+    /// * 'validated_ledgers' - the amount of ledgers validated since the start of the run, if it is known.
+    pub fn reached(&self, elapsed: Duration, validated_ledgers: Option<u64>) -> Option<StopReason> {
+        if let Some(violations) = self
+            .violations
+            .as_ref()
+            .filter(|_| self.config.on_violation)
+        {
+            if let Some(violation) = violations.lock().unwrap().first() {
+                return Some(StopReason::Violation(format!(
+                    "{} by node {}: {}",
+                    violation.property, violation.node_id, violation.description
+                )));
+            }
+        }
+        if let Some((target, validated)) = self.config.validated_ledgers.zip(validated_ledgers) {
+            if validated >= target {
+                return Some(StopReason::ValidatedLedgers(validated));
+            }
+        }
+        if let Some(duration_secs) = self.config.duration_secs {
+            let duration = Duration::from_secs(duration_secs);
+            if elapsed >= duration {
+                return Some(StopReason::Duration(duration));
+            }
+        }
+        None
+    }
+}
+
+/// Waits for the signal the controller sends once its scenario is complete, and passes on its reason.
+/// Controllers that do not implement the signal are skipped, the run then ends by the other conditions.
+///
+/// # Parameters
+/// * 'client' - the PacketClient used to subscribe to the signal.
+/// * 'signal' - where the reason of the controller is passed on.
+async fn follow_controller(client: Arc<AsyncMutex<PacketClient>>, signal: oneshot::Sender<String>) {
+    let signals = client.lock().await.subscribe_end().await;
+    let mut signals = match signals {
+        Ok(Some(signals)) => signals,
+        Ok(None) => {
+            warn!("The controller can not signal the end of its scenario, the run ends by the other conditions");
+            return;
+        }
+        Err(e) => {
+            warn!(
+                "Could not subscribe to the end signal of the controller: {}",
+                e
+            );
+            return;
+        }
+    };
+    match signals.message().await {
+        Ok(Some(end)) => {
+            info!("The controller signalled the end of its scenario");
+            let _ = signal.send(end.reason);
+        }
+        Ok(None) => info!("The controller closed the end signal without ending the run"),
+        Err(e) => warn!("The end signal of the controller stopped: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::assertion_engine::{Property, Violation};
+    use crate::config::TerminationConfig;
+    use crate::termination::{StopReason, Termination};
+    use chrono::Utc;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn the_first_condition_met_ends_the_run() {
+        let violations = Arc::new(Mutex::new(Vec::new()));
+        let config = TerminationConfig {
+            validated_ledgers: Some(20),
+            duration_secs: Some(600),
+            on_violation: true,
+            ..TerminationConfig::default()
+        };
+        let termination = Termination::new(
+            Some(config),
+            Vec::new(),
+            Some(100),
+            Some(violations.clone()),
+            None,
+        );
+        let minute = Duration::from_secs(60);
+        assert_eq!(termination.reached(minute, None), None);
+        assert_eq!(termination.reached(minute, Some(19)), None);
+        assert_eq!(
+            termination.reached(minute, Some(21)),
+            Some(StopReason::ValidatedLedgers(21))
+        );
+        assert_eq!(
+            termination.reached(Duration::from_secs(600), Some(3)),
+            Some(StopReason::Duration(Duration::from_secs(600)))
+        );
+
+        violations.lock().unwrap().push(Violation {
+            property: Property::Agreement,
+            node_id: 2,
+            description: "node 2 validated <A> & <B>".to_string(),
+            timestamp: Utc::now(),
+            timeline: Vec::new(),
+        });
+        let reason = termination.reached(minute, Some(21)).unwrap();
+        assert_eq!(
+            reason.to_string(),
+            "property violated: agreement by node 2: node 2 validated <A> & <B>"
+        );
+
+        // Without conditions, the run only ends when it is interrupted
+        let termination = Termination::new(None, Vec::new(), None, Some(violations), None);
+        assert_eq!(termination.reached(Duration::MAX, Some(u64::MAX)), None);
+    }
+}
@@ -0,0 +1,407 @@
+//! This module is responsible for the TLS connections with the nodes.
+//!
+//! The TLS library is selected with a cargo feature: `openssl-tls` (the default) uses OpenSSL,
+//! `rustls-tls` uses rustls for machines where OpenSSL can not be built. If both are enabled, OpenSSL is used.
+//!
+//! Besides connecting, every backend provides the shared value of the session that the Session-Signature signs.
+//! OpenSSL derives it from the TLS finished messages, like rippled does. rustls does not expose the finished messages,
+//! so it uses keying material exported from the session (RFC 5705) instead, which is only accepted by nodes that
+//! do not verify the Session-Signature, such as the patched rippled images this interceptor is used with.
+//!
+//! The TLS versions, cipher suites, ALPN protocols and certificate verification can be configured with `[tls]`,
+//! to reproduce the TLS behavior of different rippled builds.
+
+use sha2::{Digest, Sha512};
+
+#[cfg(not(any(feature = "openssl-tls", feature = "rustls-tls")))]
+compile_error!(
+    "Enable either the 'openssl-tls' or the 'rustls-tls' feature to select a TLS backend"
+);
+
+#[cfg(feature = "openssl-tls")]
+pub use self::openssl_backend::{connect, shared_value, TlsStream};
+#[cfg(all(feature = "rustls-tls", not(feature = "openssl-tls")))]
+pub use self::rustls_backend::{connect, shared_value, TlsStream};
+
+/// Combines the material of both sides of the session into the shared value, as rippled's `makeSharedValue` does:
+/// the SHA-512 hash of the XOR of the SHA-512 hashes of both sides.
+///
+/// # Parameters
+/// * 'local' - the material of the local side of the session.
+/// * 'peer' - the material of the peer's side of the session.
+#[allow(unused)]
+fn combine_material(local: &[u8], peer: &[u8]) -> [u8; 64] {
+    let local_hash = Sha512::digest(local);
+    let peer_hash = Sha512::digest(peer);
+    let xor = local_hash
+        .iter()
+        .zip(peer_hash.iter())
+        .map(|(a, b)| a ^ b)
+        .collect::<Vec<u8>>();
+
+    let mut shared_value = [0u8; 64];
+    shared_value.copy_from_slice(&Sha512::digest(&xor));
+    shared_value
+}
+
+/// Encodes the ALPN protocols in the wire format: every protocol is prefixed with its length.
+///
+/// # Parameters
+/// * 'protocols' - the protocols in order of preference.
+#[allow(unused)]
+fn alpn_wire_format(protocols: &[String]) -> Vec<u8> {
+    let mut wire_format = Vec::new();
+    for protocol in protocols {
+        wire_format.push(protocol.len() as u8);
+        wire_format.extend_from_slice(protocol.as_bytes());
+    }
+    wire_format
+}
+
+#[cfg(feature = "openssl-tls")]
+mod openssl_backend {
+    use crate::config::{TlsConfig, TlsVersion};
+    use crate::tls::{alpn_wire_format, combine_material};
+    use openssl::hash::MessageDigest;
+    use openssl::ssl::{Ssl, SslContext, SslMethod, SslVerifyMode, SslVersion};
+    use std::io;
+    use std::net::IpAddr;
+    use std::pin::Pin;
+    use tokio::net::TcpStream;
+    use tokio_openssl::SslStream;
+    use tracing::info;
+
+    /// The TLS stream with a node.
+    pub type TlsStream = SslStream<TcpStream>;
+
+    /// Converts the configured TLS version into the version of OpenSSL.
+    ///
+    /// # Parameters
+    /// * 'version' - the configured version.
+    fn ssl_version(version: TlsVersion) -> SslVersion {
+        match version {
+            TlsVersion::Tls10 => SslVersion::TLS1,
+            TlsVersion::Tls11 => SslVersion::TLS1_1,
+            TlsVersion::Tls12 => SslVersion::TLS1_2,
+            TlsVersion::Tls13 => SslVersion::TLS1_3,
+        }
+    }
+
+    /// Creates the SSL context with the configured settings.
+    ///
+    /// # Parameters
+    /// * 'config' - the configuration of the TLS sessions.
+    fn ssl_context(config: &TlsConfig) -> Result<SslContext, openssl::error::ErrorStack> {
+        let mut builder = SslContext::builder(SslMethod::tls())?;
+        builder.set_min_proto_version(config.min_version.map(ssl_version))?;
+        builder.set_max_proto_version(config.max_version.map(ssl_version))?;
+        if let Some(cipher_list) = &config.cipher_list {
+            builder.set_cipher_list(cipher_list)?;
+        }
+        if let Some(ciphersuites) = &config.ciphersuites {
+            builder.set_ciphersuites(ciphersuites)?;
+        }
+        if !config.alpn.is_empty() {
+            builder.set_alpn_protos(&alpn_wire_format(&config.alpn))?;
+        }
+        if config.verify {
+            if let Some(ca_file) = &config.ca_file {
+                builder.set_ca_file(ca_file)?;
+            }
+            builder.set_verify(SslVerifyMode::PEER);
+        } else {
+            builder.set_verify(SslVerifyMode::NONE);
+        }
+        Ok(builder.build())
+    }
+
+    /// Performs the TLS handshake on a connected TCP stream with the configured settings.
+    ///
+    /// # Parameters
+    /// * 'tcp_stream' - the connected TCP stream.
+    /// * 'ip' - the IP address of the node.
+    /// * 'config' - the configuration of the TLS sessions.
+    pub async fn connect(
+        tcp_stream: TcpStream,
+        ip: IpAddr,
+        config: &TlsConfig,
+    ) -> io::Result<TlsStream> {
+        let ssl_context = ssl_context(config).map_err(io::Error::other)?;
+        let ssl_session = Ssl::new(&ssl_context).map_err(io::Error::other)?;
+        let mut ssl_stream = SslStream::new(ssl_session, tcp_stream).map_err(io::Error::other)?;
+        SslStream::connect(Pin::new(&mut ssl_stream))
+            .await
+            .map_err(io::Error::other)?;
+
+        if config.log_certificates {
+            let ssl = ssl_stream.ssl();
+            match ssl.peer_certificate() {
+                Some(certificate) => info!(
+                    "TLS session with {}: version {}, cipher {:?}, ALPN {:?}, certificate subject {:?}, \
+                    issuer {:?}, SHA-256 fingerprint {}",
+                    ip,
+                    ssl.version_str(),
+                    ssl.current_cipher().map(|cipher| cipher.name()),
+                    ssl.selected_alpn_protocol()
+                        .map(|protocol| String::from_utf8_lossy(protocol).to_string()),
+                    certificate.subject_name(),
+                    certificate.issuer_name(),
+                    certificate
+                        .digest(MessageDigest::sha256())
+                        .map(hex::encode)
+                        .unwrap_or_default()
+                ),
+                None => info!("TLS session with {}: no certificate was sent", ip),
+            }
+        }
+        Ok(ssl_stream)
+    }
+
+    /// Returns the shared value of the session, derived from the finished messages.
+    /// See https://github.com/XRPLF/rippled/blob/f64cf9187affd69650907d0d92e097eb29693945/src/xrpld/overlay/detail/Handshake.cpp#L199-L203
+    /// for the original implementation by the XRPLF.
+    ///
+    /// # Parameters
+    /// * 'tls_stream' - the stream after the TLS handshake.
+    pub fn shared_value(tls_stream: &TlsStream) -> [u8; 64] {
+        let ssl_ref = tls_stream.ssl();
+        let mut buf = vec![0; 1024];
+
+        // Get the contents of the last message sent to the peer.
+        let mut size = ssl_ref.finished(&mut buf[..]);
+        if size > buf.len() {
+            buf.resize(size, 0);
+            size = ssl_ref.finished(&mut buf[..]);
+        }
+        let finished = buf[..size].to_vec();
+
+        // Get the contents of the last received message from the peer.
+        let mut size = ssl_ref.peer_finished(&mut buf[..]);
+        if size > buf.len() {
+            buf.resize(size, 0);
+            size = ssl_ref.peer_finished(&mut buf[..]);
+        }
+
+        combine_material(&finished, &buf[..size])
+    }
+}
+
+#[cfg(all(feature = "rustls-tls", not(feature = "openssl-tls")))]
+mod rustls_backend {
+    use crate::config::{TlsConfig, TlsVersion};
+    use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+    use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+    use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+    use rustls::{ClientConfig, DigitallySignedStruct, Error, SignatureScheme};
+    use rustls::{SupportedCipherSuite, SupportedProtocolVersion};
+    use sha2::{Digest, Sha256};
+    use std::io;
+    use std::net::IpAddr;
+    use std::sync::Arc;
+    use tokio::net::TcpStream;
+    use tokio_rustls::client;
+    use tokio_rustls::TlsConnector;
+    use tracing::info;
+
+    /// The label of the keying material that is exported as shared value.
+    const EXPORTER_LABEL: &[u8] = b"EXPORTER-rocket-interceptor-session";
+
+    /// The TLS stream with a node.
+    pub type TlsStream = client::TlsStream<TcpStream>;
+
+    /// Struct that represents a certificate verifier that accepts every certificate,
+    /// since nodes use self-signed certificates. Signatures of the handshake are still verified.
+    #[derive(Debug)]
+    struct AcceptAnyCertificate(Arc<CryptoProvider>);
+
+    impl ServerCertVerifier for AcceptAnyCertificate {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &CertificateDer<'_>,
+            _intermediates: &[CertificateDer<'_>],
+            _server_name: &ServerName<'_>,
+            _ocsp_response: &[u8],
+            _now: UnixTime,
+        ) -> Result<ServerCertVerified, Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, Error> {
+            verify_tls12_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, Error> {
+            verify_tls13_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.0.signature_verification_algorithms.supported_schemes()
+        }
+    }
+
+    /// Returns the protocol versions within the configured range. rustls does not support versions below TLS 1.2.
+    ///
+    /// # Parameters
+    /// * 'config' - the configuration of the TLS sessions.
+    fn protocol_versions(config: &TlsConfig) -> Vec<&'static SupportedProtocolVersion> {
+        [
+            (TlsVersion::Tls12, &rustls::version::TLS12),
+            (TlsVersion::Tls13, &rustls::version::TLS13),
+        ]
+        .into_iter()
+        .filter(|(version, _)| config.min_version.map_or(true, |min| *version >= min))
+        .filter(|(version, _)| config.max_version.map_or(true, |max| *version <= max))
+        .map(|(_, supported)| supported)
+        .collect()
+    }
+
+    /// Returns the cipher suites of the provider that are named in the cipher list or ciphersuites, or all of them
+    /// if neither is configured. Suites are named as by rustls, e.g. 'TLS13_AES_256_GCM_SHA384'.
+    ///
+    /// # Parameters
+    /// * 'config' - the configuration of the TLS sessions.
+    /// * 'suites' - the cipher suites of the provider.
+    fn cipher_suites(
+        config: &TlsConfig,
+        suites: Vec<SupportedCipherSuite>,
+    ) -> Vec<SupportedCipherSuite> {
+        let names = [&config.cipher_list, &config.ciphersuites]
+            .into_iter()
+            .flatten()
+            .flat_map(|list| list.split(':'))
+            .map(str::trim)
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            return suites;
+        }
+        suites
+            .into_iter()
+            .filter(|suite| {
+                suite
+                    .suite()
+                    .as_str()
+                    .is_some_and(|name| names.contains(&name))
+            })
+            .collect()
+    }
+
+    /// Performs the TLS handshake on a connected TCP stream with the configured settings.
+    ///
+    /// # Parameters
+    /// * 'tcp_stream' - the connected TCP stream.
+    /// * 'ip' - the IP address of the node, used as server name.
+    /// * 'config' - the configuration of the TLS sessions.
+    pub async fn connect(
+        tcp_stream: TcpStream,
+        ip: IpAddr,
+        config: &TlsConfig,
+    ) -> io::Result<TlsStream> {
+        if config.verify {
+            return Err(io::Error::other(
+                "verifying certificates is only supported by the openssl-tls backend",
+            ));
+        }
+        let default_provider = rustls::crypto::ring::default_provider();
+        let provider = Arc::new(CryptoProvider {
+            cipher_suites: cipher_suites(config, default_provider.cipher_suites.clone()),
+            ..default_provider
+        });
+        let mut client_config = ClientConfig::builder_with_provider(provider.clone())
+            .with_protocol_versions(&protocol_versions(config))
+            .map_err(io::Error::other)?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate(provider)))
+            .with_no_client_auth();
+        client_config.alpn_protocols = config
+            .alpn
+            .iter()
+            .map(|protocol| protocol.as_bytes().to_vec())
+            .collect();
+
+        let tls_stream = TlsConnector::from(Arc::new(client_config))
+            .connect(ServerName::IpAddress(ip.into()), tcp_stream)
+            .await?;
+
+        if config.log_certificates {
+            let connection = tls_stream.get_ref().1;
+            info!(
+                "TLS session with {}: version {:?}, cipher {:?}, ALPN {:?}, certificate SHA-256 fingerprint {}",
+                ip,
+                connection.protocol_version(),
+                connection
+                    .negotiated_cipher_suite()
+                    .map(|suite| suite.suite()),
+                connection
+                    .alpn_protocol()
+                    .map(|protocol| String::from_utf8_lossy(protocol).to_string()),
+                connection
+                    .peer_certificates()
+                    .and_then(|certificates| certificates.first())
+                    .map(|certificate| hex::encode(Sha256::digest(certificate.as_ref())))
+                    .unwrap_or_default()
+            );
+        }
+        Ok(tls_stream)
+    }
+
+    /// Returns the shared value of the session, derived from keying material exported from the session.
+    ///
+    /// # Parameters
+    /// * 'tls_stream' - the stream after the TLS handshake.
+    ///
+    /// # Panics
+    /// * If the keying material could not be exported, which only happens before the handshake finished.
+    pub fn shared_value(tls_stream: &TlsStream) -> [u8; 64] {
+        tls_stream
+            .get_ref()
+            .1
+            .export_keying_material([0u8; 64], EXPORTER_LABEL, None)
+            .expect("Could not export keying material from the TLS session")
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::tls::{alpn_wire_format, combine_material};
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn alpn_protocols_are_length_prefixed() {
+        assert_eq!(
+            alpn_wire_format(&["xrpl".to_string(), "h2".to_string()]),
+            b"\x04xrpl\x02h2".to_vec()
+        );
+        assert!(alpn_wire_format(&[]).is_empty());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn combine_material_is_symmetric() {
+        let shared_value = combine_material(b"local finished", b"peer finished");
+        assert_eq!(
+            shared_value,
+            combine_material(b"peer finished", b"local finished")
+        );
+        assert_ne!(shared_value, combine_material(b"local finished", b"other"));
+    }
+}
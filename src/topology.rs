@@ -0,0 +1,586 @@
+//! This module is responsible for the topology of the network: which pairs of nodes get an intercepted link.
+//!
+//! By default, every pair of nodes that share a partition of the controller is linked, which is a full mesh if there are
+//! no partitions. Instead, the links can be described in a file, either as a JSON adjacency list or as a graph in the DOT
+//! language, or generated in one of the built-in shapes: a ring, a star, a small world or a random graph. The pairs that
+//! are not described get no link at all. Links are bidirectional, so the direction of an edge in a directed graph is
+//! ignored. The random shapes are generated from a seed, such that an experiment can be repeated on the same topology.
+
+use crate::config::{TopologyConfig, TopologyShape};
+use crate::is_valid_connection;
+use crate::packet_client::proto::{Config, Partition};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::Path;
+
+/// Enum that represents the reasons a topology is invalid.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TopologyError {
+    /// The file could not be read.
+    Unreadable(String),
+    /// The description is not a valid adjacency list or graph.
+    Malformed(String),
+    /// A link of a node with itself.
+    SelfLink(u32),
+    /// A node that is not part of the network.
+    UnknownNode(u32),
+    /// A link between nodes that are in different partitions of the controller.
+    Partitioned(u32, u32),
+    /// A configuration that neither describes nor generates a topology, or whose shape can not be generated.
+    Invalid(String),
+}
+
+impl fmt::Display for TopologyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TopologyError::Unreadable(e) => write!(f, "the topology could not be read: {}", e),
+            TopologyError::Malformed(e) => write!(f, "the topology is malformed: {}", e),
+            TopologyError::SelfLink(node) => write!(f, "node {} is linked to itself", node),
+            TopologyError::UnknownNode(node) => write!(f, "node {} does not exist", node),
+            TopologyError::Partitioned(node_1, node_2) => write!(
+                f,
+                "node {} and {} are linked, but in different partitions",
+                node_1, node_2
+            ),
+            TopologyError::Invalid(e) => write!(f, "the topology configuration is invalid: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TopologyError {}
+
+/// Struct that represents the pairs of nodes that get an intercepted link.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Topology {
+    /// The linked pairs of nodes by their IDs, the lowest ID first.
+    links: BTreeSet<(u32, u32)>,
+}
+
+impl Topology {
+    /// Returns the topology in which every pair of nodes that share a partition is linked.
+    ///
+    /// # Parameters
+    /// * 'number_of_nodes' - the amount of nodes in the network.
+    /// * 'partitions' - array of partitions.
+    pub fn from_partitions(number_of_nodes: u32, partitions: &Vec<Partition>) -> Self {
+        let links = (0..number_of_nodes)
+            .flat_map(|node_1| ((node_1 + 1)..number_of_nodes).map(move |node_2| (node_1, node_2)))
+            .filter(|(node_1, node_2)| is_valid_connection(*node_1, *node_2, partitions))
+            .collect();
+        Self { links }
+    }
+
+    /// Returns the topology as it is configured, either read from a file or generated.
+    ///
+    /// # Parameters
+    /// * 'topology_config' - the configuration of the topology.
+    /// * 'number_of_nodes' - the amount of nodes in the network.
+    pub fn from_config(
+        topology_config: &TopologyConfig,
+        number_of_nodes: u32,
+    ) -> Result<Self, TopologyError> {
+        let probability = |name: &str, probability: f64| {
+            if (0.0..=1.0).contains(&probability) {
+                Ok(probability)
+            } else {
+                Err(TopologyError::Invalid(format!(
+                    "{} {} is not between 0 and 1",
+                    name, probability
+                )))
+            }
+        };
+        match (&topology_config.file, topology_config.shape) {
+            (Some(file), None) => Self::from_file(file),
+            (None, Some(TopologyShape::Ring)) => Ok(Self::ring(number_of_nodes)),
+            (None, Some(TopologyShape::Star)) => Self::star(number_of_nodes, topology_config.hub),
+            (None, Some(TopologyShape::SmallWorld)) => Self::small_world(
+                number_of_nodes,
+                topology_config.degree,
+                probability("rewire_probability", topology_config.rewire_probability)?,
+                topology_config.seed,
+            ),
+            (None, Some(TopologyShape::Random)) => Ok(Self::random(
+                number_of_nodes,
+                probability("link_probability", topology_config.link_probability)?,
+                topology_config.seed,
+            )),
+            (Some(_), Some(_)) => Err(TopologyError::Invalid(
+                "both a file and a shape are configured".to_string(),
+            )),
+            (None, None) => Err(TopologyError::Invalid(
+                "neither a file nor a shape is configured".to_string(),
+            )),
+        }
+    }
+
+    /// Returns the topology in which every node is linked to the next one, and the last one to the first one.
+    ///
+    /// # Parameters
+    /// * 'number_of_nodes' - the amount of nodes in the network.
+    pub fn ring(number_of_nodes: u32) -> Self {
+        let mut topology = Self::default();
+        if number_of_nodes > 1 {
+            for node in 0..number_of_nodes {
+                let _ = topology.link(node, (node + 1) % number_of_nodes);
+            }
+        }
+        topology
+    }
+
+    /// Returns the topology in which every node is linked to the hub, and to no other node.
+    ///
+    /// # Parameters
+    /// * 'number_of_nodes' - the amount of nodes in the network.
+    /// * 'hub' - the ID of the node every other node is linked to.
+    pub fn star(number_of_nodes: u32, hub: u32) -> Result<Self, TopologyError> {
+        if hub >= number_of_nodes {
+            return Err(TopologyError::UnknownNode(hub));
+        }
+        let mut topology = Self::default();
+        for node in (0..number_of_nodes).filter(|node| *node != hub) {
+            topology.link(hub, node)?;
+        }
+        Ok(topology)
+    }
+
+    /// Returns a small world as generated by the Watts-Strogatz model: a ring in which every node is linked to its
+    /// nearest neighbours, after which every link is moved with a probability from one of its nodes to a random node it
+    /// is not linked to yet.
+    ///
+    /// # Parameters
+    /// * 'number_of_nodes' - the amount of nodes in the network.
+    /// * 'degree' - the amount of nearest neighbours every node is linked to, an even number below the amount of nodes.
+    /// * 'rewire_probability' - the probability that a link is moved.
+    /// * 'seed' - the seed of the random choices.
+    pub fn small_world(
+        number_of_nodes: u32,
+        degree: u32,
+        rewire_probability: f64,
+        seed: u64,
+    ) -> Result<Self, TopologyError> {
+        if degree % 2 != 0 || degree >= number_of_nodes {
+            return Err(TopologyError::Invalid(format!(
+                "degree {} is not an even number below the amount of nodes {}",
+                degree, number_of_nodes
+            )));
+        }
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut topology = Self::default();
+        for distance in 1..=degree / 2 {
+            for node in 0..number_of_nodes {
+                topology.link(node, (node + distance) % number_of_nodes)?;
+            }
+        }
+        for distance in 1..=degree / 2 {
+            for node in 0..number_of_nodes {
+                let neighbour = (node + distance) % number_of_nodes;
+                if !rng.gen_bool(rewire_probability) || !topology.contains(node, neighbour) {
+                    continue;
+                }
+                let candidates: Vec<u32> = (0..number_of_nodes)
+                    .filter(|other| *other != node && !topology.contains(node, *other))
+                    .collect();
+                if candidates.is_empty() {
+                    continue;
+                }
+                let other = candidates[rng.gen_range(0..candidates.len())];
+                topology
+                    .links
+                    .remove(&(node.min(neighbour), node.max(neighbour)));
+                topology.link(node, other)?;
+            }
+        }
+        Ok(topology)
+    }
+
+    /// Returns a random graph as generated by the Erdős-Rényi model, in which every pair of nodes is linked with the
+    /// same probability.
+    ///
+    /// # Parameters
+    /// * 'number_of_nodes' - the amount of nodes in the network.
+    /// * 'link_probability' - the probability that a pair of nodes is linked.
+    /// * 'seed' - the seed of the random choices.
+    pub fn random(number_of_nodes: u32, link_probability: f64, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let links = (0..number_of_nodes)
+            .flat_map(|node_1| ((node_1 + 1)..number_of_nodes).map(move |node_2| (node_1, node_2)))
+            .filter(|_| rng.gen_bool(link_probability))
+            .collect();
+        Self { links }
+    }
+
+    /// Reads a topology from a file, as a graph in the DOT language if its extension is .dot or .gv, and as a JSON
+    /// adjacency list otherwise.
+    ///
+    /// # Parameters
+    /// * 'path' - the path of the file.
+    pub fn from_file(path: &str) -> Result<Self, TopologyError> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| TopologyError::Unreadable(format!("{}: {}", path, e)))?;
+        match Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("dot") | Some("gv") => Self::from_dot(&content),
+            _ => Self::from_json(&content),
+        }
+    }
+
+    /// Parses a JSON adjacency list, which maps the ID of every node to the IDs of the nodes it is linked to, e.g.
+    /// `{"0": [1, 2], "1": [2], "3": []}`. A link only has to be listed for one of its nodes.
+    ///
+    /// # Parameters
+    /// * 'content' - the adjacency list.
+    pub fn from_json(content: &str) -> Result<Self, TopologyError> {
+        let adjacency: BTreeMap<u32, Vec<u32>> =
+            serde_json::from_str(content).map_err(|e| TopologyError::Malformed(e.to_string()))?;
+        let mut topology = Self::default();
+        for (node, neighbours) in adjacency {
+            for neighbour in neighbours {
+                topology.link(node, neighbour)?;
+            }
+        }
+        Ok(topology)
+    }
+
+    /// Parses a graph in the DOT language, e.g. `graph { 0 -- 1 -- 2; 0 -- 2 }`. Every node ID is a number, and
+    /// attributes, subgraphs and statements without edges are ignored.
+    ///
+    /// # Parameters
+    /// * 'content' - the graph.
+    pub fn from_dot(content: &str) -> Result<Self, TopologyError> {
+        let content = strip_comments(content);
+        let body = content
+            .find('{')
+            .zip(content.rfind('}'))
+            .filter(|(start, end)| start < end)
+            .map(|(start, end)| &content[start + 1..end])
+            .ok_or_else(|| {
+                TopologyError::Malformed("the graph has no body between braces".to_string())
+            })?;
+        let mut topology = Self::default();
+        for statement in body.split(|c| c == ';' || c == '\n') {
+            let statement = strip_attributes(statement);
+            if !statement.contains("--") && !statement.contains("->") {
+                continue;
+            }
+            let nodes = statement
+                .replace("->", "--")
+                .split("--")
+                .map(|node| {
+                    let node = node
+                        .trim()
+                        .trim_matches(|c| c == '{' || c == '}' || c == '"');
+                    node.trim().parse::<u32>().map_err(|_| {
+                        TopologyError::Malformed(format!("'{}' is not a node ID", node.trim()))
+                    })
+                })
+                .collect::<Result<Vec<u32>, TopologyError>>()?;
+            for pair in nodes.windows(2) {
+                topology.link(pair[0], pair[1])?;
+            }
+        }
+        Ok(topology)
+    }
+
+    /// Links every hub to every other node it shares a partition with, on top of the links of the topology.
+    ///
+    /// # Parameters
+    /// * 'hubs' - the IDs of the hub nodes.
+    /// * 'number_of_nodes' - the amount of nodes in the network.
+    /// * 'partitions' - array of partitions.
+    pub fn link_hubs(&mut self, hubs: &[u32], number_of_nodes: u32, partitions: &Vec<Partition>) {
+        for hub in hubs.iter().copied() {
+            for node in 0..number_of_nodes {
+                if is_valid_connection(hub, node, partitions) {
+                    let _ = self.link(hub, node);
+                }
+            }
+        }
+    }
+
+    /// Returns whether two nodes are linked.
+    ///
+    /// # Parameters
+    /// * 'node_1' - the ID of the first node.
+    /// * 'node_2' - the ID of the second node.
+    pub fn contains(&self, node_1: u32, node_2: u32) -> bool {
+        self.links
+            .contains(&(node_1.min(node_2), node_1.max(node_2)))
+    }
+
+    /// Returns the amount of links.
+    pub fn len(&self) -> usize {
+        self.links.len()
+    }
+
+    /// Checks whether the topology fits the network the controller set up: every node has to exist, and linked nodes
+    /// have to share a partition.
+    ///
+    /// # Parameters
+    /// * 'config' - the configuration of the network, as it was received from the controller.
+    pub fn validate(&self, config: &Config) -> Result<(), TopologyError> {
+        for (node_1, node_2) in self.links.iter().copied() {
+            if node_2 >= config.number_of_nodes {
+                return Err(TopologyError::UnknownNode(node_2));
+            }
+            if !is_valid_connection(node_1, node_2, &config.net_partitions) {
+                return Err(TopologyError::Partitioned(node_1, node_2));
+            }
+        }
+        Ok(())
+    }
+
+    /// Links two nodes.
+    ///
+    /// # Parameters
+    /// * 'node_1' - the ID of the first node.
+    /// * 'node_2' - the ID of the second node.
+    fn link(&mut self, node_1: u32, node_2: u32) -> Result<(), TopologyError> {
+        if node_1 == node_2 {
+            return Err(TopologyError::SelfLink(node_1));
+        }
+        self.links.insert((node_1.min(node_2), node_1.max(node_2)));
+        Ok(())
+    }
+}
+
+/// Removes the line and block comments from a graph in the DOT language.
+///
+/// # Parameters
+/// * 'content' - the graph.
+fn strip_comments(content: &str) -> String {
+    let mut stripped = String::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("/*") {
+        stripped.push_str(&rest[..start]);
+        rest = rest[start + 2..]
+            .find("*/")
+            .map_or("", |end| &rest[start + 2 + end + 2..]);
+    }
+    stripped.push_str(rest);
+    stripped
+        .lines()
+        .map(|line| {
+            let line = line.split("//").next().unwrap_or_default();
+            if line.trim_start().starts_with('#') {
+                ""
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+/// Removes the attribute lists from a statement of a graph in the DOT language, e.g. `[color = red]`.
+///
+/// # Parameters
+/// * 'statement' - the statement.
+fn strip_attributes(statement: &str) -> String {
+    let mut stripped = String::new();
+    let mut depth = 0;
+    for c in statement.chars() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            _ if depth == 0 => stripped.push(c),
+            _ => {}
+        }
+    }
+    stripped
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::config::{TopologyConfig, TopologyShape};
+    use crate::packet_client::proto::{Config, Partition};
+    use crate::topology::{Topology, TopologyError};
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn full_mesh_respects_partitions() {
+        let topology = Topology::from_partitions(4, &vec![]);
+        assert_eq!(topology.len(), 6);
+
+        let partitions = vec![
+            Partition { nodes: vec![0, 1] },
+            Partition { nodes: vec![2, 3] },
+        ];
+        let topology = Topology::from_partitions(4, &partitions);
+        assert_eq!(topology.len(), 2);
+        assert!(topology.contains(1, 0));
+        assert!(!topology.contains(1, 2));
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_json_adjacency_list() {
+        let topology = Topology::from_json(r#"{"0": [1, 2], "2": [0, 3], "4": []}"#).unwrap();
+        assert_eq!(topology.len(), 3);
+        assert!(topology.contains(0, 1));
+        assert!(topology.contains(3, 2));
+        assert!(!topology.contains(1, 2));
+
+        assert_eq!(
+            Topology::from_json(r#"{"1": [1]}"#),
+            Err(TopologyError::SelfLink(1))
+        );
+        assert!(matches!(
+            Topology::from_json(r#"[[1]]"#),
+            Err(TopologyError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_dot_graph() {
+        let topology = Topology::from_dot(
+            r#"
+            // A ring with a chord
+            graph ring {
+                node [shape = circle];
+                0 -- 1 -- 2 -- "3" [color = red];
+                3 -- 0; /* the chord */ 0 -- 2
+                4
+            }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(topology.len(), 5);
+        assert!(topology.contains(3, 0));
+        assert!(topology.contains(0, 2));
+        assert!(!topology.contains(1, 3));
+
+        let directed = Topology::from_dot("digraph { 1 -> 0 }").unwrap();
+        assert!(directed.contains(0, 1));
+        assert!(matches!(
+            Topology::from_dot("graph { a -- b }"),
+            Err(TopologyError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn validate_against_config() {
+        let config = Config {
+            number_of_nodes: 3,
+            net_partitions: vec![
+                Partition { nodes: vec![0, 1] },
+                Partition { nodes: vec![2] },
+            ],
+            ..Config::default()
+        };
+        let topology = Topology::from_json(r#"{"0": [1]}"#).unwrap();
+        assert_eq!(topology.validate(&config), Ok(()));
+
+        let topology = Topology::from_json(r#"{"0": [3]}"#).unwrap();
+        assert_eq!(
+            topology.validate(&config),
+            Err(TopologyError::UnknownNode(3))
+        );
+        let topology = Topology::from_json(r#"{"1": [2]}"#).unwrap();
+        assert_eq!(
+            topology.validate(&config),
+            Err(TopologyError::Partitioned(1, 2))
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn generate_ring_and_star() {
+        let ring = Topology::ring(5);
+        assert_eq!(ring.len(), 5);
+        assert!(ring.contains(4, 0));
+        assert!(!ring.contains(0, 2));
+        assert_eq!(Topology::ring(2).len(), 1);
+
+        let star = Topology::star(5, 2).unwrap();
+        assert_eq!(star.len(), 4);
+        assert!(star.contains(0, 2));
+        assert!(!star.contains(0, 1));
+        assert_eq!(Topology::star(5, 5), Err(TopologyError::UnknownNode(5)));
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn hubs_are_linked_to_every_node() {
+        let mut topology = Topology::ring(6);
+        let partitions = vec![
+            Partition {
+                nodes: vec![0, 1, 2, 3],
+            },
+            Partition {
+                nodes: vec![0, 4, 5],
+            },
+        ];
+        topology.link_hubs(&[0, 3], 6, &partitions);
+        assert!(topology.contains(0, 2));
+        assert!(topology.contains(0, 4));
+        assert!(topology.contains(3, 1));
+        assert!(!topology.contains(3, 5));
+        assert_eq!(topology.len(), 10);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn generate_small_world() {
+        let lattice = Topology::small_world(10, 4, 0.0, 1).unwrap();
+        assert_eq!(lattice.len(), 20);
+        assert!(lattice.contains(0, 8));
+        assert!(!lattice.contains(0, 3));
+
+        // Rewiring moves links, but keeps their amount
+        let small_world = Topology::small_world(10, 4, 0.5, 1).unwrap();
+        assert_eq!(small_world.len(), 20);
+        assert_ne!(small_world, lattice);
+        assert_eq!(Topology::small_world(10, 4, 0.5, 1).unwrap(), small_world);
+
+        assert!(matches!(
+            Topology::small_world(10, 3, 0.1, 1),
+            Err(TopologyError::Invalid(_))
+        ));
+        assert!(matches!(
+            Topology::small_world(4, 4, 0.1, 1),
+            Err(TopologyError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn generate_random_from_config() {
+        let config = TopologyConfig {
+            shape: Some(TopologyShape::Random),
+            seed: 7,
+            ..TopologyConfig::default()
+        };
+        let random = Topology::from_config(&config, 20).unwrap();
+        assert_eq!(Topology::from_config(&config, 20).unwrap(), random);
+        assert!(random.len() > 0 && random.len() < 190);
+        assert_eq!(Topology::random(20, 1.0, 7).len(), 190);
+        assert_eq!(Topology::random(20, 0.0, 7).len(), 0);
+
+        let invalid = TopologyConfig {
+            link_probability: 1.5,
+            ..config.clone()
+        };
+        assert!(matches!(
+            Topology::from_config(&invalid, 20),
+            Err(TopologyError::Invalid(_))
+        ));
+        let both = TopologyConfig {
+            file: Some("topology.json".to_string()),
+            ..config
+        };
+        assert!(matches!(
+            Topology::from_config(&both, 20),
+            Err(TopologyError::Invalid(_))
+        ));
+        assert!(matches!(
+            Topology::from_config(&TopologyConfig::default(), 20),
+            Err(TopologyError::Invalid(_))
+        ));
+    }
+}
@@ -0,0 +1,297 @@
+//! This module is responsible for shaping the traffic of the links per message type, e.g. at most 2 validations per
+//! second from node 3, or 50 transactions per second on the link from node 1 to node 2.
+//!
+//! Every shaping rule limits the rate of the messages of a type on the links it matches with a token bucket per link,
+//! such that a rule without a destination limits every link of the node separately. A message that finds its bucket
+//! empty is delayed until a token is available, or dropped if the rule polices instead of shapes.
+
+use crate::message_type::MessageType;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Enum that represents what happens to a message that exceeds the rate of its shaping rule.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShapingAction {
+    /// The message is delayed until the rate allows it, so the messages are spread out over time.
+    #[default]
+    Delay,
+    /// The message is dropped.
+    Drop,
+}
+
+/// Struct that represents a shaping rule: the messages of a type on the matching links are limited to a rate.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShapingRule {
+    /// The type of the limited messages.
+    pub message_type: MessageType,
+    /// The port of the peer the limited messages come from, all peers if not set.
+    #[serde(default)]
+    pub from_port: Option<u16>,
+    /// The port of the peer the limited messages go to, all peers if not set.
+    #[serde(default)]
+    pub to_port: Option<u16>,
+    /// The amount of messages per second on every matching link.
+    pub rate: f64,
+    /// The amount of messages that can be sent at once after the link was quiet, 1 if not set.
+    #[serde(default)]
+    pub burst: Option<u32>,
+    /// What happens to the messages above the rate.
+    #[serde(default)]
+    pub action: ShapingAction,
+}
+
+impl ShapingRule {
+    /// Returns the amount of messages that can be sent at once after the link was quiet.
+    pub fn burst(&self) -> u32 {
+        self.burst.unwrap_or(1)
+    }
+
+    /// Returns whether the rule limits a message.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer where the message came from.
+    /// * 'to_port' - the port of the peer the message is sent to.
+    /// * 'message_type' - the type of the message.
+    pub fn matches(&self, from_port: u16, to_port: u16, message_type: MessageType) -> bool {
+        self.message_type == message_type
+            && self.from_port.map_or(true, |port| port == from_port)
+            && self.to_port.map_or(true, |port| port == to_port)
+    }
+
+    /// Checks whether the rule can be applied.
+    pub fn validate(&self) -> Result<(), ShapingRuleError> {
+        if !self.rate.is_finite() || self.rate <= 0.0 {
+            return Err(ShapingRuleError(format!(
+                "rate {} is not a finite number above 0",
+                self.rate
+            )));
+        }
+        if self.burst == Some(0) {
+            return Err(ShapingRuleError("burst is 0".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Struct that represents the reason a ShapingRule can not be applied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShapingRuleError(pub String);
+
+impl fmt::Display for ShapingRuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid shaping rule: {}", self.0)
+    }
+}
+
+impl Error for ShapingRuleError {}
+
+/// Enum that represents how a shaping rule treats a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shaped {
+    /// The message is within the rate, or delayed by the given duration to stay within it.
+    Delayed(Duration),
+    /// The message exceeds the rate, and is dropped.
+    Dropped,
+}
+
+/// Struct that represents a token bucket, which refills at a fixed rate up to its burst.
+/// The tokens can become negative when messages are delayed, which reserves the tokens of the coming moments.
+#[derive(Debug, Clone, PartialEq)]
+struct TokenBucket {
+    /// The amount of tokens, which is negative while tokens are reserved.
+    tokens: f64,
+    /// The moment the tokens were last refilled.
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    /// Initializes a new full TokenBucket.
+    ///
+    /// # Parameters
+    /// * 'rule' - the rule of the bucket.
+    /// * 'now' - the current moment.
+    fn new(rule: &ShapingRule, now: Instant) -> Self {
+        Self {
+            tokens: f64::from(rule.burst()),
+            refilled: now,
+        }
+    }
+
+    /// Takes a token for a message, and returns how the message is treated.
+    ///
+    /// # Parameters
+    /// * 'rule' - the rule of the bucket.
+    /// * 'now' - the current moment.
+    fn take(&mut self, rule: &ShapingRule, now: Instant) -> Shaped {
+        let refill = now.saturating_duration_since(self.refilled).as_secs_f64() * rule.rate;
+        self.tokens = (self.tokens + refill).min(f64::from(rule.burst()));
+        self.refilled = now.max(self.refilled);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Shaped::Delayed(Duration::ZERO);
+        }
+        match rule.action {
+            ShapingAction::Drop => Shaped::Dropped,
+            ShapingAction::Delay => {
+                self.tokens -= 1.0;
+                Shaped::Delayed(Duration::from_secs_f64(-self.tokens / rule.rate))
+            }
+        }
+    }
+}
+
+/// Struct that represents the shaping rules with the token buckets of the links they limit.
+#[derive(Debug, Default)]
+pub struct TrafficShaper {
+    /// The rules, of which the first matching one limits a message.
+    rules: Vec<ShapingRule>,
+    /// The token buckets by the index of their rule and the ports of the peer the messages come from and go to.
+    buckets: HashMap<(usize, u16, u16), TokenBucket>,
+}
+
+impl TrafficShaper {
+    /// Returns the shaping rules.
+    pub fn rules(&self) -> &[ShapingRule] {
+        &self.rules
+    }
+
+    /// Replaces the shaping rules. The buckets start full again, unless the rules did not change.
+    ///
+    /// # Parameters
+    /// * 'rules' - the new rules, of which the first matching one limits a message.
+    pub fn set_rules(&mut self, rules: Vec<ShapingRule>) {
+        if rules != self.rules {
+            self.rules = rules;
+            self.buckets.clear();
+        }
+    }
+
+    /// Returns how a message is treated by the first shaping rule that matches it, or None if no rule matches.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer where the message came from.
+    /// * 'to_port' - the port of the peer the message is sent to.
+    /// * 'message_type' - the type of the message.
+    /// * 'now' - the current moment.
+    pub fn shape(
+        &mut self,
+        from_port: u16,
+        to_port: u16,
+        message_type: MessageType,
+        now: Instant,
+    ) -> Option<Shaped> {
+        let (index, rule) = self
+            .rules
+            .iter()
+            .enumerate()
+            .find(|(_, rule)| rule.matches(from_port, to_port, message_type))?;
+        let bucket = self
+            .buckets
+            .entry((index, from_port, to_port))
+            .or_insert_with(|| TokenBucket::new(rule, now));
+        Some(bucket.take(rule, now))
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::message_type::MessageType;
+    use crate::traffic_shaping::{Shaped, ShapingAction, ShapingRule, TrafficShaper};
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    fn rule(rate: f64, burst: u32, action: ShapingAction) -> ShapingRule {
+        ShapingRule {
+            message_type: MessageType::Validation,
+            from_port: Some(60003),
+            to_port: None,
+            rate,
+            burst: Some(burst),
+            action,
+        }
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn rule_validation() {
+        assert!(rule(2.0, 1, ShapingAction::Delay).validate().is_ok());
+        assert!(rule(0.0, 1, ShapingAction::Delay).validate().is_err());
+        assert!(rule(f64::NAN, 1, ShapingAction::Delay).validate().is_err());
+        assert!(rule(2.0, 0, ShapingAction::Delay).validate().is_err());
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn excess_messages_are_dropped() {
+        let mut shaper = TrafficShaper::default();
+        shaper.set_rules(vec![rule(2.0, 2, ShapingAction::Drop)]);
+        let start = Instant::now();
+        let shape = |shaper: &mut TrafficShaper, ms: u64| {
+            shaper.shape(
+                60003,
+                60000,
+                MessageType::Validation,
+                start + Duration::from_millis(ms),
+            )
+        };
+        let sent = Some(Shaped::Delayed(Duration::ZERO));
+        assert_eq!(shape(&mut shaper, 0), sent);
+        assert_eq!(shape(&mut shaper, 0), sent);
+        assert_eq!(shape(&mut shaper, 0), Some(Shaped::Dropped));
+        assert_eq!(shape(&mut shaper, 400), Some(Shaped::Dropped));
+        assert_eq!(shape(&mut shaper, 600), sent);
+        assert_eq!(shape(&mut shaper, 600), Some(Shaped::Dropped));
+
+        // Every link has a bucket of its own, and other types and nodes are not limited
+        assert_eq!(
+            shaper.shape(60003, 60001, MessageType::Validation, start),
+            sent
+        );
+        assert_eq!(
+            shaper.shape(60003, 60000, MessageType::Transaction, start),
+            None
+        );
+        assert_eq!(
+            shaper.shape(60002, 60000, MessageType::Validation, start),
+            None
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn excess_messages_are_spread_out() {
+        let mut shaper = TrafficShaper::default();
+        shaper.set_rules(vec![rule(4.0, 1, ShapingAction::Delay)]);
+        let start = Instant::now();
+        let delays: Vec<Option<Shaped>> = (0..4)
+            .map(|_| shaper.shape(60003, 60000, MessageType::Validation, start))
+            .collect();
+        assert_eq!(
+            delays,
+            [0, 250, 500, 750].map(|ms| Some(Shaped::Delayed(Duration::from_millis(ms))))
+        );
+
+        // After the reserved tokens are used up, the bucket refills up to its burst only
+        let later = start + Duration::from_secs(10);
+        assert_eq!(
+            shaper.shape(60003, 60000, MessageType::Validation, later),
+            Some(Shaped::Delayed(Duration::ZERO))
+        );
+        assert_eq!(
+            shaper.shape(60003, 60000, MessageType::Validation, later),
+            Some(Shaped::Delayed(Duration::from_millis(250)))
+        );
+
+        // Replacing the rules by different rules refills the buckets
+        shaper.set_rules(vec![rule(4.0, 2, ShapingAction::Delay)]);
+        assert_eq!(
+            shaper.shape(60003, 60000, MessageType::Validation, later),
+            Some(Shaped::Delayed(Duration::ZERO))
+        );
+    }
+}
@@ -0,0 +1,309 @@
+//! This module is responsible for submitting a stream of payment transactions to the validator nodes,
+//! such that the intercepted network is processing realistic traffic instead of idling.
+
+use crate::config::TxGeneratorConfig;
+use crate::node_rpc::NodeRpcClient;
+use crate::run_seed::{self, RunSeed};
+use rand::rngs::StdRng;
+use rand::Rng;
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+
+/// The account that holds all XRP in the genesis ledger.
+const GENESIS_ADDRESS: &str = "rHb9CJAWyB4rj91VRWn96DkukG4bwdtyTh";
+/// The secret of the genesis account, derived from the passphrase 'masterpassphrase'.
+const GENESIS_SECRET: &str = "snoPBrXtMeMyMHUVTgbuqAfg1SUTb";
+/// How long to wait for the generated accounts to appear in a validated ledger.
+const FUNDING_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Struct that represents an account which can send and receive payments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Account {
+    /// The classic address of the account.
+    pub address: String,
+    /// The secret used to sign transactions of the account.
+    pub secret: String,
+}
+
+/// Struct that represents the generator which submits transactions to the nodes.
+#[derive(Debug)]
+pub struct TxGenerator {
+    /// The configuration of the generated traffic.
+    config: TxGeneratorConfig,
+    /// The RPC clients of all nodes, transactions are submitted to them in a round-robin fashion.
+    clients: Vec<NodeRpcClient>,
+    /// The generator of the random senders and receivers of the payments.
+    rng: StdRng,
+}
+
+impl TxGenerator {
+    /// Initializes a new TxGenerator.
+    ///
+    /// # Parameters
+    /// * 'config' - the configuration of the generated traffic.
+    /// * 'clients' - the RPC clients of the nodes the transactions are submitted to.
+    /// * 'seed' - the seed of the run the payments are derived from, if the run is seeded.
+    pub fn new(
+        config: TxGeneratorConfig,
+        clients: Vec<NodeRpcClient>,
+        seed: Option<RunSeed>,
+    ) -> Self {
+        Self {
+            config,
+            clients,
+            rng: run_seed::rng(seed, "tx_generator"),
+        }
+    }
+
+    /// Creates and funds the configured amount of accounts, after which payments between those accounts
+    /// are submitted at the configured rate until the configured duration has passed.
+    pub async fn run(mut self) {
+        if self.clients.is_empty() || self.config.validate().is_err() {
+            warn!("Transaction generator has no nodes or an invalid rate, not starting");
+            return;
+        }
+
+        tokio::time::sleep(Duration::from_secs(self.config.start_delay_secs)).await;
+
+        let accounts = match self.setup_accounts().await {
+            Ok(accounts) => accounts,
+            Err(e) => {
+                error!(
+                    "Could not set up accounts for the transaction generator: {}",
+                    e
+                );
+                return;
+            }
+        };
+        if accounts.len() < 2 {
+            warn!("Transaction generator needs at least one generated account, not starting");
+            return;
+        }
+        info!(
+            "Transaction generator funded {} accounts, submitting {} tx/s",
+            accounts.len(),
+            self.config.rate
+        );
+
+        let start = Instant::now();
+        let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / self.config.rate));
+        let mut submitted: u64 = 0;
+        let mut accepted: u64 = 0;
+
+        loop {
+            interval.tick().await;
+            if let Some(duration_secs) = self.config.duration_secs {
+                if start.elapsed() >= Duration::from_secs(duration_secs) {
+                    break;
+                }
+            }
+
+            let (from, to) = Self::pick_pair(&mut self.rng, accounts.len());
+            let client = &self.clients[submitted as usize % self.clients.len()];
+            submitted += 1;
+            match Self::submit_payment(
+                client,
+                &accounts[from],
+                accounts[to].address.as_str(),
+                self.config.payment_drops,
+            )
+            .await
+            {
+                Ok(engine_result) => {
+                    debug!(
+                        "Submitted payment {} -> {} to port {}: {}",
+                        accounts[from].address, accounts[to].address, client.port, engine_result
+                    );
+                    if Self::is_accepted(engine_result.as_str()) {
+                        accepted += 1;
+                    }
+                }
+                Err(e) => warn!("Could not submit payment to port {}: {}", client.port, e),
+            }
+        }
+
+        info!(
+            "Transaction generator finished: {} submitted, {} accepted",
+            submitted, accepted
+        );
+    }
+
+    /// Creates the configured amount of accounts, funds them from the genesis account and waits until
+    /// all of them are part of a validated ledger.
+    async fn setup_accounts(&self) -> Result<Vec<Account>, Box<dyn std::error::Error>> {
+        let client = &self.clients[0];
+        let genesis = Account {
+            address: GENESIS_ADDRESS.to_string(),
+            secret: GENESIS_SECRET.to_string(),
+        };
+
+        let mut accounts = Vec::new();
+        for _ in 0..self.config.accounts {
+            let wallet = client.request("wallet_propose", json!({})).await?;
+            let account = Self::parse_wallet(&wallet).ok_or("Invalid wallet_propose response")?;
+            let engine_result = Self::submit_payment(
+                client,
+                &genesis,
+                account.address.as_str(),
+                self.config.funding_drops,
+            )
+            .await?;
+            if !Self::is_accepted(engine_result.as_str()) {
+                return Err(
+                    format!("Funding {} failed: {}", account.address, engine_result).into(),
+                );
+            }
+            accounts.push(account);
+        }
+
+        let start = Instant::now();
+        for account in accounts.iter() {
+            loop {
+                let account_info = client
+                    .request(
+                        "account_info",
+                        json!({ "account": account.address, "ledger_index": "validated" }),
+                    )
+                    .await?;
+                if account_info["status"] == "success" {
+                    break;
+                }
+                if start.elapsed() > FUNDING_TIMEOUT {
+                    return Err(format!("Account {} was never validated", account.address).into());
+                }
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+
+        // The genesis account can also take part in the traffic, it has plenty of funds.
+        accounts.push(genesis);
+        Ok(accounts)
+    }
+
+    /// Signs and submits a payment on a node, returns the engine result of the submission.
+    ///
+    /// # Parameters
+    /// * 'client' - the RPC client of the node the payment is submitted to.
+    /// * 'from' - the account sending the payment.
+    /// * 'to' - the address of the account receiving the payment.
+    /// * 'drops' - the amount of drops to be sent.
+    async fn submit_payment(
+        client: &NodeRpcClient,
+        from: &Account,
+        to: &str,
+        drops: u64,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let result = client
+            .request("submit", Self::payment_params(from, to, drops))
+            .await?;
+        match result["engine_result"].as_str() {
+            Some(engine_result) => Ok(engine_result.to_string()),
+            None => Err(format!("Submission failed: {}", result).into()),
+        }
+    }
+
+    /// Creates the parameters of a sign-and-submit request for a payment.
+    ///
+    /// # Parameters
+    /// * 'from' - the account sending the payment.
+    /// * 'to' - the address of the account receiving the payment.
+    /// * 'drops' - the amount of drops to be sent.
+    fn payment_params(from: &Account, to: &str, drops: u64) -> Value {
+        json!({
+            "secret": from.secret,
+            "tx_json": {
+                "TransactionType": "Payment",
+                "Account": from.address,
+                "Destination": to,
+                "Amount": drops.to_string(),
+            },
+        })
+    }
+
+    /// Parses the response of a 'wallet_propose' request into an Account.
+    ///
+    /// # Parameters
+    /// * 'wallet' - the result object of the 'wallet_propose' request.
+    fn parse_wallet(wallet: &Value) -> Option<Account> {
+        Some(Account {
+            address: wallet["account_id"].as_str()?.to_string(),
+            secret: wallet["master_seed"].as_str()?.to_string(),
+        })
+    }
+
+    /// Checks whether an engine result means the transaction was (provisionally) accepted.
+    /// Results starting with 'ter' are retried by the node, so they are also considered accepted.
+    ///
+    /// # Parameters
+    /// * 'engine_result' - the engine result of a submission.
+    fn is_accepted(engine_result: &str) -> bool {
+        engine_result == "tesSUCCESS" || engine_result.starts_with("ter")
+    }
+
+    /// Picks two different random indices of accounts, the sender and the receiver.
+    ///
+    /// # Parameters
+    /// * 'rng' - the generator of the indices.
+    /// * 'amount' - the amount of accounts, should be at least 2.
+    fn pick_pair(rng: &mut StdRng, amount: usize) -> (usize, usize) {
+        let from = rng.gen_range(0..amount);
+        let to = (from + rng.gen_range(1..amount)) % amount;
+        (from, to)
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::run_seed::RunSeed;
+    use crate::tx_generator::{Account, TxGenerator};
+    use serde_json::json;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn payment_params_test() {
+        let from = Account {
+            address: "rFrom".to_string(),
+            secret: "sSecret".to_string(),
+        };
+        let params = TxGenerator::payment_params(&from, "rTo", 1000);
+        assert_eq!(params["secret"], "sSecret");
+        assert_eq!(params["tx_json"]["TransactionType"], "Payment");
+        assert_eq!(params["tx_json"]["Account"], "rFrom");
+        assert_eq!(params["tx_json"]["Destination"], "rTo");
+        assert_eq!(params["tx_json"]["Amount"], "1000");
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn parse_wallet_test() {
+        let wallet = json!({ "account_id": "rAccount", "master_seed": "sSeed" });
+        assert_eq!(
+            TxGenerator::parse_wallet(&wallet),
+            Some(Account {
+                address: "rAccount".to_string(),
+                secret: "sSeed".to_string(),
+            })
+        );
+        assert_eq!(TxGenerator::parse_wallet(&json!({})), None);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn pick_pair_is_distinct() {
+        let mut rng = RunSeed(1).rng("tx_generator");
+        for _ in 0..100 {
+            let (from, to) = TxGenerator::pick_pair(&mut rng, 3);
+            assert_ne!(from, to);
+            assert!(from < 3 && to < 3);
+        }
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn is_accepted_test() {
+        assert!(TxGenerator::is_accepted("tesSUCCESS"));
+        assert!(TxGenerator::is_accepted("terQUEUED"));
+        assert!(!TxGenerator::is_accepted("tefPAST_SEQ"));
+    }
+}
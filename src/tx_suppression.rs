@@ -0,0 +1,291 @@
+//! This module is responsible for the transaction-relay suppression mode: the transactions relayed on selected links are
+//! dropped for a period of the run, while the consensus messages on those links are delivered as decided, such that the
+//! effect of transactions that do not reach every node on the contents of the ledgers can be measured.
+//!
+//! Both the relay of single transactions and the batches of the transaction reduce-relay are dropped. The transaction
+//! sets that the nodes acquire during consensus are not, so a node can still learn the transactions of a proposed set.
+//! The ledgers every node closes before, during and after the suppression are counted together with their transactions,
+//! and reported when the run ends.
+
+use crate::config::TxSuppressionConfig;
+use crate::event_bus::{Event, EventKind};
+use crate::interceptor_state::InterceptorState;
+use crate::message_type::MessageType;
+use crate::run_id::RunId;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+/// The message types that relay transactions, which are dropped on the suppressed links.
+pub const SUPPRESSED_TYPES: [MessageType; 3] = [
+    MessageType::Transaction,
+    MessageType::HaveTransactions,
+    MessageType::Transactions,
+];
+
+/// Struct that represents the links on which the relay of transactions is suppressed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TxSuppression {
+    /// The links whose transactions are dropped, by the ports of the peer the messages come from and go to.
+    pub links: Vec<(u16, u16)>,
+}
+
+impl TxSuppression {
+    /// Returns whether a message on a link is dropped by the suppression.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer where the message came from.
+    /// * 'to_port' - the port of the peer the message is sent to.
+    /// * 'message_type' - the type of the message.
+    pub fn suppresses(&self, from_port: u16, to_port: u16, message_type: MessageType) -> bool {
+        SUPPRESSED_TYPES.contains(&message_type) && self.links.contains(&(from_port, to_port))
+    }
+}
+
+/// Suppresses the relay of transactions for a fixed period of the run, as configured locally.
+///
+/// # Parameters
+/// * 'suppression' - the suppression.
+/// * 'start_after' - how long after the links are started the suppression starts.
+/// * 'duration' - how long the suppression lasts, until the end of the run if None.
+/// * 'state' - the runtime state, where the suppression is set.
+pub async fn run_scheduled(
+    suppression: TxSuppression,
+    start_after: Duration,
+    duration: Option<Duration>,
+    state: Arc<InterceptorState>,
+) {
+    let clock = state.clock();
+    clock.sleep(start_after).await;
+    info!("Suppressing transaction relay on: {:?}", suppression.links);
+    state.set_tx_suppression(suppression);
+    if let Some(duration) = duration {
+        clock.sleep(duration).await;
+        info!("Ending the transaction relay suppression");
+        state.set_tx_suppression(TxSuppression::default());
+    }
+}
+
+/// Struct that represents the ledgers a node closed in a period of the run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ClosedLedgers {
+    /// The amount of closed ledgers.
+    pub ledgers: u64,
+    /// The amount of transactions in those ledgers.
+    pub transactions: u64,
+}
+
+/// Struct that represents the ledgers a node closed before, during and after the suppression.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct NodeLedgers {
+    pub node_id: u32,
+    pub before: ClosedLedgers,
+    pub during: ClosedLedgers,
+    pub after: ClosedLedgers,
+}
+
+/// Struct that represents the report of the suppression, as it is saved when the run ends.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TxSuppressionReport {
+    /// The links whose transactions were dropped, by the ports of the peer the messages come from and go to.
+    pub links: Vec<(u16, u16)>,
+    /// The amount of dropped messages that relayed transactions.
+    pub suppressed: u64,
+    /// The ledgers every node closed, ordered by node ID.
+    pub nodes: Vec<NodeLedgers>,
+}
+
+/// Enum that represents a period of the run relative to the suppression.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Period {
+    #[default]
+    Before,
+    During,
+    After,
+}
+
+/// Struct that represents the measurement of the suppression, which follows the event bus.
+#[derive(Debug, Default)]
+pub struct TxSuppressionMeter {
+    /// The period the run is in.
+    period: Mutex<Period>,
+    /// The report so far.
+    report: Mutex<TxSuppressionReport>,
+    /// The ledgers every node closed so far, by node ID.
+    nodes: Mutex<BTreeMap<u32, NodeLedgers>>,
+}
+
+impl TxSuppressionMeter {
+    /// Follows the event bus until it closes, and counts the ledger closes and the dropped transactions.
+    ///
+    /// # Parameters
+    /// * 'events' - the subscription to the event bus.
+    pub async fn follow(self: Arc<Self>, mut events: broadcast::Receiver<Arc<Event>>) {
+        loop {
+            match events.recv().await {
+                Ok(event) => self.add_event(&event),
+                Err(RecvError::Lagged(missed)) => {
+                    warn!(
+                        "The transaction suppression report missed {} events",
+                        missed
+                    )
+                }
+                Err(RecvError::Closed) => return,
+            }
+        }
+    }
+
+    /// Counts an event: a change of the suppression moves to the next period, and ledger closes and transactions that
+    /// were dropped by the suppression are counted.
+    ///
+    /// # Parameters
+    /// * 'event' - the event.
+    pub fn add_event(&self, event: &Event) {
+        match &event.kind {
+            EventKind::TxSuppressionChanged { links } => {
+                let mut period = self.period.lock().unwrap();
+                if links.is_empty() {
+                    if *period == Period::During {
+                        *period = Period::After;
+                    }
+                } else {
+                    *period = Period::During;
+                    let mut report = self.report.lock().unwrap();
+                    for link in links.iter() {
+                        if !report.links.contains(link) {
+                            report.links.push(*link);
+                        }
+                    }
+                }
+            }
+            EventKind::PacketDropped { reason, .. } if reason == "tx_suppression" => {
+                self.report.lock().unwrap().suppressed += 1;
+            }
+            EventKind::LedgerClosed {
+                node_id, txn_count, ..
+            } => {
+                let period = *self.period.lock().unwrap();
+                let mut nodes = self.nodes.lock().unwrap();
+                let node = nodes.entry(*node_id).or_insert(NodeLedgers {
+                    node_id: *node_id,
+                    ..NodeLedgers::default()
+                });
+                let closed = match period {
+                    Period::Before => &mut node.before,
+                    Period::During => &mut node.during,
+                    Period::After => &mut node.after,
+                };
+                closed.ledgers += 1;
+                closed.transactions += txn_count;
+            }
+            _ => (),
+        }
+    }
+
+    /// Returns the report of the suppression so far.
+    pub fn report(&self) -> TxSuppressionReport {
+        TxSuppressionReport {
+            nodes: self.nodes.lock().unwrap().values().copied().collect(),
+            ..self.report.lock().unwrap().clone()
+        }
+    }
+
+    /// Writes the report to a JSON file in a directory, named after the run, and returns its path.
+    ///
+    /// # Parameters
+    /// * 'config' - the configuration of the suppression, with the directory of the report.
+    /// * 'run_id' - the ID of the run.
+    pub fn save(
+        &self,
+        config: &TxSuppressionConfig,
+        run_id: &RunId,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        fs::create_dir_all(&config.directory)?;
+        let path = Path::new(&config.directory).join(format!("tx-suppression-{}.json", run_id));
+        fs::write(&path, serde_json::to_string_pretty(&self.report())?)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::event_bus::{Event, EventKind};
+    use crate::message_type::MessageType;
+    use crate::tx_suppression::{ClosedLedgers, TxSuppression, TxSuppressionMeter};
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn only_transactions_on_the_links_are_suppressed() {
+        let suppression = TxSuppression {
+            links: vec![(60000, 60001)],
+        };
+        assert!(suppression.suppresses(60000, 60001, MessageType::Transaction));
+        assert!(suppression.suppresses(60000, 60001, MessageType::HaveTransactions));
+        assert!(!suppression.suppresses(60001, 60000, MessageType::Transaction));
+        assert!(!suppression.suppresses(60000, 60001, MessageType::ProposeLedger));
+        assert!(!suppression.suppresses(60000, 60001, MessageType::HaveSet));
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn ledgers_are_counted_per_period() {
+        let meter = TxSuppressionMeter::default();
+        let event = |kind| Event {
+            timestamp_ns: 0,
+            kind,
+        };
+        let close = |node_id, txn_count| {
+            event(EventKind::LedgerClosed {
+                node_id,
+                ledger_index: 5,
+                ledger_hash: "AB".to_string(),
+                txn_count,
+            })
+        };
+        meter.add_event(&close(0, 10));
+        meter.add_event(&event(EventKind::TxSuppressionChanged {
+            links: vec![(60000, 60001)],
+        }));
+        meter.add_event(&event(EventKind::packet_dropped(
+            60000,
+            60001,
+            MessageType::Transaction,
+            Some(3),
+            "tx_suppression",
+        )));
+        meter.add_event(&event(EventKind::packet_dropped(
+            60000,
+            60001,
+            MessageType::Validation,
+            Some(4),
+            "blackhole",
+        )));
+        meter.add_event(&close(0, 2));
+        meter.add_event(&close(1, 4));
+        meter.add_event(&event(EventKind::TxSuppressionChanged {
+            links: Vec::new(),
+        }));
+        meter.add_event(&close(0, 12));
+
+        let report = meter.report();
+        assert_eq!(report.links, vec![(60000, 60001)]);
+        assert_eq!(report.suppressed, 1);
+        assert_eq!(report.nodes.len(), 2);
+        let ledgers = |ledgers, transactions| ClosedLedgers {
+            ledgers,
+            transactions,
+        };
+        assert_eq!(report.nodes[0].before, ledgers(1, 10));
+        assert_eq!(report.nodes[0].during, ledgers(1, 2));
+        assert_eq!(report.nodes[0].after, ledgers(1, 12));
+        assert_eq!(report.nodes[1].before, ledgers(0, 0));
+        assert_eq!(report.nodes[1].during, ledgers(1, 4));
+    }
+}
@@ -0,0 +1,317 @@
+//! This module is responsible for the `validate` subcommand, which checks the configuration of a run, the topology of its
+//! network and the faults it schedules without starting any containers or connecting to the controller.
+//!
+//! The network is configured by the controller when a run starts, so the amount of nodes and the first peer port are
+//! given instead. Every problem is reported, instead of only the first one that would end a run.
+
+use crate::config::{InterceptorConfig, TenantConfig};
+use crate::equivocation::EquivocationRule;
+use crate::flapping::FlapTiming;
+use crate::gray_failure::GrayFailureRule;
+use crate::hot_reload::LocalRules;
+use crate::manifest::ManifestRule;
+use crate::message_type::MessageType;
+use crate::packet_client::proto::Config;
+use crate::protocol_version::ProtocolVersion;
+use crate::squelch::SquelchRule;
+use crate::topology::Topology;
+use std::error::Error;
+
+/// Returns the problems of the configuration of a run on a network, the same ones that would end the run, or none if
+/// the run can start.
+///
+/// # Parameters
+/// * 'config' - the configuration of the run.
+/// * 'network_config' - the network as the controller would configure it.
+pub fn validate(config: &InterceptorConfig, network_config: &Config) -> Vec<String> {
+    let number_of_nodes = network_config.number_of_nodes;
+    let ports: Vec<u16> = (0..number_of_nodes)
+        .map(|i| (network_config.base_port_peer + i) as u16)
+        .collect();
+    let node_exists = |node: u32| node < number_of_nodes;
+    let mut problems = Vec::new();
+    let mut check = |section: &str, result: Result<(), String>| {
+        if let Err(e) = result {
+            problems.push(format!("Invalid {} configuration: {}", section, e));
+        }
+    };
+
+    let rules_config = match config
+        .hot_reload
+        .as_ref()
+        .and_then(|hot_reload_config| hot_reload_config.file.as_deref())
+    {
+        Some(file) => match InterceptorConfig::from_file(file) {
+            Ok(rules_config) => Some(rules_config),
+            Err(e) => {
+                check("hot reload", Err(format!("could not load {}: {}", file, e)));
+                None
+            }
+        },
+        None => Some(config.clone()),
+    };
+    if let Some(rules_config) = rules_config {
+        check(
+            "local rules",
+            LocalRules::from_config(&rules_config, &ports).map(|_| ()),
+        );
+    }
+    for rule in config
+        .gray_failure
+        .iter()
+        .flat_map(|config| config.rules.iter())
+    {
+        check(
+            "gray failure",
+            GrayFailureRule::from_config(rule, &ports)
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+        );
+    }
+    for rule in config
+        .manifests
+        .iter()
+        .flat_map(|config| config.rules.iter())
+    {
+        check(
+            "manifest",
+            ManifestRule::from_config(rule, &ports)
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+        );
+    }
+    for rule in config.squelch.iter().flat_map(|config| config.rules.iter()) {
+        check(
+            "squelch",
+            SquelchRule::from_config(rule, &ports)
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+        );
+    }
+    for rule in config
+        .equivocation
+        .iter()
+        .flat_map(|config| config.rules.iter())
+    {
+        check(
+            "equivocation",
+            EquivocationRule::from_config(rule, &ports)
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+        );
+    }
+    let time_dilation = config.forwarding.time_dilation;
+    if !time_dilation.is_finite() || time_dilation <= 0.0 {
+        check(
+            "forwarding",
+            Err(format!(
+                "time dilation {} has to be positive",
+                time_dilation
+            )),
+        );
+    }
+    let protocols: Result<Vec<ProtocolVersion>, String> = config
+        .handshake
+        .protocols
+        .iter()
+        .map(|protocol| protocol.parse())
+        .collect();
+    check(
+        "handshake",
+        protocols.and_then(|protocols| match protocols.is_empty() {
+            true => Err("no protocol versions configured".to_string()),
+            false => Ok(()),
+        }),
+    );
+
+    if let Some(topology_config) = &config.topology {
+        check(
+            "topology",
+            Topology::from_config(topology_config, number_of_nodes)
+                .and_then(|topology| topology.validate(network_config))
+                .map_err(|e| e.to_string()),
+        );
+    }
+    let roles = config.roles.clone().unwrap_or_default();
+    check("role", roles.validate(number_of_nodes));
+    if let Some(resource_config) = &config.resources {
+        check("resource", resource_config.validate(number_of_nodes));
+    }
+    if let Some(clock_skew_config) = &config.clock_skew {
+        check("clock skew", clock_skew_config.validate(number_of_nodes));
+    }
+    if let Some(amendment_config) = &config.amendments {
+        check("amendment", amendment_config.validate(number_of_nodes));
+    }
+    if let Some(validator_list_config) = &config.validator_list {
+        check(
+            "validator list",
+            validator_list_config.validate(&roles, number_of_nodes),
+        );
+    }
+    check("addressing", config.addressing.validate(number_of_nodes));
+    check("port", config.ports.validate());
+    if let Some(tx_generator_config) = &config.tx_generator {
+        check("transaction generator", tx_generator_config.validate());
+    }
+
+    if let Some(replay_config) = &config.replay {
+        for name in replay_config.message_types.iter() {
+            check("replay", name.parse::<MessageType>().map(|_| ()));
+        }
+    }
+    if let Some(capture_file_config) = &config.capture_file {
+        for name in capture_file_config.message_types.iter() {
+            check("capture file", name.parse::<MessageType>().map(|_| ()));
+        }
+    }
+    if let Some(flapping_config) = &config.flapping {
+        check(
+            "flapping",
+            FlapTiming::from_config(flapping_config).map(|_| ()),
+        );
+        for node in flapping_config.links.iter().flatten().copied() {
+            if !node_exists(node) {
+                check("flapping", Err(format!("node {} does not exist", node)));
+            }
+        }
+    }
+    if let Some(eclipse_config) = &config.eclipse {
+        for node in [eclipse_config.victim]
+            .iter()
+            .chain(eclipse_config.visible_peers.iter())
+        {
+            if !node_exists(*node) {
+                check("eclipse", Err(format!("node {} does not exist", node)));
+            }
+        }
+        for name in eclipse_config.visible_types.iter() {
+            check("eclipse", name.parse::<MessageType>().map(|_| ()));
+        }
+    }
+    if let Some(sybil_config) = &config.sybil {
+        if !node_exists(sybil_config.target) {
+            check(
+                "sybil",
+                Err(format!("node {} does not exist", sybil_config.target)),
+            );
+        }
+    }
+    if let Some(cpu_throttle_config) = &config.cpu_throttle {
+        if cpu_throttle_config.cpus <= 0.0 {
+            check(
+                "CPU throttle",
+                Err("the amount of CPUs has to be positive".to_string()),
+            );
+        }
+    }
+    if let Some(phase_config) = &config.phases {
+        if phase_config.cool_down_secs.is_some() && phase_config.fault_secs.is_none() {
+            check(
+                "phase",
+                Err("cool_down_secs requires fault_secs, the end of the fault window".to_string()),
+            );
+        }
+    }
+    if let Some(termination_config) = &config.termination {
+        if termination_config.on_violation && config.assertions.is_none() {
+            check(
+                "termination",
+                Err("on_violation requires the [assertions] section".to_string()),
+            );
+        }
+    }
+    problems
+}
+
+/// Runs the `validate` subcommand: checks a configuration file, and the configuration files of its networks if it runs
+/// several, and prints every problem. Returns an error if there are any.
+///
+/// # Parameters
+/// * 'path' - the path of the configuration file.
+/// * 'number_of_nodes' - the amount of nodes in every network.
+/// * 'base_port_peer' - the peer port of the first node of every network.
+pub fn validate_command(
+    path: &str,
+    number_of_nodes: u32,
+    base_port_peer: u32,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let network_config = Config {
+        number_of_nodes,
+        base_port_peer,
+        ..Config::default()
+    };
+    let config = InterceptorConfig::from_file(path)
+        .map_err(|e| format!("Could not load configuration file {}: {}", path, e))?;
+    let mut problems: Vec<String> = validate(&config, &network_config)
+        .into_iter()
+        .map(|problem| format!("{}: {}", path, problem))
+        .collect();
+    if let Err(e) = TenantConfig::validate(&config.tenants) {
+        problems.push(format!("{}: Invalid tenant configuration: {}", path, e));
+    }
+    for tenant in config.tenants.iter() {
+        match InterceptorConfig::from_file(&tenant.config) {
+            Ok(tenant_config) => problems.extend(
+                validate(&tenant_config, &network_config)
+                    .into_iter()
+                    .map(|problem| format!("{}: {}", tenant.config, problem)),
+            ),
+            Err(e) => problems.push(format!(
+                "Could not load configuration file {} of network {}: {}",
+                tenant.config, tenant.name, e
+            )),
+        }
+    }
+    if !problems.is_empty() {
+        for problem in problems.iter() {
+            eprintln!("{}", problem);
+        }
+        return Err(format!("{} has {} problems", path, problems.len()).into());
+    }
+    println!(
+        "{} is valid for a network of {} nodes",
+        path, number_of_nodes
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::config::InterceptorConfig;
+    use crate::packet_client::proto::Config;
+    use crate::validate::validate;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn every_problem_is_reported() {
+        let network_config = Config {
+            number_of_nodes: 3,
+            base_port_peer: 60000,
+            ..Config::default()
+        };
+        assert!(validate(&InterceptorConfig::default(), &network_config).is_empty());
+
+        let config = InterceptorConfig::parse(
+            r#"
+            [topology]
+            shape = "star"
+            hub = 5
+
+            [roles]
+            tracking = [0, 1, 2]
+
+            [eclipse]
+            victim = 1
+            visible_peers = [0]
+            visible_types = ["mtNOTHING"]
+            "#,
+        )
+        .unwrap();
+        let problems = validate(&config, &network_config);
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert!(problems[0].starts_with("Invalid topology configuration"));
+        assert!(problems[1].starts_with("Invalid role configuration"));
+        assert!(problems[2].starts_with("Invalid eclipse configuration"));
+    }
+}
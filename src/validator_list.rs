@@ -0,0 +1,369 @@
+//! This module is responsible for the validator list the nodes get their UNL from when a validator list publisher is
+//! configured, instead of a fixed list of trusted validators.
+//!
+//! The interceptor acts as the publisher: it generates a master key and a signing key, delegates from the one to the
+//! other in a manifest, and signs every published list with the signing key. The list is written to a directory that
+//! is served by the publisher container, and the nodes trust the master key of the publisher. Publishing a new list
+//! with a higher sequence rotates the UNL of all nodes, see `ValidatorList::verify` in rippled for the format.
+
+use crate::config::ValidatorListRotation;
+use crate::keygen::{self, KeyType, NODE_PUBLIC_KEY};
+use crate::peer_connector::RIPPLE_EPOCH_OFFSET_SECS;
+use base64::engine::general_purpose;
+use base64::Engine;
+use rand::rngs::StdRng;
+use rand::Rng;
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
+use serde_json::json;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// The name of the file the list is published as.
+pub const PUBLISHED_FILE: &str = "vl.json";
+/// The version of the format of the published list.
+const LIST_VERSION: u32 = 1;
+/// The prefix of the hash that is signed in a manifest, `HashPrefix::manifest` in rippled.
+pub const MANIFEST_HASH_PREFIX: [u8; 4] = *b"MAN\0";
+/// The field header of `sfSequence` in a serialized object.
+pub const FIELD_SEQUENCE: [u8; 1] = [0x24];
+/// The field header of `sfPublicKey` in a serialized object.
+pub const FIELD_PUBLIC_KEY: [u8; 1] = [0x71];
+/// The field header of `sfSigningPubKey` in a serialized object.
+pub const FIELD_SIGNING_PUBLIC_KEY: [u8; 1] = [0x73];
+/// The field header of `sfSignature` in a serialized object.
+pub const FIELD_SIGNATURE: [u8; 1] = [0x76];
+/// The field header of `sfMasterSignature` in a serialized object.
+pub const FIELD_MASTER_SIGNATURE: [u8; 2] = [0x70, 0x12];
+
+/// Struct that represents the keys of the publisher of the validator list.
+#[derive(Debug, Clone)]
+pub struct Publisher {
+    /// The master key of the publisher, which the nodes trust.
+    master_key: SecretKey,
+    /// The key the lists are signed with, delegated to by the master key in the manifest.
+    signing_key: SecretKey,
+}
+
+impl Publisher {
+    /// Generates a publisher with a random master key and signing key.
+    ///
+    /// # Parameters
+    /// * 'rng' - the generator of the keys.
+    pub fn generate(rng: &mut StdRng) -> Self {
+        Publisher {
+            master_key: random_key(rng),
+            signing_key: random_key(rng),
+        }
+    }
+
+    /// Returns the master public key of the publisher in hex, as it is configured in `[validator_list_keys]`.
+    pub fn public_key(&self) -> String {
+        hex::encode_upper(public_key(&self.master_key))
+    }
+
+    /// Returns the serialized manifest of the publisher, which delegates from its master key to its signing key and is
+    /// signed by both.
+    pub fn manifest(&self) -> Vec<u8> {
+        let master_public_key = public_key(&self.master_key);
+        let signing_public_key = public_key(&self.signing_key);
+        let mut signing_data = MANIFEST_HASH_PREFIX.to_vec();
+        signing_data.extend(FIELD_SEQUENCE);
+        signing_data.extend(1u32.to_be_bytes());
+        push_blob(&mut signing_data, &FIELD_PUBLIC_KEY, &master_public_key);
+        push_blob(
+            &mut signing_data,
+            &FIELD_SIGNING_PUBLIC_KEY,
+            &signing_public_key,
+        );
+
+        let mut manifest = signing_data[MANIFEST_HASH_PREFIX.len()..].to_vec();
+        push_blob(
+            &mut manifest,
+            &FIELD_SIGNATURE,
+            &sign(&signing_data, &self.signing_key),
+        );
+        push_blob(
+            &mut manifest,
+            &FIELD_MASTER_SIGNATURE,
+            &sign(&signing_data, &self.master_key),
+        );
+        manifest
+    }
+
+    /// Signs a list of validators and returns it in the JSON format served to the nodes.
+    ///
+    /// # Parameters
+    /// * 'validators' - the validation public keys of the listed validators, in hex.
+    /// * 'sequence' - the sequence of the list, which has to increase with every published list.
+    /// * 'expiration' - the time the list expires, in seconds since the Ripple epoch.
+    /// * 'refresh_interval_mins' - the interval in minutes in which the nodes fetch the list again.
+    pub fn sign_list(
+        &self,
+        validators: &[String],
+        sequence: u32,
+        expiration: u64,
+        refresh_interval_mins: u32,
+    ) -> String {
+        let blob = json!({
+            "sequence": sequence,
+            "expiration": expiration,
+            "validators": validators
+                .iter()
+                .map(|key| json!({ "validation_public_key": key }))
+                .collect::<Vec<_>>(),
+        })
+        .to_string();
+        json!({
+            "public_key": self.public_key(),
+            "manifest": general_purpose::STANDARD.encode(self.manifest()),
+            "blob": general_purpose::STANDARD.encode(&blob),
+            "signature": hex::encode_upper(sign(blob.as_bytes(), &self.signing_key)),
+            "version": LIST_VERSION,
+            "refresh_interval": refresh_interval_mins,
+        })
+        .to_string()
+    }
+}
+
+/// Struct that represents the site the validator list is published to: a directory served by the publisher container.
+#[derive(Debug)]
+pub struct ValidatorListSite {
+    /// The publisher that signs the lists.
+    publisher: Publisher,
+    /// The directory the list is written to.
+    directory: PathBuf,
+    /// The validation public keys of the nodes in hex, by their IDs.
+    public_keys: Vec<String>,
+    /// The sequence of the last published list.
+    sequence: u32,
+    /// How long a published list is valid.
+    expiration: Duration,
+    /// The interval in minutes in which the nodes fetch the list again.
+    refresh_interval_mins: u32,
+}
+
+impl ValidatorListSite {
+    /// Creates a site for the nodes of a network, with a newly generated publisher. Nothing is published yet.
+    /// Returns an error if a validation public key is invalid.
+    ///
+    /// # Parameters
+    /// * 'directory' - the directory the list is written to.
+    /// * 'validation_public_keys' - the validation public keys of the nodes in base58, by their IDs.
+    /// * 'expiration_days' - the amount of days after which a published list expires.
+    /// * 'refresh_interval_mins' - the interval in minutes in which the nodes fetch the list again.
+    /// * 'rng' - the generator of the keys of the publisher.
+    pub fn new(
+        directory: PathBuf,
+        validation_public_keys: &[String],
+        expiration_days: u32,
+        refresh_interval_mins: u32,
+        rng: &mut StdRng,
+    ) -> Result<Self, String> {
+        let public_keys = validation_public_keys
+            .iter()
+            .map(|key| node_public_key(key).map(hex::encode_upper))
+            .collect::<Result<Vec<String>, String>>()?;
+        Ok(ValidatorListSite {
+            publisher: Publisher::generate(rng),
+            directory,
+            public_keys,
+            sequence: 0,
+            expiration: Duration::from_secs(expiration_days as u64 * 24 * 60 * 60),
+            refresh_interval_mins,
+        })
+    }
+
+    /// Returns the master public key of the publisher in hex.
+    pub fn publisher_key(&self) -> String {
+        self.publisher.public_key()
+    }
+
+    /// Publishes a list of validators with the next sequence, replacing the published list.
+    /// Returns an error if a node does not exist or the list could not be written.
+    ///
+    /// # Parameters
+    /// * 'validators' - the IDs of the listed validators.
+    pub fn publish(&mut self, validators: &[u32]) -> Result<(), String> {
+        let keys = validators
+            .iter()
+            .map(|id| {
+                self.public_keys
+                    .get(*id as usize)
+                    .cloned()
+                    .ok_or_else(|| format!("node {} does not exist", id))
+            })
+            .collect::<Result<Vec<String>, String>>()?;
+        let expiration = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .saturating_add(self.expiration)
+            .as_secs()
+            .saturating_sub(RIPPLE_EPOCH_OFFSET_SECS);
+        let list = self.publisher.sign_list(
+            &keys,
+            self.sequence + 1,
+            expiration,
+            self.refresh_interval_mins,
+        );
+        let path = self.directory.join(PUBLISHED_FILE);
+        fs::create_dir_all(&self.directory)
+            .and_then(|_| fs::write(&path, list))
+            .map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+        self.sequence += 1;
+        Ok(())
+    }
+}
+
+/// Publishes the lists of the rotations at their time, to rotate the UNL of the nodes during the run.
+///
+/// # Parameters
+/// * 'site' - the site the lists are published to.
+/// * 'rotations' - the lists that are published, in order.
+pub async fn run_rotations(mut site: ValidatorListSite, rotations: Vec<ValidatorListRotation>) {
+    let start = tokio::time::Instant::now();
+    for rotation in rotations {
+        tokio::time::sleep_until(start + Duration::from_secs(rotation.after_secs)).await;
+        match site.publish(&rotation.validators) {
+            Ok(()) => info!(
+                "Published validator list {} with validators {:?}",
+                site.sequence, rotation.validators
+            ),
+            Err(e) => warn!("Could not publish the validator list: {}", e),
+        }
+    }
+}
+
+/// Decodes a node public key from the base58 encoding of the XRPL, e.g. a validation public key.
+///
+/// # Parameters
+/// * 'key' - the encoded key.
+pub fn node_public_key(key: &str) -> Result<Vec<u8>, String> {
+    let public_key = keygen::decode_token(NODE_PUBLIC_KEY, key)?;
+    match public_key.len() {
+        33 => Ok(public_key),
+        _ => Err(format!("'{}' is not a node public key", key)),
+    }
+}
+
+/// Returns a random secret key.
+///
+/// # Parameters
+/// * 'rng' - the generator of the key.
+fn random_key(rng: &mut StdRng) -> SecretKey {
+    loop {
+        if let Ok(key) = SecretKey::from_slice(&rng.gen::<[u8; 32]>()) {
+            return key;
+        }
+    }
+}
+
+/// Returns the compressed public key of a secret key.
+///
+/// # Parameters
+/// * 'secret_key' - the secret key.
+fn public_key(secret_key: &SecretKey) -> [u8; 33] {
+    PublicKey::from_secret_key(&Secp256k1::new(), secret_key).serialize()
+}
+
+/// Signs data with a secp256k1 key, see `keygen::sign`.
+///
+/// # Parameters
+/// * 'data' - the signed data.
+/// * 'secret_key' - the key the data is signed with.
+fn sign(data: &[u8], secret_key: &SecretKey) -> Vec<u8> {
+    keygen::sign(KeyType::Secp256k1, &secret_key.secret_bytes(), data)
+}
+
+/// Appends a variable length field to a serialized object.
+///
+/// # Parameters
+/// * 'object' - the serialized object.
+/// * 'header' - the header of the field.
+/// * 'value' - the value of the field, which is shorter than 12481 bytes.
+pub fn push_blob(object: &mut Vec<u8>, header: &[u8], value: &[u8]) {
+    object.extend(header);
+    match value.len() {
+        length @ 0..=192 => object.push(length as u8),
+        length => {
+            let length = length - 193;
+            object.push(193 + (length >> 8) as u8);
+            object.push((length & 0xff) as u8);
+        }
+    }
+    object.extend(value);
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::run_seed::RunSeed;
+    use crate::signature::verify_data;
+    use crate::validator_list::{node_public_key, Publisher};
+    use base64::engine::general_purpose;
+    use base64::Engine;
+    use serde_json::Value;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn manifest_is_signed_by_both_keys() {
+        let publisher = Publisher::generate(&mut RunSeed(1).rng("validator_list"));
+        let manifest = publisher.manifest();
+        // Sequence, PublicKey and SigningPubKey are followed by both signatures
+        assert_eq!(&manifest[..5], &[0x24, 0, 0, 0, 1]);
+        assert_eq!(&manifest[5..7], &[0x71, 33]);
+        assert_eq!(hex::encode_upper(&manifest[7..40]), publisher.public_key());
+        assert_eq!(&manifest[40..42], &[0x73, 33]);
+        let signing_key = manifest[42..75].to_vec();
+        assert_eq!(manifest[75], 0x76);
+        let signature_length = manifest[76] as usize;
+        let signature = &manifest[77..77 + signature_length];
+        let master_signature = &manifest[77 + signature_length..];
+        assert_eq!(&master_signature[..2], &[0x70, 0x12]);
+
+        let mut signing_data = b"MAN\0".to_vec();
+        signing_data.extend(&manifest[..75]);
+        assert!(verify_data(&signing_key, &signing_data, signature));
+        assert!(verify_data(
+            &manifest[7..40],
+            &signing_data,
+            &master_signature[3..]
+        ));
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn list_is_signed_by_signing_key() {
+        let publisher = Publisher::generate(&mut RunSeed(1).rng("validator_list"));
+        let validators = vec!["02AB".to_string(), "03CD".to_string()];
+        let list: Value =
+            serde_json::from_str(&publisher.sign_list(&validators, 3, 1000, 5)).unwrap();
+        assert_eq!(list["public_key"], publisher.public_key());
+        assert_eq!(list["version"], 1);
+        assert_eq!(list["refresh_interval"], 5);
+
+        let blob = general_purpose::STANDARD
+            .decode(list["blob"].as_str().unwrap())
+            .unwrap();
+        let contents: Value = serde_json::from_slice(&blob).unwrap();
+        assert_eq!(contents["sequence"], 3);
+        assert_eq!(contents["expiration"], 1000);
+        assert_eq!(contents["validators"][1]["validation_public_key"], "03CD");
+
+        let manifest = publisher.manifest();
+        let signature = hex::decode(list["signature"].as_str().unwrap()).unwrap();
+        assert!(verify_data(&manifest[42..75], &blob, &signature));
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn decode_node_public_key() {
+        let key = node_public_key("n949f75evCHwgyP4fPVgaHqNHxUVN15PsJEZ3B3HnXPcPjcZAoy7").unwrap();
+        assert_eq!(key.len(), 33);
+        assert!(key[0] == 0x02 || key[0] == 0x03);
+        // A changed checksum, a seed and letters outside the alphabet
+        assert!(node_public_key("n949f75evCHwgyP4fPVgaHqNHxUVN15PsJEZ3B3HnXPcPjcZAoy8").is_err());
+        assert!(node_public_key("sn259rEFXrQrWyx3Q7XneWcwV6dfL").is_err());
+        assert!(node_public_key("n9-not-base58").is_err());
+    }
+}
@@ -0,0 +1,408 @@
+//! This module is responsible for WASM plugins, which decide on messages with custom logic, written in any language
+//! that compiles to WebAssembly, without modifying the interceptor or running a controller.
+//!
+//! A plugin is a WASM module that exports:
+//! * `memory` - its linear memory.
+//! * `alloc(len: i32) -> i32` - returns a buffer of `len` bytes, in which the interceptor writes the message.
+//! * `on_packet(from_port: i32, to_port: i32, message_type: i32, ptr: i32, len: i32) -> i32` - the hook that is called
+//!   for every message that is sent, with the message including its 6 byte header. It returns 0 if it decided,
+//!   anything else is counted as a plugin error and leaves the message as it was decided.
+//!
+//! The hook decides through the functions the interceptor provides in the `interceptor` import module, which mirror
+//! the typed actions of the controller: `drop()`, `delay(ms: i32)`, `mutate(ptr: i32, len: i32)` and
+//! `duplicate(copies: i32)`. A hook that calls none of them forwards the message unchanged. The actions of the plugins
+//! are applied on top of the decision of the controller, in the order the plugins are configured. Plugins are packet
+//! hooks of the `AfterController` stage, see `packet_hook`.
+
+use crate::action::{Decision, PROTO_VERSION};
+use crate::message_type::MessageType;
+use crate::packet_client::proto::action::Kind;
+use crate::packet_client::proto::{Action, DropAction, PacketAck};
+use crate::packet_hook::{HookError, PacketContext, PacketHook};
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use std::sync::Mutex;
+use wasmtime::{Caller, Config, Engine, Instance, Linker, Memory, Module, Store, TypedFunc};
+
+/// The name of the module from which plugins import the functions through which they decide.
+const IMPORT_MODULE: &str = "interceptor";
+
+/// Enum that represents the reasons a plugin can fail.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PluginError {
+    /// The module could not be loaded, or does not export the required functions.
+    Load(String),
+    /// The hook trapped, e.g. because it ran out of fuel or accessed memory out of bounds.
+    Trap(String),
+    /// The hook returned a status other than 0.
+    Failed(i32),
+    /// The actions of the hook are invalid, e.g. a drop combined with other actions.
+    InvalidActions(String),
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PluginError::Load(e) => write!(f, "could not load plugin: {}", e),
+            PluginError::Trap(e) => write!(f, "plugin trapped: {}", e),
+            PluginError::Failed(status) => write!(f, "plugin returned status {}", status),
+            PluginError::InvalidActions(e) => write!(f, "invalid plugin actions: {}", e),
+        }
+    }
+}
+
+impl Error for PluginError {}
+
+/// Struct that represents the instance of a plugin together with the exports the interceptor calls.
+struct PluginInstance {
+    /// The store of the instance, in which the actions of the current call are collected.
+    store: Store<Vec<Kind>>,
+    /// The linear memory of the plugin.
+    memory: Memory,
+    /// The function that allocates a buffer for the message.
+    alloc: TypedFunc<i32, i32>,
+    /// The hook.
+    on_packet: TypedFunc<(i32, i32, i32, i32, i32), i32>,
+}
+
+/// Struct that represents a loaded WASM plugin.
+pub struct WasmPlugin {
+    /// The name of the plugin, which is the file name of its module.
+    pub name: String,
+    /// The amount of fuel a single call of the hook can use, which bounds its execution time.
+    fuel: u64,
+    /// The instance of the plugin, which handles one message at a time.
+    instance: Mutex<PluginInstance>,
+}
+
+impl fmt::Debug for WasmPlugin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WasmPlugin")
+            .field("name", &self.name)
+            .field("fuel", &self.fuel)
+            .finish()
+    }
+}
+
+impl WasmPlugin {
+    /// Loads and instantiates a plugin from a `.wasm` or `.wat` file.
+    ///
+    /// # Parameters
+    /// * 'path' - the path of the module.
+    /// * 'fuel' - the amount of fuel a single call of the hook can use.
+    pub fn load(path: &Path, fuel: u64) -> Result<Self, PluginError> {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.display().to_string());
+        let engine = Self::engine()?;
+        let module =
+            Module::from_file(&engine, path).map_err(|e| PluginError::Load(e.to_string()))?;
+        Self::instantiate(name, &engine, &module, fuel)
+    }
+
+    /// Loads and instantiates a plugin from its binary or text format.
+    ///
+    /// # Parameters
+    /// * 'name' - the name of the plugin.
+    /// * 'bytes' - the module.
+    /// * 'fuel' - the amount of fuel a single call of the hook can use.
+    pub fn from_bytes(name: &str, bytes: &[u8], fuel: u64) -> Result<Self, PluginError> {
+        let engine = Self::engine()?;
+        let module = Module::new(&engine, bytes).map_err(|e| PluginError::Load(e.to_string()))?;
+        Self::instantiate(name.to_string(), &engine, &module, fuel)
+    }
+
+    /// Returns an engine that meters the fuel plugins use.
+    fn engine() -> Result<Engine, PluginError> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        Engine::new(&config).map_err(|e| PluginError::Load(e.to_string()))
+    }
+
+    /// Links the functions through which the plugin decides, and instantiates the module.
+    ///
+    /// # Parameters
+    /// * 'name' - the name of the plugin.
+    /// * 'engine' - the engine the module was compiled with.
+    /// * 'module' - the module.
+    /// * 'fuel' - the amount of fuel a single call of the hook can use.
+    fn instantiate(
+        name: String,
+        engine: &Engine,
+        module: &Module,
+        fuel: u64,
+    ) -> Result<Self, PluginError> {
+        let load_error = |e: wasmtime::Error| PluginError::Load(e.to_string());
+        let mut linker: Linker<Vec<Kind>> = Linker::new(engine);
+        linker
+            .func_wrap(
+                IMPORT_MODULE,
+                "drop",
+                |mut caller: Caller<'_, Vec<Kind>>| {
+                    caller.data_mut().push(Kind::Drop(DropAction {}));
+                },
+            )
+            .map_err(load_error)?;
+        linker
+            .func_wrap(
+                IMPORT_MODULE,
+                "delay",
+                |mut caller: Caller<'_, Vec<Kind>>, delay_ms: i32| {
+                    caller
+                        .data_mut()
+                        .push(Kind::DelayMs(delay_ms.max(0) as u32));
+                },
+            )
+            .map_err(load_error)?;
+        linker
+            .func_wrap(
+                IMPORT_MODULE,
+                "duplicate",
+                |mut caller: Caller<'_, Vec<Kind>>, copies: i32| {
+                    caller
+                        .data_mut()
+                        .push(Kind::Duplicate(copies.max(0) as u32));
+                },
+            )
+            .map_err(load_error)?;
+        linker
+            .func_wrap(
+                IMPORT_MODULE,
+                "mutate",
+                |mut caller: Caller<'_, Vec<Kind>>, ptr: i32, len: i32| -> wasmtime::Result<()> {
+                    let memory = caller
+                        .get_export("memory")
+                        .and_then(|export| export.into_memory())
+                        .ok_or_else(|| wasmtime::Error::msg("the plugin does not export memory"))?;
+                    let mut data = vec![0; len.max(0) as usize];
+                    memory.read(&caller, ptr as u32 as usize, &mut data)?;
+                    caller.data_mut().push(Kind::Mutate(Bytes::from(data)));
+                    Ok(())
+                },
+            )
+            .map_err(load_error)?;
+
+        let mut store = Store::new(engine, Vec::new());
+        store.set_fuel(fuel).map_err(load_error)?;
+        let instance: Instance = linker.instantiate(&mut store, module).map_err(load_error)?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| PluginError::Load("the plugin does not export memory".to_string()))?;
+        let alloc = instance
+            .get_typed_func(&mut store, "alloc")
+            .map_err(load_error)?;
+        let on_packet = instance
+            .get_typed_func(&mut store, "on_packet")
+            .map_err(load_error)?;
+        Ok(Self {
+            name,
+            fuel,
+            instance: Mutex::new(PluginInstance {
+                store,
+                memory,
+                alloc,
+                on_packet,
+            }),
+        })
+    }
+
+    /// Calls the hook of the plugin for a message, and returns the actions it decided on.
+    ///
+    /// # Parameters
+    /// * 'from_port' - the port of the peer where the message came from.
+    /// * 'to_port' - the port of the peer the message is sent to.
+    /// * 'message_type' - the type of the message.
+    /// * 'message' - the message including its 6 byte header.
+    pub fn call_hook(
+        &self,
+        from_port: u16,
+        to_port: u16,
+        message_type: MessageType,
+        message: &[u8],
+    ) -> Result<Vec<Kind>, PluginError> {
+        let trap = |e: wasmtime::Error| PluginError::Trap(e.to_string());
+        let mut instance = self.instance.lock().unwrap();
+        let PluginInstance {
+            store,
+            memory,
+            alloc,
+            on_packet,
+        } = &mut *instance;
+        store.data_mut().clear();
+        store.set_fuel(self.fuel).map_err(trap)?;
+        let ptr = alloc
+            .call(&mut *store, message.len() as i32)
+            .map_err(trap)?;
+        memory
+            .write(&mut *store, ptr as u32 as usize, message)
+            .map_err(|e| PluginError::Trap(e.to_string()))?;
+        let status = on_packet
+            .call(
+                &mut *store,
+                (
+                    i32::from(from_port),
+                    i32::from(to_port),
+                    i32::from(message_type.value()),
+                    ptr,
+                    message.len() as i32,
+                ),
+            )
+            .map_err(trap)?;
+        if status != 0 {
+            return Err(PluginError::Failed(status));
+        }
+        Ok(std::mem::take(store.data_mut()))
+    }
+
+    /// Applies the actions of the plugin for a message to the decision that sends it: a drop drops the message,
+    /// a delay is added, a mutation replaces the data and duplicates are added to the amount of times it is sent.
+    ///
+    /// # Parameters
+    /// * 'decision' - the decision made for the message so far.
+    /// * 'from_port' - the port of the peer where the message came from.
+    /// * 'to_port' - the port of the peer the message is sent to.
+    /// * 'message_type' - the type of the message.
+    pub fn decide(
+        &self,
+        decision: Decision,
+        from_port: u16,
+        to_port: u16,
+        message_type: MessageType,
+    ) -> Result<Decision, PluginError> {
+        let actions = self.call_hook(from_port, to_port, message_type, &decision.data)?;
+        let ack = PacketAck {
+            actions: actions
+                .into_iter()
+                .map(|kind| Action { kind: Some(kind) })
+                .collect(),
+            ..PacketAck::default()
+        };
+        let plugin_decision = Decision::from_ack(decision.data.clone(), ack, PROTO_VERSION)
+            .map_err(|e| PluginError::InvalidActions(e.to_string()))?;
+        Ok(decision.then(plugin_decision))
+    }
+}
+
+#[async_trait]
+impl PacketHook for WasmPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn on_packet(
+        &self,
+        context: &PacketContext,
+        decision: Decision,
+    ) -> Result<Decision, HookError> {
+        Ok(self.decide(
+            decision,
+            context.from_port,
+            context.to_port,
+            context.message_type,
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::action::Decision;
+    use crate::message_type::MessageType;
+    use crate::wasm_plugin::{PluginError, WasmPlugin};
+    use bytes::Bytes;
+    use std::time::Duration;
+
+    /// A plugin that drops validations, delays proposals by 100 ms, replaces the first byte of pings by 0xFF, and
+    /// fails on transactions.
+    const PLUGIN: &str = r#"
+        (module
+            (import "interceptor" "drop" (func $drop))
+            (import "interceptor" "delay" (func $delay (param i32)))
+            (import "interceptor" "mutate" (func $mutate (param i32 i32)))
+            (memory (export "memory") 1)
+            (func (export "alloc") (param $len i32) (result i32) (i32.const 1024))
+            (func (export "on_packet")
+                (param $from i32) (param $to i32) (param $type i32) (param $ptr i32) (param $len i32)
+                (result i32)
+                (if (i32.eq (local.get $type) (i32.const 41)) (then (call $drop)))
+                (if (i32.eq (local.get $type) (i32.const 33)) (then (call $delay (i32.const 100))))
+                (if (i32.eq (local.get $type) (i32.const 3))
+                    (then
+                        (i32.store8 (local.get $ptr) (i32.const 255))
+                        (call $mutate (local.get $ptr) (local.get $len))))
+                (if (i32.eq (local.get $type) (i32.const 30)) (then (return (i32.const 7))))
+                (i32.const 0))
+        )
+    "#;
+
+    fn message(message_type: MessageType) -> Bytes {
+        let mut message = vec![0, 0, 0, 1];
+        message.extend_from_slice(&message_type.value().to_be_bytes());
+        message.push(1);
+        Bytes::from(message)
+    }
+
+    fn decide(plugin: &WasmPlugin, message_type: MessageType) -> Result<Decision, PluginError> {
+        let decision = Decision {
+            delay: Duration::from_millis(50),
+            ..Decision::forward(message(message_type))
+        };
+        plugin.decide(decision, 60000, 60001, message_type)
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn plugin_decides_on_messages() {
+        let plugin = WasmPlugin::from_bytes("test.wat", PLUGIN.as_bytes(), 100_000).unwrap();
+
+        assert_eq!(
+            decide(&plugin, MessageType::Validation)
+                .unwrap()
+                .send_amount,
+            0
+        );
+        let delayed = decide(&plugin, MessageType::ProposeLedger).unwrap();
+        assert_eq!(delayed.delay, Duration::from_millis(150));
+        assert_eq!(delayed.send_amount, 1);
+        let mutated = decide(&plugin, MessageType::Ping).unwrap();
+        assert_eq!(mutated.data, Bytes::from_static(&[0xFF, 0, 0, 1, 0, 3, 1]));
+        assert_eq!(
+            decide(&plugin, MessageType::StatusChange).unwrap(),
+            Decision {
+                delay: Duration::from_millis(50),
+                ..Decision::forward(message(MessageType::StatusChange))
+            }
+        );
+        assert_eq!(
+            decide(&plugin, MessageType::Transaction),
+            Err(PluginError::Failed(7))
+        );
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn plugin_runs_out_of_fuel() {
+        let looping = r#"
+            (module
+                (memory (export "memory") 1)
+                (func (export "alloc") (param i32) (result i32) (i32.const 0))
+                (func (export "on_packet") (param i32 i32 i32 i32 i32) (result i32)
+                    (loop $forever (br $forever))
+                    (i32.const 0))
+            )
+        "#;
+        let plugin = WasmPlugin::from_bytes("loop.wat", looping.as_bytes(), 10_000).unwrap();
+        assert!(matches!(
+            decide(&plugin, MessageType::Ping),
+            Err(PluginError::Trap(_))
+        ));
+
+        assert!(matches!(
+            WasmPlugin::from_bytes("empty.wat", b"(module)", 10_000),
+            Err(PluginError::Load(_))
+        ));
+    }
+}
@@ -0,0 +1,361 @@
+//! This module is responsible for reading and writing the protobuf wire format of peer messages, without decoding them
+//! into their generated types. Fields that are not read are kept byte for byte, which is needed to mutate, relay and
+//! verify messages exactly as they were sent.
+
+use crate::message_decoder::{SF_LEDGER_HASH, SF_LEDGER_SEQUENCE, ST_HASH256, ST_UINT32};
+use crate::message_type::MessageType;
+use prost::encoding::{decode_varint, encode_varint};
+
+/// The field number of the proposal sequence in `TMProposeSet`.
+pub const PROPOSE_FIELD_SEQ: u64 = 1;
+/// The field number of the hash of the proposed transaction set in `TMProposeSet`.
+pub const PROPOSE_FIELD_CURRENT_TX_HASH: u64 = 2;
+/// The field number of the public key of the proposer in `TMProposeSet`.
+pub const PROPOSE_FIELD_NODE_PUB_KEY: u64 = 3;
+/// The field number of the close time in `TMProposeSet`.
+pub const PROPOSE_FIELD_CLOSE_TIME: u64 = 4;
+/// The field number of the signature in `TMProposeSet`.
+pub const PROPOSE_FIELD_SIGNATURE: u64 = 5;
+/// The field number of the hash of the previous ledger in `TMProposeSet`.
+pub const PROPOSE_FIELD_PREVIOUS_LEDGER: u64 = 6;
+/// The field number of the serialized `STValidation` in `TMValidation`.
+pub const VALIDATION_FIELD_VALIDATION: u64 = 1;
+/// The field number of the ledger sequence in `TMStatusChange`.
+pub const STATUS_CHANGE_FIELD_LEDGER_SEQ: u64 = 3;
+/// The field number of the ledger sequence in `TMGetLedger`.
+pub const GET_LEDGER_FIELD_LEDGER_SEQ: u64 = 4;
+/// The field number of the ledger sequence in `TMLedgerData`.
+pub const LEDGER_DATA_FIELD_LEDGER_SEQ: u64 = 2;
+
+/// Enum that represents the value of a field on the protobuf wire format.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WireValue {
+    /// A varint field, wire type 0.
+    Varint(u64),
+    /// A length-delimited field, wire type 2.
+    LengthDelimited(Vec<u8>),
+    /// A fixed size field, wire type 1 or 5, which is kept as-is.
+    Fixed(u64, Vec<u8>),
+}
+
+/// Struct that represents a field of a protobuf message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WireField {
+    /// The field number.
+    pub number: u64,
+    /// The value.
+    pub value: WireValue,
+}
+
+/// Reads the next field of a protobuf message and advances the payload past it.
+/// Returns the field number, the wire type, the value of a varint field and the contents of any other field,
+/// or None if the payload does not start with a valid field.
+///
+/// # Parameters
+/// * 'payload' - the remainder of the protobuf message, which is not empty.
+fn next_field<'a>(payload: &mut &'a [u8]) -> Option<(u64, u64, u64, &'a [u8])> {
+    let key = decode_varint(payload).ok()?;
+    let (number, wire_type) = (key >> 3, key & 0b111);
+    let (value, contents) = match wire_type {
+        0 => (decode_varint(payload).ok()?, &payload[0..0]),
+        1 => (0, payload.get(0..8)?),
+        2 => {
+            let length = decode_varint(payload).ok()? as usize;
+            (0, payload.get(0..length)?)
+        }
+        5 => (0, payload.get(0..4)?),
+        _ => return None,
+    };
+    if number == 0 {
+        return None;
+    }
+    *payload = &payload[contents.len()..];
+    Some((number, wire_type, value, contents))
+}
+
+/// Parses all fields of a protobuf message, in the order they are serialized.
+/// Returns None if it is not a valid protobuf message.
+///
+/// # Parameters
+/// * 'payload' - the protobuf message.
+pub fn decode_fields(mut payload: &[u8]) -> Option<Vec<WireField>> {
+    let mut fields = Vec::new();
+    while !payload.is_empty() {
+        let (number, wire_type, value, contents) = next_field(&mut payload)?;
+        let value = match wire_type {
+            0 => WireValue::Varint(value),
+            2 => WireValue::LengthDelimited(contents.to_vec()),
+            _ => WireValue::Fixed(wire_type, contents.to_vec()),
+        };
+        fields.push(WireField { number, value });
+    }
+    Some(fields)
+}
+
+/// Serializes fields into a protobuf message.
+///
+/// # Parameters
+/// * 'fields' - the fields, in the order they are serialized.
+pub fn encode_fields(fields: &[WireField]) -> Vec<u8> {
+    let mut payload = Vec::new();
+    for field in fields {
+        match &field.value {
+            WireValue::Varint(value) => {
+                encode_varint(field.number << 3, &mut payload);
+                encode_varint(*value, &mut payload);
+            }
+            WireValue::LengthDelimited(contents) => {
+                encode_varint(field.number << 3 | 2, &mut payload);
+                encode_varint(contents.len() as u64, &mut payload);
+                payload.extend_from_slice(contents);
+            }
+            WireValue::Fixed(wire_type, contents) => {
+                encode_varint(field.number << 3 | wire_type, &mut payload);
+                payload.extend_from_slice(contents);
+            }
+        }
+    }
+    payload
+}
+
+/// Returns the first field of a protobuf message with the given number and wire type, without parsing the fields
+/// after it. For varint fields the value is returned, for all other fields the contents.
+///
+/// # Parameters
+/// * 'payload' - the protobuf message.
+/// * 'field' - the field number.
+/// * 'wire_type' - the wire type, 0 for varint and 2 for length-delimited.
+pub fn find_field(mut payload: &[u8], field: u64, wire_type: u64) -> Option<(u64, &[u8])> {
+    while !payload.is_empty() {
+        let (number, field_wire_type, value, contents) = next_field(&mut payload)?;
+        if number == field && field_wire_type == wire_type {
+            return Some((value, contents));
+        }
+    }
+    None
+}
+
+/// Returns the value of a varint field of a protobuf message.
+///
+/// # Parameters
+/// * 'payload' - the protobuf message.
+/// * 'field' - the field number.
+pub fn varint_field(payload: &[u8], field: u64) -> Option<u64> {
+    find_field(payload, field, 0).map(|(value, _)| value)
+}
+
+/// Returns the contents of a length-delimited field of a protobuf message.
+///
+/// # Parameters
+/// * 'payload' - the protobuf message.
+/// * 'field' - the field number.
+pub fn bytes_field(payload: &[u8], field: u64) -> Option<&[u8]> {
+    find_field(payload, field, 2).map(|(_, bytes)| bytes)
+}
+
+/// Returns the ledger sequence contained in a message, for the message types that contain one:
+/// mtSTATUS_CHANGE, mtGET_LEDGER, mtLEDGER_DATA and mtVALIDATION.
+///
+/// # Parameters
+/// * 'message' - the message including its header.
+pub fn ledger_sequence(message: &[u8]) -> Option<u32> {
+    let payload = payload(message)?;
+    match MessageType::from_message(message)? {
+        MessageType::StatusChange => varint_field(payload, STATUS_CHANGE_FIELD_LEDGER_SEQ),
+        MessageType::GetLedger => varint_field(payload, GET_LEDGER_FIELD_LEDGER_SEQ),
+        MessageType::LedgerData => varint_field(payload, LEDGER_DATA_FIELD_LEDGER_SEQ),
+        MessageType::Validation => {
+            let validation = bytes_field(payload, VALIDATION_FIELD_VALIDATION)?;
+            let sequence = validation_field(validation, ST_UINT32, SF_LEDGER_SEQUENCE)?;
+            Some(u32::from_be_bytes(sequence.try_into().ok()?) as u64)
+        }
+        _ => None,
+    }
+    .map(|sequence| sequence as u32)
+}
+
+/// Returns the hash of the ledger a message refers to, for the message types that refer to one:
+/// the validated ledger of mtVALIDATION, and the previous ledger of mtPROPOSE_LEDGER, which the proposal builds on.
+///
+/// # Parameters
+/// * 'message' - the message including its header.
+pub fn ledger_hash(message: &[u8]) -> Option<[u8; 32]> {
+    let payload = payload(message)?;
+    let hash = match MessageType::from_message(message)? {
+        MessageType::ProposeLedger => bytes_field(payload, PROPOSE_FIELD_PREVIOUS_LEDGER)?,
+        MessageType::Validation => validation_field(
+            bytes_field(payload, VALIDATION_FIELD_VALIDATION)?,
+            ST_HASH256,
+            SF_LEDGER_HASH,
+        )?,
+        _ => return None,
+    };
+    hash.try_into().ok()
+}
+
+/// Returns the part of a broadcast message that is the same in every copy of it, regardless of the peer that relays it:
+/// the serialized STValidation of mtVALIDATION, and the signature of mtPROPOSE_LEDGER.
+///
+/// # Parameters
+/// * 'message' - the message including its header.
+pub fn broadcast_contents(message: &[u8]) -> Option<&[u8]> {
+    let payload = payload(message)?;
+    match MessageType::from_message(message)? {
+        MessageType::ProposeLedger => bytes_field(payload, PROPOSE_FIELD_SIGNATURE),
+        MessageType::Validation => bytes_field(payload, VALIDATION_FIELD_VALIDATION),
+        _ => None,
+    }
+}
+
+/// Returns the payload of a message, or None if the message is shorter than its header says.
+///
+/// # Parameters
+/// * 'message' - the message including its header.
+fn payload(message: &[u8]) -> Option<&[u8]> {
+    let payload_size = u32::from_be_bytes(message.get(0..4)?.try_into().ok()?) as usize;
+    message.get(6..6 + payload_size)
+}
+
+/// Returns the value of a fixed size field of a serialized `STValidation`.
+/// Fields are serialized in order of their type and field code, so only the leading fixed size fields are read:
+/// UInt16, UInt32, UInt64, Hash128 and Hash256.
+///
+/// # Parameters
+/// * 'validation' - the serialized `STValidation`.
+/// * 'type_code' - the type code of the field.
+/// * 'field_code' - the field code of the field.
+fn validation_field(mut validation: &[u8], type_code: u8, field_code: u8) -> Option<&[u8]> {
+    loop {
+        let header = *validation.first()?;
+        let (field_type, field, header_size) = match (header >> 4, header & 0x0F) {
+            (0, _) => return None,
+            (field_type, 0) => (field_type, *validation.get(1)?, 2),
+            (field_type, field) => (field_type, field, 1),
+        };
+        let size = match field_type {
+            1 => 2,
+            2 => 4,
+            3 => 8,
+            4 => 16,
+            5 => 32,
+            _ => return None,
+        };
+        if (field_type, field) > (type_code, field_code) {
+            return None;
+        }
+        let value = validation.get(header_size..header_size + size)?;
+        if (field_type, field) == (type_code, field_code) {
+            return Some(value);
+        }
+        validation = &validation[header_size + size..];
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::message_decoder::{SF_LEDGER_HASH, SF_LEDGER_SEQUENCE, ST_HASH256, ST_UINT32};
+    use crate::message_type::MessageType;
+    use crate::wire_format::{
+        decode_fields, encode_fields, find_field, ledger_hash, ledger_sequence, WireField,
+        WireValue,
+    };
+    use prost::encoding::encode_varint;
+
+    fn message(message_type: MessageType, payload: &[u8]) -> Vec<u8> {
+        let mut message = (payload.len() as u32).to_be_bytes().to_vec();
+        message.extend_from_slice(&message_type.value().to_be_bytes());
+        message.extend_from_slice(payload);
+        message
+    }
+
+    fn status_change(ledger_seq: u64) -> Vec<u8> {
+        let mut payload = Vec::new();
+        // newEvent = neACCEPTED_LEDGER
+        encode_varint(2 << 3, &mut payload);
+        encode_varint(3, &mut payload);
+        encode_varint(3 << 3, &mut payload);
+        encode_varint(ledger_seq, &mut payload);
+        message(MessageType::StatusChange, &payload)
+    }
+
+    fn validation(ledger_seq: u32) -> Vec<u8> {
+        // sfFlags, sfLedgerSequence and sfSigningTime
+        let mut validation = vec![0x22, 0x80, 0, 0, 1, 0x26];
+        validation.extend_from_slice(&ledger_seq.to_be_bytes());
+        validation.extend_from_slice(&[0x29, 0, 0, 0, 9]);
+        let mut payload = Vec::new();
+        encode_varint(1 << 3 | 2, &mut payload);
+        encode_varint(validation.len() as u64, &mut payload);
+        payload.extend_from_slice(&validation);
+        message(MessageType::Validation, &payload)
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn decode_and_encode_fields() {
+        let fields = vec![
+            WireField {
+                number: 1,
+                value: WireValue::Varint(300),
+            },
+            WireField {
+                number: 2,
+                value: WireValue::LengthDelimited(vec![0xAB; 3]),
+            },
+            WireField {
+                number: 3,
+                value: WireValue::Fixed(5, vec![1, 2, 3, 4]),
+            },
+            WireField {
+                number: 1,
+                value: WireValue::Varint(7),
+            },
+        ];
+        let payload = encode_fields(&fields);
+        assert_eq!(decode_fields(&payload), Some(fields));
+
+        assert_eq!(find_field(&payload, 1, 0), Some((300, &[][..])));
+        assert_eq!(find_field(&payload, 2, 2), Some((0, &[0xAB; 3][..])));
+        assert_eq!(find_field(&payload, 3, 5), Some((0, &[1, 2, 3, 4][..])));
+        assert_eq!(find_field(&payload, 2, 0), None);
+
+        // Truncated contents and field number 0
+        assert_eq!(decode_fields(&payload[..payload.len() - 2]), None);
+        assert_eq!(decode_fields(&[0, 1]), None);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn ledger_sequence_of_messages() {
+        assert_eq!(ledger_sequence(&status_change(12)), Some(12));
+        assert_eq!(ledger_sequence(&validation(300)), Some(300));
+        assert_eq!(ledger_sequence(&message(MessageType::Ping, &[8, 0])), None);
+        assert_eq!(ledger_sequence(&[0, 0, 0]), None);
+    }
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn ledger_hash_of_messages() {
+        // Without sfLedgerHash
+        assert_eq!(ledger_hash(&validation(300)), None);
+        assert_eq!(ledger_hash(&status_change(12)), None);
+
+        // sfLedgerSequence, sfCookie and sfLedgerHash
+        let mut validation = vec![0x26, 0, 0, 0, 7, 0x3A, 0, 0, 0, 0, 0, 0, 0, 1, 0x51];
+        validation.extend_from_slice(&[0xAB; 32]);
+        let mut payload = Vec::new();
+        encode_varint(1 << 3 | 2, &mut payload);
+        encode_varint(validation.len() as u64, &mut payload);
+        payload.extend_from_slice(&validation);
+        let validation = message(MessageType::Validation, &payload);
+        assert_eq!(ledger_sequence(&validation), Some(7));
+        assert_eq!(ledger_hash(&validation), Some([0xAB; 32]));
+
+        let mut payload = Vec::new();
+        encode_varint(6 << 3 | 2, &mut payload);
+        encode_varint(32, &mut payload);
+        payload.extend_from_slice(&[0xCD; 32]);
+        let proposal = message(MessageType::ProposeLedger, &payload);
+        assert_eq!(ledger_hash(&proposal), Some([0xCD; 32]));
+    }
+}
@@ -0,0 +1,259 @@
+//! This module is responsible for intercepting the WebSocket traffic between clients and the public WebSocket port
+//! of every node, such that submitted transactions and subscription responses are subject to the same controller
+//! decisions and link rules as the messages between peers.
+//!
+//! Every node gets a proxy port, see `proxy::ProxyDirection` for how the frames are decided on.
+
+use crate::address;
+use crate::config::WebSocketProxyConfig;
+use crate::interceptor_state::InterceptorState;
+use crate::packet_client::proto::Channel;
+use crate::packet_client::PacketClient;
+use crate::proxy::{ProxyDirection, CLIENT_IDENTITY};
+use bytes::Bytes;
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Mutex};
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use tokio_tungstenite::WebSocketStream;
+use tracing::{debug, error, info, warn};
+
+/// Struct that represents the proxy in front of the public WebSocket port of a single node.
+#[derive(Debug, Clone)]
+pub struct WebSocketProxy {
+    /// The port the proxy listens on.
+    pub proxy_port: u16,
+    /// The host the ports of the node are published on.
+    pub node_host: String,
+    /// The public WebSocket port of the node.
+    pub node_port: u16,
+    /// The validation public key of the node.
+    pub node_key: String,
+    /// The configuration of the proxy.
+    pub config: WebSocketProxyConfig,
+    /// The PacketClient used to ask the controller for decisions.
+    pub client: Arc<Mutex<PacketClient>>,
+    /// The runtime state, containing the links of the proxy.
+    pub state: Arc<InterceptorState>,
+}
+
+impl WebSocketProxy {
+    /// Registers the links of the proxy and accepts clients until the listener fails.
+    ///
+    /// # Parameters
+    /// * 'listener' - the listener bound to the proxy port.
+    pub async fn serve(self, listener: TcpListener) {
+        info!(
+            "Proxying WebSocket clients on port {} to port {}",
+            self.proxy_port, self.node_port
+        );
+        let proxy = Arc::new(self);
+        let requests = Arc::new(ProxyDirection::register(
+            &proxy.state,
+            proxy.proxy_port,
+            proxy.node_port,
+            Channel::Websocket,
+            CLIENT_IDENTITY.to_string(),
+            proxy.node_key.clone(),
+            proxy.config.intercept_requests,
+        ));
+        let responses = Arc::new(ProxyDirection::register(
+            &proxy.state,
+            proxy.node_port,
+            proxy.proxy_port,
+            Channel::Websocket,
+            proxy.node_key.clone(),
+            CLIENT_IDENTITY.to_string(),
+            proxy.config.intercept_responses,
+        ));
+        loop {
+            match listener.accept().await {
+                Ok((stream, address)) => {
+                    debug!(
+                        "WebSocket client {} connected to {}",
+                        address, proxy.proxy_port
+                    );
+                    tokio::spawn(proxy.clone().proxy_connection(
+                        stream,
+                        requests.clone(),
+                        responses.clone(),
+                    ));
+                }
+                Err(e) => {
+                    error!("Could not accept WebSocket client: {}", e);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Connects a client to the node and forwards the frames in both directions until either side closes.
+    ///
+    /// # Parameters
+    /// * 'stream' - the accepted TCP stream of the client.
+    /// * 'requests' - the direction from the client to the node.
+    /// * 'responses' - the direction from the node to the client.
+    async fn proxy_connection(
+        self: Arc<Self>,
+        stream: TcpStream,
+        requests: Arc<ProxyDirection>,
+        responses: Arc<ProxyDirection>,
+    ) {
+        let client_socket = match tokio_tungstenite::accept_async(stream).await {
+            Ok(socket) => socket,
+            Err(e) => {
+                warn!("WebSocket handshake with a client failed: {}", e);
+                return;
+            }
+        };
+        let node_url = format!(
+            "ws://{}",
+            address::host_port(&self.node_host, self.node_port)
+        );
+        let node_socket = match tokio_tungstenite::connect_async(node_url.as_str()).await {
+            Ok((socket, _)) => socket,
+            Err(e) => {
+                warn!(
+                    "Could not connect to the WebSocket of the node at {}: {}",
+                    node_url, e
+                );
+                return;
+            }
+        };
+        let (client_sink, client_stream) = client_socket.split();
+        let (node_sink, node_stream) = node_socket.split();
+        let (client_sender, client_receiver) = mpsc::unbounded_channel();
+        let (node_sender, node_receiver) = mpsc::unbounded_channel();
+        tokio::spawn(write_frames(client_sink, client_receiver));
+        let node_writer = tokio::spawn(write_frames(node_sink, node_receiver));
+
+        let proxy = self.clone();
+        let responses = tokio::spawn(async move {
+            proxy.forward(node_stream, client_sender, responses).await;
+        });
+        self.forward(client_stream, node_sender, requests).await;
+        // Stopping the responses closes the queue to the client, after which its writer closes the connection
+        responses.abort();
+        let _ = node_writer.await;
+    }
+
+    /// Reads frames from one side of a connection, decides on them and queues them to be written to the other side.
+    /// Returns when the side that is read from closes.
+    ///
+    /// # Parameters
+    /// * 'source' - the side the frames are read from.
+    /// * 'destination' - the queue of frames written to the other side.
+    /// * 'direction' - the direction of the frames.
+    async fn forward<S>(
+        &self,
+        mut source: SplitStream<WebSocketStream<S>>,
+        destination: mpsc::UnboundedSender<WsMessage>,
+        direction: Arc<ProxyDirection>,
+    ) where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        while let Some(frame) = source.next().await {
+            let frame = match frame {
+                Ok(frame) => frame,
+                Err(e) => {
+                    debug!("WebSocket connection closed: {}", e);
+                    break;
+                }
+            };
+            let (data, text) = match frame {
+                WsMessage::Text(text) => (Bytes::from(text), true),
+                WsMessage::Binary(data) => (Bytes::from(data), false),
+                WsMessage::Close(_) => {
+                    let _ = destination.send(frame);
+                    break;
+                }
+                // Control frames are forwarded as they are
+                other => {
+                    let _ = destination.send(other);
+                    continue;
+                }
+            };
+            let decision = direction.decide(data, &self.client, &self.state).await;
+            let state = self.state.clone();
+            let destination = destination.clone();
+            let deliver = async move {
+                if state.is_paused() {
+                    state.wait_until_resumed().await;
+                }
+                for _ in 0..decision.send_amount {
+                    let _ = destination.send(frame_of(decision.data.clone(), text));
+                }
+            };
+            if decision.delay.is_zero() {
+                deliver.await;
+            } else {
+                let delay = decision.delay;
+                tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    deliver.await;
+                });
+            }
+        }
+    }
+}
+
+/// Writes the queued frames to one side of a connection until the queue is closed or writing fails.
+///
+/// # Parameters
+/// * 'sink' - the side the frames are written to.
+/// * 'frames' - the queue of frames.
+async fn write_frames<S>(
+    mut sink: SplitSink<WebSocketStream<S>, WsMessage>,
+    mut frames: mpsc::UnboundedReceiver<WsMessage>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    while let Some(frame) = frames.recv().await {
+        if let Err(e) = sink.send(frame).await {
+            debug!("Could not write WebSocket frame: {}", e);
+            return;
+        }
+    }
+    let _ = sink.close().await;
+}
+
+/// Builds the frame for a possibly mutated payload, which stays a text frame if the payload is still valid UTF-8.
+///
+/// # Parameters
+/// * 'data' - the payload.
+/// * 'text' - whether the original frame was a text frame.
+fn frame_of(data: Bytes, text: bool) -> WsMessage {
+    if text {
+        if let Ok(text) = String::from_utf8(data.to_vec()) {
+            return WsMessage::Text(text);
+        }
+    }
+    WsMessage::Binary(data.to_vec())
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::ws_proxy::frame_of;
+    use bytes::Bytes;
+    use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+    #[test]
+    // #[coverage(off)]  // Only available in nightly build, don't forget to uncomment #![feature(coverage_attribute)] on line 1 of main
+    fn frame_keeps_its_kind() {
+        assert_eq!(
+            frame_of(Bytes::from("{\"command\":\"submit\"}"), true),
+            WsMessage::Text("{\"command\":\"submit\"}".to_string())
+        );
+        assert_eq!(
+            frame_of(Bytes::from(vec![1, 2]), false),
+            WsMessage::Binary(vec![1, 2])
+        );
+        assert_eq!(
+            frame_of(Bytes::from(vec![0xff, 0xfe]), true),
+            WsMessage::Binary(vec![0xff, 0xfe])
+        );
+    }
+}